@@ -0,0 +1,63 @@
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Biên dịch file .proto bằng protox (thuần Rust) nên không cần cài `protoc`
+    let file_descriptors = protox::compile(["proto/storage.proto"], ["proto"])?;
+    // Client được sinh kèm để test tích hợp và các service khác gọi được server
+    tonic_build::configure().compile_fds(file_descriptors)?;
+    println!("cargo:rerun-if-changed=proto/storage.proto");
+
+    // Thông tin build cho endpoint /version
+    let git_sha = Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .ok()
+        .filter(|out| out.status.success())
+        .and_then(|out| String::from_utf8(out.stdout).ok())
+        .map(|sha| sha.trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    let built_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    println!("cargo:rustc-env=BUILD_GIT_SHA={}", git_sha);
+    println!("cargo:rustc-env=BUILD_TIMESTAMP={}", built_at);
+    println!("cargo:rustc-env=BUILD_SLED_VERSION={}", locked_version("sled"));
+    watch_git_head();
+    println!("cargo:rerun-if-changed=Cargo.lock");
+    Ok(())
+}
+
+// Build lại khi HEAD đổi nhánh hoặc nhánh hiện tại có commit mới: HEAD chỉ chứa tên ref
+// nên phải theo dõi cả các ref nhánh và packed-refs (nơi git gom ref sau `git gc`)
+fn watch_git_head() {
+    let Some(git_dir) = Command::new("git")
+        .args(["rev-parse", "--absolute-git-dir"])
+        .output()
+        .ok()
+        .filter(|out| out.status.success())
+        .and_then(|out| String::from_utf8(out.stdout).ok())
+        .map(|dir| std::path::PathBuf::from(dir.trim()))
+    else {
+        return;
+    };
+    // Path không tồn tại khiến cargo chạy lại build script mỗi lần nên chỉ khai báo path có thật
+    for path in ["HEAD", "packed-refs", "refs/heads"].map(|p| git_dir.join(p)) {
+        if path.exists() {
+            println!("cargo:rerun-if-changed={}", path.display());
+        }
+    }
+}
+
+// Tìm phiên bản của một crate trong Cargo.lock
+fn locked_version(package: &str) -> String {
+    let lock = std::fs::read_to_string("Cargo.lock").unwrap_or_default();
+    let name_line = format!("name = \"{}\"", package);
+    let mut lines = lock.lines();
+    while let Some(line) = lines.next() {
+        if line == name_line
+            && let Some(version) = lines.next().and_then(|l| l.strip_prefix("version = "))
+        {
+            return version.trim_matches('"').to_string();
+        }
+    }
+    "unknown".to_string()
+}
@@ -0,0 +1,19 @@
+// Fuzz target cho module key. Chạy bằng: cargo +nightly fuzz run key_decode
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+#[path = "../../src/key.rs"]
+#[allow(dead_code)]
+mod key;
+
+fuzz_target!(|data: &[u8]| {
+    // Tách key tùy ý không được panic
+    if let Some(parsed) = key::decode(data) {
+        // Key tách được thì phải ghép lại đúng byte ban đầu
+        let encoded = key::encode(parsed.file_key, parsed.chunk_hash);
+        assert_eq!(encoded.as_bytes(), data);
+        assert!(key::is_valid(parsed.file_key, parsed.chunk_hash));
+        assert_eq!(key::chunk_hash_in(data, parsed.file_key), Some(parsed.chunk_hash));
+    }
+});
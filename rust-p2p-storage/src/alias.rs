@@ -0,0 +1,34 @@
+// ## TÊN GỢI NHỚ CHO FILE (ALIAS) ##
+//
+// Alias là tên dễ đọc trỏ tới một fileKey, lưu trong tree `aliases` (key là alias, value
+// là fileKey). `POST /alias` (cần API key quản trị) tạo hoặc đổi đích của alias,
+// `GET /file/:name/resolve` cho biết `name` trỏ tới fileKey nào. Các route đọc
+// (`/file/:fileKey`, `/probe`, `/tail`, `/stream-download`, `/multipart`, `/files/batch`)
+// nhận alias ở chỗ fileKey và tự phân giải trước khi đọc; các route ghi vẫn chỉ nhận
+// fileKey thật.
+//
+// Alias có dạng một key hex thô (`0x` + chữ số hex, hoặc chỉ chữ số hex) bị từ chối, để
+// một fileKey dạng hash không bao giờ bị một alias trùng tên che mất.
+
+/// Tên có dạng key hex thô, không được dùng làm alias
+pub fn looks_like_key(name: &str) -> bool {
+    let digits = name.strip_prefix("0x").or_else(|| name.strip_prefix("0X")).unwrap_or(name);
+    !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Alias có hợp lệ không: không rỗng, không dạng key hex, không chứa ký tự điều khiển
+pub fn is_valid(alias: &str) -> bool {
+    !alias.is_empty() && !looks_like_key(alias) && !alias.chars().any(char::is_control)
+}
+
+/// fileKey mà `alias` trỏ tới, `None` nếu không có alias này
+pub fn lookup(tree: &sled::Tree, alias: &str) -> sled::Result<Option<String>> {
+    Ok(tree.get(alias)?.map(|target| String::from_utf8_lossy(&target).into_owned()))
+}
+
+/// Trỏ `alias` tới `file_key`; trả về fileKey cũ nếu alias đã tồn tại
+pub fn set(tree: &sled::Tree, alias: &str, file_key: &str) -> sled::Result<Option<String>> {
+    Ok(tree
+        .insert(alias, file_key.as_bytes())?
+        .map(|old| String::from_utf8_lossy(&old).into_owned()))
+}
@@ -0,0 +1,201 @@
+// ## XÁC THỰC BẰNG API TOKEN ##
+//
+// Mặc định ai kết nối được tới cổng HTTP cũng đọc và ghi được. Khi bật STORAGE_AUTH=1, mọi
+// request (trừ `/health` và `/version`) phải kèm `Authorization: Bearer <token>` hoặc
+// `X-Api-Key: <token>`. Mỗi token có một quyền, quyền sau bao gồm quyền trước:
+//
+// - `read`: các request GET/HEAD (đọc chunk, file, manifest, `/events`, `/metrics`...) và
+//   `POST /challenge`, `POST /challenge/sample` (xem module challenge).
+// - `write`: thêm mọi thao tác ghi, xóa, đổi tên, seal, kể cả `/replicate` và `/gossip`.
+// - `admin`: thêm các route quản trị (`/admin/...`, `/alias`, `/sync/full`, unseal...).
+//
+// Thiếu token hoặc token không đúng trả về `401` (`unauthorized`), token không đủ quyền trả
+// về `403` (`insufficient_scope`). Các route quản trị luôn tự kiểm tra quyền `admin`, kể cả
+// khi không bật STORAGE_AUTH.
+//
+// Token đến từ ba nguồn: STORAGE_ADMIN_API_KEY (quyền `admin`), STORAGE_API_TOKENS (danh sách
+// `token=quyền` phân cách bằng dấu phẩy, ví dụ `abc=read,def=write`) và tree `tokens` chứa các
+// token tạo qua `POST /admin/tokens` (`{"scope": "write", "name": "uploader"}`, response chứa
+// token đúng một lần) và thu hồi qua `DELETE /admin/tokens/:id`. Tree chỉ lưu SHA-256 của
+// token; `id` là 16 ký tự hex đầu của hash đó.
+//
+// Node gửi STORAGE_PEER_TOKEN (cần quyền `write`) kèm các request replication và gossip tới
+// peer, để các node cùng bật STORAGE_AUTH vẫn trao đổi được với nhau.
+
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderMap, Method};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+
+use crate::error::ApiError;
+use crate::{constant_time_eq, AppState};
+
+// Tiền tố của token do server tạo, để dễ nhận ra khi bị lộ trong log hoặc mã nguồn
+const TOKEN_PREFIX: &str = "st_";
+
+/// Quyền của một token; quyền sau bao gồm quyền trước
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    Read,
+    Write,
+    Admin,
+}
+
+impl std::str::FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "read" => Ok(Scope::Read),
+            "write" => Ok(Scope::Write),
+            "admin" => Ok(Scope::Admin),
+            other => Err(format!("quyền không hỗ trợ: {}", other)),
+        }
+    }
+}
+
+/// Giá trị của STORAGE_API_TOKENS: các token cố định cùng quyền của chúng
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StaticTokens(Vec<(String, Scope)>);
+
+impl std::str::FromStr for StaticTokens {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(|item| {
+                let (token, scope) = item.rsplit_once('=').ok_or_else(|| "thiếu '=' trong một token".to_string())?;
+                Ok((token.trim().to_string(), scope.parse()?))
+            })
+            .collect::<Result<_, String>>()
+            .map(StaticTokens)
+    }
+}
+
+// Token đã tạo, lưu trong tree `tokens` dưới key là id của nó
+#[derive(Serialize, Deserialize)]
+struct StoredToken {
+    // SHA-256 (hex) của token
+    hash: String,
+    scope: Scope,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(rename = "createdAt")]
+    created_at: u64,
+}
+
+// SHA-256 (hex) của token và id tương ứng
+fn digest(token: &str) -> (String, String) {
+    let hash = hex::encode(Sha256::digest(token.as_bytes()));
+    let id = hash[..16].to_string();
+    (hash, id)
+}
+
+/// Id (16 ký tự hex đầu của SHA-256) của một token, dùng để nhắc tới token mà không lộ nó
+pub fn token_id(token: &str) -> String {
+    digest(token).1
+}
+
+/// Token gửi kèm request, từ `X-Api-Key` hoặc `Authorization: Bearer ...`
+pub fn provided(headers: &HeaderMap) -> Option<&str> {
+    headers.get("x-api-key").and_then(|v| v.to_str().ok()).or_else(|| {
+        headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+    })
+}
+
+/// Quyền của token gửi kèm request; `None` nếu không có token hoặc token không hợp lệ
+pub fn authenticate(state: &AppState, headers: &HeaderMap) -> Result<Option<Scope>, ApiError> {
+    let Some(token) = provided(headers) else {
+        return Ok(None);
+    };
+    let config = state.config.load();
+    if config
+        .admin_api_key
+        .as_deref()
+        .is_some_and(|key| constant_time_eq(token.as_bytes(), key.as_bytes()))
+    {
+        return Ok(Some(Scope::Admin));
+    }
+    if let Some((_, scope)) = config
+        .api_tokens
+        .0
+        .iter()
+        .find(|(known, _)| constant_time_eq(token.as_bytes(), known.as_bytes()))
+    {
+        return Ok(Some(*scope));
+    }
+
+    let (hash, id) = digest(token);
+    let stored = state.tokens.get(&id).map_err(|e| {
+        tracing::error!("Lỗi khi đọc token {}: {}", id, e);
+        ApiError::Internal
+    })?;
+    Ok(stored
+        .and_then(|value| serde_json::from_slice::<StoredToken>(&value).ok())
+        .filter(|stored| constant_time_eq(stored.hash.as_bytes(), hash.as_bytes()))
+        .map(|stored| stored.scope))
+}
+
+// Quyền cần có cho một request; `None` nếu route không cần token ở tầng này (route công khai
+// hoặc route quản trị tự kiểm tra quyền `admin`)
+fn required_scope(method: &Method, path: &str) -> Option<Scope> {
+    if matches!(path, "/health" | "/version") || path.starts_with("/admin/") {
+        return None;
+    }
+    if matches!(*method, Method::GET | Method::HEAD) || matches!(path, "/challenge" | "/challenge/sample") {
+        Some(Scope::Read)
+    } else {
+        Some(Scope::Write)
+    }
+}
+
+/// Middleware kiểm tra token của request khi bật STORAGE_AUTH
+pub async fn enforce(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    if !state.config.load().auth {
+        return next.run(request).await;
+    }
+    let Some(required) = required_scope(request.method(), request.uri().path()) else {
+        return next.run(request).await;
+    };
+    match authenticate(&state, request.headers()) {
+        Ok(Some(scope)) if scope >= required => next.run(request).await,
+        Ok(Some(_)) => ApiError::Forbidden.into_response(),
+        Ok(None) => ApiError::Unauthorized.into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Tạo token mới với quyền `scope`; trả về id và token (chỉ có ở đây, tree chỉ giữ hash)
+pub fn mint(tree: &sled::Tree, scope: Scope, name: Option<String>, now: u64) -> sled::Result<(String, String)> {
+    loop {
+        let mut secret = [0u8; 32];
+        getrandom::getrandom(&mut secret).expect("không lấy được byte ngẫu nhiên từ hệ điều hành");
+        let token = format!("{}{}", TOKEN_PREFIX, hex::encode(secret));
+        let (hash, id) = digest(&token);
+        let stored = StoredToken {
+            hash,
+            scope,
+            name: name.clone(),
+            created_at: now,
+        };
+        let value = serde_json::to_vec(&stored).expect("token luôn serialize được");
+        // Trùng id với token khác (gần như không thể) thì tạo lại
+        if tree.compare_and_swap(&id, None::<&[u8]>, Some(value))?.is_ok() {
+            return Ok((id, token));
+        }
+    }
+}
+
+/// Thu hồi token theo id; trả về `false` nếu không có token này
+pub fn revoke(tree: &sled::Tree, id: &str) -> sled::Result<bool> {
+    Ok(tree.remove(id)?.is_some())
+}
@@ -0,0 +1,117 @@
+// ## SNAPSHOT DATABASE ĐỊNH KỲ ##
+//
+// Khi đặt STORAGE_SNAPSHOT_DIR, task nền cứ mỗi STORAGE_SNAPSHOT_INTERVAL_SECS giây flush
+// database rồi export toàn bộ tree sang một database sled mới trong thư mục
+// `snapshot-<unix giây>` bên trong STORAGE_SNAPSHOT_DIR. Snapshot được ghi vào thư mục tạm
+// `.snapshot-<unix giây>.tmp` và chỉ đổi tên khi đã flush xong, nên thư mục `snapshot-*`
+// nào cũng là một bản hoàn chỉnh. Sau mỗi lần chụp, chỉ giữ lại STORAGE_SNAPSHOT_RETAIN
+// snapshot mới nhất.
+//
+// Khôi phục: dừng server rồi chép thư mục snapshot vào chỗ thư mục database. Export đọc
+// lần lượt từng tree trong lúc server vẫn nhận ghi, nên một lần ghi diễn ra đúng lúc chụp
+// có thể chỉ có mặt ở một phần các tree (ví dụ chunk đã có nhưng entry index chưa có).
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::AppState;
+
+const PREFIX: &str = "snapshot-";
+
+/// Kết quả một lần chụp snapshot
+#[derive(Debug)]
+pub struct Snapshot {
+    pub path: PathBuf,
+    /// Tổng kích thước các file của snapshot trên đĩa
+    pub bytes: u64,
+    /// Số snapshot cũ đã bị xóa
+    pub pruned: usize,
+}
+
+/// Task nền: chụp snapshot định kỳ nếu có STORAGE_SNAPSHOT_DIR. Thư mục, chu kỳ và số bản
+/// giữ lại được đọc lại mỗi vòng nên thay đổi qua `/admin/reload` có hiệu lực từ lần sau.
+pub async fn run(state: Arc<AppState>) {
+    loop {
+        let interval = state.config.load().snapshot_interval_secs;
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+        let config = state.config.load();
+        let Some(dir) = config.snapshot_dir.clone() else {
+            continue;
+        };
+        let retain = config.snapshot_retain;
+
+        let db = state.db.clone();
+        let started = Instant::now();
+        match tokio::task::spawn_blocking(move || create(&db, Path::new(&dir), retain, crate::unix_now())).await {
+            Ok(Ok(snapshot)) => tracing::info!(
+                "📸 Đã chụp snapshot {} ({} byte) trong {:?}, xóa {} snapshot cũ",
+                snapshot.path.display(),
+                snapshot.bytes,
+                started.elapsed(),
+                snapshot.pruned
+            ),
+            Ok(Err(e)) => tracing::error!("Lỗi khi chụp snapshot database: {}", e),
+            Err(e) => tracing::error!("Tác vụ chụp snapshot bị lỗi: {}", e),
+        }
+    }
+}
+
+/// Flush `db` rồi chụp snapshot vào `dir/snapshot-<now>`, sau đó chỉ giữ lại `retain`
+/// snapshot mới nhất (tối thiểu là bản vừa chụp). Chạy đồng bộ.
+pub fn create(db: &sled::Db, dir: &Path, retain: usize, now: u64) -> std::io::Result<Snapshot> {
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(format!("{}{}", PREFIX, now));
+    if path.exists() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("snapshot {} đã tồn tại", path.display()),
+        ));
+    }
+    let tmp = dir.join(format!(".{}{}.tmp", PREFIX, now));
+    if tmp.exists() {
+        // Phần còn lại của một lần chụp bị ngắt giữa chừng
+        std::fs::remove_dir_all(&tmp)?;
+    }
+
+    // Flush trước để snapshot phản ánh mọi lần ghi đã trả về cho client
+    db.flush().map_err(std::io::Error::other)?;
+    let copy = sled::Config::new().path(&tmp).open().map_err(std::io::Error::other)?;
+    copy.import(db.export());
+    copy.flush().map_err(std::io::Error::other)?;
+    drop(copy);
+    std::fs::rename(&tmp, &path)?;
+
+    let bytes = dir_size(&path)?;
+    let pruned = prune(dir, retain.max(1))?;
+    Ok(Snapshot { path, bytes, pruned })
+}
+
+// Xóa các snapshot cũ nhất, chỉ giữ `retain` bản mới nhất
+fn prune(dir: &Path, retain: usize) -> std::io::Result<usize> {
+    let mut snapshots: Vec<(u64, PathBuf)> = std::fs::read_dir(dir)?
+        .flatten()
+        .filter_map(|entry| {
+            let taken_at = entry.file_name().to_str()?.strip_prefix(PREFIX)?.parse().ok()?;
+            Some((taken_at, entry.path()))
+        })
+        .collect();
+    snapshots.sort_unstable_by_key(|(taken_at, _)| std::cmp::Reverse(*taken_at));
+
+    let mut pruned = 0;
+    for (_, path) in snapshots.iter().skip(retain) {
+        std::fs::remove_dir_all(path)?;
+        pruned += 1;
+    }
+    Ok(pruned)
+}
+
+fn dir_size(path: &Path) -> std::io::Result<u64> {
+    let mut total = 0;
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        total += if meta.is_dir() { dir_size(&entry.path())? } else { meta.len() };
+    }
+    Ok(total)
+}
@@ -0,0 +1,362 @@
+// ## BENCHMARK TÍCH HỢP (--bench) ##
+//
+// Chạy `rust-p2p-storage --bench [--ops N] [--concurrency N] [--chunk-size BYTES]
+// [--chunks-per-file N] [--large-size BYTES] [--cache-bytes BYTES] [--batch-size N]
+// [--reads N] [--hot-reads N]` để đo
+// throughput của node mà không cần công cụ bên ngoài.
+// Benchmark mở một database tạm (bị xóa khi kết thúc), dựng cùng Router với server thật
+// rồi gọi thẳng vào nó (không qua socket), nên đo đúng handler, middleware và cấu hình
+// STORAGE_* hiện tại. Có hai pha: ghi `ops` chunk tổng hợp qua POST /store, sau đó đọc
+// `ops` lần qua GET /file/:fileKey, mỗi pha in ops/giây và các percentile độ trễ.
+//
+// Với `--large-size BYTES`, mỗi file nhỏ có thêm một file chứa một chunk lớn nằm cạnh nó
+// trong shard. Các file lớn được ghi và đọc hết trước pha đọc file nhỏ, nên pha `retrieve`
+// cho thấy độ trễ của chunk nhỏ sau khi chunk lớn đã đi qua cache. Kết hợp với
+// `--cache-bytes` (cache nhỏ của sled) và chạy lại với STORAGE_LARGE_CHUNK_BYTES để so
+// sánh khi tách chunk lớn ra tree riêng (xem module tier). Sled không công bố số lần
+// trúng cache nên độ trễ đọc là thước đo thay thế.
+//
+// Với `--batch-size N`, pha ghi gửi các chunk qua POST /store/batch, mỗi request N chunk,
+// và in thêm số chunk/giây. Chạy lại với các giá trị STORAGE_BATCH_PARALLELISM khác nhau
+// để so sánh tốc độ nạp batch lớn khi ghi song song.
+//
+// `--reads N` đặt số request của pha đọc (mặc định bằng `ops`). Ví dụ `--ops 5000
+// --chunks-per-file 5000 --reads 100` đo việc đọc một file 5k chunk; chạy lại với các giá
+// trị STORAGE_READ_PARALLELISM khác nhau để so sánh đọc tuần tự và đọc song song.
+//
+// `--hot-reads N` thêm pha `hot`: N request cùng đọc file đầu tiên với `concurrency` worker,
+// giống lúc nhiều client đổ vào một file nóng, rồi in số lượt quét database thực sự chạy.
+// Chạy với và không với STORAGE_READ_COALESCING để so sánh lượng việc của đĩa.
+
+use axum::{
+    body::{to_bytes, Body},
+    http::{header, Method, Request},
+    Router,
+};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tower::ServiceExt;
+
+use crate::{build_app, build_state, Config};
+
+/// Tham số của benchmark
+#[derive(Debug, Clone)]
+pub struct Options {
+    // Số request của mỗi pha
+    pub ops: u64,
+    // Số request chạy đồng thời
+    pub concurrency: usize,
+    // Kích thước dữ liệu mỗi chunk (bytes, trước khi mã hóa Base64)
+    pub chunk_size: usize,
+    // Số chunk mỗi file; pha đọc lấy cả file một lần
+    pub chunks_per_file: u64,
+    // Kích thước chunk của các file lớn xen kẽ; 0 thì không tạo file lớn
+    pub large_size: usize,
+    // Dung lượng cache của sled cho database tạm; không đặt thì dùng mặc định của sled
+    pub cache_bytes: Option<u64>,
+    // Số chunk mỗi request /store/batch ở pha ghi; 0 thì ghi từng chunk qua /store
+    pub batch_size: u64,
+    // Số request của pha đọc; không đặt thì bằng `ops`
+    pub reads: Option<u64>,
+    // Số request của pha đọc dồn vào một file; 0 thì bỏ qua pha này
+    pub hot_reads: u64,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            ops: 10_000,
+            concurrency: 32,
+            chunk_size: 4096,
+            chunks_per_file: 16,
+            large_size: 0,
+            cache_bytes: None,
+            batch_size: 0,
+            reads: None,
+            hot_reads: 0,
+        }
+    }
+}
+
+impl Options {
+    /// Đọc tham số từ các đối số dòng lệnh đứng sau `--bench`
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+        while let Some(name) = args.next() {
+            let value = args.next().ok_or_else(|| format!("thiếu giá trị cho {}", name))?;
+            let invalid = |_| format!("giá trị không hợp lệ cho {}: {:?}", name, value);
+            match name.as_str() {
+                "--ops" => options.ops = value.parse().map_err(invalid)?,
+                "--concurrency" => options.concurrency = value.parse().map_err(invalid)?,
+                "--chunk-size" => options.chunk_size = value.parse().map_err(invalid)?,
+                "--chunks-per-file" => options.chunks_per_file = value.parse().map_err(invalid)?,
+                "--large-size" => options.large_size = value.parse().map_err(invalid)?,
+                "--cache-bytes" => options.cache_bytes = Some(value.parse().map_err(invalid)?),
+                "--batch-size" => options.batch_size = value.parse().map_err(invalid)?,
+                "--reads" => options.reads = Some(value.parse().map_err(invalid)?),
+                "--hot-reads" => options.hot_reads = value.parse().map_err(invalid)?,
+                other => return Err(format!("đối số không hỗ trợ: {}", other)),
+            }
+        }
+        if options.ops == 0 || options.concurrency == 0 || options.chunks_per_file == 0 {
+            return Err("--ops, --concurrency và --chunks-per-file phải lớn hơn 0".to_string());
+        }
+        Ok(options)
+    }
+
+    fn files(&self) -> u64 {
+        self.ops.div_ceil(self.chunks_per_file)
+    }
+}
+
+// Kết quả của một pha
+struct Report {
+    elapsed: Duration,
+    latencies: Vec<Duration>,
+    failures: u64,
+}
+
+impl Report {
+    fn print(&self, phase: &str) {
+        let ops = self.latencies.len() as f64 / self.elapsed.as_secs_f64();
+        println!(
+            "{:<8} {:>8} ops trong {:>7.2}s  {:>10.0} ops/s  p50 {:>8.2}ms  p90 {:>8.2}ms  p99 {:>8.2}ms  max {:>8.2}ms  lỗi {}",
+            phase,
+            self.latencies.len(),
+            self.elapsed.as_secs_f64(),
+            ops,
+            millis(self.percentile(0.50)),
+            millis(self.percentile(0.90)),
+            millis(self.percentile(0.99)),
+            millis(self.latencies.last().copied().unwrap_or_default()),
+            self.failures,
+        );
+    }
+
+    // `latencies` đã được sắp xếp tăng dần
+    fn percentile(&self, p: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::ZERO;
+        }
+        let rank = ((self.latencies.len() as f64 * p).ceil() as usize).max(1);
+        self.latencies[rank.min(self.latencies.len()) - 1]
+    }
+}
+
+fn millis(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+fn file_key(file: u64) -> String {
+    format!("0xbench{:08x}", file)
+}
+
+// Đứng ngay sau file nhỏ cùng số thứ tự theo thứ tự key
+fn large_file_key(file: u64) -> String {
+    format!("0xbench{:08x}-large", file)
+}
+
+// Dữ liệu tổng hợp khác nhau giữa các chunk để nén (nếu bật) không thu nhỏ bất thường
+fn synthetic_chunk(op: u64, size: usize) -> String {
+    let mut state = op.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1;
+    let data: Vec<u8> = (0..size)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect();
+    STANDARD.encode(data)
+}
+
+fn store_payload(options: &Options, op: u64) -> serde_json::Value {
+    serde_json::json!({
+        "fileKey": file_key(op / options.chunks_per_file),
+        "chunkHash": format!("0x{:016x}", op),
+        "chunkData": synthetic_chunk(op, options.chunk_size),
+        "chunkIndex": op % options.chunks_per_file,
+    })
+}
+
+fn store_request(options: &Options, op: u64) -> Request<Body> {
+    let body = store_payload(options, op);
+    Request::builder()
+        .method(Method::POST)
+        .uri("/store")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .expect("request benchmark hợp lệ")
+}
+
+// Request thứ `request` chứa các chunk `[request * batch_size, ...)` trong `ops` chunk
+fn store_batch_request(options: &Options, request: u64) -> Request<Body> {
+    let first = request * options.batch_size;
+    let last = (first + options.batch_size).min(options.ops);
+    let body: Vec<_> = (first..last).map(|op| store_payload(options, op)).collect();
+    Request::builder()
+        .method(Method::POST)
+        .uri("/store/batch")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::Value::from(body).to_string()))
+        .expect("request benchmark hợp lệ")
+}
+
+fn store_large_request(options: &Options, op: u64) -> Request<Body> {
+    let body = serde_json::json!({
+        "fileKey": large_file_key(op),
+        "chunkHash": "0xlarge",
+        "chunkData": synthetic_chunk(u64::MAX - op, options.large_size),
+        "chunkIndex": 0,
+    });
+    Request::builder()
+        .method(Method::POST)
+        .uri("/store")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .expect("request benchmark hợp lệ")
+}
+
+fn retrieve_large_request(_options: &Options, op: u64) -> Request<Body> {
+    Request::builder()
+        .uri(format!("/file/{}", large_file_key(op)))
+        .body(Body::empty())
+        .expect("request benchmark hợp lệ")
+}
+
+fn hot_request(_options: &Options, _op: u64) -> Request<Body> {
+    Request::builder()
+        .uri(format!("/file/{}", file_key(0)))
+        .body(Body::empty())
+        .expect("request benchmark hợp lệ")
+}
+
+fn retrieve_request(options: &Options, op: u64) -> Request<Body> {
+    Request::builder()
+        .uri(format!("/file/{}", file_key(op % options.files())))
+        .body(Body::empty())
+        .expect("request benchmark hợp lệ")
+}
+
+// Chạy `ops` request với `options.concurrency` worker, mỗi worker lấy số thứ tự của
+// request tiếp theo từ bộ đếm chung
+async fn run_phase(app: &Router, options: &Options, ops: u64, request: fn(&Options, u64) -> Request<Body>) -> Report {
+    let next = Arc::new(AtomicU64::new(0));
+    let started = Instant::now();
+    let mut workers = Vec::with_capacity(options.concurrency);
+    for _ in 0..options.concurrency {
+        let (app, options, next) = (app.clone(), options.clone(), next.clone());
+        workers.push(tokio::spawn(async move {
+            let mut latencies = Vec::new();
+            let mut failures = 0;
+            loop {
+                let op = next.fetch_add(1, Ordering::Relaxed);
+                if op >= ops {
+                    break;
+                }
+                let begin = Instant::now();
+                let ok = match app.clone().oneshot(request(&options, op)).await {
+                    // Đọc hết body để tính cả thời gian tạo response
+                    Ok(response) => {
+                        let success = response.status().is_success();
+                        to_bytes(response.into_body(), usize::MAX).await.is_ok() && success
+                    }
+                    Err(never) => match never {},
+                };
+                latencies.push(begin.elapsed());
+                if !ok {
+                    failures += 1;
+                }
+            }
+            (latencies, failures)
+        }));
+    }
+
+    let mut report = Report {
+        elapsed: Duration::ZERO,
+        latencies: Vec::with_capacity(ops as usize),
+        failures: 0,
+    };
+    for worker in workers {
+        let (latencies, failures) = worker.await.expect("worker benchmark bị panic");
+        report.latencies.extend(latencies);
+        report.failures += failures;
+    }
+    report.elapsed = started.elapsed();
+    report.latencies.sort_unstable();
+    report
+}
+
+/// Chạy benchmark trên database tạm với cấu hình `config`
+pub async fn run(options: Options, config: Config) {
+    let mut db_config = sled::Config::new().temporary(true);
+    if let Some(bytes) = options.cache_bytes {
+        db_config = db_config.cache_capacity(bytes);
+    }
+    let db = db_config
+        .open()
+        .expect("Không thể mở database tạm cho benchmark");
+    let state = build_state(db, config);
+    let app = build_app(state.clone());
+
+    println!(
+        "📊 Benchmark: {} ops, {} đồng thời, chunk {} bytes, {} chunks/file ({} file)",
+        options.ops,
+        options.concurrency,
+        options.chunk_size,
+        options.chunks_per_file,
+        options.files(),
+    );
+    let store = if options.batch_size > 0 {
+        run_phase(&app, &options, options.ops.div_ceil(options.batch_size), store_batch_request).await
+    } else {
+        run_phase(&app, &options, options.ops, store_request).await
+    };
+    let large = if options.large_size > 0 {
+        let files = options.files();
+        let stored = run_phase(&app, &options, files, store_large_request).await;
+        Some((stored, run_phase(&app, &options, files, retrieve_large_request).await))
+    } else {
+        None
+    };
+    let retrieve = run_phase(&app, &options, options.reads.unwrap_or(options.ops), retrieve_request).await;
+    let hot = if options.hot_reads > 0 {
+        let coalesced_before = state.metrics.coalesced_reads.load(Ordering::Relaxed);
+        let report = run_phase(&app, &options, options.hot_reads, hot_request).await;
+        let coalesced = state.metrics.coalesced_reads.load(Ordering::Relaxed) - coalesced_before;
+        Some((report, coalesced))
+    } else {
+        None
+    };
+
+    // In sau cùng để kết quả không lẫn vào log của các handler
+    println!("📊 Kết quả benchmark:");
+    if options.batch_size > 0 {
+        store.print("batch");
+        println!(
+            "{:<8} {:>10.0} chunks/s",
+            "",
+            options.ops as f64 / store.elapsed.as_secs_f64()
+        );
+    } else {
+        store.print("store");
+    }
+    if let Some((stored, retrieved)) = &large {
+        stored.print("store-l");
+        retrieved.print("read-l");
+    }
+    retrieve.print("retrieve");
+    if let Some((report, coalesced)) = &hot {
+        report.print("hot");
+        println!(
+            "{:<8} {} lượt quét cho {} request ({} request dùng chung lượt quét khác)",
+            "",
+            options.hot_reads - coalesced,
+            options.hot_reads,
+            coalesced
+        );
+    }
+}
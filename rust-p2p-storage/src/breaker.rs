@@ -0,0 +1,132 @@
+// ## CIRCUIT BREAKER CHO TỪNG PEER ##
+//
+// Peer lỗi liên tục (tắt máy, mạng đứt, trả về `5xx`) thì gọi tiếp chỉ thêm độ trễ và log.
+// Mỗi peer có một breaker riêng, dùng cho các lần gửi của hàng đợi replication:
+//
+// - `closed`: gọi bình thường. Sau STORAGE_BREAKER_FAILURES lần lỗi liên tiếp thì chuyển
+//   sang `open`.
+// - `open`: không gọi peer. Entry replication của peer được hẹn lại tới khi hết
+//   STORAGE_BREAKER_COOLDOWN_MS mà không tính là một lần thử.
+// - `half_open`: hết thời gian chờ thì một lần gọi được đi qua để thử. Thành công thì
+//   breaker đóng lại (`closed`), lỗi thì mở lại (`open`) và chờ thêm một lần nữa. Lần thử
+//   không có kết quả sau thời gian chờ thì một lần thử khác được phép.
+//
+// STORAGE_BREAKER_FAILURES=0 tắt breaker. Gossip không dùng breaker vì đã có cơ chế nghi
+// ngờ/quên peer riêng (xem module gossip). Trạng thái breaker của từng peer nằm trong
+// `/diagnostics` và chỉ giữ trong bộ nhớ.
+
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy)]
+enum Phase {
+    Closed,
+    // Thời điểm breaker mở
+    Open(Instant),
+    // Thời điểm lần gọi thử được cho qua
+    HalfOpen(Instant),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Breaker {
+    phase: Phase,
+    // Số lần lỗi liên tiếp
+    failures: u32,
+}
+
+/// Trạng thái breaker của một peer trong `/diagnostics`
+#[derive(Debug, Serialize)]
+pub struct PeerBreaker {
+    // "closed", "open" hoặc "half_open"
+    pub state: &'static str,
+    #[serde(rename = "consecutiveFailures")]
+    pub consecutive_failures: u32,
+}
+
+#[derive(Default)]
+pub struct Breakers {
+    peers: Mutex<HashMap<String, Breaker>>,
+}
+
+impl Breakers {
+    /// Có được gọi `peer` lúc này không; `Err` chứa thời gian còn phải chờ trước khi peer
+    /// được thử lại (0 khi đang có một lần gọi thử chưa xong)
+    pub fn allow(&self, peer: &str, threshold: u32, cooldown: Duration) -> Result<(), Duration> {
+        if threshold == 0 {
+            return Ok(());
+        }
+        let mut peers = self.peers.lock().unwrap();
+        let Some(breaker) = peers.get_mut(peer) else {
+            return Ok(());
+        };
+        match breaker.phase {
+            Phase::Closed => Ok(()),
+            Phase::Open(since) | Phase::HalfOpen(since) if since.elapsed() >= cooldown => {
+                breaker.phase = Phase::HalfOpen(Instant::now());
+                Ok(())
+            }
+            Phase::Open(since) => Err(cooldown - since.elapsed()),
+            Phase::HalfOpen(_) => Err(Duration::ZERO),
+        }
+    }
+
+    /// Ghi nhận kết quả một lần gọi `peer`
+    pub fn record(&self, peer: &str, ok: bool, threshold: u32) {
+        let mut peers = self.peers.lock().unwrap();
+        if ok {
+            let recovered = peers.remove(peer).is_some_and(|breaker| !matches!(breaker.phase, Phase::Closed));
+            if recovered {
+                tracing::info!("✅ Peer {} đã phục hồi, đóng circuit breaker", peer);
+            }
+            return;
+        }
+        let breaker = peers.entry(peer.to_string()).or_insert(Breaker {
+            phase: Phase::Closed,
+            failures: 0,
+        });
+        breaker.failures += 1;
+        let trip = match breaker.phase {
+            Phase::Closed => threshold > 0 && breaker.failures >= threshold,
+            Phase::HalfOpen(_) => true,
+            Phase::Open(_) => false,
+        };
+        if trip {
+            breaker.phase = Phase::Open(Instant::now());
+            tracing::error!("⚠️  Peer {} lỗi {} lần liên tiếp, mở circuit breaker", peer, breaker.failures);
+        }
+    }
+
+    /// Trạng thái breaker của các peer `known` và mọi peer đang lỗi
+    pub fn snapshot(&self, known: &[String]) -> BTreeMap<String, PeerBreaker> {
+        let peers = self.peers.lock().unwrap();
+        let mut snapshot: BTreeMap<String, PeerBreaker> = known
+            .iter()
+            .map(|peer| {
+                (
+                    peer.clone(),
+                    PeerBreaker {
+                        state: "closed",
+                        consecutive_failures: 0,
+                    },
+                )
+            })
+            .collect();
+        for (peer, breaker) in peers.iter() {
+            let state = match breaker.phase {
+                Phase::Closed => "closed",
+                Phase::Open(_) => "open",
+                Phase::HalfOpen(_) => "half_open",
+            };
+            snapshot.insert(
+                peer.clone(),
+                PeerBreaker {
+                    state,
+                    consecutive_failures: breaker.failures,
+                },
+            );
+        }
+        snapshot
+    }
+}
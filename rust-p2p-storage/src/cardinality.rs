@@ -0,0 +1,268 @@
+// ## SỐ LƯỢNG FILEKEY KHÁC NHAU ##
+//
+// Tree `file_chunks` giữ số chunk và tổng số byte của từng fileKey (`FileStats`, hai u64
+// big-endian; tổng số byte tính như quota: record trong shard cộng value trong
+// `chunks_large`). Lần ghi chunk cập nhật tree này cùng transaction với chunk (xem
+// `Quotas::write_chunks`), nên khi một fileKey có chunk đầu tiên thì bộ đếm số file tăng
+// lên. Đổi tên và xóa file cập nhật tree trong transaction chuyển hoặc xóa chunk (xem
+// `move_in`, `remove_in`); sửa metadata hay CAS thì cập nhật lại sau khi thao tác thành công,
+// giống cách dung lượng quota được điều chỉnh. Tree này cũng là index cho `GET /files` (xem `list`), nên liệt kê file
+// không phải quét các shard.
+//
+// Database cũ chỉ lưu số chunk (một u64); lần mở đầu tiên dựng lại cả tree từ các shard.
+//
+// Số file được giữ trong bộ nhớ để `/metrics` không phải quét toàn bộ database. Khi vượt
+// ngưỡng STORAGE_FILE_KEYS_ALERT, server ghi cảnh báo một lần mỗi khi vượt lên (quét
+// prefix chậm dần khi số file quá lớn) và gauge `storage_file_keys_alert` bằng 1.
+
+use std::collections::HashMap;
+use std::ops::Bound;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use sled::transaction::{TransactionalTree, UnabortableTransactionError};
+
+pub struct FileCounts {
+    // fileKey -> số chunk đang lưu
+    pub tree: sled::Tree,
+    // Số fileKey khác nhau đang có ít nhất một chunk
+    distinct: AtomicU64,
+    // Đã cảnh báo cho lần vượt ngưỡng hiện tại hay chưa
+    alerted: AtomicBool,
+}
+
+impl FileCounts {
+    /// Mở bộ đếm, dựng lại từ các shard nếu tree còn trống (database có dữ liệu từ trước)
+    /// hoặc còn ở định dạng chỉ có số chunk
+    pub fn open(tree: sled::Tree, shards: &[sled::Tree], large: &crate::tier::Large) -> Self {
+        let legacy = matches!(tree.first(), Ok(Some((_, value))) if value.len() < STATS_LEN);
+        if tree.is_empty() || legacy {
+            rebuild(&tree, shards, large);
+        }
+        let distinct = tree.len() as u64;
+        FileCounts {
+            tree,
+            distinct: AtomicU64::new(distinct),
+            alerted: AtomicBool::new(false),
+        }
+    }
+
+    pub fn distinct(&self) -> u64 {
+        self.distinct.load(Ordering::Relaxed)
+    }
+
+    /// Ghi nhận `new_files` fileKey vừa có chunk đầu tiên (do `write_chunks` trả về)
+    pub fn added(&self, new_files: u64) {
+        self.distinct.fetch_add(new_files, Ordering::Relaxed);
+    }
+
+    /// Ghi nhận một chunk mới `bytes` byte của `file_key` được tạo ngoài `write_chunks`
+    /// (ví dụ qua CAS)
+    pub fn created(&self, file_key: &str, bytes: u64) {
+        let result = self.tree.update_and_fetch(file_key, |old| {
+            let stats = old.map(FileStats::decode).unwrap_or_default();
+            Some(FileStats { chunks: stats.chunks + 1, bytes: stats.bytes + bytes }.encode().to_vec())
+        });
+        match result {
+            Ok(Some(stats)) if decode(&stats) == 1 => {
+                self.distinct.fetch_add(1, Ordering::Relaxed);
+            }
+            Ok(_) => {}
+            Err(e) => tracing::error!("Lỗi khi cập nhật số chunk của {}: {}", file_key, e),
+        }
+    }
+
+    /// Cộng (hoặc trừ) `delta` byte vào tổng của `file_key` khi một chunk đã có đổi kích
+    /// thước (sửa metadata, CAS); không tạo entry cho fileKey chưa có
+    pub fn resized(&self, file_key: &str, delta: i64) {
+        let result = self.tree.update_and_fetch(file_key, |old| {
+            let stats = FileStats::decode(old?);
+            Some(FileStats { bytes: stats.bytes.saturating_add_signed(delta), ..stats }.encode().to_vec())
+        });
+        if let Err(e) = result {
+            tracing::error!("Lỗi khi cập nhật dung lượng của {}: {}", file_key, e);
+        }
+    }
+
+    /// Ghi nhận một chunk `freed` byte của `file_key` bị xóa riêng lẻ (ví dụ khi hết hạn, xem
+    /// module ttl). Trả về `true` nếu đó là chunk cuối cùng của file.
+    pub fn chunk_removed(&self, file_key: &str, freed: u64) -> bool {
+        let result = self.tree.fetch_and_update(file_key, |old| {
+            let stats = old.map(FileStats::decode).unwrap_or_default();
+            let chunks = stats.chunks.saturating_sub(1);
+            (chunks > 0).then(|| FileStats { chunks, bytes: stats.bytes.saturating_sub(freed) }.encode().to_vec())
+        });
+        match result {
+            Ok(Some(old)) if decode(&old) <= 1 => {
+                self.distinct.fetch_sub(1, Ordering::Relaxed);
+                true
+            }
+            Ok(_) => false,
+            Err(e) => {
+                tracing::error!("Lỗi khi cập nhật số chunk của {}: {}", file_key, e);
+                false
+            }
+        }
+    }
+
+    /// Cộng `delta` (có thể âm) vào số fileKey sau khi tree đã được cập nhật trong một
+    /// transaction (xem `move_in`)
+    pub fn shift(&self, delta: i64) {
+        if delta >= 0 {
+            self.distinct.fetch_add(delta as u64, Ordering::Relaxed);
+        } else {
+            self.distinct.fetch_sub(delta.unsigned_abs(), Ordering::Relaxed);
+        }
+    }
+
+    /// Đếm lại số fileKey từ tree sau khi tree được dựng lại (xem module reindex)
+    pub fn recount(&self) {
+        self.distinct.store(self.tree.len() as u64, Ordering::Relaxed);
+    }
+
+    /// So số file hiện tại với ngưỡng cảnh báo; trả về `true` khi đang vượt ngưỡng
+    pub fn check(&self, threshold: Option<u64>) -> bool {
+        let count = self.distinct();
+        let over = threshold.is_some_and(|limit| count > limit);
+        if !over {
+            self.alerted.store(false, Ordering::Relaxed);
+        } else if !self.alerted.swap(true, Ordering::Relaxed) {
+            tracing::warn!(
+                file_keys = count,
+                threshold = threshold.unwrap_or_default(),
+                "Số fileKey đã vượt ngưỡng cảnh báo, truy vấn theo prefix có thể chậm dần"
+            );
+        }
+        over
+    }
+}
+
+/// Ghi nhận `chunks` chunk của `from` đã chuyển sang `to` (thay thế chunk cũ của `to`),
+/// trong transaction của lần đổi tên (`files` là tree `file_chunks` trong transaction). Tổng
+/// số byte đi theo entry của `from`. Trả về thay đổi của số fileKey để truyền cho
+/// `FileCounts::shift` sau khi transaction thành công.
+pub fn move_in(files: &TransactionalTree, from: &[u8], to: &[u8], chunks: u64) -> Result<i64, UnabortableTransactionError> {
+    let old_from = files.remove(from)?;
+    let bytes = old_from.as_deref().map_or(0, |v| FileStats::decode(v).bytes);
+    let old_to = files.insert(to, &FileStats { chunks, bytes }.encode())?;
+    Ok(i64::from(old_to.is_none()) - i64::from(old_from.is_some()))
+}
+
+/// Ghi nhận fileKey đã bị xóa hết chunk, trong transaction của lần xóa file. Trả về thay đổi
+/// của số fileKey như `move_in`.
+pub fn remove_in(files: &TransactionalTree, file_key: &[u8]) -> Result<i64, UnabortableTransactionError> {
+    Ok(-i64::from(files.remove(file_key)?.is_some()))
+}
+
+/// Cộng (hoặc trừ) `delta` byte vào tổng của `file_key` như `FileCounts::resized`, trong
+/// transaction của thao tác làm chunk đổi kích thước (xem `dedup::migrate`)
+pub fn resize_in(files: &TransactionalTree, file_key: &[u8], delta: i64) -> Result<(), UnabortableTransactionError> {
+    if let Some(old) = files.get(file_key)? {
+        let stats = FileStats::decode(&old);
+        files.insert(file_key, &FileStats { bytes: stats.bytes.saturating_add_signed(delta), ..stats }.encode())?;
+    }
+    Ok(())
+}
+
+/// Đọc một u64 big-endian, ví dụ số chunk ở đầu value của tree `file_chunks`
+pub fn decode(bytes: &[u8]) -> u64 {
+    bytes.get(..8).and_then(|b| b.try_into().ok()).map_or(0, u64::from_be_bytes)
+}
+
+// Độ dài value của tree `file_chunks`
+const STATS_LEN: usize = 16;
+
+/// Số chunk và tổng số byte của một fileKey, value của tree `file_chunks`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FileStats {
+    pub chunks: u64,
+    pub bytes: u64,
+}
+
+impl FileStats {
+    /// Đọc value của tree `file_chunks`; value cũ chỉ có số chunk thì tổng số byte là 0
+    pub fn decode(value: &[u8]) -> Self {
+        FileStats {
+            chunks: decode(value),
+            bytes: value.get(8..).map_or(0, decode),
+        }
+    }
+
+    pub fn encode(self) -> [u8; STATS_LEN] {
+        let mut value = [0; STATS_LEN];
+        value[..8].copy_from_slice(&self.chunks.to_be_bytes());
+        value[8..].copy_from_slice(&self.bytes.to_be_bytes());
+        value
+    }
+}
+
+/// Một fileKey trong danh sách của `GET /files`
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct FileEntry {
+    #[serde(rename = "fileKey")]
+    pub file_key: String,
+    pub chunks: u64,
+    pub bytes: u64,
+}
+
+/// Tối đa `limit` fileKey dạng chữ bắt đầu bằng `prefix`, theo thứ tự byte và sau fileKey
+/// `after` nếu có. Trả về thêm fileKey cuối cùng khi còn trang sau. fileKey nhị phân (xem
+/// `b64_file_key`) bị bỏ qua.
+pub fn list(tree: &sled::Tree, prefix: &str, after: Option<&str>, limit: usize) -> sled::Result<(Vec<FileEntry>, Option<String>)> {
+    let mut entries = Vec::new();
+    let mut iter = match after {
+        Some(after) if after >= prefix => tree.range::<&[u8], _>((Bound::Excluded(after.as_bytes()), Bound::Unbounded)),
+        _ => tree.range::<&[u8], _>(prefix.as_bytes()..),
+    };
+    for item in iter.by_ref() {
+        let (file_key, value) = item?;
+        if !file_key.starts_with(prefix.as_bytes()) {
+            return Ok((entries, None));
+        }
+        let Ok(file_key) = std::str::from_utf8(&file_key) else {
+            continue;
+        };
+        if entries.len() == limit {
+            let last = entries.last().map(|e: &FileEntry| e.file_key.clone());
+            return Ok((entries, last));
+        }
+        let stats = FileStats::decode(&value);
+        entries.push(FileEntry {
+            file_key: file_key.to_string(),
+            chunks: stats.chunks,
+            bytes: stats.bytes,
+        });
+    }
+    Ok((entries, None))
+}
+
+fn rebuild(tree: &sled::Tree, shards: &[sled::Tree], large: &crate::tier::Large) {
+    let mut stats: HashMap<Vec<u8>, FileStats> = HashMap::new();
+    for (key, value) in shards.iter().flat_map(|shard| shard.iter()).flatten() {
+        let Some((file_key, _)) = crate::key::decode_raw(&key) else {
+            continue;
+        };
+        let large_len = match crate::record::decode(&value) {
+            Some(record) if record.large => large.get(&key).ok().flatten().map_or(0, |v| v.len()),
+            _ => 0,
+        };
+        let entry = stats.entry(file_key.to_vec()).or_default();
+        entry.chunks += 1;
+        entry.bytes += (value.len() + large_len) as u64;
+    }
+    if let Err(e) = tree.clear() {
+        tracing::error!("Lỗi khi dựng bộ đếm số file: {}", e);
+        return;
+    }
+    if stats.is_empty() {
+        return;
+    }
+
+    tracing::info!("-> Đang đếm lại số chunk và dung lượng của {} file", stats.len());
+    let mut batch = sled::Batch::default();
+    for (file_key, stats) in stats {
+        batch.insert(file_key, &stats.encode());
+    }
+    if let Err(e) = tree.apply_batch(batch) {
+        tracing::error!("Lỗi khi dựng bộ đếm số file: {}", e);
+    }
+}
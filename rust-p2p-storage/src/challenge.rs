@@ -0,0 +1,222 @@
+// ## THÁCH THỨC CHỨNG MINH LƯU TRỮ (PROOF OF STORAGE) ##
+//
+// Bên kiểm toán giữ một bản dữ liệu (hoặc các hash đã tính trước) và muốn biết node còn giữ
+// chunk hay không mà không phải tải cả chunk về. Node chỉ trả lời đúng được khi đọc lại dữ
+// liệu đang lưu, vì `nonce` do bên kiểm toán chọn mới cho mỗi lần hỏi.
+//
+// `POST /challenge` với `{"fileKey", "chunkHash", "nonce"}` trả về
+// `proof = hex(SHA-256(nonce || dữ liệu của chunk))`, trong đó `nonce` là chuỗi hex (tối đa
+// `MAX_NONCE_BYTES` byte sau khi giải mã) và dữ liệu là bytes gốc của chunk như
+// `/chunk/raw/...` trả về (đã giải nén, giải mã hóa).
+//
+// `POST /challenge/sample` với `{"fileKey", "nonce", "samples", "length"}` kiểm tra cả file
+// bằng `samples` mẫu (mặc định 16, tối đa STORAGE_CHALLENGE_MAX_SAMPLES), mỗi mẫu là
+// `length` byte (mặc định 64) của một chunk. Vị trí mẫu được suy ra từ nonce để bên kiểm
+// toán tự tính lại được mà node không chọn được mẫu có lợi cho mình: với mẫu thứ `i`,
+// `seed = SHA-256(nonce || i)` (`i` là u64 big-endian), chunk được chọn là chunk thứ
+// `seed[0..8] mod n` trong các chunk của file xếp theo thứ tự byte của chunkHash, offset là
+// `seed[8..16] mod (size - length + 1)` (0 nếu chunk ngắn hơn `length`, khi đó mẫu là cả
+// chunk). Mỗi mẫu có `proof = hex(SHA-256(nonce || i || bytes của mẫu))`; `proof` của cả
+// response là SHA-256 của các proof mẫu (dạng bytes) nối theo thứ tự.
+//
+// Cả hai route chỉ đọc nên chỉ cần quyền `read` khi bật STORAGE_AUTH (xem module auth).
+
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::error::ApiError;
+use crate::{blocking, key, AppState};
+
+/// Độ dài tối đa (byte) của nonce sau khi giải mã hex
+pub const MAX_NONCE_BYTES: usize = 256;
+
+const DEFAULT_SAMPLES: usize = 16;
+const DEFAULT_SAMPLE_LENGTH: usize = 64;
+
+// Body của POST /challenge
+#[derive(Deserialize)]
+pub(crate) struct ChallengeRequest {
+    #[serde(rename = "fileKey")]
+    file_key: String,
+    #[serde(rename = "chunkHash")]
+    chunk_hash: String,
+    nonce: String,
+}
+
+// Response của POST /challenge
+#[derive(Serialize)]
+pub(crate) struct ChallengeResponse {
+    #[serde(rename = "fileKey")]
+    file_key: String,
+    #[serde(rename = "chunkHash")]
+    chunk_hash: String,
+    // Kích thước dữ liệu gốc của chunk
+    size: usize,
+    proof: String,
+}
+
+// Body của POST /challenge/sample
+#[derive(Deserialize)]
+pub(crate) struct SampleRequest {
+    #[serde(rename = "fileKey")]
+    file_key: String,
+    nonce: String,
+    samples: Option<usize>,
+    length: Option<usize>,
+}
+
+// Một mẫu trong response của POST /challenge/sample
+#[derive(Serialize)]
+struct Sample {
+    #[serde(rename = "chunkHash")]
+    chunk_hash: String,
+    offset: usize,
+    // Số byte thực sự được lấy, nhỏ hơn `length` được yêu cầu khi chunk ngắn hơn
+    length: usize,
+    proof: String,
+}
+
+// Response của POST /challenge/sample
+#[derive(Serialize)]
+pub(crate) struct SampleResponse {
+    #[serde(rename = "fileKey")]
+    file_key: String,
+    // Số chunk của file mà các mẫu được chọn từ đó
+    chunks: usize,
+    samples: Vec<Sample>,
+    proof: String,
+}
+
+fn decode_nonce(nonce: &str) -> Result<Vec<u8>, ApiError> {
+    match hex::decode(nonce) {
+        Ok(bytes) if !bytes.is_empty() && bytes.len() <= MAX_NONCE_BYTES => Ok(bytes),
+        _ => Err(ApiError::BadRequest),
+    }
+}
+
+// Số nguyên u64 big-endian tại `at` của seed
+fn word(seed: &[u8; 32], at: usize) -> u64 {
+    u64::from_be_bytes(seed[at..at + 8].try_into().expect("seed đủ 32 byte"))
+}
+
+// Đọc dữ liệu gốc của một chunk trên blocking pool hiện tại
+fn read(state: &AppState, file_key: &str, chunk_hash: &str) -> Result<Option<Bytes>, StatusCode> {
+    let db_key = key::encode(file_key, chunk_hash);
+    match state.read_chunk(db_key.as_bytes()) {
+        Ok(found) => Ok(found.map(|(bytes, _)| bytes)),
+        Err(e) => {
+            tracing::error!("Lỗi khi đọc chunk {} để trả lời thách thức: {}", db_key, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Handler trả lời thách thức cho một chunk: SHA-256 của nonce nối với dữ liệu của chunk
+pub(crate) async fn challenge(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<ChallengeRequest>,
+) -> Result<Json<ChallengeResponse>, ApiError> {
+    let file_key = state.resolve_file_key(request.file_key)?;
+    if !key::is_valid(&file_key, &request.chunk_hash) {
+        return Err(ApiError::BadRequest);
+    }
+    let nonce = decode_nonce(&request.nonce)?;
+    let _permit = state.acquire_read()?;
+
+    let (read_state, read_key, chunk_hash) = (state.clone(), file_key.clone(), request.chunk_hash.clone());
+    let bytes = blocking(move || read(&read_state, &read_key, &chunk_hash)).await?.ok_or(ApiError::ChunkNotFound)?;
+    let proof = Sha256::new().chain_update(&nonce).chain_update(&bytes).finalize();
+    tracing::info!("<- Đã trả lời thách thức lưu trữ cho chunk {}:{}", file_key, request.chunk_hash);
+
+    Ok(Json(ChallengeResponse {
+        file_key,
+        chunk_hash: request.chunk_hash,
+        size: bytes.len(),
+        proof: hex::encode(proof),
+    }))
+}
+
+/// Handler trả lời thách thức theo mẫu cho cả file: các đoạn ngắn của những chunk được chọn
+/// ngẫu nhiên từ nonce (xem đầu module)
+pub(crate) async fn sample(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<SampleRequest>,
+) -> Result<Json<SampleResponse>, ApiError> {
+    let file_key = state.resolve_file_key(request.file_key)?;
+    let nonce = decode_nonce(&request.nonce)?;
+    let count = request.samples.unwrap_or(DEFAULT_SAMPLES);
+    let length = request.length.unwrap_or(DEFAULT_SAMPLE_LENGTH);
+    let config = state.config.load();
+    if count == 0 || count > config.challenge_max_samples || length == 0 || length > config.max_chunk_size {
+        return Err(ApiError::BadRequest);
+    }
+    let _permit = state.acquire_read()?;
+
+    let (read_state, read_key) = (state.clone(), file_key.clone());
+    let (chunks, samples, proof) = blocking(move || {
+        let mut hashes = Vec::new();
+        for item in read_state.tree_for(&read_key).scan_prefix(key::prefix(&read_key)).keys() {
+            let db_key = item.map_err(|e| {
+                tracing::error!("Lỗi khi quét chunk của fileKey {}: {}", read_key, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+            if let Some(chunk_hash) = key::chunk_hash_in(&db_key, &read_key) {
+                hashes.push(chunk_hash.to_string());
+            }
+        }
+        if hashes.is_empty() {
+            return Err(StatusCode::NOT_FOUND);
+        }
+
+        // Seed của từng mẫu, nhóm theo chunk được chọn để mỗi chunk chỉ được đọc một lần
+        // và không phải giữ nhiều chunk trong bộ nhớ cùng lúc
+        let mut picks: BTreeMap<usize, Vec<(u64, [u8; 32])>> = BTreeMap::new();
+        for i in 0..count as u64 {
+            let seed: [u8; 32] = Sha256::new().chain_update(&nonce).chain_update(i.to_be_bytes()).finalize().into();
+            let index = (word(&seed, 0) % hashes.len() as u64) as usize;
+            picks.entry(index).or_default().push((i, seed));
+        }
+
+        let mut slots: Vec<Option<(Sample, [u8; 32])>> = (0..count).map(|_| None).collect();
+        for (index, seeds) in picks {
+            // Chunk bị xóa giữa lúc quét và lúc đọc thì coi như không tìm thấy
+            let bytes = read(&read_state, &read_key, &hashes[index])?.ok_or(StatusCode::NOT_FOUND)?;
+            for (i, seed) in seeds {
+                let offset = if bytes.len() > length { (word(&seed, 8) % (bytes.len() - length + 1) as u64) as usize } else { 0 };
+                let end = (offset + length).min(bytes.len());
+                let proof: [u8; 32] =
+                    Sha256::new().chain_update(&nonce).chain_update(i.to_be_bytes()).chain_update(&bytes[offset..end]).finalize().into();
+                let sample = Sample {
+                    chunk_hash: hashes[index].clone(),
+                    offset,
+                    length: end - offset,
+                    proof: hex::encode(proof),
+                };
+                slots[i as usize] = Some((sample, proof));
+            }
+        }
+
+        let mut combined = Sha256::new();
+        let mut samples = Vec::with_capacity(count);
+        for (sample, proof) in slots.into_iter().flatten() {
+            combined.update(proof);
+            samples.push(sample);
+        }
+        Ok((hashes.len(), samples, combined.finalize()))
+    })
+    .await?;
+
+    tracing::info!("<- Đã trả lời thách thức lưu trữ {} mẫu cho fileKey {}", samples.len(), file_key);
+    Ok(Json(SampleResponse {
+        file_key,
+        chunks,
+        samples,
+        proof: hex::encode(proof),
+    }))
+}
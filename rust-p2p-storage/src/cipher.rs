@@ -0,0 +1,147 @@
+// ## MÃ HÓA DỮ LIỆU KHI LƯU (AT-REST) ##
+//
+// Node chạy trên VPS không tin cậy có thể mã hóa mọi value chunk trước khi ghi vào sled
+// bằng AES-256-GCM với khóa riêng của node: STORAGE_ENCRYPTION_KEY (64 ký tự hex) hoặc
+// STORAGE_ENCRYPTION_KEY_FILE (file chứa 64 ký tự hex, hoặc đúng 32 byte khóa thô). Chỉ
+// đặt một trong hai; không đặt thì không mã hóa.
+//
+// Value mã hóa gồm byte tag 0x03, 8 byte id của khóa (8 byte đầu SHA-256 của khóa), 12 byte
+// nonce ngẫu nhiên rồi ciphertext kèm tag xác thực 16 byte. Tag và id khóa là dữ liệu xác
+// thực đi kèm (AAD) nên mọi thay đổi ở header hay ciphertext đều làm việc giải mã thất bại:
+// server ghi log lỗi và coi value như bị hỏng (đọc một chunk trả về lỗi, quét file bỏ qua
+// chunk) thay vì trả về dữ liệu sai. Nonce lấy ngẫu nhiên cho mỗi lần ghi, an toàn tới
+// khoảng 2^32 lần ghi với cùng một khóa.
+//
+// Phần được mã hóa là record (và value của chunk lớn trong tree `chunks_large`, xem module
+// tier) sau khi nén; mã xóa (module erasure) bọc bên ngoài ciphertext để mảnh hỏng được sửa
+// trước khi kiểm tra xác thực. Value ghi trước khi bật mã hóa vẫn đọc được và được mã hóa ở
+// lần ghi lại tiếp theo. Replication và `/sync/full` gửi dữ liệu đã giải mã, nên mỗi node
+// dùng khóa riêng của nó. Khóa chỉ đọc lúc khởi động.
+
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::sync::{Arc, RwLock};
+
+/// Byte đầu của value đã mã hóa
+pub const TAG: u8 = 0x03;
+const KEY_ID_LEN: usize = 8;
+const HEADER_LEN: usize = 1 + KEY_ID_LEN + NONCE_LEN;
+
+/// Khóa mã hóa của node
+#[derive(Clone)]
+pub struct Key {
+    id: [u8; KEY_ID_LEN],
+    cipher: Arc<LessSafeKey>,
+}
+
+impl Key {
+    /// Khóa từ 32 byte thô
+    pub fn new(secret: &[u8]) -> Result<Self, String> {
+        let unbound = UnboundKey::new(&AES_256_GCM, secret).map_err(|_| "khóa mã hóa phải dài đúng 32 byte".to_string())?;
+        let digest = Sha256::digest(secret);
+        Ok(Key {
+            id: digest[..KEY_ID_LEN].try_into().expect("đủ 8 byte"),
+            cipher: Arc::new(LessSafeKey::new(unbound)),
+        })
+    }
+
+    /// Đọc khóa từ STORAGE_ENCRYPTION_KEY (`hex`) hoặc file STORAGE_ENCRYPTION_KEY_FILE
+    /// (`path`); `None` nếu không đặt cái nào
+    pub fn load(hex: Option<&str>, path: Option<&str>) -> Result<Option<Self>, String> {
+        let secret = match (hex, path) {
+            (None, None) => return Ok(None),
+            (Some(_), Some(_)) => {
+                return Err("chỉ đặt một trong STORAGE_ENCRYPTION_KEY và STORAGE_ENCRYPTION_KEY_FILE".to_string());
+            }
+            (Some(text), None) => hex::decode(text.trim()).map_err(|_| "STORAGE_ENCRYPTION_KEY phải là chuỗi hex".to_string())?,
+            (None, Some(path)) => {
+                let raw = std::fs::read(path).map_err(|e| format!("không đọc được {}: {}", path, e))?;
+                match std::str::from_utf8(&raw).ok().and_then(|text| hex::decode(text.trim()).ok()) {
+                    Some(secret) if secret.len() == 32 => secret,
+                    _ => raw,
+                }
+            }
+        };
+        Key::new(&secret).map(Some)
+    }
+
+    /// Id của khóa (hex), ghi trong log để biết value được mã hóa bằng khóa nào
+    pub fn id(&self) -> String {
+        hex::encode(self.id)
+    }
+}
+
+// Không bao giờ in khóa ra log
+impl std::fmt::Debug for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Key").field("id", &self.id()).finish()
+    }
+}
+
+impl PartialEq for Key {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+// Các khóa đã dùng trong process, để giải mã không cần truyền cấu hình qua mọi đường đọc
+static KEYRING: RwLock<Vec<Key>> = RwLock::new(Vec::new());
+
+/// Ghi nhớ `key` để giải mã các value được mã hóa bằng nó
+pub fn install(key: &Key) {
+    let mut keyring = KEYRING.write().unwrap();
+    if !keyring.contains(key) {
+        keyring.push(key.clone());
+    }
+}
+
+/// Mã hóa `bytes` bằng `key`; không có khóa thì trả lại nguyên bản
+pub fn encrypt(bytes: Vec<u8>, key: Option<&Key>) -> Vec<u8> {
+    let Some(key) = key else {
+        return bytes;
+    };
+    let mut nonce = [0u8; NONCE_LEN];
+    getrandom::getrandom(&mut nonce).expect("không lấy được byte ngẫu nhiên từ hệ điều hành");
+
+    let mut out = Vec::with_capacity(HEADER_LEN + bytes.len() + AES_256_GCM.tag_len());
+    out.push(TAG);
+    out.extend_from_slice(&key.id);
+    out.extend_from_slice(&nonce);
+    let mut sealed = bytes;
+    key.cipher
+        .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(&out[..1 + KEY_ID_LEN]), &mut sealed)
+        .expect("AES-GCM không mã hóa được value");
+    out.extend_from_slice(&sealed);
+    out
+}
+
+/// Giải mã value đã lưu; value không mã hóa được trả lại nguyên vẹn. Trả về `None` (và ghi
+/// log lỗi) nếu không có khóa tương ứng hoặc value bị hỏng hay bị sửa.
+pub fn decrypt(bytes: &[u8]) -> Option<Cow<'_, [u8]>> {
+    if bytes.first() != Some(&TAG) {
+        return Some(Cow::Borrowed(bytes));
+    }
+    let Some(header) = bytes.get(..HEADER_LEN) else {
+        tracing::error!("⚠️  Value mã hóa bị cắt cụt ({} byte)", bytes.len());
+        return None;
+    };
+    let (aad, nonce) = header.split_at(1 + KEY_ID_LEN);
+    let Some(key) = KEYRING.read().unwrap().iter().find(|key| key.id == aad[1..]).cloned() else {
+        tracing::error!("⚠️  Không có khóa {} để giải mã value", hex::encode(&aad[1..]));
+        return None;
+    };
+    let nonce = Nonce::try_assume_unique_for_key(nonce).ok()?;
+    let mut buffer = bytes[HEADER_LEN..].to_vec();
+    match key.cipher.open_in_place(nonce, Aad::from(aad), &mut buffer) {
+        Ok(plain) => {
+            let len = plain.len();
+            buffer.truncate(len);
+            Some(Cow::Owned(buffer))
+        }
+        Err(_) => {
+            tracing::error!("⚠️  Value mã hóa bằng khóa {} bị hỏng hoặc bị sửa đổi, không trả về dữ liệu", key.id());
+            None
+        }
+    }
+}
@@ -0,0 +1,79 @@
+// ## THAM SỐ DÒNG LỆNH CỦA SERVER ##
+//
+// Khi chạy server (không phải `--bench`, `--selftest`, `--migrate-dry-run`), các đối số dòng
+// lệnh ghi đè thiết lập tương ứng, ưu tiên hơn cả file STORAGE_CONFIG_FILE lẫn biến môi
+// trường, để cùng một binary chạy được trên nhiều máy mà không phải sửa môi trường:
+//
+//     rust-p2p-storage --config /etc/storage.toml --listen 0.0.0.0:3000 --data-dir /var/lib/storage
+//
+// | Đối số                     | Thiết lập                      |
+// |----------------------------|--------------------------------|
+// | `--config <file>`          | STORAGE_CONFIG_FILE            |
+// | `--listen <addr>`          | STORAGE_HTTP_ADDR              |
+// | `--data-dir <dir>`         | STORAGE_DATA_DIR               |
+// | `--max-chunk-size <bytes>` | STORAGE_MAX_CHUNK_SIZE         |
+// | `--flush-interval-ms <ms>` | STORAGE_FLUSH_MIN_INTERVAL_MS  |
+// | `--flush-every <n>`        | STORAGE_FLUSH_EVERY_WRITES     |
+// | `--durability <policy>`    | STORAGE_DURABILITY             |
+// | `--log-level <level>`      | STORAGE_LOG_LEVEL              |
+//
+// Giá trị có thể viết liền sau dấu '=' (`--listen=0.0.0.0:3000`); `--help` (hoặc `-h`) in
+// bảng trên rồi thoát. Giá trị ghi đè được giữ suốt đời process nên `POST /admin/reload`
+// vẫn dùng chúng.
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+const FLAGS: &[(&str, &str, &str)] = &[
+    ("--config", "<file>", "STORAGE_CONFIG_FILE"),
+    ("--listen", "<addr>", "STORAGE_HTTP_ADDR"),
+    ("--data-dir", "<dir>", "STORAGE_DATA_DIR"),
+    ("--max-chunk-size", "<bytes>", "STORAGE_MAX_CHUNK_SIZE"),
+    ("--flush-interval-ms", "<ms>", "STORAGE_FLUSH_MIN_INTERVAL_MS"),
+    ("--flush-every", "<n>", "STORAGE_FLUSH_EVERY_WRITES"),
+    ("--durability", "<policy>", "STORAGE_DURABILITY"),
+    ("--log-level", "<level>", "STORAGE_LOG_LEVEL"),
+];
+
+static OVERRIDES: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+/// Đọc các đối số dòng lệnh của server và ghi nhớ chúng làm giá trị ghi đè cấu hình.
+/// Chỉ gọi một lần, trước lần đầu `Config::load`.
+pub fn install(args: impl IntoIterator<Item = String>) -> Result<(), String> {
+    let mut overrides = HashMap::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+            None => (arg, None),
+        };
+        let &(_, _, name) = FLAGS
+            .iter()
+            .find(|(known, _, _)| *known == flag)
+            .ok_or_else(|| format!("đối số không hỗ trợ: {} (xem --help)", flag))?;
+        let value = inline
+            .or_else(|| args.next())
+            .ok_or_else(|| format!("thiếu giá trị cho {}", flag))?;
+        overrides.insert(name.to_string(), value);
+    }
+    OVERRIDES
+        .set(overrides)
+        .map_err(|_| "đối số dòng lệnh đã được đọc".to_string())
+}
+
+/// Nội dung in ra cho `--help`
+pub fn usage() -> String {
+    let mut out = String::from(
+        "Cách dùng: rust-p2p-storage [--bench ... | --selftest <dir> | --migrate-dry-run | [ĐỐI SỐ]...]\n\nĐối số (ghi đè file cấu hình và biến môi trường):\n",
+    );
+    for (flag, value, name) in FLAGS {
+        out.push_str(&format!("  {:<28} {}\n", format!("{} {}", flag, value), name));
+    }
+    out.push_str("  -h, --help                   In hướng dẫn này\n");
+    out
+}
+
+/// Giá trị ghi đè từ dòng lệnh cho thiết lập `name`, nếu có
+pub(crate) fn get(name: &str) -> Option<String> {
+    OVERRIDES.get()?.get(name).cloned()
+}
@@ -0,0 +1,253 @@
+// ## NÉN CHUNK KHI LƯU TRỮ ##
+//
+// Khi đặt STORAGE_COMPRESSION=zstd hoặc STORAGE_COMPRESSION=lz4, dữ liệu chunk (sau khi
+// giải Base64) được nén trước khi ghi, record lưu lại `encoding` để biết cách giải nén. Khi
+// đọc, client gửi header `Accept-Chunk-Encoding: zstd` (hoặc `lz4`) sẽ nhận nguyên bytes đã
+// nén (vẫn ở dạng Base64) kèm `"encoding": "zstd"` trong từng chunk và tự giải nén; client
+// khác nhận dữ liệu đã được server giải nén với `"encoding": "identity"`.
+//
+// zstd nén tốt hơn, mức nén lấy từ STORAGE_COMPRESSION_LEVEL (mặc định 3, số lớn hơn nén
+// nhỏ hơn nhưng chậm hơn). lz4 không có mức nén, nhanh hơn nhiều cả khi nén lẫn giải nén.
+// Bytes lz4 là một block LZ4 chuẩn đứng sau 4 byte kích thước gốc (u32 little-endian).
+//
+// Chunk không được nén khi dữ liệu đã ở dạng nén (nhận ra theo chữ ký ở đầu chunk như
+// PNG, JPEG, ZIP, gzip, zstd, hoặc khi các byte đầu gần như ngẫu nhiên), khi chunk quá
+// nhỏ, hay khi bản nén không nhỏ hơn bản gốc; record khi đó giữ `"encoding": "identity"`.
+
+use axum::http::HeaderMap;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Header client dùng để báo các encoding chunk nó tự giải được
+pub const ACCEPT_CHUNK_ENCODING: &str = "accept-chunk-encoding";
+
+/// Mức nén zstd mặc định: cân bằng giữa tốc độ và tỉ lệ nén
+pub const DEFAULT_LEVEL: i32 = 3;
+
+// Chunk nhỏ hơn thì không nén: phần header của codec gần như ăn hết phần tiết kiệm được
+const MIN_COMPRESS_BYTES: usize = 64;
+
+// Số byte đầu dùng để ước lượng độ ngẫu nhiên của dữ liệu
+const ENTROPY_SAMPLE: usize = 4096;
+
+// Dữ liệu có entropy (bit mỗi byte) cao hơn ngưỡng này coi như đã nén hoặc mã hóa
+const MAX_ENTROPY_BITS: f64 = 7.5;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    #[default]
+    Identity,
+    Zstd,
+    Lz4,
+}
+
+impl FromStr for Codec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "identity" | "none" | "off" => Ok(Codec::Identity),
+            "zstd" => Ok(Codec::Zstd),
+            "lz4" => Ok(Codec::Lz4),
+            other => Err(format!("encoding không hỗ trợ: {}", other)),
+        }
+    }
+}
+
+impl Codec {
+    pub fn is_identity(&self) -> bool {
+        *self == Codec::Identity
+    }
+}
+
+/// Nén chuỗi Base64 `value` bằng `codec` (`level` chỉ dùng cho zstd). Trả về `None` (lưu
+/// nguyên bản) nếu không nén, value không phải Base64 hợp lệ, dữ liệu trông như đã nén
+/// (xem `worth_compressing`) hoặc bản nén không nhỏ hơn bản gốc.
+pub fn compress(value: &str, codec: Codec, level: i32) -> Option<String> {
+    if codec.is_identity() {
+        return None;
+    }
+    let raw = STANDARD.decode(value).ok()?;
+    if !worth_compressing(&raw) {
+        return None;
+    }
+    let packed = match codec {
+        Codec::Identity => return None,
+        Codec::Zstd => zstd::bulk::compress(&raw, level).ok()?,
+        Codec::Lz4 => lz4_compress(&raw),
+    };
+    (packed.len() < raw.len()).then(|| STANDARD.encode(packed))
+}
+
+/// Dữ liệu có đáng nén không: đủ lớn, không mang chữ ký của định dạng đã nén và không
+/// có entropy quá cao
+pub fn worth_compressing(raw: &[u8]) -> bool {
+    if raw.len() < MIN_COMPRESS_BYTES {
+        return false;
+    }
+    if let Some(content_type) = crate::mime::sniff(&raw[..raw.len().min(crate::mime::SNIFF_LEN)])
+        && content_type != "application/pdf"
+    {
+        return false;
+    }
+    entropy(&raw[..raw.len().min(ENTROPY_SAMPLE)]) <= MAX_ENTROPY_BITS
+}
+
+// Entropy Shannon (bit mỗi byte) của `sample`
+fn entropy(sample: &[u8]) -> f64 {
+    let mut counts = [0u32; 256];
+    for &b in sample {
+        counts[b as usize] += 1;
+    }
+    let total = sample.len() as f64;
+    counts
+        .iter()
+        .filter(|&&c| c > 0)
+        .map(|&c| {
+            let p = c as f64 / total;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// Giải nén chuỗi Base64 `value` đã nén bằng `codec`, trả về Base64 của dữ liệu gốc
+pub fn decompress(value: &str, codec: Codec) -> Option<String> {
+    let packed = STANDARD.decode(value).ok()?;
+    let raw = match codec {
+        Codec::Identity => return Some(value.to_string()),
+        Codec::Zstd => zstd::stream::decode_all(packed.as_slice()).ok()?,
+        Codec::Lz4 => lz4_decompress(&packed)?,
+    };
+    Some(STANDARD.encode(raw))
+}
+
+// Độ dài match ngắn nhất của LZ4
+const LZ4_MIN_MATCH: usize = 4;
+// Match phải bắt đầu cách cuối dữ liệu ít nhất 12 byte, và 5 byte cuối luôn là literal
+const LZ4_MF_LIMIT: usize = 12;
+const LZ4_LAST_LITERALS: usize = 5;
+const LZ4_HASH_BITS: u32 = 12;
+
+/// Nén `raw` thành một block LZ4 (tìm match tham lam bằng bảng băm 4 byte), có 4 byte kích
+/// thước gốc ở đầu
+pub fn lz4_compress(raw: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(4 + raw.len() + raw.len() / 255 + 16);
+    out.extend_from_slice(&(raw.len() as u32).to_le_bytes());
+
+    let read = |at: usize| u32::from_le_bytes(raw[at..at + 4].try_into().expect("đủ 4 byte"));
+    // Vị trí gần nhất (cộng 1, 0 là trống) của mỗi giá trị băm
+    let mut table = vec![0u32; 1 << LZ4_HASH_BITS];
+    let (mut anchor, mut pos) = (0, 0);
+    if raw.len() > LZ4_MF_LIMIT {
+        let limit = raw.len() - LZ4_MF_LIMIT;
+        let match_end = raw.len() - LZ4_LAST_LITERALS;
+        while pos < limit {
+            let seq = read(pos);
+            let slot = (seq.wrapping_mul(2654435761) >> (32 - LZ4_HASH_BITS)) as usize;
+            let candidate = std::mem::replace(&mut table[slot], pos as u32 + 1) as usize;
+            if candidate == 0 || pos - (candidate - 1) > u16::MAX as usize || read(candidate - 1) != seq {
+                pos += 1;
+                continue;
+            }
+            let from = candidate - 1;
+            let mut len = LZ4_MIN_MATCH;
+            while pos + len < match_end && raw[from + len] == raw[pos + len] {
+                len += 1;
+            }
+            lz4_sequence(&mut out, &raw[anchor..pos], Some(((pos - from) as u16, len)));
+            pos += len;
+            anchor = pos;
+        }
+    }
+    lz4_sequence(&mut out, &raw[anchor..], None);
+    out
+}
+
+// Ghi một sequence LZ4: literal rồi match (offset, độ dài); sequence cuối không có match
+fn lz4_sequence(out: &mut Vec<u8>, literals: &[u8], matched: Option<(u16, usize)>) {
+    let match_len = matched.map_or(0, |(_, len)| len - LZ4_MIN_MATCH);
+    out.push(((literals.len().min(15) as u8) << 4) | match_len.min(15) as u8);
+    if literals.len() >= 15 {
+        lz4_length(out, literals.len() - 15);
+    }
+    out.extend_from_slice(literals);
+    if let Some((offset, _)) = matched {
+        out.extend_from_slice(&offset.to_le_bytes());
+        if match_len >= 15 {
+            lz4_length(out, match_len - 15);
+        }
+    }
+}
+
+fn lz4_length(out: &mut Vec<u8>, mut len: usize) {
+    while len >= 255 {
+        out.push(255);
+        len -= 255;
+    }
+    out.push(len as u8);
+}
+
+/// Giải nén block do `lz4_compress` tạo; `None` nếu dữ liệu hỏng
+pub fn lz4_decompress(packed: &[u8]) -> Option<Vec<u8>> {
+    let size = u32::from_le_bytes(packed.get(..4)?.try_into().ok()?) as usize;
+    let mut input = packed[4..].iter().copied();
+    // Không tin vào kích thước khai báo khi cấp phát, dữ liệu hỏng có thể ghi số rất lớn
+    let mut out = Vec::with_capacity(size.min(packed.len().saturating_mul(255)));
+    while let Some(token) = input.next() {
+        let mut literals = (token >> 4) as usize;
+        if literals == 15 {
+            literals = lz4_read_length(&mut input, literals)?;
+        }
+        if out.len() + literals > size {
+            return None;
+        }
+        for _ in 0..literals {
+            out.push(input.next()?);
+        }
+        let Some(low) = input.next() else {
+            break; // Sequence cuối chỉ có literal
+        };
+        let offset = u16::from_le_bytes([low, input.next()?]) as usize;
+        if offset == 0 || offset > out.len() {
+            return None;
+        }
+        let mut len = (token & 0x0f) as usize;
+        if len == 15 {
+            len = lz4_read_length(&mut input, len)?;
+        }
+        if out.len() + len + LZ4_MIN_MATCH > size {
+            return None;
+        }
+        let start = out.len() - offset;
+        for i in 0..len + LZ4_MIN_MATCH {
+            out.push(out[start + i]);
+        }
+    }
+    (out.len() == size).then_some(out)
+}
+
+// Đọc phần mở rộng của độ dài literal/match: cộng dồn các byte cho tới byte khác 255
+fn lz4_read_length(input: &mut impl Iterator<Item = u8>, mut len: usize) -> Option<usize> {
+    loop {
+        let b = input.next()?;
+        len += b as usize;
+        if b != 255 {
+            return Some(len);
+        }
+    }
+}
+
+/// Các encoding client chấp nhận qua header `Accept-Chunk-Encoding` (danh sách phân
+/// cách bằng dấu phẩy). `identity` luôn được chấp nhận nên không cần liệt kê.
+pub fn accepted(headers: &HeaderMap) -> Vec<Codec> {
+    headers
+        .get_all(ACCEPT_CHUNK_ENCODING)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|item| item.split(';').next()?.parse().ok())
+        .filter(|codec: &Codec| !codec.is_identity())
+        .collect()
+}
@@ -0,0 +1,162 @@
+// ## NGUỒN GIÁ TRỊ CẤU HÌNH ##
+//
+// Mỗi thiết lập được tra theo thứ tự ưu tiên: đối số dòng lệnh (module cli), file TOML
+// STORAGE_CONFIG_FILE, rồi biến môi trường cùng tên. File là TOML thật; tên khóa viết
+// thường không có tiền tố được đổi thành tên biến môi trường, khóa trong bảng được ghép
+// với tên bảng, nên ba cách viết sau là như nhau:
+//
+//     flush_min_interval_ms = 50
+//     STORAGE_FLUSH_MIN_INTERVAL_MS = 50
+//
+//     [flush]
+//     min_interval_ms = 50
+//
+// Chuỗi, số, bool và ngày giờ được đổi thành chuỗi rồi parse như giá trị của biến môi
+// trường; mảng được nối bằng dấu phẩy (ví dụ `peers = ["http://a", "http://b"]`). Mảng
+// bảng (`[[x]]`) không có thiết lập tương ứng nên bị từ chối.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use toml_edit::{DocumentMut, Item, Table, Value};
+
+use crate::cli;
+
+#[derive(Default)]
+pub(crate) struct ConfigVars {
+    file: HashMap<String, String>,
+    // Các biến có giá trị không parse được (`NAME="giá trị"`), ghi lại trong lúc đọc
+    invalid: RefCell<Vec<String>>,
+}
+
+impl ConfigVars {
+    /// Đọc file TOML `path`; không có file thì chỉ dùng dòng lệnh và biến môi trường
+    pub(crate) fn load(path: Option<&str>) -> Result<Self, String> {
+        let Some(path) = path else {
+            return Ok(ConfigVars::default());
+        };
+        let raw = std::fs::read_to_string(path).map_err(|e| format!("không đọc được {}: {}", path, e))?;
+        let doc: DocumentMut = raw.parse().map_err(|e| format!("{} không phải TOML hợp lệ: {}", path, e))?;
+
+        let mut file = HashMap::new();
+        flatten_table(doc.as_table(), "", &mut file).map_err(|e| format!("{}: {}", path, e))?;
+        Ok(ConfigVars {
+            file,
+            ..ConfigVars::default()
+        })
+    }
+
+    /// Các cặp tên/giá trị cho sẵn thay cho nội dung file (dùng khi nhúng server vào test)
+    pub(crate) fn from_pairs<'a>(pairs: impl IntoIterator<Item = (&'a str, &'a str)>) -> Self {
+        ConfigVars {
+            file: pairs.into_iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            ..ConfigVars::default()
+        }
+    }
+
+    /// Các biến sai định dạng đã gặp trong lúc đọc
+    pub(crate) fn into_invalid(self) -> Vec<String> {
+        self.invalid.into_inner()
+    }
+
+    /// Giá trị thô của biến `name` theo thứ tự ưu tiên ở đầu module
+    pub(crate) fn get(&self, name: &str) -> Option<String> {
+        cli::get(name)
+            .or_else(|| self.file.get(name).cloned())
+            .or_else(|| std::env::var(name).ok())
+    }
+
+    /// Đọc và parse một biến, dùng giá trị mặc định nếu thiếu hoặc sai định dạng
+    pub(crate) fn parse<T: std::str::FromStr>(&self, name: &str, default: T) -> T {
+        self.opt(name).unwrap_or(default)
+    }
+
+    /// Đọc và parse một biến không bắt buộc; sai định dạng thì coi như không đặt và biến
+    /// được ghi vào `invalid`
+    pub(crate) fn opt<T: std::str::FromStr>(&self, name: &str) -> Option<T> {
+        let raw = self.get(name)?;
+        match raw.parse() {
+            Ok(value) => Some(value),
+            Err(_) => {
+                self.invalid.borrow_mut().push(format!("{}={:?}", name, raw));
+                None
+            }
+        }
+    }
+
+    /// In cảnh báo cho các biến sai định dạng đã bị bỏ qua. Cấu hình được đọc trước khi
+    /// khởi tạo tracing nên in thẳng ra stderr.
+    pub(crate) fn warn_invalid(&self) {
+        for entry in self.invalid.borrow().iter() {
+            eprintln!("Giá trị không hợp lệ: {}, bỏ qua", entry);
+        }
+    }
+
+    /// Đọc một biến dạng bật/tắt ("1", "true", "yes", "on")
+    pub(crate) fn flag(&self, name: &str) -> bool {
+        matches!(
+            self.get(name).map(|v| v.to_ascii_lowercase()).as_deref(),
+            Some("1" | "true" | "yes" | "on")
+        )
+    }
+}
+
+// Tên biến môi trường của khóa `key` nằm trong bảng có tiền tố `prefix` ("" ở gốc)
+fn var_name(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() && key.starts_with("STORAGE_") {
+        return key.to_string();
+    }
+    let key = key.to_ascii_uppercase().replace('-', "_");
+    if prefix.is_empty() {
+        format!("STORAGE_{}", key)
+    } else {
+        format!("{}_{}", prefix, key)
+    }
+}
+
+fn flatten_table(table: &Table, prefix: &str, out: &mut HashMap<String, String>) -> Result<(), String> {
+    for (key, item) in table.iter() {
+        let name = var_name(prefix, key);
+        match item {
+            Item::None => {}
+            Item::Value(value) => flatten_value(value, name, out)?,
+            Item::Table(table) => flatten_table(table, &name, out)?,
+            Item::ArrayOfTables(_) => return Err(format!("mảng bảng `{}` không được hỗ trợ", key)),
+        }
+    }
+    Ok(())
+}
+
+fn flatten_value(value: &Value, name: String, out: &mut HashMap<String, String>) -> Result<(), String> {
+    match value {
+        Value::InlineTable(table) => {
+            for (key, value) in table.iter() {
+                flatten_value(value, var_name(&name, key), out)?;
+            }
+        }
+        Value::Array(items) => {
+            let parts = items
+                .iter()
+                .map(|item| scalar(item).ok_or_else(|| format!("`{}` chỉ được chứa giá trị đơn", name)))
+                .collect::<Result<Vec<_>, _>>()?;
+            out.insert(name, parts.join(","));
+        }
+        value => {
+            if let Some(text) = scalar(value) {
+                out.insert(name, text);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn scalar(value: &Value) -> Option<String> {
+    Some(match value {
+        Value::String(v) => v.value().clone(),
+        Value::Integer(v) => v.value().to_string(),
+        Value::Float(v) => v.value().to_string(),
+        Value::Boolean(v) => v.value().to_string(),
+        Value::Datetime(v) => v.value().to_string(),
+        Value::Array(_) | Value::InlineTable(_) => return None,
+    })
+}
@@ -0,0 +1,157 @@
+// ## GIỚI HẠN SỐ KẾT NỐI TCP ##
+//
+// `axum::serve` nhận mọi kết nối, mỗi kết nối giữ một file descriptor nên quá nhiều
+// client cùng lúc có thể làm cạn fd của tiến trình (kể cả fd sled cần). Vòng accept ở
+// đây giữ một permit của semaphore cho mỗi kết nối đang mở (STORAGE_MAX_CONNECTIONS).
+// Khi hết permit, kết nối mới vẫn được accept nhưng chỉ nhận một response `503` kèm
+// `Connection: close` rồi bị đóng, để client biết cần thử lại thay vì bị treo trong
+// hàng đợi backlog. Đây là lớp bảo vệ tài nguyên ở mức kết nối, khác với giới hạn số
+// lượt đọc đồng thời ở mức request (STORAGE_MAX_CONCURRENT_READS).
+//
+// Với TLS (`serve_tls`, xem module tls), kết nối vượt giới hạn bị đóng ngay mà không có
+// response vì client chưa bắt tay TLS, và permit được giữ cả trong lúc bắt tay.
+//
+// Khi future `shutdown` hoàn tất (xem module shutdown), vòng accept dừng và đóng listener,
+// mọi kết nối đang mở được báo tắt êm: request đang chạy được xử lý xong và nhận response,
+// kết nối HTTP/1 đang rảnh bị đóng ngay, HTTP/2 gửi GOAWAY. `serve` chỉ trả về khi kết nối
+// cuối cùng đã đóng.
+
+use axum::extract::ConnectInfo;
+use axum::Router;
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::conn::auto,
+    server::graceful::{GracefulShutdown, Watcher},
+    service::TowerToHyperService,
+};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Semaphore;
+use tower::ServiceExt;
+
+use crate::tls::{TlsAcceptor, HANDSHAKE_TIMEOUT};
+
+// Body của response gửi cho kết nối vượt giới hạn trước khi đóng, cùng dạng với `ApiError`
+const BUSY_BODY: &str = r#"{"code":"too_many_connections","message":"too many open connections, retry later"}"#;
+
+// Thời gian tối đa để gửi response từ chối, tránh client chậm giữ kết nối mãi
+const BUSY_WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Phục vụ `app` trên `listener` với tối đa `max_connections` kết nối mở cùng lúc, tới khi
+/// `shutdown` hoàn tất và các kết nối đang mở đã xử lý xong
+pub async fn serve(listener: TcpListener, app: Router, max_connections: usize, shutdown: impl Future<Output = ()>) {
+    accept_loop(listener, app, max_connections, None, shutdown).await
+}
+
+/// Như `serve` nhưng mọi kết nối phải bắt tay TLS qua `acceptor` trước
+pub async fn serve_tls(
+    listener: TcpListener,
+    app: Router,
+    max_connections: usize,
+    acceptor: TlsAcceptor,
+    shutdown: impl Future<Output = ()>,
+) {
+    accept_loop(listener, app, max_connections, Some(acceptor), shutdown).await
+}
+
+async fn accept_loop(
+    listener: TcpListener,
+    app: Router,
+    max_connections: usize,
+    tls: Option<TlsAcceptor>,
+    shutdown: impl Future<Output = ()>,
+) {
+    let permits = Arc::new(Semaphore::new(max_connections));
+    let graceful = GracefulShutdown::new();
+    tokio::pin!(shutdown);
+    loop {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            _ = &mut shutdown => break,
+        };
+        let (stream, peer) = match accepted {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                // Thường là hết fd tạm thời (EMFILE); chờ một chút thay vì quay vòng liên tục
+                tracing::error!("Lỗi khi accept kết nối: {}", e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+
+        let Ok(permit) = permits.clone().try_acquire_owned() else {
+            tracing::warn!("Từ chối kết nối từ {}: đã đạt {} kết nối", peer, max_connections);
+            if tls.is_none() {
+                tokio::spawn(reject(stream));
+            }
+            continue;
+        };
+        tracing::debug!(%peer, open = max_connections - permits.available_permits(), "Kết nối mới");
+
+        let app = app.clone();
+        let tls = tls.clone();
+        let watcher = graceful.watcher();
+        tokio::spawn(async move {
+            let result = match tls {
+                None => serve_connection(stream, app, peer, watcher).await,
+                Some(acceptor) => match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => serve_connection(stream, app, peer, watcher).await,
+                    Ok(Err(e)) => Err(e.into()),
+                    Err(_) => Err("hết thời gian bắt tay TLS".into()),
+                },
+            };
+            if let Err(e) = result {
+                tracing::debug!(%peer, error = %e, "Kết nối đóng với lỗi");
+            }
+            // Trả permit khi kết nối đóng
+            drop(permit);
+        });
+    }
+
+    // Không nhận kết nối mới nữa rồi chờ các kết nối đang mở xử lý xong
+    drop(listener);
+    let open = graceful.count();
+    if open > 0 {
+        tracing::info!("Đang chờ {} kết nối xử lý xong request", open);
+    }
+    graceful.shutdown().await;
+}
+
+// Phục vụ một kết nối bằng HTTP/1.1 hoặc HTTP/2 tùy preface client gửi, tắt êm khi
+// `watcher` nhận tín hiệu tắt
+async fn serve_connection<I>(
+    io: I,
+    app: Router,
+    peer: SocketAddr,
+    watcher: Watcher,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    // Địa chỉ của client cho các middleware cần biết (xem module ratelimit)
+    let app = app.map_request(move |mut request: axum::http::Request<_>| {
+        request.extensions_mut().insert(ConnectInfo(peer));
+        request
+    });
+    let service = TowerToHyperService::new(app);
+    let builder = auto::Builder::new(TokioExecutor::new());
+    let connection = builder.serve_connection_with_upgrades(TokioIo::new(io), service).into_owned();
+    watcher.watch(connection).await
+}
+
+async fn reject(mut stream: TcpStream) {
+    let _ = tokio::time::timeout(BUSY_WRITE_TIMEOUT, async {
+        let response = format!(
+            "HTTP/1.1 503 Service Unavailable\r\ncontent-type: application/json\r\nretry-after: 1\r\nconnection: close\r\ncontent-length: {}\r\n\r\n{}",
+            BUSY_BODY.len(),
+            BUSY_BODY
+        );
+        stream.write_all(response.as_bytes()).await?;
+        stream.shutdown().await
+    })
+    .await;
+}
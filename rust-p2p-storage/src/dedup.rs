@@ -0,0 +1,364 @@
+// ## KHỬ TRÙNG LẶP CHUNK THEO NỘI DUNG ##
+//
+// Khi bật STORAGE_DEDUP, value của mọi chunk khác rỗng được tách khỏi record như chunk lớn
+// (xem module tier) nhưng không nằm trong `chunks_large` theo key của chunk: bytes được lưu
+// một lần trong tree `chunks`, key là SHA-256 của value (chuỗi Base64 sau khi nén/delta,
+// trước khi mã hóa), value là số tham chiếu (u64 big-endian) rồi tới bytes đã mã hóa và
+// thêm mã xóa như value trong `chunks_large`. Tree `files` ánh xạ key của từng chunk
+// (`fileKey:chunkHash`) tới hash đó, nên nhiều file có chunk giống hệt nhau chỉ tốn một bản.
+//
+// Ghi, ghi đè, đổi tên, xóa file, xóa chunk và TTL cập nhật `files` cùng số tham chiếu
+// trong transaction của thao tác đó (xem `put_in`, `release_in`, `rename_in`); blob có số
+// tham chiếu về 0 bị xóa ngay trong transaction, nên không cần task dọn riêng. Transaction
+// của sled tuần tự hóa các lần ghi đồng thời cùng một nội dung nên số tham chiếu không lệch.
+// Dung lượng quota và `file_chunks` vẫn tính theo dữ liệu logic: mỗi chunk tính đủ độ dài
+// blob nó trỏ tới, nên tenant không hưởng lợi hay chịu thiệt vì dữ liệu của tenant khác.
+//
+// Response của `/store` có `deduplicated` cho biết chunk có dùng lại blob đã có không;
+// `/metrics` đếm số lần đó và số byte không phải ghi (`storage_dedup_*_total`, từ lúc khởi
+// động), còn `dedup` trong `/stats` cho tỉ lệ giữa số byte các chunk trỏ tới và số byte blob
+// thực sự chiếm (xem module usage).
+//
+// Record trỏ tới blob vẫn mang cờ `large` và `tier::Large::get` tìm trong `files` trước rồi
+// mới tới `chunks_large`, nên các đường đọc không cần biết chunk có được khử trùng lặp hay
+// không. Bật STORAGE_DEDUP trên database đã có dữ liệu thì chunk cũ vẫn đọc được như cũ;
+// `POST /admin/dedup/migrate` chuyển chúng sang blob dùng chung (xem `migrate`). Tắt lại
+// STORAGE_DEDUP không làm mất gì: chunk mới được lưu như trước, chunk đã khử trùng lặp vẫn
+// đọc qua `files` cho tới khi bị ghi lại hoặc xóa. `GET /admin/consistency` so số tham
+// chiếu với `files` và `POST /admin/reindex` sửa lại (xem module reindex).
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sled::transaction::{TransactionalTree, UnabortableTransactionError};
+use sled::{IVec, Transactional};
+use std::collections::HashMap;
+
+use crate::{key, record, AppState};
+
+// Độ dài số tham chiếu ở đầu value của tree `chunks`
+const COUNT_LEN: usize = 8;
+
+type TxResult<T> = Result<T, UnabortableTransactionError>;
+
+/// Hai tree của blob dùng chung
+#[derive(Clone)]
+pub struct Blobs {
+    // Key của chunk -> hash của blob
+    pub files: sled::Tree,
+    // Hash -> số tham chiếu và bytes đã lưu
+    pub chunks: sled::Tree,
+}
+
+/// Hash dùng làm key của blob cho value `value` (chưa mã hóa)
+pub fn hash(value: &[u8]) -> [u8; 32] {
+    Sha256::digest(value).into()
+}
+
+/// Số tham chiếu của một value trong tree `chunks`
+pub fn count_of(entry: &[u8]) -> u64 {
+    entry.get(..COUNT_LEN).and_then(|b| b.try_into().ok()).map_or(0, u64::from_be_bytes)
+}
+
+/// Bytes đã lưu của blob trong một value của tree `chunks`, bỏ số tham chiếu ở đầu
+pub fn data_of(entry: &IVec) -> IVec {
+    entry.subslice(COUNT_LEN.min(entry.len()), entry.len().saturating_sub(COUNT_LEN))
+}
+
+fn with_count(count: u64, data: &[u8]) -> Vec<u8> {
+    let mut entry = Vec::with_capacity(COUNT_LEN + data.len());
+    entry.extend_from_slice(&count.to_be_bytes());
+    entry.extend_from_slice(data);
+    entry
+}
+
+impl Blobs {
+    /// Có chunk nào đang trỏ tới blob không
+    pub fn in_use(&self) -> bool {
+        !self.files.is_empty()
+    }
+
+    /// Bytes đã lưu của blob mà chunk `key` trỏ tới, `None` nếu chunk không dùng blob
+    pub fn get(&self, key: &[u8]) -> sled::Result<Option<IVec>> {
+        let Some(hash) = self.files.get(key)? else {
+            return Ok(None);
+        };
+        Ok(self.chunks.get(hash)?.map(|entry| data_of(&entry)))
+    }
+
+    /// Bỏ tham chiếu của chunk `key` nếu blob nó trỏ tới vẫn có bytes `old` (đọc bằng `get`
+    /// trước đó), như `compare_and_swap` trên `chunks_large`. Trả về `false` nếu chunk không
+    /// còn trỏ tới blob đó.
+    pub fn release_if(&self, key: &[u8], old: &[u8]) -> sled::Result<bool> {
+        let result = (&self.files, &self.chunks).transaction(|(files, chunks)| {
+            let Some(hash) = files.get(key)? else {
+                return Ok(false);
+            };
+            if chunks.get(&hash)?.is_none_or(|entry| data_of(&entry) != old) {
+                return Ok(false);
+            }
+            release_in(files, chunks, key)?;
+            Ok(true)
+        });
+        result.map_err(|e: sled::transaction::TransactionError<()>| match e {
+            sled::transaction::TransactionError::Storage(e) => e,
+            sled::transaction::TransactionError::Abort(()) => sled::Error::Unsupported("transaction bị hủy".into()),
+        })
+    }
+}
+
+/// Kết quả của `put_in`
+pub struct Put {
+    // Bytes của blob mà chunk trỏ tới trước đó, nếu có
+    pub old: Option<IVec>,
+    // Chunk vừa thêm tham chiếu tới blob cùng nội dung đã có sẵn nên không phải ghi thêm
+    // bytes nào (ghi lại đúng nội dung cũ vào cùng key không tính)
+    pub shared: bool,
+}
+
+/// Trỏ chunk `key` tới blob `hash`, tạo blob với bytes `data` nếu chưa có, và bỏ tham chiếu
+/// tới blob cũ của chunk. Chạy trong transaction của lần ghi (`files`, `chunks` là hai tree
+/// trong transaction).
+pub fn put_in(files: &TransactionalTree, chunks: &TransactionalTree, key: &[u8], hash: &[u8; 32], data: &[u8]) -> TxResult<Put> {
+    let old_hash = files.insert(key, &hash[..])?;
+    if old_hash.as_deref() == Some(&hash[..]) {
+        // Ghi lại đúng nội dung cũ: số tham chiếu giữ nguyên
+        let old = chunks.get(hash)?.map(|entry| data_of(&entry));
+        if old.is_none() {
+            chunks.insert(&hash[..], with_count(1, data))?;
+        }
+        return Ok(Put { old, shared: false });
+    }
+    let shared = match chunks.get(hash)? {
+        Some(entry) => {
+            chunks.insert(&hash[..], with_count(count_of(&entry) + 1, &data_of(&entry)))?;
+            true
+        }
+        None => {
+            chunks.insert(&hash[..], with_count(1, data))?;
+            false
+        }
+    };
+    let old = match old_hash {
+        Some(old_hash) => release_blob(chunks, &old_hash)?,
+        None => None,
+    };
+    Ok(Put { old, shared })
+}
+
+/// Bỏ ánh xạ của chunk `key` và tham chiếu tới blob của nó (xóa blob khi không còn tham
+/// chiếu). Trả về bytes của blob đó nếu chunk có dùng blob.
+pub fn release_in(files: &TransactionalTree, chunks: &TransactionalTree, key: &[u8]) -> TxResult<Option<IVec>> {
+    match files.remove(key)? {
+        Some(hash) => release_blob(chunks, &hash),
+        None => Ok(None),
+    }
+}
+
+/// Chuyển ánh xạ của chunk `from` sang key `to` khi đổi tên file; số tham chiếu không đổi.
+/// Ánh xạ cũ của `to` phải được bỏ trước bằng `release_in`. Trả về bytes của blob nếu có.
+pub fn rename_in(files: &TransactionalTree, chunks: &TransactionalTree, from: &[u8], to: &[u8]) -> TxResult<Option<IVec>> {
+    let Some(hash) = files.remove(from)? else {
+        return Ok(None);
+    };
+    files.insert(to, hash.clone())?;
+    Ok(chunks.get(hash)?.map(|entry| data_of(&entry)))
+}
+
+/// Bytes của blob mà chunk `key` trỏ tới, đọc trong transaction
+pub fn get_in(files: &TransactionalTree, chunks: &TransactionalTree, key: &[u8]) -> TxResult<Option<IVec>> {
+    let Some(hash) = files.get(key)? else {
+        return Ok(None);
+    };
+    Ok(chunks.get(hash)?.map(|entry| data_of(&entry)))
+}
+
+// Giảm số tham chiếu của blob `hash`, xóa blob khi về 0; trả về bytes của blob
+fn release_blob(chunks: &TransactionalTree, hash: &[u8]) -> TxResult<Option<IVec>> {
+    let Some(entry) = chunks.get(hash)? else {
+        return Ok(None);
+    };
+    let data = data_of(&entry);
+    match count_of(&entry) {
+        0 | 1 => {
+            chunks.remove(hash)?;
+        }
+        count => {
+            chunks.insert(hash, with_count(count - 1, &data))?;
+        }
+    }
+    Ok(Some(data))
+}
+
+/// Số chunk trỏ tới từng blob theo tree `files`
+pub fn references(blobs: &Blobs) -> sled::Result<HashMap<IVec, u64>> {
+    let mut refs: HashMap<IVec, u64> = HashMap::new();
+    for item in blobs.files.iter() {
+        let (_, hash) = item?;
+        *refs.entry(hash).or_default() += 1;
+    }
+    Ok(refs)
+}
+
+/// Đặt lại số tham chiếu của mọi blob theo tree `files` và xóa blob không còn ai trỏ tới.
+/// Trả về số blob đã sửa hoặc xóa. Chỉ chạy khi node không nhận ghi (xem module reindex).
+pub fn recount(blobs: &Blobs) -> sled::Result<u64> {
+    let refs = references(blobs)?;
+    let mut fixed = 0;
+    for item in blobs.chunks.iter() {
+        let (hash, entry) = item?;
+        match refs.get(&hash) {
+            None => {
+                blobs.chunks.remove(&hash)?;
+                fixed += 1;
+            }
+            Some(&count) if count != count_of(&entry) => {
+                blobs.chunks.insert(&hash, with_count(count, &data_of(&entry)))?;
+                fixed += 1;
+            }
+            Some(_) => {}
+        }
+    }
+    Ok(fixed)
+}
+
+/// Kết quả của `POST /admin/dedup/migrate`
+#[derive(Debug, Default, Serialize)]
+pub struct MigrateReport {
+    // Số chunk đã quét trong các shard
+    pub scanned: u64,
+    // Số chunk vừa chuyển sang blob dùng chung
+    pub migrated: u64,
+    // Trong số đó, số chunk trùng nội dung với một blob đã có
+    pub shared: u64,
+    // Số chunk bỏ qua vì bị ghi lại trong lúc chuyển hoặc record hỏng
+    pub skipped: u64,
+}
+
+/// Chuyển các chunk đang lưu value trong record hoặc trong `chunks_large` sang blob dùng
+/// chung. Mỗi chunk được chuyển trong một transaction cùng dung lượng quota và `file_chunks`,
+/// và chỉ khi record vẫn đúng là bytes vừa đọc, nên chạy được khi node đang nhận ghi. Chạy
+/// đồng bộ nên cần được gọi qua `blocking`.
+pub fn migrate(state: &AppState) -> sled::Result<MigrateReport> {
+    let mut report = MigrateReport::default();
+    let config = state.config.load();
+    for shard in &state.shards {
+        for item in shard.iter() {
+            let (db_key, bytes) = item?;
+            let Some((file_key, _)) = key::decode_raw(&db_key) else {
+                continue;
+            };
+            report.scanned += 1;
+            let _locked = state.large.lock([file_key]);
+            if state.large.blobs.files.contains_key(&db_key)? {
+                continue; // Đã dùng blob
+            }
+            let Some(mut stored) = record::decode(&bytes) else {
+                report.skipped += 1;
+                continue;
+            };
+            // Value cần chuyển: bytes đã lưu trong `chunks_large` (giữ nguyên) hoặc value trong
+            // record (được mã hóa như value lớn)
+            let (value, data, new_record) = if stored.large {
+                let Some(data) = state.large.store.get(&db_key)? else {
+                    report.skipped += 1;
+                    continue;
+                };
+                let Some(value) = crate::tier::value_of(&data) else {
+                    report.skipped += 1;
+                    continue;
+                };
+                (value, data.to_vec(), None)
+            } else if stored.value.is_empty() {
+                continue;
+            } else {
+                let value = std::mem::take(&mut stored.value);
+                stored.large = true;
+                let Ok(new_record) = crate::encode_value(stored, &config) else {
+                    report.skipped += 1;
+                    continue;
+                };
+                let data = crate::protect(value.clone().into_bytes(), &config);
+                (value, data, Some(new_record))
+            };
+            let owner = owner_of(state, file_key);
+            match move_chunk(state, shard, &db_key, &bytes, hash(value.as_bytes()), &data, new_record.as_deref(), file_key, &owner)? {
+                Some(shared) => {
+                    report.migrated += 1;
+                    report.shared += u64::from(shared);
+                }
+                None => report.skipped += 1,
+            }
+        }
+    }
+    state.flush()?;
+    Ok(report)
+}
+
+// Tenant sở hữu fileKey dạng chữ hoặc nhị phân
+fn owner_of(state: &AppState, file_key: &[u8]) -> String {
+    match std::str::from_utf8(file_key) {
+        Ok(file_key) => state.quotas.owner(file_key).to_string(),
+        Err(_) => state.quotas.owner_raw(file_key),
+    }
+}
+
+// Chuyển một chunk sang blob trong một transaction. `None` nếu record đã đổi kể từ lúc đọc.
+// Value trong backend ngoài sled (xem module store) bị xóa sau khi transaction xong.
+#[allow(clippy::too_many_arguments)]
+fn move_chunk(
+    state: &AppState,
+    shard: &sled::Tree,
+    db_key: &[u8],
+    old_record: &[u8],
+    hash: [u8; 32],
+    data: &[u8],
+    new_record: Option<&[u8]>,
+    file_key: &[u8],
+    owner: &str,
+) -> sled::Result<Option<bool>> {
+    let large_tree = state.large.tree();
+    let mut trees = vec![shard];
+    trees.extend(large_tree);
+    trees.extend([&state.large.blobs.files, &state.large.blobs.chunks, &state.quotas.usage, &state.files.tree]);
+    let result = trees.as_slice().transaction(|txs| {
+        let (shard, rest) = txs.split_first().expect("luôn có shard");
+        let (large, rest) = match large_tree {
+            Some(_) => (rest.first(), &rest[1..]),
+            None => (None, rest),
+        };
+        let [files, chunks, usage, file_stats] = rest else {
+            unreachable!("luôn có bốn tree sau shard và chunks_large");
+        };
+        if shard.get(db_key)?.as_deref() != Some(old_record) {
+            return Ok(None);
+        }
+        let put = put_in(files, chunks, db_key, &hash, data)?;
+        // Dung lượng đổi theo record mới cộng blob được dùng (blob trùng nội dung có sẵn có thể
+        // khác bytes vì mã hóa với nonce khác) so với record cũ cộng value lớn cũ
+        let new_len = match new_record {
+            Some(new_record) => {
+                shard.insert(db_key, new_record)?;
+                new_record.len()
+            }
+            None => old_record.len(),
+        };
+        let old_large = match large {
+            Some(large) => large.remove(db_key)?,
+            None => state.large.store.get(db_key)?,
+        }
+        .map_or(0, |v| v.len());
+        let used = chunks.get(hash)?.map_or(0, |entry| data_of(&entry).len());
+        let delta = (new_len + used) as i64 - (old_record.len() + old_large) as i64;
+        crate::quota::adjust_in(usage, owner, delta)?;
+        crate::cardinality::resize_in(file_stats, file_key, delta)?;
+        Ok(Some(put.shared))
+    });
+    let shared = result.map_err(|e: sled::transaction::TransactionError<()>| match e {
+        sled::transaction::TransactionError::Storage(e) => e,
+        sled::transaction::TransactionError::Abort(()) => sled::Error::Unsupported("transaction bị hủy".into()),
+    })?;
+    if shared.is_some() && large_tree.is_none() {
+        state.large.store.delete(db_key)?;
+    }
+    Ok(shared)
+}
@@ -0,0 +1,99 @@
+// ## LƯU CHUNK DẠNG DELTA ##
+//
+// Khi bật STORAGE_DELTA_ENCODING, /store nhận thêm gợi ý `baseChunkHash`: một chunk
+// khác của cùng fileKey mà chunk mới chỉ khác một chút (ví dụ phiên bản trước của cùng
+// vùng dữ liệu). Server tính bản diff bsdiff từ chunk base sang chunk mới, nén bằng
+// zstd rồi lưu bản diff thay cho dữ liệu đầy đủ, record ghi lại `deltaBase`. Nếu không
+// có base, base không đọc được, base cũng là delta (không cho phép chuỗi delta để mỗi
+// lần đọc chỉ cần thêm đúng một chunk) hoặc bản diff không nhỏ hơn cách lưu thường thì
+// chunk được lưu đầy đủ như cũ.
+//
+// Khi đọc, chunk delta được dựng lại từ base trước mọi bước khác nên client luôn nhận
+// dữ liệu gốc. Chunk base không nên bị ghi đè bằng dữ liệu khác: khi đó các chunk delta
+// dựa trên nó không dựng lại được và bị bỏ qua như value bị hỏng.
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use std::collections::{HashMap, HashSet};
+
+use crate::{compress, Chunk, StoredChunkValue};
+
+// Mức nén zstd cho bản diff; diff bsdiff chủ yếu là byte 0 nên nén rất tốt
+const ZSTD_LEVEL: i32 = 3;
+
+/// Dữ liệu gốc (chưa nén, chưa mã hóa) của một record không phải delta
+pub fn raw_of(record: &StoredChunkValue) -> Option<Vec<u8>> {
+    if record.delta_base.is_some() {
+        return None;
+    }
+    let value = compress::decompress(&record.value, record.encoding)?;
+    STANDARD.decode(value).ok()
+}
+
+/// Bản diff (Base64) từ `base` sang dữ liệu Base64 `value`, chỉ khi nhỏ hơn `full_len`
+/// (số byte Base64 nếu lưu đầy đủ)
+pub fn encode(base: &[u8], value: &str, full_len: usize) -> Option<String> {
+    let raw = STANDARD.decode(value).ok()?;
+    let mut patch = Vec::new();
+    bsdiff::diff(base, &raw, &mut patch).ok()?;
+    let packed = zstd::bulk::compress(&patch, ZSTD_LEVEL).ok()?;
+    let encoded = STANDARD.encode(packed);
+    (encoded.len() < full_len).then_some(encoded)
+}
+
+/// Dựng lại dữ liệu gốc (Base64) từ dữ liệu của base và bản diff Base64 `delta`
+pub fn apply(base: &[u8], delta: &str) -> Option<String> {
+    let packed = STANDARD.decode(delta).ok()?;
+    let patch = zstd::stream::decode_all(packed.as_slice()).ok()?;
+    let mut raw = Vec::new();
+    bsdiff::patch(base, &mut patch.as_slice(), &mut raw).ok()?;
+    Some(STANDARD.encode(raw))
+}
+
+/// Dựng lại các chunk delta trong danh sách chunk của một file (base luôn thuộc cùng
+/// file nên đã có trong danh sách). Chunk không dựng lại được bị bỏ qua.
+pub fn resolve(chunks: Vec<Chunk>, file_key: &str) -> Vec<Chunk> {
+    if chunks.iter().all(|c| c.delta_base.is_none()) {
+        return chunks;
+    }
+
+    // key -> dữ liệu gốc của các chunk được dùng làm base
+    let wanted: HashSet<String> = chunks
+        .iter()
+        .filter_map(|c| Some(crate::key::encode(file_key, c.delta_base.as_deref()?)))
+        .collect();
+    let bases: HashMap<&str, Vec<u8>> = chunks
+        .iter()
+        .filter(|c| c.delta_base.is_none() && wanted.contains(&c.key))
+        .filter_map(|c| {
+            let value = compress::decompress(&c.value, c.encoding)?;
+            Some((c.key.as_str(), STANDARD.decode(value).ok()?))
+        })
+        .collect();
+    let mut rebuilt: HashMap<String, String> = chunks
+        .iter()
+        .filter_map(|c| {
+            let base_key = crate::key::encode(file_key, c.delta_base.as_deref()?);
+            Some((c.key.clone(), apply(bases.get(base_key.as_str())?, &c.value)?))
+        })
+        .collect();
+
+    chunks
+        .into_iter()
+        .filter_map(|mut chunk| {
+            let Some(base_hash) = chunk.delta_base.take() else {
+                return Some(chunk);
+            };
+            match rebuilt.remove(&chunk.key) {
+                Some(value) => {
+                    chunk.value = value;
+                    chunk.encoding = compress::Codec::Identity;
+                    Some(chunk)
+                }
+                None => {
+                    tracing::error!("Không dựng lại được chunk delta {} từ base {}", chunk.key, base_hash);
+                    None
+                }
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,135 @@
+// ## TỰ CHUYỂN SANG CHỈ ĐỌC KHI ĐẦY ĐĨA ##
+//
+// Khi đĩa sắp hết hoặc đã hết chỗ, node tự bật chế độ chỉ đọc (cùng cờ với
+// `POST /admin/readonly`): các lần ghi trả về `503` `read_only` thay vì lần nào cũng `500`,
+// còn đọc vẫn phục vụ bình thường. `/health` báo `status: "degraded"` cùng
+// `diskFull: true`. Hai cách node nhận ra đĩa đầy:
+//
+// - Với STORAGE_DISK_MIN_FREE_BYTES > 0, task nền cứ mỗi STORAGE_DISK_RECHECK_SECS đọc
+//   số byte còn trống của filesystem chứa STORAGE_DATA_DIR; còn ít hơn mức này thì chuyển
+//   sang chỉ đọc, và khi chỗ trống trở lại từ mức này trở lên (ví dụ TTL sweeper vừa xóa
+//   chunk hết hạn hoặc quản trị viên dọn đĩa) thì tự nhận ghi trở lại. Đây là cách nên
+//   dùng: node dừng ghi trước khi sled thực sự gặp lỗi. sled nới file database theo
+//   từng bước lớn (hàng chục MB) và giữa hai lần kiểm tra vẫn có ghi, nên mức tối thiểu
+//   cần rộng rãi hơn lượng ghi trong một chu kỳ cộng vài segment của sled.
+// - Lần ghi chunk hoặc flush thất bại vì đĩa đầy (ENOSPC, hoặc vượt disk quota của hệ
+//   điều hành) trả về `507` và chuyển node sang chỉ đọc. Sau lỗi IO, sled từ chối mọi
+//   lần ghi tiếp theo cho tới khi database được mở lại, nên trường hợp này phải khởi động
+//   lại node sau khi dọn đĩa; task nền ghi thử mỗi chu kỳ và chỉ nhận ghi trở lại khi ghi
+//   thử thành công.
+//
+// Quản trị viên bật/tắt chỉ đọc bằng tay thì node bỏ trạng thái đĩa đầy và không tự phục
+// hồi lần đó nữa: quyết định của người vận hành được ưu tiên.
+
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::AppState;
+
+/// Số byte ghi thử để kiểm tra database đã ghi được trở lại
+pub const PROBE_BYTES: usize = 64 * 1024;
+
+// Mã lỗi của hệ điều hành khi hết chỗ trống (ENOSPC) hoặc vượt disk quota (EDQUOT)
+const ENOSPC: i32 = 28;
+const EDQUOT: i32 = 122;
+
+/// Lỗi sled có phải do đĩa đầy không
+pub fn is_full(e: &sled::Error) -> bool {
+    match e {
+        sled::Error::Io(e) => {
+            e.kind() == std::io::ErrorKind::StorageFull
+                || e.kind() == std::io::ErrorKind::QuotaExceeded
+                || matches!(e.raw_os_error(), Some(ENOSPC | EDQUOT))
+        }
+        _ => false,
+    }
+}
+
+/// Số byte còn trống cho process không phải root trên filesystem chứa `path`; `None` nếu
+/// không đọc được (đường dẫn không tồn tại hoặc hệ điều hành không hỗ trợ)
+#[cfg(unix)]
+pub fn free_bytes(path: &str) -> Option<u64> {
+    let path = std::ffi::CString::new(path).ok()?;
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `path` là chuỗi C hợp lệ, `stat` chỉ được đọc khi statvfs thành công
+    let stat = unsafe {
+        if libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) != 0 {
+            return None;
+        }
+        stat.assume_init()
+    };
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+pub fn free_bytes(_path: &str) -> Option<u64> {
+    None
+}
+
+impl AppState {
+    /// Chuyển node sang chỉ đọc vì đĩa đầy
+    pub(crate) fn degrade_disk_full(&self) {
+        if !self.disk_full.swap(true, Ordering::Relaxed) {
+            self.read_only.store(true, Ordering::Relaxed);
+            tracing::warn!("⚠️  Đĩa đầy: chuyển sang chế độ chỉ đọc cho tới khi có chỗ trống");
+        }
+    }
+
+    /// Node đang chỉ đọc vì đĩa đầy
+    pub fn is_disk_full(&self) -> bool {
+        self.disk_full.load(Ordering::Relaxed)
+    }
+}
+
+// Ghi thử rồi xóa dữ liệu thử; lỗi nghĩa là database vẫn chưa ghi được
+fn probe(db: &sled::Db) -> sled::Result<()> {
+    let tree = db.open_tree("disk_probe")?;
+    tree.insert("probe", vec![0u8; PROBE_BYTES])?;
+    let result = db.flush();
+    tree.remove("probe")?;
+    db.flush()?;
+    result.map(|_| ())
+}
+
+/// Task nền theo dõi chỗ trống của đĩa và tự phục hồi sau khi đĩa có chỗ trở lại
+pub async fn run(state: Arc<AppState>) {
+    loop {
+        let config = state.config.load();
+        let interval = Duration::from_secs(config.disk_recheck_secs);
+        let (data_dir, min_free) = (config.data_dir.clone(), config.disk_min_free_bytes);
+        drop(config);
+        tokio::time::sleep(interval).await;
+
+        let free = if min_free > 0 { free_bytes(&data_dir) } else { None };
+        let low = free.is_some_and(|free| free < min_free);
+        if !state.is_disk_full() {
+            if low && !state.is_read_only() {
+                tracing::warn!(
+                    "⚠️  Chỉ còn {} byte trống trong {} (tối thiểu {})",
+                    free.unwrap_or_default(),
+                    data_dir,
+                    min_free
+                );
+                state.degrade_disk_full();
+            }
+            continue;
+        }
+        if low {
+            continue;
+        }
+
+        let probe_state = state.clone();
+        match tokio::task::spawn_blocking(move || probe(&probe_state.db)).await {
+            Ok(Ok(())) => {
+                // Quản trị viên có thể đã tắt cờ trong lúc thử
+                if state.disk_full.swap(false, Ordering::Relaxed) {
+                    state.read_only.store(false, Ordering::Relaxed);
+                    tracing::info!("✅ Đĩa đã có chỗ trống: nhận ghi trở lại");
+                }
+            }
+            Ok(Err(e)) => tracing::error!("Database vẫn chưa ghi được (cần khởi động lại nếu đĩa đã được dọn): {}", e),
+            Err(_) => {}
+        }
+    }
+}
@@ -0,0 +1,131 @@
+// ## MÃ XÓA REED-SOLOMON CHO TỪNG CHUNK ##
+//
+// Khi STORAGE_ERASURE_DATA_SHARDS (k) > 0, mỗi record chunk ghi xuống (và value của chunk
+// lớn trong tree `chunks_large`, xem module tier) được chia thành k mảnh dữ liệu bằng nhau
+// (mảnh cuối đệm 0) và thêm STORAGE_ERASURE_PARITY_SHARDS (m, mặc định 2) mảnh parity
+// Reed-Solomon, dùng chung số học GF(2^8) với module parity. Mỗi mảnh kèm 8 byte đầu SHA-256
+// của nó nên mảnh bị hỏng được nhận ra khi đọc và coi như mất: chunk vẫn đọc được khi mất
+// hoặc hỏng tối đa m mảnh bất kỳ, với dung lượng thêm m/k cộng 8 byte mỗi mảnh.
+//
+// Khác với parity của file đã seal (tính một lần cho cả stripe nhiều chunk), mã xóa áp dụng
+// cho từng lần ghi và không cần seal. Các mảnh nằm liền nhau trong value của chính key đó:
+// byte tag 0x02, k, m, độ dài gốc (u32 big-endian), rồi k + m mảnh. Nhờ vậy mọi transaction
+// ghi, đổi tên và xóa chunk vẫn chỉ chạm đúng các key như trước, và tag khác với byte đầu
+// của record `json`/`bare` lẫn value Base64 trong `chunks_large` nên dữ liệu cũ vẫn đọc được.
+//
+// k và m của mỗi record nằm trong chính record nên đổi cấu hình lúc chạy chỉ áp dụng cho
+// lần ghi sau; chunk đã lưu giữ nguyên cách lưu cho tới khi bị ghi lại. Dữ liệu dựng lại từ
+// parity không được ghi đè lên đĩa: chunk được sửa ở lần ghi tiếp theo.
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+
+use crate::parity;
+
+/// Byte đầu của value đã mã hóa
+pub const TAG: u8 = 0x02;
+// Byte đầu SHA-256 lưu kèm mỗi mảnh
+const CHECKSUM_LEN: usize = 8;
+// Tag, k, m, độ dài gốc
+const HEADER_LEN: usize = 1 + 1 + 1 + 4;
+
+/// Tham số mã xóa: k mảnh dữ liệu và m mảnh parity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Params {
+    #[serde(rename = "dataShards")]
+    pub data_shards: usize,
+    #[serde(rename = "parityShards")]
+    pub parity_shards: usize,
+}
+
+impl Params {
+    /// Kiểm tra tham số: cần ít nhất một mảnh mỗi loại và k + m không vượt quá 255 (k, m
+    /// được lưu trong một byte)
+    pub fn new(data_shards: usize, parity_shards: usize) -> Result<Self, String> {
+        if parity_shards == 0 {
+            return Err("STORAGE_ERASURE_PARITY_SHARDS phải lớn hơn 0".to_string());
+        }
+        if data_shards + parity_shards > u8::MAX as usize {
+            return Err(format!(
+                "STORAGE_ERASURE_DATA_SHARDS + STORAGE_ERASURE_PARITY_SHARDS không được vượt quá {}",
+                u8::MAX
+            ));
+        }
+        Ok(Params { data_shards, parity_shards })
+    }
+}
+
+fn checksum(shard: &[u8]) -> [u8; CHECKSUM_LEN] {
+    Sha256::digest(shard)[..CHECKSUM_LEN].try_into().expect("SHA-256 dài hơn checksum")
+}
+
+/// Mã hóa `bytes` theo `params`; giữ nguyên khi không bật mã xóa
+pub fn encode(bytes: Vec<u8>, params: Option<Params>) -> Vec<u8> {
+    let Some(Params { data_shards: k, parity_shards: m }) = params else {
+        return bytes;
+    };
+    let width = bytes.len().div_ceil(k).max(1);
+    let data: Vec<Vec<u8>> = (0..k)
+        .map(|i| {
+            let mut shard = bytes.get(i * width..).unwrap_or_default().to_vec();
+            shard.resize(width, 0);
+            shard
+        })
+        .collect();
+    let parity = parity::encode(&data, m);
+
+    let mut out = Vec::with_capacity(HEADER_LEN + (k + m) * (CHECKSUM_LEN + width));
+    out.extend_from_slice(&[TAG, k as u8, m as u8]);
+    out.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+    for shard in data.iter().chain(&parity) {
+        out.extend_from_slice(&checksum(shard));
+        out.extend_from_slice(shard);
+    }
+    out
+}
+
+/// Tham số của value đã mã hóa, `None` nếu value không dùng mã xóa
+pub fn params_of(bytes: &[u8]) -> Option<Params> {
+    match bytes {
+        [TAG, k, m, ..] => Some(Params {
+            data_shards: *k as usize,
+            parity_shards: *m as usize,
+        }),
+        _ => None,
+    }
+}
+
+/// Dữ liệu gốc của value; value không dùng mã xóa được trả lại nguyên vẹn. `None` nếu mất
+/// hoặc hỏng nhiều hơn m mảnh.
+pub fn decode(bytes: &[u8]) -> Option<Cow<'_, [u8]>> {
+    let Some(Params { data_shards: k, parity_shards: m }) = params_of(bytes) else {
+        return Some(Cow::Borrowed(bytes));
+    };
+    let len = u32::from_be_bytes(bytes.get(3..HEADER_LEN)?.try_into().ok()?) as usize;
+    if k == 0 {
+        return None;
+    }
+    let width = len.div_ceil(k).max(1);
+    // Mảnh bị cắt cụt hoặc sai checksum coi như mất
+    let shards: Vec<Option<Vec<u8>>> = (0..k + m)
+        .map(|i| {
+            let start = HEADER_LEN + i * (CHECKSUM_LEN + width);
+            let stored = bytes.get(start..start + CHECKSUM_LEN)?;
+            let shard = bytes.get(start + CHECKSUM_LEN..start + CHECKSUM_LEN + width)?;
+            (checksum(shard) == stored).then(|| shard.to_vec())
+        })
+        .collect();
+
+    let data = if shards[..k].iter().all(Option::is_some) {
+        shards.into_iter().take(k).flatten().collect()
+    } else {
+        let lost = shards.iter().filter(|s| s.is_none()).count();
+        let data = parity::solve(&shards, k)?;
+        tracing::warn!("Dựng lại value từ parity, {} trên {} mảnh bị mất hoặc hỏng", lost, k + m);
+        data
+    };
+    let mut out: Vec<u8> = data.concat();
+    out.truncate(len);
+    Some(Cow::Owned(out))
+}
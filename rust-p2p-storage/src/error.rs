@@ -0,0 +1,344 @@
+// ## LỖI TRẢ VỀ CHO CLIENT ##
+//
+// Handler trả về `ApiError`; response có body JSON `{"code": ..., "message": ...}`.
+// `code` cố định, không phụ thuộc ngôn ngữ, để client xử lý bằng máy; `message` được
+// dịch theo header Accept-Language (hoặc STORAGE_ERROR_LANG khi client không yêu cầu)
+// bởi middleware `localize`. Log phía server vẫn giữ tiếng Việt.
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use std::sync::Arc;
+
+use crate::AppState;
+
+/// Ngôn ngữ của thông báo lỗi
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lang {
+    En,
+    Vi,
+}
+
+impl std::str::FromStr for Lang {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Chỉ xét phần ngôn ngữ chính, ví dụ "vi-VN" -> "vi"
+        let primary = s.trim().split(['-', '_']).next().unwrap_or_default();
+        match primary.to_ascii_lowercase().as_str() {
+            "en" => Ok(Lang::En),
+            "vi" => Ok(Lang::Vi),
+            other => Err(format!("ngôn ngữ không được hỗ trợ: {}", other)),
+        }
+    }
+}
+
+impl Lang {
+    /// Chọn ngôn ngữ được hỗ trợ có trọng số `q` cao nhất trong Accept-Language,
+    /// dùng `default` nếu header không có hoặc không chứa ngôn ngữ nào được hỗ trợ
+    pub fn negotiate(headers: &HeaderMap, default: Lang) -> Lang {
+        let Some(value) = headers.get(header::ACCEPT_LANGUAGE).and_then(|v| v.to_str().ok()) else {
+            return default;
+        };
+
+        let mut best: Option<(f32, Lang)> = None;
+        for item in value.split(',') {
+            let mut parts = item.split(';');
+            let Ok(lang) = parts.next().unwrap_or_default().parse::<Lang>() else {
+                continue;
+            };
+            let q = parts
+                .find_map(|p| p.trim().strip_prefix("q="))
+                .and_then(|q| q.parse().ok())
+                .unwrap_or(1.0);
+            if q > 0.0 && best.is_none_or(|(best_q, _)| q > best_q) {
+                best = Some((q, lang));
+            }
+        }
+        best.map_or(default, |(_, lang)| lang)
+    }
+}
+
+/// Lỗi trả về từ handler HTTP
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiError {
+    BadRequest,
+    InvalidFields,
+    InvalidKey,
+    InvalidBody,
+    JsonRequired,
+    DuplicateChunk,
+    HashMismatch,
+    ChecksumMismatch,
+    NotFound,
+    ChunkNotFound,
+    NamespaceNotFound,
+    Conflict,
+    NamespaceExists,
+    CasMismatch,
+    QuotaExceeded,
+    Sealed,
+    PayloadTooLarge,
+    ResponseTooLarge,
+    UnsupportedMediaType,
+    RangeNotSatisfiable,
+    Overloaded,
+    FileBusy,
+    RateLimited,
+    ClientBusy,
+    ReadOnly,
+    DiskFull,
+    StorageLimit,
+    Timeout,
+    Unauthorized,
+    Forbidden,
+    AdminDisabled,
+    Internal,
+}
+
+impl ApiError {
+    pub fn status(self) -> StatusCode {
+        match self {
+            ApiError::BadRequest
+            | ApiError::InvalidFields
+            | ApiError::InvalidKey
+            | ApiError::DuplicateChunk
+            | ApiError::HashMismatch => StatusCode::BAD_REQUEST,
+            ApiError::ChecksumMismatch | ApiError::InvalidBody => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::NotFound | ApiError::ChunkNotFound | ApiError::NamespaceNotFound => StatusCode::NOT_FOUND,
+            ApiError::Conflict | ApiError::CasMismatch | ApiError::NamespaceExists => StatusCode::CONFLICT,
+            ApiError::QuotaExceeded | ApiError::Sealed => StatusCode::FORBIDDEN,
+            ApiError::PayloadTooLarge | ApiError::ResponseTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::RangeNotSatisfiable => StatusCode::RANGE_NOT_SATISFIABLE,
+            ApiError::UnsupportedMediaType | ApiError::JsonRequired => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ApiError::Overloaded | ApiError::ReadOnly => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::FileBusy | ApiError::RateLimited | ApiError::ClientBusy => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::DiskFull | ApiError::StorageLimit => StatusCode::INSUFFICIENT_STORAGE,
+            ApiError::Timeout => StatusCode::REQUEST_TIMEOUT,
+            ApiError::Unauthorized => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden | ApiError::AdminDisabled => StatusCode::FORBIDDEN,
+            ApiError::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Mã lỗi cho máy đọc, giống nhau ở mọi ngôn ngữ
+    pub fn code(self) -> &'static str {
+        match self {
+            ApiError::BadRequest => "bad_request",
+            ApiError::InvalidFields => "invalid_fields",
+            ApiError::InvalidKey => "invalid_key",
+            ApiError::InvalidBody => "invalid_body",
+            ApiError::JsonRequired => "json_required",
+            ApiError::DuplicateChunk => "duplicate_chunk",
+            ApiError::HashMismatch => "hash_mismatch",
+            ApiError::ChecksumMismatch => "checksum_mismatch",
+            ApiError::NotFound => "not_found",
+            ApiError::ChunkNotFound => "chunk_not_found",
+            ApiError::NamespaceNotFound => "namespace_not_found",
+            ApiError::Conflict => "conflict",
+            ApiError::NamespaceExists => "namespace_exists",
+            ApiError::CasMismatch => "cas_mismatch",
+            ApiError::QuotaExceeded => "quota_exceeded",
+            ApiError::Sealed => "file_sealed",
+            ApiError::PayloadTooLarge => "payload_too_large",
+            ApiError::ResponseTooLarge => "response_too_large",
+            ApiError::RangeNotSatisfiable => "range_not_satisfiable",
+            ApiError::UnsupportedMediaType => "unsupported_media_type",
+            ApiError::Overloaded => "overloaded",
+            ApiError::FileBusy => "file_busy",
+            ApiError::RateLimited => "rate_limited",
+            ApiError::ClientBusy => "client_busy",
+            ApiError::ReadOnly => "read_only",
+            ApiError::DiskFull => "disk_full",
+            ApiError::StorageLimit => "storage_limit_exceeded",
+            ApiError::Timeout => "request_timeout",
+            ApiError::Unauthorized => "unauthorized",
+            ApiError::Forbidden => "insufficient_scope",
+            ApiError::AdminDisabled => "admin_disabled",
+            ApiError::Internal => "internal_error",
+        }
+    }
+
+    pub fn message(self, lang: Lang) -> &'static str {
+        match (self, lang) {
+            (ApiError::BadRequest, Lang::En) => "invalid request",
+            (ApiError::BadRequest, Lang::Vi) => "request không hợp lệ",
+            (ApiError::InvalidFields, Lang::En) => "request has invalid fields, see errors",
+            (ApiError::InvalidFields, Lang::Vi) => "request có trường không hợp lệ, xem errors",
+            (ApiError::InvalidKey, Lang::En) => {
+                "fileKey and chunkHash must be non-empty, within the length limit and free of ':' and control characters"
+            }
+            (ApiError::InvalidKey, Lang::Vi) => {
+                "fileKey và chunkHash không được rỗng, vượt giới hạn độ dài hay chứa ':' hoặc ký tự điều khiển"
+            }
+            (ApiError::InvalidBody, Lang::En) => "request body does not have the expected fields or types, see reason",
+            (ApiError::InvalidBody, Lang::Vi) => "body của request thiếu trường hoặc sai kiểu dữ liệu, xem reason",
+            (ApiError::JsonRequired, Lang::En) => "request body must be sent with Content-Type: application/json",
+            (ApiError::JsonRequired, Lang::Vi) => "body của request phải được gửi với Content-Type: application/json",
+            (ApiError::DuplicateChunk, Lang::En) => "batch contains the same chunk twice with different data",
+            (ApiError::DuplicateChunk, Lang::Vi) => "batch chứa cùng một chunk hai lần với dữ liệu khác nhau",
+            (ApiError::HashMismatch, Lang::En) => "chunkHash does not match the hash of the uploaded data",
+            (ApiError::HashMismatch, Lang::Vi) => "chunkHash không khớp với hash của dữ liệu gửi lên",
+            (ApiError::ChecksumMismatch, Lang::En) => "chunkHash does not match the hash of chunkData",
+            (ApiError::ChecksumMismatch, Lang::Vi) => "chunkHash không khớp với hash của chunkData",
+            (ApiError::NotFound, Lang::En) => "file not found",
+            (ApiError::NotFound, Lang::Vi) => "không tìm thấy file",
+            (ApiError::ChunkNotFound, Lang::En) => "chunk not found",
+            (ApiError::ChunkNotFound, Lang::Vi) => "không tìm thấy chunk",
+            (ApiError::NamespaceNotFound, Lang::En) => "namespace not found",
+            (ApiError::NamespaceNotFound, Lang::Vi) => "không tìm thấy namespace",
+            (ApiError::Conflict, Lang::En) => "a file or chunk with this key already exists",
+            (ApiError::Conflict, Lang::Vi) => "đã có file hoặc chunk với key này",
+            (ApiError::NamespaceExists, Lang::En) => "a namespace with this name already exists",
+            (ApiError::NamespaceExists, Lang::Vi) => "đã có namespace với tên này",
+            (ApiError::CasMismatch, Lang::En) => "the chunk's current value does not match the expected value",
+            (ApiError::CasMismatch, Lang::Vi) => "giá trị hiện tại của chunk không khớp với giá trị mong đợi",
+            (ApiError::QuotaExceeded, Lang::En) => "storage quota exceeded",
+            (ApiError::QuotaExceeded, Lang::Vi) => "vượt quá quota lưu trữ",
+            (ApiError::Sealed, Lang::En) => "file is sealed and can no longer be modified",
+            (ApiError::Sealed, Lang::Vi) => "file đã bị seal, không thể sửa đổi",
+            (ApiError::PayloadTooLarge, Lang::En) => "payload too large",
+            (ApiError::PayloadTooLarge, Lang::Vi) => "dữ liệu gửi lên quá lớn",
+            (ApiError::ResponseTooLarge, Lang::En) => {
+                "response exceeds the size limit; use /file/:fileKey/tail or /file/:fileKey/stream-download"
+            }
+            (ApiError::ResponseTooLarge, Lang::Vi) => {
+                "response vượt quá giới hạn kích thước; hãy dùng /file/:fileKey/tail hoặc /file/:fileKey/stream-download"
+            }
+            (ApiError::RangeNotSatisfiable, Lang::En) => "requested byte range is outside the file",
+            (ApiError::RangeNotSatisfiable, Lang::Vi) => "khoảng byte được yêu cầu nằm ngoài file",
+            (ApiError::UnsupportedMediaType, Lang::En) => "content type is not allowed on this node",
+            (ApiError::UnsupportedMediaType, Lang::Vi) => "kiểu nội dung không được phép lưu trên node này",
+            (ApiError::Overloaded, Lang::En) => "server is busy, retry later",
+            (ApiError::Overloaded, Lang::Vi) => "server đang quá tải, hãy thử lại sau",
+            (ApiError::FileBusy, Lang::En) => "too many concurrent requests for this file, retry later",
+            (ApiError::FileBusy, Lang::Vi) => "file đang có quá nhiều request đồng thời, hãy thử lại sau",
+            (ApiError::RateLimited, Lang::En) => "request rate limit exceeded, retry after the time in Retry-After",
+            (ApiError::RateLimited, Lang::Vi) => "vượt giới hạn tốc độ request, hãy thử lại sau thời gian trong Retry-After",
+            (ApiError::ClientBusy, Lang::En) => "too many concurrent file reads from this client, retry later",
+            (ApiError::ClientBusy, Lang::Vi) => "client đang có quá nhiều lượt đọc file đồng thời, hãy thử lại sau",
+            (ApiError::ReadOnly, Lang::En) => "node is in read-only maintenance mode, writes are disabled",
+            (ApiError::ReadOnly, Lang::Vi) => "node đang ở chế độ bảo trì chỉ đọc, không nhận ghi",
+            (ApiError::DiskFull, Lang::En) => "disk is full, node switched to read-only until space is freed",
+            (ApiError::DiskFull, Lang::Vi) => "đĩa đầy, node chuyển sang chỉ đọc cho tới khi có chỗ trống",
+            (ApiError::StorageLimit, Lang::En) => "storage limit of this node reached",
+            (ApiError::StorageLimit, Lang::Vi) => "node đã đạt giới hạn dung lượng lưu trữ",
+            (ApiError::Timeout, Lang::En) => "request took longer than this route allows",
+            (ApiError::Timeout, Lang::Vi) => "request xử lý quá thời gian cho phép của route",
+            (ApiError::Unauthorized, Lang::En) => "missing or invalid API key",
+            (ApiError::Unauthorized, Lang::Vi) => "thiếu API key hoặc API key không đúng",
+            (ApiError::Forbidden, Lang::En) => "API token does not allow this operation",
+            (ApiError::Forbidden, Lang::Vi) => "API token không có quyền thực hiện thao tác này",
+            (ApiError::AdminDisabled, Lang::En) => "admin API is disabled on this node",
+            (ApiError::AdminDisabled, Lang::Vi) => "API quản trị đang tắt trên node này",
+            (ApiError::Internal, Lang::En) => "internal server error",
+            (ApiError::Internal, Lang::Vi) => "lỗi nội bộ của server",
+        }
+    }
+
+    /// Response với thông báo theo ngôn ngữ `lang`
+    pub fn render(self, lang: Lang) -> Response {
+        self.render_with(lang, None)
+    }
+
+    /// Response có thêm các trường `details` trong body, ví dụ `{"key": ...}` của chunk bị
+    /// trùng. Các trường này được giữ nguyên khi `localize` dịch lại thông báo.
+    pub fn with_details(self, details: serde_json::Map<String, serde_json::Value>) -> Response {
+        self.render_with(Lang::En, Some(ErrorDetails(details)))
+    }
+
+    fn render_with(self, lang: Lang, details: Option<ErrorDetails>) -> Response {
+        let mut body = details.clone().map(|d| d.0).unwrap_or_default();
+        body.insert("code".into(), self.code().into());
+        body.insert("message".into(), self.message(lang).into());
+        let mut response = (self.status(), Json(body)).into_response();
+        // Đánh dấu để middleware `localize` biết cần dịch lại thông báo
+        response.extensions_mut().insert(self);
+        if let Some(details) = details {
+            response.extensions_mut().insert(details);
+        }
+        response
+    }
+}
+
+// Các trường bổ sung của một response lỗi (xem `ApiError::with_details`)
+#[derive(Debug, Clone)]
+struct ErrorDetails(serde_json::Map<String, serde_json::Value>);
+
+// Các hàm tiện ích nội bộ vẫn trả `StatusCode`; `?` trong handler tự chuyển sang mã lỗi
+impl From<StatusCode> for ApiError {
+    fn from(status: StatusCode) -> Self {
+        match status {
+            StatusCode::BAD_REQUEST => ApiError::BadRequest,
+            StatusCode::NOT_FOUND => ApiError::NotFound,
+            StatusCode::CONFLICT => ApiError::Conflict,
+            StatusCode::FORBIDDEN => ApiError::QuotaExceeded,
+            StatusCode::PAYLOAD_TOO_LARGE => ApiError::PayloadTooLarge,
+            StatusCode::UNSUPPORTED_MEDIA_TYPE => ApiError::UnsupportedMediaType,
+            StatusCode::SERVICE_UNAVAILABLE => ApiError::Overloaded,
+            StatusCode::INSUFFICIENT_STORAGE => ApiError::DiskFull,
+            _ => ApiError::Internal,
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        self.render(Lang::En)
+    }
+}
+
+// Độ dài tối đa của thông báo lỗi văn bản được giữ lại trong `reason`
+const MAX_REASON_BYTES: usize = 1024;
+
+/// Lỗi tương ứng với response văn bản của extractor axum (`Json`, `Query`, `Path`, giới
+/// hạn body) khi parse request thất bại, `None` nếu response không phải loại đó
+fn rejection_error(response: &Response) -> Option<ApiError> {
+    let is_text = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/plain"));
+    if !is_text || response.extensions().get::<ApiError>().is_some() {
+        return None;
+    }
+    match response.status() {
+        StatusCode::BAD_REQUEST => Some(ApiError::BadRequest),
+        StatusCode::UNPROCESSABLE_ENTITY => Some(ApiError::InvalidBody),
+        StatusCode::UNSUPPORTED_MEDIA_TYPE => Some(ApiError::JsonRequired),
+        StatusCode::PAYLOAD_TOO_LARGE => Some(ApiError::PayloadTooLarge),
+        _ => None,
+    }
+}
+
+/// Middleware dịch thông báo lỗi sang ngôn ngữ client yêu cầu. Lỗi parse request của
+/// extractor axum vốn là văn bản thuần cũng được đổi thành body JSON như mọi lỗi khác,
+/// với thông báo gốc của axum trong `reason`.
+pub async fn localize(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let lang = Lang::negotiate(request.headers(), state.config.load().error_lang);
+    let response = next.run(request).await;
+    if let Some(err) = rejection_error(&response) {
+        let reason = axum::body::to_bytes(response.into_body(), MAX_REASON_BYTES).await.unwrap_or_default();
+        let mut details = serde_json::Map::new();
+        details.insert("reason".into(), String::from_utf8_lossy(&reason).into_owned().into());
+        return err.render_with(lang, Some(ErrorDetails(details)));
+    }
+    match response.extensions().get::<ApiError>() {
+        Some(&err) if lang != Lang::En => {
+            let mut localized = err.render_with(lang, response.extensions().get::<ErrorDetails>().cloned());
+            // Header của lỗi (Retry-After của module ratelimit, Content-Range của `416`...)
+            // phải đi theo response đã dịch; chỉ các header mô tả body cũ bị bỏ
+            for (name, value) in response.headers() {
+                if !matches!(*name, header::CONTENT_TYPE | header::CONTENT_LENGTH | header::CONTENT_ENCODING) {
+                    localized.headers_mut().append(name, value.clone());
+                }
+            }
+            localized
+        }
+        _ => response,
+    }
+}
@@ -0,0 +1,356 @@
+// ## EXPORT / IMPORT TOÀN BỘ DATABASE ##
+//
+// `GET /admin/export` (cần quyền quản trị) trả về mọi cặp key/value của mọi tree trong
+// database dạng nhị phân, để chuyển node sang máy khác hoặc cất bản sao lưu ở nơi khác;
+// `POST /admin/import` nhận lại đúng luồng đó. Luồng bắt đầu bằng 8 byte `P2PDUMP1`, sau
+// đó là các frame, mỗi frame mở đầu bằng một byte loại (số nguyên đều là big-endian):
+//
+// - `0x01` tree: u32 độ dài tên rồi tên tree; các cặp phía sau thuộc tree này.
+// - `0x02` cặp: u32 độ dài key, key, u32 độ dài value, value.
+// - `0x00` kết thúc: u64 số cặp của cả luồng. Luồng không có frame này là bị cắt giữa chừng.
+//
+// Trước khi export, bộ đệm ghi (module write_buffer) được ghi hết và database được flush.
+// Export đọc từng tree bằng iterator của sled nên không chặn lần ghi nào: mỗi cặp là một
+// value hoàn chỉnh, nhưng như snapshot của module backup, lần ghi diễn ra đúng lúc export
+// có thể chỉ có mặt ở một phần các tree. Cần bản chụp đúng một thời điểm thì bật
+// `POST /admin/readonly` trong lúc export. Value được xuất nguyên dạng lưu trên đĩa (đã nén,
+// đã mã hóa nếu bật STORAGE_ENCRYPTION_KEY), nên node nhập phải dùng cùng khóa mã hóa và
+// cùng STORAGE_SHARDS.
+//
+// Import ghi thẳng từng cặp vào tree cùng tên (tạo tree nếu chưa có), không qua quota hay
+// replication. Cặp trùng key và trùng value được bỏ qua; khi value khác, `?onConflict=`
+// quyết định: `skip` (mặc định) giữ value hiện có, `overwrite` ghi đè, `abort` dừng và trả
+// về `409`. Các cặp đã ghi trước khi dừng (luồng hỏng, bị cắt, `abort`) vẫn được giữ, và
+// import lại cùng luồng là an toàn. Import vào database đang có dữ liệu có thể làm lệch các
+// index phụ (số chunk của file, dung lượng của tenant): chạy `POST /admin/reindex` sau đó.
+
+use axum::body::{Body, Bytes};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::mpsc::Sender;
+
+use crate::error::ApiError;
+use crate::AppState;
+
+const MAGIC: &[u8; 8] = b"P2PDUMP1";
+const END: u8 = 0x00;
+const TREE: u8 = 0x01;
+const PAIR: u8 = 0x02;
+// Độ dài tối đa của một tên tree, key hoặc value, để luồng hỏng không làm cấp phát quá lớn
+const MAX_FIELD: usize = 1 << 30;
+// Số byte gom lại trước khi gửi một phần của luồng export
+const SEND_BYTES: usize = 64 * 1024;
+// Số byte frame gom lại trước khi ghi một lô khi import
+const BATCH_BYTES: usize = 4 * 1024 * 1024;
+
+/// Cách xử lý cặp có key đã tồn tại với value khác khi import
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Conflict {
+    #[default]
+    Skip,
+    Overwrite,
+    Abort,
+}
+
+/// Kết quả của một lần import
+#[derive(Debug, Default, Serialize)]
+pub struct ImportReport {
+    // Số frame tree trong luồng
+    pub trees: u64,
+    // Số cặp đã ghi, kể cả cặp ghi đè
+    pub imported: u64,
+    // Số cặp đã ghi đè value khác (`overwrite`)
+    pub overwritten: u64,
+    // Số cặp đã có sẵn với đúng value đó
+    pub unchanged: u64,
+    // Số cặp bị bỏ vì value hiện có khác (`skip`)
+    pub skipped: u64,
+}
+
+/// Luồng export của toàn bộ database. Database cần được flush trước khi gọi.
+pub fn stream(state: &Arc<AppState>) -> Body {
+    let (tx, rx) = tokio::sync::mpsc::channel::<std::io::Result<Bytes>>(16);
+    let db = state.db.clone();
+    tokio::task::spawn_blocking(move || match write_all(&db, &tx) {
+        Ok(Some(pairs)) => tracing::info!("-> Đã export {} cặp key/value", pairs),
+        Ok(None) => tracing::warn!("Client ngắt kết nối giữa lúc export"),
+        Err(e) => {
+            tracing::error!("Lỗi khi đọc database để export: {}", e);
+            let _ = tx.blocking_send(Err(std::io::Error::other(e)));
+        }
+    });
+    Body::from_stream(tokio_stream::wrappers::ReceiverStream::new(rx))
+}
+
+// Ghi mọi tree vào `tx`; `None` khi client ngắt kết nối giữa chừng
+fn write_all(db: &sled::Db, tx: &Sender<std::io::Result<Bytes>>) -> sled::Result<Option<u64>> {
+    let mut out = MAGIC.to_vec();
+    let mut pairs = 0u64;
+    for name in db.tree_names() {
+        let tree = db.open_tree(&name)?;
+        out.push(TREE);
+        put_field(&mut out, &name);
+        for item in tree.iter() {
+            let (key, value) = item?;
+            out.push(PAIR);
+            put_field(&mut out, &key);
+            put_field(&mut out, &value);
+            pairs += 1;
+            if out.len() >= SEND_BYTES && tx.blocking_send(Ok(Bytes::from(std::mem::take(&mut out)))).is_err() {
+                return Ok(None);
+            }
+        }
+    }
+    out.push(END);
+    out.extend_from_slice(&pairs.to_be_bytes());
+    Ok(tx.blocking_send(Ok(Bytes::from(out))).ok().map(|_| pairs))
+}
+
+fn put_field(out: &mut Vec<u8>, bytes: &[u8]) {
+    let len = u32::try_from(bytes.len()).expect("key/value của sled không dài quá 4GB");
+    out.extend_from_slice(&len.to_be_bytes());
+    out.extend_from_slice(bytes);
+}
+
+/// Một frame của luồng export
+#[derive(Debug, PartialEq, Eq)]
+pub enum Frame {
+    Tree(Vec<u8>),
+    Pair(Vec<u8>, Vec<u8>),
+    End(u64),
+}
+
+impl Frame {
+    fn len(&self) -> usize {
+        match self {
+            Frame::Tree(name) => name.len(),
+            Frame::Pair(key, value) => key.len() + value.len(),
+            Frame::End(_) => 0,
+        }
+    }
+}
+
+/// Tách luồng export thành frame khi dữ liệu tới dần từng phần
+#[derive(Debug, Default)]
+pub struct Decoder {
+    buffer: Vec<u8>,
+    // Vị trí đầu phần chưa đọc trong `buffer`
+    pos: usize,
+    started: bool,
+}
+
+impl Decoder {
+    /// Thêm dữ liệu vừa nhận
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buffer.drain(..self.pos);
+        self.pos = 0;
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Frame kế tiếp, `None` khi cần thêm dữ liệu
+    pub fn next_frame(&mut self) -> Result<Option<Frame>, String> {
+        if !self.started {
+            let Some(magic) = self.buffer.get(self.pos..self.pos + MAGIC.len()) else {
+                return Ok(None);
+            };
+            if magic != MAGIC {
+                return Err("không phải luồng export của storage server".to_string());
+            }
+            self.pos += MAGIC.len();
+            self.started = true;
+        }
+
+        let mut cursor = Cursor {
+            data: &self.buffer[self.pos..],
+            at: 0,
+        };
+        let Some(&[tag]) = cursor.take(1) else {
+            return Ok(None);
+        };
+        let frame = match tag {
+            END => cursor.take(8).map(|n| Frame::End(u64::from_be_bytes(n.try_into().expect("đủ 8 byte")))),
+            TREE => cursor.field()?.map(|name| Frame::Tree(name.to_vec())),
+            PAIR => match cursor.field()? {
+                Some(key) => cursor.field()?.map(|value| Frame::Pair(key.to_vec(), value.to_vec())),
+                None => None,
+            },
+            other => return Err(format!("loại frame không hợp lệ: {:#04x}", other)),
+        };
+        if frame.is_some() {
+            self.pos += cursor.at;
+        }
+        Ok(frame)
+    }
+
+    /// Còn dữ liệu chưa thành frame hay không
+    pub fn has_pending(&self) -> bool {
+        self.pos < self.buffer.len()
+    }
+}
+
+struct Cursor<'a> {
+    data: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.at..self.at + len)?;
+        self.at += len;
+        Some(bytes)
+    }
+
+    // Một trường có độ dài u32 đứng trước
+    fn field(&mut self) -> Result<Option<&'a [u8]>, String> {
+        let Some(len) = self.take(4) else {
+            return Ok(None);
+        };
+        let len = u32::from_be_bytes(len.try_into().expect("đủ 4 byte")) as usize;
+        if len > MAX_FIELD {
+            return Err(format!("trường dài {} byte vượt giới hạn", len));
+        }
+        Ok(self.take(len))
+    }
+}
+
+/// Đọc luồng export trong `body` và ghi vào database theo `conflict`
+pub async fn import(state: &Arc<AppState>, body: Body, conflict: Conflict) -> Result<ImportReport, ApiError> {
+    let importer = Importer {
+        db: state.db.clone(),
+        conflict,
+        tree: None,
+        pairs: 0,
+        ended: false,
+        report: ImportReport::default(),
+    };
+    let result = read_body(importer, body).await;
+    // Giữ bền những gì đã ghi, kể cả khi luồng bị lỗi giữa chừng
+    let flush_state = state.clone();
+    crate::blocking(move || {
+        flush_state.flush().map_err(|e| {
+            tracing::error!("Lỗi khi flush database sau khi import: {}", e);
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR
+        })
+    })
+    .await?;
+    state.files.recount();
+    result
+}
+
+async fn read_body(mut importer: Importer, body: Body) -> Result<ImportReport, ApiError> {
+    let mut stream = body.into_data_stream();
+    let mut decoder = Decoder::default();
+    let mut batch = Vec::new();
+    let mut batch_bytes = 0;
+    loop {
+        let bytes = match tokio_stream::StreamExt::next(&mut stream).await {
+            Some(Ok(bytes)) => bytes,
+            Some(Err(e)) => {
+                tracing::error!("Lỗi khi đọc luồng import: {}", e);
+                return Err(ApiError::BadRequest);
+            }
+            None => break,
+        };
+        decoder.push(&bytes);
+        while let Some(frame) = decoder.next_frame().map_err(|e| {
+            tracing::warn!("Luồng import không hợp lệ: {}", e);
+            ApiError::BadRequest
+        })? {
+            batch_bytes += frame.len();
+            batch.push(frame);
+        }
+        if batch_bytes >= BATCH_BYTES {
+            importer = apply_batch(importer, std::mem::take(&mut batch)).await?;
+            batch_bytes = 0;
+        }
+    }
+    importer = apply_batch(importer, batch).await?;
+    if !importer.ended || decoder.has_pending() {
+        tracing::warn!("Luồng import bị cắt hoặc có dữ liệu thừa sau frame kết thúc");
+        return Err(ApiError::BadRequest);
+    }
+    Ok(importer.report)
+}
+
+// Ghi một lô frame trên blocking pool
+async fn apply_batch(mut importer: Importer, frames: Vec<Frame>) -> Result<Importer, ApiError> {
+    if frames.is_empty() {
+        return Ok(importer);
+    }
+    crate::blocking(move || Ok(importer.apply(frames).map(|()| importer))).await?
+}
+
+struct Importer {
+    db: sled::Db,
+    conflict: Conflict,
+    // Tree của frame tree gần nhất
+    tree: Option<sled::Tree>,
+    // Số frame cặp đã đọc, so với frame kết thúc
+    pairs: u64,
+    ended: bool,
+    report: ImportReport,
+}
+
+impl Importer {
+    fn apply(&mut self, frames: Vec<Frame>) -> Result<(), ApiError> {
+        for frame in frames {
+            if self.ended {
+                tracing::warn!("Luồng import có frame sau frame kết thúc");
+                return Err(ApiError::BadRequest);
+            }
+            match frame {
+                Frame::Tree(name) => {
+                    self.tree = Some(self.db.open_tree(name).map_err(db_error)?);
+                    self.report.trees += 1;
+                }
+                Frame::Pair(key, value) => {
+                    self.pairs += 1;
+                    self.insert(key, value)?;
+                }
+                Frame::End(pairs) if pairs == self.pairs => self.ended = true,
+                Frame::End(pairs) => {
+                    tracing::warn!("Luồng import báo {} cặp nhưng có {} cặp", pairs, self.pairs);
+                    return Err(ApiError::BadRequest);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn insert(&mut self, key: Vec<u8>, value: Vec<u8>) -> Result<(), ApiError> {
+        let Some(tree) = &self.tree else {
+            tracing::warn!("Luồng import có cặp key/value trước frame tree đầu tiên");
+            return Err(ApiError::BadRequest);
+        };
+        let current = match tree.compare_and_swap(&key, None::<&[u8]>, Some(value.as_slice())).map_err(db_error)? {
+            Ok(()) => {
+                self.report.imported += 1;
+                return Ok(());
+            }
+            Err(cas) => cas.current,
+        };
+        if current.as_deref() == Some(value.as_slice()) {
+            self.report.unchanged += 1;
+            return Ok(());
+        }
+        match self.conflict {
+            Conflict::Skip => self.report.skipped += 1,
+            Conflict::Overwrite => {
+                tree.insert(key, value).map_err(db_error)?;
+                self.report.imported += 1;
+                self.report.overwritten += 1;
+            }
+            Conflict::Abort => {
+                tracing::warn!("Dừng import: key {} đã có value khác", String::from_utf8_lossy(&key));
+                return Err(ApiError::Conflict);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn db_error(e: sled::Error) -> ApiError {
+    tracing::error!("Lỗi database khi import: {}", e);
+    ApiError::Internal
+}
@@ -0,0 +1,220 @@
+// ## GIỚI HẠN TẦN SUẤT FSYNC ##
+//
+// Mỗi lần ghi flush database trước khi trả về, nên một vòng lặp store dày đặc có thể đòi
+// nhiều fsync hơn mức đĩa chịu được. Với STORAGE_FLUSH_MIN_INTERVAL_MS > 0, hai lần flush
+// liên tiếp cách nhau ít nhất khoảng đó: lần ghi đầu tiên gặp lúc chưa có flush nào đang
+// chờ sẽ làm "leader", ngủ tới khi hết khoảng cách tối thiểu rồi flush; các lần ghi tới
+// trong lúc leader đang ngủ dùng chung lần flush đó. Lần ghi tới khi flush đã bắt đầu phải
+// chờ lần flush kế tiếp, vì lần đang chạy có thể không bao gồm dữ liệu của nó. Như vậy mỗi
+// lần ghi vẫn chỉ trả về sau khi có một lần flush bắt đầu sau nó hoàn tất thành công,
+// tính bền vững không đổi, chỉ độ trễ tăng thêm tối đa một khoảng cách tối thiểu.
+//
+// Giá trị 0 (mặc định) giữ hành vi cũ: mỗi lần ghi tự flush.
+//
+// ## FLUSH THEO SỐ LẦN GHI (STORAGE_FLUSH_EVERY_WRITES) ##
+//
+// Với STORAGE_FLUSH_EVERY_WRITES = N > 0, lần ghi không tự flush mà chỉ cộng số chunk vừa
+// ghi vào bộ đếm chunk chưa flush; lần ghi đưa bộ đếm tới N sẽ flush và chờ flush xong
+// trước khi trả về, các lần ghi khác trả về ngay. Khác với giới hạn theo thời gian ở
+// trên, chế độ này giới hạn SỐ chunk có thể mất khi process chết (tối đa N - 1), không
+// giới hạn thời gian: khi không còn lần ghi mới, chunk chưa flush nằm chờ tới lần flush
+// kế tiếp (đọc `consistency=strong`, thao tác admin, tắt server, hoặc lần flush định kỳ
+// của sled). Mọi lần flush đều đặt lại bộ đếm.
+//
+// Hai chế độ loại trừ nhau: đặt cả STORAGE_FLUSH_MIN_INTERVAL_MS và
+// STORAGE_FLUSH_EVERY_WRITES là lỗi cấu hình. STORAGE_FLUSH_EVERY_WRITES dùng được cùng
+// bộ đệm ghi (STORAGE_WRITE_BUFFER): mỗi lượt ghi bộ đệm được tính bằng số chunk của nó.
+//
+// ## CHÍNH SÁCH BỀN VỮNG (STORAGE_DURABILITY) ##
+//
+// - `always` (mặc định): mỗi lần ghi flush trước khi trả về, như mô tả ở trên.
+// - `batched`: flush theo số lần ghi như trên, N = STORAGE_FLUSH_EVERY_WRITES (mặc định
+//   `DEFAULT_BATCH_WRITES` khi không đặt). Đặt STORAGE_FLUSH_EVERY_WRITES mà không đặt
+//   STORAGE_DURABILITY cũng chọn chế độ này.
+// - `interval`: lần ghi không bao giờ tự flush; task nền flush mỗi
+//   STORAGE_DURABILITY_INTERVAL_MS nếu có chunk chưa flush. Process chết có thể mất các
+//   chunk được ghi trong khoảng đó.
+//
+// Ở mọi chế độ, mỗi chunk ghi xong được đánh một số thứ tự "đã xác nhận" và mỗi lần flush
+// thành công nâng số thứ tự "đã xuống đĩa" lên số đã xác nhận lúc nó bắt đầu. `GET /stats`
+// trả về chính sách đang dùng cùng hai số này, nên client dùng `interval` hay `batched`
+// biết được các lần ghi đã được xác nhận đã thực sự nằm trên đĩa hay chưa.
+
+use serde::Serialize;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::AppState;
+
+/// N mặc định của chế độ `batched` khi STORAGE_FLUSH_EVERY_WRITES không được đặt
+pub const DEFAULT_BATCH_WRITES: u64 = 100;
+
+/// Khi nào lần ghi được flush xuống đĩa (STORAGE_DURABILITY)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Durability {
+    Always,
+    Interval,
+    Batched,
+}
+
+impl FromStr for Durability {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "always" => Ok(Durability::Always),
+            "interval" => Ok(Durability::Interval),
+            "batched" => Ok(Durability::Batched),
+            other => Err(format!("chính sách bền vững không hỗ trợ: {}", other)),
+        }
+    }
+}
+
+/// Tiến độ xuống đĩa của các lần ghi đã xác nhận, trả về trong `GET /stats`
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Progress {
+    pub policy: Durability,
+    // Số chunk đã ghi và xác nhận với client từ khi server chạy
+    #[serde(rename = "acknowledgedWrites")]
+    pub acknowledged: u64,
+    // Số chunk trong số đó chắc chắn đã nằm trên đĩa
+    #[serde(rename = "durableWrites")]
+    pub durable: u64,
+    // Số chunk đã xác nhận nhưng chưa được flush
+    #[serde(rename = "pendingWrites")]
+    pub pending: u64,
+    // Thời điểm flush thành công gần nhất (unix mili giây), 0 nếu chưa có
+    #[serde(rename = "lastFlushMs")]
+    pub last_flush_ms: u64,
+}
+
+#[derive(Default)]
+pub struct FlushLimiter {
+    inner: Mutex<Inner>,
+    done: Condvar,
+    // Số chunk đã ghi từ lần flush gần nhất (STORAGE_FLUSH_EVERY_WRITES)
+    unflushed: AtomicU64,
+    // Số thứ tự của chunk được xác nhận gần nhất
+    acknowledged: AtomicU64,
+    // Số thứ tự lớn nhất đã được một lần flush thành công bao gồm
+    durable: AtomicU64,
+}
+
+#[derive(Default)]
+struct Inner {
+    // Số lần flush đã bắt đầu; lần ghi mới tới chờ lần flush thứ `started + 1`
+    started: u64,
+    // Đã có leader chịu trách nhiệm cho lần flush kế tiếp hoặc lần flush đang chạy
+    leader: bool,
+    // Số thứ tự của lần flush gần nhất đã xong (thành công hay không)
+    finished: u64,
+    // Số thứ tự lớn nhất của lần flush thành công
+    succeeded: u64,
+    // Thời điểm bắt đầu lần flush gần nhất
+    last_started: Option<Instant>,
+}
+
+impl FlushLimiter {
+    /// Cộng `count` chunk vừa ghi vào bộ đếm chunk chưa flush; trả về `true` nếu bộ đếm
+    /// đạt `every` và lời gọi này phải flush
+    pub fn count_writes(&self, count: u64, every: u64) -> bool {
+        self.unflushed.fetch_add(count, Ordering::Relaxed) + count >= every
+    }
+
+    /// Đánh số thứ tự cho `count` chunk vừa được ghi vào sled; gọi trước lần flush có thể
+    /// bao gồm chúng
+    pub fn acknowledge(&self, count: u64) {
+        self.acknowledged.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Số chunk đã xác nhận và số chunk đã xuống đĩa
+    pub fn progress(&self) -> (u64, u64) {
+        let durable = self.durable.load(Ordering::Relaxed);
+        (self.acknowledged.load(Ordering::Relaxed).max(durable), durable)
+    }
+
+    // Flush thật và nâng số thứ tự đã xuống đĩa lên số đã xác nhận trước khi flush
+    fn flush_now(&self, db: &sled::Db, flushes: &AtomicU64) -> sled::Result<()> {
+        let covered = self.acknowledged.load(Ordering::Relaxed);
+        flushes.fetch_add(1, Ordering::Relaxed);
+        db.flush()?;
+        self.durable.fetch_max(covered, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Flush `db`, cách lần flush trước ít nhất `min_interval`. Chỉ trả về sau khi một
+    /// lần flush bắt đầu sau lời gọi này đã thành công. Mỗi lần flush thực sự được gọi
+    /// tăng `flushes` thêm 1. Chạy đồng bộ.
+    pub fn flush(&self, db: &sled::Db, min_interval: Duration, flushes: &AtomicU64) -> sled::Result<()> {
+        // Đặt lại trước khi flush: chunk ghi trong lúc flush vẫn được đếm dù có thể đã nằm
+        // trong lần flush này
+        self.unflushed.store(0, Ordering::Relaxed);
+        if min_interval.is_zero() {
+            return self.flush_now(db, flushes);
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        let wanted = inner.started + 1;
+        loop {
+            if inner.finished >= wanted {
+                // Một lần flush sau lần cần chờ cũng bao gồm dữ liệu của lời gọi này
+                if inner.succeeded >= wanted {
+                    return Ok(());
+                }
+                return Err(sled::Error::Io(std::io::Error::other("lần flush dùng chung thất bại")));
+            }
+            if !inner.leader {
+                break;
+            }
+            inner = self.done.wait(inner).unwrap();
+        }
+
+        // Leader: chờ tới khi đủ khoảng cách với lần flush trước; các lần ghi tới trong
+        // lúc này vẫn chờ lần flush `wanted`
+        inner.leader = true;
+        let wait = inner
+            .last_started
+            .map_or(Duration::ZERO, |started| (started + min_interval).saturating_duration_since(Instant::now()));
+        drop(inner);
+        std::thread::sleep(wait);
+
+        let mut inner = self.inner.lock().unwrap();
+        inner.started += 1;
+        inner.last_started = Some(Instant::now());
+        drop(inner);
+
+        let result = self.flush_now(db, flushes);
+
+        let mut inner = self.inner.lock().unwrap();
+        inner.finished = wanted;
+        if result.is_ok() {
+            inner.succeeded = wanted;
+        }
+        inner.leader = false;
+        self.done.notify_all();
+        result
+    }
+}
+
+/// Task nền của chế độ `interval`: flush mỗi STORAGE_DURABILITY_INTERVAL_MS khi có chunk đã
+/// xác nhận mà chưa xuống đĩa. Không làm gì ở các chế độ khác.
+pub async fn run(state: Arc<AppState>) {
+    loop {
+        let interval = state.config.load().durability_interval_ms;
+        tokio::time::sleep(Duration::from_millis(interval)).await;
+        let (acknowledged, durable) = state.flusher.progress();
+        if state.config.load().durability != Durability::Interval || acknowledged == durable {
+            continue;
+        }
+
+        let flush_state = state.clone();
+        match tokio::task::spawn_blocking(move || flush_state.flush()).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => tracing::error!("Lỗi khi flush database theo chu kỳ: {}", e),
+            Err(e) => tracing::error!("Lỗi khi flush database theo chu kỳ: {}", e),
+        }
+    }
+}
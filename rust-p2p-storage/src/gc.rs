@@ -0,0 +1,198 @@
+// ## DỌN CHUNK MỒ CÔI (GC) ##
+//
+// Chunk mồ côi là chunk của một file có manifest (module manifest) nhưng chunkHash không có
+// trong manifest đó, ví dụ chunk của lần upload cũ mà manifest mới không còn nhắc tới. Với
+// `?unmanifested=true` (STORAGE_GC_UNMANIFESTED cho task nền), chunk của fileKey không có
+// manifest nào cũng bị coi là mồ côi; chỉ bật khi mọi file đều được ghi kèm manifest.
+//
+// Không bao giờ bị coi là mồ côi: chunk của file đã seal, chunk của fileKey đang có alias
+// trỏ tới, chunk của fileKey nhị phân (không thể có manifest), chunk có value hỏng (để
+// `/file/:fileKey/reconstruct` xử lý) và chunk được lưu chưa quá STORAGE_GC_GRACE_SECS giây
+// (mặc định một ngày), vì manifest có thể được gửi sau khi upload chunk.
+//
+// `POST /admin/gc` quét mọi shard rồi trả về số chunk đã quét, số chunk mồ côi, số file có
+// chunk mồ côi, số byte thu hồi được (record cùng value trong `chunks_large`) và vài key ví
+// dụ, mà không xóa gì. Với `?apply=true`, chunk mồ côi bị xóa cùng dữ liệu đi kèm như
+// `DELETE /chunk/...` (value lớn, entry index thứ tự, quota, số chunk của file) và response
+// có thêm số chunk đã xóa. Chunk được ghi lại trong lúc quét thì không bị xóa.
+//
+// Task nền chạy mỗi STORAGE_GC_INTERVAL_SECS giây (mặc định một ngày) theo
+// STORAGE_GC_SCHEDULE: `off` (mặc định) không chạy, `report` chỉ ghi báo cáo vào log, `apply`
+// xóa luôn. Task không chạy khi node đang ở chế độ chỉ đọc.
+
+use serde::Serialize;
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::{key, manifest, record, seal, AppState};
+
+// Số key ví dụ tối đa trong báo cáo
+const MAX_EXAMPLES: usize = 10;
+
+/// Chế độ của task nền (STORAGE_GC_SCHEDULE)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schedule {
+    Off,
+    Report,
+    Apply,
+}
+
+impl FromStr for Schedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "off" => Ok(Schedule::Off),
+            "report" => Ok(Schedule::Report),
+            "apply" => Ok(Schedule::Apply),
+            other => Err(format!("chế độ GC không hỗ trợ: {}", other)),
+        }
+    }
+}
+
+/// Cách chạy một lượt dọn
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Options {
+    // Xóa chunk mồ côi thay vì chỉ báo cáo
+    pub apply: bool,
+    // Coi chunk của fileKey không có manifest là mồ côi
+    pub unmanifested: bool,
+}
+
+/// Kết quả của một lượt dọn
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+    pub applied: bool,
+    #[serde(rename = "scannedChunks")]
+    pub scanned_chunks: u64,
+    #[serde(rename = "orphanedChunks")]
+    pub orphaned_chunks: u64,
+    #[serde(rename = "orphanedFiles")]
+    pub orphaned_files: u64,
+    #[serde(rename = "reclaimableBytes")]
+    pub reclaimable_bytes: u64,
+    // Số chunk đã xóa, chỉ khác `orphanedChunks` khi có chunk được ghi lại trong lúc quét
+    #[serde(rename = "deletedChunks")]
+    pub deleted_chunks: u64,
+    pub examples: Vec<String>,
+}
+
+// Cách xử lý chunk của file đang quét
+enum FilePolicy {
+    // Giữ mọi chunk của file
+    Keep,
+    // Mọi chunk là mồ côi
+    Orphaned,
+    // Chunk không có trong manifest là mồ côi
+    Listed(HashSet<String>),
+}
+
+fn policy_for(state: &AppState, file_key: &str, aliased: &HashSet<Vec<u8>>, options: Options) -> sled::Result<FilePolicy> {
+    if seal::is_sealed(&state.sealed, file_key)? || aliased.contains(file_key.as_bytes()) {
+        return Ok(FilePolicy::Keep);
+    }
+    Ok(match manifest::get(&state.manifests, file_key)? {
+        Some(manifest) => FilePolicy::Listed(manifest.chunks.into_iter().collect()),
+        None if options.unmanifested => FilePolicy::Orphaned,
+        None => FilePolicy::Keep,
+    })
+}
+
+/// Quét mọi shard tìm chunk mồ côi tại thời điểm `now` (unix giây), xóa chúng nếu
+/// `options.apply`. Chạy đồng bộ.
+pub fn collect(state: &AppState, options: Options, now: u64) -> sled::Result<Report> {
+    let grace = state.config.load().gc_grace_secs;
+    let mut aliased = HashSet::new();
+    for target in state.aliases.iter().values() {
+        aliased.insert(target?.to_vec());
+    }
+    let mut report = Report {
+        applied: options.apply,
+        ..Report::default()
+    };
+    // File đang quét cùng cách xử lý; chunk của một file nằm liền nhau trong shard
+    let mut current: Option<(String, FilePolicy, bool)> = None;
+
+    for tree in &state.shards {
+        for item in tree.iter() {
+            let (key_bytes, value) = item?;
+            let Some(parsed) = key::decode_raw(&key_bytes) else {
+                continue;
+            };
+            report.scanned_chunks += 1;
+            let Ok(file_key) = std::str::from_utf8(parsed.0) else {
+                continue;
+            };
+            if current.as_ref().is_none_or(|(current_key, _, _)| current_key != file_key) {
+                current = Some((file_key.to_string(), policy_for(state, file_key, &aliased, options)?, false));
+            }
+            let (_, policy, counted) = current.as_mut().expect("vừa gán ở trên");
+            let orphaned = match policy {
+                FilePolicy::Keep => false,
+                FilePolicy::Orphaned => true,
+                FilePolicy::Listed(chunks) => !chunks.contains(parsed.1),
+            };
+            if !orphaned {
+                continue;
+            }
+            let Some(record) = record::decode(&value) else {
+                continue;
+            };
+            if record.stored_at.is_some_and(|stored_at| stored_at.saturating_add(grace) > now) {
+                continue;
+            }
+
+            let large = if record.large { state.large.get(&key_bytes)?.map_or(0, |v| v.len()) } else { 0 };
+            report.orphaned_chunks += 1;
+            report.reclaimable_bytes += (value.len() + large) as u64;
+            if !*counted {
+                *counted = true;
+                report.orphaned_files += 1;
+            }
+            if report.examples.len() < MAX_EXAMPLES {
+                report.examples.push(String::from_utf8_lossy(&key_bytes).into_owned());
+            }
+            if options.apply && crate::ttl::remove_if_unchanged(state, tree, &key_bytes, &value, record.large, record.index)? {
+                report.deleted_chunks += 1;
+            }
+        }
+    }
+    if report.deleted_chunks > 0 {
+        state.flush()?;
+    }
+    state.notifier.gc_run(&report);
+    Ok(report)
+}
+
+/// Task nền dọn chunk mồ côi theo chu kỳ STORAGE_GC_INTERVAL_SECS và chế độ
+/// STORAGE_GC_SCHEDULE
+pub async fn run(state: Arc<AppState>) {
+    loop {
+        let interval = state.config.load().gc_interval_secs;
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+        let config = state.config.load();
+        if config.gc_schedule == Schedule::Off || state.is_read_only() {
+            continue;
+        }
+
+        let options = Options {
+            apply: config.gc_schedule == Schedule::Apply,
+            unmanifested: config.gc_unmanifested,
+        };
+        let gc_state = state.clone();
+        match tokio::task::spawn_blocking(move || collect(&gc_state, options, crate::unix_now())).await {
+            Ok(Ok(report)) if report.orphaned_chunks == 0 => {}
+            Ok(Ok(report)) => tracing::info!(
+                "-> GC: {} chunk mồ côi ({} byte) trong {} file, đã xóa {}",
+                report.orphaned_chunks,
+                report.reclaimable_bytes,
+                report.orphaned_files,
+                report.deleted_chunks
+            ),
+            Ok(Err(e)) => tracing::error!("Lỗi khi dọn chunk mồ côi: {}", e),
+            Err(e) => tracing::error!("Lỗi khi dọn chunk mồ côi: {}", e),
+        }
+    }
+}
@@ -0,0 +1,261 @@
+// ## DANH SÁCH PEER VÀ GOSSIP ##
+//
+// Khi bật STORAGE_GOSSIP, mỗi node giữ trong bộ nhớ danh sách các peer cùng trạng thái
+// sống/chết. Định kỳ (STORAGE_GOSSIP_INTERVAL_MS) node chọn tối đa STORAGE_GOSSIP_FANOUT
+// peer đến hạn, gửi `POST /gossip` kèm danh sách peer của mình và nhận lại danh sách của
+// peer đó. Peer mới nghe được qua gossip chỉ là "chưa kiểm chứng" cho tới khi chính node
+// này gọi thành công tới nó, nên một node báo sai không làm các node khác tin theo.
+//
+// Gọi lỗi liên tiếp `UNREACHABLE_AFTER` lần thì peer bị đánh dấu không liên lạc được và
+// được thử lại thưa dần (khoảng cách nhân đôi sau mỗi lần lỗi, tối đa `MAX_BACKOFF_SHIFT`
+// lần). Peer không phải seed (STORAGE_PEERS) bị quên sau `FORGET_AFTER` lần lỗi liên tiếp.
+// Danh sách bị giới hạn ở STORAGE_MAX_PEERS để cụm nhỏ không bị gossip làm ngập.
+// Nên đặt STORAGE_NODE_URL để peer biết địa chỉ của node và node không tự thêm chính
+// mình vào danh sách khi nghe lại địa chỉ đó từ peer khác.
+//
+// `POST /admin/reload` đổi được STORAGE_PEERS và STORAGE_MAX_PEERS mà không cần khởi động
+// lại (xem `Membership::reseed`): seed mới được thêm và phải tự kiểm chứng như lúc khởi động,
+// seed bị bỏ khỏi STORAGE_PEERS bị xóa khỏi danh sách. Bật/tắt STORAGE_GOSSIP và đổi
+// STORAGE_NODE_URL vẫn cần khởi động lại.
+//
+// `alive_peers` là danh sách peer đang sống mà các tính năng như read-repair dùng để
+// chọn node; replication gửi tới mọi peer đang biết (`peer_urls`, xem module replication).
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::{unix_now, AppState};
+
+// Số lần gọi lỗi liên tiếp trước khi coi peer là không liên lạc được
+const UNREACHABLE_AFTER: u32 = 3;
+// Số lần gọi lỗi liên tiếp trước khi quên peer học được qua gossip
+const FORGET_AFTER: u32 = 10;
+// Khoảng thử lại tối đa là chu kỳ gossip nhân 2^MAX_BACKOFF_SHIFT
+const MAX_BACKOFF_SHIFT: u32 = 6;
+
+/// Một peer trong thông điệp gossip
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PeerInfo {
+    pub url: String,
+    pub alive: bool,
+    // Lần cuối node gửi thông điệp này gọi thành công tới peer (unix giây)
+    #[serde(rename = "lastSeen", default, skip_serializing_if = "Option::is_none")]
+    pub last_seen: Option<u64>,
+}
+
+/// Body của `POST /gossip` và response của nó
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GossipMessage {
+    // URL mà node gửi tự công bố (STORAGE_NODE_URL), nếu có
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    #[serde(default)]
+    pub peers: Vec<PeerInfo>,
+}
+
+struct PeerState {
+    seed: bool,
+    alive: bool,
+    failures: u32,
+    last_seen: Option<u64>,
+    next_probe: Instant,
+}
+
+impl PeerState {
+    fn new(seed: bool) -> Self {
+        PeerState {
+            seed,
+            alive: false,
+            failures: 0,
+            last_seen: None,
+            next_probe: Instant::now(),
+        }
+    }
+}
+
+pub struct Membership {
+    node_url: Option<String>,
+    // STORAGE_MAX_PEERS, đổi được khi đọc lại cấu hình
+    max_peers: AtomicUsize,
+    peers: Mutex<HashMap<String, PeerState>>,
+}
+
+impl Membership {
+    pub fn new(seeds: &[String], node_url: Option<String>, max_peers: usize) -> Self {
+        let membership = Membership {
+            node_url,
+            max_peers: AtomicUsize::new(max_peers),
+            peers: Mutex::new(HashMap::new()),
+        };
+        membership.reseed(seeds, max_peers);
+        membership
+    }
+
+    /// Áp dụng STORAGE_PEERS và STORAGE_MAX_PEERS mới: thêm seed chưa biết, đánh dấu peer
+    /// đã học qua gossip là seed nếu nó có trong danh sách mới, và xóa seed cũ không còn
+    /// trong danh sách. Peer học qua gossip khác được giữ nguyên.
+    pub fn reseed(&self, seeds: &[String], max_peers: usize) {
+        self.max_peers.store(max_peers, Ordering::Relaxed);
+        let seeds: Vec<String> = seeds.iter().map(|seed| normalize(seed)).filter(|url| !self.is_self(url)).collect();
+        let mut peers = self.peers.lock().unwrap();
+        peers.retain(|url, peer| {
+            let keep = !peer.seed || seeds.contains(url);
+            if !keep {
+                tracing::info!("-> Bỏ seed {} khỏi danh sách peer", url);
+            }
+            keep
+        });
+        for url in seeds {
+            peers.entry(url).or_insert_with(|| PeerState::new(true)).seed = true;
+        }
+    }
+
+    fn is_self(&self, url: &str) -> bool {
+        self.node_url.as_deref().map(normalize).as_deref() == Some(url)
+    }
+
+    /// URL các peer đang sống, sắp xếp để thứ tự ổn định
+    pub fn alive_peers(&self) -> Vec<String> {
+        let peers = self.peers.lock().unwrap();
+        let mut alive: Vec<String> = peers.iter().filter(|(_, p)| p.alive).map(|(url, _)| url.clone()).collect();
+        alive.sort();
+        alive
+    }
+
+    /// URL mọi peer đang biết, sống hay không, sắp xếp để thứ tự ổn định
+    pub fn peer_urls(&self) -> Vec<String> {
+        let mut urls: Vec<String> = self.peers.lock().unwrap().keys().cloned().collect();
+        urls.sort();
+        urls
+    }
+
+    /// Thông điệp gossip mô tả những gì node này biết
+    pub fn message(&self) -> GossipMessage {
+        let peers = self.peers.lock().unwrap();
+        let mut list: Vec<PeerInfo> = peers
+            .iter()
+            .map(|(url, p)| PeerInfo {
+                url: url.clone(),
+                alive: p.alive,
+                last_seen: p.last_seen,
+            })
+            .collect();
+        list.sort_by(|a, b| a.url.cmp(&b.url));
+        GossipMessage {
+            from: self.node_url.clone(),
+            peers: list,
+        }
+    }
+
+    /// Thêm các peer chưa biết trong `message` (trong giới hạn STORAGE_MAX_PEERS). Trạng
+    /// thái sống/chết do peer khác báo không được tin: peer mới luôn phải được tự kiểm chứng.
+    pub fn merge(&self, message: &GossipMessage) {
+        let mut peers = self.peers.lock().unwrap();
+        let urls = message.from.iter().chain(message.peers.iter().map(|p| &p.url));
+        for url in urls.map(|u| normalize(u)) {
+            if peers.len() >= self.max_peers.load(Ordering::Relaxed) {
+                break;
+            }
+            if !self.is_self(&url) && is_http_url(&url) && !peers.contains_key(&url) {
+                tracing::info!("-> Biết thêm peer {}", url);
+                peers.insert(url, PeerState::new(false));
+            }
+        }
+    }
+
+    // Tối đa `fanout` peer đã đến hạn gọi, ưu tiên peer chờ lâu nhất
+    fn due(&self, fanout: usize) -> Vec<String> {
+        let now = Instant::now();
+        let peers = self.peers.lock().unwrap();
+        let mut due: Vec<(&String, Instant)> =
+            peers.iter().filter(|(_, p)| p.next_probe <= now).map(|(url, p)| (url, p.next_probe)).collect();
+        due.sort_by_key(|(_, at)| *at);
+        due.into_iter().take(fanout).map(|(url, _)| url.clone()).collect()
+    }
+
+    // Ghi nhận kết quả của một lần gọi tới `url`
+    fn record(&self, url: &str, ok: bool, interval: Duration) {
+        let mut peers = self.peers.lock().unwrap();
+        let Some(peer) = peers.get_mut(url) else {
+            return;
+        };
+        if ok {
+            if !peer.alive {
+                tracing::info!("-> Peer {} đang hoạt động", url);
+            }
+            peer.alive = true;
+            peer.failures = 0;
+            peer.last_seen = Some(unix_now());
+            peer.next_probe = Instant::now() + interval;
+            return;
+        }
+
+        peer.failures += 1;
+        if peer.alive && peer.failures >= UNREACHABLE_AFTER {
+            tracing::warn!("Peer {} không liên lạc được sau {} lần thử", url, peer.failures);
+            peer.alive = false;
+        }
+        if !peer.seed && peer.failures >= FORGET_AFTER {
+            tracing::warn!("Bỏ peer {} khỏi danh sách", url);
+            peers.remove(url);
+            return;
+        }
+        let shift = peer.failures.saturating_sub(UNREACHABLE_AFTER - 1).min(MAX_BACKOFF_SHIFT);
+        peer.next_probe = Instant::now() + interval * (1 << shift);
+    }
+}
+
+// Bỏ dấu '/' thừa ở cuối để cùng một node không bị tính hai lần
+fn normalize(url: &str) -> String {
+    url.trim().trim_end_matches('/').to_string()
+}
+
+fn is_http_url(url: &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://")
+}
+
+/// Task nền trao đổi danh sách peer định kỳ; kết thúc ngay nếu không bật gossip
+pub async fn run(state: Arc<AppState>) {
+    let Some(membership) = state.membership.as_ref() else {
+        return;
+    };
+    let client = reqwest::Client::new();
+    loop {
+        let config = state.config.load();
+        let interval = Duration::from_millis(config.gossip_interval_ms);
+        let fanout = config.gossip_fanout;
+        let peer_token = config.peer_token.clone();
+        drop(config);
+
+        // Gọi song song các peer đến hạn (số lượng đã bị giới hạn bởi fanout)
+        let message = membership.message();
+        let mut calls = tokio::task::JoinSet::new();
+        for url in membership.due(fanout) {
+            let mut request = client
+                .post(format!("{}/gossip", url))
+                .timeout(interval.clamp(Duration::from_millis(200), Duration::from_secs(2)))
+                .json(&message);
+            if let Some(token) = peer_token.as_deref() {
+                request = request.bearer_auth(token);
+            }
+            calls.spawn(async move {
+                let reply = match request.send().await {
+                    Ok(response) if response.status().is_success() => response.json::<GossipMessage>().await.ok(),
+                    _ => None,
+                };
+                (url, reply)
+            });
+        }
+        while let Some(Ok((url, reply))) = calls.join_next().await {
+            membership.record(&url, reply.is_some(), interval);
+            if let Some(reply) = reply {
+                membership.merge(&reply);
+            }
+        }
+
+        tokio::time::sleep(interval).await;
+    }
+}
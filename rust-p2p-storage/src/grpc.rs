@@ -0,0 +1,268 @@
+// ## gRPC SERVICE ##
+//
+// Giao diện gRPC chạy song song với HTTP API trên cổng riêng (STORAGE_GRPC_ADDR), dùng chung
+// database, quota, giới hạn dung lượng và giới hạn đọc với HTTP server. Dữ liệu chunk truyền
+// dạng bytes nên không tốn chi phí Base64 trên đường truyền.
+//
+// - `StoreChunk`: lưu một chunk, như `POST /store`.
+// - `StoreChunkStream`: client gửi liên tiếp nhiều chunk trên một stream; chunk được ghi theo
+//   lô `INGEST_BATCH` (mỗi lô một transaction và một lần flush) và response cho biết số chunk
+//   đã lưu. Một chunk lỗi làm hỏng cả stream nhưng các lô trước đó vẫn được giữ.
+// - `GetChunk`: lấy một chunk, `NOT_FOUND` nếu không có.
+// - `ListFileChunks`: stream mọi chunk của một file, cùng thứ tự với `GET /file/:fileKey`.
+//
+// `Store` và `RetrieveFile` là tên cũ của `StoreChunk` và `ListFileChunks`, giữ lại cho các
+// client đã dùng. Lỗi được đổi sang mã gRPC tương ứng với mã HTTP (xem `status_of`).
+
+// `tonic::Status` khá lớn nhưng là kiểu lỗi bắt buộc của tonic
+#![allow(clippy::result_large_err)]
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use std::pin::Pin;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio_stream::Stream;
+use tonic::{Request, Response, Status};
+
+pub mod proto {
+    tonic::include_proto!("storage");
+}
+
+use proto::storage_server::{Storage, StorageServer};
+use proto::{ChunkRequest, ChunkResponse, FileRequest, StoreRequest, StoreResponse, StoreStreamResponse};
+
+use crate::error::ApiError;
+use crate::AppState;
+
+pub struct StorageService {
+    state: Arc<AppState>,
+}
+
+/// Tạo gRPC service dùng chung state (database, giới hạn đọc) với HTTP server; từ đó
+/// `GET /version` báo `grpc: true`
+pub fn service(state: Arc<AppState>) -> StorageServer<StorageService> {
+    state.grpc_enabled.store(true, Ordering::Relaxed);
+    StorageServer::new(StorageService { state })
+}
+
+type ChunkStream = Pin<Box<dyn Stream<Item = Result<ChunkResponse, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl Storage for StorageService {
+    async fn store_chunk(&self, request: Request<StoreRequest>) -> Result<Response<StoreResponse>, Status> {
+        let started = Instant::now();
+        let result = self.store_one(request).await;
+        self.record("/storage.Storage/StoreChunk", &result, started);
+        result
+    }
+
+    async fn store_chunk_stream(
+        &self,
+        request: Request<tonic::Streaming<StoreRequest>>,
+    ) -> Result<Response<StoreStreamResponse>, Status> {
+        let started = Instant::now();
+        let result = self.store_many(request).await;
+        self.record("/storage.Storage/StoreChunkStream", &result, started);
+        result
+    }
+
+    async fn get_chunk(&self, request: Request<ChunkRequest>) -> Result<Response<ChunkResponse>, Status> {
+        let started = Instant::now();
+        let result = self.read_one(request).await;
+        self.record("/storage.Storage/GetChunk", &result, started);
+        result
+    }
+
+    type ListFileChunksStream = ChunkStream;
+
+    async fn list_file_chunks(&self, request: Request<FileRequest>) -> Result<Response<ChunkStream>, Status> {
+        let started = Instant::now();
+        let result = self.stream_file(request).await;
+        self.record("/storage.Storage/ListFileChunks", &result, started);
+        result
+    }
+
+    async fn store(&self, request: Request<StoreRequest>) -> Result<Response<StoreResponse>, Status> {
+        let started = Instant::now();
+        let result = self.store_one(request).await;
+        self.record("/storage.Storage/Store", &result, started);
+        result
+    }
+
+    type RetrieveFileStream = ChunkStream;
+
+    async fn retrieve_file(&self, request: Request<FileRequest>) -> Result<Response<ChunkStream>, Status> {
+        let started = Instant::now();
+        let result = self.stream_file(request).await;
+        self.record("/storage.Storage/RetrieveFile", &result, started);
+        result
+    }
+}
+
+/// Mã gRPC tương ứng với lỗi của HTTP API
+fn status_of(error: ApiError) -> Status {
+    match error {
+        ApiError::BadRequest | ApiError::InvalidFields | ApiError::InvalidKey => Status::invalid_argument("fileKey hoặc chunkHash không hợp lệ"),
+        ApiError::NotFound | ApiError::ChunkNotFound => Status::not_found("không tìm thấy chunk"),
+        ApiError::Sealed => Status::permission_denied("file đã bị seal"),
+        ApiError::QuotaExceeded => Status::resource_exhausted("vượt quota của tenant"),
+        ApiError::StorageLimit => Status::resource_exhausted("node đã đạt giới hạn dung lượng lưu trữ"),
+        ApiError::DiskFull => Status::resource_exhausted("đĩa của node đã đầy"),
+        ApiError::PayloadTooLarge => Status::invalid_argument("chunk vượt STORAGE_MAX_CHUNK_SIZE"),
+        ApiError::UnsupportedMediaType => Status::invalid_argument("kiểu nội dung của chunk không được phép"),
+        ApiError::Conflict => Status::already_exists("key đã tồn tại hoặc chứa chunk được ghi bằng chế độ khác"),
+        ApiError::ReadOnly => Status::unavailable("node đang ở chế độ chỉ đọc"),
+        ApiError::Overloaded => Status::unavailable("quá nhiều truy vấn đọc đồng thời"),
+        ApiError::FileBusy => Status::resource_exhausted("file đang có quá nhiều request ghi đồng thời"),
+        e => Status::internal(format!("lỗi khi xử lý chunk ({})", e.status())),
+    }
+}
+
+impl StorageService {
+    // Đếm lời gọi trong metrics chung với request HTTP (xem module metrics)
+    fn record<T>(&self, method: &str, result: &Result<T, Status>, started: Instant) {
+        let code = match result {
+            Ok(_) => tonic::Code::Ok,
+            Err(status) => status.code(),
+        };
+        let code = format!("{:?}", code);
+        self.state.metrics.record_request("GRPC", method, &code, started.elapsed());
+    }
+
+    // Kiểm tra chung cho mọi lần ghi: node không ở chế độ chỉ đọc và file chưa bị seal
+    fn ensure_writable(&self, file_key: &str) -> Result<(), Status> {
+        if self.state.is_read_only() {
+            return Err(status_of(ApiError::ReadOnly));
+        }
+        self.state.ensure_unsealed(file_key).map_err(status_of)
+    }
+
+    // Kiểm tra key, kích thước và kiểu nội dung như `POST /store` (xem `ensure_storable`)
+    fn ensure_storable(&self, req: &StoreRequest) -> Result<(), Status> {
+        let len = req.chunk_data.len() as u64;
+        self.state
+            .ensure_storable(&req.file_key, &req.chunk_hash, len, None, req.chunk_index, &req.chunk_data)
+            .map_err(status_of)
+    }
+
+    async fn store_one(&self, request: Request<StoreRequest>) -> Result<Response<StoreResponse>, Status> {
+        let req = request.into_inner();
+        self.ensure_writable(&req.file_key)?;
+        self.ensure_storable(&req)?;
+        let _permit = self.state.acquire_file(&req.file_key).map_err(status_of)?;
+
+        // Database vẫn lưu Base64 để tương thích với các chunk được ghi qua HTTP
+        let db_value = crate::StoredChunkValue::raw(STANDARD.encode(&req.chunk_data), req.chunk_index);
+        crate::save_chunk(&self.state, &req.file_key, &req.chunk_hash, db_value, req.overwrite)
+            .await
+            .map_err(status_of)?;
+
+        Ok(Response::new(StoreResponse {}))
+    }
+
+    async fn store_many(
+        &self,
+        request: Request<tonic::Streaming<StoreRequest>>,
+    ) -> Result<Response<StoreStreamResponse>, Status> {
+        let mut messages = request.into_inner();
+        let mut batch = Vec::with_capacity(crate::sync::INGEST_BATCH);
+        // Suất ghi của các fileKey trong lô đang gom, trả lại sau khi lô được ghi
+        let mut permits = std::collections::HashMap::new();
+        let mut stored = 0u64;
+        loop {
+            let req = messages.message().await?;
+            if let Some(req) = &req {
+                self.ensure_writable(&req.file_key)?;
+                self.ensure_storable(req)?;
+                if !permits.contains_key(&req.file_key) {
+                    let permit = self.state.acquire_file(&req.file_key).map_err(status_of)?;
+                    permits.insert(req.file_key.clone(), permit);
+                }
+                let db_value = crate::StoredChunkValue::raw(STANDARD.encode(&req.chunk_data), req.chunk_index);
+                let mut write = self
+                    .state
+                    .pending_write(&req.file_key, &req.chunk_hash, db_value)
+                    .map_err(|status| status_of(status.into()))?;
+                write.set_overwrite(req.overwrite);
+                batch.push(write);
+            }
+            if batch.len() >= crate::sync::INGEST_BATCH || (req.is_none() && !batch.is_empty()) {
+                self.state.ensure_capacity(&batch).map_err(status_of)?;
+                let writes = std::mem::take(&mut batch);
+                stored += writes.len() as u64;
+                let state = self.state.clone();
+                crate::blocking(move || state.commit_writes(&writes))
+                    .await
+                    .map_err(|status| status_of(status.into()))?;
+                permits.clear();
+            }
+            if req.is_none() {
+                break;
+            }
+        }
+        tracing::info!("-> [gRPC] Đã lưu {} chunk từ stream", stored);
+
+        Ok(Response::new(StoreStreamResponse { stored }))
+    }
+
+    async fn read_one(&self, request: Request<ChunkRequest>) -> Result<Response<ChunkResponse>, Status> {
+        let req = request.into_inner();
+        if !crate::key::is_valid(&req.file_key, &req.chunk_hash) {
+            return Err(status_of(ApiError::BadRequest));
+        }
+        let _permit = self.state.acquire_read().map_err(|_| status_of(ApiError::Overloaded))?;
+
+        let state = self.state.clone();
+        let db_key = crate::key::encode(&req.file_key, &req.chunk_hash);
+        let read_key = db_key.clone();
+        let (data, record) = crate::blocking(move || {
+            state.read_chunk(read_key.as_bytes()).map_err(|e| {
+                tracing::error!("Lỗi khi đọc chunk {}: {}", read_key, e);
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR
+            })
+        })
+        .await
+        .map_err(|status| status_of(status.into()))?
+        .ok_or_else(|| status_of(ApiError::ChunkNotFound))?;
+        self.state.record_read(&req.file_key, 1);
+
+        Ok(Response::new(ChunkResponse {
+            key: db_key,
+            data: data.to_vec(),
+            index: record.index,
+            content_type: record.content_type,
+        }))
+    }
+
+    async fn stream_file(&self, request: Request<FileRequest>) -> Result<Response<ChunkStream>, Status> {
+        let file_key = request.into_inner().file_key;
+        tracing::info!("<- [gRPC] Đang truy vấn tất cả chunk cho fileKey: {}", file_key);
+
+        let chunks = {
+            let _permit = self
+                .state
+                .acquire_read()
+                .map_err(|_| status_of(ApiError::Overloaded))?;
+            crate::load_file_chunks(&self.state, &file_key, &[])
+                .await
+                .map_err(|status| Status::internal(format!("không thể đọc chunk ({})", status)))?
+        };
+        if !chunks.is_empty() {
+            self.state.record_read(&file_key, chunks.len());
+        }
+        let stream = tokio_stream::iter(chunks.into_iter().map(|chunk| {
+            let data = STANDARD
+                .decode(&chunk.value)
+                .map_err(|_| Status::data_loss(format!("chunk {} không phải Base64 hợp lệ", chunk.key)))?;
+            Ok(ChunkResponse {
+                key: chunk.key,
+                data,
+                index: chunk.index,
+                content_type: chunk.content_type,
+            })
+        }));
+
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
@@ -0,0 +1,95 @@
+// ## THỐNG KÊ FILE ĐƯỢC TRUY CẬP NHIỀU ##
+//
+// Mỗi lần đọc chunk của một file sẽ tăng bộ đếm trong tree `access_counts`.
+// Một task nền định kỳ chụp lại bộ đếm, tính số lượt truy cập trong cửa sổ vừa qua
+// và sắp xếp sẵn, nên endpoint `/stats/hot` chỉ tốn O(N) với N là số file trả về.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::AppState;
+
+// Số file tối đa giữ lại trong mỗi bản chụp
+const MAX_SNAPSHOT_FILES: usize = 1000;
+
+#[derive(Serialize, Clone)]
+pub struct HotFile {
+    #[serde(rename = "fileKey")]
+    pub file_key: String,
+    pub hits: u64,
+}
+
+#[derive(Serialize, Default)]
+pub struct HotSnapshot {
+    #[serde(rename = "windowSecs")]
+    pub window_secs: u64,
+    // Thời điểm chụp (unix giây); 0 nghĩa là chưa có bản chụp nào
+    #[serde(rename = "generatedAt")]
+    pub generated_at: u64,
+    pub files: Vec<HotFile>,
+}
+
+/// Tăng bộ đếm truy cập của một file
+pub fn record_access(counts: &sled::Tree, file_key: &str) {
+    let result = counts.update_and_fetch(file_key.as_bytes(), |old| {
+        let current = old.map_or(0, decode_count);
+        Some((current + 1).to_be_bytes().to_vec())
+    });
+    if let Err(e) = result {
+        tracing::error!("Lỗi khi cập nhật bộ đếm truy cập: {}", e);
+    }
+}
+
+/// Task nền: sau mỗi cửa sổ thời gian, tính số lượt truy cập trong cửa sổ đó
+/// (chênh lệch so với lần chụp trước) và thay bản chụp mới vào state.
+pub async fn run_snapshots(state: Arc<AppState>) {
+    let window_secs = state.config.load().hot_window_secs;
+    let mut previous: HashMap<String, u64> = HashMap::new();
+    let mut interval = tokio::time::interval(Duration::from_secs(window_secs));
+    interval.tick().await; // Tick đầu tiên trả về ngay
+
+    loop {
+        interval.tick().await;
+
+        let mut current = HashMap::new();
+        let mut files = Vec::new();
+        for (key, value) in state.access_counts.iter().flatten() {
+            let Ok(file_key) = String::from_utf8(key.to_vec()) else {
+                continue;
+            };
+            let total = decode_count(&value);
+            let hits = total.saturating_sub(previous.get(&file_key).copied().unwrap_or(0));
+            if hits > 0 {
+                files.push(HotFile {
+                    file_key: file_key.clone(),
+                    hits,
+                });
+            }
+            current.insert(file_key, total);
+        }
+        previous = current;
+
+        files.sort_by(|a, b| b.hits.cmp(&a.hits).then_with(|| a.file_key.cmp(&b.file_key)));
+        files.truncate(MAX_SNAPSHOT_FILES);
+
+        let snapshot = HotSnapshot {
+            window_secs,
+            generated_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            files,
+        };
+        *state.hot.write().unwrap() = Arc::new(snapshot);
+    }
+}
+
+/// Bản chụp đang dùng, chia sẻ qua `Arc` nên không phải copy
+pub fn current(hot: &RwLock<Arc<HotSnapshot>>) -> Arc<HotSnapshot> {
+    hot.read().unwrap().clone()
+}
+
+fn decode_count(bytes: &[u8]) -> u64 {
+    bytes.try_into().map_or(0, u64::from_be_bytes)
+}
@@ -0,0 +1,58 @@
+// ## KIỂM TRA HASH CỦA CHUNK ##
+//
+// Mặc định server tin chunkHash do client gửi. Khi đặt STORAGE_VERIFY_HASH (`sha256` hoặc
+// `keccak256`, khớp với cách Go uploader tính hash), `/store` tính hash của dữ liệu đã giải
+// Base64 và từ chối chunk có chunkHash khác hash đó với `422` (`checksum_mismatch`). So sánh
+// không phân biệt hoa thường, tiền tố `0x` là không bắt buộc.
+//
+// `GET /verify/:fileKey/:chunkHash` đọc lại dữ liệu đã lưu (sau khi giải nén, dựng lại
+// delta) và tính lại hash để phát hiện dữ liệu bị hỏng trên đĩa. Thuật toán lấy từ
+// `?algorithm=`, rồi STORAGE_VERIFY_HASH, mặc định `sha256`.
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use sha3::Keccak256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    Keccak256,
+}
+
+impl std::str::FromStr for HashAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "sha256" | "sha-256" => Ok(HashAlgorithm::Sha256),
+            "keccak256" | "keccak-256" | "keccak" => Ok(HashAlgorithm::Keccak256),
+            other => Err(format!("thuật toán hash không được hỗ trợ: {}", other)),
+        }
+    }
+}
+
+impl HashAlgorithm {
+    pub fn name(self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Keccak256 => "keccak256",
+        }
+    }
+
+    /// Hash của `data` dạng `0x` + hex chữ thường
+    pub fn digest(self, data: &[u8]) -> String {
+        let digest = match self {
+            HashAlgorithm::Sha256 => Sha256::digest(data).to_vec(),
+            HashAlgorithm::Keccak256 => Keccak256::digest(data).to_vec(),
+        };
+        format!("0x{}", hex::encode(digest))
+    }
+}
+
+/// chunkHash có khớp với hash `computed` (kết quả của `digest`) không
+pub fn matches(chunk_hash: &str, computed: &str) -> bool {
+    let chunk_hash = chunk_hash.strip_prefix("0x").or_else(|| chunk_hash.strip_prefix("0X")).unwrap_or(chunk_hash);
+    chunk_hash.eq_ignore_ascii_case(&computed[2..])
+}
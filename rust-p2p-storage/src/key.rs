@@ -0,0 +1,123 @@
+// ## KEY TỔNG HỢP "fileKey:chunkHash" ##
+//
+// Mọi chỗ cần ghép hoặc tách key trong database đều đi qua module này.
+// Quy ước: chunkHash không chứa ':' nên key luôn được tách ở dấu ':' CUỐI CÙNG;
+// fileKey thì có thể chứa ':' (dữ liệu cũ), khi đó prefix scan của fileKey ngắn hơn
+// có thể trả về cả key của fileKey dài hơn, nên bên quét phải so sánh lại fileKey.
+// Module không phụ thuộc crate nào khác để có thể dùng lại trong fuzz target.
+//
+// Các hàm `*_raw` làm việc với fileKey là byte tùy ý (fileKey nhị phân gửi qua
+// `/b64/...`, xem `b64_file_key`). Vì chunkHash luôn là UTF-8 không chứa ':', dấu ':'
+// cuối cùng của key vẫn là dấu phân tách kể cả khi fileKey nhị phân chứa byte ':', nên
+// quy tắc lọc lại kết quả prefix scan không đổi.
+
+/// Ký tự phân tách giữa fileKey và chunkHash
+pub const SEPARATOR: u8 = b':';
+
+/// Hai thành phần của một key đã được tách
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkKey<'a> {
+    pub file_key: &'a str,
+    pub chunk_hash: &'a str,
+}
+
+/// Ghép key tổng hợp `fileKey:chunkHash`
+pub fn encode(file_key: &str, chunk_hash: &str) -> String {
+    let mut key = String::with_capacity(file_key.len() + 1 + chunk_hash.len());
+    key.push_str(file_key);
+    key.push(SEPARATOR as char);
+    key.push_str(chunk_hash);
+    key
+}
+
+/// Prefix dùng để quét toàn bộ chunk của một file. Có dấu ':' ở cuối để không lấy
+/// nhầm fileKey khác có cùng tiền tố (ví dụ "0xab" và "0xabc").
+pub fn prefix(file_key: &str) -> String {
+    let mut prefix = String::with_capacity(file_key.len() + 1);
+    prefix.push_str(file_key);
+    prefix.push(SEPARATOR as char);
+    prefix
+}
+
+/// Tách key thành fileKey và chunkHash. Trả về `None` nếu key không phải UTF-8,
+/// không có dấu ':' hoặc có thành phần rỗng. Không bao giờ panic.
+pub fn decode(key: &[u8]) -> Option<ChunkKey<'_>> {
+    let (file_key, chunk_hash) = decode_raw(key)?;
+    let file_key = std::str::from_utf8(file_key).ok()?;
+    Some(ChunkKey { file_key, chunk_hash })
+}
+
+/// Trả về chunkHash nếu `key` thực sự thuộc về `file_key`. Dùng khi lọc kết quả
+/// prefix scan để loại các key của fileKey dài hơn có chứa ':'.
+pub fn chunk_hash_in<'a>(key: &'a [u8], file_key: &str) -> Option<&'a str> {
+    decode(key)
+        .filter(|parsed| parsed.file_key == file_key)
+        .map(|parsed| parsed.chunk_hash)
+}
+
+/// Một cặp fileKey/chunkHash có ghép và tách lại được đúng như ban đầu hay không
+pub fn is_valid(file_key: &str, chunk_hash: &str) -> bool {
+    !file_key.is_empty() && !chunk_hash.is_empty() && !chunk_hash.contains(SEPARATOR as char)
+}
+
+/// Lý do một fileKey hoặc chunkHash không được dùng cho lần ghi MỚI, `None` nếu hợp lệ.
+/// Chặt hơn `is_valid`: cả fileKey cũng không được chứa ':', không được dài quá
+/// `max_len` byte và không chứa ký tự điều khiển (xuống dòng, tab, NUL...). Key cũ
+/// không thỏa điều kiện này vẫn đọc, sửa và xóa được qua `is_valid`.
+pub fn component_error(part: &str, max_len: usize) -> Option<&'static str> {
+    if let Some(error) = raw_component_error(part.as_bytes(), max_len) {
+        Some(error)
+    } else if part.contains(SEPARATOR as char) {
+        Some("must not contain ':'")
+    } else if part.chars().any(char::is_control) {
+        Some("must not contain control characters")
+    } else {
+        None
+    }
+}
+
+/// Phần của `component_error` áp dụng cho fileKey dạng byte tùy ý: không rỗng và không dài
+/// quá `max_len` byte. FileKey nhị phân vẫn được chứa ':' và byte điều khiển vì key được
+/// tách ở dấu ':' cuối cùng (xem `decode_raw`).
+pub fn raw_component_error(part: &[u8], max_len: usize) -> Option<&'static str> {
+    if part.is_empty() {
+        Some("must not be empty")
+    } else if part.len() > max_len {
+        Some("exceeds the maximum key length")
+    } else {
+        None
+    }
+}
+
+/// Ghép key tổng hợp cho fileKey dạng byte tùy ý
+pub fn encode_raw(file_key: &[u8], chunk_hash: &str) -> Vec<u8> {
+    let mut key = prefix_raw(file_key);
+    key.extend_from_slice(chunk_hash.as_bytes());
+    key
+}
+
+/// Prefix quét chunk của fileKey dạng byte, có dấu ':' ở cuối như `prefix`
+pub fn prefix_raw(file_key: &[u8]) -> Vec<u8> {
+    let mut prefix = Vec::with_capacity(file_key.len() + 1);
+    prefix.extend_from_slice(file_key);
+    prefix.push(SEPARATOR);
+    prefix
+}
+
+/// Tách key ở dấu ':' cuối cùng mà không yêu cầu fileKey là UTF-8. Trả về `None` nếu
+/// không có dấu ':', có thành phần rỗng hoặc chunkHash không phải UTF-8.
+pub fn decode_raw(key: &[u8]) -> Option<(&[u8], &str)> {
+    let at = key.iter().rposition(|&b| b == SEPARATOR)?;
+    let (file_key, chunk_hash) = (&key[..at], &key[at + 1..]);
+    if file_key.is_empty() || chunk_hash.is_empty() {
+        return None;
+    }
+    Some((file_key, std::str::from_utf8(chunk_hash).ok()?))
+}
+
+/// Như `chunk_hash_in` cho fileKey dạng byte
+pub fn chunk_hash_in_raw<'a>(key: &'a [u8], file_key: &[u8]) -> Option<&'a str> {
+    decode_raw(key)
+        .filter(|(owner, _)| *owner == file_key)
+        .map(|(_, chunk_hash)| chunk_hash)
+}
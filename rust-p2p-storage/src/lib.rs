@@ -0,0 +1,6194 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{DefaultBodyLimit, Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{Html, IntoResponse, Response},
+    routing::{delete, get, post, put},
+    Json, Router,
+};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use arc_swap::ArcSwap;
+use sled::Transactional;
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tower_http::catch_panic::CatchPanicLayer;
+
+mod alias;
+mod auth;
+mod backup;
+pub mod bench;
+mod breaker;
+mod cardinality;
+mod challenge;
+pub mod cli;
+mod cipher;
+pub mod compress;
+mod config;
+mod conn;
+mod dedup;
+mod delta;
+mod disk;
+mod erasure;
+mod error;
+mod export;
+mod flush;
+mod gc;
+mod gossip;
+mod grpc;
+mod hot;
+mod integrity;
+pub mod key;
+pub mod logging;
+mod manifest;
+mod metrics;
+mod mime;
+pub mod migrate;
+mod namespace;
+mod notify;
+mod order;
+mod parity;
+mod quota;
+mod ratelimit;
+mod record;
+mod reindex;
+mod replication;
+mod scrub;
+mod seal;
+pub mod selftest;
+pub mod shutdown;
+mod store;
+mod sync;
+mod tier;
+mod timeout;
+mod tls;
+mod ttl;
+mod upload;
+mod usage;
+mod vfile;
+mod write_buffer;
+
+use config::ConfigVars;
+use error::ApiError;
+use metrics::Metrics;
+
+pub use backup::{create as create_snapshot, run as run_backups, Snapshot};
+pub use conn::{serve, serve_tls};
+pub use disk::run as run_disk_monitor;
+pub use flush::run as run_flusher;
+pub use gc::{collect as collect_garbage, run as run_gc, Options as GcOptions, Report as GcReport};
+pub use gossip::run as run_gossip;
+pub use grpc::{proto as grpc_proto, service as grpc_service};
+pub use hot::run_snapshots;
+pub use replication::run as run_replication;
+pub use scrub::{pass as scrub_pass, run as run_scrubber};
+pub use sync::run as run_full_sync;
+pub use ttl::{run as run_ttl_sweeper, sweep as sweep_expired};
+pub use upload::{run as run_upload_sweeper, sweep as sweep_uploads};
+pub use usage::{refresh as refresh_usage, run as run_usage_tracker, Usage};
+pub use write_buffer::run as run_write_buffer;
+
+// ## CÁC CẤU TRÚC DỮ LIỆU ##
+
+// Struct để nhận payload khi Go Listener gọi /store
+#[derive(Deserialize)]
+struct StorePayload {
+    #[serde(rename = "fileKey")]
+    file_key: String,
+    #[serde(rename = "chunkHash")]
+    chunk_hash: String,
+    #[serde(rename = "chunkData")]
+    chunk_data: String, // Dữ liệu chunk ở dạng Base64 (chuỗi rỗng là chunk 0 byte hợp lệ)
+    #[serde(rename = "chunkIndex", default)]
+    chunk_index: Option<u64>, // Vị trí của chunk trong file (không bắt buộc)
+    #[serde(rename = "contentType", default)]
+    content_type: Option<String>, // Kiểu MIME của dữ liệu chunk (không bắt buộc)
+    // Chunk cùng file mà chunk này gần giống, dùng để lưu dạng delta (không bắt buộc,
+    // chỉ có tác dụng khi bật STORAGE_DELTA_ENCODING, xem module delta)
+    #[serde(rename = "baseChunkHash", default)]
+    base_chunk_hash: Option<String>,
+    // Metadata tùy ý của client (ví dụ URL nguồn, tag), lưu cùng chunk và trả về khi đọc;
+    // tổng độ dài khóa và giá trị không vượt quá STORAGE_MAX_METADATA_BYTES
+    #[serde(default)]
+    metadata: std::collections::HashMap<String, String>,
+    // Thời gian sống của chunk tính từ lúc lưu (giây, không bắt buộc); hết hạn thì chunk
+    // không đọc được nữa và bị task TTL xóa (xem module ttl)
+    #[serde(rename = "ttlSeconds", default)]
+    ttl_seconds: Option<u64>,
+}
+
+// Struct để trả về khi Go Downloader gọi /file/:fileKey
+#[derive(Serialize)]
+struct FileChunksResponse {
+    #[serde(rename = "fileKey")]
+    file_key: String,
+    chunks: Vec<Chunk>,
+    // Giá trị `after` để lấy trang tiếp theo; không có khi đây là trang cuối
+    #[serde(rename = "nextCursor", skip_serializing_if = "Option::is_none")]
+    next_cursor: Option<String>,
+}
+
+// Bố cục của file trả về bởi /file/:fileKey/manifest, không kèm dữ liệu
+#[derive(Serialize)]
+struct ManifestResponse {
+    #[serde(rename = "fileKey")]
+    file_key: String,
+    chunks: Vec<ManifestChunk>,
+    #[serde(rename = "totalSize")]
+    total_size: u64,
+    // Tham số mã xóa của các chunk ghi mới (chỉ có khi bật STORAGE_ERASURE_DATA_SHARDS)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    erasure: Option<erasure::Params>,
+}
+
+// Manifest do client khai báo, trả về bởi /manifest/:fileKey
+#[derive(Serialize)]
+struct StoredManifestResponse {
+    #[serde(rename = "fileKey")]
+    file_key: String,
+    #[serde(flatten)]
+    manifest: manifest::Manifest,
+}
+
+// Một chunk trong manifest: dữ liệu gốc của nó chiếm `[offset, offset + byteLength)`
+#[derive(Serialize)]
+struct ManifestChunk {
+    index: Option<u64>,
+    #[serde(rename = "chunkHash")]
+    chunk_hash: String,
+    #[serde(rename = "byteLength")]
+    byte_length: u64,
+    offset: u64,
+}
+
+// Struct đại diện cho một chunk trong mảng trả về
+#[derive(Serialize, Clone)]
+struct Chunk {
+    key: String,   // Key tổng hợp, ví dụ: "0x...:0x..."
+    value: String, // Dữ liệu chunk ở dạng Base64 (hoặc hex "0x..." với ?encoding=hex)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    index: Option<u64>,
+    #[serde(rename = "storedAt", skip_serializing_if = "Option::is_none")]
+    stored_at: Option<u64>,
+    #[serde(rename = "expiresAt", skip_serializing_if = "Option::is_none")]
+    expires_at: Option<u64>,
+    // Encoding của `value`: "identity" hoặc codec nén nếu client chấp nhận (xem module compress)
+    encoding: compress::Codec,
+    #[serde(rename = "contentType", skip_serializing_if = "Option::is_none")]
+    content_type: Option<String>,
+    #[serde(skip_serializing_if = "std::collections::HashMap::is_empty")]
+    metadata: std::collections::HashMap<String, String>,
+    // chunkHash của base khi `value` vẫn là bản diff chưa được dựng lại (xem module delta)
+    #[serde(skip)]
+    delta_base: Option<String>,
+    // `value` vẫn nằm trong tree `chunks_large`, chưa được đọc (xem module tier)
+    #[serde(skip)]
+    large: bool,
+}
+
+impl Chunk {
+    /// Chunk trả về cho client từ record đọc được ở `db_key`
+    fn from_record(db_key: String, stored_value: StoredChunkValue) -> Self {
+        Chunk {
+            key: db_key,
+            value: stored_value.value,
+            index: stored_value.index,
+            stored_at: stored_value.stored_at,
+            expires_at: stored_value.expires_at,
+            encoding: stored_value.encoding,
+            content_type: stored_value.content_type,
+            metadata: stored_value.metadata,
+            delta_base: stored_value.delta_base,
+            large: stored_value.large,
+        }
+    }
+}
+
+// Struct để serialize/deserialize dữ liệu chunk trong database
+#[derive(Serialize, Deserialize)]
+struct StoredChunkValue {
+    // Bỏ qua khi rỗng để định dạng `bare` chỉ đưa metadata vào header (xem module record).
+    // `default` giữ cho chunk rỗng (chunkData "") đọc lại được thành value rỗng hợp lệ.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    value: String,
+    // Record cũ không có index nên mặc định là None
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    index: Option<u64>,
+    // Thời điểm lưu (unix giây); record cũ không có trường này
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stored_at: Option<u64>,
+    // Thời điểm hết hạn (unix giây) khi /store có `ttlSeconds`; record cũ không có trường này
+    #[serde(rename = "expiresAt", default, skip_serializing_if = "Option::is_none")]
+    expires_at: Option<u64>,
+    // Codec đã dùng để nén `value`; record cũ hoặc không nén là identity
+    #[serde(default, skip_serializing_if = "compress::Codec::is_identity")]
+    encoding: compress::Codec,
+    #[serde(rename = "contentType", default, skip_serializing_if = "Option::is_none")]
+    content_type: Option<String>,
+    // Khi có: `value` là bản diff so với chunk có chunkHash này trong cùng file
+    #[serde(rename = "deltaBase", default, skip_serializing_if = "Option::is_none")]
+    delta_base: Option<String>,
+    // Khi bật: `value` được lưu trong tree `chunks_large` với cùng key (xem module tier)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    large: bool,
+    // Độ dài dữ liệu gốc (sau khi giải Base64, trước khi nén) để dựng bảng offset của file
+    // mà không cần giải nén (xem module vfile); record cũ không có trường này
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    size: Option<u64>,
+    // Đường ghi đã tạo record (xem `record::StoreMode`); record cũ không có trường này
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mode: Option<record::StoreMode>,
+    // Metadata của client; record cũ không có trường này thì là map rỗng
+    #[serde(default, skip_serializing_if = "std::collections::HashMap::is_empty")]
+    metadata: std::collections::HashMap<String, String>,
+}
+
+impl StoredChunkValue {
+    // Tạo record mới với thời điểm lưu là hiện tại, ghi qua đường Base64
+    fn new(value: String, index: Option<u64>) -> Self {
+        StoredChunkValue {
+            value,
+            index,
+            stored_at: Some(unix_now()),
+            expires_at: None,
+            encoding: compress::Codec::Identity,
+            content_type: None,
+            delta_base: None,
+            large: false,
+            size: None,
+            mode: Some(record::StoreMode::Base64),
+            metadata: Default::default(),
+        }
+    }
+
+    // Record mới cho dữ liệu được gửi dạng byte thô (/store/raw, gRPC)
+    fn raw(value: String, index: Option<u64>) -> Self {
+        StoredChunkValue {
+            mode: Some(record::StoreMode::Raw),
+            ..StoredChunkValue::new(value, index)
+        }
+    }
+
+    /// Chunk đã hết hạn tại thời điểm `now` (unix giây) chưa; chunk hết hạn mà task TTL chưa
+    /// kịp xóa được coi như không tồn tại
+    fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+impl StorePayload {
+    // Dấu vân tay của dữ liệu và metadata, dùng để nhận ra chunk bị gửi trùng trong batch
+    fn digest(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(self.chunk_data.as_bytes());
+        hasher.update([0]);
+        hasher.update(format!("{:?}:{:?}", self.chunk_index, self.content_type).as_bytes());
+        let mut metadata: Vec<_> = self.metadata.iter().collect();
+        metadata.sort();
+        hasher.update(format!("{:?}", metadata).as_bytes());
+        hasher.finalize().into()
+    }
+
+    /// Kiểm tra mọi trường và trả về tất cả lỗi cùng lúc để client sửa một lần. Khi
+    /// payload hợp lệ thì không cấp phát gì: `errors` chỉ cấp phát khi có lỗi đầu tiên và
+    /// Base64 được kiểm tra bằng cách giải mã theo luồng vào `io::sink`.
+    fn validate(&self, limits: &Config) -> Result<(), Vec<FieldError>> {
+        let (max_chunk_size, max_metadata_bytes) = (limits.max_chunk_size, limits.max_metadata_bytes);
+        let mut errors = Vec::new();
+        let mut fail = |field: &'static str, message: String| errors.push(FieldError { field, message });
+        if let Some(message) = key::component_error(&self.file_key, limits.max_key_bytes) {
+            fail("fileKey", message.into());
+        }
+        if let Some(message) = key::component_error(&self.chunk_hash, limits.max_key_bytes) {
+            fail("chunkHash", message.into());
+        }
+        let mut reader = self.chunk_data.as_bytes();
+        let mut decoder = base64::read::DecoderReader::new(&mut reader, &STANDARD);
+        match std::io::copy(&mut decoder, &mut std::io::sink()) {
+            Err(_) => fail("chunkData", "must be valid base64".into()),
+            Ok(len) if len > max_chunk_size as u64 => {
+                fail("chunkData", format!("decoded size {} exceeds the limit of {} bytes", len, max_chunk_size));
+            }
+            Ok(_) => {}
+        }
+        if let Some(message) = self.base_chunk_hash.as_deref().and_then(|base| key::component_error(base, limits.max_key_bytes)) {
+            fail("baseChunkHash", message.into());
+        }
+        if self.metadata_bytes() > max_metadata_bytes {
+            fail("metadata", format!("keys and values exceed the limit of {} bytes", max_metadata_bytes));
+        }
+        if self.ttl_seconds == Some(0) {
+            fail("ttlSeconds", "must be greater than 0".into());
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Đưa `chunkData` về dạng Base64 chuẩn khi client gửi biến thể khác (xem
+    /// `normalize_base64`). Dữ liệu không hợp lệ ở mọi biến thể được giữ nguyên để
+    /// `validate` từ chối.
+    fn normalize_base64(&mut self) {
+        if let Some(canonical) = normalize_base64(&self.chunk_data) {
+            self.chunk_data = canonical;
+        }
+    }
+
+    // Tổng độ dài các khóa và giá trị metadata
+    fn metadata_bytes(&self) -> usize {
+        self.metadata.iter().map(|(k, v)| k.len() + v.len()).sum()
+    }
+
+    // `AppState::ensure_storable` cho payload đã qua `validate`
+    fn ensure_storable(&self, state: &AppState) -> Result<(), ApiError> {
+        let len = base64_decoded_len(&self.chunk_data);
+        state.ensure_storable(&self.file_key, &self.chunk_hash, len, self.content_type.as_deref(), self.chunk_index, &self.head())
+    }
+
+    // Vài byte đầu của dữ liệu chunk để nhận ra kiểu nội dung (xem module mime)
+    fn head(&self) -> Vec<u8> {
+        // Giải mã theo nhóm 4 ký tự Base64 nên không cần giải cả chunk
+        let len = (mime::SNIFF_LEN.div_ceil(3) * 4).min(self.chunk_data.len());
+        let len = if len < self.chunk_data.len() { len / 4 * 4 } else { len };
+        STANDARD.decode(&self.chunk_data[..len]).unwrap_or_default()
+    }
+
+    // Tách payload thành fileKey, chunkHash và record cần lưu. `baseChunkHash` được đặt
+    // vào `delta_base` như một gợi ý; `pending_write` quyết định có lưu dạng delta không.
+    fn into_parts(self) -> (String, String, StoredChunkValue) {
+        let db_value = StoredChunkValue {
+            content_type: self.content_type,
+            delta_base: self.base_chunk_hash,
+            metadata: self.metadata,
+            expires_at: self.ttl_seconds.map(|ttl| unix_now().saturating_add(ttl)),
+            ..StoredChunkValue::new(self.chunk_data, self.chunk_index)
+        };
+        (self.file_key, self.chunk_hash, db_value)
+    }
+}
+
+// Một trường không hợp lệ trong body, trả về trong mảng `errors` của lỗi `invalid_fields`
+#[derive(Debug, Serialize)]
+struct FieldError {
+    field: &'static str,
+    message: String,
+}
+
+// Chi tiết `{"errors": [...]}` của lỗi `invalid_fields`
+fn field_errors(errors: Vec<FieldError>) -> serde_json::Map<String, serde_json::Value> {
+    let mut details = serde_json::Map::new();
+    details.insert("errors".into(), serde_json::to_value(errors).unwrap_or_default());
+    details
+}
+
+// Query cho /file/:fileKey/probe, ví dụ: ?expected=10
+#[derive(Deserialize)]
+struct ProbeQuery {
+    expected: Option<u64>,
+}
+
+// Kết quả kiểm tra các chunk còn thiếu của một file
+#[derive(Serialize)]
+struct ProbeResponse {
+    #[serde(rename = "fileKey")]
+    file_key: String,
+    expected: u64,
+    present: usize,
+    missing: Vec<u64>,
+}
+
+// Payload cho /file/:fileKey/rename
+#[derive(Deserialize)]
+struct RenamePayload {
+    #[serde(rename = "newFileKey")]
+    new_file_key: String,
+}
+
+// Query cho /store và /store/batch, ví dụ: ?overwrite=true để thay chunk đã có ở key đó bất
+// kể STORAGE_OVERWRITE_MODE, kể cả chunk được ghi qua /store/raw hoặc gRPC (xem
+// `record::StoreMode`)
+#[derive(Deserialize)]
+struct StoreQuery {
+    #[serde(default)]
+    overwrite: bool,
+}
+
+// Query cho /file/:fileKey/rename, ví dụ: ?overwrite=true
+#[derive(Deserialize)]
+struct RenameQuery {
+    #[serde(default)]
+    overwrite: bool,
+}
+
+// Struct trả về sau khi đổi tên file
+#[derive(Serialize)]
+struct RenameResponse {
+    #[serde(rename = "fileKey")]
+    file_key: String,
+    #[serde(rename = "newFileKey")]
+    new_file_key: String,
+    moved: usize,
+}
+
+// Trạng thái seal của file, trả về bởi /file/:fileKey/seal và /file/:fileKey/unseal
+#[derive(Serialize)]
+struct SealResponse {
+    #[serde(rename = "fileKey")]
+    file_key: String,
+    sealed: bool,
+    // Số stripe parity vừa tính khi seal (chỉ có khi bật STORAGE_PARITY_SHARDS)
+    #[serde(rename = "parityStripes", skip_serializing_if = "Option::is_none")]
+    parity_stripes: Option<usize>,
+}
+
+// Kết quả của /file/:fileKey/reconstruct
+#[derive(Serialize)]
+struct ReconstructResponse {
+    #[serde(rename = "fileKey")]
+    file_key: String,
+    // chunkHash của các chunk đã được dựng lại và ghi lại
+    reconstructed: Vec<String>,
+    // chunkHash của các chunk bị mất mà stripe không đủ dữ liệu để dựng lại
+    unrecoverable: Vec<String>,
+}
+
+// Payload cho POST /alias
+#[derive(Deserialize)]
+struct AliasPayload {
+    alias: String,
+    #[serde(rename = "fileKey")]
+    file_key: String,
+}
+
+// Kết quả phân giải tên, trả về bởi /file/:fileKey/resolve và POST /alias
+#[derive(Serialize)]
+struct AliasResponse {
+    name: String,
+    #[serde(rename = "fileKey")]
+    file_key: String,
+    // `name` là alias (ngược lại là chính fileKey)
+    alias: bool,
+    // fileKey mà alias trỏ tới trước khi bị đổi (chỉ có ở POST /alias)
+    #[serde(rename = "previousFileKey", skip_serializing_if = "Option::is_none")]
+    previous_file_key: Option<String>,
+}
+
+// Struct trả về sau khi xóa file hoặc chunk: danh sách chunkHash đã bị xóa để client đối
+// chiếu và số chunk đã xóa
+#[derive(Serialize)]
+struct DeleteResponse {
+    #[serde(rename = "fileKey")]
+    file_key: String,
+    removed: Vec<String>,
+    count: usize,
+}
+
+impl DeleteResponse {
+    fn new(file_key: String, removed: Vec<String>) -> Self {
+        let count = removed.len();
+        DeleteResponse { file_key, removed, count }
+    }
+}
+
+// Payload cho /files/batch
+#[derive(Deserialize)]
+struct BatchFilesPayload {
+    #[serde(rename = "fileKeys")]
+    file_keys: Vec<String>,
+}
+
+// Kết quả của /files/batch: map fileKey -> danh sách chunk, cùng các fileKey không tồn tại
+#[derive(Serialize)]
+struct BatchFilesResponse {
+    files: std::collections::BTreeMap<String, Vec<Chunk>>,
+    #[serde(rename = "notFound")]
+    not_found: Vec<String>,
+}
+
+// Body của POST /file/:fileKey/chunks
+#[derive(Deserialize)]
+struct ChunkHashesPayload {
+    #[serde(rename = "chunkHashes")]
+    chunk_hashes: Vec<String>,
+}
+
+// Body của POST /chunks/exists
+#[derive(Deserialize)]
+struct ChunksExistPayload {
+    #[serde(rename = "fileKey")]
+    file_key: String,
+    #[serde(rename = "chunkHashes")]
+    chunk_hashes: Vec<String>,
+}
+
+// Kết quả của POST /chunks/exists: `present[i]` cho biết chunkHash thứ i có ở node này
+// không, `missing` là các chunkHash còn thiếu theo thứ tự được hỏi (không lặp lại)
+#[derive(Serialize)]
+struct ChunksExistResponse {
+    #[serde(rename = "fileKey")]
+    file_key: String,
+    present: Vec<bool>,
+    missing: Vec<String>,
+}
+
+// Kết quả của POST /file/:fileKey/chunks: các chunk tìm thấy theo thứ tự được yêu cầu,
+// cùng các chunkHash không có trong file
+#[derive(Serialize)]
+struct SelectedChunksResponse {
+    #[serde(rename = "fileKey")]
+    file_key: String,
+    chunks: Vec<Chunk>,
+    missing: Vec<String>,
+}
+
+// Query riêng của /store/batch, ví dụ: ?partial=true
+#[derive(Deserialize)]
+struct BatchQuery {
+    // Bỏ qua các chunk không hợp lệ thay vì từ chối cả batch
+    #[serde(default)]
+    partial: bool,
+}
+
+// Kết quả của /store/batch
+#[derive(Serialize)]
+struct BatchStoreResponse {
+    stored: usize,
+    // Kết quả của từng chunk theo thứ tự trong batch
+    items: Vec<BatchItemStatus>,
+}
+
+#[derive(Serialize)]
+struct BatchItemStatus {
+    #[serde(rename = "fileKey")]
+    file_key: String,
+    #[serde(rename = "chunkHash")]
+    chunk_hash: String,
+    // "stored", "duplicate" (bản trùng giống hệt một chunk trước đó trong batch) hoặc
+    // "rejected" (chỉ có với ?partial=true)
+    status: &'static str,
+    // Mã lỗi của chunk bị từ chối, như trường `code` của response lỗi
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'static str>,
+}
+
+// Query cho /store/raw, ví dụ: ?split=true&startIndex=0
+#[derive(Deserialize)]
+struct RawStoreQuery {
+    // Cho phép server tự chia blob lớn hơn giới hạn chunk thành nhiều chunk nhỏ
+    #[serde(default)]
+    split: bool,
+    #[serde(rename = "startIndex", default)]
+    start_index: u64,
+    // Index của chunk khi không chia nhỏ
+    index: Option<u64>,
+    // Thay chunk đã có bất kể STORAGE_OVERWRITE_MODE, kể cả chunk được ghi qua /store (xem
+    // `record::StoreMode`)
+    #[serde(default)]
+    overwrite: bool,
+    // Server tự tính chunkHash từ dữ liệu (xem `auto_chunk_hash`)
+    #[serde(default)]
+    autohash: bool,
+}
+
+// Query cho /store/stream, ví dụ: ?index=3
+#[derive(Deserialize)]
+struct StreamStoreQuery {
+    index: Option<u64>,
+    // Thay chunk đã có bất kể STORAGE_OVERWRITE_MODE, kể cả chunk được ghi qua /store (xem
+    // `record::StoreMode`)
+    #[serde(default)]
+    overwrite: bool,
+}
+
+// Kết quả của /store/stream
+#[derive(Serialize)]
+struct StreamStoreResponse {
+    #[serde(rename = "fileKey")]
+    file_key: String,
+    #[serde(rename = "chunkHash")]
+    chunk_hash: String,
+    // Số byte dữ liệu gốc đã lưu
+    bytes: u64,
+}
+
+// Kết quả của /store
+#[derive(Serialize)]
+struct StoreChunkResponse {
+    // Value trỏ tới blob cùng nội dung đã có sẵn thay vì được lưu thêm (STORAGE_DEDUP, xem
+    // module dedup)
+    deduplicated: bool,
+}
+
+// Kết quả của /store/raw
+#[derive(Serialize)]
+struct RawStoreResponse {
+    #[serde(rename = "fileKey")]
+    file_key: String,
+    #[serde(rename = "chunkHashes")]
+    chunk_hashes: Vec<String>,
+}
+
+// Payload cho PATCH /chunk/:fileKey/:chunkHash. Trường không có mặt thì giữ nguyên,
+// `null` thì xóa giá trị hiện tại.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ChunkPatch {
+    #[serde(default, deserialize_with = "patch_field")]
+    index: Option<Option<u64>>,
+    #[serde(rename = "contentType", default, deserialize_with = "patch_field")]
+    content_type: Option<Option<String>>,
+    #[serde(rename = "storedAt", default, deserialize_with = "patch_field")]
+    stored_at: Option<Option<u64>>,
+    // Dữ liệu và hash xác định địa chỉ nội dung nên không được sửa; có mặt thì trả về 400
+    #[serde(default, deserialize_with = "patch_field")]
+    value: Option<Option<serde_json::Value>>,
+    #[serde(rename = "chunkHash", default, deserialize_with = "patch_field")]
+    chunk_hash: Option<Option<serde_json::Value>>,
+}
+
+// Metadata của chunk sau khi PATCH
+#[derive(Serialize)]
+struct ChunkMetadata {
+    key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    index: Option<u64>,
+    #[serde(rename = "contentType", skip_serializing_if = "Option::is_none")]
+    content_type: Option<String>,
+    #[serde(rename = "storedAt", skip_serializing_if = "Option::is_none")]
+    stored_at: Option<u64>,
+}
+
+// Payload cho POST /chunk/:fileKey/:chunkHash/cas. `expected` là dữ liệu Base64 mà
+// client tin là đang được lưu, `null` nghĩa là chunk phải chưa tồn tại.
+#[derive(Deserialize)]
+struct CasPayload {
+    expected: Option<String>,
+    #[serde(rename = "new")]
+    new_value: String,
+    // Không có thì giữ index/contentType của chunk hiện tại
+    #[serde(rename = "chunkIndex")]
+    chunk_index: Option<u64>,
+    #[serde(rename = "contentType")]
+    content_type: Option<String>,
+}
+
+// Kết quả của một lần compare-and-swap
+enum CasOutcome {
+    Swapped(ChunkMetadata),
+    // Dữ liệu hiện tại (Base64) khác `expected`; `None` nếu chunk không tồn tại
+    Mismatch(Option<String>),
+}
+
+// Mức nhất quán của một lượt đọc
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Consistency {
+    // Đọc ngay những gì tree đang có (mặc định, nhanh nhất)
+    #[default]
+    Weak,
+    // Flush database trước khi đọc để chắc chắn thấy và đã lưu bền mọi lần ghi trước đó
+    Strong,
+}
+
+// Query dùng chung cho các route đọc, ví dụ: ?consistency=strong
+#[derive(Deserialize)]
+struct ReadQuery {
+    #[serde(default)]
+    consistency: Consistency,
+}
+
+// Query phân trang cho /file/:fileKey, ví dụ: ?limit=1000&after=0xab12
+#[derive(Deserialize)]
+struct PageQuery {
+    // Số chunk bỏ qua (sau `after` nếu có)
+    #[serde(default)]
+    offset: usize,
+    limit: Option<usize>,
+    // chunkHash của chunk cuối cùng ở trang trước (`nextCursor`)
+    after: Option<String>,
+}
+
+impl PageQuery {
+    fn is_paged(&self) -> bool {
+        self.offset > 0 || self.limit.is_some() || self.after.is_some()
+    }
+
+    /// Cắt `chunks` (theo thứ tự trả về của file) thành trang được yêu cầu; trả về cursor của
+    /// trang tiếp theo nếu còn chunk. `after` không còn trong file (ví dụ vừa bị xóa) thì
+    /// trang bắt đầu từ chunk đầu tiên có chunkHash lớn hơn, đúng với thứ tự key khi không
+    /// bật index thứ tự; khi bật thì trả về `400`. `key_of` trả về key tổng hợp của phần tử.
+    fn apply<T>(&self, chunks: &mut Vec<T>, ordered: bool, key_of: impl Fn(&T) -> &str) -> Result<Option<String>, ApiError> {
+        if self.limit == Some(0) {
+            return Err(ApiError::BadRequest);
+        }
+        let hash_of = |chunk: &T| key::decode(key_of(chunk).as_bytes()).map_or("", |k| k.chunk_hash).to_string();
+        let start = match &self.after {
+            None => 0,
+            Some(after) => match chunks.iter().position(|chunk| hash_of(chunk) == *after) {
+                Some(at) => at + 1,
+                None if !ordered => chunks.partition_point(|chunk| hash_of(chunk).as_str() <= after.as_str()),
+                None => {
+                    tracing::warn!("Cursor {} không còn trong file", after);
+                    return Err(ApiError::BadRequest);
+                }
+            },
+        };
+        let start = start.saturating_add(self.offset).min(chunks.len());
+        let end = self.limit.map_or(chunks.len(), |limit| start.saturating_add(limit).min(chunks.len()));
+        let next = (end < chunks.len() && end > start).then(|| hash_of(&chunks[end - 1]));
+        chunks.truncate(end);
+        chunks.drain(..start);
+        Ok(next)
+    }
+}
+
+// Query cho /vfile/:fileKey, ví dụ: ?offset=4096&length=1024
+#[derive(Deserialize)]
+struct VfileQuery {
+    #[serde(default)]
+    offset: u64,
+    // Không có thì đọc tới hết file
+    length: Option<u64>,
+    #[serde(default)]
+    consistency: Consistency,
+}
+
+// Cách mã hóa `value` trong response của /file/:fileKey
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum DataEncoding {
+    // Base64 như lúc lưu (mặc định)
+    #[default]
+    Base64,
+    // Hex có tiền tố "0x", giống quy ước của fileKey và chunkHash
+    Hex,
+}
+
+// Query cho /file/:fileKey, ví dụ: ?encoding=hex
+#[derive(Deserialize)]
+struct DataEncodingQuery {
+    #[serde(default)]
+    encoding: DataEncoding,
+}
+
+// Query cho /file/:fileKey/tail, ví dụ: ?n=5
+#[derive(Deserialize)]
+struct TailQuery {
+    n: Option<usize>,
+}
+
+// Query cho /stats/hot, ví dụ: ?n=10
+#[derive(Deserialize)]
+struct HotQuery {
+    n: Option<usize>,
+}
+
+// Query cho GET /files, ví dụ: ?prefix=0xab&limit=100&after=0xab12
+#[derive(Deserialize)]
+struct FilesQuery {
+    #[serde(default)]
+    prefix: String,
+    // Số fileKey mỗi trang, mặc định 100 và không quá STORAGE_MAX_LIST_FILES
+    limit: Option<usize>,
+    // fileKey cuối cùng ở trang trước (`nextCursor`)
+    after: Option<String>,
+}
+
+// Kết quả của GET /files
+#[derive(Serialize)]
+struct FilesResponse {
+    files: Vec<cardinality::FileEntry>,
+    #[serde(rename = "nextCursor", skip_serializing_if = "Option::is_none")]
+    next_cursor: Option<String>,
+}
+
+// Kết quả của /admin/reload
+#[derive(Serialize)]
+struct ReloadResponse {
+    reloaded: bool,
+    // Các thiết lập đã bị sửa nhưng cần khởi động lại mới áp dụng được
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    rejected: Vec<&'static str>,
+    // Các biến có giá trị sai định dạng (`NAME="giá trị"`); cấu hình cũ được giữ nguyên
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    invalid: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+}
+
+// Thông tin phiên bản và build của server, trả về tại /version
+#[derive(Serialize)]
+struct VersionResponse {
+    version: &'static str,
+    #[serde(rename = "gitSha")]
+    git_sha: &'static str,
+    #[serde(rename = "builtAt")]
+    built_at: String,
+    #[serde(rename = "sledVersion")]
+    sled_version: &'static str,
+    features: FeatureFlags,
+}
+
+// Các tính năng tùy chọn đang được bật trên node
+#[derive(Serialize)]
+struct FeatureFlags {
+    // Có gRPC server chạy cùng HTTP server hay không
+    grpc: bool,
+    // Codec nén value khi ghi (STORAGE_COMPRESSION), "identity" là không nén
+    compression: compress::Codec,
+    // Value có được mã hóa khi ghi không (STORAGE_ENCRYPTION_KEY)
+    encryption: bool,
+    // Lưu trữ trên S3 chưa được hỗ trợ nên luôn là false
+    s3: bool,
+    #[serde(rename = "htmlIndex")]
+    html_index: bool,
+    #[serde(rename = "orderIndex")]
+    order_index: bool,
+    shards: usize,
+}
+
+// Phần kích thước còn lại của một response JSON theo STORAGE_MAX_RESPONSE_BYTES
+struct ResponseBudget {
+    remaining: Option<usize>,
+}
+
+impl ResponseBudget {
+    fn new(limit: Option<usize>) -> Self {
+        ResponseBudget { remaining: limit }
+    }
+
+    /// Trừ kích thước của `chunks` (key + value Base64) vào phần còn lại; trả về `413`
+    /// khi vượt giới hạn để client chuyển sang các endpoint trả về từng phần
+    fn take(&mut self, chunks: &[Chunk]) -> Result<(), ApiError> {
+        let Some(remaining) = self.remaining.as_mut() else {
+            return Ok(());
+        };
+        for chunk in chunks {
+            let size = chunk.key.len() + chunk.value.len();
+            *remaining = remaining.checked_sub(size).ok_or(ApiError::ResponseTooLarge)?;
+        }
+        Ok(())
+    }
+}
+
+// Thời gian của từng bước khi đọc một file, gửi cho client qua header `Server-Timing`
+// khi bật STORAGE_SERVER_TIMING (trình duyệt hiển thị trong tab Network)
+#[derive(Default, Clone, Copy)]
+struct ReadTimings {
+    // Quét sled lấy record
+    scan: Duration,
+    // Dựng lại chunk delta
+    delta: Duration,
+    // Giải nén chunk mà client không tự giải được
+    decompress: Duration,
+    // Đổi Base64 sang hex (?encoding=hex)
+    encode: Duration,
+    // Serialize body JSON
+    serialize: Duration,
+}
+
+impl ReadTimings {
+    fn measure<T>(&mut self, stage: impl FnOnce(&mut Self) -> &mut Duration, f: impl FnOnce() -> T) -> T {
+        let started = Instant::now();
+        let out = f();
+        *stage(self) += started.elapsed();
+        out
+    }
+
+    // Giá trị header, ví dụ "scan;dur=1.204, delta;dur=0.000, ..."
+    fn header_value(&self) -> Option<HeaderValue> {
+        let stages = [
+            ("scan", self.scan),
+            ("delta", self.delta),
+            ("decompress", self.decompress),
+            ("encode", self.encode),
+            ("serialize", self.serialize),
+        ];
+        let value = stages
+            .iter()
+            .map(|(name, d)| format!("{};dur={:.3}", name, d.as_secs_f64() * 1000.0))
+            .collect::<Vec<_>>()
+            .join(", ");
+        HeaderValue::from_str(&value).ok()
+    }
+}
+
+// Kết quả nội bộ của `move_chunks`
+struct MoveResult {
+    moved: usize,
+}
+
+// Thông tin tóm tắt của một file, dùng cho trang index
+struct FileSummary {
+    file_key: String,
+    chunk_count: usize,
+}
+
+// Cấu hình server, đọc từ biến môi trường khi khởi động
+#[derive(Clone)]
+pub struct Config {
+    // Bật trang HTML liệt kê file tại `GET /` (STORAGE_HTML_INDEX=1)
+    html_index: bool,
+    // Địa chỉ lắng nghe của gRPC server (STORAGE_GRPC_ADDR)
+    grpc_addr: SocketAddr,
+    // Số lượt quét prefix (truy vấn file) được chạy đồng thời (STORAGE_MAX_CONCURRENT_READS)
+    max_concurrent_reads: usize,
+    // Số kết nối TCP được mở cùng lúc (STORAGE_MAX_CONNECTIONS), xem module conn
+    max_connections: usize,
+    // Thuật toán dùng để kiểm tra chunkHash của /store (STORAGE_VERIFY_HASH), không đặt
+    // thì không kiểm tra (xem module integrity)
+    verify_hash: Option<integrity::HashAlgorithm>,
+    // Thời gian xử lý tối đa của một request (STORAGE_REQUEST_TIMEOUT_MS), 0 là không giới
+    // hạn; STORAGE_ROUTE_TIMEOUTS ghi đè cho từng route (xem module timeout)
+    request_timeout_ms: u64,
+    route_timeouts: timeout::RouteTimeouts,
+    // Số request ghi đang chạy cùng lúc cho một fileKey (STORAGE_MAX_REQUESTS_PER_FILE),
+    // 0 là không giới hạn
+    max_requests_per_file: usize,
+    // Số request mỗi giây của cả node và của một client (STORAGE_RATE_LIMIT,
+    // STORAGE_CLIENT_RATE_LIMIT), số byte body mỗi giây của các route ghi chunk
+    // (STORAGE_STORE_BYTES_PER_SEC, STORAGE_CLIENT_STORE_BYTES_PER_SEC) và số lượt đọc cả
+    // file cùng lúc của một client (STORAGE_MAX_SCANS_PER_CLIENT, 0 là không giới hạn), xem
+    // module ratelimit
+    rate_limit: Option<u64>,
+    client_rate_limit: Option<u64>,
+    store_bytes_per_sec: Option<u64>,
+    client_store_bytes_per_sec: Option<u64>,
+    max_scans_per_client: usize,
+    // max-age (giây) cho header Cache-Control của dữ liệu chunk (STORAGE_CACHE_MAX_AGE).
+    // Không đặt thì không gửi header cache, giữ nguyên hành vi cũ.
+    cache_max_age: Option<u64>,
+    // Số fileKey tối đa trong một request /files/batch (STORAGE_MAX_BATCH_FILES)
+    max_batch_files: usize,
+    // Số chunkHash tối đa trong một request POST /file/:fileKey/chunks
+    // (STORAGE_MAX_CHUNK_HASHES)
+    max_chunk_hashes: usize,
+    // Số mẫu tối đa trong một request POST /challenge/sample
+    // (STORAGE_CHALLENGE_MAX_SAMPLES, xem module challenge)
+    challenge_max_samples: usize,
+    // Số fileKey tối đa trong một trang của GET /files (STORAGE_MAX_LIST_FILES)
+    max_list_files: usize,
+    // Số tree (shard) dùng để chia dữ liệu (STORAGE_SHARDS). Giá trị 1 dùng tree mặc định
+    // như trước đây. Đổi số shard trên database đã có dữ liệu cần migrate lại các key.
+    shards: usize,
+    // Kích thước tối đa (byte) của một chunk upload qua /store/raw (STORAGE_MAX_CHUNK_SIZE)
+    max_chunk_size: usize,
+    // Tổng độ dài tối đa (byte) các khóa và giá trị metadata của một chunk
+    // (STORAGE_MAX_METADATA_BYTES)
+    max_metadata_bytes: usize,
+    // Độ dài tối đa (byte) của fileKey và của chunkHash khi ghi chunk mới
+    // (STORAGE_MAX_KEY_BYTES)
+    max_key_bytes: usize,
+    // Kích thước mỗi phần khi tự động chia blob lớn (STORAGE_SPLIT_CHUNK_SIZE)
+    split_chunk_size: usize,
+    // Kích thước body tối đa của /store/raw khi bật chia nhỏ (STORAGE_MAX_RAW_UPLOAD)
+    max_raw_upload: usize,
+    // Độ dài cửa sổ thống kê file truy cập nhiều (STORAGE_HOT_WINDOW_SECS)
+    hot_window_secs: u64,
+    // File JSON cấu hình quota theo tenant (STORAGE_QUOTA_FILE)
+    quota_file: Option<String>,
+    // Số ký tự đầu của fileKey xác định tenant (STORAGE_QUOTA_OWNER_PREFIX_LEN)
+    quota_owner_prefix_len: usize,
+    // Kích thước body tối đa của /store/batch dạng mảng JSON (STORAGE_MAX_BATCH_BYTES)
+    max_batch_bytes: usize,
+    // Định dạng value khi ghi chunk mới: `json` (mặc định) hoặc `bare` (STORAGE_VALUE_FORMAT)
+    value_format: record::ValueFormat,
+    // Ghi thêm index phụ theo chunkIndex để đọc file đúng thứ tự mà không cần sắp xếp
+    // lại (STORAGE_ORDER_INDEX=1, xem module order)
+    order_index: bool,
+    // Ngôn ngữ mặc định của thông báo lỗi khi client không gửi Accept-Language
+    // (STORAGE_ERROR_LANG, `en` hoặc `vi`)
+    error_lang: error::Lang,
+    // Nén dữ liệu chunk khi lưu: `identity` (mặc định, không nén), `zstd` hoặc `lz4`
+    // (STORAGE_COMPRESSION), với mức nén zstd STORAGE_COMPRESSION_LEVEL; xem module compress
+    compression: compress::Codec,
+    compression_level: i32,
+    // API key cho các endpoint /admin (STORAGE_ADMIN_API_KEY). Không đặt (và không bật
+    // STORAGE_AUTH) thì tắt các endpoint này.
+    admin_api_key: Option<String>,
+    // Bắt mọi request phải có API token (STORAGE_AUTH=1, xem module auth)
+    auth: bool,
+    // Các token cố định cùng quyền của chúng (STORAGE_API_TOKENS)
+    api_tokens: auth::StaticTokens,
+    // Token gửi kèm request replication và gossip tới peer (STORAGE_PEER_TOKEN)
+    peer_token: Option<String>,
+    // Số chunk được đọc trước khi /stream-download đang gửi chunk hiện tại (STORAGE_STREAM_PREFETCH)
+    stream_prefetch: usize,
+    // Tổng kích thước dữ liệu chunk tối đa trong một response JSON (STORAGE_MAX_RESPONSE_BYTES).
+    // Không đặt thì không giới hạn.
+    max_response_bytes: Option<usize>,
+    // Đọc lại và so sánh mỗi chunk ngay sau khi ghi (STORAGE_VERIFY_WRITES=1). Tốn gấp
+    // đôi IO cho mỗi lần ghi nên chỉ nên bật trên phần cứng không đáng tin cậy.
+    verify_writes: bool,
+    // Cách xử lý khi /store ghi vào key đã có: `allow`, `reject` hoặc `verify` (mặc định)
+    // (STORAGE_OVERWRITE_MODE, xem `quota::OverwriteMode`); `?overwrite=true` luôn ghi đè
+    overwrite_mode: quota::OverwriteMode,
+    // Cảnh báo khi số fileKey khác nhau vượt ngưỡng này (STORAGE_FILE_KEYS_ALERT), xem
+    // module cardinality; không đặt thì không cảnh báo
+    file_keys_alert: Option<u64>,
+    // Lưu chunk dạng delta khi /store có `baseChunkHash` (STORAGE_DELTA_ENCODING=1, xem
+    // module delta). Chunk delta đã lưu luôn đọc được dù tắt cờ này.
+    delta_encoding: bool,
+    // Chunk có value lớn hơn ngưỡng này (byte, sau khi nén) được lưu trong tree riêng để
+    // không chiếm cache của chunk nhỏ (STORAGE_LARGE_CHUNK_BYTES, xem module tier). Không
+    // đặt thì mọi chunk nằm trong shard như trước.
+    large_chunk_bytes: Option<usize>,
+    // Lưu value của chunk một lần theo hash nội dung, dùng chung giữa các chunk giống hệt
+    // nhau (STORAGE_DEDUP=1, xem module dedup). Chunk đã khử trùng lặp luôn đọc được dù tắt cờ này.
+    dedup: bool,
+    // Nơi lưu value của chunk đã tách khỏi record (STORAGE_BACKEND, xem module store); record
+    // luôn nằm trong sled. `sled` (mặc định, tree `chunks_large`) hoặc `fs` (mỗi chunk một
+    // file). Chỉ đọc lúc khởi động; tên backend khác làm server không khởi động.
+    backend: store::Backend,
+    // Thư mục chứa file của backend `fs` (STORAGE_CHUNK_DIR, mặc định `data`)
+    chunk_dir: String,
+    // Gửi header Server-Timing với thời gian từng bước của GET /file/:fileKey
+    // (STORAGE_SERVER_TIMING=1)
+    server_timing: bool,
+    // Trao đổi danh sách peer qua /gossip (STORAGE_GOSSIP=1, xem module gossip)
+    gossip: bool,
+    // Các peer ban đầu, URL gốc phân cách bằng dấu phẩy (STORAGE_PEERS)
+    peers: Vec<String>,
+    // URL mà các peer dùng để gọi tới node này (STORAGE_NODE_URL)
+    node_url: Option<String>,
+    // Chu kỳ gossip (STORAGE_GOSSIP_INTERVAL_MS)
+    gossip_interval_ms: u64,
+    // Số peer được gọi trong mỗi chu kỳ (STORAGE_GOSSIP_FANOUT)
+    gossip_fanout: usize,
+    // Số peer tối đa được ghi nhớ (STORAGE_MAX_PEERS)
+    max_peers: usize,
+    // Thời gian tối đa chờ flush database lần cuối khi tắt server
+    // (STORAGE_SHUTDOWN_FLUSH_TIMEOUT_MS, xem module shutdown)
+    shutdown_flush_timeout_ms: u64,
+    // Thời gian tối đa chờ các request đang chạy xử lý xong khi tắt server
+    // (STORAGE_SHUTDOWN_DRAIN_TIMEOUT_MS, xem module shutdown)
+    shutdown_drain_timeout_ms: u64,
+    // Thời gian sống của chunk theo prefix fileKey, đọc từ file STORAGE_TTL_FILE (xem
+    // module ttl). Không đặt thì chunk không bao giờ hết hạn.
+    ttl: ttl::TtlPolicy,
+    // Chu kỳ quét chunk hết hạn (STORAGE_TTL_SWEEP_SECS)
+    ttl_sweep_secs: u64,
+    // Thư mục chứa snapshot database định kỳ (STORAGE_SNAPSHOT_DIR, xem module backup).
+    // Không đặt thì không chụp snapshot.
+    snapshot_dir: Option<String>,
+    // Chu kỳ chụp snapshot (STORAGE_SNAPSHOT_INTERVAL_SECS)
+    snapshot_interval_secs: u64,
+    // Số snapshot mới nhất được giữ lại (STORAGE_SNAPSHOT_RETAIN)
+    snapshot_retain: usize,
+    // Địa chỉ lắng nghe của HTTP server (STORAGE_HTTP_ADDR)
+    http_addr: SocketAddr,
+    // Mức log thấp nhất được ghi (STORAGE_LOG_LEVEL: error, warn, info, debug, trace)
+    log_level: tracing::Level,
+    // Định dạng log: `text` hoặc `json` (STORAGE_LOG_FORMAT, xem module logging)
+    log_format: logging::LogFormat,
+    // Chứng chỉ và private key PEM để phục vụ HTTPS/HTTP2 (STORAGE_TLS_CERT,
+    // STORAGE_TLS_KEY, xem module tls); phải đặt cả hai hoặc không đặt cái nào
+    tls: Option<(String, String)>,
+    // Khoảng cách tối thiểu giữa hai lần flush database, 0 là không giới hạn
+    // (STORAGE_FLUSH_MIN_INTERVAL_MS, xem module flush)
+    flush_min_interval_ms: u64,
+    // Khi nào lần ghi được flush: `always`, `interval` hoặc `batched`
+    // (STORAGE_DURABILITY, xem module flush)
+    durability: flush::Durability,
+    // Chu kỳ flush của chế độ `interval` (STORAGE_DURABILITY_INTERVAL_MS)
+    durability_interval_ms: u64,
+    // Chế độ `batched` flush sau mỗi N chunk được ghi thay vì sau mỗi lần ghi
+    // (STORAGE_FLUSH_EVERY_WRITES, xem module flush); không dùng cùng
+    // STORAGE_FLUSH_MIN_INTERVAL_MS
+    flush_every_writes: u64,
+    // Số blocking worker ghi song song một batch lớn (STORAGE_BATCH_PARALLELISM, xem
+    // `commit_batch`); 1 là ghi tuần tự trong một transaction
+    batch_parallelism: usize,
+    // Batch có ít chunk hơn thì luôn ghi tuần tự (STORAGE_BATCH_PARALLEL_MIN_CHUNKS)
+    batch_parallel_min_chunks: usize,
+    // Số blocking worker đọc song song chunk của một file ở GET /file/:fileKey
+    // (STORAGE_READ_PARALLELISM, xem `load_file_chunks_parallel`); 1 là đọc tuần tự
+    read_parallelism: usize,
+    // File có ít chunk hơn thì luôn đọc trong một worker (STORAGE_PARALLEL_READ_MIN_CHUNKS)
+    parallel_read_min_chunks: usize,
+    // Gộp các lượt đọc cùng fileKey đang chạy đồng thời vào một lượt quét
+    // (STORAGE_READ_COALESCING, xem `load_file_chunks_shared`)
+    read_coalescing: bool,
+    // Đưa chunk vừa ghi vào hàng đợi replication tới các peer (STORAGE_REPLICATION=1, xem
+    // module replication)
+    replication: bool,
+    // Chu kỳ gửi hàng đợi replication (STORAGE_REPLICATION_INTERVAL_MS), cũng là khoảng
+    // thử lại đầu tiên sau một lần lỗi
+    replication_interval_ms: u64,
+    // Số entry tối đa gửi trong một chu kỳ (STORAGE_REPLICATION_BATCH)
+    replication_batch: usize,
+    // Số peer nhận mỗi chunk, 0 là mọi peer (STORAGE_REPLICATION_FACTOR)
+    replication_factor: usize,
+    // Hỏi các peer khi đọc một chunk không có ở node này (STORAGE_READ_FALLTHROUGH=1)
+    read_fallthrough: bool,
+    // Số lần lỗi liên tiếp trước khi ngừng gọi một peer, 0 là tắt (STORAGE_BREAKER_FAILURES),
+    // và thời gian chờ trước khi gọi thử lại (STORAGE_BREAKER_COOLDOWN_MS); xem module breaker
+    breaker_failures: u32,
+    breaker_cooldown_ms: u64,
+    // Số luồng `GET /sync/full` chạy cùng lúc (STORAGE_SYNC_MAX_STREAMS) và số byte tối đa
+    // mỗi luồng gửi mỗi giây, 0 là không giới hạn (STORAGE_SYNC_RATE_BYTES); xem module sync
+    sync_max_streams: usize,
+    sync_rate_bytes: u64,
+    // Seed để kéo toàn bộ chunk lúc khởi động (STORAGE_SYNC_FROM) và API key gửi kèm
+    // (STORAGE_SYNC_API_KEY)
+    sync_from: Option<String>,
+    sync_api_key: Option<String>,
+    // Thư mục chứa database (STORAGE_DATA_DIR)
+    data_dir: String,
+    // Số sự kiện chưa đọc mỗi subscriber của `/events` được giữ trước khi bị bỏ
+    // (STORAGE_NOTIFY_BUFFER, xem module notify)
+    notify_buffer: usize,
+    // Chu kỳ kiểm tra chỗ trống của đĩa (STORAGE_DISK_RECHECK_SECS) và số byte trống tối
+    // thiểu trước khi tự chuyển sang chỉ đọc, 0 là chỉ khi ghi lỗi vì đĩa đầy
+    // (STORAGE_DISK_MIN_FREE_BYTES); xem module disk
+    disk_recheck_secs: u64,
+    disk_min_free_bytes: u64,
+    // Thời gian giữ số key và dung lượng đĩa đã đếm cho /diagnostics
+    // (STORAGE_DIAGNOSTICS_CACHE_SECS), vì phải quét cả database
+    diagnostics_cache_secs: u64,
+    // Số chunk tối đa giữ trong bộ đệm ghi trước khi /store phải chờ ghi xuống sled
+    // (STORAGE_WRITE_BUFFER, xem module write_buffer); 0 là ghi và flush trước khi trả về
+    write_buffer: usize,
+    // Chu kỳ ghi bộ đệm xuống sled (STORAGE_WRITE_BUFFER_FLUSH_MS)
+    write_buffer_flush_ms: u64,
+    // Số chunk dữ liệu (N) và số chunk parity (K) của mỗi stripe Reed-Solomon tính khi seal
+    // file (STORAGE_PARITY_DATA_SHARDS, STORAGE_PARITY_SHARDS; xem module parity). K = 0 là
+    // không tính parity.
+    parity_data_shards: usize,
+    parity_shards: usize,
+    // Mã xóa cho từng chunk ghi xuống (STORAGE_ERASURE_DATA_SHARDS, 0 là tắt, và
+    // STORAGE_ERASURE_PARITY_SHARDS; xem module erasure)
+    erasure: Option<erasure::Params>,
+    // Chấp nhận `chunkData` ở mọi biến thể Base64 (url-safe, không padding) và lưu ở dạng
+    // chuẩn (STORAGE_BASE64_NORMALIZE, xem `normalize_base64`)
+    base64_normalize: bool,
+    // Các kiểu nội dung được phép lưu (STORAGE_ALLOWED_CONTENT_TYPES, xem module mime);
+    // `None` là nhận mọi kiểu
+    allowed_content_types: Option<Vec<String>>,
+    // Khóa AES-256-GCM mã hóa value trước khi ghi (STORAGE_ENCRYPTION_KEY hoặc
+    // STORAGE_ENCRYPTION_KEY_FILE, xem module cipher); `None` là không mã hóa
+    encryption: Option<cipher::Key>,
+    // Tổng số byte tối đa node được lưu (STORAGE_MAX_TOTAL_BYTES) và số chunk tối đa của
+    // một fileKey (STORAGE_MAX_CHUNKS_PER_FILE); không đặt thì không giới hạn. Xem module usage
+    max_total_bytes: Option<u64>,
+    max_chunks_per_file: Option<u64>,
+    // Chu kỳ cộng dồn dung lượng của node cho /stats (STORAGE_USAGE_REFRESH_SECS)
+    usage_refresh_secs: u64,
+    // Thời gian sống của phiên upload kể từ lần gửi phần gần nhất
+    // (STORAGE_UPLOAD_SESSION_TTL_SECS) và chu kỳ xóa phiên hết hạn
+    // (STORAGE_UPLOAD_SWEEP_SECS). Xem module upload
+    upload_session_ttl_secs: u64,
+    upload_sweep_secs: u64,
+    // Dọn chunk mồ côi trong nền (xem module gc): chế độ (STORAGE_GC_SCHEDULE), chu kỳ
+    // (STORAGE_GC_INTERVAL_SECS), có coi chunk của file không có manifest là mồ côi không
+    // (STORAGE_GC_UNMANIFESTED) và tuổi tối thiểu của chunk bị dọn (STORAGE_GC_GRACE_SECS)
+    gc_schedule: gc::Schedule,
+    gc_interval_secs: u64,
+    gc_unmanifested: bool,
+    gc_grace_secs: u64,
+    // Kiểm tra hash của chunk trong nền (STORAGE_SCRUB, xem module scrub): số chunk mỗi lô
+    // (STORAGE_SCRUB_BATCH), thời gian nghỉ giữa hai lô (STORAGE_SCRUB_PAUSE_MS) và giữa
+    // hai lượt quét (STORAGE_SCRUB_INTERVAL_SECS)
+    scrub: bool,
+    scrub_batch: usize,
+    scrub_pause_ms: u64,
+    scrub_interval_secs: u64,
+}
+
+impl Config {
+    /// Đọc cấu hình từ đối số dòng lệnh (xem module cli), biến môi trường và file TOML
+    /// STORAGE_CONFIG_FILE (nếu có, xem module config). Biến có giá trị sai định dạng bị
+    /// bỏ qua (dùng giá trị mặc định) kèm cảnh báo trên stderr.
+    pub fn load() -> Result<Self, String> {
+        let vars = ConfigVars::load(Config::file_path().as_deref())?;
+        let config = Config::from_vars(&vars)?;
+        vars.warn_invalid();
+        Ok(config)
+    }
+
+    /// Đọc lại cấu hình cho `POST /admin/reload`: như `load` nhưng biến có giá trị sai định
+    /// dạng là lỗi. `Err` chứa lý do, và các biến sai định dạng nếu có.
+    fn reload() -> Result<Self, (String, Vec<String>)> {
+        let vars = ConfigVars::load(Config::file_path().as_deref()).map_err(|e| (e, Vec::new()))?;
+        let config = Config::from_vars(&vars).map_err(|e| (e, Vec::new()))?;
+        let invalid = vars.into_invalid();
+        if !invalid.is_empty() {
+            return Err((format!("invalid values: {}", invalid.join(", ")), invalid));
+        }
+        Ok(config)
+    }
+
+    // Đường dẫn STORAGE_CONFIG_FILE, đọc lại mỗi lần vì file có thể được đổi khi đang chạy
+    fn file_path() -> Option<String> {
+        cli::get("STORAGE_CONFIG_FILE").or_else(|| std::env::var("STORAGE_CONFIG_FILE").ok())
+    }
+
+    /// Cấu hình từ các cặp tên/giá trị (ưu tiên hơn biến môi trường), dùng khi nhúng
+    /// server vào test. Panic nếu file cấu hình được tham chiếu (ví dụ STORAGE_TTL_FILE)
+    /// không đọc được.
+    pub fn from_pairs<'a>(pairs: impl IntoIterator<Item = (&'a str, &'a str)>) -> Self {
+        let vars = ConfigVars::from_pairs(pairs);
+        let config = Config::from_vars(&vars).expect("cấu hình không hợp lệ");
+        vars.warn_invalid();
+        config
+    }
+
+    /// Mức log thấp nhất được ghi (STORAGE_LOG_LEVEL, mặc định "info")
+    pub fn log_level(&self) -> tracing::Level {
+        self.log_level
+    }
+
+    /// Định dạng log (STORAGE_LOG_FORMAT, mặc định "text")
+    pub fn log_format(&self) -> logging::LogFormat {
+        self.log_format
+    }
+
+    /// Thư mục chứa database (STORAGE_DATA_DIR, mặc định "my_database")
+    pub fn data_dir(&self) -> &str {
+        &self.data_dir
+    }
+
+    fn from_vars(vars: &ConfigVars) -> Result<Self, String> {
+        let parity_data_shards = vars.parse("STORAGE_PARITY_DATA_SHARDS", 8).max(1);
+        let parity_shards = vars.parse("STORAGE_PARITY_SHARDS", 0);
+        if parity_shards > 0 && parity_data_shards + parity_shards > parity::MAX_SHARDS {
+            return Err(format!(
+                "STORAGE_PARITY_DATA_SHARDS + STORAGE_PARITY_SHARDS không được vượt quá {}",
+                parity::MAX_SHARDS
+            ));
+        }
+        // Chạy trên backend khác với backend đã cấu hình sẽ ghi dữ liệu vào nhầm chỗ, nên tên
+        // backend sai không bị bỏ qua như các biến khác
+        let backend = match vars.get("STORAGE_BACKEND") {
+            Some(name) => name.parse().map_err(|e| format!("STORAGE_BACKEND={:?}: {}", name, e))?,
+            None => store::Backend::Sled,
+        };
+        let erasure = match vars.parse("STORAGE_ERASURE_DATA_SHARDS", 0) {
+            0 => None,
+            data_shards => Some(erasure::Params::new(data_shards, vars.parse("STORAGE_ERASURE_PARITY_SHARDS", 2))?),
+        };
+        let flush_min_interval_ms = vars.parse("STORAGE_FLUSH_MIN_INTERVAL_MS", 0);
+        let flush_every_writes = vars.parse("STORAGE_FLUSH_EVERY_WRITES", 0);
+        let default_durability = if flush_every_writes > 0 { flush::Durability::Batched } else { flush::Durability::Always };
+        let durability = vars.parse("STORAGE_DURABILITY", default_durability);
+        if flush_every_writes > 0 && durability != flush::Durability::Batched {
+            return Err("STORAGE_FLUSH_EVERY_WRITES chỉ dùng được với STORAGE_DURABILITY=batched".to_string());
+        }
+        let flush_every_writes = match durability {
+            flush::Durability::Batched if flush_every_writes == 0 => flush::DEFAULT_BATCH_WRITES,
+            _ => flush_every_writes,
+        };
+        if flush_min_interval_ms > 0 && durability == flush::Durability::Batched {
+            return Err("STORAGE_FLUSH_MIN_INTERVAL_MS và STORAGE_FLUSH_EVERY_WRITES không được đặt cùng nhau".to_string());
+        }
+        Ok(Config {
+            html_index: vars.flag("STORAGE_HTML_INDEX"),
+            grpc_addr: vars.parse("STORAGE_GRPC_ADDR", SocketAddr::from(([127, 0, 0, 1], 50051))),
+            max_concurrent_reads: vars.parse("STORAGE_MAX_CONCURRENT_READS", 64),
+            max_connections: vars.parse("STORAGE_MAX_CONNECTIONS", 1024).max(1),
+            verify_hash: vars.opt("STORAGE_VERIFY_HASH"),
+            request_timeout_ms: vars.parse("STORAGE_REQUEST_TIMEOUT_MS", 30_000),
+            route_timeouts: vars.parse("STORAGE_ROUTE_TIMEOUTS", timeout::RouteTimeouts::default()),
+            max_requests_per_file: vars.parse("STORAGE_MAX_REQUESTS_PER_FILE", 0),
+            rate_limit: vars.opt("STORAGE_RATE_LIMIT"),
+            client_rate_limit: vars.opt("STORAGE_CLIENT_RATE_LIMIT"),
+            store_bytes_per_sec: vars.opt("STORAGE_STORE_BYTES_PER_SEC"),
+            client_store_bytes_per_sec: vars.opt("STORAGE_CLIENT_STORE_BYTES_PER_SEC"),
+            max_scans_per_client: vars.parse("STORAGE_MAX_SCANS_PER_CLIENT", 0),
+            cache_max_age: vars.opt("STORAGE_CACHE_MAX_AGE"),
+            max_batch_files: vars.parse("STORAGE_MAX_BATCH_FILES", 100),
+            max_chunk_hashes: vars.parse("STORAGE_MAX_CHUNK_HASHES", 1000),
+            challenge_max_samples: vars.parse("STORAGE_CHALLENGE_MAX_SAMPLES", 256).max(1),
+            max_list_files: vars.parse("STORAGE_MAX_LIST_FILES", 1000).max(1),
+            shards: vars.parse("STORAGE_SHARDS", 1).max(1),
+            max_chunk_size: vars.parse("STORAGE_MAX_CHUNK_SIZE", 4 * 1024 * 1024),
+            max_metadata_bytes: vars.parse("STORAGE_MAX_METADATA_BYTES", 1024),
+            max_key_bytes: vars.parse("STORAGE_MAX_KEY_BYTES", 512).max(1),
+            split_chunk_size: vars.parse("STORAGE_SPLIT_CHUNK_SIZE", 4 * 1024 * 1024).max(1),
+            max_raw_upload: vars.parse("STORAGE_MAX_RAW_UPLOAD", 256 * 1024 * 1024),
+            hot_window_secs: vars.parse("STORAGE_HOT_WINDOW_SECS", 60).max(1),
+            quota_file: vars.opt("STORAGE_QUOTA_FILE"),
+            quota_owner_prefix_len: vars.parse("STORAGE_QUOTA_OWNER_PREFIX_LEN", 42),
+            max_batch_bytes: vars.parse("STORAGE_MAX_BATCH_BYTES", 64 * 1024 * 1024),
+            value_format: vars.parse("STORAGE_VALUE_FORMAT", record::ValueFormat::Json),
+            order_index: vars.flag("STORAGE_ORDER_INDEX"),
+            error_lang: vars.parse("STORAGE_ERROR_LANG", error::Lang::En),
+            compression: vars.parse("STORAGE_COMPRESSION", compress::Codec::Identity),
+            compression_level: {
+                let range = zstd::compression_level_range();
+                vars.parse("STORAGE_COMPRESSION_LEVEL", compress::DEFAULT_LEVEL).clamp(*range.start(), *range.end())
+            },
+            admin_api_key: vars.opt("STORAGE_ADMIN_API_KEY"),
+            auth: vars.flag("STORAGE_AUTH"),
+            api_tokens: vars.parse("STORAGE_API_TOKENS", auth::StaticTokens::default()),
+            peer_token: vars.get("STORAGE_PEER_TOKEN"),
+            stream_prefetch: vars.parse("STORAGE_STREAM_PREFETCH", 4).max(1),
+            max_response_bytes: vars.opt("STORAGE_MAX_RESPONSE_BYTES"),
+            verify_writes: vars.flag("STORAGE_VERIFY_WRITES"),
+            overwrite_mode: vars.parse("STORAGE_OVERWRITE_MODE", quota::OverwriteMode::Verify),
+            file_keys_alert: vars.opt("STORAGE_FILE_KEYS_ALERT"),
+            delta_encoding: vars.flag("STORAGE_DELTA_ENCODING"),
+            large_chunk_bytes: vars.opt("STORAGE_LARGE_CHUNK_BYTES"),
+            dedup: vars.flag("STORAGE_DEDUP"),
+            backend,
+            chunk_dir: vars.get("STORAGE_CHUNK_DIR").unwrap_or_else(|| "data".to_string()),
+            server_timing: vars.flag("STORAGE_SERVER_TIMING"),
+            gossip: vars.flag("STORAGE_GOSSIP"),
+            peers: vars
+                .get("STORAGE_PEERS")
+                .map(|list| list.split(',').map(str::trim).filter(|p| !p.is_empty()).map(String::from).collect())
+                .unwrap_or_default(),
+            node_url: vars.opt("STORAGE_NODE_URL"),
+            gossip_interval_ms: vars.parse("STORAGE_GOSSIP_INTERVAL_MS", 5000).max(100),
+            gossip_fanout: vars.parse("STORAGE_GOSSIP_FANOUT", 3).max(1),
+            max_peers: vars.parse("STORAGE_MAX_PEERS", 64),
+            shutdown_flush_timeout_ms: vars.parse("STORAGE_SHUTDOWN_FLUSH_TIMEOUT_MS", 5000),
+            shutdown_drain_timeout_ms: vars.parse("STORAGE_SHUTDOWN_DRAIN_TIMEOUT_MS", 30_000),
+            ttl: ttl::TtlPolicy::load(vars.get("STORAGE_TTL_FILE").as_deref())?,
+            ttl_sweep_secs: vars.parse("STORAGE_TTL_SWEEP_SECS", 60).max(1),
+            snapshot_dir: vars.opt("STORAGE_SNAPSHOT_DIR"),
+            snapshot_interval_secs: vars.parse("STORAGE_SNAPSHOT_INTERVAL_SECS", 3600).max(1),
+            snapshot_retain: vars.parse("STORAGE_SNAPSHOT_RETAIN", 24).max(1),
+            http_addr: vars.parse("STORAGE_HTTP_ADDR", SocketAddr::from(([127, 0, 0, 1], 3000))),
+            log_level: vars.parse("STORAGE_LOG_LEVEL", tracing::Level::INFO),
+            log_format: vars.parse("STORAGE_LOG_FORMAT", logging::LogFormat::Text),
+            tls: match (vars.get("STORAGE_TLS_CERT"), vars.get("STORAGE_TLS_KEY")) {
+                (Some(cert), Some(key)) => Some((cert, key)),
+                (None, None) => None,
+                _ => return Err("STORAGE_TLS_CERT và STORAGE_TLS_KEY phải được đặt cùng nhau".to_string()),
+            },
+            flush_min_interval_ms,
+            durability,
+            durability_interval_ms: vars.parse("STORAGE_DURABILITY_INTERVAL_MS", 1000).max(1),
+            flush_every_writes,
+            batch_parallelism: vars.parse("STORAGE_BATCH_PARALLELISM", 1).max(1),
+            batch_parallel_min_chunks: vars.parse("STORAGE_BATCH_PARALLEL_MIN_CHUNKS", 256),
+            read_parallelism: vars.parse("STORAGE_READ_PARALLELISM", 1).max(1),
+            parallel_read_min_chunks: vars.parse("STORAGE_PARALLEL_READ_MIN_CHUNKS", 256),
+            read_coalescing: vars.flag("STORAGE_READ_COALESCING"),
+            replication: vars.flag("STORAGE_REPLICATION"),
+            replication_interval_ms: vars.parse("STORAGE_REPLICATION_INTERVAL_MS", 1000).max(10),
+            replication_batch: vars.parse("STORAGE_REPLICATION_BATCH", 64).max(1),
+            replication_factor: vars.parse("STORAGE_REPLICATION_FACTOR", 0),
+            read_fallthrough: vars.flag("STORAGE_READ_FALLTHROUGH"),
+            breaker_failures: vars.parse("STORAGE_BREAKER_FAILURES", 5),
+            breaker_cooldown_ms: vars.parse("STORAGE_BREAKER_COOLDOWN_MS", 30000),
+            sync_max_streams: vars.parse("STORAGE_SYNC_MAX_STREAMS", 1),
+            sync_rate_bytes: vars.parse("STORAGE_SYNC_RATE_BYTES", 0),
+            sync_from: vars.get("STORAGE_SYNC_FROM"),
+            sync_api_key: vars.get("STORAGE_SYNC_API_KEY"),
+            data_dir: vars.get("STORAGE_DATA_DIR").unwrap_or_else(|| "my_database".to_string()),
+            notify_buffer: vars.parse("STORAGE_NOTIFY_BUFFER", 1024).max(1),
+            disk_recheck_secs: vars.parse("STORAGE_DISK_RECHECK_SECS", 30).max(1),
+            disk_min_free_bytes: vars.parse("STORAGE_DISK_MIN_FREE_BYTES", 0),
+            diagnostics_cache_secs: vars.parse("STORAGE_DIAGNOSTICS_CACHE_SECS", 30),
+            write_buffer: vars.parse("STORAGE_WRITE_BUFFER", 0),
+            write_buffer_flush_ms: vars.parse("STORAGE_WRITE_BUFFER_FLUSH_MS", 50).max(1),
+            parity_data_shards,
+            parity_shards,
+            erasure,
+            base64_normalize: vars.flag("STORAGE_BASE64_NORMALIZE"),
+            allowed_content_types: vars
+                .get("STORAGE_ALLOWED_CONTENT_TYPES")
+                .map(|list| list.split(',').map(str::trim).filter(|t| !t.is_empty()).map(String::from).collect()),
+            encryption: cipher::Key::load(
+                vars.get("STORAGE_ENCRYPTION_KEY").as_deref(),
+                vars.get("STORAGE_ENCRYPTION_KEY_FILE").as_deref(),
+            )?,
+            max_total_bytes: vars.opt("STORAGE_MAX_TOTAL_BYTES"),
+            max_chunks_per_file: vars.opt("STORAGE_MAX_CHUNKS_PER_FILE"),
+            usage_refresh_secs: vars.parse("STORAGE_USAGE_REFRESH_SECS", 10).max(1),
+            upload_session_ttl_secs: vars.parse("STORAGE_UPLOAD_SESSION_TTL_SECS", 24 * 60 * 60),
+            upload_sweep_secs: vars.parse("STORAGE_UPLOAD_SWEEP_SECS", 60).max(1),
+            gc_schedule: vars.parse("STORAGE_GC_SCHEDULE", gc::Schedule::Off),
+            gc_interval_secs: vars.parse("STORAGE_GC_INTERVAL_SECS", 24 * 60 * 60).max(1),
+            gc_unmanifested: vars.flag("STORAGE_GC_UNMANIFESTED"),
+            gc_grace_secs: vars.parse("STORAGE_GC_GRACE_SECS", 24 * 60 * 60),
+            scrub: vars.flag("STORAGE_SCRUB"),
+            scrub_batch: vars.parse("STORAGE_SCRUB_BATCH", 100).max(1),
+            scrub_pause_ms: vars.parse("STORAGE_SCRUB_PAUSE_MS", 200),
+            scrub_interval_secs: vars.parse("STORAGE_SCRUB_INTERVAL_SECS", 24 * 60 * 60).max(1),
+        })
+    }
+
+    /// Tên các thiết lập khác nhau giữa hai cấu hình nhưng chỉ có hiệu lực khi khởi
+    /// động (shard đã mở, địa chỉ đã bind, route đã tạo, ...)
+    fn immutable_changes(&self, new: &Config) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+        let mut check = |name, same: bool| {
+            if !same {
+                changed.push(name);
+            }
+        };
+        check("STORAGE_HTML_INDEX", self.html_index == new.html_index);
+        check("STORAGE_GRPC_ADDR", self.grpc_addr == new.grpc_addr);
+        check("STORAGE_HTTP_ADDR", self.http_addr == new.http_addr);
+        check("STORAGE_LOG_LEVEL", self.log_level == new.log_level);
+        check("STORAGE_LOG_FORMAT", self.log_format == new.log_format);
+        check("STORAGE_TLS_CERT/STORAGE_TLS_KEY", self.tls == new.tls);
+        check("STORAGE_ENCRYPTION_KEY/STORAGE_ENCRYPTION_KEY_FILE", self.encryption == new.encryption);
+        check("STORAGE_MAX_CONCURRENT_READS", self.max_concurrent_reads == new.max_concurrent_reads);
+        check("STORAGE_MAX_CONNECTIONS", self.max_connections == new.max_connections);
+        check("STORAGE_REQUEST_TIMEOUT_MS", self.request_timeout_ms == new.request_timeout_ms);
+        check("STORAGE_ROUTE_TIMEOUTS", self.route_timeouts == new.route_timeouts);
+        check("STORAGE_DATA_DIR", self.data_dir == new.data_dir);
+        check("STORAGE_BACKEND", self.backend == new.backend);
+        check("STORAGE_CHUNK_DIR", self.chunk_dir == new.chunk_dir);
+        check("STORAGE_NOTIFY_BUFFER", self.notify_buffer == new.notify_buffer);
+        check("STORAGE_SHARDS", self.shards == new.shards);
+        check("STORAGE_MAX_RAW_UPLOAD", self.max_raw_upload == new.max_raw_upload);
+        check("STORAGE_HOT_WINDOW_SECS", self.hot_window_secs == new.hot_window_secs);
+        check("STORAGE_QUOTA_FILE", self.quota_file == new.quota_file);
+        check("STORAGE_QUOTA_OWNER_PREFIX_LEN", self.quota_owner_prefix_len == new.quota_owner_prefix_len);
+        check("STORAGE_ORDER_INDEX", self.order_index == new.order_index);
+        check("STORAGE_GOSSIP", self.gossip == new.gossip);
+        check("STORAGE_NODE_URL", self.node_url == new.node_url);
+        changed
+    }
+}
+
+// State dùng chung giữa các handler
+pub struct AppState {
+    db: sled::Db,
+    // Các tree chứa chunk; mỗi fileKey luôn nằm trọn trong một tree (xem `tree_for`)
+    shards: Vec<sled::Tree>,
+    // Value của các chunk lớn, key giống key trong shard (xem module tier), và blob dùng
+    // chung khi khử trùng lặp (xem module dedup)
+    large: tier::Large,
+    // Tree `chunk_order` khi bật index thứ tự
+    order: Option<sled::Tree>,
+    // Cấu hình hiện tại; được thay nguyên khối khi `POST /admin/reload`
+    config: ArcSwap<Config>,
+    // Bộ đếm số lượt đọc của từng fileKey
+    access_counts: sled::Tree,
+    // Quota và dung lượng đang dùng của từng tenant
+    quotas: quota::Quotas,
+    // Số chunk của từng fileKey và số fileKey khác nhau
+    files: cardinality::FileCounts,
+    // Danh sách peer khi bật gossip
+    membership: Option<gossip::Membership>,
+    // Các fileKey đã bị seal, không được sửa đổi nữa (xem module seal)
+    sealed: sled::Tree,
+    // Alias dễ đọc -> fileKey (xem module alias)
+    aliases: sled::Tree,
+    // Manifest do client khai báo: fileKey -> thứ tự chunk và metadata (xem module manifest)
+    manifests: sled::Tree,
+    // Các API token đã tạo qua /admin/tokens: id -> hash và quyền (xem module auth)
+    tokens: sled::Tree,
+    // Các stripe parity Reed-Solomon của file đã seal (xem module parity)
+    parity: sled::Tree,
+    // Key chunk -> thời điểm hết hạn của các chunk lưu kèm `ttlSeconds` (xem module ttl)
+    expiry: sled::Tree,
+    // Dung lượng của node cộng dồn lần gần nhất (xem module usage)
+    node_usage: sled::Tree,
+    // Phiên upload nhiều phần: sessionId -> phiên, và các phần đã nhận (xem module upload)
+    uploads: sled::Tree,
+    upload_parts: sled::Tree,
+    // Chế độ bảo trì chỉ đọc, bật/tắt qua `POST /admin/readonly`; dùng chung cho mọi
+    // namespace của node
+    read_only: Arc<AtomicBool>,
+    // Chế độ chỉ đọc được bật tự động vì đĩa đầy (xem module disk)
+    disk_full: Arc<AtomicBool>,
+    // Có gRPC service phục vụ state này hay không (bật khi `grpc_service` được gọi)
+    grpc_enabled: Arc<AtomicBool>,
+    // Bản chụp top file truy cập nhiều, cập nhật bởi task nền
+    hot: std::sync::RwLock<Arc<hot::HotSnapshot>>,
+    // Giới hạn số lượt quét prefix đồng thời của cả node để bảo vệ độ trễ đọc
+    read_limiter: Arc<Semaphore>,
+    // Các lượt quét file đang chạy mà lượt đọc tới sau có thể dùng chung (xem
+    // `load_file_chunks_shared`)
+    read_flights: std::sync::Mutex<std::collections::HashMap<ReadFlightKey, Arc<ReadFlight>>>,
+    // Số request ghi đang chạy của từng fileKey (xem `acquire_file`); fileKey không còn
+    // request nào thì bị xóa khỏi map
+    file_requests: std::sync::Mutex<std::collections::HashMap<String, usize>>,
+    // Bucket tốc độ và số lượt đọc đang chạy của node và từng client (xem module ratelimit),
+    // dùng chung cho mọi namespace
+    limiter: Arc<ratelimit::Limiter>,
+    // Gộp các lần flush để giới hạn tần suất fsync (xem module flush)
+    flusher: flush::FlushLimiter,
+    // Các chunk đang chờ gửi tới peer (xem module replication)
+    replication: replication::Queue,
+    // Circuit breaker của từng peer (xem module breaker)
+    breakers: breaker::Breakers,
+    // Tiến độ của lần dựng lại các index phụ (xem module reindex)
+    reindex: reindex::Reindexer,
+    // Tiến độ của scrubber và tree `quarantine` (xem module scrub)
+    scrubber: scrub::Scrubber,
+    // Namespace của state này và các namespace khác của node (xem module namespace)
+    namespaces: namespace::Namespaces,
+    // Kênh sự kiện cho các subscriber của `/events` (xem module notify)
+    notifier: notify::Notifier,
+    // Các lần ghi đã trả `200` nhưng chưa nằm trong sled (xem module write_buffer)
+    write_buffer: write_buffer::WriteBuffer,
+    // Số luồng `/sync/full` đang chạy (xem module sync)
+    sync_streams: std::sync::atomic::AtomicUsize,
+    // Số key và dung lượng đĩa đếm lần gần nhất cho /diagnostics, kèm thời điểm đếm
+    db_stats: std::sync::Mutex<Option<(Instant, DbStats)>>,
+    metrics: Metrics,
+    // Thời điểm dựng state, để tính thời gian chạy trong log lúc tắt
+    started: Instant,
+}
+
+// fileKey và các codec client chấp nhận (ảnh hưởng tới kết quả đọc)
+type ReadFlightKey = (String, Vec<compress::Codec>);
+// Kết quả của một lượt quét dùng chung, có sau khi lượt quét xong
+type ReadFlight = tokio::sync::OnceCell<Result<Arc<(Vec<Chunk>, ReadTimings)>, StatusCode>>;
+
+// Giữ một suất đọc; tự trả lại suất và cập nhật metrics khi bị drop
+struct ReadPermit<'a> {
+    _permit: SemaphorePermit<'a>,
+    metrics: &'a Metrics,
+}
+
+impl Drop for ReadPermit<'_> {
+    fn drop(&mut self) {
+        self.metrics.reads_in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+// Giữ một suất ghi của một fileKey; tự trả lại khi bị drop, kể cả khi handler trả lỗi
+// hoặc request bị hủy giữa chừng
+struct FilePermit<'a> {
+    state: &'a AppState,
+    file_key: Option<String>,
+}
+
+impl Drop for FilePermit<'_> {
+    fn drop(&mut self) {
+        let Some(file_key) = self.file_key.take() else {
+            return;
+        };
+        let mut requests = self.state.file_requests.lock().unwrap();
+        if let Some(count) = requests.get_mut(&file_key) {
+            *count -= 1;
+            if *count == 0 {
+                requests.remove(&file_key);
+            }
+        }
+    }
+}
+
+impl AppState {
+    /// Địa chỉ lắng nghe của gRPC server
+    pub fn grpc_addr(&self) -> SocketAddr {
+        self.config.load().grpc_addr
+    }
+
+    /// Trả về lỗi `503` khi node đang ở chế độ chỉ đọc; gọi đầu mọi handler ghi/xóa/sửa
+    fn ensure_writable(&self) -> Result<(), ApiError> {
+        if self.read_only.load(Ordering::Relaxed) {
+            return Err(ApiError::ReadOnly);
+        }
+        Ok(())
+    }
+
+    /// Trả về lỗi `415` khi bật STORAGE_ALLOWED_CONTENT_TYPES và kiểu nội dung của chunk
+    /// (khai báo, hoặc nhận ra từ `head` nếu là chunk đầu tiên) không được phép
+    fn ensure_content_type_allowed(&self, declared: Option<&str>, index: Option<u64>, head: &[u8]) -> Result<(), ApiError> {
+        let config = self.config.load();
+        let Some(allowlist) = &config.allowed_content_types else {
+            return Ok(());
+        };
+        match mime::effective(declared, index, head) {
+            Some(content_type) if !mime::is_allowed(allowlist, &content_type) => {
+                tracing::info!("-> Từ chối chunk có kiểu nội dung không được phép: {}", content_type);
+                Err(ApiError::UnsupportedMediaType)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Trả về lỗi `400` khi fileKey hoặc chunkHash không dùng được cho lần ghi mới (xem
+    /// `key::component_error`); gọi ở mọi đường ghi chunk mới nhận key từ client
+    fn ensure_valid_key(&self, file_key: &str, chunk_hash: &str) -> Result<(), ApiError> {
+        let max_len = self.config.load().max_key_bytes;
+        if key::component_error(file_key, max_len).or(key::component_error(chunk_hash, max_len)).is_some() {
+            return Err(ApiError::InvalidKey);
+        }
+        Ok(())
+    }
+
+    /// Kiểm tra chung cho mọi đường ghi chunk mới nhận từ client, qua HTTP hay gRPC: key
+    /// dùng được (`400`), `len` byte dữ liệu không vượt STORAGE_MAX_CHUNK_SIZE (`413`) và kiểu
+    /// nội dung được phép (`415`). `data` chỉ cần chứa phần đầu của dữ liệu.
+    fn ensure_storable(
+        &self,
+        file_key: &str,
+        chunk_hash: &str,
+        len: u64,
+        content_type: Option<&str>,
+        index: Option<u64>,
+        data: &[u8],
+    ) -> Result<(), ApiError> {
+        self.ensure_valid_key(file_key, chunk_hash)?;
+        if len > self.config.load().max_chunk_size as u64 {
+            return Err(ApiError::PayloadTooLarge);
+        }
+        self.ensure_content_type_allowed(content_type, index, &data[..data.len().min(mime::SNIFF_LEN)])
+    }
+
+    /// Như `ensure_valid_key` cho fileKey nhị phân (xem `key::raw_component_error`)
+    fn ensure_valid_raw_key(&self, file_key: &[u8], chunk_hash: &str) -> Result<(), ApiError> {
+        let max_len = self.config.load().max_key_bytes;
+        if key::raw_component_error(file_key, max_len).or(key::component_error(chunk_hash, max_len)).is_some() {
+            return Err(ApiError::InvalidKey);
+        }
+        Ok(())
+    }
+
+    /// Trả về lỗi `403` khi `file_key` đã bị seal; gọi đầu mọi handler ghi/xóa/sửa chunk
+    /// của một file cụ thể
+    fn ensure_unsealed(&self, file_key: &str) -> Result<(), ApiError> {
+        match seal::is_sealed(&self.sealed, file_key) {
+            Ok(false) => Ok(()),
+            Ok(true) => Err(ApiError::Sealed),
+            Err(e) => {
+                tracing::error!("Lỗi khi đọc cờ seal của {}: {}", file_key, e);
+                Err(ApiError::Internal)
+            }
+        }
+    }
+
+    /// fileKey thật của `name` trên các route đọc: fileKey mà alias `name` trỏ tới, hoặc
+    /// chính `name` nếu đó không phải alias
+    fn resolve_file_key(&self, name: String) -> Result<String, ApiError> {
+        if alias::looks_like_key(&name) {
+            return Ok(name);
+        }
+        match alias::lookup(&self.aliases, &name) {
+            Ok(target) => Ok(target.unwrap_or(name)),
+            Err(e) => {
+                tracing::error!("Lỗi khi phân giải alias {}: {}", name, e);
+                Err(ApiError::Internal)
+            }
+        }
+    }
+
+    /// Manifest client đã khai báo cho file, `None` nếu chưa có
+    fn manifest(&self, file_key: &str) -> Result<Option<manifest::Manifest>, ApiError> {
+        manifest::get(&self.manifests, file_key).map_err(|e| {
+            tracing::error!("Lỗi khi đọc manifest của file {}: {}", file_key, e);
+            ApiError::Internal
+        })
+    }
+
+    /// Node có đang ở chế độ bảo trì chỉ đọc không
+    pub fn is_read_only(&self) -> bool {
+        self.read_only.load(Ordering::Relaxed)
+    }
+
+    /// Các peer đang sống theo gossip; rỗng nếu không bật STORAGE_GOSSIP
+    pub fn alive_peers(&self) -> Vec<String> {
+        self.membership.as_ref().map(|m| m.alive_peers()).unwrap_or_default()
+    }
+
+    /// Các peer nhận replication: mọi peer gossip đang biết (kể cả peer tạm không liên lạc
+    /// được, để dữ liệu được gửi bù khi nó quay lại), hoặc STORAGE_PEERS nếu không bật gossip
+    fn replication_peers(&self) -> Vec<String> {
+        match &self.membership {
+            Some(membership) => membership.peer_urls(),
+            None => {
+                let config = self.config.load();
+                let node_url = config.node_url.as_deref().map(|url| url.trim_end_matches('/'));
+                config
+                    .peers
+                    .iter()
+                    .map(|url| url.trim_end_matches('/').to_string())
+                    .filter(|url| Some(url.as_str()) != node_url)
+                    .collect()
+            }
+        }
+    }
+
+    /// Địa chỉ lắng nghe của HTTP server
+    pub fn http_addr(&self) -> SocketAddr {
+        self.config.load().http_addr
+    }
+
+    /// Acceptor TLS nếu đã cấu hình STORAGE_TLS_CERT/STORAGE_TLS_KEY, `None` nếu server
+    /// chạy plaintext. Lỗi khi không đọc được chứng chỉ hoặc private key.
+    pub fn tls_acceptor(&self) -> Result<Option<tls::TlsAcceptor>, String> {
+        let config = self.config.load();
+        config.tls.as_ref().map(|(cert, key)| tls::acceptor(cert, key)).transpose()
+    }
+
+    /// Số kết nối TCP tối đa mà HTTP server nhận cùng lúc
+    pub fn max_connections(&self) -> usize {
+        self.config.load().max_connections
+    }
+
+    /// Trả về tree chứa các chunk của `file_key`. Dùng consistent hash trên fileKey
+    /// để các chunk của cùng một file nằm chung một tree (quét prefix hiệu quả),
+    /// còn tải tổng thể được trải đều trên các tree.
+    fn tree_for(&self, file_key: &str) -> &sled::Tree {
+        &self.shards[self.shard_for(file_key)]
+    }
+
+    fn tree_for_raw(&self, file_key: &[u8]) -> &sled::Tree {
+        &self.shards[self.shard_for_raw(file_key)]
+    }
+
+    /// Ghi nhận một lượt đọc trả về `chunks` chunk của `file_key`: cộng vào bộ đếm truy cập
+    /// (xem module hot) và số chunk đã phục vụ
+    fn record_read(&self, file_key: &str, chunks: usize) {
+        hot::record_access(&self.access_counts, file_key);
+        self.metrics.chunks_served.fetch_add(chunks as u64, Ordering::Relaxed);
+    }
+
+    /// Thời gian từ lúc dựng state (khởi động server)
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Dữ liệu gốc và record của chunk có key `db_key` (xem `read_chunk`). Chạy đồng bộ.
+    fn read_chunk(&self, db_key: &[u8]) -> std::io::Result<Option<(Bytes, StoredChunkValue)>> {
+        let Some((file_key, _)) = key::decode_raw(db_key) else {
+            return Ok(None);
+        };
+        read_chunk(self.tree_for_raw(file_key), &self.large, db_key)
+    }
+
+    /// Record của chunk `db_key` nếu chunk tồn tại và chưa hết hạn, không đọc value trong
+    /// tree `chunks_large` hay giải nén dữ liệu. Chạy đồng bộ.
+    fn chunk_record(&self, db_key: &[u8]) -> std::io::Result<Option<StoredChunkValue>> {
+        let Some((file_key, _)) = key::decode_raw(db_key) else {
+            return Ok(None);
+        };
+        let Some(value_bytes) = self.tree_for_raw(file_key).get(db_key).map_err(std::io::Error::other)? else {
+            return Ok(None);
+        };
+        let record = record::decode(&value_bytes).ok_or_else(|| invalid_chunk("value bị hỏng", db_key))?;
+        Ok((!record.is_expired(unix_now())).then_some(record))
+    }
+
+    /// Chunk đã lưu ở key `file_key:chunk_hash` có cùng dữ liệu, index, kiểu nội dung,
+    /// metadata và chế độ ghi với `db_value` không, tức ghi `db_value` chỉ đổi thời điểm lưu
+    fn stores_same_chunk(&self, file_key: &str, chunk_hash: &str, db_value: &StoredChunkValue) -> Result<bool, StatusCode> {
+        let db_key = key::encode(file_key, chunk_hash);
+        let stored = self.read_chunk(db_key.as_bytes()).map_err(|e| {
+            tracing::error!("Lỗi khi đọc chunk {}: {}", db_key, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        Ok(stored.is_some_and(|(bytes, record)| {
+            record.index == db_value.index
+                && record.content_type == db_value.content_type
+                && record.metadata == db_value.metadata
+                && record.mode == db_value.mode
+                && STANDARD.decode(&db_value.value).is_ok_and(|data| data == bytes)
+        }))
+    }
+
+    // Vị trí shard của `file_key` trong `shards`
+    fn shard_for(&self, file_key: &str) -> usize {
+        self.shard_for_raw(file_key.as_bytes())
+    }
+
+    // Như `shard_for` cho fileKey nhị phân; cùng kết quả với fileKey dạng chữ có cùng byte
+    fn shard_for_raw(&self, file_key: &[u8]) -> usize {
+        jump_consistent_hash(fnv1a_64(file_key), self.shards.len())
+    }
+
+    /// Dữ liệu gốc của chunk `base_hash` trong `file_key` để làm base cho delta; `None` nếu
+    /// chunk không có, bị hỏng hoặc chính nó là delta
+    fn delta_base_data(&self, file_key: &str, base_hash: &str) -> Option<Vec<u8>> {
+        let base_key = key::encode(file_key, base_hash);
+        let bytes = self.tree_for(file_key).get(&base_key).ok()??;
+        delta::raw_of(&tier::fill(&self.large, base_key.as_bytes(), record::decode(&bytes)?)?)
+    }
+
+    /// Chuẩn bị một lần ghi chunk kèm thông tin shard, tenant và entry index thứ tự
+    fn pending_write(
+        &self,
+        file_key: &str,
+        chunk_hash: &str,
+        mut db_value: StoredChunkValue,
+    ) -> Result<quota::PendingWrite, StatusCode> {
+        let config = self.config.load();
+        // Dữ liệu gốc của chunk base nếu có gợi ý delta dùng được
+        let base = db_value
+            .delta_base
+            .take()
+            .filter(|base_hash| config.delta_encoding && base_hash != chunk_hash)
+            .and_then(|base_hash| Some((self.delta_base_data(file_key, &base_hash)?, base_hash)));
+        let original = base.as_ref().map(|_| db_value.value.clone());
+        db_value.size = Some(base64_decoded_len(&db_value.value));
+
+        if let Some(packed) = compress::compress(&db_value.value, config.compression, config.compression_level) {
+            db_value.value = packed;
+            db_value.encoding = config.compression;
+        }
+        // Chỉ lưu bản diff khi nó nhỏ hơn cách lưu thường (kể cả đã nén)
+        if let (Some((base, base_hash)), Some(original)) = (base, original)
+            && let Some(delta) = delta::encode(&base, &original, db_value.value.len())
+        {
+            db_value.value = delta;
+            db_value.encoding = compress::Codec::Identity;
+            db_value.delta_base = Some(base_hash);
+        }
+        let (large, blob) = split_value(&mut db_value, &config);
+        let order_key = self
+            .order
+            .as_ref()
+            .map(|_| order::entry_key(file_key, db_value.index, chunk_hash));
+        Ok(quota::PendingWrite {
+            shard: self.shard_for(file_key),
+            owner: self.quotas.owner(file_key).to_string(),
+            // Tạo key tổng hợp để lưu vào database, định dạng: "fileKey:chunkHash"
+            key: key::encode(file_key, chunk_hash).into_bytes(),
+            mode: db_value.mode,
+            expires_at: db_value.expires_at,
+            value: encode_value(db_value, &config)?,
+            large,
+            blob,
+            order_key,
+            replace_other_mode: false,
+            overwrite: config.overwrite_mode,
+            replicate: true,
+        })
+    }
+
+    /// Chuẩn bị một lần ghi chunk cho fileKey nhị phân không phải UTF-8 (xem `b64_file_key`).
+    /// Không dùng delta và không ghi entry index thứ tự: bên đọc sắp xếp theo index sau khi quét.
+    fn pending_write_raw(
+        &self,
+        file_key: &[u8],
+        chunk_hash: &str,
+        mut db_value: StoredChunkValue,
+    ) -> Result<quota::PendingWrite, StatusCode> {
+        let config = self.config.load();
+        db_value.delta_base = None;
+        db_value.size = Some(base64_decoded_len(&db_value.value));
+        if let Some(packed) = compress::compress(&db_value.value, config.compression, config.compression_level) {
+            db_value.value = packed;
+            db_value.encoding = config.compression;
+        }
+        let (large, blob) = split_value(&mut db_value, &config);
+        Ok(quota::PendingWrite {
+            shard: self.shard_for_raw(file_key),
+            owner: self.quotas.owner_raw(file_key),
+            key: key::encode_raw(file_key, chunk_hash),
+            mode: db_value.mode,
+            expires_at: db_value.expires_at,
+            value: encode_value(db_value, &config)?,
+            large,
+            blob,
+            order_key: None,
+            replace_other_mode: false,
+            overwrite: config.overwrite_mode,
+            replicate: true,
+        })
+    }
+
+    /// Flush database (và file của backend ngoài sled, xem module store) xuống đĩa, cách lần
+    /// flush trước ít nhất STORAGE_FLUSH_MIN_INTERVAL_MS (các lời gọi trong khoảng đó dùng
+    /// chung một lần flush). Chạy đồng bộ.
+    fn flush(&self) -> sled::Result<()> {
+        let min_interval = Duration::from_millis(self.config.load().flush_min_interval_ms);
+        // Backend sled được flush cùng database
+        let store = match self.large.tree() {
+            Some(_) => Ok(()),
+            None => self.large.store.flush(),
+        };
+        if let Err(e) = store.and_then(|()| self.flusher.flush(&self.db, min_interval, &self.metrics.flushes)) {
+            self.metrics.flush_failures.fetch_add(1, Ordering::Relaxed);
+            if disk::is_full(&e) {
+                self.degrade_disk_full();
+            }
+            return Err(e);
+        }
+        self.metrics.last_flush_ms.store(unix_now_ms(), Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Chính sách STORAGE_DURABILITY và tiến độ xuống đĩa của các chunk đã xác nhận
+    fn durability(&self) -> flush::Progress {
+        let (acknowledged, durable) = self.flusher.progress();
+        flush::Progress {
+            policy: self.config.load().durability,
+            acknowledged,
+            durable,
+            pending: acknowledged - durable,
+            last_flush_ms: self.metrics.last_flush_ms.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Ghi các chunk (kèm quota và index thứ tự) rồi flush xuống đĩa. Khi bật
+    /// STORAGE_VERIFY_WRITES, đọc lại từng key và so sánh với bytes đã định ghi để phát
+    /// hiện lỗi đĩa/sled ngay lúc ghi. Trả về lần ghi nào dùng lại blob đã có (xem
+    /// `apply_writes`). Chạy đồng bộ nên cần được gọi qua `blocking`.
+    fn commit_writes(&self, writes: &[quota::PendingWrite]) -> Result<Vec<bool>, StatusCode> {
+        let deduplicated = self.apply_writes(writes)?;
+        self.finish_writes(writes)?;
+        Ok(deduplicated)
+    }
+
+    // Ghi các chunk trong một transaction (xem `Quotas::write_chunks`), chưa flush. Trả về,
+    // theo thứ tự của `writes`, lần ghi nào trỏ tới blob cùng nội dung đã có sẵn thay vì lưu
+    // thêm (xem module dedup).
+    fn apply_writes(&self, writes: &[quota::PendingWrite]) -> Result<Vec<bool>, StatusCode> {
+        let config = self.config.load();
+        let written = self.quotas.write_chunks(
+            &self.shards,
+            self.order.as_ref(),
+            self.large_tree_in_use(&config).then_some(&self.large),
+            &self.files.tree,
+            writes,
+        )
+        .inspect_err(|&status| {
+            if status == StatusCode::INSUFFICIENT_STORAGE {
+                self.degrade_disk_full();
+            }
+        })?;
+        if written.new_files > 0 {
+            self.files.added(written.new_files);
+            self.files.check(config.file_keys_alert);
+        }
+        let shared = written.deduplicated.iter().filter(|&&d| d).count() as u64;
+        self.metrics.dedup_chunks.fetch_add(shared, Ordering::Relaxed);
+        self.metrics.dedup_bytes_saved.fetch_add(written.bytes_saved, Ordering::Relaxed);
+        Ok(written.deduplicated)
+    }
+
+    // Ghi entry hết hạn và đưa các chunk vừa ghi vào hàng đợi replication, flush cùng lúc (hoặc
+    // theo STORAGE_DURABILITY, xem module flush) rồi đọc lại để kiểm tra khi bật
+    // STORAGE_VERIFY_WRITES
+    fn finish_writes(&self, writes: &[quota::PendingWrite]) -> Result<(), StatusCode> {
+        let config = self.config.load();
+        if let Err(e) = ttl::track(&self.expiry, writes) {
+            tracing::error!("Lỗi khi ghi thời điểm hết hạn của chunk: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+        if config.replication {
+            let peers = self.replication_peers();
+            let keys = writes.iter().filter(|w| w.replicate).map(|w| w.key.as_slice());
+            if !peers.is_empty()
+                && let Err(e) = self.replication.enqueue(&self.db, &peers, config.replication_factor, keys)
+            {
+                tracing::error!("Lỗi khi thêm chunk vào hàng đợi replication: {}", e);
+                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            }
+        }
+        self.flusher.acknowledge(writes.len() as u64);
+        let flush_now = match config.durability {
+            flush::Durability::Always => true,
+            flush::Durability::Batched => self.flusher.count_writes(writes.len() as u64, config.flush_every_writes),
+            flush::Durability::Interval => false,
+        };
+        if flush_now && let Err(e) = self.flush() {
+            tracing::error!("Lỗi khi flush database: {}", e);
+            if disk::is_full(&e) {
+                return Err(StatusCode::INSUFFICIENT_STORAGE);
+            }
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+        self.notifier.stored(writes.iter().map(|w| w.key.as_slice()));
+        notify::completed_by(self, writes);
+
+        if !config.verify_writes {
+            return Ok(());
+        }
+        for write in writes {
+            let stored = self.shards[write.shard].get(&write.key);
+            let large_ok = match (&write.large, write.blob) {
+                (None, _) => true,
+                // Blob có thể là bản đã có sẵn của cùng nội dung nên chỉ so hash được trỏ tới
+                (Some(_), Some(hash)) => {
+                    matches!(self.large.blobs.files.get(&write.key), Ok(Some(stored)) if stored == hash[..])
+                        && matches!(self.large.blobs.chunks.contains_key(hash), Ok(true))
+                }
+                (Some(value), None) => matches!(self.large.store.get(&write.key), Ok(Some(bytes)) if bytes == value.as_slice()),
+            };
+            if !large_ok || !matches!(&stored, Ok(Some(bytes)) if bytes == write.value.as_slice()) {
+                self.metrics.write_verify_failures.fetch_add(1, Ordering::Relaxed);
+                tracing::error!(
+                    "⚠️  Dữ liệu đọc lại không khớp với dữ liệu vừa ghi: {}",
+                    String::from_utf8_lossy(&write.key)
+                );
+                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            }
+        }
+        Ok(())
+    }
+
+    /// Lần ghi có cần đưa tree `chunks_large` và các tree blob vào transaction không: khi
+    /// đang bật ngưỡng hoặc khử trùng lặp, hoặc khi các tree còn value cũ có thể bị ghi đè.
+    /// Backend ngoài sled luôn được dùng (xem `split_value`).
+    fn large_tree_in_use(&self, config: &Config) -> bool {
+        config.large_chunk_bytes.is_some()
+            || config.dedup
+            || self.large.tree().is_none_or(|tree| !tree.is_empty())
+            || self.large.blobs.in_use()
+    }
+
+    /// Lấy một suất đọc. Trả về `503` ngay khi đã hết suất thay vì xếp hàng,
+    /// để một vài file lớn không làm chậm tất cả các request đọc khác.
+    fn acquire_read(&self) -> Result<ReadPermit<'_>, StatusCode> {
+        match self.read_limiter.try_acquire() {
+            Ok(permit) => {
+                self.metrics.reads_in_flight.fetch_add(1, Ordering::Relaxed);
+                Ok(ReadPermit {
+                    _permit: permit,
+                    metrics: &self.metrics,
+                })
+            }
+            Err(_) => {
+                self.metrics.reads_rejected.fetch_add(1, Ordering::Relaxed);
+                Err(StatusCode::SERVICE_UNAVAILABLE)
+            }
+        }
+    }
+
+    /// Lấy một suất ghi cho `file_key`. Trả về `429` khi fileKey đã có
+    /// STORAGE_MAX_REQUESTS_PER_FILE request ghi đang chạy, để một client upload song song
+    /// một file lớn không chiếm hết node; các file khác không bị ảnh hưởng.
+    fn acquire_file(&self, file_key: &str) -> Result<FilePermit<'_>, ApiError> {
+        let limit = self.config.load().max_requests_per_file;
+        if limit == 0 {
+            return Ok(FilePermit {
+                state: self,
+                file_key: None,
+            });
+        }
+        let mut requests = self.file_requests.lock().unwrap();
+        let count = requests.entry(file_key.to_string()).or_default();
+        if *count >= limit {
+            drop(requests);
+            self.metrics.file_requests_rejected.fetch_add(1, Ordering::Relaxed);
+            return Err(ApiError::FileBusy);
+        }
+        *count += 1;
+        Ok(FilePermit {
+            state: self,
+            file_key: Some(file_key.to_string()),
+        })
+    }
+
+    /// Lần ghi cho một chunk nhận từ peer (`/replicate`) hoặc seed (module sync): ghi
+    /// nguyên trạng, thay cả record ghi bởi chế độ khác và không đưa vào hàng đợi replication
+    fn replica_write(&self, payload: replication::ReplicatePayload) -> Result<quota::PendingWrite, ApiError> {
+        let db_key = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(&payload.key)
+            .map_err(|_| ApiError::BadRequest)?;
+        let Some((file_key, chunk_hash)) = key::decode_raw(&db_key) else {
+            return Err(ApiError::BadRequest);
+        };
+        let Ok(data) = STANDARD.decode(&payload.chunk_data) else {
+            return Err(ApiError::BadRequest);
+        };
+        // Peer cũng không được ghi key hay chunk mà client không ghi được qua /store
+        match std::str::from_utf8(file_key) {
+            Ok(file_key) => self.ensure_valid_key(file_key, chunk_hash)?,
+            Err(_) => self.ensure_valid_raw_key(file_key, chunk_hash)?,
+        }
+        if data.len() > self.config.load().max_chunk_size {
+            return Err(ApiError::PayloadTooLarge);
+        }
+
+        let mut db_value = StoredChunkValue::new(payload.chunk_data, payload.chunk_index);
+        db_value.content_type = payload.content_type;
+        db_value.mode = payload.mode;
+        db_value.metadata = payload.metadata;
+        db_value.expires_at = payload.expires_at;
+        let mut write = match std::str::from_utf8(file_key) {
+            Ok(file_key) => {
+                self.ensure_unsealed(file_key)?;
+                self.pending_write(file_key, chunk_hash, db_value)?
+            }
+            Err(_) => self.pending_write_raw(file_key, chunk_hash, db_value)?,
+        };
+        write.replace_other_mode = true;
+        // Chunk từ peer là bản mới nhất ở node nguồn (có thể đã được ghi đè với
+        // `?overwrite=true`), nên chỉ STORAGE_OVERWRITE_MODE=reject mới giữ chunk đã có
+        if write.overwrite == quota::OverwriteMode::Verify {
+            write.overwrite = quota::OverwriteMode::Allow;
+        }
+        write.replicate = false;
+        Ok(write)
+    }
+}
+
+
+// ## KHỞI TẠO STATE VÀ ROUTER ##
+
+/// Mở các tree và dựng state dùng chung từ database và cấu hình đã đọc
+pub fn build_state(db: sled::Db, config: Config) -> Arc<AppState> {
+    let registry = db.open_tree("namespaces").expect("Không thể mở tree namespaces");
+    build_state_in(db, config, namespace::Namespaces::open(registry), None)
+}
+
+// Dựng state của namespace `namespaces.name()` từ các tree mang tiền tố của namespace đó.
+// State của namespace khác dùng chung với `node` (state của namespace mặc định) chế độ chỉ
+// đọc, suất đọc và giới hạn tốc độ của cả node.
+fn build_state_in(db: sled::Db, config: Config, namespaces: namespace::Namespaces, node: Option<&AppState>) -> Arc<AppState> {
+    let prefix = namespaces.name().map(namespace::tree_prefix).unwrap_or_default();
+    let open_tree = |name: &str| {
+        db.open_tree(format!("{}{}", prefix, name))
+            .unwrap_or_else(|e| panic!("Không thể mở tree {}: {}", name, e))
+    };
+    let shards = open_shards(&db, config.shards, &prefix);
+    let store = store::open(&db, &config, &prefix).unwrap_or_else(|e| panic!("Không thể mở backend {:?}: {}", config.backend, e));
+    let large = tier::Large::new(
+        store,
+        dedup::Blobs {
+            files: open_tree("files"),
+            chunks: open_tree("chunks"),
+        },
+    );
+    let access_counts = open_tree("access_counts");
+    let sealed = open_tree("sealed_files");
+    let aliases = open_tree("aliases");
+    let manifests = open_tree("manifests");
+    let tokens = open_tree("tokens");
+    let parity = open_tree("parity");
+    let expiry = open_tree("chunk_expiry");
+    let node_usage = open_tree("storage_usage");
+    let uploads = open_tree("upload_sessions");
+    let upload_parts = open_tree("upload_parts");
+    let quarantine = open_tree("quarantine");
+    let replication = replication::Queue::open(open_tree("replication_queue"));
+    if let Some(key) = &config.encryption {
+        cipher::install(key);
+    }
+    let quotas = quota::Quotas {
+        config: quota::QuotaConfig::load(config.quota_file.as_deref()).expect("Không thể đọc cấu hình quota"),
+        prefix_len: config.quota_owner_prefix_len,
+        usage: open_tree("quota_usage"),
+    };
+    quotas.rebuild_if_empty(&shards, &large);
+    let files = cardinality::FileCounts::open(open_tree("file_chunks"), &shards, &large);
+    files.check(config.file_keys_alert);
+    let order = config.order_index.then(|| {
+        let tree = open_tree("chunk_order");
+        order::rebuild_if_empty(&tree, &shards);
+        tree
+    });
+
+    // Bọc state trong Arc để chia sẻ an toàn giữa các thread
+    Arc::new(AppState {
+        db,
+        shards,
+        large,
+        order,
+        access_counts,
+        quotas,
+        files,
+        membership: config
+            .gossip
+            .then(|| gossip::Membership::new(&config.peers, config.node_url.clone(), config.max_peers)),
+        sealed,
+        aliases,
+        manifests,
+        tokens,
+        parity,
+        expiry,
+        node_usage,
+        uploads,
+        upload_parts,
+        read_only: node.map(|node| node.read_only.clone()).unwrap_or_default(),
+        disk_full: node.map(|node| node.disk_full.clone()).unwrap_or_default(),
+        grpc_enabled: node.map(|node| node.grpc_enabled.clone()).unwrap_or_default(),
+        hot: Default::default(),
+        read_limiter: node
+            .map(|node| node.read_limiter.clone())
+            .unwrap_or_else(|| Arc::new(Semaphore::new(config.max_concurrent_reads))),
+        file_requests: Default::default(),
+        limiter: node.map(|node| node.limiter.clone()).unwrap_or_default(),
+        read_flights: Default::default(),
+        notifier: notify::Notifier::new(config.notify_buffer),
+        config: ArcSwap::from_pointee(config),
+        flusher: Default::default(),
+        replication,
+        breakers: Default::default(),
+        reindex: Default::default(),
+        scrubber: scrub::Scrubber::open(quarantine),
+        namespaces,
+        write_buffer: Default::default(),
+        sync_streams: Default::default(),
+        db_stats: Default::default(),
+        metrics: Metrics::default(),
+        started: Instant::now(),
+    })
+}
+
+/// Tạo router HTTP với toàn bộ route và middleware của server
+pub fn build_app(state: Arc<AppState>) -> Router {
+    let config = state.config.load();
+
+    // Định nghĩa các route cho ứng dụng, mỗi route có giới hạn thời gian riêng
+    let mut app = timeout::Routes::new(&config.route_timeouts, config.request_timeout_ms)
+        .route("/store", post(store_chunk))
+        .route("/store/batch", post(store_batch))
+        .route("/file/:fileKey", get(retrieve_file_chunks).delete(delete_file))
+        .route("/file/:fileKey/rename", post(rename_file))
+        .route("/file/:fileKey/probe", get(probe_file))
+        .route("/file/:fileKey/tail", get(tail_file))
+        .route("/file/:fileKey/stream", get(stream_download))
+        .route("/file/:fileKey/stream-download", get(stream_download))
+        .route("/file/:fileKey/multipart", get(multipart_download))
+        .route("/file/:fileKey/resolve", get(resolve_alias))
+        .route("/file/:fileKey/manifest", get(file_manifest))
+        .route("/manifest/:fileKey", get(get_manifest).post(put_manifest))
+        .route("/file/:fileKey/chunks", post(retrieve_selected_chunks))
+        .route("/file/:fileKey/chunk/:chunkHash", get(retrieve_chunk))
+        .route("/file/:fileKey/query", post(query_chunks))
+        .route("/verify/:fileKey/:chunkHash", get(verify_chunk))
+        .route("/challenge", post(challenge::challenge))
+        .route("/challenge/sample", post(challenge::sample))
+        .route("/vfile/:fileKey", get(read_vfile))
+        .route("/file/:fileKey/seal", post(seal_file))
+        .route("/file/:fileKey/unseal", post(unseal_file))
+        .route("/file/:fileKey/reconstruct", post(reconstruct_file))
+        .route("/files", get(list_files_handler))
+        .route("/files/batch", post(retrieve_files_batch))
+        .route("/alias", post(set_alias))
+        .route(
+            "/chunk/:fileKey/:chunkHash",
+            get(retrieve_chunk).head(chunk_exists).patch(patch_chunk).delete(delete_chunk),
+        )
+        .route("/chunks/exists", post(chunks_exist))
+        .route("/chunk/raw/:fileKey/:chunkHash", get(retrieve_raw_chunk))
+        .route("/chunk/:fileKey/:chunkHash/cas", post(compare_and_swap_chunk))
+        .route("/upload/init", post(upload::init))
+        .route("/upload/:session", get(upload::status))
+        .route("/upload/:session/complete", post(upload::complete))
+        .route("/stats", get(usage::stats))
+        .route("/stats/hot", get(hot_files))
+        .route("/metrics", get(metrics_handler))
+        .route("/version", get(version_info))
+        .route("/health", get(health))
+        .route("/diagnostics", get(diagnostics))
+        .route("/events", get(notify::subscribe))
+        .route("/sync/full", get(full_sync))
+        .route("/admin/reload", post(reload_config))
+        .route("/admin/readonly", post(set_read_only))
+        .route("/admin/flush", post(flush_write_buffer))
+        .route("/admin/consistency", get(check_consistency))
+        .route("/admin/gc", post(collect_garbage_handler))
+        .route("/admin/dedup/migrate", post(migrate_dedup))
+        .route("/admin/export", get(export_database))
+        .route("/admin/reindex", get(reindex_progress).post(start_reindex))
+        .route("/admin/scrub/status", get(scrub::status))
+        .route("/admin/tokens", post(create_token))
+        .route("/admin/tokens/:id", delete(revoke_token));
+
+    if state.namespaces.name().is_none() {
+        app = app
+            .route("/admin/namespaces", get(namespace::list).post(namespace::create))
+            .route("/admin/namespaces/:name", delete(namespace::remove));
+    }
+
+    if config.gossip {
+        app = app.route("/gossip", get(gossip_view).post(gossip_exchange));
+    }
+
+    // Trang HTML chỉ bật khi được cấu hình, để deployment chỉ dùng API vẫn gọn
+    if config.html_index {
+        app = app.route("/", get(index_page));
+    }
+
+    // Route upload nhị phân cần giới hạn body lớn hơn mặc định (2MB) của axum
+    app = app
+        .route(
+            "/store/raw/:fileKey/:chunkHash",
+            post(store_raw).layer(DefaultBodyLimit::max(config.max_raw_upload)),
+        )
+        .route("/store/stream/:fileKey/:chunkHash", post(store_stream))
+        .route(
+            "/b64/store/raw/:encodedKey/:chunkHash",
+            post(store_raw_b64).layer(DefaultBodyLimit::max(config.max_raw_upload)),
+        )
+        .route("/b64/file/:encodedKey", get(retrieve_b64_file).delete(delete_b64_file))
+        .route(
+            "/upload/:session/:index",
+            put(upload::put_part).layer(DefaultBodyLimit::max(config.max_raw_upload)),
+        )
+        .route(
+            "/replicate",
+            post(replicate_chunk).layer(DefaultBodyLimit::max(config.max_raw_upload)),
+        )
+        .route("/admin/import", post(import_database).layer(DefaultBodyLimit::disable()));
+
+    // Kiểm tra token nằm trong middleware dịch lỗi để lỗi 401/403 cũng được dịch, và trong
+    // middleware metrics và span log của request để request bị từ chối vẫn được đếm và ghi log
+    let router = app
+        .into_router()
+        .layer(axum::middleware::from_fn_with_state(state.clone(), auth::enforce))
+        .layer(axum::middleware::from_fn_with_state(state.clone(), ratelimit::enforce))
+        .layer(axum::middleware::from_fn_with_state(state.clone(), metrics::track))
+        .layer(logging::trace_layer());
+    // Request của namespace khác được chuyển đi trước các middleware trên (xem module namespace)
+    let router = match state.namespaces.name() {
+        None => router.layer(axum::middleware::from_fn_with_state(state.clone(), namespace::dispatch)),
+        Some(_) => router,
+    };
+    with_middleware(router, state)
+}
+
+/// Gắn các middleware dùng chung cho mọi route rồi gắn state vào router
+pub fn with_middleware(router: Router<Arc<AppState>>, state: Arc<AppState>) -> Router {
+    // Handler bị panic sẽ trả về 500 thay vì đóng kết nối mà không có response.
+    // Middleware dịch lỗi nằm ngoài cùng để dịch cả response của panic.
+    router
+        .layer(CatchPanicLayer::custom(handle_panic))
+        .layer(axum::middleware::from_fn_with_state(state.clone(), error::localize))
+        .with_state(state)
+}
+
+
+// ## CÁC HANDLER XỬ LÝ REQUEST ##
+
+/// Handler cho việc LƯU TRỮ chunk mới. Trả về `201` kèm header `Location` là URL của chunk
+/// (`/file/:fileKey/chunk/:chunkHash`), hoặc `200` khi chunk giống hệt đã có sẵn và lần ghi
+/// không thay đổi gì. Body cho biết value có trỏ tới blob cùng nội dung đã có thay vì được
+/// lưu thêm không (`{"deduplicated": true}`, xem module dedup); khi lần ghi chỉ vào bộ đệm
+/// ghi, đó là trạng thái của blob lúc nhận request. Payload không hợp lệ trả về `400` với mọi
+/// trường lỗi trong `errors`, ví dụ `{"errors": [{"field": "chunkHash", "message": "..."}]}`.
+async fn store_chunk(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<StoreQuery>,
+    Json(mut payload): Json<StorePayload>,
+) -> Result<Response, ApiError> {
+    logging::record_file_key(&payload.file_key);
+    state.ensure_writable()?;
+    if state.config.load().base64_normalize {
+        payload.normalize_base64();
+    }
+    if let Err(errors) = payload.validate(&state.config.load()) {
+        return Ok(ApiError::InvalidFields.with_details(field_errors(errors)));
+    }
+    if let Some(algorithm) = state.config.load().verify_hash {
+        let data = STANDARD.decode(&payload.chunk_data).map_err(|_| ApiError::BadRequest)?;
+        let computed = algorithm.digest(&data);
+        if !integrity::matches(&payload.chunk_hash, &computed) {
+            tracing::warn!(
+                "chunkHash {} không khớp với hash {} {} của dữ liệu",
+                payload.chunk_hash,
+                algorithm.name(),
+                computed
+            );
+            return Err(ApiError::ChecksumMismatch);
+        }
+    }
+    payload.ensure_storable(&state)?;
+    let (file_key, chunk_hash, db_value) = payload.into_parts();
+    state.ensure_unsealed(&file_key)?;
+    let _permit = state.acquire_file(&file_key)?;
+    let (check_state, check_file, check_hash) = (state.clone(), file_key.clone(), chunk_hash.clone());
+    let (unchanged, db_value) = blocking(move || {
+        let unchanged = check_state.stores_same_chunk(&check_file, &check_hash, &db_value)?;
+        Ok((unchanged, db_value))
+    })
+    .await?;
+    let status = if unchanged { StatusCode::OK } else { StatusCode::CREATED };
+    let location = chunk_location(&file_key, &chunk_hash);
+
+    let capacity = state.config.load().write_buffer;
+    if capacity == 0 {
+        let deduplicated = save_chunk(&state, &file_key, &chunk_hash, db_value, query.overwrite).await?;
+        return Ok((status, location, Json(StoreChunkResponse { deduplicated })).into_response());
+    }
+
+    // Trả về ngay khi lần ghi đã vào bộ đệm; chỉ chờ ghi xuống sled khi bộ đệm đầy
+    let mut write = state.pending_write(&file_key, &chunk_hash, db_value)?;
+    write.set_overwrite(query.overwrite);
+    state.ensure_capacity(std::slice::from_ref(&write))?;
+    let deduplicated = match &write.blob {
+        Some(hash) => state.large.blobs.chunks.contains_key(hash).map_err(|e| {
+            tracing::error!("Lỗi khi đọc blob dùng chung: {}", e);
+            ApiError::Internal
+        })?,
+        None => false,
+    };
+    if state.write_buffer.push(write) >= capacity {
+        write_buffer::drain(&state).await?;
+    }
+    Ok((status, location, Json(StoreChunkResponse { deduplicated })).into_response())
+}
+
+// Header `Location` trỏ tới `GET /file/:fileKey/chunk/:chunkHash` của chunk vừa lưu
+fn chunk_location(file_key: &str, chunk_hash: &str) -> HeaderMap {
+    let url = format!("/file/{}/chunk/{}", path_segment(file_key), path_segment(chunk_hash));
+    let mut headers = HeaderMap::new();
+    headers.insert(header::LOCATION, HeaderValue::from_str(&url).expect("URL đã được mã hóa"));
+    headers
+}
+
+// Mã hóa phần trăm một thành phần đường dẫn, giữ nguyên các ký tự không cần mã hóa
+// (RFC 3986 "unreserved")
+fn path_segment(segment: &str) -> String {
+    let mut out = String::with_capacity(segment.len());
+    for &b in segment.as_bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~') {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+/// Handler LƯU NHIỀU chunk trong một request: ghi trong một transaction và flush một lần.
+/// Body là mảng JSON (`application/json`) cho batch nhỏ, hoặc NDJSON
+/// (`application/x-ndjson`, mỗi dòng một StorePayload) được parse dần theo luồng
+/// nên không phải giữ body thô và toàn bộ danh sách payload trong bộ nhớ cùng lúc.
+/// Response có `items` là kết quả của từng chunk. Mặc định một chunk không hợp lệ làm cả
+/// batch bị từ chối (`invalid_fields` kèm `errors` và vị trí `index` của chunk đó); với `?partial=true` chunk đó được đánh dấu `rejected` kèm mã lỗi và
+/// các chunk còn lại vẫn được ghi chung một transaction. Lỗi khi ghi (quota, xung đột)
+/// luôn áp dụng cho cả batch.
+async fn store_batch(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<StoreQuery>,
+    Query(batch): Query<BatchQuery>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Response, ApiError> {
+    state.ensure_writable()?;
+    let is_ndjson = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/x-ndjson"));
+
+    let mut writes = Vec::new();
+    let mut items = Vec::new();
+    // Một key xuất hiện nhiều lần chỉ được chấp nhận khi các bản giống hệt nhau, để lỗi
+    // của client không bị "lần ghi sau thắng" che mất
+    let mut seen: std::collections::HashMap<String, [u8; 32]> = Default::default();
+    // Lỗi làm cả batch bị từ chối, kèm chi tiết (key của chunk bị trùng, trường không hợp
+    // lệ) khi có
+    type Failure = (ApiError, Option<serde_json::Map<String, serde_json::Value>>);
+    let mut failure: Option<Failure> = None;
+    // Một suất ghi cho mỗi fileKey trong batch, giữ tới khi batch được ghi xong
+    let mut permits: std::collections::HashMap<String, FilePermit> = Default::default();
+    let limits = state.config.load_full();
+    let mut push = |mut payload: StorePayload| -> Result<(), StatusCode> {
+        if limits.base64_normalize {
+            payload.normalize_base64();
+        }
+        let mut item = BatchItemStatus {
+            file_key: payload.file_key.clone(),
+            chunk_hash: payload.chunk_hash.clone(),
+            status: "stored",
+            code: None,
+        };
+        let position = items.len();
+        let admit = || -> Result<Option<quota::PendingWrite>, Failure> {
+            if let Err(errors) = payload.validate(&limits) {
+                let mut details = field_errors(errors);
+                details.insert("index".into(), position.into());
+                return Err((ApiError::InvalidFields, Some(details)));
+            }
+            payload.ensure_storable(&state).map_err(|e| (e, None))?;
+            let digest = payload.digest();
+            let (file_key, chunk_hash, db_value) = payload.into_parts();
+            state.ensure_unsealed(&file_key).map_err(|e| (e, None))?;
+            if !permits.contains_key(&file_key) {
+                let permit = state.acquire_file(&file_key).map_err(|e| (e, None))?;
+                permits.insert(file_key.clone(), permit);
+            }
+            let db_key = key::encode(&file_key, &chunk_hash);
+            match seen.get(&db_key) {
+                Some(previous) if *previous == digest => return Ok(None), // Bản trùng giống hệt, bỏ qua
+                Some(_) => {
+                    tracing::warn!("Batch chứa chunk {} hai lần với dữ liệu khác nhau", db_key);
+                    let mut details = serde_json::Map::new();
+                    details.insert("key".into(), db_key.into());
+                    return Err((ApiError::DuplicateChunk, Some(details)));
+                }
+                None => {
+                    seen.insert(db_key, digest);
+                }
+            }
+            let mut write = state.pending_write(&file_key, &chunk_hash, db_value).map_err(|e| (e.into(), None))?;
+            write.set_overwrite(query.overwrite);
+            Ok(Some(write))
+        };
+        match admit() {
+            Ok(Some(write)) => writes.push(write),
+            Ok(None) => item.status = "duplicate",
+            Err((e, _)) if batch.partial => {
+                item.status = "rejected";
+                item.code = Some(e.code());
+            }
+            Err((e, key)) => {
+                let status = e.status();
+                failure = Some((e, key));
+                return Err(status);
+            }
+        }
+        items.push(item);
+        Ok(())
+    };
+
+    let parsed = parse_batch(&state, is_ndjson, body, &mut push).await;
+    match failure {
+        Some((e, Some(details))) => return Ok(e.with_details(details)),
+        Some((e, None)) => return Err(e),
+        None => parsed?,
+    }
+
+    let stored = writes.len();
+    tracing::info!("-> Đang lưu batch {} chunks", stored);
+    state.ensure_capacity(&writes)?;
+
+    commit_batch(&state, writes).await?;
+
+    Ok(Json(BatchStoreResponse { stored, items }).into_response())
+}
+
+/// Ghi các chunk của /store/batch. Batch có ít nhất STORAGE_BATCH_PARALLEL_MIN_CHUNKS
+/// chunk được chia thành STORAGE_BATCH_PARALLELISM phần liên tiếp, mỗi phần ghi trong một
+/// transaction riêng trên một blocking worker, rồi flush một lần cho cả batch. Khi đó mỗi
+/// phần là nguyên tử nhưng cả batch thì không: nếu một phần thất bại (vượt quota, lỗi
+/// database) thì các phần khác có thể đã được ghi, response vẫn là lỗi và client cần gửi
+/// lại cả batch. Vì gửi lại sẽ gặp các key đã ghi, batch không được chia khi
+/// STORAGE_OVERWRITE_MODE=reject để lần gửi lại không bị `409`.
+async fn commit_batch(state: &Arc<AppState>, mut writes: Vec<quota::PendingWrite>) -> Result<(), StatusCode> {
+    let config = state.config.load();
+    let parallelism = config.batch_parallelism.min(writes.len());
+    if parallelism <= 1
+        || writes.len() < config.batch_parallel_min_chunks
+        || config.overwrite_mode == quota::OverwriteMode::Reject
+    {
+        let write_state = state.clone();
+        return blocking(move || write_state.commit_writes(&writes)).await.map(drop);
+    }
+
+    // Chia thành các phần liên tiếp để chunk của cùng một file thường nằm chung một phần
+    let part_size = writes.len().div_ceil(parallelism);
+    let mut workers = Vec::with_capacity(parallelism);
+    while !writes.is_empty() {
+        let rest = writes.split_off(part_size.min(writes.len()));
+        let part = std::mem::replace(&mut writes, rest);
+        let write_state = state.clone();
+        workers.push(tokio::task::spawn_blocking(move || write_state.apply_writes(&part).map(|_| part)));
+    }
+
+    let mut result = Ok(());
+    for worker in workers {
+        match worker.await {
+            Ok(Ok(part)) => writes.extend(part),
+            Ok(Err(status)) => result = result.and(Err(status)),
+            Err(e) => {
+                tracing::error!("Lỗi khi chạy tác vụ ghi batch song song: {}", e);
+                result = result.and(Err(StatusCode::INTERNAL_SERVER_ERROR));
+            }
+        }
+    }
+    // Vẫn flush các phần đã ghi được khi có phần thất bại
+    let write_state = state.clone();
+    let flushed = blocking(move || write_state.finish_writes(&writes)).await;
+    result.and(flushed)
+}
+
+/// Đọc body của /store/batch (mảng JSON hoặc NDJSON) và gọi `push` cho từng payload
+async fn parse_batch(
+    state: &AppState,
+    is_ndjson: bool,
+    body: Body,
+    push: &mut impl FnMut(StorePayload) -> Result<(), StatusCode>,
+) -> Result<(), ApiError> {
+    if is_ndjson {
+        // Mỗi dòng chứa một chunk Base64 nên không dài quá ~4/3 giới hạn chunk
+        let max_line = state.config.load().max_chunk_size * 2 + 64 * 1024;
+        let mut stream = body.into_data_stream();
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut line_no = 0;
+        loop {
+            let frame = tokio_stream::StreamExt::next(&mut stream).await;
+            let done = frame.is_none();
+            match frame {
+                Some(Ok(bytes)) => buffer.extend_from_slice(&bytes),
+                Some(Err(e)) => {
+                    tracing::error!("Lỗi khi đọc body batch: {}", e);
+                    return Err(ApiError::BadRequest);
+                }
+                None => buffer.push(b'\n'), // Dòng cuối có thể không có ký tự xuống dòng
+            }
+
+            while let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=pos).collect();
+                line_no += 1;
+                if line.iter().all(u8::is_ascii_whitespace) {
+                    continue;
+                }
+                let payload: StorePayload = serde_json::from_slice(&line).map_err(|e| {
+                    tracing::warn!("Dòng {} của batch không hợp lệ: {}", line_no, e);
+                    ApiError::BadRequest
+                })?;
+                push(payload)?;
+            }
+            if buffer.len() > max_line {
+                tracing::warn!("Dòng {} của batch quá dài", line_no + 1);
+                return Err(ApiError::PayloadTooLarge);
+            }
+            if done {
+                break;
+            }
+        }
+    } else {
+        let bytes = axum::body::to_bytes(body, state.config.load().max_batch_bytes)
+            .await
+            .map_err(|_| ApiError::PayloadTooLarge)?;
+        let payloads: Vec<StorePayload> = serde_json::from_slice(&bytes).map_err(|e| {
+            tracing::warn!("Batch không hợp lệ: {}", e);
+            ApiError::BadRequest
+        })?;
+        for payload in payloads {
+            push(payload)?;
+        }
+    }
+    Ok(())
+}
+
+/// chunkHash do server tính cho dữ liệu: `0x` cùng SHA-256 dạng hex chữ thường, giống
+/// hash của các phần khi `/store/raw` chia nhỏ blob
+fn content_hash(data: &[u8]) -> String {
+    format!("0x{}", hex::encode(Sha256::digest(data)))
+}
+
+// chunkHash dùng làm key cho `?autohash=true`: hash server tính từ body. chunkHash trên
+// đường dẫn phải là `auto` hoặc trùng với hash đó (không phân biệt hoa thường), khác thì
+// trả về `400` `hash_mismatch`.
+fn auto_chunk_hash(chunk_hash: &str, body: &[u8]) -> Result<String, ApiError> {
+    let computed = content_hash(body);
+    if chunk_hash != "auto" && !chunk_hash.eq_ignore_ascii_case(&computed) {
+        tracing::warn!("chunkHash {} không khớp với hash {} của dữ liệu", chunk_hash, computed);
+        return Err(ApiError::HashMismatch);
+    }
+    Ok(computed)
+}
+
+/// Handler LƯU chunk dạng nhị phân (application/octet-stream), không cần Base64.
+/// Với `?split=true`, blob lớn hơn giới hạn chunk sẽ được chia thành các chunk
+/// `split_chunk_size` byte, mỗi chunk được hash bằng SHA-256 và lưu với index tăng dần
+/// bắt đầu từ `startIndex`. Khi đó chunkHash trên đường dẫn không được dùng làm key.
+/// Với `?autohash=true`, chunk được lưu dưới hash server tính từ body (xem
+/// `content_hash`) và response trả về hash đó.
+async fn store_raw(
+    State(state): State<Arc<AppState>>,
+    Path((file_key, chunk_hash)): Path<(String, String)>,
+    Query(query): Query<RawStoreQuery>,
+    body: Bytes,
+) -> Result<Json<RawStoreResponse>, ApiError> {
+    state.ensure_writable()?;
+    state.ensure_unsealed(&file_key)?;
+    let first_index = if query.split { Some(query.start_index) } else { query.index };
+    state.ensure_content_type_allowed(None, first_index, &body[..body.len().min(mime::SNIFF_LEN)])?;
+    let chunk_hash = if query.autohash { auto_chunk_hash(&chunk_hash, &body)? } else { chunk_hash };
+    state.ensure_valid_key(&file_key, &chunk_hash)?;
+    let _permit = state.acquire_file(&file_key)?;
+    let config = state.config.load();
+
+    if body.len() <= config.max_chunk_size {
+        let db_value = StoredChunkValue::raw(STANDARD.encode(&body), query.index);
+        save_chunk(&state, &file_key, &chunk_hash, db_value, query.overwrite).await?;
+        return Ok(Json(RawStoreResponse {
+            file_key,
+            chunk_hashes: vec![chunk_hash],
+        }));
+    }
+
+    if !query.split {
+        return Err(ApiError::PayloadTooLarge);
+    }
+
+    tracing::info!(
+        "-> Đang chia blob {} byte thành các chunk {} byte cho fileKey: {}",
+        body.len(),
+        config.split_chunk_size,
+        file_key
+    );
+
+    let mut writes = Vec::new();
+    let mut chunk_hashes = Vec::new();
+    for (i, piece) in body.chunks(config.split_chunk_size).enumerate() {
+        let piece_hash = content_hash(piece);
+        let db_value = StoredChunkValue::raw(STANDARD.encode(piece), Some(query.start_index + i as u64));
+        let mut write = state.pending_write(&file_key, &piece_hash, db_value)?;
+        write.set_overwrite(query.overwrite);
+        writes.push(write);
+        chunk_hashes.push(piece_hash);
+    }
+
+    // Tất cả các phần được ghi trong một transaction cùng với bộ đếm quota
+    state.ensure_capacity(&writes)?;
+    let write_state = state.clone();
+    blocking(move || write_state.commit_writes(&writes)).await?;
+
+    tracing::info!("   -> Đã lưu {} chunks", chunk_hashes.len());
+
+    Ok(Json(RawStoreResponse {
+        file_key,
+        chunk_hashes,
+    }))
+}
+
+/// Header chứa hash SHA-256 mong đợi của body gửi tới /store/stream
+const EXPECTED_HASH: &str = "x-expected-hash";
+
+/// Handler LƯU một chunk lớn từ body nhị phân được đọc dần: mỗi phần của body được đưa
+/// vào SHA-256 và mã hóa Base64 ngay khi tới nên server không giữ cùng lúc body gốc, bản
+/// Base64 và payload JSON như `/store`. Header `X-Expected-Hash` (hex, có hoặc không có
+/// `0x`) là bắt buộc; body có hash khác thì không có gì được ghi và trả về `400`
+/// `hash_mismatch`. Body vượt STORAGE_MAX_CHUNK_SIZE bị dừng đọc ngay và trả về `413`.
+/// Thành công trả về `201` kèm `Location` và số byte đã lưu. Record vẫn cần toàn bộ value
+/// khi ghi vào sled nên bộ nhớ dùng cỡ bản Base64 của chunk.
+async fn store_stream(
+    State(state): State<Arc<AppState>>,
+    Path((file_key, chunk_hash)): Path<(String, String)>,
+    Query(query): Query<StreamStoreQuery>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Response, ApiError> {
+    use tokio_stream::StreamExt as _;
+
+    state.ensure_writable()?;
+    state.ensure_valid_key(&file_key, &chunk_hash)?;
+    let expected = headers
+        .get(EXPECTED_HASH)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim().trim_start_matches("0x").to_ascii_lowercase())
+        .ok_or(ApiError::BadRequest)?;
+    let max_chunk_size = state.config.load().max_chunk_size;
+    let declared = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+    if declared.is_some_and(|len| len > max_chunk_size) {
+        return Err(ApiError::PayloadTooLarge);
+    }
+    state.ensure_unsealed(&file_key)?;
+    let _permit = state.acquire_file(&file_key)?;
+
+    let mut hasher = Sha256::new();
+    let mut encoded = String::with_capacity(declared.map_or(0, |len| len.div_ceil(3) * 4));
+    // Các byte chưa đủ một nhóm 3 byte để mã hóa Base64 và vài byte đầu để đoán kiểu nội dung
+    let mut pending = Vec::new();
+    let mut head = Vec::new();
+    let mut received = 0usize;
+    let mut stream = body.into_data_stream();
+    while let Some(frame) = stream.next().await {
+        let frame = frame.map_err(|e| {
+            tracing::error!("Lỗi khi đọc body của /store/stream cho {}: {}", file_key, e);
+            ApiError::BadRequest
+        })?;
+        received += frame.len();
+        if received > max_chunk_size {
+            return Err(ApiError::PayloadTooLarge);
+        }
+        hasher.update(&frame);
+        if head.len() < mime::SNIFF_LEN {
+            head.extend_from_slice(&frame[..frame.len().min(mime::SNIFF_LEN - head.len())]);
+        }
+        pending.extend_from_slice(&frame);
+        let whole = pending.len() / 3 * 3;
+        STANDARD.encode_string(&pending[..whole], &mut encoded);
+        pending.drain(..whole);
+    }
+    STANDARD.encode_string(&pending, &mut encoded);
+
+    let computed = hex::encode(hasher.finalize());
+    if computed != expected {
+        tracing::warn!("Body của {}:{} có hash 0x{}, khác hash mong đợi 0x{}", file_key, chunk_hash, computed, expected);
+        return Err(ApiError::HashMismatch);
+    }
+    state.ensure_content_type_allowed(None, query.index, &head)?;
+
+    let db_value = StoredChunkValue::raw(encoded, query.index);
+    save_chunk(&state, &file_key, &chunk_hash, db_value, query.overwrite).await?;
+    tracing::info!("-> Đã lưu chunk {} byte qua /store/stream cho fileKey: {}", received, file_key);
+
+    let location = chunk_location(&file_key, &chunk_hash);
+    let response = StreamStoreResponse {
+        file_key,
+        chunk_hash,
+        bytes: received as u64,
+    };
+    Ok((StatusCode::CREATED, location, Json(response)).into_response())
+}
+
+/// Handler cho việc LẤY TẤT CẢ chunk của một file. File lớn có thể lấy theo trang với
+/// `?limit=` (số chunk mỗi trang), `?after=` (giá trị `nextCursor` của trang trước) và
+/// `?offset=`; response có `nextCursor` khi còn trang sau. Khi phân trang, server chỉ đọc
+/// value của các chunk trong trang (xem `load_file_page`).
+async fn retrieve_file_chunks(
+    State(state): State<Arc<AppState>>,
+    Path(file_key): Path<String>,
+    Query(read): Query<ReadQuery>,
+    Query(format): Query<DataEncodingQuery>,
+    Query(page): Query<PageQuery>,
+    request_headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let file_key = state.resolve_file_key(file_key)?;
+    let _permit = state.acquire_read()?;
+    sync_for_read(&state, read.consistency).await?;
+    
+    tracing::info!("<- Đang truy vấn tất cả chunk cho fileKey: {}", file_key);
+    let accepted = compress::accepted(&request_headers);
+    let manifest = state.manifest(&file_key)?;
+    let (page, mut timings) = if page.is_paged() {
+        load_file_page(&state, &file_key, &accepted, &page, manifest.as_ref()).await?
+    } else {
+        let (mut chunks, timings) = match read.consistency {
+            Consistency::Weak => load_file_chunks_shared(&state, &file_key, &accepted).await?,
+            // Lượt quét đang chạy có thể đã bắt đầu trước lần flush của request này
+            Consistency::Strong => load_file_chunks_timed(&state, &file_key, &accepted).await?,
+        };
+        let last_modified = chunks.iter().filter_map(|c| c.stored_at).max();
+        if let Some(manifest) = &manifest {
+            manifest.sort(&mut chunks, |chunk| key::decode(chunk.key.as_bytes()).map(|k| k.chunk_hash));
+        }
+        let total = chunks.len();
+        (FilePage { chunks, total, last_modified, next_cursor: None }, timings)
+    };
+    let FilePage { mut chunks, total, last_modified, next_cursor } = page;
+    // Trả `304` trước mọi bước chỉ cần cho body (hex, giới hạn kích thước)
+    let mut headers = cache_headers(&state.config.load());
+    if let Some(last_modified) = last_modified {
+        if let Some(since) = request_headers
+            .get(header::IF_MODIFIED_SINCE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| httpdate::parse_http_date(v).ok())
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            && last_modified <= since.as_secs()
+        {
+            return Ok((StatusCode::NOT_MODIFIED, headers).into_response());
+        }
+
+        let date = std::time::UNIX_EPOCH + std::time::Duration::from_secs(last_modified);
+        if let Ok(value) = HeaderValue::from_str(&httpdate::fmt_http_date(date)) {
+            headers.insert(header::LAST_MODIFIED, value);
+        }
+    }
+
+    if format.encoding == DataEncoding::Hex {
+        timings.measure(|t| &mut t.encode, || {
+            for chunk in &mut chunks {
+                let bytes = STANDARD.decode(&chunk.value).map_err(|_| ApiError::Internal)?;
+                chunk.value = format!("0x{}", hex::encode(bytes));
+            }
+            Ok::<_, ApiError>(())
+        })?;
+    }
+    if !chunks.is_empty() {
+        state.record_read(&file_key, chunks.len());
+    }
+
+    tracing::info!("   -> Tìm thấy {} chunks, trả về {}", total, chunks.len());
+    let mut budget = ResponseBudget::new(state.config.load().max_response_bytes);
+    budget.take(&chunks)?;
+
+    // Tạo response cuối cùng
+    let response = FileChunksResponse {
+        file_key,
+        chunks,
+        next_cursor,
+    };
+
+    if !state.config.load().server_timing {
+        return Ok((headers, Json(response)).into_response());
+    }
+    // Tự serialize để đo được thời gian của bước này
+    let body = timings.measure(|t| &mut t.serialize, || serde_json::to_vec(&response)).map_err(|e| {
+        tracing::error!("Lỗi khi serialize response: {}", e);
+        ApiError::Internal
+    })?;
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    if let Some(value) = timings.header_value() {
+        headers.insert("server-timing", value);
+    }
+    Ok((headers, body).into_response())
+}
+
+/// Handler LIỆT KÊ các fileKey đang có chunk cùng số chunk và tổng số byte của từng file,
+/// đọc từ tree `file_chunks` (xem `cardinality::list`) thay vì quét các shard. Hỗ trợ
+/// `?prefix=`, `?limit=` và `?after=` (giá trị `nextCursor` của trang trước).
+async fn list_files_handler(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<FilesQuery>,
+) -> Result<Json<FilesResponse>, ApiError> {
+    let limit = query.limit.unwrap_or(100).min(state.config.load().max_list_files);
+    if limit == 0 {
+        return Err(ApiError::BadRequest);
+    }
+    let _permit = state.acquire_read()?;
+
+    let list_state = state.clone();
+    let (files, next_cursor) = blocking(move || {
+        cardinality::list(&list_state.files.tree, &query.prefix, query.after.as_deref(), limit).map_err(|e| {
+            tracing::error!("Lỗi khi liệt kê fileKey: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+    })
+    .await?;
+    tracing::info!("<- Đã liệt kê {} fileKey", files.len());
+
+    Ok(Json(FilesResponse { files, next_cursor }))
+}
+
+/// Handler LẤY chunk của NHIỀU file trong một request, tránh N lượt round trip
+async fn retrieve_files_batch(
+    State(state): State<Arc<AppState>>,
+    Query(read): Query<ReadQuery>,
+    request_headers: HeaderMap,
+    Json(payload): Json<BatchFilesPayload>,
+) -> Result<(HeaderMap, Json<BatchFilesResponse>), ApiError> {
+    if payload.file_keys.len() > state.config.load().max_batch_files {
+        return Err(ApiError::PayloadTooLarge);
+    }
+    let _permit = state.acquire_read()?;
+    sync_for_read(&state, read.consistency).await?;
+
+    tracing::info!("<- Đang truy vấn chunk cho {} fileKey", payload.file_keys.len());
+    let mut files = std::collections::BTreeMap::new();
+    let mut not_found = Vec::new();
+    let accepted = compress::accepted(&request_headers);
+    let mut budget = ResponseBudget::new(state.config.load().max_response_bytes);
+
+    // fileKey bị lặp lại chỉ được đọc và báo cáo một lần
+    let mut seen = std::collections::HashSet::new();
+    let file_keys: Vec<String> = payload.file_keys.into_iter().filter(|k| seen.insert(k.clone())).collect();
+    for file_key in file_keys {
+        let resolved = state.resolve_file_key(file_key.clone())?;
+        let chunks = load_file_chunks(&state, &resolved, &accepted).await?;
+        if chunks.is_empty() {
+            not_found.push(file_key);
+        } else {
+            budget.take(&chunks)?;
+            state.record_read(&resolved, chunks.len());
+            files.insert(file_key, chunks);
+        }
+    }
+
+    tracing::info!("   -> Tìm thấy {} file, {} không tồn tại", files.len(), not_found.len());
+
+    Ok((cache_headers(&state.config.load()), Json(BatchFilesResponse { files, not_found })))
+}
+
+/// Handler LẤY MỘT SỐ chunk của file theo danh sách chunkHash, ví dụ để lấy lại đúng
+/// những chunk còn thiếu khi đồng bộ giữa các node. Mỗi chunk được đọc bằng một lần `get`
+/// theo key thay vì quét cả file. chunkHash bị lặp chỉ được trả về một lần.
+async fn retrieve_selected_chunks(
+    State(state): State<Arc<AppState>>,
+    Path(file_key): Path<String>,
+    Query(read): Query<ReadQuery>,
+    Query(format): Query<DataEncodingQuery>,
+    Json(payload): Json<ChunkHashesPayload>,
+) -> Result<(HeaderMap, Json<SelectedChunksResponse>), ApiError> {
+    if payload.chunk_hashes.len() > state.config.load().max_chunk_hashes {
+        return Err(ApiError::PayloadTooLarge);
+    }
+    let file_key = state.resolve_file_key(file_key)?;
+    if !payload.chunk_hashes.iter().all(|hash| key::is_valid(&file_key, hash)) {
+        return Err(ApiError::BadRequest);
+    }
+    let _permit = state.acquire_read()?;
+    sync_for_read(&state, read.consistency).await?;
+
+    tracing::info!("<- Đang truy vấn {} chunk của fileKey: {}", payload.chunk_hashes.len(), file_key);
+    let read_state = state.clone();
+    let read_key = file_key.clone();
+    let (chunks, missing) = blocking(move || {
+        let tree = read_state.tree_for(&read_key);
+        let mut seen = std::collections::HashSet::new();
+        let (mut chunks, mut missing) = (Vec::new(), Vec::new());
+        for chunk_hash in payload.chunk_hashes {
+            if !seen.insert(chunk_hash.clone()) {
+                continue;
+            }
+            let db_key = key::encode(&read_key, &chunk_hash);
+            match read_chunk(tree, &read_state.large, db_key.as_bytes()) {
+                Ok(Some((bytes, record))) => {
+                    let mut chunk = Chunk::from_record(db_key, record);
+                    chunk.value = match format.encoding {
+                        DataEncoding::Base64 => STANDARD.encode(&bytes),
+                        DataEncoding::Hex => format!("0x{}", hex::encode(&bytes)),
+                    };
+                    chunk.encoding = compress::Codec::Identity;
+                    chunks.push(chunk);
+                }
+                Ok(None) => missing.push(chunk_hash),
+                Err(e) => {
+                    tracing::error!("Lỗi khi đọc chunk {}: {}", db_key, e);
+                    return Err(StatusCode::INTERNAL_SERVER_ERROR);
+                }
+            }
+        }
+        Ok((chunks, missing))
+    })
+    .await?;
+    if !chunks.is_empty() {
+        state.record_read(&file_key, chunks.len());
+    }
+
+    tracing::info!("   -> Tìm thấy {} chunks, {} không tồn tại", chunks.len(), missing.len());
+    ResponseBudget::new(state.config.load().max_response_bytes).take(&chunks)?;
+    Ok((
+        cache_headers(&state.config.load()),
+        Json(SelectedChunksResponse {
+            file_key,
+            chunks,
+            missing,
+        }),
+    ))
+}
+
+/// Handler LẤY MỘT chunk của file theo chunkHash, là URL trong header `Location` mà
+/// /store trả về. Trả về `404` `chunk_not_found` khi chunk không tồn tại (ở node này, và ở
+/// mọi peer khi bật STORAGE_READ_FALLTHROUGH, xem module replication).
+async fn retrieve_chunk(
+    State(state): State<Arc<AppState>>,
+    Path((file_key, chunk_hash)): Path<(String, String)>,
+    Query(read): Query<ReadQuery>,
+    Query(format): Query<DataEncodingQuery>,
+    headers: HeaderMap,
+    uri: axum::http::Uri,
+) -> Result<Response, ApiError> {
+    let file_key = state.resolve_file_key(file_key)?;
+    if !key::is_valid(&file_key, &chunk_hash) {
+        return Err(ApiError::BadRequest);
+    }
+    let _permit = state.acquire_read()?;
+    sync_for_read(&state, read.consistency).await?;
+
+    let read_state = state.clone();
+    let db_key = key::encode(&file_key, &chunk_hash);
+    let lookup_key = db_key.clone();
+    let chunk = blocking(move || match read_state.read_chunk(db_key.as_bytes()) {
+        Ok(Some((bytes, record))) => {
+            let mut chunk = Chunk::from_record(db_key, record);
+            chunk.value = match format.encoding {
+                DataEncoding::Base64 => STANDARD.encode(&bytes),
+                DataEncoding::Hex => format!("0x{}", hex::encode(&bytes)),
+            };
+            chunk.encoding = compress::Codec::Identity;
+            Ok(Some(chunk))
+        }
+        Ok(None) => Ok(None),
+        Err(e) => {
+            tracing::error!("Lỗi khi đọc chunk {}: {}", db_key, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    })
+    .await?;
+
+    let Some(chunk) = chunk else {
+        // Request do peer chuyển tới thì không hỏi tiếp, tránh vòng lặp giữa các node
+        if !state.config.load().read_fallthrough || headers.contains_key(replication::FORWARDED_HEADER) {
+            return Err(ApiError::ChunkNotFound);
+        }
+        let (body, peer) = replication::fetch_from_peers(&state, &lookup_key, uri.path(), uri.query())
+            .await
+            .ok_or(ApiError::ChunkNotFound)?;
+        tracing::info!("   -> Chunk {} lấy từ peer {}", lookup_key, peer);
+        return Ok(Json(body).into_response());
+    };
+    state.record_read(&file_key, 1);
+
+    ResponseBudget::new(state.config.load().max_response_bytes).take(std::slice::from_ref(&chunk))?;
+    Ok((cache_headers(&state.config.load()), Json(chunk)).into_response())
+}
+
+/// Handler KIỂM TRA một chunk có tồn tại không mà không trả về dữ liệu
+/// (`HEAD /chunk/:fileKey/:chunkHash`): `200` kèm `X-Chunk-Size` (số byte dữ liệu gốc) và
+/// `X-Chunk-Index` (nếu có), hoặc `404`. Chỉ record cũ chưa lưu kích thước mới phải đọc cả
+/// chunk để đo.
+async fn chunk_exists(
+    State(state): State<Arc<AppState>>,
+    Path((file_key, chunk_hash)): Path<(String, String)>,
+) -> Result<Response, ApiError> {
+    let file_key = state.resolve_file_key(file_key)?;
+    if !key::is_valid(&file_key, &chunk_hash) {
+        return Err(ApiError::BadRequest);
+    }
+    let _permit = state.acquire_read()?;
+
+    let read_state = state.clone();
+    let db_key = key::encode(&file_key, &chunk_hash);
+    let (size, index) = blocking(move || {
+        let db_error = |e: std::io::Error| {
+            tracing::error!("Lỗi khi đọc chunk {}: {}", db_key, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        };
+        let Some(record) = read_state.chunk_record(db_key.as_bytes()).map_err(db_error)? else {
+            return Ok(None);
+        };
+        let size = match record.size {
+            Some(size) => size,
+            None => read_state
+                .read_chunk(db_key.as_bytes())
+                .map_err(db_error)?
+                .map_or(0, |(bytes, _)| bytes.len() as u64),
+        };
+        Ok(Some((size, record.index)))
+    })
+    .await?
+    .ok_or(ApiError::ChunkNotFound)?;
+
+    let mut headers = cache_headers(&state.config.load());
+    headers.insert("x-chunk-size", HeaderValue::from(size));
+    if let Some(index) = index {
+        headers.insert("x-chunk-index", HeaderValue::from(index));
+    }
+    Ok(headers.into_response())
+}
+
+/// Handler KIỂM TRA NHIỀU chunk của một file có ở node này không, để client chỉ upload lại
+/// những chunk còn thiếu. Không đọc dữ liệu chunk; tối đa STORAGE_MAX_CHUNK_HASHES chunkHash
+/// mỗi request.
+async fn chunks_exist(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<ChunksExistPayload>,
+) -> Result<Json<ChunksExistResponse>, ApiError> {
+    if payload.chunk_hashes.len() > state.config.load().max_chunk_hashes {
+        return Err(ApiError::PayloadTooLarge);
+    }
+    let file_key = state.resolve_file_key(payload.file_key)?;
+    if !payload.chunk_hashes.iter().all(|hash| key::is_valid(&file_key, hash)) {
+        return Err(ApiError::BadRequest);
+    }
+    let _permit = state.acquire_read()?;
+
+    let read_state = state.clone();
+    let read_key = file_key.clone();
+    let (present, missing) = blocking(move || {
+        let mut present = Vec::with_capacity(payload.chunk_hashes.len());
+        let mut missing = Vec::new();
+        let mut seen = std::collections::HashSet::new();
+        for chunk_hash in payload.chunk_hashes {
+            let db_key = key::encode(&read_key, &chunk_hash);
+            let found = read_state.chunk_record(db_key.as_bytes()).map_err(|e| {
+                tracing::error!("Lỗi khi đọc chunk {}: {}", db_key, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+            present.push(found.is_some());
+            if found.is_none() && seen.insert(chunk_hash.clone()) {
+                missing.push(chunk_hash);
+            }
+        }
+        Ok((present, missing))
+    })
+    .await?;
+
+    tracing::info!("<- Kiểm tra {} chunk của fileKey {}: thiếu {}", present.len(), file_key, missing.len());
+    Ok(Json(ChunksExistResponse {
+        file_key,
+        present,
+        missing,
+    }))
+}
+
+/// Handler trả về dữ liệu gốc của một chunk dưới dạng nhị phân, không bọc JSON và không
+/// mã hóa Base64. `Content-Type` là kiểu nội dung đã lưu cùng chunk (mặc định
+/// `application/octet-stream`), index của chunk (nếu có) nằm trong header `X-Chunk-Index`.
+async fn retrieve_raw_chunk(
+    State(state): State<Arc<AppState>>,
+    Path((file_key, chunk_hash)): Path<(String, String)>,
+    Query(read): Query<ReadQuery>,
+) -> Result<Response, ApiError> {
+    let file_key = state.resolve_file_key(file_key)?;
+    if !key::is_valid(&file_key, &chunk_hash) {
+        return Err(ApiError::BadRequest);
+    }
+    let _permit = state.acquire_read()?;
+    sync_for_read(&state, read.consistency).await?;
+
+    let read_state = state.clone();
+    let db_key = key::encode(&file_key, &chunk_hash);
+    let (bytes, record) = blocking(move || {
+        read_state.read_chunk(db_key.as_bytes()).map_err(|e| {
+            tracing::error!("Lỗi khi đọc chunk {}: {}", db_key, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+    })
+    .await?
+    .ok_or(ApiError::ChunkNotFound)?;
+    state.record_read(&file_key, 1);
+
+    let mut headers = cache_headers(&state.config.load());
+    let content_type = record.content_type.as_deref().unwrap_or("application/octet-stream");
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_str(&header_safe(content_type)).unwrap_or(HeaderValue::from_static("application/octet-stream")),
+    );
+    if let Some(index) = record.index {
+        headers.insert("x-chunk-index", HeaderValue::from(index));
+    }
+    Ok((headers, bytes).into_response())
+}
+
+// Query cho /verify/:fileKey/:chunkHash
+#[derive(Deserialize)]
+struct VerifyQuery {
+    algorithm: Option<integrity::HashAlgorithm>,
+}
+
+// Kết quả của /verify/:fileKey/:chunkHash
+#[derive(Serialize)]
+struct VerifyResponse {
+    #[serde(rename = "fileKey")]
+    file_key: String,
+    #[serde(rename = "chunkHash")]
+    chunk_hash: String,
+    algorithm: &'static str,
+    // Hash tính lại từ dữ liệu đang lưu
+    computed: String,
+    valid: bool,
+}
+
+/// Handler KIỂM TRA lại dữ liệu đã lưu của một chunk: tính lại hash và so với chunkHash
+/// (xem module integrity). Chunk không có trả về `404`.
+async fn verify_chunk(
+    State(state): State<Arc<AppState>>,
+    Path((file_key, chunk_hash)): Path<(String, String)>,
+    Query(query): Query<VerifyQuery>,
+) -> Result<Json<VerifyResponse>, ApiError> {
+    let file_key = state.resolve_file_key(file_key)?;
+    if !key::is_valid(&file_key, &chunk_hash) {
+        return Err(ApiError::BadRequest);
+    }
+    let algorithm = query.algorithm.or(state.config.load().verify_hash).unwrap_or_default();
+    let _permit = state.acquire_read()?;
+
+    let read_state = state.clone();
+    let db_key = key::encode(&file_key, &chunk_hash);
+    let computed = blocking(move || match read_state.read_chunk(db_key.as_bytes()) {
+        Ok(found) => Ok(found.map(|(bytes, _)| algorithm.digest(&bytes))),
+        Err(e) => {
+            tracing::error!("Lỗi khi đọc chunk {}: {}", db_key, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    })
+    .await?
+    .ok_or(ApiError::ChunkNotFound)?;
+
+    let valid = integrity::matches(&chunk_hash, &computed);
+    if !valid {
+        tracing::error!("⚠️  Dữ liệu của chunk {}:{} có hash {}, có thể đã bị hỏng", file_key, chunk_hash, computed);
+    }
+    Ok(Json(VerifyResponse {
+        file_key,
+        chunk_hash,
+        algorithm: algorithm.name(),
+        computed,
+        valid,
+    }))
+}
+
+/// Handler LỌC chunk của file theo metadata: body là các cặp khóa/giá trị mà metadata của
+/// chunk phải có đủ, ví dụ `{"tag":"thumbnail"}` (body rỗng `{}` khớp mọi chunk). Không
+/// có index theo metadata nên handler quét toàn bộ prefix của file và lọc từng record:
+/// chi phí O(n) theo số chunk của file dù chỉ vài chunk khớp, nhưng chỉ chunk khớp mới
+/// được giải mã dữ liệu. Trả về `404` khi file không có chunk nào, danh sách rỗng khi file
+/// có chunk nhưng không chunk nào khớp.
+async fn query_chunks(
+    State(state): State<Arc<AppState>>,
+    Path(file_key): Path<String>,
+    Query(read): Query<ReadQuery>,
+    Query(format): Query<DataEncodingQuery>,
+    Json(predicate): Json<std::collections::HashMap<String, String>>,
+) -> Result<(HeaderMap, Json<FileChunksResponse>), ApiError> {
+    let file_key = state.resolve_file_key(file_key)?;
+    let _permit = state.acquire_read()?;
+    sync_for_read(&state, read.consistency).await?;
+
+    tracing::info!("<- Đang lọc chunk theo metadata {:?} của fileKey: {}", predicate, file_key);
+    let read_state = state.clone();
+    let read_key = file_key.clone();
+    let (chunks, scanned) = blocking(move || {
+        let tree = read_state.tree_for(&read_key);
+        let read_error = |e: std::io::Error| {
+            tracing::error!("Lỗi khi đọc chunk của fileKey {}: {}", read_key, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        };
+        let (mut chunks, mut scanned, now) = (Vec::new(), 0, unix_now());
+        for entry in tree.scan_prefix(key::prefix(&read_key).as_bytes()) {
+            let (db_key, value) = entry.map_err(|e| read_error(std::io::Error::other(e)))?;
+            scanned += 1;
+            let record = record::decode(&value).ok_or_else(|| read_error(invalid_chunk("value bị hỏng", &db_key)))?;
+            if record.is_expired(now) || !predicate.iter().all(|(k, v)| record.metadata.get(k) == Some(v)) {
+                continue;
+            }
+            let (bytes, record) = decode_chunk_data(tree, &read_state.large, &db_key, record).map_err(read_error)?;
+            let mut chunk = Chunk::from_record(String::from_utf8_lossy(&db_key).into_owned(), record);
+            chunk.value = match format.encoding {
+                DataEncoding::Base64 => STANDARD.encode(&bytes),
+                DataEncoding::Hex => format!("0x{}", hex::encode(&bytes)),
+            };
+            chunk.encoding = compress::Codec::Identity;
+            chunks.push(chunk);
+        }
+        chunks.sort_by_key(|c| (c.index.is_none(), c.index));
+        Ok((chunks, scanned))
+    })
+    .await?;
+    if scanned == 0 {
+        return Err(ApiError::NotFound);
+    }
+    state.record_read(&file_key, chunks.len());
+
+    tracing::info!("   -> {} / {} chunks khớp", chunks.len(), scanned);
+    ResponseBudget::new(state.config.load().max_response_bytes).take(&chunks)?;
+    Ok((cache_headers(&state.config.load()), Json(FileChunksResponse { file_key, chunks, next_cursor: None })))
+}
+
+/// Handler ĐỔI TÊN file: chuyển toàn bộ chunk sang fileKey mới mà không cần upload lại
+async fn rename_file(
+    State(state): State<Arc<AppState>>,
+    Path(file_key): Path<String>,
+    Query(query): Query<RenameQuery>,
+    Json(payload): Json<RenamePayload>,
+) -> Result<Json<RenameResponse>, ApiError> {
+    state.ensure_writable()?;
+    let new_file_key = payload.new_file_key;
+    if key::component_error(&new_file_key, state.config.load().max_key_bytes).is_some() {
+        return Err(ApiError::InvalidKey);
+    }
+    if new_file_key == file_key {
+        return Err(ApiError::BadRequest);
+    }
+    state.ensure_unsealed(&file_key)?;
+    state.ensure_unsealed(&new_file_key)?;
+    // Cả hai fileKey đều bị ghi nên cần suất ghi của cả hai
+    let _permits = (state.acquire_file(&file_key)?, state.acquire_file(&new_file_key)?);
+
+    tracing::info!("-> Đang đổi tên file {} thành {}", file_key, new_file_key);
+
+    let move_state = state.clone();
+    let (old_key, new_key) = (file_key.clone(), new_file_key.clone());
+    let result = blocking(move || move_chunks(&move_state, &old_key, &new_key, query.overwrite)).await?;
+    flush_db(&state).await?;
+    let moved = result.moved;
+    state.files.check(state.config.load().file_keys_alert);
+
+    tracing::info!("   -> Đã chuyển {} chunks", moved);
+
+    Ok(Json(RenameResponse {
+        file_key,
+        new_file_key,
+        moved,
+    }))
+}
+
+/// Handler SỬA metadata (index, contentType, storedAt) của một chunk mà không cần upload
+/// lại dữ liệu. Record được đọc, sửa và ghi lại bằng compare-and-swap nên không ghi đè
+/// mất một lần ghi đồng thời.
+async fn patch_chunk(
+    State(state): State<Arc<AppState>>,
+    Path((file_key, chunk_hash)): Path<(String, String)>,
+    Json(patch): Json<ChunkPatch>,
+) -> Result<Json<ChunkMetadata>, ApiError> {
+    state.ensure_writable()?;
+    if patch.value.is_some() || patch.chunk_hash.is_some() || !key::is_valid(&file_key, &chunk_hash) {
+        return Err(ApiError::BadRequest);
+    }
+    state.ensure_unsealed(&file_key)?;
+    let _permit = state.acquire_file(&file_key)?;
+    if let Some(Some(content_type)) = &patch.content_type {
+        state.ensure_content_type_allowed(Some(content_type), None, &[])?;
+    }
+
+    tracing::info!("-> Đang sửa metadata của chunk {}:{}", file_key, chunk_hash);
+
+    let update_state = state.clone();
+    let (update_key, update_hash) = (file_key.clone(), chunk_hash.clone());
+    let updated = blocking(move || update_chunk_metadata(&update_state, &update_key, &update_hash, &patch)).await?;
+    let Some((metadata, size_delta)) = updated else {
+        return Err(ApiError::ChunkNotFound);
+    };
+    flush_db(&state).await?;
+    state.quotas.adjust(state.quotas.owner(&file_key), size_delta);
+    state.files.resized(&file_key, size_delta);
+
+    Ok(Json(metadata))
+}
+
+/// Handler COMPARE-AND-SWAP dữ liệu của một chunk (xem `swap_chunk`). Khi dữ liệu hiện tại
+/// khác `expected` thì trả về `409` kèm giá trị hiện tại để client thử lại.
+async fn compare_and_swap_chunk(
+    State(state): State<Arc<AppState>>,
+    Path((file_key, chunk_hash)): Path<(String, String)>,
+    Json(payload): Json<CasPayload>,
+) -> Result<Response, ApiError> {
+    state.ensure_writable()?;
+    if !key::is_valid(&file_key, &chunk_hash) {
+        return Err(ApiError::BadRequest);
+    }
+    state.ensure_unsealed(&file_key)?;
+    // So sánh trên dữ liệu gốc nên cả hai giá trị phải là Base64 hợp lệ
+    let expected = match &payload.expected {
+        Some(value) => Some(STANDARD.decode(value).map_err(|_| ApiError::BadRequest)?),
+        None => None,
+    };
+    let new_data = STANDARD.decode(&payload.new_value).map_err(|_| ApiError::BadRequest)?;
+    // `expected: null` tạo chunk mới nên chịu cùng kiểm tra với POST /store
+    state.ensure_storable(
+        &file_key,
+        &chunk_hash,
+        new_data.len() as u64,
+        payload.content_type.as_deref(),
+        payload.chunk_index,
+        &new_data,
+    )?;
+    let _permit = state.acquire_file(&file_key)?;
+
+    tracing::info!("-> Đang compare-and-swap chunk {}:{}", file_key, chunk_hash);
+
+    let swap_state = state.clone();
+    let (swap_key, swap_hash) = (file_key.clone(), chunk_hash.clone());
+    let outcome = blocking(move || Ok(swap_chunk(&swap_state, &swap_key, &swap_hash, expected, payload))).await??;
+    match outcome {
+        CasOutcome::Swapped(metadata) => {
+            flush_db(&state).await?;
+            Ok(Json(metadata).into_response())
+        }
+        CasOutcome::Mismatch(current) => {
+            let mut details = serde_json::Map::new();
+            details.insert("key".into(), key::encode(&file_key, &chunk_hash).into());
+            details.insert("current".into(), current.into());
+            Ok(ApiError::CasMismatch.with_details(details))
+        }
+    }
+}
+
+/// Handler XÓA toàn bộ chunk của một file trong một lần ghi nguyên tử và trả về các
+/// chunkHash đã xóa, để client tải lại file mà không có lúc file nửa cũ nửa mới.
+/// Trả về `404` nếu file không có chunk nào.
+async fn delete_file(
+    State(state): State<Arc<AppState>>,
+    Path(file_key): Path<String>,
+) -> Result<Json<DeleteResponse>, ApiError> {
+    state.ensure_writable()?;
+    state.ensure_unsealed(&file_key)?;
+    let _permit = state.acquire_file(&file_key)?;
+    tracing::info!("-> Đang xóa file {}", file_key);
+
+    let (delete_state, delete_key) = (state.clone(), file_key.clone());
+    let removed = blocking(move || {
+        let tree = delete_state.tree_for(&delete_key);
+        let owner = delete_state.quotas.owner(&delete_key);
+        delete_chunks(&delete_state, tree, delete_key.as_bytes(), owner)
+    })
+    .await?;
+    flush_db(&state).await?;
+    if !removed.is_empty() {
+        state.files.check(state.config.load().file_keys_alert);
+        state.notifier.deleted(&file_key);
+    }
+
+    if removed.is_empty() {
+        return Err(ApiError::NotFound);
+    }
+    tracing::info!("   -> Đã xóa {} chunks", removed.len());
+
+    Ok(Json(DeleteResponse::new(file_key, removed)))
+}
+
+/// Handler XÓA một chunk cùng value lớn, entry index thứ tự và dung lượng quota đi kèm.
+/// Trả về `404` nếu chunk không có.
+async fn delete_chunk(
+    State(state): State<Arc<AppState>>,
+    Path((file_key, chunk_hash)): Path<(String, String)>,
+) -> Result<Json<DeleteResponse>, ApiError> {
+    state.ensure_writable()?;
+    if !key::is_valid(&file_key, &chunk_hash) {
+        return Err(ApiError::BadRequest);
+    }
+    state.ensure_unsealed(&file_key)?;
+    let _permit = state.acquire_file(&file_key)?;
+
+    let (delete_state, delete_key) = (state.clone(), file_key.clone());
+    let db_key = key::encode(&file_key, &chunk_hash);
+    let removed = blocking(move || {
+        let tree = delete_state.tree_for(&delete_key);
+        let db_error = |e: sled::Error| {
+            tracing::error!("Lỗi khi xóa chunk {}: {}", db_key, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        };
+        // Chunk bị ghi lại giữa lúc đọc và lúc xóa thì đọc lại rồi thử lần nữa
+        loop {
+            let Some(value) = tree.get(&db_key).map_err(db_error)? else {
+                return Ok(false);
+            };
+            let record = record::decode(&value);
+            let (large, index) = record.map_or((false, None), |r| (r.large, r.index));
+            if ttl::remove_if_unchanged(&delete_state, tree, db_key.as_bytes(), &value, large, index).map_err(db_error)? {
+                return Ok(true);
+            }
+        }
+    })
+    .await?;
+    if !removed {
+        return Err(ApiError::ChunkNotFound);
+    }
+    flush_db(&state).await?;
+    tracing::info!("-> Đã xóa chunk {} của file {}", chunk_hash, file_key);
+
+    Ok(Json(DeleteResponse::new(file_key, vec![chunk_hash])))
+}
+
+// fileKey nhận từ các route `/b64/...`
+enum B64FileKey {
+    // Các byte là UTF-8 hợp lệ: xử lý như fileKey dạng chữ
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+/// Giải mã fileKey base64url (có hoặc không có padding) trong các route `/b64/...`. Byte
+/// giải mã được dùng thẳng làm prefix của key trong sled (xem `key::encode_raw`) nên
+/// fileKey nhị phân không phải đi qua bước chuyển UTF-8 làm mất dữ liệu. fileKey có byte
+/// là UTF-8 hợp lệ dùng chung đường xử lý (alias, seal, index thứ tự...) với fileKey dạng
+/// chữ; fileKey nhị phân chỉ đọc, ghi và xóa được qua các route `/b64/...`.
+fn b64_file_key(encoded: &str) -> Result<B64FileKey, ApiError> {
+    let bytes = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(encoded.trim_end_matches('='))
+        .map_err(|_| ApiError::BadRequest)?;
+    if bytes.is_empty() {
+        return Err(ApiError::BadRequest);
+    }
+    Ok(match String::from_utf8(bytes) {
+        Ok(text) => B64FileKey::Text(text),
+        Err(e) => B64FileKey::Binary(e.into_bytes()),
+    })
+}
+
+/// Handler LƯU chunk nhị phân cho fileKey base64url, như /store/raw. fileKey nhị phân
+/// không hỗ trợ `?split=true`: body lớn hơn giới hạn chunk trả về `413`.
+async fn store_raw_b64(
+    State(state): State<Arc<AppState>>,
+    Path((encoded, chunk_hash)): Path<(String, String)>,
+    Query(query): Query<RawStoreQuery>,
+    body: Bytes,
+) -> Result<Json<RawStoreResponse>, ApiError> {
+    let file_key = match b64_file_key(&encoded)? {
+        B64FileKey::Text(file_key) => {
+            let Json(mut response) = store_raw(State(state), Path((file_key, chunk_hash)), Query(query), body).await?;
+            response.file_key = encoded;
+            return Ok(Json(response));
+        }
+        B64FileKey::Binary(file_key) => file_key,
+    };
+    state.ensure_writable()?;
+    if body.len() > state.config.load().max_chunk_size {
+        return Err(ApiError::PayloadTooLarge);
+    }
+    state.ensure_content_type_allowed(None, query.index, &body[..body.len().min(mime::SNIFF_LEN)])?;
+    let chunk_hash = if query.autohash { auto_chunk_hash(&chunk_hash, &body)? } else { chunk_hash };
+    state.ensure_valid_raw_key(&file_key, &chunk_hash)?;
+    // Suất ghi của fileKey nhị phân tính theo dạng base64url của nó
+    let _permit = state.acquire_file(&encoded)?;
+
+    let db_value = StoredChunkValue::raw(STANDARD.encode(&body), query.index);
+    let mut write = state.pending_write_raw(&file_key, &chunk_hash, db_value)?;
+    write.set_overwrite(query.overwrite);
+    state.ensure_capacity(std::slice::from_ref(&write))?;
+    tracing::info!("-> Đang lưu chunk {} cho fileKey nhị phân {}", chunk_hash, encoded);
+    let write_state = state.clone();
+    blocking(move || write_state.commit_writes(&[write])).await?;
+
+    Ok(Json(RawStoreResponse {
+        file_key: encoded,
+        chunk_hashes: vec![chunk_hash],
+    }))
+}
+
+/// Handler NHẬN chunk từ hàng đợi replication của một peer (xem module replication).
+/// Chunk được ghi nguyên trạng, thay cả record ghi bởi chế độ khác, và không được đưa vào
+/// hàng đợi replication của node này nữa.
+async fn replicate_chunk(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<replication::ReplicatePayload>,
+) -> Result<StatusCode, ApiError> {
+    state.ensure_writable()?;
+    let write = state.replica_write(payload)?;
+    state.ensure_capacity(std::slice::from_ref(&write))?;
+    let write_state = state.clone();
+    blocking(move || write_state.commit_writes(&[write])).await?;
+    Ok(StatusCode::OK)
+}
+
+// Query của GET /sync/full
+#[derive(Deserialize)]
+struct FullSyncQuery {
+    // Key (base64url) của chunk cuối đã nhận; luồng bắt đầu sau key này
+    after: Option<String>,
+}
+
+/// Handler gửi TOÀN BỘ chunk của node dạng NDJSON cho node mới đồng bộ (xem module sync).
+/// Cần API key quản trị; trả về `503` khi đã đủ STORAGE_SYNC_MAX_STREAMS luồng.
+async fn full_sync(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<FullSyncQuery>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    require_admin(&state, &headers)?;
+    let body = sync::stream(&state, query.after.as_deref())?;
+    tracing::info!("<- Đang gửi luồng đồng bộ toàn bộ chunk (sau {:?})", query.after);
+    Ok(([(header::CONTENT_TYPE, "application/x-ndjson")], body).into_response())
+}
+
+/// Handler LẤY TẤT CẢ chunk của fileKey base64url, như GET /file/:fileKey. Với fileKey
+/// nhị phân, `key` của mỗi chunk có dạng "encodedKey:chunkHash" và chunk được sắp theo index.
+async fn retrieve_b64_file(
+    State(state): State<Arc<AppState>>,
+    Path(encoded): Path<String>,
+    Query(read): Query<ReadQuery>,
+    Query(format): Query<DataEncodingQuery>,
+    Query(page): Query<PageQuery>,
+    request_headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let file_key = match b64_file_key(&encoded)? {
+        B64FileKey::Text(file_key) => {
+            return retrieve_file_chunks(
+                State(state),
+                Path(file_key),
+                Query(read),
+                Query(format),
+                Query(page),
+                request_headers,
+            )
+            .await;
+        }
+        B64FileKey::Binary(file_key) => file_key,
+    };
+    let _permit = state.acquire_read()?;
+    sync_for_read(&state, read.consistency).await?;
+
+    tracing::info!("<- Đang truy vấn tất cả chunk cho fileKey nhị phân: {}", encoded);
+    let accepted = compress::accepted(&request_headers);
+    let tree = state.tree_for_raw(&file_key).clone();
+    let large = state.large.clone();
+    let response_key = encoded.clone();
+    let mut chunks = blocking(move || {
+        let now = unix_now();
+        let mut chunks: Vec<Chunk> = tree
+            .scan_prefix(key::prefix_raw(&file_key))
+            .flatten()
+            .filter_map(|(key_bytes, value_bytes)| {
+                let chunk_hash = key::chunk_hash_in_raw(&key_bytes, &file_key)?;
+                let stored_value = record::decode(&value_bytes)?; // Bỏ qua nếu value bị hỏng
+                if stored_value.is_expired(now) {
+                    return None;
+                }
+                Some(Chunk::from_record(key::encode(&response_key, chunk_hash), stored_value))
+            })
+            .collect();
+        chunks.sort_by_key(|c| (c.index.is_none(), c.index));
+        Ok(decode_chunks(tier::resolve(chunks, &large), &accepted))
+    })
+    .await?;
+    // Chunk được sắp theo index như khi bật index thứ tự
+    let next_cursor = if page.is_paged() { page.apply(&mut chunks, true, |c| c.key.as_str())? } else { None };
+    if format.encoding == DataEncoding::Hex {
+        for chunk in &mut chunks {
+            let bytes = STANDARD.decode(&chunk.value).map_err(|_| ApiError::Internal)?;
+            chunk.value = format!("0x{}", hex::encode(bytes));
+        }
+    }
+
+    tracing::info!("   -> Tìm thấy {} chunks", chunks.len());
+    let mut budget = ResponseBudget::new(state.config.load().max_response_bytes);
+    budget.take(&chunks)?;
+    Ok(Json(FileChunksResponse { file_key: encoded, chunks, next_cursor }).into_response())
+}
+
+/// Handler XÓA file theo fileKey base64url, như DELETE /file/:fileKey
+async fn delete_b64_file(
+    State(state): State<Arc<AppState>>,
+    Path(encoded): Path<String>,
+) -> Result<Json<DeleteResponse>, ApiError> {
+    let file_key = match b64_file_key(&encoded)? {
+        B64FileKey::Text(file_key) => {
+            let Json(mut response) = delete_file(State(state), Path(file_key)).await?;
+            response.file_key = encoded;
+            return Ok(Json(response));
+        }
+        B64FileKey::Binary(file_key) => file_key,
+    };
+    state.ensure_writable()?;
+    let _permit = state.acquire_file(&encoded)?;
+    tracing::info!("-> Đang xóa file nhị phân {}", encoded);
+
+    let delete_state = state.clone();
+    let removed = blocking(move || {
+        let tree = delete_state.tree_for_raw(&file_key);
+        let owner = delete_state.quotas.owner_raw(&file_key);
+        delete_chunks(&delete_state, tree, &file_key, &owner)
+    })
+    .await?;
+    flush_db(&state).await?;
+    if !removed.is_empty() {
+        state.files.check(state.config.load().file_keys_alert);
+    }
+
+    if removed.is_empty() {
+        return Err(ApiError::NotFound);
+    }
+    tracing::info!("   -> Đã xóa {} chunks", removed.len());
+    Ok(Json(DeleteResponse::new(encoded, removed)))
+}
+
+/// Handler SEAL file đã hoàn tất: sau đó file không thể bị ghi thêm, xóa hay đổi tên nữa
+/// (xem module seal). Chỉ seal được file đang có chunk; seal lại file đã seal không đổi gì.
+async fn seal_file(
+    State(state): State<Arc<AppState>>,
+    Path(file_key): Path<String>,
+) -> Result<Json<SealResponse>, ApiError> {
+    state.ensure_writable()?;
+    let db_error = |e: sled::Error| {
+        tracing::error!("Lỗi khi seal file {}: {}", file_key, e);
+        ApiError::Internal
+    };
+    if !state.files.tree.contains_key(&file_key).map_err(db_error)? {
+        return Err(ApiError::NotFound);
+    }
+    let mut parity_stripes = None;
+    if seal::seal(&state.sealed, &file_key, unix_now()).map_err(db_error)? {
+        let config = state.config.load();
+        let (data_shards, parity_shards) = (config.parity_data_shards, config.parity_shards);
+        if parity_shards > 0 {
+            let parity_state = state.clone();
+            let parity_key = file_key.clone();
+            let stripes =
+                blocking(move || store_parity(&parity_state, &parity_key, data_shards, parity_shards)).await?;
+            tracing::info!("-> Đã tính {} stripe parity cho file {}", stripes, file_key);
+            parity_stripes = Some(stripes);
+        }
+        flush_db(&state).await?;
+        tracing::info!("-> Đã seal file {}", file_key);
+    }
+
+    Ok(Json(SealResponse {
+        file_key,
+        sealed: true,
+        parity_stripes,
+    }))
+}
+
+/// Handler GỠ seal của một file, chỉ dành cho quản trị (cần API key)
+async fn unseal_file(
+    State(state): State<Arc<AppState>>,
+    Path(file_key): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<SealResponse>, ApiError> {
+    require_admin(&state, &headers)?;
+    state.ensure_writable()?;
+    let removed = seal::unseal(&state.sealed, &file_key).map_err(|e| {
+        tracing::error!("Lỗi khi gỡ seal file {}: {}", file_key, e);
+        ApiError::Internal
+    })?;
+    if removed {
+        // File sửa được trở lại nên parity không còn đúng nữa
+        let parity_state = state.clone();
+        let parity_key = file_key.clone();
+        blocking(move || remove_parity(&parity_state, &parity_key)).await?;
+        flush_db(&state).await?;
+        tracing::info!("-> Đã gỡ seal file {}", file_key);
+    }
+
+    Ok(Json(SealResponse {
+        file_key,
+        sealed: false,
+        parity_stripes: None,
+    }))
+}
+
+/// Handler DỰNG LẠI các chunk bị mất hoặc hỏng của một file đã seal từ parity
+/// Reed-Solomon (xem module parity). Trả về `404` nếu file không có parity.
+async fn reconstruct_file(
+    State(state): State<Arc<AppState>>,
+    Path(file_key): Path<String>,
+) -> Result<Json<ReconstructResponse>, ApiError> {
+    state.ensure_writable()?;
+    let file_key = state.resolve_file_key(file_key)?;
+    let rebuild_state = state.clone();
+    let rebuild_key = file_key.clone();
+    let Some((reconstructed, unrecoverable)) =
+        blocking(move || reconstruct_chunks(&rebuild_state, &rebuild_key)).await?
+    else {
+        return Err(ApiError::NotFound);
+    };
+    tracing::info!(
+        "-> Dựng lại file {}: {} chunk đã dựng lại, {} chunk không dựng lại được",
+        file_key,
+        reconstructed.len(),
+        unrecoverable.len()
+    );
+
+    Ok(Json(ReconstructResponse {
+        file_key,
+        reconstructed,
+        unrecoverable,
+    }))
+}
+
+/// Handler PHÂN GIẢI tên: alias được trả về cùng fileKey nó trỏ tới, fileKey thật được
+/// trả về nguyên vẹn. Trả về `404` nếu tên không phải alias và không có file nào như vậy.
+async fn resolve_alias(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<Json<AliasResponse>, ApiError> {
+    let file_key = state.resolve_file_key(name.clone())?;
+    let is_alias = file_key != name;
+    if !is_alias && !state.files.tree.contains_key(&file_key).map_err(|_| ApiError::Internal)? {
+        return Err(ApiError::NotFound);
+    }
+    Ok(Json(AliasResponse {
+        name,
+        file_key,
+        alias: is_alias,
+        previous_file_key: None,
+    }))
+}
+
+/// Handler LƯU manifest của file (xem module manifest): thứ tự chunk cùng tổng kích thước,
+/// MIME type và tên file gốc. Trả về `201` khi tạo mới, `200` khi thay manifest cũ và `400`
+/// nếu danh sách chunk rỗng, có chunkHash không hợp lệ hoặc lặp lại.
+async fn put_manifest(
+    State(state): State<Arc<AppState>>,
+    Path(file_key): Path<String>,
+    Json(manifest): Json<manifest::Manifest>,
+) -> Result<(StatusCode, Json<StoredManifestResponse>), ApiError> {
+    state.ensure_writable()?;
+    state.ensure_unsealed(&file_key)?;
+    if !manifest.is_valid(&file_key) {
+        return Err(ApiError::BadRequest);
+    }
+    let replaced = manifest::put(&state.manifests, &file_key, &manifest).map_err(|e| {
+        tracing::error!("Lỗi khi lưu manifest của file {}: {}", file_key, e);
+        ApiError::Internal
+    })?;
+    flush_db(&state).await?;
+    tracing::info!("-> Đã lưu manifest {} chunks cho fileKey: {}", manifest.chunks.len(), file_key);
+    let (check_state, check_key) = (state.clone(), file_key.clone());
+    blocking(move || {
+        notify::check_completed(&check_state, &check_key, None);
+        Ok(())
+    })
+    .await?;
+
+    let status = if replaced { StatusCode::OK } else { StatusCode::CREATED };
+    Ok((status, Json(StoredManifestResponse { file_key, manifest })))
+}
+
+/// Handler LẤY manifest đã lưu của file; `404` nếu file chưa có manifest
+async fn get_manifest(
+    State(state): State<Arc<AppState>>,
+    Path(file_key): Path<String>,
+) -> Result<Json<StoredManifestResponse>, ApiError> {
+    let file_key = state.resolve_file_key(file_key)?;
+    let manifest = state.manifest(&file_key)?.ok_or(ApiError::NotFound)?;
+    Ok(Json(StoredManifestResponse { file_key, manifest }))
+}
+
+/// Handler TẠO hoặc ĐỔI alias, chỉ dành cho quản trị (cần API key). Alias có dạng key hex
+/// thô bị từ chối với `400`. fileKey đích không cần đang có chunk.
+async fn set_alias(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(payload): Json<AliasPayload>,
+) -> Result<Json<AliasResponse>, ApiError> {
+    require_admin(&state, &headers)?;
+    state.ensure_writable()?;
+    if !alias::is_valid(&payload.alias) || payload.file_key.is_empty() || payload.file_key == payload.alias {
+        return Err(ApiError::BadRequest);
+    }
+    let previous = alias::set(&state.aliases, &payload.alias, &payload.file_key).map_err(|e| {
+        tracing::error!("Lỗi khi lưu alias {}: {}", payload.alias, e);
+        ApiError::Internal
+    })?;
+    flush_db(&state).await?;
+    tracing::info!("-> Alias {} trỏ tới fileKey {}", payload.alias, payload.file_key);
+
+    Ok(Json(AliasResponse {
+        name: payload.alias,
+        file_key: payload.file_key,
+        alias: true,
+        previous_file_key: previous,
+    }))
+}
+
+/// Handler KIỂM TRA chunk còn thiếu của một file dựa trên chunkIndex.
+/// Số chunk mong đợi lấy từ `?expected=N`; nếu không có thì suy ra từ index lớn nhất + 1
+/// (khi đó không phát hiện được các chunk bị thiếu ở cuối file).
+async fn probe_file(
+    State(state): State<Arc<AppState>>,
+    Path(file_key): Path<String>,
+    Query(query): Query<ProbeQuery>,
+) -> Result<Json<ProbeResponse>, ApiError> {
+    let file_key = state.resolve_file_key(file_key)?;
+    let _permit = state.acquire_read()?;
+
+    tracing::info!("<- Đang kiểm tra chunk còn thiếu cho fileKey: {}", file_key);
+    // Chỉ cần index nên không giải nén dữ liệu
+    let chunks = load_file_chunks(&state, &file_key, &[compress::Codec::Zstd, compress::Codec::Lz4]).await?;
+    if chunks.is_empty() {
+        return Err(ApiError::NotFound);
+    }
+
+    let present: std::collections::BTreeSet<u64> = chunks.iter().filter_map(|c| c.index).collect();
+    let expected = query
+        .expected
+        .unwrap_or_else(|| present.last().map_or(0, |max| max + 1));
+    let missing: Vec<u64> = (0..expected).filter(|i| !present.contains(i)).collect();
+
+    tracing::info!("   -> Thiếu {} / {} chunks", missing.len(), expected);
+
+    Ok(Json(ProbeResponse {
+        file_key,
+        expected,
+        present: present.len(),
+        missing,
+    }))
+}
+
+/// Handler trả về top-N file được truy cập nhiều nhất trong cửa sổ thống kê gần nhất
+async fn hot_files(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<HotQuery>,
+) -> Json<hot::HotSnapshot> {
+    let snapshot = hot::current(&state.hot);
+    let n = query.n.unwrap_or(10);
+
+    Json(hot::HotSnapshot {
+        window_secs: state.config.load().hot_window_secs,
+        generated_at: snapshot.generated_at,
+        files: snapshot.files.iter().take(n).cloned().collect(),
+    })
+}
+
+/// Handler trả về phiên bản, thông tin build và các tính năng đang bật
+async fn version_info(State(state): State<Arc<AppState>>) -> Json<VersionResponse> {
+    let built_at = env!("BUILD_TIMESTAMP")
+        .parse()
+        .map(|secs| httpdate::fmt_http_date(std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs)))
+        .unwrap_or_default();
+    let config = state.config.load();
+
+    Json(VersionResponse {
+        version: env!("CARGO_PKG_VERSION"),
+        git_sha: env!("BUILD_GIT_SHA"),
+        built_at,
+        sled_version: env!("BUILD_SLED_VERSION"),
+        features: FeatureFlags {
+            grpc: state.grpc_enabled.load(Ordering::Relaxed),
+            compression: config.compression,
+            encryption: config.encryption.is_some(),
+            s3: false,
+            html_index: config.html_index,
+            order_index: state.order.is_some(),
+            shards: state.shards.len(),
+        },
+    })
+}
+
+/// Handler trả về N chunk có index lớn nhất của một file (mặc định 10), theo thứ tự
+/// index tăng dần, dùng cho các file dạng log chỉ cần dữ liệu mới nhất.
+/// Key trong sled được sắp theo hash chứ không theo index, nên handler quét toàn bộ
+/// chunk của file rồi sắp xếp trong bộ nhớ (khi bật index thứ tự thì danh sách đã được
+/// sắp sẵn); chunk không có index bị bỏ qua. Response vượt STORAGE_MAX_RESPONSE_BYTES trả về
+/// `413` như `GET /file/:fileKey`.
+async fn tail_file(
+    State(state): State<Arc<AppState>>,
+    Path(file_key): Path<String>,
+    Query(query): Query<TailQuery>,
+    Query(read): Query<ReadQuery>,
+    request_headers: HeaderMap,
+) -> Result<Json<FileChunksResponse>, ApiError> {
+    let file_key = state.resolve_file_key(file_key)?;
+    let _permit = state.acquire_read()?;
+    sync_for_read(&state, read.consistency).await?;
+    let n = query.n.unwrap_or(10);
+
+    tracing::info!("<- Đang lấy {} chunk cuối cho fileKey: {}", n, file_key);
+    let accepted = compress::accepted(&request_headers);
+    let mut chunks: Vec<Chunk> = load_file_chunks(&state, &file_key, &accepted)
+        .await?
+        .into_iter()
+        .filter(|c| c.index.is_some())
+        .collect();
+    if chunks.is_empty() {
+        return Err(ApiError::NotFound);
+    }
+
+    chunks.sort_by_key(|c| c.index);
+    let chunks = chunks.split_off(chunks.len().saturating_sub(n));
+    ResponseBudget::new(state.config.load().max_response_bytes).take(&chunks)?;
+    state.record_read(&file_key, chunks.len());
+
+    Ok(Json(FileChunksResponse { file_key, chunks, next_cursor: None }))
+}
+
+// Body của /admin/readonly, ví dụ: {"readOnly": true}
+#[derive(Deserialize)]
+struct ReadOnlyPayload {
+    #[serde(rename = "readOnly")]
+    read_only: bool,
+}
+
+// Các số liệu phải quét database mới có, được giữ lại giữa các lần gọi /diagnostics và
+// /metrics
+#[derive(Clone, Copy)]
+struct DbStats {
+    size_on_disk: u64,
+    chunk_keys: u64,
+}
+
+// Kết quả của /diagnostics
+#[derive(Serialize)]
+struct DiagnosticsResponse {
+    status: &'static str,
+    // Node nhận cả đọc lẫn ghi (không ở chế độ chỉ đọc)
+    ready: bool,
+    #[serde(rename = "readOnly")]
+    read_only: bool,
+    #[serde(rename = "sizeOnDisk")]
+    size_on_disk: u64,
+    #[serde(rename = "chunkKeys")]
+    chunk_keys: u64,
+    #[serde(rename = "fileKeys")]
+    file_keys: u64,
+    // Tuổi (mili giây) của `sizeOnDisk` và `chunkKeys`
+    #[serde(rename = "statsAgeMs")]
+    stats_age_ms: u64,
+    // Thời điểm flush thành công gần nhất (unix mili giây), `null` nếu chưa flush lần nào
+    #[serde(rename = "lastFlush")]
+    last_flush: Option<u64>,
+    flushes: u64,
+    buffered: usize,
+    #[serde(rename = "replicationQueue")]
+    replication_queue: u64,
+    #[serde(rename = "readsInFlight")]
+    reads_in_flight: i64,
+    // Trạng thái circuit breaker của từng peer (xem module breaker)
+    breakers: std::collections::BTreeMap<String, breaker::PeerBreaker>,
+}
+
+// Trạng thái trả về của /health và /admin/readonly
+#[derive(Serialize)]
+struct HealthResponse {
+    status: &'static str,
+    #[serde(rename = "readOnly")]
+    read_only: bool,
+    // Node tự chuyển sang chỉ đọc vì đĩa đầy (xem module disk)
+    #[serde(rename = "diskFull")]
+    disk_full: bool,
+    // Số chunk đã nhận nhưng chưa được ghi xuống sled (xem module write_buffer)
+    buffered: usize,
+}
+
+impl HealthResponse {
+    fn of(state: &AppState) -> Self {
+        let read_only = state.is_read_only();
+        let disk_full = state.is_disk_full();
+        HealthResponse {
+            status: match (disk_full, read_only) {
+                (true, _) => "degraded",
+                (false, true) => "read_only",
+                (false, false) => "ok",
+            },
+            read_only,
+            disk_full,
+            buffered: state.write_buffer.pending(),
+        }
+    }
+}
+
+/// Handler kiểm tra node còn phục vụ được không. Chế độ chỉ đọc vẫn trả `200` vì node
+/// vẫn phục vụ đọc; bên điều phối xem `readOnly` để biết có gửi ghi tới được không.
+/// `status` là `degraded` khi node tự chuyển sang chỉ đọc vì đĩa đầy (xem module disk).
+async fn health(State(state): State<Arc<AppState>>) -> Json<HealthResponse> {
+    Json(HealthResponse::of(&state))
+}
+
+/// Handler gom các tín hiệu chính của node vào một response để chẩn đoán nhanh lúc có
+/// sự cố, chỉ dành cho quản trị (cần API key). Số key và dung lượng đĩa phải quét cả
+/// database nên được đếm lại tối đa mỗi STORAGE_DIAGNOSTICS_CACHE_SECS; các số còn lại
+/// đọc thẳng từ bộ đếm trong bộ nhớ. Server không có cache ở tầng ứng dụng và sled không
+/// công bố tỉ lệ hit của page cache nên response không có số liệu này.
+async fn diagnostics(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<DiagnosticsResponse>, ApiError> {
+    require_admin(&state, &headers)?;
+    let (counted_at, stats) = db_stats(&state).await?;
+
+    let health = HealthResponse::of(&state);
+    let last_flush = state.metrics.last_flush_ms.load(Ordering::Relaxed);
+    Ok(Json(DiagnosticsResponse {
+        status: health.status,
+        ready: !health.read_only,
+        read_only: health.read_only,
+        size_on_disk: stats.size_on_disk,
+        chunk_keys: stats.chunk_keys,
+        file_keys: state.files.distinct(),
+        stats_age_ms: counted_at.elapsed().as_millis() as u64,
+        last_flush: (last_flush > 0).then_some(last_flush),
+        flushes: state.metrics.flushes.load(Ordering::Relaxed),
+        buffered: health.buffered,
+        replication_queue: state.replication.depth(),
+        reads_in_flight: state.metrics.reads_in_flight.load(Ordering::Relaxed),
+        breakers: state.breakers.snapshot(&state.replication_peers()),
+    }))
+}
+
+/// Số key và dung lượng đĩa của database, đếm lại khi kết quả cũ hơn
+/// STORAGE_DIAGNOSTICS_CACHE_SECS. Trả về kèm thời điểm đếm.
+async fn db_stats(state: &Arc<AppState>) -> Result<(Instant, DbStats), StatusCode> {
+    let max_age = Duration::from_secs(state.config.load().diagnostics_cache_secs);
+    let cached = *state.db_stats.lock().unwrap();
+    if let Some((counted_at, stats)) = cached
+        && counted_at.elapsed() < max_age
+    {
+        return Ok((counted_at, stats));
+    }
+    let count_state = state.clone();
+    let stats = blocking(move || {
+        let size_on_disk = count_state.db.size_on_disk().map_err(|e| {
+            tracing::error!("Lỗi khi đọc dung lượng database: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        let chunk_keys = count_state.shards.iter().map(|tree| tree.len() as u64).sum();
+        Ok(DbStats { size_on_disk, chunk_keys })
+    })
+    .await?;
+    let counted_at = Instant::now();
+    *state.db_stats.lock().unwrap() = Some((counted_at, stats));
+    Ok((counted_at, stats))
+}
+
+/// Handler BẬT/TẮT chế độ bảo trì chỉ đọc: khi bật, mọi thao tác ghi, xóa, đổi tên, sửa
+/// metadata trả về `503` còn các route đọc vẫn hoạt động (dùng khi backup hoặc chuyển dữ
+/// liệu). Trạng thái chỉ nằm trong bộ nhớ, khởi động lại thì node nhận ghi như bình thường.
+async fn set_read_only(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(payload): Json<ReadOnlyPayload>,
+) -> Result<Json<HealthResponse>, ApiError> {
+    require_admin(&state, &headers)?;
+    let was = state.read_only.swap(payload.read_only, Ordering::Relaxed);
+    // Người vận hành đã quyết định nên node không tự phục hồi sau khi đầy đĩa nữa
+    state.disk_full.store(false, Ordering::Relaxed);
+    if was != payload.read_only {
+        tracing::info!("-> {} chế độ chỉ đọc", if payload.read_only { "Bật" } else { "Tắt" });
+    }
+    // Ghi nốt bộ đệm để database không còn thay đổi trong lúc bảo trì
+    if payload.read_only {
+        write_buffer::drain(&state).await?;
+    }
+    Ok(Json(HealthResponse::of(&state)))
+}
+
+// Payload cho POST /admin/tokens
+#[derive(Deserialize)]
+struct TokenPayload {
+    scope: auth::Scope,
+    name: Option<String>,
+}
+
+// Token vừa tạo, trả về bởi POST /admin/tokens; `token` không được lưu lại ở đâu khác
+#[derive(Serialize)]
+struct TokenResponse {
+    id: String,
+    token: String,
+    scope: auth::Scope,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(rename = "createdAt")]
+    created_at: u64,
+}
+
+/// Handler TẠO API token với quyền `read`, `write` hoặc `admin` (xem module auth). Token chỉ
+/// có trong response này; thu hồi bằng `DELETE /admin/tokens/:id`.
+async fn create_token(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(payload): Json<TokenPayload>,
+) -> Result<(StatusCode, Json<TokenResponse>), ApiError> {
+    require_admin(&state, &headers)?;
+    state.ensure_writable()?;
+    let created_at = unix_now();
+    let (id, token) = auth::mint(&state.tokens, payload.scope, payload.name.clone(), created_at).map_err(|e| {
+        tracing::error!("Lỗi khi tạo API token: {}", e);
+        ApiError::Internal
+    })?;
+    flush_db(&state).await?;
+    tracing::info!("-> Đã tạo API token {} với quyền {:?}", id, payload.scope);
+
+    Ok((
+        StatusCode::CREATED,
+        Json(TokenResponse {
+            id,
+            token,
+            scope: payload.scope,
+            name: payload.name,
+            created_at,
+        }),
+    ))
+}
+
+/// Handler THU HỒI API token theo id; `404` nếu không có token này
+async fn revoke_token(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<StatusCode, ApiError> {
+    require_admin(&state, &headers)?;
+    state.ensure_writable()?;
+    let removed = auth::revoke(&state.tokens, &id).map_err(|e| {
+        tracing::error!("Lỗi khi thu hồi API token {}: {}", id, e);
+        ApiError::Internal
+    })?;
+    if !removed {
+        return Err(ApiError::NotFound);
+    }
+    flush_db(&state).await?;
+    tracing::info!("-> Đã thu hồi API token {}", id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Kết quả của /admin/flush
+#[derive(Serialize)]
+struct FlushResponse {
+    // Số chunk được lấy ra khỏi bộ đệm ghi
+    drained: usize,
+}
+
+/// Handler GHI NGAY bộ đệm ghi (STORAGE_WRITE_BUFFER) xuống sled rồi flush database, kể
+/// cả khi bộ đệm đang trống.
+async fn flush_write_buffer(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<FlushResponse>, ApiError> {
+    require_admin(&state, &headers)?;
+    let drained = write_buffer::drain(&state).await?;
+    flush_db(&state).await?;
+    tracing::info!("-> /admin/flush: đã ghi {} chunk trong bộ đệm", drained);
+    Ok(Json(FlushResponse { drained }))
+}
+
+/// Handler KIỂM TRA các index phụ so với dữ liệu trong shard mà không sửa gì (xem module
+/// reindex)
+async fn check_consistency(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<reindex::Report>, ApiError> {
+    require_admin(&state, &headers)?;
+    let report = blocking(move || {
+        reindex::check(&state).map_err(|e| {
+            tracing::error!("Lỗi khi kiểm tra các index phụ: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+    })
+    .await?;
+    Ok(Json(report))
+}
+
+/// Handler EXPORT mọi cặp key/value của database dạng nhị phân (xem module export). Bộ
+/// đệm ghi được ghi hết và database được flush trước khi bắt đầu.
+async fn export_database(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Result<Response, ApiError> {
+    require_admin(&state, &headers)?;
+    write_buffer::drain(&state).await?;
+    flush_db(&state).await?;
+    tracing::info!("<- Đang export toàn bộ database");
+    Ok(([(header::CONTENT_TYPE, "application/octet-stream")], export::stream(&state)).into_response())
+}
+
+// Query cho /admin/import, ví dụ: ?onConflict=overwrite
+#[derive(Deserialize)]
+struct ImportQuery {
+    #[serde(default, rename = "onConflict")]
+    on_conflict: export::Conflict,
+}
+
+/// Handler IMPORT luồng của `GET /admin/export` vào database (xem module export)
+async fn import_database(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<ImportQuery>,
+    body: Body,
+) -> Result<Json<export::ImportReport>, ApiError> {
+    require_admin(&state, &headers)?;
+    state.ensure_writable()?;
+    let report = export::import(&state, body, query.on_conflict).await?;
+    tracing::info!(
+        "-> /admin/import: ghi {} cặp ({} ghi đè), {} đã có, bỏ {} cặp xung đột",
+        report.imported,
+        report.overwritten,
+        report.unchanged,
+        report.skipped
+    );
+    Ok(Json(report))
+}
+
+// Query cho /admin/gc, ví dụ: ?apply=true&unmanifested=true
+#[derive(Deserialize)]
+struct GcQuery {
+    #[serde(default)]
+    apply: bool,
+    #[serde(default)]
+    unmanifested: bool,
+}
+
+/// Handler TÌM (và với `?apply=true` thì XÓA) các chunk mồ côi không còn được manifest nào
+/// nhắc tới (xem module gc). Mặc định chỉ báo cáo. Xóa không được khi node đang chỉ đọc.
+async fn collect_garbage_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<GcQuery>,
+) -> Result<Json<gc::Report>, ApiError> {
+    require_admin(&state, &headers)?;
+    if query.apply {
+        state.ensure_writable()?;
+    }
+    let options = gc::Options {
+        apply: query.apply,
+        unmanifested: query.unmanifested,
+    };
+    let report = blocking(move || {
+        gc::collect(&state, options, unix_now()).map_err(|e| {
+            tracing::error!("Lỗi khi dọn chunk mồ côi: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+    })
+    .await?;
+    tracing::info!(
+        "-> /admin/gc: {} chunk mồ côi ({} byte), đã xóa {}",
+        report.orphaned_chunks,
+        report.reclaimable_bytes,
+        report.deleted_chunks
+    );
+    Ok(Json(report))
+}
+
+/// Handler CHUYỂN các chunk đã lưu sang blob dùng chung theo hash nội dung (xem module
+/// dedup). Chạy được trong lúc node nhận ghi nhưng không khi node đang chỉ đọc.
+async fn migrate_dedup(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<dedup::MigrateReport>, ApiError> {
+    require_admin(&state, &headers)?;
+    state.ensure_writable()?;
+    let report = blocking(move || {
+        dedup::migrate(&state).map_err(|e| {
+            tracing::error!("Lỗi khi chuyển chunk sang blob dùng chung: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+    })
+    .await?;
+    tracing::info!(
+        "-> /admin/dedup/migrate: đã chuyển {}/{} chunk, {} chunk trùng nội dung, bỏ qua {}",
+        report.migrated,
+        report.scanned,
+        report.shared,
+        report.skipped
+    );
+    Ok(Json(report))
+}
+
+#[derive(Deserialize)]
+struct ReindexQuery {
+    // Bỏ tiến độ đã lưu của lần dựng lại bị dừng giữa chừng
+    #[serde(default)]
+    restart: bool,
+}
+
+/// Handler BẮT ĐẦU dựng lại các index phụ trong nền; trả về `202` kèm tiến độ. Nếu đang
+/// có một lần dựng lại chạy thì chỉ trả về tiến độ của lần đó.
+async fn start_reindex(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<ReindexQuery>,
+) -> Result<(StatusCode, Json<reindex::Progress>), ApiError> {
+    require_admin(&state, &headers)?;
+    Ok((StatusCode::ACCEPTED, Json(reindex::start(&state, query.restart))))
+}
+
+/// Handler trả về tiến độ của lần dựng lại các index phụ gần nhất
+async fn reindex_progress(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<reindex::Progress>, ApiError> {
+    require_admin(&state, &headers)?;
+    Ok(Json(state.reindex.progress()))
+}
+
+/// Handler ĐỌC LẠI cấu hình (biến môi trường và STORAGE_CONFIG_FILE) rồi thay vào state,
+/// áp dụng cho các request sau đó; STORAGE_PEERS mới được đưa vào danh sách peer gossip.
+/// Nếu có biến sai định dạng thì không thay gì cả và trả về `400` kèm danh sách `invalid`;
+/// nếu có thiết lập chỉ đổi được khi khởi động thì trả về `409` kèm danh sách `rejected`.
+async fn reload_config(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<(StatusCode, Json<ReloadResponse>), ApiError> {
+    require_admin(&state, &headers)?;
+
+    let new_config = match Config::reload() {
+        Ok(config) => config,
+        Err((e, invalid)) => {
+            tracing::error!("Không thể đọc lại cấu hình: {}", e);
+            let response = ReloadResponse {
+                reloaded: false,
+                rejected: Vec::new(),
+                invalid,
+                message: Some(format!("failed to read configuration: {}", e)),
+            };
+            return Ok((StatusCode::BAD_REQUEST, Json(response)));
+        }
+    };
+
+    let rejected = state.config.load().immutable_changes(&new_config);
+    if !rejected.is_empty() {
+        tracing::warn!("Từ chối đọc lại cấu hình, cần khởi động lại để đổi: {}", rejected.join(", "));
+        let response = ReloadResponse {
+            message: Some(format!("these settings require a restart: {}", rejected.join(", "))),
+            reloaded: false,
+            rejected,
+            invalid: Vec::new(),
+        };
+        return Ok((StatusCode::CONFLICT, Json(response)));
+    }
+
+    if let Some(membership) = &state.membership {
+        membership.reseed(&new_config.peers, new_config.max_peers);
+    }
+    state.namespaces.reload(&new_config);
+    state.config.store(Arc::new(new_config));
+    tracing::info!("-> Đã đọc lại cấu hình");
+
+    Ok((
+        StatusCode::OK,
+        Json(ReloadResponse {
+            reloaded: true,
+            rejected: Vec::new(),
+            invalid: Vec::new(),
+            message: None,
+        }),
+    ))
+}
+
+/// Handler TẢI file dạng luồng: nối dữ liệu gốc (đã giải Base64 và giải nén) của các
+/// chunk theo thứ tự index và gửi dần thành `application/octet-stream`.
+/// Một task trên blocking pool đọc trước tối đa `stream_prefetch` chunk vào channel có
+/// giới hạn trong lúc chunk hiện tại đang được ghi ra socket, nên thời gian chờ đĩa
+/// chồng lên thời gian gửi mạng thay vì cộng dồn. Có ở cả `/file/:fileKey/stream` và
+/// `/file/:fileKey/stream-download` (tên cũ).
+/// Hỗ trợ tải tiếp và cache qua HTTP: `ETag` lấy từ chunkHash/độ dài các chunk (xem
+/// `vfile::etag`), `If-None-Match` khớp thì trả `304`; một khoảng `Range: bytes=...` được
+/// ánh xạ lên các chunk qua bảng offset của /vfile và trả `206` kèm `Content-Range`, chỉ
+/// đọc những chunk giao với khoảng đó (`416` nếu khoảng nằm ngoài file). `If-Range` khác
+/// ETag hiện tại thì bỏ qua `Range`. File bị ghi lại giữa chừng thì luồng bị ngắt.
+async fn stream_download(
+    State(state): State<Arc<AppState>>,
+    Path(file_key): Path<String>,
+    Query(read): Query<ReadQuery>,
+    request_headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let file_key = state.resolve_file_key(file_key)?;
+    let plan = stream_plan(&state, &file_key, read.consistency).await?;
+    let tree = state.tree_for(&file_key).clone();
+    let large = state.large.clone();
+    let extents = {
+        let (tree, large) = (tree.clone(), large.clone());
+        blocking(move || {
+            vfile::layout(&tree, &large, plan).map_err(|e| {
+                tracing::error!("Lỗi khi lập bảng offset của file: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })
+        })
+        .await?
+    };
+    let total = vfile::total_len(&extents);
+    let etag = vfile::etag(&extents);
+
+    let header_str = |name| request_headers.get(name).and_then(|v: &HeaderValue| v.to_str().ok());
+    let mut headers = HeaderMap::new();
+    headers.insert(header::ETAG, HeaderValue::from_str(&etag).expect("header hợp lệ"));
+    headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    if header_str(header::IF_NONE_MATCH).is_some_and(|tags| vfile::none_match(tags, &etag)) {
+        return Ok((StatusCode::NOT_MODIFIED, headers).into_response());
+    }
+
+    let range = match header_str(header::RANGE) {
+        // If-Range chỉ chấp nhận ETag mạnh; ngày tháng hoặc ETag cũ thì gửi cả file
+        Some(_) if header_str(header::IF_RANGE).is_some_and(|tag| tag.trim() != etag) => vfile::ByteRange::Full,
+        Some(value) => vfile::parse_range(value, total),
+        None => vfile::ByteRange::Full,
+    };
+    let (status, start, end) = match range {
+        vfile::ByteRange::Full => (StatusCode::OK, 0, total),
+        vfile::ByteRange::Partial { start, end } => (StatusCode::PARTIAL_CONTENT, start, end),
+        vfile::ByteRange::Unsatisfiable => {
+            let value = HeaderValue::from_str(&format!("bytes */{}", total)).expect("header hợp lệ");
+            headers.insert(header::CONTENT_RANGE, value);
+            return Ok((headers, ApiError::RangeNotSatisfiable.render(error::Lang::En)).into_response());
+        }
+    };
+    if status == StatusCode::PARTIAL_CONTENT {
+        let value = HeaderValue::from_str(&format!("bytes {}-{}/{}", start, end - 1, total)).expect("header hợp lệ");
+        headers.insert(header::CONTENT_RANGE, value);
+    }
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from(end - start));
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/octet-stream"));
+    tracing::info!("<- Đang gửi luồng byte {}-{}/{} cho fileKey: {}", start, end, total, file_key);
+
+    let (tx, rx) = tokio::sync::mpsc::channel(state.config.load().stream_prefetch);
+    tokio::task::spawn_blocking(move || {
+        let first = extents.partition_point(|extent| extent.offset + extent.len <= start);
+        for extent in extents[first..].iter().take_while(|extent| extent.offset < end) {
+            let item = match fetch_stream_chunk(&tree, &large, &extent.db_key, extent.index) {
+                Ok(Some((bytes, _))) if bytes.len() as u64 == extent.len => {
+                    let from = start.saturating_sub(extent.offset) as usize;
+                    let to = (end - extent.offset).min(extent.len) as usize;
+                    Ok(bytes.slice(from..to))
+                }
+                // Chunk đã bị xóa hoặc ghi lại: phần còn lại không khớp ETag/Content-Length
+                Ok(_) => Err(std::io::Error::other(format!("chunk đã thay đổi: {}", extent.db_key))),
+                Err(e) => Err(e),
+            };
+            let failed = item.is_err();
+            // Client ngắt kết nối thì channel bị đóng, dừng đọc
+            if tx.blocking_send(item).is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    let body = Body::from_stream(tokio_stream::wrappers::ReceiverStream::new(rx));
+    Ok((status, headers, body).into_response())
+}
+
+/// Handler trả về MANIFEST của file: danh sách chunk theo thứ tự index cùng độ dài dữ
+/// liệu gốc và offset trong file, để client lập kế hoạch tải song song hoặc đọc theo khoảng
+/// byte (/vfile) mà không phải tải dữ liệu. Dùng chung bảng offset với /vfile nên chỉ
+/// record cũ không có `size` mới phải giải mã. Trả về `404` nếu file không có chunk nào.
+async fn file_manifest(
+    State(state): State<Arc<AppState>>,
+    Path(file_key): Path<String>,
+    Query(read): Query<ReadQuery>,
+) -> Result<Json<ManifestResponse>, ApiError> {
+    let file_key = state.resolve_file_key(file_key)?;
+    let plan = stream_plan(&state, &file_key, read.consistency).await?;
+    let tree = state.tree_for(&file_key).clone();
+    let large = state.large.clone();
+    let extents = blocking(move || {
+        vfile::layout(&tree, &large, plan).map_err(|e| {
+            tracing::error!("Lỗi khi lập manifest của file: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+    })
+    .await?;
+
+    let total_size = vfile::total_len(&extents);
+    let chunks = extents
+        .into_iter()
+        .filter_map(|extent| {
+            let chunk_hash = key::decode(extent.db_key.as_bytes())?.chunk_hash.to_string();
+            Some(ManifestChunk {
+                index: extent.index,
+                chunk_hash,
+                byte_length: extent.len,
+                offset: extent.offset,
+            })
+        })
+        .collect();
+    Ok(Json(ManifestResponse {
+        file_key,
+        chunks,
+        total_size,
+        erasure: state.config.load().erasure,
+    }))
+}
+
+/// Handler ĐỌC một khoảng byte của file (xem module vfile). Trả về dữ liệu gốc dạng
+/// `application/octet-stream` kèm header `X-File-Size` là tổng kích thước file; `416`
+/// nếu khoảng nằm ngoài file, `409` nếu file bị ghi lại trong lúc đọc.
+async fn read_vfile(
+    State(state): State<Arc<AppState>>,
+    Path(file_key): Path<String>,
+    Query(query): Query<VfileQuery>,
+) -> Result<Response, ApiError> {
+    let file_key = state.resolve_file_key(file_key)?;
+    let plan = stream_plan(&state, &file_key, query.consistency).await?;
+    let tree = state.tree_for(&file_key).clone();
+    let large = state.large.clone();
+    let max_response_bytes = state.config.load().max_response_bytes;
+
+    let (total, range) = blocking(move || {
+        let io_error = |e: std::io::Error| {
+            tracing::error!("Lỗi khi đọc khoảng byte của file: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        };
+        let extents = vfile::layout(&tree, &large, plan).map_err(io_error)?;
+        let total = vfile::total_len(&extents);
+        let length = query.length.unwrap_or(total.saturating_sub(query.offset));
+        let in_file = query.offset.checked_add(length).is_some_and(|end| end <= total)
+            && (query.offset < total || length == 0);
+        if !in_file {
+            return Ok((total, Err(ApiError::RangeNotSatisfiable)));
+        }
+        if max_response_bytes.is_some_and(|max| length > max as u64) {
+            return Ok((total, Err(ApiError::ResponseTooLarge)));
+        }
+        let range = vfile::read_range(&tree, &large, &extents, query.offset, length).map_err(io_error)?;
+        Ok((total, range.ok_or(ApiError::Conflict)))
+    })
+    .await?;
+
+    let mut headers = HeaderMap::new();
+    headers.insert("x-file-size", HeaderValue::from(total));
+    let data = match range {
+        Ok(data) => data,
+        Err(ApiError::RangeNotSatisfiable) => {
+            let value = HeaderValue::from_str(&format!("bytes */{}", total)).expect("header hợp lệ");
+            headers.insert(header::CONTENT_RANGE, value);
+            return Ok((headers, ApiError::RangeNotSatisfiable.render(error::Lang::En)).into_response());
+        }
+        Err(e) => return Err(e),
+    };
+    tracing::info!("<- Đã đọc {} byte tại offset {} của fileKey: {}", data.len(), query.offset, file_key);
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/octet-stream"));
+    Ok((headers, data).into_response())
+}
+
+/// Handler TẢI file dạng `multipart/mixed`: mỗi chunk là một part theo thứ tự index, với
+/// header `X-Chunk-Hash` (và `X-Chunk-Index` nếu có) cùng dữ liệu gốc làm body, để các
+/// parser multipart chuẩn đọc được file mà không cần xử lý JSON/Base64. Các part được
+/// đọc trước và gửi dần như /stream-download nên bộ nhớ không phụ thuộc kích thước file.
+async fn multipart_download(
+    State(state): State<Arc<AppState>>,
+    Path(file_key): Path<String>,
+    Query(read): Query<ReadQuery>,
+) -> Result<Response, ApiError> {
+    let file_key = state.resolve_file_key(file_key)?;
+    let plan = stream_plan(&state, &file_key, read.consistency).await?;
+    tracing::info!("<- Đang gửi {} chunks dạng multipart cho fileKey: {}", plan.len(), file_key);
+
+    // Boundary đủ dài và ngẫu nhiên để không trùng với dữ liệu chunk
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64);
+    let boundary = format!("chunk-{:016x}{:016x}", nanos, fnv1a_64(file_key.as_bytes()));
+    let content_type = format!("multipart/mixed; boundary={}", boundary);
+
+    let (tx, rx) = tokio::sync::mpsc::channel(state.config.load().stream_prefetch * 2);
+    let tree = state.tree_for(&file_key).clone();
+    let large = state.large.clone();
+    tokio::task::spawn_blocking(move || {
+        for (index, db_key) in plan {
+            let (bytes, content_type) = match fetch_stream_chunk(&tree, &large, &db_key, index) {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => continue, // Chunk đã bị xóa hoặc ghi lại với index khác
+                Err(e) => {
+                    let _ = tx.blocking_send(Err(e));
+                    return;
+                }
+            };
+            let chunk_hash = key::decode(db_key.as_bytes()).map_or("", |k| k.chunk_hash);
+            let mut head = format!(
+                "--{}\r\nContent-Type: {}\r\nContent-Length: {}\r\nX-Chunk-Hash: {}\r\n",
+                boundary,
+                header_safe(content_type.as_deref().unwrap_or("application/octet-stream")),
+                bytes.len(),
+                header_safe(chunk_hash),
+            );
+            if let Some(index) = index {
+                head.push_str(&format!("X-Chunk-Index: {}\r\n", index));
+            }
+            head.push_str("\r\n");
+            let parts = [Bytes::from(head), bytes, Bytes::from_static(b"\r\n")];
+            // Client ngắt kết nối thì channel bị đóng, dừng đọc
+            if parts.into_iter().any(|part| tx.blocking_send(Ok(part)).is_err()) {
+                return;
+            }
+        }
+        let _ = tx.blocking_send(Ok(Bytes::from(format!("--{}--\r\n", boundary))));
+    });
+
+    let body = Body::from_stream(tokio_stream::wrappers::ReceiverStream::new(rx));
+    Ok(([(header::CONTENT_TYPE, content_type)], body).into_response())
+}
+
+/// Handler trả về metrics theo định dạng text của Prometheus
+async fn metrics_handler(State(state): State<Arc<AppState>>) -> Result<String, ApiError> {
+    let (_, stats) = db_stats(&state).await?;
+    let stored_bytes = state.quotas.total().map_err(|e| {
+        tracing::error!("Lỗi khi đọc dung lượng đang lưu: {}", e);
+        ApiError::Internal
+    })?;
+    Ok(state.metrics.render(&metrics::NodeStats {
+        file_keys: state.files.distinct(),
+        file_keys_alert: state.files.check(state.config.load().file_keys_alert),
+        replication_queue: state.replication.depth(),
+        unflushed_writes: state.durability().pending,
+        chunks: stats.chunk_keys,
+        stored_bytes,
+        size_on_disk: stats.size_on_disk,
+    }))
+}
+
+/// Handler nhận thông điệp gossip của một peer và trả về danh sách peer của node này
+async fn gossip_exchange(
+    State(state): State<Arc<AppState>>,
+    Json(message): Json<gossip::GossipMessage>,
+) -> Result<Json<gossip::GossipMessage>, ApiError> {
+    let membership = state.membership.as_ref().ok_or(ApiError::NotFound)?;
+    membership.merge(&message);
+    Ok(Json(membership.message()))
+}
+
+/// Handler trả về danh sách peer hiện tại, để vận hành kiểm tra trạng thái cụm
+async fn gossip_view(State(state): State<Arc<AppState>>) -> Result<Json<gossip::GossipMessage>, ApiError> {
+    let membership = state.membership.as_ref().ok_or(ApiError::NotFound)?;
+    Ok(Json(membership.message()))
+}
+
+/// Handler trả về trang HTML đơn giản liệt kê các file đang lưu trữ, mỗi file kèm link
+/// tới danh sách chunk, manifest và bản tải về. Lỗi khi quét database trả về `500` thay vì
+/// một trang rỗng.
+async fn index_page(State(state): State<Arc<AppState>>) -> Result<impl IntoResponse, ApiError> {
+    let shards = state.shards.clone();
+    let files = blocking(move || {
+        list_files(&shards).map_err(|e| {
+            tracing::error!("Lỗi khi liệt kê file cho trang chủ: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+    })
+    .await?;
+
+    let mut rows = String::new();
+    for file in &files {
+        // Key trong href được mã hóa phần trăm (ký tự '/', '?', '#' không phá đường dẫn) rồi
+        // mới escape HTML; key hiển thị chỉ cần escape HTML
+        let href = html_escape(&format!("/file/{}", path_segment(&file.file_key)));
+        rows.push_str(&format!(
+            "<tr><td><code>{}</code></td><td>{}</td><td><a href=\"{href}\">chunks (JSON)</a> \
+             <a href=\"{href}/manifest\">manifest</a> <a href=\"{href}/stream\">download</a></td></tr>\n",
+            html_escape(&file.file_key),
+            file.chunk_count
+        ));
+    }
+
+    let page = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Storage node</title></head>\n\
+         <body><h1>Stored files ({})</h1>\n\
+         <table border=\"1\" cellpadding=\"4\">\n<tr><th>fileKey</th><th>chunks</th><th>links</th></tr>\n\
+         {rows}</table></body></html>\n",
+        files.len()
+    );
+
+    // Danh sách file thay đổi liên tục nên không cho cache
+    Ok(([(header::CACHE_CONTROL, "no-cache")], Html(page)))
+}
+
+
+// ## CÁC HÀM TIỆN ÍCH ##
+
+/// Chuyển panic trong handler thành response `500` có body JSON và ghi log nội dung panic
+fn handle_panic(err: Box<dyn std::any::Any + Send + 'static>) -> Response {
+    let message = if let Some(s) = err.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = err.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic".to_string()
+    };
+    tracing::error!(panic = %message, "Handler bị panic");
+
+    ApiError::Internal.into_response()
+}
+
+/// Kiểm tra header `X-Api-Key` (hoặc `Authorization: Bearer ...`) của các endpoint /admin:
+/// cần STORAGE_ADMIN_API_KEY hoặc một token có quyền `admin` (xem module auth)
+fn require_admin(state: &AppState, headers: &HeaderMap) -> Result<(), ApiError> {
+    {
+        let config = state.config.load();
+        if config.admin_api_key.is_none() && !config.auth {
+            return Err(ApiError::AdminDisabled);
+        }
+    }
+    match auth::authenticate(state, headers)? {
+        Some(auth::Scope::Admin) => Ok(()),
+        Some(_) => Err(ApiError::Forbidden),
+        None => Err(ApiError::Unauthorized),
+    }
+}
+
+// So sánh hai chuỗi byte trong thời gian không phụ thuộc vào vị trí khác nhau đầu tiên
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Chạy thao tác sled (có thể block khi đĩa chậm) trên blocking pool của tokio
+/// để các thread async vẫn phục vụ được request khác trong lúc chờ IO.
+async fn blocking<T, F>(f: F) -> Result<T, StatusCode>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, StatusCode> + Send + 'static,
+{
+    tokio::task::spawn_blocking(f).await.unwrap_or_else(|e| {
+        tracing::error!("Lỗi khi chạy tác vụ database: {}", e);
+        Err(StatusCode::INTERNAL_SERVER_ERROR)
+    })
+}
+
+/// Flush toàn bộ database xuống đĩa trên blocking pool
+async fn flush_db(state: &Arc<AppState>) -> Result<(), StatusCode> {
+    let state = state.clone();
+    blocking(move || {
+        state.flush().map_err(|e| {
+            tracing::error!("Lỗi khi flush database: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+    })
+    .await
+}
+
+/// Chuẩn bị cho một lượt đọc theo mức nhất quán được yêu cầu. `strong` ghi hết bộ đệm
+/// ghi rồi flush database trước khi quét nên đảm bảo read-your-writes kể cả khi lần ghi
+/// chưa được flush, đổi lại request phải chờ thêm một lần fsync (thường vài ms, lâu hơn
+/// nhiều khi đĩa đang bận ghi). `weak` bỏ qua bước này.
+async fn sync_for_read(state: &Arc<AppState>, consistency: Consistency) -> Result<(), StatusCode> {
+    match consistency {
+        Consistency::Weak => Ok(()),
+        Consistency::Strong => {
+            write_buffer::drain(state).await?;
+            flush_db(state).await
+        }
+    }
+}
+
+/// Lưu một chunk vào database và flush xuống đĩa. Dùng chung cho HTTP và gRPC.
+/// Trả về `403` nếu lần ghi làm tenant sở hữu fileKey vượt quota, `507` nếu vượt giới hạn
+/// dung lượng của node (xem module usage), `409` nếu key đã có chunk khác mà
+/// STORAGE_OVERWRITE_MODE không cho ghi đè hoặc chunk đó được ghi bằng chế độ khác, trừ khi
+/// `overwrite` (`?overwrite=true`) được bật. Trả về `true` nếu value trỏ tới blob cùng nội
+/// dung đã có sẵn thay vì được lưu thêm (xem module dedup).
+async fn save_chunk(
+    state: &Arc<AppState>,
+    file_key: &str,
+    chunk_hash: &str,
+    db_value: StoredChunkValue,
+    overwrite: bool,
+) -> Result<bool, ApiError> {
+    // Key chứa ':' hoặc thành phần rỗng sẽ làm hỏng key tổng hợp
+    state.ensure_valid_key(file_key, chunk_hash)?;
+
+    let mut write = state.pending_write(file_key, chunk_hash, db_value)?;
+    write.set_overwrite(overwrite);
+    state.ensure_capacity(std::slice::from_ref(&write))?;
+
+    tracing::info!("-> Đang lưu chunk với key: {}", String::from_utf8_lossy(&write.key));
+
+    // Lưu cặp key-value vào Sled DB (kèm cập nhật quota) và đảm bảo dữ liệu được ghi xuống đĩa
+    let state = state.clone();
+    Ok(blocking(move || state.commit_writes(&[write])).await?.contains(&true))
+}
+
+/// Áp dụng `patch` lên record của một chunk. Trả về `None` nếu chunk không tồn tại,
+/// ngược lại trả về metadata mới cùng độ chênh kích thước record (để cập nhật quota).
+/// Chạy đồng bộ nên cần được gọi qua `blocking`.
+fn update_chunk_metadata(
+    state: &AppState,
+    file_key: &str,
+    chunk_hash: &str,
+    patch: &ChunkPatch,
+) -> Result<Option<(ChunkMetadata, i64)>, StatusCode> {
+    let tree = state.tree_for(file_key);
+    let db_key = key::encode(file_key, chunk_hash);
+    let db_error = |e: sled::Error| {
+        tracing::error!("Lỗi khi cập nhật chunk {}: {}", db_key, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    loop {
+        let Some(old_bytes) = tree.get(db_key.as_bytes()).map_err(db_error)? else {
+            return Ok(None);
+        };
+        let Some(mut record) = record::decode(&old_bytes) else {
+            tracing::error!("Value của chunk {} bị hỏng", db_key);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        };
+
+        let old_index = record.index;
+        if let Some(index) = patch.index {
+            record.index = index;
+        }
+        if let Some(content_type) = &patch.content_type {
+            record.content_type = content_type.clone();
+        }
+        if let Some(stored_at) = patch.stored_at {
+            record.stored_at = stored_at;
+        }
+        let metadata = ChunkMetadata {
+            key: db_key.clone(),
+            index: record.index,
+            content_type: record.content_type.clone(),
+            stored_at: record.stored_at,
+        };
+        let new_bytes = encode_value(record, &state.config.load())?;
+        let size_delta = new_bytes.len() as i64 - old_bytes.len() as i64;
+
+        match tree
+            .compare_and_swap(db_key.as_bytes(), Some(&old_bytes), Some(new_bytes))
+            .map_err(db_error)?
+        {
+            Ok(()) => {}
+            Err(_) => continue, // Chunk vừa bị ghi bởi request khác, đọc lại và thử lại
+        }
+
+        // Entry cũ của index thứ tự trở thành lỗi thời nên được thay bằng entry mới
+        if let Some(order) = &state.order
+            && old_index != metadata.index
+        {
+            order.remove(order::entry_key(file_key, old_index, chunk_hash)).map_err(db_error)?;
+            order.insert(order::entry_key(file_key, metadata.index, chunk_hash), &[]).map_err(db_error)?;
+        }
+        return Ok(Some((metadata, size_delta)));
+    }
+}
+
+/// Các stripe parity của `file_key` cùng key của chúng trong tree `parity`. Bỏ qua key có
+/// cùng prefix nhưng thuộc fileKey khác (fileKey chứa ':').
+fn parity_stripes(state: &AppState, file_key: &str) -> Result<Vec<(sled::IVec, parity::Stripe)>, StatusCode> {
+    let prefix = key::prefix(file_key);
+    let mut stripes = Vec::new();
+    for entry in state.parity.scan_prefix(prefix.as_bytes()) {
+        let (stripe_key, value) = entry.map_err(|e| {
+            tracing::error!("Lỗi khi đọc parity của file {}: {}", file_key, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        if stripe_key.len() != prefix.len() + 4 {
+            continue;
+        }
+        let stripe = serde_json::from_slice(&value).map_err(|e| {
+            tracing::error!("Stripe parity của file {} bị hỏng: {}", file_key, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        stripes.push((stripe_key, stripe));
+    }
+    Ok(stripes)
+}
+
+// Xóa mọi stripe parity của `file_key`
+fn remove_parity(state: &AppState, file_key: &str) -> Result<(), StatusCode> {
+    let mut batch = sled::Batch::default();
+    for (stripe_key, _) in parity_stripes(state, file_key)? {
+        batch.remove(stripe_key);
+    }
+    state.parity.apply_batch(batch).map_err(|e| {
+        tracing::error!("Lỗi khi xóa parity của file {}: {}", file_key, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+/// Tính và lưu parity cho toàn bộ chunk của `file_key` theo thứ tự index, thay parity cũ.
+/// Trả về số stripe. Chạy đồng bộ nên cần được gọi qua `blocking`.
+fn store_parity(state: &AppState, file_key: &str, data_shards: usize, parity_shards: usize) -> Result<usize, StatusCode> {
+    let tree = state.tree_for(file_key);
+    let read_error = |e: std::io::Error| {
+        tracing::error!("Lỗi khi đọc chunk để tính parity: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let mut chunks = Vec::new();
+    for entry in tree.scan_prefix(key::prefix(file_key).as_bytes()) {
+        let (db_key, value) = entry.map_err(|e| read_error(std::io::Error::other(e)))?;
+        let Some((_, chunk_hash)) = key::decode_raw(&db_key) else {
+            continue;
+        };
+        let record = record::decode(&value).ok_or_else(|| read_error(invalid_chunk("value bị hỏng", &db_key)))?;
+        let (bytes, record) = decode_chunk_data(tree, &state.large, &db_key, record).map_err(read_error)?;
+        let meta = parity::StripeChunk {
+            hash: chunk_hash.to_string(),
+            index: record.index,
+            content_type: record.content_type,
+            mode: record.mode,
+            metadata: record.metadata,
+            len: bytes.len(),
+            sha256: parity::digest(&bytes),
+        };
+        chunks.push((meta, bytes.to_vec()));
+    }
+    // Theo index thứ tự; chunk không có index xếp cuối theo chunkHash
+    chunks.sort_by(|(a, _), (b, _)| (a.index.is_none(), a.index, &a.hash).cmp(&(b.index.is_none(), b.index, &b.hash)));
+
+    remove_parity(state, file_key)?;
+    let stripes = parity::build(chunks, data_shards, parity_shards);
+    let mut batch = sled::Batch::default();
+    for (n, stripe) in stripes.iter().enumerate() {
+        let value = serde_json::to_vec(stripe).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        batch.insert(parity::stripe_key(file_key, n as u32), value);
+    }
+    state.parity.apply_batch(batch).map_err(|e| {
+        tracing::error!("Lỗi khi lưu parity của file {}: {}", file_key, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok(stripes.len())
+}
+
+// chunkHash của các chunk đã dựng lại và của các chunk không dựng lại được
+type Reconstructed = (Vec<String>, Vec<String>);
+
+/// Dựng lại và ghi lại các chunk bị mất (hoặc không còn khớp SHA-256 lúc seal) của
+/// `file_key` từ parity; `None` nếu file không có parity. Chạy đồng bộ nên cần được gọi
+/// qua `blocking`.
+fn reconstruct_chunks(state: &AppState, file_key: &str) -> Result<Option<Reconstructed>, StatusCode> {
+    let stripes = parity_stripes(state, file_key)?;
+    if stripes.is_empty() {
+        return Ok(None);
+    }
+    let tree = state.tree_for(file_key);
+    let (mut reconstructed, mut unrecoverable, mut writes) = (Vec::new(), Vec::new(), Vec::new());
+
+    for (_, stripe) in stripes {
+        let present: Vec<Option<Vec<u8>>> = stripe
+            .chunks
+            .iter()
+            .map(|chunk| {
+                let db_key = key::encode(file_key, &chunk.hash);
+                match read_chunk(tree, &state.large, db_key.as_bytes()) {
+                    Ok(Some((bytes, _))) if parity::digest(&bytes) == chunk.sha256 => Some(bytes.to_vec()),
+                    _ => None,
+                }
+            })
+            .collect();
+        if present.iter().all(Option::is_some) {
+            continue;
+        }
+
+        let Some(rebuilt) = parity::reconstruct(&stripe, &present) else {
+            let lost = stripe.chunks.iter().zip(&present).filter(|(_, data)| data.is_none());
+            unrecoverable.extend(lost.map(|(chunk, _)| chunk.hash.clone()));
+            continue;
+        };
+        for (i, data) in rebuilt {
+            let chunk = &stripe.chunks[i];
+            let db_value = StoredChunkValue {
+                content_type: chunk.content_type.clone(),
+                mode: chunk.mode,
+                metadata: chunk.metadata.clone(),
+                ..StoredChunkValue::new(STANDARD.encode(&data), chunk.index)
+            };
+            let mut write = state.pending_write(file_key, &chunk.hash, db_value)?;
+            // Chunk hỏng vẫn còn record cũ cần được thay
+            write.set_overwrite(true);
+            writes.push(write);
+            reconstructed.push(chunk.hash.clone());
+        }
+    }
+
+    if !writes.is_empty() {
+        state.commit_writes(&writes)?;
+    }
+    Ok(Some((reconstructed, unrecoverable)))
+}
+
+/// Dữ liệu gốc của một record đã lưu (giải nén, dựng lại nếu là delta); `None` nếu
+/// record bị hỏng hoặc không dựng lại được
+fn stored_data(state: &AppState, file_key: &str, record: &StoredChunkValue) -> Option<Vec<u8>> {
+    let Some(base_hash) = &record.delta_base else {
+        return delta::raw_of(record);
+    };
+    let base = state.delta_base_data(file_key, base_hash)?;
+    STANDARD.decode(delta::apply(&base, &record.value)?).ok()
+}
+
+/// Thay dữ liệu của chunk bằng `payload.new_value` nếu dữ liệu hiện tại đúng bằng
+/// `expected`, dùng `compare_and_swap` của sled trên đúng bytes vừa đọc. Nếu chunk bị ghi
+/// bởi request khác giữa lúc đọc và lúc swap thì trả về `Mismatch` với giá trị mới nhất
+/// thay vì thử lại, để client tự quyết định. STORAGE_OVERWRITE_MODE không áp dụng ở đây
+/// vì client đã nêu rõ giá trị mong đợi. Value lớn được giữ trong record với backend
+/// `sled`, và được ghi vào backend như mọi lần ghi khác với backend ngoài sled (xem module
+/// store). Chạy đồng bộ nên cần được gọi qua `blocking`.
+fn swap_chunk(
+    state: &AppState,
+    file_key: &str,
+    chunk_hash: &str,
+    expected: Option<Vec<u8>>,
+    payload: CasPayload,
+) -> Result<CasOutcome, ApiError> {
+    let tree = state.tree_for(file_key);
+    let db_key = key::encode(file_key, chunk_hash);
+    let db_error = |e: sled::Error| {
+        tracing::error!("Lỗi khi compare-and-swap chunk {}: {}", db_key, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    // Dữ liệu gốc của bytes đang lưu; bytes hỏng là lỗi của server chứ không phải xung đột
+    let current_data = |bytes: Option<&[u8]>| -> Result<Option<Vec<u8>>, StatusCode> {
+        let Some(bytes) = bytes else {
+            return Ok(None);
+        };
+        let record = record::decode(bytes).and_then(|record| tier::fill(&state.large, db_key.as_bytes(), record));
+        match record.and_then(|record| stored_data(state, file_key, &record)) {
+            Some(data) => Ok(Some(data)),
+            None => {
+                tracing::error!("Value của chunk {} bị hỏng", db_key);
+                Err(StatusCode::INTERNAL_SERVER_ERROR)
+            }
+        }
+    };
+
+    // Với backend ngoài sled, value tách khỏi record được ghi dưới khóa của fileKey như mọi
+    // thao tác đổi value khác (xem `tier::Large::lock`)
+    let locked = state.large.lock([file_key.as_bytes()]);
+    let old_bytes = tree.get(db_key.as_bytes()).map_err(db_error)?;
+    let current = current_data(old_bytes.as_deref())?;
+    if current != expected {
+        return Ok(CasOutcome::Mismatch(current.map(|data| STANDARD.encode(data))));
+    }
+
+    let old_record = old_bytes.as_deref().and_then(record::decode);
+    let old_index = old_record.as_ref().and_then(|r| r.index);
+    // Value trong chunks_large (hoặc blob) mà record hiện tại trỏ tới, bỏ sau khi swap thành công
+    let old_large = match &old_record {
+        Some(record) if record.large => state.large.get(db_key.as_bytes()).map_err(db_error)?,
+        _ => None,
+    };
+    let db_value = StoredChunkValue {
+        content_type: payload
+            .content_type
+            .or_else(|| old_record.as_ref().and_then(|r| r.content_type.clone())),
+        metadata: old_record.as_ref().map(|r| r.metadata.clone()).unwrap_or_default(),
+        ..StoredChunkValue::new(payload.new_value, payload.chunk_index.or(old_index))
+    };
+    let metadata = ChunkMetadata {
+        key: db_key.clone(),
+        index: db_value.index,
+        content_type: db_value.content_type.clone(),
+        stored_at: db_value.stored_at,
+    };
+    let mut write = state.pending_write(file_key, chunk_hash, db_value)?;
+    write.blob = None;
+    if state.large.tree().is_some()
+        && let Some(value) = write.large.take()
+    {
+        // Chỉ swap được một tree nên giữ value trong record; chunk chuyển sang tree
+        // chunks_large ở lần ghi tiếp theo qua /store
+        let record = tier::decode_with(&write.value, Some(&value)).ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+        write.value = encode_value(record, &state.config.load())?;
+    }
+
+    // Kiểm tra quota và giới hạn dung lượng của node trước khi swap; sai lệch nhỏ khi nhiều
+    // request cùng tăng dung lượng được chấp nhận như với PATCH
+    let new_len = write.value.len() + write.large.as_ref().map_or(0, Vec::len);
+    let size_delta = new_len as i64 - old_bytes.as_ref().map_or(0, |b| b.len() as i64);
+    if size_delta > 0
+        && let Some(limit) = state.quotas.limit_for(&write.owner)
+        && state.quotas.used(&write.owner).map_err(db_error)? + size_delta as u64 > limit
+    {
+        tracing::warn!("Tenant {} vượt quota ({} byte)", write.owner, limit);
+        return Err(ApiError::QuotaExceeded);
+    }
+    state.ensure_capacity(std::slice::from_ref(&write))?;
+
+    // Backend ngoài sled: value mới được ghi trước khi swap record và được trả lại như cũ
+    // nếu swap không thành (xem `tier::Large::stage`)
+    let staged = match write.large.take() {
+        Some(value) => Some(state.large.stage(vec![(db_key.as_bytes().into(), value.into())], Vec::new()).map_err(db_error)?),
+        None => None,
+    };
+    let swapped = tree.compare_and_swap(db_key.as_bytes(), old_bytes.as_ref(), Some(write.value.as_slice()));
+    // Value cũ cùng key trong backend đã bị value mới ghi đè
+    let overwritten = match (swapped, staged) {
+        (Ok(Ok(())), Some(staged)) => {
+            let previous = staged.get(db_key.as_bytes()).map_err(db_error)?;
+            staged.commit(&Default::default());
+            previous.is_some()
+        }
+        (Ok(Ok(())), None) => false,
+        (result, staged) => {
+            if let Some(staged) = staged {
+                staged.rollback();
+            }
+            let conflict = result.map_err(db_error)?.expect_err("swap thành công đã được xử lý ở trên");
+            let current = current_data(conflict.current.as_deref())?;
+            return Ok(CasOutcome::Mismatch(current.map(|data| STANDARD.encode(data))));
+        }
+    };
+    drop(locked);
+
+    state.quotas.adjust(&write.owner, size_delta);
+    // Value cũ trong chunks_large hoặc blob không còn được record nào dùng. Chỉ bỏ nếu vẫn
+    // đúng là value cũ, phòng khi một lần /store vừa ghi value lớn mới cho key này.
+    if let Some(old_large) = old_large
+        && (overwritten || state.large.remove_if(db_key.as_bytes(), &old_large).map_err(db_error)?)
+    {
+        state.quotas.adjust(&write.owner, -(old_large.len() as i64));
+        state.files.resized(file_key, -(old_large.len() as i64));
+    }
+    if let (Some(order), Some(order_key)) = (&state.order, &write.order_key) {
+        if old_bytes.is_some() && old_index != metadata.index {
+            order.remove(order::entry_key(file_key, old_index, chunk_hash)).map_err(db_error)?;
+        }
+        order.insert(order_key.as_slice(), &[]).map_err(db_error)?;
+    }
+    if old_bytes.is_none() {
+        state.files.created(file_key, size_delta as u64);
+        state.files.check(state.config.load().file_keys_alert);
+    } else {
+        state.files.resized(file_key, size_delta);
+    }
+    Ok(CasOutcome::Swapped(metadata))
+}
+
+/// Xóa toàn bộ chunk của `file_key` (cùng entry index thứ tự, value của chunk lớn và
+/// manifest nếu có) trong một transaction, trừ luôn dung lượng của tenant `owner` và số
+/// chunk của file. Trả về các chunkHash đã xóa. Chạy đồng bộ nên cần được gọi qua `blocking`.
+fn delete_chunks(state: &AppState, tree: &sled::Tree, file_key: &[u8], owner: &str) -> Result<Vec<String>, StatusCode> {
+    let order = state.order.as_ref();
+    let _locked = state.large.lock([file_key]);
+    let scan_error = |e: sled::Error| {
+        tracing::error!("Lỗi khi quét database: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    let mut batch = sled::Batch::default();
+    let mut removed = Vec::new();
+    let mut removed_keys = Vec::new();
+    let mut removed_bytes = 0;
+    for result in tree.scan_prefix(key::prefix_raw(file_key)) {
+        let (key_bytes, value_bytes) = result.map_err(scan_error)?;
+        let Some(chunk_hash) = key::chunk_hash_in_raw(&key_bytes, file_key) else {
+            continue; // Key của một fileKey khác có chứa ':'
+        };
+        removed.push(chunk_hash.to_string());
+        removed_bytes += value_bytes.len() as u64;
+        batch.remove(key_bytes.clone());
+        removed_keys.push(key_bytes);
+    }
+    if removed.is_empty() {
+        return Ok(removed);
+    }
+
+    // Mỗi tree bị thay đổi đi kèm một batch
+    let mut trees = vec![tree];
+    let mut batches = vec![batch];
+    let mut large_keys = Vec::new();
+    for (key_bytes, value_bytes) in state.large.entries_of(file_key).map_err(scan_error)? {
+        removed_bytes += value_bytes.len() as u64;
+        large_keys.push(key_bytes);
+    }
+    let (large_batch, staged) = state.large.changes(Vec::new(), large_keys).map_err(scan_error)?;
+    if let Some((large, large_batch)) = large_batch {
+        trees.push(large);
+        batches.push(large_batch);
+    }
+    // fileKey nhị phân không có entry trong index thứ tự (xem `pending_write_raw`)
+    if let (Some(order), Ok(file_key)) = (order, std::str::from_utf8(file_key)) {
+        let mut order_batch = sled::Batch::default();
+        for (entry, _, _) in order::entries_of(order, file_key) {
+            order_batch.remove(entry);
+        }
+        trees.push(order);
+        batches.push(order_batch);
+    }
+
+    // Dung lượng của tenant, số chunk và manifest của file đổi cùng transaction với dữ liệu,
+    // tham chiếu tới blob dùng chung cũng vậy (blob không còn ai dùng bị xóa luôn)
+    let blobs = &state.large.blobs;
+    trees.extend([&state.quotas.usage, &state.files.tree, &state.manifests, &blobs.files, &blobs.chunks]);
+    let result = apply_batches(&trees, batches, |txs| {
+        let [usage, files, manifests, blob_files, blob_chunks] = txs else {
+            unreachable!("năm tree bộ đếm đứng sau các batch");
+        };
+        let mut freed = removed_bytes;
+        for key_bytes in &removed_keys {
+            if let Some(blob) = dedup::release_in(blob_files, blob_chunks, key_bytes)? {
+                freed += blob.len() as u64;
+            }
+        }
+        quota::adjust_in(usage, owner, -(freed as i64))?;
+        manifest::remove_in(manifests, file_key)?;
+        Ok(cardinality::remove_in(files, file_key)?)
+    });
+    match result {
+        Ok(distinct) => {
+            staged.commit(&Default::default());
+            state.files.shift(distinct);
+        }
+        Err(e) => {
+            staged.rollback();
+            tracing::error!("Lỗi khi ghi batch xóa file: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+    Ok(removed)
+}
+
+/// Áp dụng mỗi batch lên tree tương ứng trong một transaction để không ai thấy trạng thái
+/// dở dang, rồi chạy `extra` trên các tree đứng sau các tree có batch
+/// (`trees[batches.len()..]`) để các bộ đếm (quota, số chunk của file...) đổi cùng lúc
+fn apply_batches<R>(
+    trees: &[&sled::Tree],
+    batches: Vec<sled::Batch>,
+    extra: impl Fn(&[sled::transaction::TransactionalTree]) -> sled::transaction::ConflictableTransactionResult<R, ()>,
+) -> sled::Result<R> {
+    trees
+        .transaction(|txs| {
+            for (tx, batch) in txs.iter().zip(&batches) {
+                tx.apply_batch(batch)?;
+            }
+            extra(&txs[batches.len()..])
+        })
+        .map_err(|e: sled::transaction::TransactionError<()>| match e {
+            sled::transaction::TransactionError::Storage(e) => e,
+            sled::transaction::TransactionError::Abort(()) => sled::Error::Unsupported("transaction bị hủy".into()),
+        })
+}
+
+/// Chuyển toàn bộ chunk của `file_key` sang `new_file_key` (cùng entry index thứ tự, thời
+/// điểm hết hạn và manifest nếu có) trong một transaction, cập nhật luôn dung lượng của hai
+/// tenant và số chunk của hai file. Chạy đồng bộ nên cần được gọi qua `blocking`.
+fn move_chunks(state: &AppState, file_key: &str, new_file_key: &str, overwrite: bool) -> Result<MoveResult, StatusCode> {
+    // fileKey cũ và mới có thể nằm ở hai shard khác nhau
+    let (source, target) = (state.tree_for(file_key), state.tree_for(new_file_key));
+    let order = state.order.as_ref();
+    let _locked = state.large.lock([file_key.as_bytes(), new_file_key.as_bytes()]);
+    let old_prefix = key::prefix(file_key);
+
+    // Các chunk đang có sẵn dưới fileKey đích
+    let mut existing_targets = Vec::new();
+    let mut replaced_bytes = 0;
+    for kv in target.scan_prefix(key::prefix(new_file_key).as_bytes()) {
+        match kv {
+            Ok((k, v)) => {
+                if key::chunk_hash_in(&k, new_file_key).is_none() {
+                    continue;
+                }
+                replaced_bytes += v.len() as u64;
+                existing_targets.push(k);
+            }
+            Err(e) => {
+                tracing::error!("Lỗi khi quét database: {}", e);
+                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            }
+        }
+    }
+    if !existing_targets.is_empty() && !overwrite {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    // Gom mọi thay đổi vào batch để sled áp dụng nguyên tử. Nếu cùng một tree thì
+    // gộp chung một batch (key cũ và mới khác prefix nên không đè lên nhau).
+    let same_tree = source.name() == target.name();
+    let mut source_batch = sled::Batch::default();
+    let mut target_batch = sled::Batch::default();
+    for key in &existing_targets {
+        target_batch.remove(key);
+    }
+
+    // (key cũ, key mới) của các chunk được chuyển, để chuyển cả ánh xạ tới blob
+    let mut moved_keys = Vec::new();
+    let mut moved = 0;
+    let mut moved_bytes = 0;
+    for result in source.scan_prefix(old_prefix.as_bytes()) {
+        let (key_bytes, value_bytes) = match result {
+            Ok(kv) => kv,
+            Err(e) => {
+                tracing::error!("Lỗi khi quét database: {}", e);
+                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            }
+        };
+        let Some(chunk_hash) = key::chunk_hash_in(&key_bytes, file_key) else {
+            continue; // Key của một fileKey khác có chứa ':'
+        };
+        let new_key = key::encode(new_file_key, chunk_hash);
+
+        moved_bytes += value_bytes.len() as u64;
+        target_batch.insert(new_key.as_bytes(), value_bytes);
+        if same_tree {
+            target_batch.remove(key_bytes.clone());
+        } else {
+            source_batch.remove(key_bytes.clone());
+        }
+        moved_keys.push((key_bytes, new_key));
+        moved += 1;
+    }
+
+    if moved == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    // Mỗi tree bị thay đổi đi kèm một batch
+    let mut trees = vec![target];
+    let mut batches = vec![target_batch];
+    if !same_tree {
+        trees.push(source);
+        batches.push(source_batch);
+    }
+    let scan_error = |e: sled::Error| {
+        tracing::error!("Lỗi khi quét database: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    let expiry_batch = ttl::rename_batch(&state.expiry, file_key, new_file_key).map_err(scan_error)?;
+    // Value của chunk lớn đi theo key của chunk: xóa của fileKey đích, chuyển của fileKey nguồn
+    // (backend ngoài sled được chép trước transaction, xem `tier::Large::stage`)
+    let (mut large_puts, mut large_deletes) = (Vec::new(), Vec::new());
+    for (key_bytes, value_bytes) in state.large.entries_of(new_file_key.as_bytes()).map_err(scan_error)? {
+        replaced_bytes += value_bytes.len() as u64;
+        large_deletes.push(key_bytes);
+    }
+    for (key_bytes, value_bytes) in state.large.entries_of(file_key.as_bytes()).map_err(scan_error)? {
+        let Some(chunk_hash) = key::chunk_hash_in(&key_bytes, file_key) else {
+            continue;
+        };
+        moved_bytes += value_bytes.len() as u64;
+        large_puts.push((key::encode(new_file_key, chunk_hash).as_bytes().into(), value_bytes));
+        large_deletes.push(key_bytes);
+    }
+    let (large_batch, staged) = state.large.changes(large_puts, large_deletes).map_err(scan_error)?;
+    if let Some((large, large_batch)) = large_batch {
+        trees.push(large);
+        batches.push(large_batch);
+    }
+    if let Some(order) = order {
+        let mut order_batch = sled::Batch::default();
+        for (entry, _, _) in order::entries_of(order, new_file_key) {
+            order_batch.remove(entry);
+        }
+        for (entry, index, chunk_hash) in order::entries_of(order, file_key) {
+            order_batch.remove(entry);
+            order_batch.insert(order::entry_key(new_file_key, index, &chunk_hash), &[]);
+        }
+        trees.push(order);
+        batches.push(order_batch);
+    }
+
+    trees.push(&state.expiry);
+    batches.push(expiry_batch);
+
+    // Dung lượng chuyển sang tenant mới; đổi tên không kiểm tra quota vì dữ liệu đã nằm sẵn trên node
+    // Ánh xạ tới blob dùng chung cũng đi theo key của chunk: bỏ của fileKey đích rồi chuyển
+    // của fileKey nguồn, số tham chiếu của blob được chuyển không đổi
+    let (old_owner, new_owner) = (state.quotas.owner(file_key), state.quotas.owner(new_file_key));
+    let blobs = &state.large.blobs;
+    trees.extend([&state.quotas.usage, &state.files.tree, &state.manifests, &blobs.files, &blobs.chunks]);
+    let result = apply_batches(&trees, batches, |txs| {
+        let [usage, files, manifests, blob_files, blob_chunks] = txs else {
+            unreachable!("năm tree bộ đếm đứng sau các batch");
+        };
+        let (mut moved_bytes, mut replaced_bytes) = (moved_bytes, replaced_bytes);
+        for key_bytes in &existing_targets {
+            if let Some(blob) = dedup::release_in(blob_files, blob_chunks, key_bytes)? {
+                replaced_bytes += blob.len() as u64;
+            }
+        }
+        for (key_bytes, new_key) in &moved_keys {
+            if let Some(blob) = dedup::rename_in(blob_files, blob_chunks, key_bytes, new_key.as_bytes())? {
+                moved_bytes += blob.len() as u64;
+            }
+        }
+        quota::adjust_in(usage, old_owner, -(moved_bytes as i64))?;
+        quota::adjust_in(usage, new_owner, moved_bytes as i64 - replaced_bytes as i64)?;
+        manifest::rename_in(manifests, file_key, new_file_key)?;
+        Ok(cardinality::move_in(files, file_key.as_bytes(), new_file_key.as_bytes(), moved as u64)?)
+    });
+    match result {
+        Ok(distinct) => {
+            staged.commit(&Default::default());
+            state.files.shift(distinct);
+        }
+        Err(e) => {
+            staged.rollback();
+            tracing::error!("Lỗi khi ghi batch đổi tên: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+    Ok(MoveResult { moved })
+}
+
+/// Số byte mà một chuỗi Base64 hợp lệ mã hóa, tính từ độ dài chuỗi mà không cần giải mã
+fn base64_decoded_len(value: &str) -> u64 {
+    let padding = value.bytes().rev().take_while(|&b| b == b'=').count().min(2);
+    (value.len() * 3 / 4).saturating_sub(padding) as u64
+}
+
+/// Serialize value thành bytes để lưu trữ theo định dạng đã cấu hình
+fn encode_value(db_value: StoredChunkValue, config: &Config) -> Result<Vec<u8>, StatusCode> {
+    let bytes = record::encode(db_value, config.value_format).map_err(|e| {
+        tracing::error!("Lỗi khi serialize value: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok(protect(bytes, config))
+}
+
+// Mã hóa (xem module cipher) rồi thêm mã xóa (xem module erasure) cho bytes sắp ghi xuống
+fn protect(bytes: Vec<u8>, config: &Config) -> Vec<u8> {
+    erasure::encode(cipher::encrypt(bytes, config.encryption.as_ref()), config.erasure)
+}
+
+// Tách value của chunk ra khỏi record nếu là chunk lớn, hoặc với mọi chunk khác rỗng khi bật
+// STORAGE_DEDUP hay khi backend không phải sled (xem module store). Trả về value đã bảo vệ để
+// ghi riêng và hash của blob khi khử trùng lặp.
+fn split_value(db_value: &mut StoredChunkValue, config: &Config) -> (Option<Vec<u8>>, Option<[u8; 32]>) {
+    let threshold = if config.dedup || config.backend != store::Backend::Sled {
+        Some(0)
+    } else {
+        config.large_chunk_bytes
+    };
+    let Some(value) = tier::split(db_value, threshold) else {
+        return (None, None);
+    };
+    let blob = config.dedup.then(|| dedup::hash(&value));
+    (Some(protect(value, config)), blob)
+}
+
+/// Quét tất cả chunk của một file trên blocking pool. Khi bật index thứ tự, chunk được
+/// trả về theo chunkIndex tăng dần (chunk không có index đứng cuối); nếu không thì theo
+/// thứ tự key trong sled. Chunk nén bằng codec không có trong `accepted` được giải nén.
+async fn load_file_chunks(
+    state: &Arc<AppState>,
+    file_key: &str,
+    accepted: &[compress::Codec],
+) -> Result<Vec<Chunk>, StatusCode> {
+    Ok(load_file_chunks_timed(state, file_key, accepted).await?.0)
+}
+
+/// Như `load_file_chunks_timed`, nhưng khi bật STORAGE_READ_COALESCING thì các lượt đọc
+/// cùng fileKey (và cùng codec được chấp nhận) tới trong lúc một lượt quét đang chạy sẽ
+/// chờ và nhận bản sao kết quả của lượt quét đó thay vì tự quét lại, tránh việc nhiều
+/// client cùng đọc một file nóng làm database phải quét lặp lại cùng một prefix. Lượt
+/// đọc tới sau có thể không thấy lần ghi hoàn tất trong lúc lượt quét chung đang chạy, nên
+/// chỉ dùng cho đọc `consistency=weak`. Nếu request đang quét bị hủy, một request đang chờ
+/// sẽ quét thay.
+async fn load_file_chunks_shared(
+    state: &Arc<AppState>,
+    file_key: &str,
+    accepted: &[compress::Codec],
+) -> Result<(Vec<Chunk>, ReadTimings), StatusCode> {
+    if !state.config.load().read_coalescing {
+        return load_file_chunks_timed(state, file_key, accepted).await;
+    }
+
+    let flight_key = (file_key.to_string(), accepted.to_vec());
+    let flight = {
+        let mut flights = state.read_flights.lock().unwrap();
+        match flights.get(&flight_key) {
+            Some(flight) => {
+                state.metrics.coalesced_reads.fetch_add(1, Ordering::Relaxed);
+                flight.clone()
+            }
+            None => {
+                let flight = Arc::new(ReadFlight::new());
+                flights.insert(flight_key.clone(), flight.clone());
+                flight
+            }
+        }
+    };
+    let result = flight
+        .get_or_init(|| async { load_file_chunks_timed(state, file_key, accepted).await.map(Arc::new) })
+        .await
+        .clone();
+
+    // Lượt đọc tới sau khi đã có kết quả phải quét lại để thấy dữ liệu mới
+    let mut flights = state.read_flights.lock().unwrap();
+    if flights.get(&flight_key).is_some_and(|current| Arc::ptr_eq(current, &flight)) {
+        flights.remove(&flight_key);
+    }
+    drop(flights);
+    result.map(|shared| (shared.0.clone(), shared.1))
+}
+
+/// Như `load_file_chunks`, kèm thời gian của từng bước
+async fn load_file_chunks_timed(
+    state: &Arc<AppState>,
+    file_key: &str,
+    accepted: &[compress::Codec],
+) -> Result<(Vec<Chunk>, ReadTimings), StatusCode> {
+    let tree = state.tree_for(file_key).clone();
+    let large = state.large.clone();
+    let order = state.order.clone();
+    let file_key = file_key.to_string();
+    let accepted = accepted.to_vec();
+    let parallelism = state.config.load().read_parallelism;
+    if parallelism > 1 {
+        let min_chunks = state.config.load().parallel_read_min_chunks;
+        return load_file_chunks_parallel(tree, large, order, file_key, accepted, parallelism, min_chunks).await;
+    }
+    blocking(move || {
+        let mut timings = ReadTimings::default();
+        let chunks = timings.measure(
+            |t| &mut t.scan,
+            || {
+                let chunks = match order {
+                    Some(order) => scan_ordered_chunks(&tree, &order, &file_key),
+                    None => scan_file_chunks(&tree, &file_key),
+                };
+                tier::resolve(chunks, &large)
+            },
+        );
+        let chunks = timings.measure(|t| &mut t.delta, || delta::resolve(chunks, &file_key));
+        let chunks = timings.measure(|t| &mut t.decompress, || decode_chunks(chunks, &accepted));
+        Ok((chunks, timings))
+    })
+    .await
+}
+
+// Một trang chunk của file cho /file/:fileKey cùng các thông tin của cả file
+struct FilePage {
+    chunks: Vec<Chunk>,
+    // Số chunk của cả file
+    total: usize,
+    // Thời điểm sửa đổi cuối của file là stored_at lớn nhất trong các chunk, kể cả khi
+    // chỉ trả về một trang
+    last_modified: Option<u64>,
+    next_cursor: Option<String>,
+}
+
+/// Đọc trang `page` của file cho /file/:fileKey có phân trang. Chỉ quét key và record của
+/// file (không giữ value, xem `scan_file_keys`) để đếm chunk, lấy Last-Modified và cắt
+/// trang; value của chunk lớn, chunk delta và chunk nén chỉ được đọc cho các chunk trong
+/// trang, cùng base nằm ngoài trang của chunk delta. Không dùng chung lượt quét với
+/// STORAGE_READ_COALESCING.
+async fn load_file_page(
+    state: &Arc<AppState>,
+    file_key: &str,
+    accepted: &[compress::Codec],
+    page: &PageQuery,
+    manifest: Option<&manifest::Manifest>,
+) -> Result<(FilePage, ReadTimings), ApiError> {
+    let mut timings = ReadTimings::default();
+    let started = Instant::now();
+    let (mut keys, tree) = {
+        let tree = state.tree_for(file_key).clone();
+        let order = state.order.clone();
+        let file_key = file_key.to_string();
+        blocking(move || Ok((scan_file_keys(&tree, order.as_ref(), &file_key), tree))).await?
+    };
+    timings.scan = started.elapsed();
+    let total = keys.len();
+    let last_modified = keys.iter().filter_map(|(_, stored_at)| *stored_at).max();
+    if let Some(manifest) = manifest {
+        manifest.sort(&mut keys, |(db_key, _)| key::decode(db_key.as_bytes()).map(|k| k.chunk_hash));
+    }
+    let ordered = state.order.is_some() || manifest.is_some();
+    let next_cursor = page.apply(&mut keys, ordered, |(db_key, _)| db_key.as_str())?;
+
+    let large = state.large.clone();
+    let file_key = file_key.to_string();
+    let accepted = accepted.to_vec();
+    let (chunks, timings) = blocking(move || {
+        let now = unix_now();
+        let read = |db_key: String| {
+            let stored_value = record::decode(&tree.get(db_key.as_bytes()).ok().flatten()?)?;
+            (!stored_value.is_expired(now)).then(|| Chunk::from_record(db_key, stored_value))
+        };
+        let chunks = timings.measure(
+            |t| &mut t.scan,
+            || {
+                let mut chunks: Vec<Chunk> = keys.into_iter().filter_map(|(db_key, _)| read(db_key)).collect();
+                // Base của chunk delta nằm ngoài trang chỉ được đọc để dựng lại
+                let bases: std::collections::HashSet<String> = chunks
+                    .iter()
+                    .filter_map(|c| Some(key::encode(&file_key, c.delta_base.as_deref()?)))
+                    .filter(|base_key| !chunks.iter().any(|c| c.key == *base_key))
+                    .collect();
+                chunks.extend(bases.iter().filter_map(|base_key| read(base_key.clone())));
+                (tier::resolve(chunks, &large), bases)
+            },
+        );
+        let (chunks, bases) = chunks;
+        let mut chunks = timings.measure(|t| &mut t.delta, || delta::resolve(chunks, &file_key));
+        chunks.retain(|c| !bases.contains(&c.key));
+        let chunks = timings.measure(|t| &mut t.decompress, || decode_chunks(chunks, &accepted));
+        Ok((chunks, timings))
+    })
+    .await?;
+    Ok((FilePage { chunks, total, last_modified, next_cursor }, timings))
+}
+
+/// Đường đọc song song khi STORAGE_READ_PARALLELISM > 1: lấy danh sách key của file
+/// trước (theo thứ tự của index thứ tự nếu bật, ngược lại theo thứ tự key), rồi chia
+/// thành các nhóm liên tiếp để nhiều blocking worker cùng đọc record và giải nén. Kết quả
+/// của các nhóm được nối lại theo đúng thứ tự nhóm nên thứ tự chunk giống hệt đường đọc
+/// tuần tự. File có ít hơn STORAGE_PARALLEL_READ_MIN_CHUNKS chunk được đọc trong một
+/// worker. Dựng lại chunk delta cần cả file nên vẫn chạy tuần tự giữa hai pha.
+async fn load_file_chunks_parallel(
+    tree: sled::Tree,
+    large: tier::Large,
+    order: Option<sled::Tree>,
+    file_key: String,
+    accepted: Vec<compress::Codec>,
+    parallelism: usize,
+    min_chunks: usize,
+) -> Result<(Vec<Chunk>, ReadTimings), StatusCode> {
+    let mut timings = ReadTimings::default();
+    let started = Instant::now();
+    let (keys, tree, ordered) = {
+        let file_key = file_key.clone();
+        blocking(move || {
+            let keys = match &order {
+                Some(order) => order::scan(order, &file_key)
+                    .into_iter()
+                    .map(|(index, chunk_hash)| (index, key::encode(&file_key, &chunk_hash)))
+                    .collect(),
+                None => tree
+                    .scan_prefix(key::prefix(&file_key).as_bytes())
+                    .keys()
+                    .flatten()
+                    .filter(|key_bytes| key::chunk_hash_in(key_bytes, &file_key).is_some())
+                    .map(|key_bytes| (None, String::from_utf8_lossy(&key_bytes).into_owned()))
+                    .collect::<Vec<_>>(),
+            };
+            Ok((keys, tree, order.is_some()))
+        })
+        .await?
+    };
+    let workers = if keys.len() < min_chunks { 1 } else { parallelism };
+
+    let chunks = in_parallel(keys, workers, move |group| {
+        let now = unix_now();
+        let chunks = group
+            .into_iter()
+            .filter_map(|(index, db_key)| {
+                let value_bytes = tree.get(db_key.as_bytes()).ok().flatten()?; // Chunk đã bị xóa
+                let stored_value = record::decode(&value_bytes)?; // Bỏ qua nếu value bị hỏng
+                // Entry cũ của index thứ tự trỏ tới chunk đã được ghi lại với index khác
+                ((!ordered || stored_value.index == index) && !stored_value.is_expired(now))
+                    .then(|| Chunk::from_record(db_key, stored_value))
+            })
+            .collect();
+        tier::resolve(chunks, &large)
+    })
+    .await?;
+    timings.scan = started.elapsed();
+
+    let chunks = timings.measure(|t| &mut t.delta, || delta::resolve(chunks, &file_key));
+    let started = Instant::now();
+    let chunks = in_parallel(chunks, workers, move |group| decode_chunks(group, &accepted)).await?;
+    timings.decompress = started.elapsed();
+    Ok((chunks, timings))
+}
+
+/// Chia `items` thành tối đa `workers` nhóm liên tiếp, xử lý mỗi nhóm bằng `f` trên một
+/// blocking worker rồi nối kết quả theo thứ tự nhóm
+async fn in_parallel<T, U, F>(mut items: Vec<T>, workers: usize, f: F) -> Result<Vec<U>, StatusCode>
+where
+    T: Send + 'static,
+    U: Send + 'static,
+    F: Fn(Vec<T>) -> Vec<U> + Send + Sync + 'static,
+{
+    let f = Arc::new(f);
+    let group_size = items.len().div_ceil(workers.max(1)).max(1);
+    let mut handles = Vec::with_capacity(workers);
+    while !items.is_empty() {
+        let rest = items.split_off(group_size.min(items.len()));
+        let group = std::mem::replace(&mut items, rest);
+        let f = f.clone();
+        handles.push(tokio::task::spawn_blocking(move || f(group)));
+    }
+
+    let mut out = Vec::new();
+    for handle in handles {
+        out.extend(handle.await.map_err(|e| {
+            tracing::error!("Lỗi khi chạy tác vụ đọc song song: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?);
+    }
+    Ok(out)
+}
+
+/// Giải nén các chunk mà client không tự giải được; chunk giải nén lỗi bị bỏ qua
+/// giống như value bị hỏng
+fn decode_chunks(chunks: Vec<Chunk>, accepted: &[compress::Codec]) -> Vec<Chunk> {
+    chunks
+        .into_iter()
+        .filter_map(|mut chunk| {
+            if chunk.encoding.is_identity() || accepted.contains(&chunk.encoding) {
+                return Some(chunk);
+            }
+            match compress::decompress(&chunk.value, chunk.encoding) {
+                Some(value) => {
+                    chunk.value = value;
+                    chunk.encoding = compress::Codec::Identity;
+                    Some(chunk)
+                }
+                None => {
+                    tracing::error!("Lỗi khi giải nén chunk {}", chunk.key);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Đọc chunk của một file theo index thứ tự: duyệt tree `chunk_order` rồi lấy record
+/// thật từ tree chính, bỏ qua entry không còn khớp với record.
+fn scan_ordered_chunks(db: &sled::Tree, order: &sled::Tree, file_key: &str) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+    let now = unix_now();
+
+    for (index, chunk_hash) in order::scan(order, file_key) {
+        let db_key = key::encode(file_key, &chunk_hash);
+        let Ok(Some(value_bytes)) = db.get(db_key.as_bytes()) else {
+            continue; // Chunk đã bị xóa hoặc chuyển đi
+        };
+        let Some(stored_value) = record::decode(&value_bytes) else {
+            continue; // Bỏ qua nếu value bị hỏng
+        };
+        if stored_value.index != index {
+            continue; // Entry cũ của chunk đã được ghi lại với index khác
+        }
+        if stored_value.is_expired(now) {
+            continue; // Hết hạn nhưng task TTL chưa kịp xóa
+        }
+
+        chunks.push(Chunk::from_record(db_key, stored_value));
+    }
+
+    chunks
+}
+
+/// Key và stored_at của các chunk còn hạn của một file theo thứ tự trả về của
+/// /file/:fileKey: theo index thứ tự nếu bật, ngược lại theo thứ tự key. Như `plan_stream`,
+/// chỉ giữ key nên không giữ dữ liệu chunk trong bộ nhớ.
+fn scan_file_keys(db: &sled::Tree, order: Option<&sled::Tree>, file_key: &str) -> Vec<(String, Option<u64>)> {
+    let now = unix_now();
+    let live = |stored_value: StoredChunkValue| (!stored_value.is_expired(now)).then_some(stored_value.stored_at);
+    match order {
+        Some(order) => order::scan(order, file_key)
+            .into_iter()
+            .filter_map(|(index, chunk_hash)| {
+                let db_key = key::encode(file_key, &chunk_hash);
+                let stored_value = record::decode(&db.get(db_key.as_bytes()).ok().flatten()?)?;
+                if stored_value.index != index {
+                    return None; // Entry cũ của chunk đã được ghi lại với index khác
+                }
+                Some((db_key, live(stored_value)?))
+            })
+            .collect(),
+        None => db
+            .scan_prefix(key::prefix(file_key).as_bytes())
+            .flatten()
+            .filter(|(key_bytes, _)| key::chunk_hash_in(key_bytes, file_key).is_some())
+            .filter_map(|(key_bytes, value_bytes)| {
+                let stored_at = live(record::decode(&value_bytes)?)?;
+                Some((String::from_utf8_lossy(&key_bytes).into_owned(), stored_at))
+            })
+            .collect(),
+    }
+}
+
+/// Lập danh sách chunk cần gửi cho /stream-download và /multipart (giữ suất đọc trong lúc
+/// quét) và ghi nhận lượt truy cập. Trả về `404` nếu file không có chunk nào.
+async fn stream_plan(
+    state: &Arc<AppState>,
+    file_key: &str,
+    consistency: Consistency,
+) -> Result<Vec<(Option<u64>, String)>, ApiError> {
+    let mut plan = {
+        let _permit = state.acquire_read()?;
+        sync_for_read(state, consistency).await?;
+        let tree = state.tree_for(file_key).clone();
+        let order = state.order.clone();
+        let plan_key = file_key.to_string();
+        blocking(move || Ok(plan_stream(&tree, order.as_ref(), &plan_key))).await?
+    };
+    if plan.is_empty() {
+        return Err(ApiError::NotFound);
+    }
+    if let Some(manifest) = state.manifest(file_key)? {
+        manifest.sort(&mut plan, |(_, db_key)| key::decode(db_key.as_bytes()).map(|k| k.chunk_hash));
+    }
+    state.record_read(file_key, plan.len());
+    Ok(plan)
+}
+
+/// Danh sách (index, key) các chunk của một file theo thứ tự gửi của /stream-download.
+/// Dùng index thứ tự nếu có; nếu không thì quét rồi sắp xếp theo index (chunk không có
+/// index đứng cuối), chỉ giữ key nên không giữ dữ liệu chunk trong bộ nhớ.
+fn plan_stream(db: &sled::Tree, order: Option<&sled::Tree>, file_key: &str) -> Vec<(Option<u64>, String)> {
+    if let Some(order) = order {
+        return order::scan(order, file_key)
+            .into_iter()
+            .map(|(index, chunk_hash)| (index, key::encode(file_key, &chunk_hash)))
+            .collect();
+    }
+
+    let mut plan: Vec<(Option<u64>, String)> = db
+        .scan_prefix(key::prefix(file_key).as_bytes())
+        .flatten()
+        .filter(|(key_bytes, _)| key::chunk_hash_in(key_bytes, file_key).is_some())
+        .filter_map(|(key_bytes, value_bytes)| {
+            let index = record::decode(&value_bytes)?.index;
+            Some((index, String::from_utf8_lossy(&key_bytes).into_owned()))
+        })
+        .collect();
+    plan.sort_by_key(|(index, _)| (index.is_none(), *index));
+    plan
+}
+
+/// Đọc dữ liệu gốc và contentType của một chunk cho /stream-download. Trả về `None` nếu
+/// chunk không còn hoặc index không còn khớp với kế hoạch.
+fn fetch_stream_chunk(
+    db: &sled::Tree,
+    large: &tier::Large,
+    db_key: &str,
+    index: Option<u64>,
+) -> std::io::Result<Option<(Bytes, Option<String>)>> {
+    let Some(value_bytes) = db.get(db_key.as_bytes()).map_err(std::io::Error::other)? else {
+        return Ok(None);
+    };
+    let record = record::decode(&value_bytes).ok_or_else(|| invalid_chunk("value bị hỏng", db_key.as_bytes()))?;
+    if record.index != index || record.is_expired(unix_now()) {
+        return Ok(None);
+    }
+    let (raw, record) = decode_chunk_data(db, large, db_key.as_bytes(), record)?;
+    Ok(Some((raw, record.content_type)))
+}
+
+/// Dữ liệu gốc của chunk `db_key` (fileKey có thể là nhị phân) cùng record của nó; `None`
+/// nếu chunk không tồn tại. Chạy đồng bộ.
+fn read_chunk(db: &sled::Tree, large: &tier::Large, db_key: &[u8]) -> std::io::Result<Option<(Bytes, StoredChunkValue)>> {
+    let Some(value_bytes) = db.get(db_key).map_err(std::io::Error::other)? else {
+        return Ok(None);
+    };
+    let record = record::decode(&value_bytes).ok_or_else(|| invalid_chunk("value bị hỏng", db_key))?;
+    if record.is_expired(unix_now()) {
+        return Ok(None);
+    }
+    decode_chunk_data(db, large, db_key, record).map(Some)
+}
+
+fn invalid_chunk(what: &str, db_key: &[u8]) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("{}: {}", what, String::from_utf8_lossy(db_key)),
+    )
+}
+
+/// Dạng chuẩn của Base64 nhận được ở bất kỳ biến thể nào: bảng chữ cái chuẩn (`+`, `/`)
+/// hoặc url-safe (`-`, `_`), có hoặc không có padding `=`. Dạng chuẩn là bảng chữ cái
+/// chuẩn có padding, cũng là dạng được lưu và trả về cho client, nên cùng một dữ liệu
+/// luôn được lưu thành cùng một chuỗi. Trả về `None` nếu chuỗi đã ở dạng chuẩn hoặc không
+/// hợp lệ ở mọi biến thể (ví dụ trộn hai bảng chữ cái).
+fn normalize_base64(data: &str) -> Option<String> {
+    use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+    const INDIFFERENT: GeneralPurposeConfig =
+        GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent);
+    const VARIANTS: [GeneralPurpose; 2] = [
+        GeneralPurpose::new(&base64::alphabet::STANDARD, INDIFFERENT),
+        GeneralPurpose::new(&base64::alphabet::URL_SAFE, INDIFFERENT),
+    ];
+
+    // Dạng chuẩn thì không có '-', '_' và độ dài chia hết cho 4; để `validate` kiểm tra
+    if data.len().is_multiple_of(4) && !data.contains(['-', '_']) {
+        return None;
+    }
+    VARIANTS
+        .iter()
+        .find_map(|engine| engine.decode(data).ok())
+        .map(|bytes| STANDARD.encode(bytes))
+}
+
+// Đọc value của chunk lớn, dựng lại chunk delta hoặc giải nén để lấy dữ liệu gốc
+fn decode_chunk_data(
+    db: &sled::Tree,
+    large: &tier::Large,
+    db_key: &[u8],
+    record: StoredChunkValue,
+) -> std::io::Result<(Bytes, StoredChunkValue)> {
+    let invalid = |what: &str| invalid_chunk(what, db_key);
+    let record = tier::fill(large, db_key, record).ok_or_else(|| invalid("thiếu value của chunk lớn"))?;
+    let value = match &record.delta_base {
+        // Chunk delta: lấy thêm chunk base cùng file để dựng lại
+        Some(base_hash) => {
+            let (file_key, _) = key::decode_raw(db_key).ok_or_else(|| invalid("key không hợp lệ"))?;
+            let base_key = key::encode_raw(file_key, base_hash);
+            let base = db
+                .get(&base_key)
+                .map_err(std::io::Error::other)?
+                .and_then(|bytes| delta::raw_of(&tier::fill(large, &base_key, record::decode(&bytes)?)?));
+            base.and_then(|base| delta::apply(&base, &record.value))
+                .ok_or_else(|| invalid("không dựng lại được chunk delta"))?
+        }
+        None => compress::decompress(&record.value, record.encoding).ok_or_else(|| invalid("không giải nén được"))?,
+    };
+    let raw = STANDARD.decode(value).map_err(|_| invalid("value không phải Base64"))?;
+    Ok((Bytes::from(raw), record))
+}
+
+/// Quét và trả về tất cả chunk của một file. Dùng chung cho HTTP và gRPC.
+fn scan_file_chunks(db: &sled::Tree, file_key: &str) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+    let now = unix_now();
+    
+    // Tạo prefix để quét database. Thêm dấu ':' để đảm bảo không lấy nhầm
+    // fileKey khác có tiền tố tương tự.
+    let prefix = key::prefix(file_key);
+
+    // Quét tất cả các key có tiền tố là `file_key:`
+    for result in db.scan_prefix(prefix.as_bytes()) {
+        match result {
+            Ok((key_bytes, value_bytes)) => {
+                // Bỏ qua key không phải UTF-8 hợp lệ hoặc thuộc fileKey khác có chứa ':'
+                if key::chunk_hash_in(&key_bytes, file_key).is_none() {
+                    continue;
+                }
+                let key_str = String::from_utf8_lossy(&key_bytes).into_owned();
+                
+                // Deserialize value (JSON hoặc bare)
+                let stored_value: StoredChunkValue = match record::decode(&value_bytes) {
+                    Some(v) => v,
+                    None => continue, // Bỏ qua nếu value bị hỏng
+                };
+                if stored_value.is_expired(now) {
+                    continue;
+                }
+
+                // Thêm chunk đã tìm thấy vào danh sách
+                chunks.push(Chunk::from_record(key_str, stored_value));
+            }
+            Err(_) => {
+                // Bỏ qua các key lỗi
+                continue;
+            }
+        }
+    }
+
+    chunks
+}
+
+/// Liệt kê tất cả fileKey trong database cùng số chunk của mỗi file.
+/// Key được sắp xếp theo byte nên các chunk của cùng một file luôn nằm liền nhau,
+/// và mỗi file chỉ nằm trong một shard.
+fn list_files(shards: &[sled::Tree]) -> sled::Result<Vec<FileSummary>> {
+    let mut files: Vec<FileSummary> = Vec::new();
+
+    for key_bytes in shards.iter().flat_map(|tree| tree.iter().keys()) {
+        let key_bytes = key_bytes?;
+        let Some(key::ChunkKey { file_key, .. }) = key::decode(&key_bytes) else {
+            continue; // Bỏ qua nếu key không hợp lệ
+        };
+
+        match files.last_mut() {
+            Some(last) if last.file_key == file_key => last.chunk_count += 1,
+            _ => files.push(FileSummary {
+                file_key: file_key.to_string(),
+                chunk_count: 1,
+            }),
+        }
+    }
+
+    if shards.len() > 1 {
+        files.sort_by(|a, b| a.file_key.cmp(&b.file_key));
+    }
+    Ok(files)
+}
+
+/// Mở các tree dùng làm shard. Với 1 shard thì dùng tree mặc định để tương thích
+/// với database cũ; với nhiều shard thì dùng các tree "shard-0", "shard-1", ... Shard của
+/// namespace (`prefix` khác rỗng) luôn là các tree "<prefix>shard-N".
+fn open_shards(db: &sled::Db, count: usize, prefix: &str) -> Vec<sled::Tree> {
+    // Namespace không dùng tree mặc định (của namespace mặc định) kể cả khi chỉ có một shard
+    if !prefix.is_empty() {
+        return (0..count.max(1))
+            .map(|i| db.open_tree(format!("{}shard-{}", prefix, i)).expect("Không thể mở shard"))
+            .collect();
+    }
+    if count <= 1 {
+        return vec![(**db).clone()];
+    }
+
+    if db.iter().next().is_some() {
+        tracing::warn!(
+            "⚠️  Tree mặc định vẫn còn dữ liệu nhưng STORAGE_SHARDS={}; các chunk này sẽ không được đọc tới",
+            count
+        );
+    }
+    (0..count)
+        .map(|i| db.open_tree(format!("shard-{}", i)).expect("Không thể mở shard"))
+        .collect()
+}
+
+// Hàm hash FNV-1a 64-bit: ổn định giữa các phiên bản, không phụ thuộc vào std
+fn fnv1a_64(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in bytes {
+        hash ^= u64::from(*b);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// Jump consistent hash (Lamping & Veach): khi tăng số bucket từ N lên N+1,
+/// chỉ khoảng 1/(N+1) số key phải chuyển sang bucket mới.
+fn jump_consistent_hash(mut key: u64, buckets: usize) -> usize {
+    let mut b: i64 = -1;
+    let mut j: i64 = 0;
+    while j < buckets as i64 {
+        b = j;
+        key = key.wrapping_mul(2862933555777941757).wrapping_add(1);
+        j = ((b + 1) as f64 * ((1u64 << 31) as f64 / ((key >> 33) + 1) as f64)) as i64;
+    }
+    b as usize
+}
+
+/// Header Cache-Control / Expires cho response chứa dữ liệu chunk.
+/// Chunk được định địa chỉ theo hash nên gần như bất biến và có thể cache công khai.
+fn cache_headers(config: &Config) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let Some(max_age) = config.cache_max_age else {
+        return headers;
+    };
+
+    let expires = std::time::SystemTime::now() + std::time::Duration::from_secs(max_age);
+    if let Ok(value) = HeaderValue::from_str(&format!("public, max-age={}", max_age)) {
+        headers.insert(header::CACHE_CONTROL, value);
+    }
+    if let Ok(value) = HeaderValue::from_str(&httpdate::fmt_http_date(expires)) {
+        headers.insert(header::EXPIRES, value);
+    }
+    // Nội dung chunk phụ thuộc vào encoding mà client chấp nhận
+    headers.insert(header::VARY, HeaderValue::from_static(compress::ACCEPT_CHUNK_ENCODING));
+    headers
+}
+
+// Thời điểm hiện tại tính bằng unix giây
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+fn unix_now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+// Phân biệt trường không có mặt (None) với trường có giá trị `null` (Some(None)) trong payload PATCH
+fn patch_field<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: serde::Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+// Bỏ các ký tự điều khiển (CR, LF, ...) trước khi ghi chuỗi do client gửi vào header
+fn header_safe(input: &str) -> String {
+    input.chars().filter(|c| !c.is_control()).collect()
+}
+
+// Escape các ký tự đặc biệt trước khi chèn chuỗi vào HTML
+fn html_escape(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
@@ -0,0 +1,92 @@
+// ## LOG CÓ CẤU TRÚC ##
+//
+// Server ghi log qua `tracing`. Mức thấp nhất lấy từ STORAGE_LOG_LEVEL (mặc định `info`),
+// định dạng từ STORAGE_LOG_FORMAT: `text` (mặc định, dễ đọc trên terminal) hoặc `json` (mỗi
+// dòng một object JSON, để gom log của nhiều node vào một hệ thống chung).
+//
+// Mỗi request HTTP chạy trong một span `request` có `method`, `path`, `route`, `fileKey`
+// (lấy từ đường dẫn, hoặc được handler /store điền khi fileKey nằm trong body) và `bytes`
+// (Content-Length của request). Mọi log của handler nằm trong span này, và khi có response
+// server ghi thêm một dòng kèm status và thời gian xử lý (`latency`, mili giây).
+
+use axum::body::Body;
+use axum::extract::MatchedPath;
+use axum::http::{header, Request};
+use tower_http::classify::{ServerErrorsAsFailures, SharedClassifier};
+use tower_http::trace::{DefaultOnResponse, TraceLayer};
+use tower_http::LatencyUnit;
+use tracing::{Level, Span};
+
+/// Định dạng log (STORAGE_LOG_FORMAT)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            other => Err(format!("định dạng log không hỗ trợ: {}", other)),
+        }
+    }
+}
+
+/// Khởi tạo subscriber ghi log ra stdout với mức và định dạng đã cấu hình
+pub fn init(level: Level, format: LogFormat) {
+    let builder = tracing_subscriber::fmt().with_max_level(level);
+    match format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder.json().init(),
+    }
+}
+
+type MakeSpan = fn(&Request<Body>) -> Span;
+
+/// Layer tạo span cho mỗi request HTTP và ghi một dòng log khi có response
+pub fn trace_layer() -> TraceLayer<SharedClassifier<ServerErrorsAsFailures>, MakeSpan> {
+    TraceLayer::new_for_http()
+        .make_span_with(request_span as MakeSpan)
+        .on_response(DefaultOnResponse::new().level(Level::INFO).latency_unit(LatencyUnit::Millis))
+}
+
+fn request_span(request: &Request<Body>) -> Span {
+    let path = request.uri().path();
+    let route = request.extensions().get::<MatchedPath>().map(MatchedPath::as_str);
+    let bytes = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    let span = tracing::info_span!(
+        "request",
+        method = %request.method(),
+        path,
+        route,
+        fileKey = tracing::field::Empty,
+        bytes,
+    );
+    if let Some(file_key) = route.and_then(|route| file_key_in(route, path)) {
+        span.record("fileKey", file_key);
+    }
+    span
+}
+
+// Đoạn đường dẫn ứng với tham số `:fileKey` (hoặc `:encodedKey`) của route
+fn file_key_in<'a>(route: &str, path: &'a str) -> Option<&'a str> {
+    route
+        .split('/')
+        .zip(path.split('/'))
+        .find(|(pattern, _)| matches!(*pattern, ":fileKey" | ":encodedKey"))
+        .map(|(_, segment)| segment)
+}
+
+/// Ghi fileKey vào span của request hiện tại, cho các route nhận fileKey trong body
+pub fn record_file_key(file_key: &str) {
+    Span::current().record("fileKey", file_key);
+}
@@ -1,164 +1,126 @@
-use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    routing::{get, post},
-    Json, Router,
-};
-use serde::{Deserialize, Serialize};
-use std::net::SocketAddr;
-use std::sync::Arc;
-
-// ## CÁC CẤU TRÚC DỮ LIỆU ##
-
-// Struct để nhận payload khi Go Listener gọi /store
-#[derive(Deserialize)]
-struct StorePayload {
-    #[serde(rename = "fileKey")]
-    file_key: String,
-    #[serde(rename = "chunkHash")]
-    chunk_hash: String,
-    #[serde(rename = "chunkData")]
-    chunk_data: String, // Dữ liệu chunk ở dạng Base64
-}
-
-// Struct để trả về khi Go Downloader gọi /file/:fileKey
-#[derive(Serialize)]
-struct FileChunksResponse {
-    #[serde(rename = "fileKey")]
-    file_key: String,
-    chunks: Vec<Chunk>,
-}
-
-// Struct đại diện cho một chunk trong mảng trả về
-#[derive(Serialize)]
-struct Chunk {
-    key: String,   // Key tổng hợp, ví dụ: "0x...:0x..."
-    value: String, // Dữ liệu chunk ở dạng Base64
-}
-
-// Struct để serialize/deserialize dữ liệu chunk trong database
-#[derive(Serialize, Deserialize)]
-struct StoredChunkValue {
-    value: String,
-}
-
+use rust_p2p_storage::{bench, build_app, build_state, cli, grpc_service, logging, migrate, run_backups, run_disk_monitor, run_flusher, run_full_sync, run_gc, run_gossip, run_replication, run_scrubber, run_snapshots, run_ttl_sweeper, run_upload_sweeper, run_usage_tracker, run_write_buffer, selftest, serve, serve_tls, shutdown, Config};
 
 // ## HÀM MAIN - KHỞI TẠO SERVER ##
 
 #[tokio::main]
 async fn main() {
-    // Mở hoặc tạo database. Dữ liệu sẽ được lưu trong thư mục "my_database"
-    let db = sled::open("my_database").expect("Không thể mở database");
-    
-    // Bọc database trong Arc để chia sẻ an toàn giữa các thread
-    let shared_state = Arc::new(db);
-
-    // Định nghĩa các route cho ứng dụng
-    let app = Router::new()
-        .route("/store", post(store_chunk))
-        .route("/file/:fileKey", get(retrieve_file_chunks))
-        .with_state(shared_state);
-
-    // Chạy server
-    let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
-    println!("🚀 Server lưu trữ đang lắng nghe trên http://{}", addr);
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
-}
-
-
-// ## CÁC HANDLER XỬ LÝ REQUEST ##
-
-/// Handler cho việc LƯU TRỮ chunk mới
-async fn store_chunk(
-    State(db): State<Arc<sled::Db>>,
-    Json(payload): Json<StorePayload>,
-) -> StatusCode {
-    // Tạo key tổng hợp để lưu vào database, định dạng: "fileKey:chunkHash"
-    let db_key = format!("{}:{}", payload.file_key, payload.chunk_hash);
-
-    // Chuẩn bị value để lưu. Chúng ta sẽ lưu lại cấu trúc JSON {"value": "..."}
-    let db_value = StoredChunkValue {
-        value: payload.chunk_data,
-    };
-
-    // Serialize value thành JSON bytes để lưu trữ
-    let value_bytes = match serde_json::to_vec(&db_value) {
-        Ok(bytes) => bytes,
-        Err(e) => {
-            eprintln!("Lỗi khi serialize value: {}", e);
-            return StatusCode::INTERNAL_SERVER_ERROR;
+    let mut args = std::env::args().skip(1);
+    let first = args.next();
+    match first.as_deref() {
+        // `--help`: in danh sách đối số rồi thoát
+        Some("--help" | "-h") => {
+            print!("{}", cli::usage());
+            return;
         }
-    };
-    
-    println!("-> Đang lưu chunk với key: {}", db_key);
-
-    // Lưu cặp key-value vào Sled DB
-    match db.insert(db_key.as_bytes(), value_bytes) {
-        Ok(_) => {
-            // Đảm bảo dữ liệu được ghi xuống đĩa một cách bất đồng bộ
-            if db.flush_async().await.is_err() {
-                eprintln!("Lỗi khi flush database");
-                return StatusCode::INTERNAL_SERVER_ERROR;
+        // `--bench`: đo throughput trên database tạm thay vì chạy server
+        Some("--bench") => {
+            let options = bench::Options::from_args(args).expect("Đối số benchmark không hợp lệ");
+            let config = Config::load().expect("Không thể đọc cấu hình");
+            init_tracing(&config);
+            bench::run(options, config).await;
+            return;
+        }
+        // `--selftest`: lưu, đọc, xóa một chunk trên database tạm rồi thoát
+        Some("--selftest") => {
+            let dir = selftest::dir_from_args(args).expect("Đối số selftest không hợp lệ");
+            let config = Config::load().expect("Không thể đọc cấu hình");
+            init_tracing(&config);
+            match selftest::run(config, &dir).await {
+                Ok(()) => {
+                    println!("✅ Tự kiểm tra thành công");
+                    return;
+                }
+                Err(e) => {
+                    eprintln!("❌ Tự kiểm tra thất bại: {}", e);
+                    std::process::exit(1);
+                }
             }
-            StatusCode::OK
         }
-        Err(e) => {
-            eprintln!("Lỗi khi insert vào database: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
+        // `--migrate-dry-run`: báo cáo việc ghi lại record sang STORAGE_VALUE_FORMAT, không ghi gì
+        Some("--migrate-dry-run") => {
+            let config = Config::load().expect("Không thể đọc cấu hình");
+            init_tracing(&config);
+            let db = sled::open(config.data_dir()).expect("Không thể mở database");
+            match migrate::dry_run(&db, &config) {
+                Ok(report) => report.print(),
+                Err(e) => {
+                    eprintln!("❌ Không quét được database: {}", e);
+                    std::process::exit(1);
+                }
+            }
+            return;
         }
+        _ => {}
     }
-}
-
-/// Handler cho việc LẤY TẤT CẢ chunk của một file
-async fn retrieve_file_chunks(
-    State(db): State<Arc<sled::Db>>,
-    Path(file_key): Path<String>,
-) -> Result<Json<FileChunksResponse>, StatusCode> {
-    
-    println!("<- Đang truy vấn tất cả chunk cho fileKey: {}", file_key);
-    let mut chunks = Vec::new();
-    
-    // Tạo prefix để quét database. Thêm dấu ':' để đảm bảo không lấy nhầm
-    // fileKey khác có tiền tố tương tự.
-    let prefix = format!("{}:", file_key);
 
-    // Quét tất cả các key có tiền tố là `file_key:`
-    for result in db.scan_prefix(prefix.as_bytes()) {
-        match result {
-            Ok((key_bytes, value_bytes)) => {
-                // Chuyển đổi key từ bytes sang String
-                let key_str = match String::from_utf8(key_bytes.to_vec()) {
-                    Ok(s) => s,
-                    Err(_) => continue, // Bỏ qua nếu key không phải UTF-8 hợp lệ
-                };
-                
-                // Deserialize value từ JSON bytes
-                let stored_value: StoredChunkValue = match serde_json::from_slice(&value_bytes) {
-                    Ok(v) => v,
-                    Err(_) => continue, // Bỏ qua nếu value không phải JSON hợp lệ
-                };
+    // Các đối số còn lại ghi đè cấu hình (xem module cli), ví dụ `--listen 0.0.0.0:3000`
+    cli::install(first.into_iter().chain(args)).expect("Đối số dòng lệnh không hợp lệ");
+
+    // Mở hoặc tạo database trong thư mục STORAGE_DATA_DIR (mặc định "my_database")
+    let config = Config::load().expect("Không thể đọc cấu hình");
+    init_tracing(&config);
+    let db = sled::open(config.data_dir()).expect("Không thể mở database");
+    let shared_state = build_state(db, config);
+    let grpc_addr = shared_state.grpc_addr();
+    let tls = shared_state.tls_acceptor().expect("Không thể cấu hình TLS");
+
+    tokio::spawn(run_snapshots(shared_state.clone()));
+    tokio::spawn(run_gossip(shared_state.clone()));
+    tokio::spawn(run_ttl_sweeper(shared_state.clone()));
+    tokio::spawn(run_usage_tracker(shared_state.clone()));
+    tokio::spawn(run_upload_sweeper(shared_state.clone()));
+    tokio::spawn(run_gc(shared_state.clone()));
+    tokio::spawn(run_backups(shared_state.clone()));
+    tokio::spawn(run_replication(shared_state.clone()));
+    tokio::spawn(run_write_buffer(shared_state.clone()));
+    tokio::spawn(run_full_sync(shared_state.clone()));
+    tokio::spawn(run_disk_monitor(shared_state.clone()));
+    tokio::spawn(run_flusher(shared_state.clone()));
+    tokio::spawn(run_scrubber(shared_state.clone()));
+
+    let app = build_app(shared_state.clone());
+
+    // Chạy HTTP server và gRPC server song song trên cùng database
+    let addr = shared_state.http_addr();
+    let scheme = if tls.is_some() { "https" } else { "http" };
+    tracing::info!("🚀 Server lưu trữ đang lắng nghe trên {}://{}", scheme, addr);
+    tracing::info!("🚀 gRPC server đang lắng nghe trên {}", grpc_addr);
+    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
 
-                // Thêm chunk đã tìm thấy vào danh sách
-                chunks.push(Chunk {
-                    key: key_str,
-                    value: stored_value.value,
-                });
-            }
-            Err(_) => {
-                // Bỏ qua các key lỗi
-                continue;
-            }
+    let max_connections = shared_state.max_connections();
+    let (stop, stopped) = shutdown::channel();
+    let http_stopped = stopped.clone().wait();
+    let http = async {
+        match tls {
+            Some(acceptor) => serve_tls(listener, app, max_connections, acceptor, http_stopped).await,
+            None => serve(listener, app, max_connections, http_stopped).await,
+        }
+    };
+    let grpc = async {
+        tonic::transport::Server::builder()
+            .add_service(grpc_service(shared_state.clone()))
+            .serve_with_shutdown(grpc_addr, stopped.wait())
+            .await
+            .unwrap()
+    };
+    let servers = async {
+        tokio::join!(http, grpc);
+    };
+    tokio::pin!(servers);
+    tokio::select! {
+        _ = &mut servers => {}
+        _ = shutdown::signal() => {
+            tracing::info!("🛑 Nhận tín hiệu tắt, ngừng nhận kết nối mới và chờ các request đang chạy...");
+            stop.stop();
+            shutdown::drain(&shared_state, servers).await;
         }
     }
 
-    println!("   -> Tìm thấy {} chunks", chunks.len());
-
-    // Tạo response cuối cùng
-    let response = FileChunksResponse {
-        file_key,
-        chunks,
-    };
+    // Flush lần cuối có giới hạn thời gian để đĩa bị treo không giữ process mãi
+    std::process::exit(shutdown::final_flush(&shared_state).await);
+}
 
-    Ok(Json(response))
-}
\ No newline at end of file
+// Ghi log ra stdout từ mức STORAGE_LOG_LEVEL trở lên, theo định dạng STORAGE_LOG_FORMAT
+fn init_tracing(config: &Config) {
+    logging::init(config.log_level(), config.log_format());
+}
@@ -0,0 +1,84 @@
+// ## MANIFEST DO CLIENT KHAI BÁO ##
+//
+// `POST /manifest/:fileKey` lưu danh sách chunkHash theo đúng thứ tự ghép file cùng tổng
+// kích thước, MIME type và tên file gốc do client khai báo; `GET /manifest/:fileKey` trả lại
+// đúng những gì đã lưu (`404` nếu chưa có). Manifest nằm trong tree `manifests` (key là
+// fileKey, value là JSON), tách khỏi các shard nên không lẫn vào kết quả quét chunk, và có
+// thể được gửi trước hoặc sau khi upload chunk.
+//
+// Khi file có manifest, `/file/:fileKey`, `/file/:fileKey/stream` (và `/stream-download`),
+// `/file/:fileKey/manifest` và `/vfile/:fileKey` trả chunk theo thứ tự của manifest thay vì
+// theo index: chunk có trong manifest đứng trước theo đúng thứ tự khai báo, chunk đã lưu
+// nhưng không có trong manifest đứng sau theo thứ tự thường; chunk khai báo mà chưa lưu thì
+// bị bỏ qua. Manifest bị xóa cùng file và đi theo file khi đổi tên.
+
+use serde::{Deserialize, Serialize};
+use sled::transaction::{TransactionalTree, UnabortableTransactionError};
+use std::collections::{HashMap, HashSet};
+
+use crate::key;
+
+/// Manifest đã lưu của một file
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Manifest {
+    pub chunks: Vec<String>,
+    #[serde(rename = "totalSize", default, skip_serializing_if = "Option::is_none")]
+    pub total_size: Option<u64>,
+    #[serde(rename = "mimeType", default, skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+}
+
+impl Manifest {
+    /// Manifest có hợp lệ cho `file_key` không: ít nhất một chunk, mọi chunkHash hợp lệ và
+    /// không lặp lại
+    pub fn is_valid(&self, file_key: &str) -> bool {
+        let mut seen = HashSet::new();
+        !self.chunks.is_empty()
+            && self
+                .chunks
+                .iter()
+                .all(|chunk_hash| key::is_valid(file_key, chunk_hash) && seen.insert(chunk_hash.as_str()))
+            && self.mime_type.as_deref().is_none_or(|mime| !mime.is_empty())
+    }
+
+    /// Sắp xếp lại `items` theo thứ tự của manifest; phần tử không có trong manifest giữ
+    /// nguyên thứ tự tương đối và đứng sau
+    pub fn sort<T>(&self, items: &mut [T], chunk_hash_of: impl Fn(&T) -> Option<&str>) {
+        let position: HashMap<&str, usize> =
+            self.chunks.iter().enumerate().map(|(at, chunk_hash)| (chunk_hash.as_str(), at)).collect();
+        items.sort_by_key(|item| {
+            chunk_hash_of(item)
+                .and_then(|chunk_hash| position.get(chunk_hash).copied())
+                .unwrap_or(usize::MAX)
+        });
+    }
+}
+
+/// Manifest của `file_key`, `None` nếu chưa có
+pub fn get(tree: &sled::Tree, file_key: &str) -> sled::Result<Option<Manifest>> {
+    Ok(tree
+        .get(file_key)?
+        .and_then(|value| serde_json::from_slice(&value).ok()))
+}
+
+/// Lưu manifest của `file_key`; trả về `true` nếu thay cho manifest cũ
+pub fn put(tree: &sled::Tree, file_key: &str, manifest: &Manifest) -> sled::Result<bool> {
+    let value = serde_json::to_vec(manifest).expect("manifest luôn serialize được");
+    Ok(tree.insert(file_key, value)?.is_some())
+}
+
+/// Xóa manifest của `file_key`, trong transaction của lần xóa file
+pub fn remove_in(tree: &TransactionalTree, file_key: &[u8]) -> Result<(), UnabortableTransactionError> {
+    tree.remove(file_key)?;
+    Ok(())
+}
+
+/// Chuyển manifest sang fileKey mới khi đổi tên file, trong transaction của lần đổi tên
+pub fn rename_in(tree: &TransactionalTree, file_key: &str, new_file_key: &str) -> Result<(), UnabortableTransactionError> {
+    if let Some(value) = tree.remove(file_key)? {
+        tree.insert(new_file_key, value)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,301 @@
+// ## METRICS ##
+//
+// Bộ đếm nội bộ của server, xuất ra theo định dạng text của Prometheus tại `/metrics`.
+//
+// Mỗi request HTTP được đếm theo method, route (đúng như khi đăng ký, ví dụ
+// `/file/:fileKey`, để số series không tăng theo số file) và status, cùng histogram thời
+// gian tới lúc có header response. Request gRPC được đếm chung với method `GRPC`, route là
+// tên phương thức gRPC và status là mã gRPC. Request không khớp route nào được gộp vào
+// route `unmatched`.
+
+use axum::extract::{MatchedPath, Request, State};
+use axum::middleware::Next;
+use axum::response::Response;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::AppState;
+
+// Cận trên (giây) của các bucket trong histogram thời gian xử lý request
+const LATENCY_BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+// Số liệu của một cặp (method, route)
+#[derive(Default)]
+struct RouteStats {
+    // Số request theo status
+    statuses: BTreeMap<String, u64>,
+    // Số request có thời gian không vượt cận trên của từng bucket (chưa cộng dồn)
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    seconds: f64,
+}
+
+/// Các số liệu của node phải đọc từ database hoặc module khác, truyền vào `render`
+pub struct NodeStats {
+    pub file_keys: u64,
+    // Số fileKey đang vượt ngưỡng cảnh báo STORAGE_FILE_KEYS_ALERT
+    pub file_keys_alert: bool,
+    pub replication_queue: u64,
+    // Số chunk đã xác nhận với client nhưng chưa được flush (xem module flush)
+    pub unflushed_writes: u64,
+    pub chunks: u64,
+    pub stored_bytes: u64,
+    pub size_on_disk: u64,
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    // Số lượt quét prefix đang chạy
+    pub reads_in_flight: AtomicI64,
+    // Số request đọc bị từ chối vì đã hết suất đọc
+    pub reads_rejected: AtomicU64,
+    // Số lượt đọc dùng chung kết quả của lượt quét đang chạy (STORAGE_READ_COALESCING)
+    pub coalesced_reads: AtomicU64,
+    // Số request ghi bị từ chối vì fileKey đã đủ STORAGE_MAX_REQUESTS_PER_FILE request
+    pub file_requests_rejected: AtomicU64,
+    // Số request bị từ chối vì vượt giới hạn tốc độ hoặc số lượt đọc của client (xem module
+    // ratelimit)
+    pub rate_limited: AtomicU64,
+    // Số lần dữ liệu đọc lại sau khi ghi không khớp (STORAGE_VERIFY_WRITES)
+    pub write_verify_failures: AtomicU64,
+    // Số lần flush database thực sự được gọi (xem module flush)
+    pub flushes: AtomicU64,
+    // Thời điểm flush thành công gần nhất (unix mili giây), 0 nếu chưa flush lần nào
+    pub last_flush_ms: AtomicU64,
+    // Số chunk đã được peer xác nhận replication và số lần gửi lỗi (xem module replication)
+    pub replications: AtomicU64,
+    pub replication_failures: AtomicU64,
+    // Số chunk trong bộ đệm ghi bị bỏ vì lỗi lúc ghi xuống sled (xem module write_buffer)
+    pub write_buffer_failures: AtomicU64,
+    // Số sự kiện bị bỏ vì subscriber của `/events` đọc không kịp (xem module notify)
+    pub notify_dropped: AtomicU64,
+    // Số lần flush database thất bại
+    pub flush_failures: AtomicU64,
+    // Số chunk đã trả về cho client qua HTTP và gRPC
+    pub chunks_served: AtomicU64,
+    // Số lần ghi dùng lại blob cùng nội dung đã có và số byte không phải ghi nhờ đó (xem
+    // module dedup), cộng dồn từ lúc khởi động
+    pub dedup_chunks: AtomicU64,
+    pub dedup_bytes_saved: AtomicU64,
+    // (method, route) -> số request và thời gian xử lý
+    requests: Mutex<BTreeMap<(String, String), RouteStats>>,
+}
+
+impl Metrics {
+    /// Ghi nhận một request đã có response
+    pub fn record_request(&self, method: &str, route: &str, status: &str, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        let mut requests = self.requests.lock().unwrap();
+        let stats = requests.entry((method.to_string(), route.to_string())).or_default();
+        *stats.statuses.entry(status.to_string()).or_default() += 1;
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|&le| seconds <= le) {
+            stats.buckets[bucket] += 1;
+        }
+        stats.count += 1;
+        stats.seconds += seconds;
+    }
+
+    /// Tổng số request đã có response
+    pub fn requests_total(&self) -> u64 {
+        self.requests.lock().unwrap().values().map(|stats| stats.count).sum()
+    }
+
+    /// Xuất toàn bộ metrics theo định dạng text của Prometheus. Số fileKey, độ dài hàng đợi
+    /// replication và các số liệu của database được giữ ở nơi khác nên truyền vào.
+    pub fn render(&self, node: &NodeStats) -> String {
+        let mut out = String::new();
+        self.render_requests(&mut out);
+        gauge(&mut out, "storage_chunks", "Number of stored chunk records", node.chunks as i64);
+        gauge(
+            &mut out,
+            "storage_stored_bytes",
+            "Bytes of chunk data stored, as counted for tenant quotas",
+            node.stored_bytes as i64,
+        );
+        gauge(
+            &mut out,
+            "storage_db_size_bytes",
+            "Size of the sled database on disk",
+            node.size_on_disk as i64,
+        );
+        counter(
+            &mut out,
+            "storage_flush_failures_total",
+            "Database flushes that returned an error",
+            self.flush_failures.load(Ordering::Relaxed),
+        );
+        gauge(
+            &mut out,
+            "storage_reads_in_flight",
+            "Number of prefix scans currently running",
+            self.reads_in_flight.load(Ordering::Relaxed),
+        );
+        counter(
+            &mut out,
+            "storage_reads_rejected_total",
+            "Reads rejected because the read concurrency limit was reached",
+            self.reads_rejected.load(Ordering::Relaxed),
+        );
+        counter(
+            &mut out,
+            "storage_chunks_served_total",
+            "Chunks returned to clients over HTTP and gRPC",
+            self.chunks_served.load(Ordering::Relaxed),
+        );
+        counter(
+            &mut out,
+            "storage_coalesced_reads_total",
+            "Reads served from another in-flight scan of the same fileKey",
+            self.coalesced_reads.load(Ordering::Relaxed),
+        );
+        counter(
+            &mut out,
+            "storage_file_requests_rejected_total",
+            "Writes rejected because their fileKey reached STORAGE_MAX_REQUESTS_PER_FILE",
+            self.file_requests_rejected.load(Ordering::Relaxed),
+        );
+        counter(
+            &mut out,
+            "storage_rate_limited_total",
+            "Requests rejected with 429 by the rate and per-client concurrency limits",
+            self.rate_limited.load(Ordering::Relaxed),
+        );
+        counter(
+            &mut out,
+            "storage_dedup_chunks_total",
+            "Chunk writes that referenced an existing content blob instead of storing new bytes",
+            self.dedup_chunks.load(Ordering::Relaxed),
+        );
+        counter(
+            &mut out,
+            "storage_dedup_bytes_saved_total",
+            "Bytes not written because an identical content blob was already stored",
+            self.dedup_bytes_saved.load(Ordering::Relaxed),
+        );
+        counter(
+            &mut out,
+            "storage_write_verify_failures_total",
+            "Writes whose read-back bytes did not match what was written",
+            self.write_verify_failures.load(Ordering::Relaxed),
+        );
+        counter(
+            &mut out,
+            "storage_flushes_total",
+            "Database flushes (fsyncs) issued by write and strong-read paths",
+            self.flushes.load(Ordering::Relaxed),
+        );
+        gauge(
+            &mut out,
+            "storage_unflushed_writes",
+            "Acknowledged chunk writes not yet covered by a successful flush",
+            node.unflushed_writes as i64,
+        );
+        gauge(
+            &mut out,
+            "storage_file_keys",
+            "Number of distinct fileKeys with at least one chunk",
+            node.file_keys as i64,
+        );
+        gauge(
+            &mut out,
+            "storage_file_keys_alert",
+            "1 when the number of fileKeys exceeds STORAGE_FILE_KEYS_ALERT",
+            node.file_keys_alert as i64,
+        );
+        gauge(
+            &mut out,
+            "storage_replication_queue_depth",
+            "Chunk replications waiting to be acknowledged by a peer",
+            node.replication_queue as i64,
+        );
+        counter(
+            &mut out,
+            "storage_replications_total",
+            "Chunk replications acknowledged by a peer",
+            self.replications.load(Ordering::Relaxed),
+        );
+        counter(
+            &mut out,
+            "storage_replication_failures_total",
+            "Failed attempts to send a chunk replication to a peer",
+            self.replication_failures.load(Ordering::Relaxed),
+        );
+        counter(
+            &mut out,
+            "storage_write_buffer_failures_total",
+            "Buffered writes dropped because persisting them failed after they were acknowledged",
+            self.write_buffer_failures.load(Ordering::Relaxed),
+        );
+        counter(
+            &mut out,
+            "storage_notify_dropped_events_total",
+            "Change events dropped because a /events subscriber fell behind",
+            self.notify_dropped.load(Ordering::Relaxed),
+        );
+        out
+    }
+
+    fn render_requests(&self, out: &mut String) {
+        let requests = self.requests.lock().unwrap();
+        let _ = writeln!(
+            out,
+            "# HELP storage_http_requests_total Requests by method, route and status\n\
+             # TYPE storage_http_requests_total counter"
+        );
+        for ((method, route), stats) in requests.iter() {
+            for (status, count) in &stats.statuses {
+                let _ = writeln!(
+                    out,
+                    "storage_http_requests_total{{method=\"{method}\",route=\"{route}\",status=\"{status}\"}} {count}"
+                );
+            }
+        }
+        let _ = writeln!(
+            out,
+            "# HELP storage_http_request_duration_seconds Time until the response headers were ready\n\
+             # TYPE storage_http_request_duration_seconds histogram"
+        );
+        for ((method, route), stats) in requests.iter() {
+            let labels = format!("method=\"{method}\",route=\"{route}\"");
+            let mut cumulative = 0;
+            for (le, count) in LATENCY_BUCKETS.iter().zip(stats.buckets) {
+                cumulative += count;
+                let _ = writeln!(out, "storage_http_request_duration_seconds_bucket{{{labels},le=\"{le}\"}} {cumulative}");
+            }
+            let _ = writeln!(
+                out,
+                "storage_http_request_duration_seconds_bucket{{{labels},le=\"+Inf\"}} {}\n\
+                 storage_http_request_duration_seconds_sum{{{labels}}} {}\n\
+                 storage_http_request_duration_seconds_count{{{labels}}} {}",
+                stats.count, stats.seconds, stats.count
+            );
+        }
+    }
+}
+
+/// Middleware đếm request HTTP và đo thời gian xử lý theo route
+pub async fn track(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let started = Instant::now();
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched", |path| path.as_str())
+        .to_string();
+    let response = next.run(request).await;
+    state
+        .metrics
+        .record_request(&method, &route, response.status().as_str(), started.elapsed());
+    response
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: i64) {
+    let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} gauge\n{name} {value}");
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: u64) {
+    let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} counter\n{name} {value}");
+}
@@ -0,0 +1,61 @@
+// ## GIỚI HẠN KIỂU NỘI DUNG ĐƯỢC LƯU (STORAGE_ALLOWED_CONTENT_TYPES) ##
+//
+// Khi đặt STORAGE_ALLOWED_CONTENT_TYPES (danh sách phân tách bằng dấu phẩy, ví dụ
+// `image/*,application/pdf`), các route ghi chunk từ chối với `415` chunk có kiểu nội dung
+// không nằm trong danh sách. Kiểu của chunk là `contentType` được khai báo; nếu không khai
+// báo thì kiểu của file được nhận ra từ vài byte đầu của chunk đầu tiên (index 0), và là
+// `application/octet-stream` khi không nhận ra được. Chunk sau không khai báo kiểu thì
+// thuộc kiểu của file nên không bị kiểm tra lại. Không đặt biến thì nhận mọi kiểu như
+// trước đây.
+//
+// So khớp không phân biệt hoa thường và bỏ qua tham số sau ';' (ví dụ `; charset=utf-8`);
+// `type/*` khớp mọi kiểu con, `*/*` khớp tất cả.
+
+/// Số byte đầu của chunk đủ để nhận ra kiểu bằng `sniff`
+pub const SNIFF_LEN: usize = 16;
+
+/// Kiểu của dữ liệu theo chữ ký ở đầu file, `None` nếu không nhận ra
+pub fn sniff(head: &[u8]) -> Option<&'static str> {
+    const SIGNATURES: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"%PDF-", "application/pdf"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\x1f\x8b", "application/gzip"),
+        (b"\x28\xb5\x2f\xfd", "application/zstd"),
+    ];
+    if head.len() >= 12 && &head[..4] == b"RIFF" && &head[8..12] == b"WEBP" {
+        return Some("image/webp");
+    }
+    if head.len() >= 8 && &head[4..8] == b"ftyp" {
+        return Some("video/mp4");
+    }
+    SIGNATURES
+        .iter()
+        .find(|(signature, _)| head.starts_with(signature))
+        .map(|(_, content_type)| *content_type)
+}
+
+/// Kiểu cần kiểm tra của một chunk: kiểu khai báo, hoặc kiểu nhận ra từ `head` nếu là
+/// chunk đầu tiên của file; `None` nếu chunk không quyết định kiểu của file
+pub fn effective(declared: Option<&str>, index: Option<u64>, head: &[u8]) -> Option<String> {
+    match declared {
+        Some(declared) => Some(declared.to_string()),
+        None if index == Some(0) => Some(sniff(head).unwrap_or("application/octet-stream").to_string()),
+        None => None,
+    }
+}
+
+/// `content_type` có khớp một mẫu trong `allowlist` không
+pub fn is_allowed(allowlist: &[String], content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    let main_type = essence.split('/').next().unwrap_or_default();
+    allowlist.iter().any(|pattern| {
+        let pattern = pattern.trim().to_ascii_lowercase();
+        pattern == "*/*"
+            || pattern == essence
+            || pattern.strip_suffix("/*").is_some_and(|prefix| prefix == main_type)
+    })
+}
@@ -0,0 +1,374 @@
+// ## NAMESPACE CHO NHIỀU ỨNG DỤNG ##
+//
+// Một node có thể phục vụ nhiều ứng dụng tách biệt nhau. Các route hiện có thuộc namespace
+// mặc định; namespace khác dùng cùng các route đó sau tiền tố `/ns/:namespace`, ví dụ
+// `POST /ns/app1/store` hay `GET /ns/app1/file/:fileKey`.
+//
+// Mỗi namespace có state riêng dựng từ cùng database nhưng trên các tree riêng mang tiền tố
+// `ns/<tên>/` (shard, value lớn, quota, số chunk của file, alias, manifest, token, TTL...),
+// nên dữ liệu, quota theo tenant, API token tạo qua `/ns/:namespace/admin/tokens`, `/stats`,
+// `/metrics` và `/events` của các namespace không lẫn vào nhau. Các task nền của node (TTL,
+// GC, bộ đệm ghi, scrub...) được chạy riêng cho mỗi namespace từ lần đầu namespace được dùng.
+//
+// Namespace dùng cấu hình của node, và các giới hạn của node vẫn tính chung cho mọi
+// namespace: chế độ chỉ đọc (`POST /admin/readonly`, đĩa đầy), STORAGE_RATE_LIMIT và giới hạn
+// theo client, STORAGE_MAX_CONCURRENT_READS, và STORAGE_MAX_TOTAL_BYTES được so với tổng số
+// byte của mọi namespace (xem `node_bytes`). `quotaBytes` của namespace là giới hạn thêm
+// cho riêng namespace đó.
+//
+// Replication, gossip, đồng bộ từ node khác và snapshot là việc của cả node nên chỉ có ở
+// namespace mặc định (snapshot và `/admin/export` chứa mọi namespace); các route thuộc nhóm
+// này cùng `/admin/reload`, `/admin/readonly` và `/admin/reindex` trả về `404` khi đi qua
+// `/ns/:namespace`.
+//
+// Quản lý namespace (cần STORAGE_ADMIN_API_KEY): `POST /admin/namespaces` với
+// `{"name", "quotaBytes"}` tạo namespace (`409` nếu đã có, `400` nếu tên không hợp lệ: 1-32 ký
+// tự `a-z`, `0-9`, `-`, `_`, bắt đầu bằng chữ hoặc số, khác `default`), `GET /admin/namespaces`
+// liệt kê, `DELETE /admin/namespaces/:name` xóa namespace cùng mọi dữ liệu của nó. Request
+// đang chạy trong namespace bị xóa có thể lỗi.
+
+use axum::extract::{Path, Request, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::task::AbortHandle;
+use tower::ServiceExt;
+
+use crate::error::ApiError;
+use crate::{blocking, build_app, flush_db, require_admin, unix_now, AppState, Config};
+
+/// Độ dài tối đa của tên namespace
+pub const MAX_NAME_LEN: usize = 32;
+// Tên dành cho namespace mặc định (các route không có tiền tố)
+const DEFAULT_NAME: &str = "default";
+
+// Route của cả node, không dùng được qua `/ns/:namespace`
+const NODE_ROUTES: &[&str] = &[
+    "/admin/reload",
+    "/admin/readonly",
+    "/admin/reindex",
+    "/admin/export",
+    "/admin/import",
+    "/admin/namespaces",
+    "/replicate",
+    "/gossip",
+    "/sync/full",
+];
+
+/// Thông tin của một namespace, lưu trong tree `namespaces` dưới dạng JSON
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Info {
+    #[serde(rename = "createdAt")]
+    pub created_at: u64,
+    // Giới hạn tổng số byte của riêng namespace, ngoài STORAGE_MAX_TOTAL_BYTES của node
+    #[serde(rename = "quotaBytes", default, skip_serializing_if = "Option::is_none")]
+    pub quota_bytes: Option<u64>,
+}
+
+// Namespace đã được mở: state, router và các task nền của nó
+struct Opened {
+    state: Arc<AppState>,
+    router: Router,
+    tasks: Vec<AbortHandle>,
+}
+
+pub struct Namespaces {
+    // Tên namespace của state sở hữu; `None` với namespace mặc định
+    name: Option<String>,
+    // `quotaBytes` của namespace này
+    quota_bytes: Option<u64>,
+    // Danh sách namespace, chỉ có ở state của namespace mặc định
+    registry: Option<sled::Tree>,
+    opened: Mutex<HashMap<String, Arc<Opened>>>,
+}
+
+impl Namespaces {
+    /// Quản lý namespace của node, dùng cho state của namespace mặc định
+    pub fn open(registry: sled::Tree) -> Self {
+        Namespaces {
+            name: None,
+            quota_bytes: None,
+            registry: Some(registry),
+            opened: Mutex::default(),
+        }
+    }
+
+    // Dùng cho state của namespace `name`, không quản lý namespace nào
+    fn member(name: &str, info: &Info) -> Self {
+        Namespaces {
+            name: Some(name.to_string()),
+            quota_bytes: info.quota_bytes,
+            registry: None,
+            opened: Mutex::default(),
+        }
+    }
+
+    /// Tên namespace của state; `None` với namespace mặc định
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Giới hạn số byte của riêng namespace này (`quotaBytes`), nếu có
+    pub fn quota_bytes(&self) -> Option<u64> {
+        self.quota_bytes
+    }
+
+    fn registry(&self) -> Result<&sled::Tree, ApiError> {
+        self.registry.as_ref().ok_or(ApiError::NamespaceNotFound)
+    }
+
+    fn info(&self, name: &str) -> sled::Result<Option<Info>> {
+        let Some(registry) = &self.registry else {
+            return Ok(None);
+        };
+        Ok(registry.get(name)?.and_then(|value| serde_json::from_slice(&value).ok()))
+    }
+
+    /// Áp dụng cấu hình mới của node cho các namespace đang mở
+    pub fn reload(&self, config: &Config) {
+        for opened in self.opened.lock().unwrap().values() {
+            opened.state.config.store(Arc::new(config_for(config)));
+        }
+    }
+
+    // State và router của namespace `name`, mở nếu chưa mở. Chạy đồng bộ.
+    fn get_or_open(&self, root: &AppState, name: &str) -> Result<Arc<Opened>, ApiError> {
+        let mut opened = self.opened.lock().unwrap();
+        if let Some(found) = opened.get(name) {
+            return Ok(found.clone());
+        }
+        let info = self
+            .info(name)
+            .map_err(|e| {
+                tracing::error!("Lỗi khi đọc namespace {}: {}", name, e);
+                ApiError::Internal
+            })?
+            .ok_or(ApiError::NamespaceNotFound)?;
+
+        let config = config_for(&root.config.load());
+        let state = crate::build_state_in(root.db.clone(), config, Namespaces::member(name, &info), Some(root));
+        let router = build_app(state.clone());
+        let tasks = spawn_tasks(&state);
+        tracing::info!("-> Đã mở namespace {}", name);
+        let entry = Arc::new(Opened { state, router, tasks });
+        opened.insert(name.to_string(), entry.clone());
+        Ok(entry)
+    }
+}
+
+/// Tổng số byte đang lưu của cả node: bộ đếm `quota_usage` của namespace mặc định cộng với
+/// của mọi namespace khác, kể cả namespace chưa được mở. Chạy đồng bộ.
+pub fn node_bytes(db: &sled::Db) -> sled::Result<u64> {
+    let mut total = 0u64;
+    for name in db.tree_names() {
+        let usage = &name[..] == b"quota_usage" || (name.starts_with(b"ns/") && name.ends_with(b"/quota_usage"));
+        if usage {
+            total = total.saturating_add(crate::quota::sum(&db.open_tree(name)?)?);
+        }
+    }
+    Ok(total)
+}
+
+/// Tiền tố tên tree của namespace `name`
+pub fn tree_prefix(name: &str) -> String {
+    format!("ns/{}/", name)
+}
+
+/// Tên namespace có hợp lệ không (xem đầu module)
+pub fn is_valid_name(name: &str) -> bool {
+    let mut bytes = name.bytes();
+    name.len() <= MAX_NAME_LEN
+        && name != DEFAULT_NAME
+        && bytes.next().is_some_and(|b| b.is_ascii_lowercase() || b.is_ascii_digit())
+        && bytes.all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'_')
+}
+
+// Cấu hình của namespace: cấu hình của node, bỏ các tính năng của cả node
+fn config_for(node: &Config) -> Config {
+    let mut config = node.clone();
+    config.gossip = false;
+    config.peers = Vec::new();
+    config.replication = false;
+    config.read_fallthrough = false;
+    config.sync_from = None;
+    config.snapshot_dir = None;
+    config.html_index = false;
+    config
+}
+
+// Chạy các task nền cần cho dữ liệu của một namespace
+fn spawn_tasks(state: &Arc<AppState>) -> Vec<AbortHandle> {
+    vec![
+        tokio::spawn(crate::hot::run_snapshots(state.clone())).abort_handle(),
+        tokio::spawn(crate::ttl::run(state.clone())).abort_handle(),
+        tokio::spawn(crate::usage::run(state.clone())).abort_handle(),
+        tokio::spawn(crate::upload::run(state.clone())).abort_handle(),
+        tokio::spawn(crate::gc::run(state.clone())).abort_handle(),
+        tokio::spawn(crate::write_buffer::run(state.clone())).abort_handle(),
+        tokio::spawn(crate::flush::run(state.clone())).abort_handle(),
+        tokio::spawn(crate::scrub::run(state.clone())).abort_handle(),
+    ]
+}
+
+/// Middleware chuyển request `/ns/:namespace/<route>` cho router của namespace với đường
+/// dẫn `/<route>`, trước khi qua các middleware của namespace mặc định (token, giới hạn tốc
+/// độ, metrics). Request khác đi tiếp như bình thường.
+pub async fn dispatch(State(state): State<Arc<AppState>>, mut request: Request, next: Next) -> Response {
+    // Tên hợp lệ không có ký tự cần mã hóa nên phần còn lại lấy thẳng từ URI gốc
+    let Some((name, path)) = request.uri().path().strip_prefix("/ns/").and_then(|rest| rest.split_once('/')) else {
+        return next.run(request).await;
+    };
+    if !is_valid_name(name) {
+        return ApiError::NamespaceNotFound.into_response();
+    }
+    let path = format!("/{}", path);
+    if NODE_ROUTES.iter().any(|route| path == *route || path.starts_with(&format!("{}/", route))) {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let name = name.to_string();
+    let uri = match request.uri().query() {
+        Some(query) => format!("{}?{}", path, query),
+        None => path,
+    };
+    let Ok(uri) = uri.parse() else {
+        return ApiError::BadRequest.into_response();
+    };
+    *request.uri_mut() = uri;
+
+    let open_state = state.clone();
+    let opened = match blocking(move || Ok(open_state.namespaces.get_or_open(&open_state, &name))).await {
+        Ok(Ok(opened)) => opened,
+        Ok(Err(e)) => return e.into_response(),
+        Err(status) => return ApiError::from(status).into_response(),
+    };
+    match opened.router.clone().oneshot(request).await {
+        Ok(response) => response,
+        Err(never) => match never {},
+    }
+}
+
+// Body của POST /admin/namespaces
+#[derive(Deserialize)]
+pub(crate) struct CreateRequest {
+    name: String,
+    #[serde(rename = "quotaBytes", default)]
+    quota_bytes: Option<u64>,
+}
+
+// Một namespace trong response của các route /admin/namespaces
+#[derive(Serialize)]
+pub(crate) struct NamespaceEntry {
+    name: String,
+    #[serde(flatten)]
+    info: Info,
+}
+
+// Response của GET /admin/namespaces
+#[derive(Serialize)]
+pub(crate) struct ListResponse {
+    namespaces: Vec<NamespaceEntry>,
+}
+
+/// Handler TẠO namespace
+pub(crate) async fn create(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(request): Json<CreateRequest>,
+) -> Result<(StatusCode, Json<NamespaceEntry>), ApiError> {
+    require_admin(&state, &headers)?;
+    state.ensure_writable()?;
+    if !is_valid_name(&request.name) {
+        return Err(ApiError::BadRequest);
+    }
+    let info = Info {
+        created_at: unix_now(),
+        quota_bytes: request.quota_bytes,
+    };
+    let value = serde_json::to_vec(&info).expect("namespace luôn serialize được");
+    let created = state
+        .namespaces
+        .registry()?
+        .compare_and_swap(&request.name, None as Option<&[u8]>, Some(value))
+        .map_err(|e| {
+            tracing::error!("Lỗi khi tạo namespace {}: {}", request.name, e);
+            ApiError::Internal
+        })?
+        .is_ok();
+    if !created {
+        return Err(ApiError::NamespaceExists);
+    }
+    flush_db(&state).await?;
+    tracing::info!("-> Đã tạo namespace {}", request.name);
+    Ok((StatusCode::CREATED, Json(NamespaceEntry { name: request.name, info })))
+}
+
+/// Handler LIỆT KÊ các namespace (không gồm namespace mặc định)
+pub(crate) async fn list(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Result<Json<ListResponse>, ApiError> {
+    require_admin(&state, &headers)?;
+    let mut namespaces = Vec::new();
+    for item in state.namespaces.registry()?.iter() {
+        let (name, value) = item.map_err(|e| {
+            tracing::error!("Lỗi khi đọc danh sách namespace: {}", e);
+            ApiError::Internal
+        })?;
+        if let Ok(info) = serde_json::from_slice(&value) {
+            namespaces.push(NamespaceEntry {
+                name: String::from_utf8_lossy(&name).into_owned(),
+                info,
+            });
+        }
+    }
+    Ok(Json(ListResponse { namespaces }))
+}
+
+/// Handler XÓA namespace cùng mọi tree của nó; `404` nếu không có namespace này
+pub(crate) async fn remove(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+) -> Result<StatusCode, ApiError> {
+    require_admin(&state, &headers)?;
+    state.ensure_writable()?;
+    let remove_state = state.clone();
+    blocking(move || {
+        let db_error = |e: sled::Error| {
+            tracing::error!("Lỗi khi xóa namespace {}: {}", name, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        };
+        let namespaces = &remove_state.namespaces;
+        let registry = namespaces.registry().map_err(|_| StatusCode::NOT_FOUND)?;
+        if registry.remove(&name).map_err(db_error)?.is_none() {
+            return Err(StatusCode::NOT_FOUND);
+        }
+        if let Some(opened) = namespaces.opened.lock().unwrap().remove(&name) {
+            opened.tasks.iter().for_each(AbortHandle::abort);
+        }
+        let prefix = tree_prefix(&name);
+        // Value của chunk trong backend ngoài sled không nằm trong tree nào của namespace
+        let store = crate::store::open(&remove_state.db, &remove_state.config.load(), &prefix).map_err(db_error)?;
+        if store.tree().is_none() {
+            for item in store.scan_keys(&[]) {
+                store.delete(&item.map_err(db_error)?).map_err(db_error)?;
+            }
+            store.flush().map_err(db_error)?;
+        }
+        for tree in remove_state.db.tree_names() {
+            if tree.starts_with(prefix.as_bytes()) {
+                remove_state.db.drop_tree(&tree).map_err(db_error)?;
+            }
+        }
+        tracing::info!("-> Đã xóa namespace {}", name);
+        Ok(())
+    })
+    .await
+    .map_err(|status| match status {
+        StatusCode::NOT_FOUND => ApiError::NamespaceNotFound,
+        status => ApiError::from(status),
+    })?;
+    flush_db(&state).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,229 @@
+// ## THÔNG BÁO THAY ĐỔI QUA WEBSOCKET ##
+//
+// `GET /events` nâng cấp kết nối lên WebSocket rồi gửi cho client một message text JSON cho
+// mỗi thay đổi: `{"event":"stored","fileKey":...,"chunkHash":...}` sau khi chunk được ghi
+// (mọi đường ghi, kể cả chunk nhận từ peer hoặc qua /sync/full) và
+// `{"event":"deleted","fileKey":...}` khi file không còn chunk nào: sau `DELETE /file/:fileKey`,
+// hoặc khi chunk cuối cùng của file bị xóa (`DELETE /chunk/...`), hết hạn (module ttl) hay
+// bị dọn (module gc). Thay đổi của fileKey nhị phân (route `/b64/...`) không được thông báo.
+//
+// File có manifest (module manifest) được báo `{"event":"completed","fileKey":...,"chunks":N}`
+// khi mọi chunk khai báo trong manifest đã được lưu: sau lần ghi một chunk của manifest, hoặc
+// ngay khi lưu manifest nếu các chunk đã có sẵn. Ghi lại một chunk của file đã đủ thì sự
+// kiện được gửi lại. Mỗi lượt dọn chunk mồ côi (module gc, cả `POST /admin/gc` lẫn task nền)
+// được báo `{"event":"gc_run","applied":...,"orphanedChunks":N,"deletedChunks":N}`.
+//
+// Với `?fileKey=` (alias được phân giải như các route khác), subscriber chỉ nhận sự kiện của
+// file đó cùng các sự kiện không gắn với file nào (`gc_run`, `resync_needed`).
+//
+// Mọi subscriber dùng chung một kênh broadcast giữ tối đa STORAGE_NOTIFY_BUFFER sự kiện
+// chưa được đọc. Subscriber đọc chậm hơn tốc độ ghi (ví dụ khi có một batch lớn) bị kênh
+// vượt qua: các sự kiện cũ nhất của nó bị bỏ và thay vào đó nó nhận
+// `{"event":"resync_needed","dropped":N}`, báo rằng cần truy vấn lại các file đang theo
+// dõi thay vì tin vào luồng sự kiện. Số sự kiện bị bỏ được đếm trong metric
+// `storage_notify_dropped_events_total`.
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Query, State};
+use axum::response::Response;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::error::ApiError;
+use crate::{cardinality, gc, key, manifest, quota, AppState};
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Event<'a> {
+    Stored {
+        #[serde(rename = "fileKey")]
+        file_key: &'a str,
+        #[serde(rename = "chunkHash")]
+        chunk_hash: &'a str,
+    },
+    Deleted {
+        #[serde(rename = "fileKey")]
+        file_key: &'a str,
+    },
+    // Mọi chunk trong manifest của file đã được lưu
+    Completed {
+        #[serde(rename = "fileKey")]
+        file_key: &'a str,
+        chunks: usize,
+    },
+    GcRun {
+        applied: bool,
+        #[serde(rename = "orphanedChunks")]
+        orphaned_chunks: u64,
+        #[serde(rename = "deletedChunks")]
+        deleted_chunks: u64,
+    },
+    // Subscriber đã bị bỏ `dropped` sự kiện vì đọc không kịp
+    ResyncNeeded { dropped: u64 },
+}
+
+// Sự kiện đã serialize sẵn để mỗi subscriber không phải serialize lại, kèm fileKey để lọc
+struct Published {
+    file_key: Option<String>,
+    text: String,
+}
+
+pub struct Notifier {
+    tx: broadcast::Sender<Arc<Published>>,
+}
+
+impl Notifier {
+    pub fn new(capacity: usize) -> Self {
+        let (tx, _) = broadcast::channel(capacity.max(1));
+        Notifier { tx }
+    }
+
+    fn has_subscribers(&self) -> bool {
+        self.tx.receiver_count() > 0
+    }
+
+    fn publish(&self, file_key: Option<&str>, event: &Event) {
+        // Không có subscriber thì không cần serialize
+        if !self.has_subscribers() {
+            return;
+        }
+        let text = serde_json::to_string(event).expect("sự kiện luôn serialize được");
+        let _ = self.tx.send(Arc::new(Published {
+            file_key: file_key.map(str::to_string),
+            text,
+        }));
+    }
+
+    /// Thông báo các chunk vừa được ghi, theo key trong database
+    pub fn stored<'a>(&self, keys: impl Iterator<Item = &'a [u8]>) {
+        for key in keys.filter_map(key::decode) {
+            self.publish(
+                Some(key.file_key),
+                &Event::Stored {
+                    file_key: key.file_key,
+                    chunk_hash: key.chunk_hash,
+                },
+            );
+        }
+    }
+
+    /// Thông báo file vừa bị xóa
+    pub fn deleted(&self, file_key: &str) {
+        self.publish(Some(file_key), &Event::Deleted { file_key });
+    }
+
+    /// Thông báo kết quả của một lượt dọn chunk mồ côi
+    pub fn gc_run(&self, report: &gc::Report) {
+        self.publish(
+            None,
+            &Event::GcRun {
+                applied: report.applied,
+                orphaned_chunks: report.orphaned_chunks,
+                deleted_chunks: report.deleted_chunks,
+            },
+        );
+    }
+}
+
+/// Thông báo `completed` cho các file có manifest vừa đủ chunk sau `writes`. Chạy đồng bộ.
+pub fn completed_by(state: &AppState, writes: &[quota::PendingWrite]) {
+    if !state.notifier.has_subscribers() {
+        return;
+    }
+    let mut written: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for key in writes.iter().filter_map(|w| key::decode(&w.key)) {
+        written.entry(key.file_key).or_default().push(key.chunk_hash);
+    }
+    for (file_key, chunk_hashes) in written {
+        check_completed(state, file_key, Some(&chunk_hashes));
+    }
+}
+
+/// Thông báo `completed` nếu file có manifest và đã lưu đủ mọi chunk trong đó. Với
+/// `written` (các chunkHash vừa ghi), file chỉ được kiểm tra khi có chunk vừa ghi nằm trong
+/// manifest. Chạy đồng bộ.
+pub fn check_completed(state: &AppState, file_key: &str, written: Option<&[&str]>) {
+    if !state.notifier.has_subscribers() {
+        return;
+    }
+    let Ok(Some(manifest)) = manifest::get(&state.manifests, file_key) else {
+        return;
+    };
+    if written.is_some_and(|written| !written.iter().any(|hash| manifest.chunks.iter().any(|c| c == hash))) {
+        return;
+    }
+    // Số chunk đã đếm của file (module cardinality) loại nhanh file chắc chắn chưa đủ, để
+    // mỗi lần ghi trong lúc upload không phải tra cả manifest
+    let stored = state.files.tree.get(file_key).ok().flatten().map_or(0, |v| cardinality::decode(&v));
+    if stored < manifest.chunks.len() as u64 {
+        return;
+    }
+    let tree = state.tree_for(file_key);
+    let complete = manifest
+        .chunks
+        .iter()
+        .all(|chunk_hash| tree.contains_key(key::encode(file_key, chunk_hash)).unwrap_or(false));
+    if complete {
+        state.notifier.publish(
+            Some(file_key),
+            &Event::Completed {
+                file_key,
+                chunks: manifest.chunks.len(),
+            },
+        );
+    }
+}
+
+#[derive(Deserialize)]
+pub struct EventsQuery {
+    #[serde(rename = "fileKey")]
+    file_key: Option<String>,
+}
+
+/// Handler `GET /events`: nâng cấp lên WebSocket và gửi các sự kiện từ lúc này trở đi
+pub async fn subscribe(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<EventsQuery>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, ApiError> {
+    let filter = query.file_key.map(|file_key| state.resolve_file_key(file_key)).transpose()?;
+    // Đăng ký trước khi nâng cấp để không lỡ sự kiện trong lúc bắt tay
+    let rx = state.notifier.tx.subscribe();
+    Ok(ws.on_upgrade(move |socket| forward(state, socket, rx, filter)))
+}
+
+// Chuyển sự kiện cho một subscriber tới khi client đóng kết nối
+async fn forward(state: Arc<AppState>, mut socket: WebSocket, mut rx: broadcast::Receiver<Arc<Published>>, filter: Option<String>) {
+    loop {
+        tokio::select! {
+            event = rx.recv() => {
+                let text = match event {
+                    Ok(published) => {
+                        let other_file = matches!((&filter, &published.file_key), (Some(wanted), Some(file_key)) if wanted != file_key);
+                        if other_file {
+                            continue;
+                        }
+                        published.text.clone()
+                    }
+                    Err(RecvError::Lagged(dropped)) => {
+                        state.metrics.notify_dropped.fetch_add(dropped, Ordering::Relaxed);
+                        serde_json::to_string(&Event::ResyncNeeded { dropped }).expect("sự kiện luôn serialize được")
+                    }
+                    Err(RecvError::Closed) => return,
+                };
+                if socket.send(Message::Text(text)).await.is_err() {
+                    return;
+                }
+            }
+            // Client không cần gửi gì; ping được axum tự trả lời
+            incoming = socket.recv() => {
+                if !matches!(incoming, Some(Ok(message)) if !matches!(message, Message::Close(_))) {
+                    return;
+                }
+            }
+        }
+    }
+}
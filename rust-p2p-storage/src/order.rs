@@ -0,0 +1,102 @@
+// ## INDEX PHỤ THEO THỨ TỰ CHUNK ##
+//
+// Key chính "fileKey:chunkHash" được sắp theo hash nên muốn trả chunk theo index thì
+// phải sắp xếp lại trong bộ nhớ. Khi bật STORAGE_ORDER_INDEX, mỗi lần ghi chunk còn ghi
+// thêm một entry vào tree `chunk_order` với key "fileKey:<index 20 chữ số>:chunkHash",
+// nên quét prefix của tree này cho ra các chunk đúng thứ tự index. Chunk không có index
+// dùng "~" thay cho số (sắp sau mọi chữ số) nên nằm cuối danh sách.
+//
+// Tree chính vẫn là nguồn dữ liệu gốc: entry chỉ trỏ tới chunkHash, bên đọc luôn lấy
+// record thật và bỏ qua entry lỗi thời (chunk đã bị ghi lại với index khác). chunkHash
+// nằm trong key để hai chunk trùng index không đè entry của nhau.
+//
+// Đo trên file 10k chunk (bản release, chunk nhỏ): quét + sắp xếp trong bộ nhớ mất
+// ~16ms, đọc qua index mất ~30ms vì mỗi chunk cần một lần `get` ngẫu nhiên. Index
+// không làm việc đọc toàn bộ file nhanh hơn; lợi ích là thứ tự ổn định ngay từ lúc
+// quét, nên bên đọc dạng luồng có thể trả chunk theo thứ tự mà không cần gom hết trước.
+
+use std::collections::HashSet;
+
+// Vị trí dùng cho chunk không có index
+const NO_INDEX: &str = "~";
+
+/// Key của entry trong tree `chunk_order`
+pub fn entry_key(file_key: &str, index: Option<u64>, chunk_hash: &str) -> Vec<u8> {
+    let slot = match index {
+        Some(index) => format!("{:020}", index),
+        None => NO_INDEX.to_string(),
+    };
+    let mut key = crate::key::prefix(file_key);
+    key.push_str(&slot);
+    key.push(crate::key::SEPARATOR as char);
+    key.push_str(chunk_hash);
+    key.into_bytes()
+}
+
+/// Tách entry thành (fileKey, index, chunkHash)
+pub fn decode_entry(key: &[u8]) -> Option<(&str, Option<u64>, &str)> {
+    let parsed = crate::key::decode(key)?;
+    let (file_key, slot) = parsed.file_key.rsplit_once(crate::key::SEPARATOR as char)?;
+    let index = match slot {
+        NO_INDEX => None,
+        digits => Some(digits.parse().ok()?),
+    };
+    Some((file_key, index, parsed.chunk_hash))
+}
+
+/// Các chunk của `file_key` theo thứ tự index: (index, chunkHash). Một chunkHash có
+/// thể xuất hiện nhiều lần nếu từng được ghi với index khác; bên đọc kiểm tra lại index
+/// trong record để bỏ entry cũ.
+pub fn scan(tree: &sled::Tree, file_key: &str) -> Vec<(Option<u64>, String)> {
+    let mut entries = Vec::new();
+    for key_bytes in tree.scan_prefix(crate::key::prefix(file_key).as_bytes()).keys() {
+        let Ok(key_bytes) = key_bytes else {
+            continue; // Bỏ qua các key lỗi
+        };
+        if let Some((owner, index, chunk_hash)) = decode_entry(&key_bytes)
+            && owner == file_key
+        {
+            entries.push((index, chunk_hash.to_string()));
+        }
+    }
+    entries
+}
+
+/// Các entry của `file_key`, dùng khi xóa hoặc chuyển file sang fileKey khác
+pub fn entries_of(tree: &sled::Tree, file_key: &str) -> Vec<(Vec<u8>, Option<u64>, String)> {
+    scan(tree, file_key)
+        .into_iter()
+        .map(|(index, chunk_hash)| (entry_key(file_key, index, &chunk_hash), index, chunk_hash))
+        .collect()
+}
+
+/// Dựng lại index từ các shard nếu tree còn trống (database có dữ liệu từ trước khi
+/// bật index). Tắt index rồi bật lại cần xóa tree `chunk_order` để được dựng lại.
+pub fn rebuild_if_empty(tree: &sled::Tree, shards: &[sled::Tree]) {
+    if !tree.is_empty() {
+        return;
+    }
+
+    let mut batch = sled::Batch::default();
+    let mut files = HashSet::new();
+    let mut count = 0;
+    for (key, value) in shards.iter().flat_map(|shard| shard.iter()).flatten() {
+        let Some(parsed) = crate::key::decode(&key) else {
+            continue;
+        };
+        let Some(record) = crate::record::decode(&value) else {
+            continue;
+        };
+        batch.insert(entry_key(parsed.file_key, record.index, parsed.chunk_hash), &[]);
+        files.insert(parsed.file_key.to_string());
+        count += 1;
+    }
+    if count == 0 {
+        return;
+    }
+
+    tracing::info!("-> Đang dựng index thứ tự cho {} chunks của {} file", count, files.len());
+    if let Err(e) = tree.apply_batch(batch) {
+        tracing::error!("Lỗi khi dựng index thứ tự: {}", e);
+    }
+}
@@ -0,0 +1,243 @@
+// ## PARITY REED-SOLOMON CHO FILE ĐÃ SEAL ##
+//
+// Khi STORAGE_PARITY_SHARDS (K) > 0, lần seal một file tính thêm K chunk parity
+// Reed-Solomon cho mỗi nhóm ("stripe") tối đa STORAGE_PARITY_DATA_SHARDS (N) chunk dữ
+// liệu liên tiếp theo index thứ tự (chunk không có index xếp cuối, theo chunkHash). Mỗi
+// stripe chịu được mất tối đa K chunk dữ liệu bất kỳ, với dung lượng thêm K/N thay vì
+// một bản sao đầy đủ như replication. Chunk trong stripe dài khác nhau được đệm 0 tới độ
+// dài lớn nhất; độ dài gốc được lưu lại.
+//
+// Parity nằm trong tree `parity` riêng (key là `fileKey:` rồi số thứ tự stripe u32
+// big-endian, value là JSON gồm metadata các chunk dữ liệu và K chunk parity Base64),
+// không lẫn vào kết quả quét chunk. N và K của mỗi stripe suy ra từ chính stripe nên đổi
+// cấu hình không làm hỏng parity đã có. Gỡ seal thì parity bị xóa vì file lại sửa được;
+// seal lại sẽ tính lại.
+//
+// `POST /file/:fileKey/reconstruct` dựng lại các chunk dữ liệu bị mất từ phần còn lại
+// của stripe, kiểm tra SHA-256 với giá trị lúc seal rồi ghi lại. Stripe mất nhiều hơn K
+// chunk (hoặc chunk còn lại đã bị hỏng) thì các chunk mất được báo là không dựng lại được.
+//
+// Mã dùng ma trận Cauchy trên GF(2^8) (đa thức 0x11d) dạng hệ thống: chunk dữ liệu giữ
+// nguyên, mọi ma trận con vuông của phần Cauchy khả nghịch nên N chunk bất kỳ trong N+K
+// chunk của stripe đủ để dựng lại dữ liệu. Vì vậy N+K không được vượt quá 256.
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::OnceLock;
+
+use crate::key;
+use crate::record::StoreMode;
+
+/// Số chunk tối đa của một stripe (dữ liệu + parity)
+pub const MAX_SHARDS: usize = 256;
+
+// Metadata của một chunk dữ liệu trong stripe, đủ để ghi lại record khi dựng lại
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StripeChunk {
+    pub hash: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index: Option<u64>,
+    #[serde(rename = "contentType", default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<StoreMode>,
+    #[serde(default, skip_serializing_if = "std::collections::HashMap::is_empty")]
+    pub metadata: std::collections::HashMap<String, String>,
+    // Độ dài dữ liệu gốc trước khi đệm
+    pub len: usize,
+    // SHA-256 (hex) của dữ liệu gốc
+    pub sha256: String,
+}
+
+// Value của một stripe trong tree `parity`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Stripe {
+    pub chunks: Vec<StripeChunk>,
+    // K chunk parity ở dạng Base64, cùng độ dài với chunk dữ liệu dài nhất
+    pub parity: Vec<String>,
+}
+
+/// Key của stripe thứ `n` của `file_key` trong tree `parity`
+pub fn stripe_key(file_key: &str, n: u32) -> Vec<u8> {
+    let mut key = key::prefix(file_key).into_bytes();
+    key.extend_from_slice(&n.to_be_bytes());
+    key
+}
+
+/// SHA-256 dạng hex của dữ liệu một chunk
+pub fn digest(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+/// Tính stripe cho các chunk dữ liệu (đã theo thứ tự index) với `parity_shards` chunk
+/// parity mỗi stripe gồm tối đa `data_shards` chunk dữ liệu
+pub fn build(chunks: Vec<(StripeChunk, Vec<u8>)>, data_shards: usize, parity_shards: usize) -> Vec<Stripe> {
+    let mut stripes = Vec::new();
+    let mut chunks = chunks.into_iter().peekable();
+    while chunks.peek().is_some() {
+        let group: Vec<_> = chunks.by_ref().take(data_shards).collect();
+        let width = group.iter().map(|(_, data)| data.len()).max().unwrap_or(0);
+        let (metas, mut data): (Vec<_>, Vec<_>) = group.into_iter().unzip();
+        for shard in &mut data {
+            shard.resize(width, 0);
+        }
+        stripes.push(Stripe {
+            chunks: metas,
+            parity: encode(&data, parity_shards).iter().map(|p| STANDARD.encode(p)).collect(),
+        });
+    }
+    stripes
+}
+
+/// Dựng lại dữ liệu các chunk bị mất của stripe từ các chunk còn lại (`present[i]` là
+/// dữ liệu chunk thứ i nếu còn) và parity. Trả về `None` nếu không đủ chunk hoặc dữ liệu
+/// dựng lại không khớp SHA-256 lúc seal.
+pub fn reconstruct(stripe: &Stripe, present: &[Option<Vec<u8>>]) -> Option<Vec<(usize, Vec<u8>)>> {
+    let n = stripe.chunks.len();
+    let parity: Vec<Vec<u8>> = stripe.parity.iter().map(|p| STANDARD.decode(p).ok()).collect::<Option<_>>()?;
+    let width = parity.first().map_or(0, |p| p.len());
+
+    let mut shards: Vec<Option<Vec<u8>>> = present
+        .iter()
+        .map(|data| {
+            data.as_ref().map(|d| {
+                let mut d = d.clone();
+                d.resize(width, 0);
+                d
+            })
+        })
+        .collect();
+    shards.extend(parity.into_iter().map(Some));
+    let missing: Vec<usize> = (0..n).filter(|&i| shards[i].is_none()).collect();
+    let rebuilt = solve(&shards, n)?;
+
+    missing
+        .into_iter()
+        .map(|i| {
+            let mut data = rebuilt[i].clone();
+            data.truncate(stripe.chunks[i].len);
+            (digest(&data) == stripe.chunks[i].sha256).then_some((i, data))
+        })
+        .collect()
+}
+
+// ## SỐ HỌC GF(2^8) ##
+
+struct Tables {
+    exp: [u8; 512],
+    log: [u8; 256],
+}
+
+fn tables() -> &'static Tables {
+    static TABLES: OnceLock<Tables> = OnceLock::new();
+    TABLES.get_or_init(|| {
+        let mut t = Tables { exp: [0; 512], log: [0; 256] };
+        let mut x: u16 = 1;
+        for i in 0..255 {
+            t.exp[i] = x as u8;
+            t.log[x as usize] = i as u8;
+            x <<= 1;
+            if x & 0x100 != 0 {
+                x ^= 0x11d;
+            }
+        }
+        for i in 255..512 {
+            t.exp[i] = t.exp[i - 255];
+        }
+        t
+    })
+}
+
+fn mul(a: u8, b: u8) -> u8 {
+    if a == 0 || b == 0 {
+        return 0;
+    }
+    let t = tables();
+    t.exp[t.log[a as usize] as usize + t.log[b as usize] as usize]
+}
+
+fn inv(a: u8) -> u8 {
+    let t = tables();
+    t.exp[255 - t.log[a as usize] as usize]
+}
+
+// Hệ số của chunk thứ `row` (dữ liệu rồi tới parity) theo `n` chunk dữ liệu: hàng đơn vị
+// cho chunk dữ liệu, hàng Cauchy 1/(x_i + y_j) với x_i = n + i, y_j = j cho parity
+fn coefficients(row: usize, n: usize) -> Vec<u8> {
+    (0..n)
+        .map(|j| if row < n { (row == j) as u8 } else { inv(row as u8 ^ j as u8) })
+        .collect()
+}
+
+/// `parity_shards` mảnh parity cho các mảnh dữ liệu cùng độ dài trong `data`
+pub fn encode(data: &[Vec<u8>], parity_shards: usize) -> Vec<Vec<u8>> {
+    let n = data.len();
+    let width = data.first().map_or(0, |d| d.len());
+    (0..parity_shards)
+        .map(|i| {
+            let coefficients = coefficients(n + i, n);
+            let mut out = vec![0u8; width];
+            for (c, shard) in coefficients.iter().zip(data) {
+                for (o, &b) in out.iter_mut().zip(shard) {
+                    *o ^= mul(*c, b);
+                }
+            }
+            out
+        })
+        .collect()
+}
+
+/// Dựng lại toàn bộ `n` mảnh dữ liệu từ `n` mảnh bất kỳ còn trong `shards` (dữ liệu rồi
+/// tới parity, cùng độ dài)
+pub fn solve(shards: &[Option<Vec<u8>>], n: usize) -> Option<Vec<Vec<u8>>> {
+    let chosen: Vec<usize> = (0..shards.len()).filter(|&i| shards[i].is_some()).take(n).collect();
+    if chosen.len() < n {
+        return None;
+    }
+    let matrix: Vec<Vec<u8>> = chosen.iter().map(|&row| coefficients(row, n)).collect();
+    let inverse = invert(matrix)?;
+    let width = shards[chosen[0]].as_ref()?.len();
+    Some(
+        inverse
+            .iter()
+            .map(|row| {
+                let mut out = vec![0u8; width];
+                for (c, &source) in row.iter().zip(&chosen) {
+                    let shard = shards[source].as_ref().expect("chunk đã được chọn");
+                    for (o, &b) in out.iter_mut().zip(shard) {
+                        *o ^= mul(*c, b);
+                    }
+                }
+                out
+            })
+            .collect(),
+    )
+}
+
+// Nghịch đảo ma trận vuông bằng khử Gauss-Jordan; `None` nếu suy biến
+fn invert(mut m: Vec<Vec<u8>>) -> Option<Vec<Vec<u8>>> {
+    let n = m.len();
+    let mut out: Vec<Vec<u8>> = (0..n).map(|i| (0..n).map(|j| (i == j) as u8).collect()).collect();
+    for col in 0..n {
+        let pivot = (col..n).find(|&r| m[r][col] != 0)?;
+        m.swap(col, pivot);
+        out.swap(col, pivot);
+        let scale = inv(m[col][col]);
+        for j in 0..n {
+            m[col][j] = mul(m[col][j], scale);
+            out[col][j] = mul(out[col][j], scale);
+        }
+        for r in 0..n {
+            let factor = m[r][col];
+            if r == col || factor == 0 {
+                continue;
+            }
+            for j in 0..n {
+                m[r][j] ^= mul(factor, m[col][j]);
+                out[r][j] ^= mul(factor, out[col][j]);
+            }
+        }
+    }
+    Some(out)
+}
@@ -0,0 +1,458 @@
+// ## QUOTA THEO TENANT ##
+//
+// Tenant được xác định bởi phần đầu của fileKey (mặc định 42 ký tự, tức `0x` + địa chỉ
+// 20 byte). Tổng số byte đang lưu của mỗi tenant được giữ trong tree `quota_usage` và
+// được cập nhật cùng transaction với lần ghi chunk, nên bộ đếm không bị lệch khi có
+// nhiều request ghi đồng thời.
+
+use axum::http::StatusCode;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use serde::Deserialize;
+use sled::transaction::{abort, TransactionError, TransactionalTree, UnabortableTransactionError};
+use sled::Transactional;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use crate::cardinality::FileStats;
+
+// Nội dung file cấu hình quota (STORAGE_QUOTA_FILE), ví dụ:
+// {"defaultBytes": 1073741824, "owners": {"0xabc...": 5368709120}}
+#[derive(Deserialize, Default)]
+pub struct QuotaConfig {
+    // Quota mặc định cho mọi tenant; không đặt nghĩa là không giới hạn
+    #[serde(rename = "defaultBytes", default)]
+    pub default_bytes: Option<u64>,
+    // Quota riêng cho từng tenant, ghi đè giá trị mặc định
+    #[serde(default)]
+    pub owners: HashMap<String, u64>,
+}
+
+impl QuotaConfig {
+    /// Đọc file cấu hình quota. Không có đường dẫn thì không giới hạn tenant nào.
+    pub fn load(path: Option<&str>) -> Result<Self, String> {
+        let Some(path) = path else {
+            return Ok(QuotaConfig::default());
+        };
+        let raw = std::fs::read_to_string(path).map_err(|e| format!("không đọc được {}: {}", path, e))?;
+        serde_json::from_str(&raw).map_err(|e| format!("file quota {} không hợp lệ: {}", path, e))
+    }
+}
+
+// Cách xử lý khi ghi vào key đã có dữ liệu (STORAGE_OVERWRITE_MODE, mặc định `verify`).
+// Lần ghi có `?overwrite=true` luôn thay chunk đã có (xem `PendingWrite::set_overwrite`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverwriteMode {
+    // Luôn ghi đè
+    Allow,
+    // Trả về `409` nếu key đã tồn tại
+    Reject,
+    // Trả về `409` nếu dữ liệu khác; giống hệt thì bỏ qua lần ghi và coi như thành công
+    Verify,
+}
+
+impl FromStr for OverwriteMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "allow" => Ok(OverwriteMode::Allow),
+            "reject" => Ok(OverwriteMode::Reject),
+            "verify" => Ok(OverwriteMode::Verify),
+            other => Err(format!("chế độ ghi đè không hỗ trợ: {}", other)),
+        }
+    }
+}
+
+// Một chunk chờ được ghi cùng với thông tin tenant để tính quota
+pub struct PendingWrite {
+    pub shard: usize,
+    pub owner: String,
+    pub key: Vec<u8>,
+    pub value: Vec<u8>,
+    // Value của chunk lớn cho tree `chunks_large`; khi có thì `value` chỉ là record metadata
+    // (xem module tier)
+    pub large: Option<Vec<u8>>,
+    // Hash của value khi khử trùng lặp: `large` được lưu thành blob dùng chung thay vì trong
+    // `chunks_large` (xem module dedup)
+    pub blob: Option<[u8; 32]>,
+    // Entry cho tree `chunk_order`, chỉ có khi bật index thứ tự (xem module order)
+    pub order_key: Option<Vec<u8>>,
+    // Thời điểm hết hạn của chunk, để ghi vào tree `chunk_expiry` (xem module ttl)
+    pub expires_at: Option<u64>,
+    // Chế độ ghi của record mới (xem `record::StoreMode`)
+    pub mode: Option<crate::record::StoreMode>,
+    // Cho phép thay record được ghi bởi chế độ khác (`?overwrite=true`)
+    pub replace_other_mode: bool,
+    // Cách xử lý khi key đã có dữ liệu; mặc định là STORAGE_OVERWRITE_MODE
+    pub overwrite: OverwriteMode,
+    // Đưa chunk vào hàng đợi replication sau khi ghi (false với chunk nhận từ peer, xem
+    // module replication)
+    pub replicate: bool,
+}
+
+impl PendingWrite {
+    /// Áp dụng `?overwrite=true`: thay chunk đã có bất kể STORAGE_OVERWRITE_MODE, kể cả
+    /// record được ghi bởi chế độ khác
+    pub fn set_overwrite(&mut self, overwrite: bool) {
+        self.replace_other_mode = overwrite;
+        if overwrite {
+            self.overwrite = OverwriteMode::Allow;
+        }
+    }
+}
+
+/// Kết quả của `Quotas::write_chunks`
+pub struct Written {
+    // Số fileKey vừa có chunk đầu tiên (xem module cardinality)
+    pub new_files: u64,
+    // Với từng lần ghi, theo thứ tự của `writes`: value trỏ tới blob cùng nội dung đã có sẵn
+    // thay vì được lưu thêm (xem module dedup)
+    pub deduplicated: Vec<bool>,
+    // Số byte không phải ghi nhờ dùng lại blob đã có
+    pub bytes_saved: u64,
+}
+
+pub struct Quotas {
+    pub config: QuotaConfig,
+    // Số ký tự đầu của fileKey dùng làm định danh tenant
+    pub prefix_len: usize,
+    // owner -> tổng số byte đang lưu (u64 big-endian)
+    pub usage: sled::Tree,
+}
+
+impl Quotas {
+    /// Phần đầu của fileKey xác định tenant sở hữu file
+    pub fn owner<'a>(&self, file_key: &'a str) -> &'a str {
+        match file_key.char_indices().nth(self.prefix_len) {
+            Some((end, _)) => &file_key[..end],
+            None => file_key,
+        }
+    }
+
+    /// Tenant của fileKey nhị phân (xem `b64_file_key`): phần đầu cùng độ dài tính theo
+    /// byte, mã hóa base64url với tiền tố "b64:" để không trùng tenant của fileKey dạng chữ
+    pub fn owner_raw(&self, file_key: &[u8]) -> String {
+        let end = file_key.len().min(self.prefix_len);
+        format!("b64:{}", URL_SAFE_NO_PAD.encode(&file_key[..end]))
+    }
+
+    /// Quota của một tenant, `None` nghĩa là không giới hạn
+    pub fn limit_for(&self, owner: &str) -> Option<u64> {
+        self.config.owners.get(owner).copied().or(self.config.default_bytes)
+    }
+
+    /// Ghi các chunk (có thể thuộc nhiều file, nhiều shard) và cập nhật dung lượng của
+    /// các tenant liên quan trong cùng một transaction. Nếu có tenant vượt quota thì
+    /// không chunk nào được ghi và trả về `403`. Entry của index thứ tự (nếu có `order`)
+    /// cũng được ghi trong transaction này, value của chunk lớn cũng vậy khi có `large`
+    /// (value cũ của key được ghi lại dưới dạng chunk nhỏ bị xóa), kể cả tham chiếu tới
+    /// blob dùng chung khi khử trùng lặp (xem module dedup). Key đã tồn tại được
+    /// xử lý theo `overwrite` của từng lần ghi; khi có xung đột thì không chunk nào được ghi và trả về
+    /// `409`, kể cả khi record cũ được ghi bởi chế độ khác mà `replace_other_mode` không
+    /// bật (xem `record::StoreMode`). Số chunk và tổng số byte của từng file trong tree
+    /// `files` được cập nhật cùng lúc. Kết quả cho biết số fileKey vừa có chunk đầu tiên và
+    /// lần ghi nào dùng lại blob đã có; vì được tính trong transaction nên vẫn đúng khi nhiều
+    /// request cùng ghi một nội dung. Backend của `large` không nằm trong sled thì value được
+    /// ghi trước transaction và trả lại như cũ nếu transaction bị hủy (xem `tier::Large::stage`).
+    pub fn write_chunks(
+        &self,
+        shards: &[sled::Tree],
+        order: Option<&sled::Tree>,
+        large: Option<&crate::tier::Large>,
+        files: &sled::Tree,
+        writes: &[PendingWrite],
+    ) -> Result<Written, StatusCode> {
+        let storage_error = |e: sled::Error| {
+            tracing::error!("Lỗi khi insert vào database: {}", e);
+            if crate::disk::is_full(&e) {
+                return StatusCode::INSUFFICIENT_STORAGE;
+            }
+            StatusCode::INTERNAL_SERVER_ERROR
+        };
+
+        // Backend ngoài sled: giữ khóa của các fileKey tới khi xong, đọc độ dài value đang lưu
+        // của từng key rồi ghi trước value mới; value của key không còn dùng bị xóa sau cùng
+        let external = large.filter(|large| large.tree().is_none());
+        let _locked = external.map(|large| large.lock(writes.iter().filter_map(|w| crate::key::decode_raw(&w.key).map(|(f, _)| f))));
+        let mut stored: HashMap<&[u8], Option<usize>> = HashMap::new();
+        let staged = match external {
+            Some(large) => {
+                // Value cuối cùng theo key của chunk của từng key, theo thứ tự ghi
+                let mut last: HashMap<&[u8], Option<&Vec<u8>>> = HashMap::new();
+                for write in writes {
+                    let old = large.store.get(&write.key).map_err(storage_error)?;
+                    stored.entry(write.key.as_slice()).or_insert(old.map(|v| v.len()));
+                    last.insert(write.key.as_slice(), write.large.as_ref().filter(|_| write.blob.is_none()));
+                }
+                let (mut puts, mut deletes) = (Vec::new(), Vec::new());
+                for (key, value) in last {
+                    match value {
+                        Some(value) => puts.push((key.into(), value.as_slice().into())),
+                        None if stored[key].is_some() => deletes.push(key.into()),
+                        None => {}
+                    }
+                }
+                Some(large.stage(puts, deletes).map_err(storage_error)?)
+            }
+            None => None,
+        };
+
+        // Chỉ đưa vào transaction các shard thực sự được ghi, sau đó là tree chunk_order và
+        // tree chunks_large (nếu dùng và là backend) cùng hai tree blob, tree file_chunks và
+        // tree quota_usage nằm cuối
+        let mut touched: Vec<usize> = writes.iter().map(|w| w.shard).collect();
+        touched.sort_unstable();
+        touched.dedup();
+        let mut trees: Vec<&sled::Tree> = touched.iter().map(|&i| &shards[i]).collect();
+        trees.extend(order);
+        if let Some(large) = large {
+            trees.extend(large.tree());
+            trees.extend([&large.blobs.files, &large.blobs.chunks]);
+        }
+        trees.push(files);
+        trees.push(&self.usage);
+
+        let result = trees.as_slice().transaction(|txs| {
+            let (usage, rest) = txs.split_last().expect("luôn có tree quota_usage");
+            let (files_tx, rest) = rest.split_last().expect("luôn có tree file_chunks");
+            let (large_tx, rest) = match large {
+                Some(large) => {
+                    let (rest, large_txs) = rest.split_at(rest.len() - 2 - usize::from(large.tree().is_some()));
+                    match large_txs {
+                        [large_tx, blob_files, blob_chunks] => (Some((Some(large_tx), blob_files, blob_chunks)), rest),
+                        [blob_files, blob_chunks] => (Some((None, blob_files, blob_chunks)), rest),
+                        _ => unreachable!("có hai tree blob, thêm tree chunks_large nếu là backend"),
+                    }
+                }
+                None => (None, rest),
+            };
+            let (order_tx, chunk_trees) = match order {
+                Some(_) => {
+                    let (order_tx, chunk_trees) = rest.split_last().expect("có tree chunk_order");
+                    (Some(order_tx), chunk_trees)
+                }
+                None => (None, rest),
+            };
+
+            // owner -> (số byte thêm vào, số byte bị ghi đè)
+            let mut deltas: HashMap<&str, (u64, u64)> = HashMap::new();
+            // fileKey -> (số chunk mới, số byte thêm vào, số byte bị ghi đè)
+            let mut file_deltas: HashMap<&[u8], (u64, u64, u64)> = HashMap::new();
+            let mut deduplicated = vec![false; writes.len()];
+            let mut bytes_saved = 0;
+            // Độ dài value trong backend ngoài sled của từng key, đổi dần theo các lần ghi
+            let mut current = stored.clone();
+            // Key -> lần ghi có thay record không; value ghi trước cho key không đổi bị trả lại
+            let mut applied: HashMap<&[u8], bool> = HashMap::new();
+            for (write, deduplicated) in writes.iter().zip(&mut deduplicated) {
+                let pos = touched.binary_search(&write.shard).expect("shard đã được thu thập");
+                if write.overwrite != OverwriteMode::Allow
+                    && let Some(existing) = chunk_trees[pos].get(write.key.as_slice())?
+                {
+                    if write.overwrite == OverwriteMode::Verify {
+                        let existing_large = match large_tx {
+                            Some((large_tx, blob_files, blob_chunks)) => {
+                                match (crate::dedup::get_in(blob_files, blob_chunks, &write.key)?, large_tx) {
+                                    (Some(value), _) => Some(value),
+                                    (None, Some(large_tx)) => large_tx.get(write.key.as_slice())?,
+                                    (None, None) => match &staged {
+                                        Some(staged) => staged.get(&write.key)?,
+                                        None => None,
+                                    },
+                                }
+                            }
+                            None => None,
+                        };
+                        let records = (
+                            crate::tier::decode_with(&existing, existing_large.as_deref()),
+                            crate::tier::decode_with(&write.value, write.large.as_deref()),
+                        );
+                        if let (Some(a), Some(b)) = records
+                            && crate::record::same_chunk(&a, &b)
+                        {
+                            applied.entry(write.key.as_slice()).or_insert(false);
+                            continue; // Chunk giống hệt đã có sẵn, không cần ghi lại
+                        }
+                    }
+                    tracing::warn!("Key {} đã tồn tại", String::from_utf8_lossy(&write.key));
+                    return abort(StatusCode::CONFLICT);
+                }
+                let old = chunk_trees[pos].insert(write.key.as_slice(), write.value.as_slice())?;
+                if !write.replace_other_mode
+                    && let (Some(old_mode), Some(mode)) = (old.as_deref().and_then(crate::record::mode_of), write.mode)
+                    && old_mode != mode
+                {
+                    tracing::error!(
+                        "Key {} đang chứa chunk ghi bằng chế độ {:?}, không ghi đè bằng chế độ {:?}",
+                        String::from_utf8_lossy(&write.key),
+                        old_mode,
+                        mode
+                    );
+                    return abort(StatusCode::CONFLICT);
+                }
+                applied.insert(write.key.as_slice(), true);
+                // Độ dài value cũ của key trong backend và trong blob dùng chung
+                let old_large = match large_tx {
+                    Some((large_tx, blob_files, blob_chunks)) => {
+                        // Value theo key của chunk chỉ còn khi value mới không thành blob
+                        let kept = write.large.as_ref().filter(|_| write.blob.is_none());
+                        let old_value = match large_tx {
+                            Some(large_tx) => match kept {
+                                Some(value) => large_tx.insert(write.key.as_slice(), value.as_slice())?,
+                                None => large_tx.remove(write.key.as_slice())?,
+                            }
+                            .map(|v| v.len()),
+                            // Backend ngoài sled đã được ghi trước transaction
+                            None => current.insert(write.key.as_slice(), kept.map(Vec::len)).flatten(),
+                        };
+                        let old_blob = match (&write.large, &write.blob) {
+                            (Some(value), Some(hash)) => {
+                                let put = crate::dedup::put_in(blob_files, blob_chunks, &write.key, hash, value)?;
+                                if put.shared {
+                                    *deduplicated = true;
+                                    bytes_saved += value.len() as u64;
+                                }
+                                put.old
+                            }
+                            _ => crate::dedup::release_in(blob_files, blob_chunks, &write.key)?,
+                        };
+                        [old_value, old_blob.map(|v| v.len())]
+                    }
+                    None => [None, None],
+                };
+                if let (Some(order_tx), Some(order_key)) = (order_tx, &write.order_key) {
+                    order_tx.insert(order_key.as_slice(), &[])?;
+                }
+                let added = (write.value.len() + write.large.as_ref().map_or(0, Vec::len)) as u64;
+                let removed = (old.as_ref().map_or(0, |v| v.len()) + old_large.iter().flatten().sum::<usize>()) as u64;
+                let delta = deltas.entry(write.owner.as_str()).or_default();
+                delta.0 += added;
+                delta.1 += removed;
+                if let Some((file_key, _)) = crate::key::decode_raw(&write.key) {
+                    let file_delta = file_deltas.entry(file_key).or_default();
+                    file_delta.0 += u64::from(old.is_none());
+                    file_delta.1 += added;
+                    file_delta.2 += removed;
+                }
+            }
+
+            let mut new_files = 0;
+            for (file_key, (chunks, added, removed)) in file_deltas {
+                let stats = files_tx.get(file_key)?.map(|v| FileStats::decode(&v)).unwrap_or_default();
+                if stats.chunks == 0 {
+                    new_files += 1;
+                }
+                let stats = FileStats {
+                    chunks: stats.chunks + chunks,
+                    bytes: (stats.bytes + added).saturating_sub(removed),
+                };
+                files_tx.insert(file_key, &stats.encode())?;
+            }
+
+            for (owner, (added, removed)) in deltas {
+                let used = usage.get(owner)?.map_or(0, |v| decode(&v));
+                let new_used = (used + added).saturating_sub(removed);
+                if let Some(limit) = self.limit_for(owner)
+                    && new_used > limit
+                    && added > removed
+                {
+                    tracing::warn!("Tenant {} vượt quota ({} byte)", owner, limit);
+                    return abort(StatusCode::FORBIDDEN);
+                }
+                usage.insert(owner, &new_used.to_be_bytes())?;
+            }
+            let unchanged: HashSet<&[u8]> = applied.into_iter().filter(|&(_, applied)| !applied).map(|(key, _)| key).collect();
+            let written = Written {
+                new_files,
+                deduplicated,
+                bytes_saved,
+            };
+            Ok((written, unchanged))
+        });
+
+        match result {
+            Ok((written, unchanged)) => {
+                if let Some(staged) = staged {
+                    staged.commit(&unchanged);
+                }
+                Ok(written)
+            }
+            Err(e) => {
+                if let Some(staged) = staged {
+                    staged.rollback();
+                }
+                match e {
+                    TransactionError::Abort(status) => Err(status),
+                    TransactionError::Storage(e) => Err(storage_error(e)),
+                }
+            }
+        }
+    }
+
+    /// Số byte tenant đang dùng
+    pub fn used(&self, owner: &str) -> Result<u64, sled::Error> {
+        Ok(self.usage.get(owner)?.map_or(0, |v| decode(&v)))
+    }
+
+    /// Tổng số byte mọi tenant đang dùng
+    pub fn total(&self) -> Result<u64, sled::Error> {
+        sum(&self.usage)
+    }
+
+    /// Cộng (hoặc trừ) trực tiếp vào dung lượng của tenant, dùng cho các thao tác
+    /// không đi qua `write_chunks` như sửa metadata (đổi tên dùng `adjust_in`)
+    pub fn adjust(&self, owner: &str, delta: i64) {
+        let result = self.usage.update_and_fetch(owner, |old| {
+            let used = old.map_or(0, decode);
+            Some(used.saturating_add_signed(delta).to_be_bytes().to_vec())
+        });
+        if let Err(e) = result {
+            tracing::error!("Lỗi khi cập nhật dung lượng tenant {}: {}", owner, e);
+        }
+    }
+
+    /// Tính lại dung lượng của mọi tenant từ dữ liệu thật nếu tree `quota_usage` còn trống
+    /// (database được tạo trước khi có tính năng quota). Value trong backend của `large`
+    /// được tính cùng record.
+    pub fn rebuild_if_empty(&self, shards: &[sled::Tree], large: &crate::tier::Large) {
+        if !self.usage.is_empty() {
+            return;
+        }
+
+        let mut totals: HashMap<String, u64> = HashMap::new();
+        let stored = shards.iter().flat_map(|tree| tree.iter()).chain(large.store.scan_prefix(&[]));
+        for (key, value) in stored.flatten() {
+            let Some(parsed) = crate::key::decode(&key) else {
+                continue;
+            };
+            *totals.entry(self.owner(parsed.file_key).to_string()).or_default() += value.len() as u64;
+        }
+        if totals.is_empty() {
+            return;
+        }
+
+        tracing::info!("-> Đang tính lại dung lượng cho {} tenant", totals.len());
+        for (owner, bytes) in totals {
+            if let Err(e) = self.usage.insert(owner.as_bytes(), &bytes.to_be_bytes()) {
+                tracing::error!("Lỗi khi ghi dung lượng tenant {}: {}", owner, e);
+            }
+        }
+    }
+}
+
+/// Như `Quotas::adjust` nhưng trong transaction của thao tác gây ra thay đổi (`usage` là
+/// tree `quota_usage` trong transaction), để bộ đếm không lệch khi thao tác thất bại
+pub fn adjust_in(usage: &TransactionalTree, owner: &str, delta: i64) -> Result<(), UnabortableTransactionError> {
+    let used = usage.get(owner)?.map_or(0, |v| decode(&v));
+    usage.insert(owner, &used.saturating_add_signed(delta).to_be_bytes())?;
+    Ok(())
+}
+
+fn decode(bytes: &[u8]) -> u64 {
+    bytes.try_into().map_or(0, u64::from_be_bytes)
+}
+
+/// Tổng dung lượng trong một tree `quota_usage`
+pub fn sum(usage: &sled::Tree) -> Result<u64, sled::Error> {
+    usage.iter().values().try_fold(0u64, |sum, v| Ok(sum.saturating_add(decode(&v?))))
+}
@@ -0,0 +1,310 @@
+// ## GIỚI HẠN TỐC ĐỘ VÀ SỐ LƯỢT ĐỌC ĐỒNG THỜI CỦA CLIENT ##
+//
+// Một client upload quá nhanh có thể chiếm hết node khiến các client đọc phải chờ. Middleware
+// `enforce` áp các giới hạn sau, mỗi giới hạn tắt khi không đặt (hoặc đặt 0):
+//
+// - STORAGE_RATE_LIMIT / STORAGE_CLIENT_RATE_LIMIT: số request mỗi giây của cả node / của
+//   một client.
+// - STORAGE_STORE_BYTES_PER_SEC / STORAGE_CLIENT_STORE_BYTES_PER_SEC: số byte body mỗi giây
+//   của các route ghi dữ liệu chunk (`/store...`, `/b64/store...`, `/upload/...`,
+//   `/replicate`) của cả node / của một client.
+// - STORAGE_MAX_SCANS_PER_CLIENT: số request đọc cả file (quét prefix, xem `SCAN_ROUTES`)
+//   chạy cùng lúc của một client, tính tới lúc có header response. Giới hạn chung của cả
+//   node vẫn là STORAGE_MAX_CONCURRENT_READS.
+//
+// Tốc độ dùng token bucket chứa tối đa lượng của một giây. Request chỉ được nhận khi bucket
+// byte chưa bị âm; byte của body được trừ dần khi server đọc tới, nên một upload lớn có thể
+// làm bucket âm và các upload tiếp theo phải chờ tới khi bù lại. Request vượt giới hạn nhận
+// `429` (`rate_limited`, hoặc `client_busy` với số lượt đọc đồng thời) kèm header
+// `Retry-After` (giây) và trường `retryAfter` trong body.
+//
+// Client là token gửi kèm request nếu token hợp lệ (xem module auth), ngược lại là địa chỉ IP
+// của kết nối, nên các client sau cùng một NAT dùng chung giới hạn trừ khi dùng token riêng.
+// `/health` và `/version` không bị giới hạn. Các giới hạn đọc lại từ cấu hình ở mỗi request
+// nên `/admin/reload` áp dụng ngay.
+
+use axum::body::Body;
+use axum::extract::{ConnectInfo, MatchedPath, Request, State};
+use axum::http::{header, HeaderValue, Method};
+use axum::middleware::Next;
+use axum::response::Response;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio_stream::StreamExt;
+
+use crate::error::ApiError;
+use crate::{auth, AppState};
+
+/// Các route đọc cả file bằng một lượt quét prefix, bị giới hạn bởi
+/// STORAGE_MAX_SCANS_PER_CLIENT
+pub const SCAN_ROUTES: &[&str] = &[
+    "/file/:fileKey",
+    "/file/:fileKey/probe",
+    "/file/:fileKey/tail",
+    "/file/:fileKey/stream",
+    "/file/:fileKey/stream-download",
+    "/file/:fileKey/multipart",
+    "/file/:fileKey/manifest",
+    "/file/:fileKey/query",
+    "/files/batch",
+    "/vfile/:fileKey",
+    "/b64/file/:encodedKey",
+    "/challenge/sample",
+];
+
+// Số client tối đa được giữ trạng thái trước khi dọn các client đã rảnh
+const MAX_CLIENTS: usize = 10_000;
+
+// Token bucket chứa tối đa lượng của một giây; `level` âm là đang nợ
+#[derive(Clone, Copy)]
+struct Bucket {
+    level: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn full(rate: u64, now: Instant) -> Self {
+        Bucket {
+            level: rate as f64,
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, rate: u64, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.level = (self.level + elapsed * rate as f64).min(rate as f64);
+        self.updated = now;
+    }
+
+    // Thời gian chờ tới khi bucket có đủ `amount`, `None` nếu đã đủ
+    fn shortfall(&mut self, rate: u64, amount: f64, now: Instant) -> Option<Duration> {
+        self.refill(rate, now);
+        (self.level < amount).then(|| Duration::from_secs_f64((amount - self.level) / rate as f64))
+    }
+
+    fn is_full(&self, rate: u64, now: Instant) -> bool {
+        let mut bucket = *self;
+        bucket.refill(rate, now);
+        bucket.level >= rate as f64
+    }
+}
+
+// Trạng thái của một client
+struct Client {
+    requests: Bucket,
+    bytes: Bucket,
+    scans: usize,
+}
+
+/// Trạng thái giới hạn của cả node, nằm trong `AppState`
+pub struct Limiter {
+    requests: Mutex<Option<Bucket>>,
+    bytes: Arc<Mutex<Option<Bucket>>>,
+    clients: Arc<Mutex<HashMap<String, Client>>>,
+}
+
+impl Default for Limiter {
+    fn default() -> Self {
+        Limiter {
+            requests: Mutex::new(None),
+            bytes: Arc::new(Mutex::new(None)),
+            clients: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+}
+
+// Các giới hạn đang dùng, đọc từ cấu hình của request hiện tại
+#[derive(Clone, Copy)]
+struct Rates {
+    requests: Option<u64>,
+    client_requests: Option<u64>,
+    bytes: Option<u64>,
+    client_bytes: Option<u64>,
+}
+
+fn nonzero(rate: Option<u64>) -> Option<u64> {
+    rate.filter(|&rate| rate > 0)
+}
+
+impl Limiter {
+    // Lấy suất cho một request; trả về thời gian cần chờ nếu vượt giới hạn tốc độ
+    fn admit(&self, client: &str, rates: Rates, upload: bool, now: Instant) -> Result<(), Duration> {
+        let mut requests = self.requests.lock().unwrap();
+        let mut bytes = self.bytes.lock().unwrap();
+        let mut clients = self.clients.lock().unwrap();
+        if clients.len() >= MAX_CLIENTS {
+            let (client_requests, client_bytes) = (rates.client_requests.unwrap_or(0), rates.client_bytes.unwrap_or(0));
+            clients.retain(|_, c| {
+                c.scans > 0 || !c.requests.is_full(client_requests, now) || !c.bytes.is_full(client_bytes, now)
+            });
+        }
+        let entry = clients.entry(client.to_string()).or_insert_with(|| Client {
+            requests: Bucket::full(rates.client_requests.unwrap_or(0), now),
+            bytes: Bucket::full(rates.client_bytes.unwrap_or(0), now),
+            scans: 0,
+        });
+
+        // Kiểm tra mọi bucket trước rồi mới trừ, để request bị từ chối không tốn suất
+        let mut wait = Duration::ZERO;
+        if let Some(rate) = rates.requests {
+            let bucket = requests.get_or_insert_with(|| Bucket::full(rate, now));
+            wait = wait.max(bucket.shortfall(rate, 1.0, now).unwrap_or_default());
+        }
+        if let Some(rate) = rates.client_requests {
+            wait = wait.max(entry.requests.shortfall(rate, 1.0, now).unwrap_or_default());
+        }
+        if upload {
+            // Bucket byte chỉ cần không âm, byte thực tế được trừ khi đọc body
+            if let Some(rate) = rates.bytes {
+                let bucket = bytes.get_or_insert_with(|| Bucket::full(rate, now));
+                wait = wait.max(bucket.shortfall(rate, f64::MIN_POSITIVE, now).unwrap_or_default());
+            }
+            if let Some(rate) = rates.client_bytes {
+                wait = wait.max(entry.bytes.shortfall(rate, f64::MIN_POSITIVE, now).unwrap_or_default());
+            }
+        }
+        if wait > Duration::ZERO {
+            return Err(wait);
+        }
+        if let Some(bucket) = requests.as_mut().filter(|_| rates.requests.is_some()) {
+            bucket.level -= 1.0;
+        }
+        if rates.client_requests.is_some() {
+            entry.requests.level -= 1.0;
+        }
+        Ok(())
+    }
+
+    // Trừ `amount` byte body đã đọc vào bucket của node và của client
+    fn charge(bytes: &Mutex<Option<Bucket>>, clients: &Mutex<HashMap<String, Client>>, client: &str, rates: Rates, amount: usize) {
+        let now = Instant::now();
+        if let Some(rate) = rates.bytes
+            && let Some(bucket) = bytes.lock().unwrap().as_mut()
+        {
+            bucket.refill(rate, now);
+            bucket.level -= amount as f64;
+        }
+        if let Some(rate) = rates.client_bytes
+            && let Some(entry) = clients.lock().unwrap().get_mut(client)
+        {
+            entry.bytes.refill(rate, now);
+            entry.bytes.level -= amount as f64;
+        }
+    }
+
+    // Lấy một suất đọc cả file cho client; `None` nếu client đã đủ `limit` lượt
+    fn start_scan(&self, client: &str, limit: usize) -> Option<ScanGuard> {
+        let mut clients = self.clients.lock().unwrap();
+        let now = Instant::now();
+        let entry = clients.entry(client.to_string()).or_insert_with(|| Client {
+            requests: Bucket::full(0, now),
+            bytes: Bucket::full(0, now),
+            scans: 0,
+        });
+        if entry.scans >= limit {
+            return None;
+        }
+        entry.scans += 1;
+        Some(ScanGuard {
+            clients: self.clients.clone(),
+            client: client.to_string(),
+        })
+    }
+}
+
+// Trả lại suất đọc cả file khi response đã có header (hoặc request bị hủy)
+struct ScanGuard {
+    clients: Arc<Mutex<HashMap<String, Client>>>,
+    client: String,
+}
+
+impl Drop for ScanGuard {
+    fn drop(&mut self) {
+        if let Some(entry) = self.clients.lock().unwrap().get_mut(&self.client) {
+            entry.scans = entry.scans.saturating_sub(1);
+        }
+    }
+}
+
+// Route ghi dữ liệu chunk có body bị tính vào giới hạn byte
+fn is_upload(method: &Method, route: &str) -> bool {
+    matches!(*method, Method::POST | Method::PUT)
+        && (route.starts_with("/store") || route.starts_with("/b64/store") || route.starts_with("/upload/") || route == "/replicate")
+}
+
+// Định danh client của request: id của token hợp lệ, ngược lại là IP của kết nối
+fn client_of(state: &AppState, request: &Request) -> String {
+    if let Some(token) = auth::provided(request.headers())
+        && matches!(auth::authenticate(state, request.headers()), Ok(Some(_)))
+    {
+        return format!("token:{}", auth::token_id(token));
+    }
+    match request.extensions().get::<ConnectInfo<SocketAddr>>() {
+        Some(ConnectInfo(addr)) => format!("ip:{}", addr.ip()),
+        None => "unknown".to_string(),
+    }
+}
+
+fn rejected(state: &AppState, err: ApiError, wait: Duration) -> Response {
+    state.metrics.rate_limited.fetch_add(1, Ordering::Relaxed);
+    let seconds = wait.as_secs_f64().ceil().max(1.0) as u64;
+    let mut details = serde_json::Map::new();
+    details.insert("retryAfter".into(), seconds.into());
+    let mut response = err.with_details(details);
+    response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+    response
+}
+
+/// Middleware áp giới hạn tốc độ và số lượt đọc đồng thời (xem đầu module)
+pub async fn enforce(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let config = state.config.load();
+    let rates = Rates {
+        requests: nonzero(config.rate_limit),
+        client_requests: nonzero(config.client_rate_limit),
+        bytes: nonzero(config.store_bytes_per_sec),
+        client_bytes: nonzero(config.client_store_bytes_per_sec),
+    };
+    let max_scans = config.max_scans_per_client;
+    drop(config);
+    let route = request.extensions().get::<MatchedPath>().map_or("", |path| path.as_str()).to_string();
+    let upload = is_upload(request.method(), &route) && (rates.bytes.is_some() || rates.client_bytes.is_some());
+    let scan = max_scans > 0 && SCAN_ROUTES.contains(&route.as_str());
+    let limited = rates.requests.is_some() || rates.client_requests.is_some() || upload || scan;
+    if !limited || matches!(route.as_str(), "/health" | "/version") {
+        return next.run(request).await;
+    }
+
+    let client = client_of(&state, &request);
+    if let Err(wait) = state.limiter.admit(&client, rates, upload, Instant::now()) {
+        tracing::warn!("Từ chối request {} {} của {}: vượt giới hạn tốc độ", request.method(), route, client);
+        return rejected(&state, ApiError::RateLimited, wait);
+    }
+    let _scan = if scan {
+        let Some(guard) = state.limiter.start_scan(&client, max_scans) else {
+            tracing::warn!("Từ chối request {} của {}: đã có {} lượt đọc file đồng thời", route, client, max_scans);
+            return rejected(&state, ApiError::ClientBusy, Duration::from_secs(1));
+        };
+        Some(guard)
+    } else {
+        None
+    };
+
+    if !upload {
+        return next.run(request).await;
+    }
+    // Trừ byte của body vào bucket khi handler đọc tới
+    let (bytes, clients) = (state.limiter.bytes.clone(), state.limiter.clients.clone());
+    let request = request.map(|body| {
+        let counted = body.into_data_stream().map(move |frame| {
+            if let Ok(data) = &frame {
+                Limiter::charge(&bytes, &clients, &client, rates, data.len());
+            }
+            frame
+        });
+        Body::from_stream(counted)
+    });
+    next.run(request).await
+}
@@ -0,0 +1,109 @@
+// ## ĐỊNH DẠNG VALUE TRONG DATABASE ##
+//
+// Có hai định dạng record:
+// - `json`: cấu trúc JSON {"value": "...", ...} như từ trước tới nay (byte đầu là '{').
+// - `bare`: byte tag 0x01, 4 byte độ dài header (big-endian), header JSON chỉ chứa các
+//   trường metadata, rồi tới chuỗi Base64 nguyên văn. Chuỗi Base64 (phần lớn nhất của
+//   record) không phải escape/parse qua JSON nữa.
+// Khi đọc, định dạng được nhận ra qua byte đầu nên hai loại record dùng chung được.
+// Record ở cả hai định dạng có thể được mã hóa (byte đầu 0x03, xem module cipher) và bọc
+// thêm mã xóa (byte đầu 0x02, xem module erasure).
+
+use crate::StoredChunkValue;
+use std::str::FromStr;
+
+// Byte đầu tiên của record định dạng `bare`; không trùng với '{' của JSON
+const TAG_BARE: u8 = 0x01;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValueFormat {
+    Json,
+    Bare,
+}
+
+impl FromStr for ValueFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(ValueFormat::Json),
+            "bare" | "raw" => Ok(ValueFormat::Bare),
+            other => Err(format!("định dạng value không hỗ trợ: {}", other)),
+        }
+    }
+}
+
+/// Đường ghi đã tạo ra record. Dữ liệu được gửi dạng Base64 trong JSON (`/store`,
+/// `/store/batch`, CAS) và dạng byte thô (`/store/raw`, gRPC) đều được lưu thành Base64,
+/// nhưng hai đường ghi không được âm thầm thay record của nhau: ghi vào key đang chứa
+/// record của chế độ khác trả về `409` trừ khi request bật `?overwrite=true`. Record cũ
+/// không có tag này tương thích với mọi chế độ. Nén (`encoding`) được ghi riêng trong
+/// record nên không phải là một chế độ.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreMode {
+    Base64,
+    Raw,
+}
+
+/// Chế độ ghi của record đã lưu, `None` nếu record hỏng hoặc không có tag
+pub fn mode_of(bytes: &[u8]) -> Option<StoreMode> {
+    decode(bytes)?.mode
+}
+
+/// Serialize record theo định dạng được chọn
+pub fn encode(mut record: StoredChunkValue, format: ValueFormat) -> serde_json::Result<Vec<u8>> {
+    match format {
+        ValueFormat::Json => serde_json::to_vec(&record),
+        ValueFormat::Bare => {
+            // `value` rỗng sẽ bị bỏ qua khi serialize nên header chỉ còn metadata
+            let value = std::mem::take(&mut record.value);
+            let header = serde_json::to_vec(&record)?;
+
+            let mut out = Vec::with_capacity(1 + 4 + header.len() + value.len());
+            out.push(TAG_BARE);
+            out.extend_from_slice(&(header.len() as u32).to_be_bytes());
+            out.extend_from_slice(&header);
+            out.extend_from_slice(value.as_bytes());
+            Ok(out)
+        }
+    }
+}
+
+/// Hai record có cùng dữ liệu chunk (sau khi giải nén) và metadata hay không, bỏ qua
+/// thời điểm lưu và định dạng/codec đã dùng khi ghi
+pub fn same_chunk(a: &StoredChunkValue, b: &StoredChunkValue) -> bool {
+    a.index == b.index
+        && a.content_type == b.content_type
+        && a.delta_base == b.delta_base
+        && a.metadata == b.metadata
+        && crate::compress::decompress(&a.value, a.encoding) == crate::compress::decompress(&b.value, b.encoding)
+}
+
+/// Định dạng của record đã lưu, nhận ra qua byte đầu (không kiểm tra phần còn lại; với
+/// record dùng mã xóa hoặc đã mã hóa là byte đầu của record bên trong)
+pub fn format_of(bytes: &[u8]) -> ValueFormat {
+    match bytes.first() {
+        Some(&TAG_BARE) => ValueFormat::Bare,
+        Some(&crate::erasure::TAG) => crate::erasure::decode(bytes).map_or(ValueFormat::Json, |inner| format_of(&inner)),
+        Some(&crate::cipher::TAG) => crate::cipher::decrypt(bytes).map_or(ValueFormat::Json, |inner| format_of(&inner)),
+        _ => ValueFormat::Json,
+    }
+}
+
+/// Đọc record ở bất kỳ định dạng nào. Trả về `None` nếu dữ liệu hỏng (kể cả khi không
+/// giải mã được).
+pub fn decode(bytes: &[u8]) -> Option<StoredChunkValue> {
+    let unwrapped = crate::erasure::decode(bytes)?;
+    let bytes = &*crate::cipher::decrypt(&unwrapped)?;
+    match format_of(bytes) {
+        ValueFormat::Bare => {
+            let len_bytes: [u8; 4] = bytes.get(1..5)?.try_into().ok()?;
+            let header_end = 5usize.checked_add(u32::from_be_bytes(len_bytes) as usize)?;
+            let mut record: StoredChunkValue = serde_json::from_slice(bytes.get(5..header_end)?).ok()?;
+            record.value = String::from_utf8(bytes.get(header_end..)?.to_vec()).ok()?;
+            Some(record)
+        }
+        ValueFormat::Json => serde_json::from_slice(bytes).ok(),
+    }
+}
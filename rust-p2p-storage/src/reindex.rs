@@ -0,0 +1,516 @@
+// ## KIỂM TRA VÀ DỰNG LẠI CÁC INDEX PHỤ ##
+//
+// Ngoài các shard (nguồn dữ liệu gốc), database còn các cấu trúc suy ra từ chunk: số chunk
+// và số byte của từng file (tree `file_chunks`, xem module cardinality), dung lượng của từng tenant
+// (`quota_usage`), index thứ tự (`chunk_order` khi bật STORAGE_ORDER_INDEX), value của
+// chunk lớn (`chunks_large` hoặc file của backend khác, xem module tier và store) và số
+// tham chiếu của blob dùng chung (`files` và `chunks`, xem module dedup). Một số thao tác
+// (xóa, đổi tên, TTL) cập nhật chúng sau khi đã sửa shard, nên process bị dừng đột ngột có
+// thể để lại chỗ lệch.
+//
+// `GET /admin/consistency` quét shard rồi so với các cấu trúc này và trả về số chỗ lệch của
+// từng loại (`missing`: thiếu, `wrong`: sai giá trị, `orphaned`: thừa, kèm vài key ví dụ)
+// mà không sửa gì. Alias trỏ tới fileKey không còn chunk cũng được báo nhưng không bao giờ
+// bị xóa: alias là dữ liệu của client.
+//
+// `POST /admin/reindex` chạy nền việc dựng lại từ đầu. Node chuyển sang chỉ đọc trong lúc
+// chạy (trở lại trạng thái cũ khi xong), quét shard theo lô `BATCH` chunk và ghi kết quả
+// vào các tree tạm `reindex_*` cùng con trỏ tiến độ trong một transaction, sau đó thay nội
+// dung các tree thật bằng tree tạm, xóa value chunk lớn không còn record và đếm lại số tham
+// chiếu của blob (blob không còn ai dùng bị xóa). Process bị dừng
+// giữa chừng thì lần gọi sau tiếp tục từ con trỏ đã lưu thay vì quét lại từ đầu; chỉ nên
+// tiếp tục khi node chưa nhận ghi kể từ lần dừng, nếu không hãy gọi với `?restart=true`.
+// `GET /admin/reindex` (và response của `POST`) cho biết tiến độ.
+
+use serde::Serialize;
+use sled::Transactional;
+use std::collections::HashMap;
+use std::ops::Bound;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+
+use crate::cardinality::{self, FileStats};
+use crate::{dedup, key, order, record, write_buffer, AppState};
+
+/// Số chunk xử lý trong một transaction khi dựng lại
+pub const BATCH: usize = 1000;
+// Số key ví dụ tối đa cho mỗi loại lệch trong báo cáo
+const MAX_EXAMPLES: usize = 10;
+
+// Tree giữ con trỏ tiến độ và các tree tạm chứa kết quả dựng lại
+const STATE_TREE: &str = "reindex_state";
+const FILES_TREE: &str = "reindex_file_chunks";
+const USAGE_TREE: &str = "reindex_quota_usage";
+const ORDER_TREE: &str = "reindex_chunk_order";
+
+/// Các chỗ lệch của một cấu trúc so với shard
+#[derive(Debug, Default, Serialize)]
+pub struct Discrepancies {
+    pub missing: u64,
+    pub wrong: u64,
+    pub orphaned: u64,
+    pub examples: Vec<String>,
+}
+
+impl Discrepancies {
+    fn is_empty(&self) -> bool {
+        self.missing + self.wrong + self.orphaned == 0
+    }
+
+    fn note(&mut self, count: fn(&mut Self) -> &mut u64, key: &[u8]) {
+        *count(self) += 1;
+        if self.examples.len() < MAX_EXAMPLES {
+            self.examples.push(String::from_utf8_lossy(key).into_owned());
+        }
+    }
+}
+
+/// Kết quả của `GET /admin/consistency`
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub consistent: bool,
+    // Số chunk đã quét trong các shard
+    pub chunks: u64,
+    #[serde(rename = "fileCounts")]
+    pub file_counts: Discrepancies,
+    #[serde(rename = "quotaUsage")]
+    pub quota_usage: Discrepancies,
+    // `null` khi không bật STORAGE_ORDER_INDEX
+    #[serde(rename = "orderIndex")]
+    pub order_index: Option<Discrepancies>,
+    #[serde(rename = "largeValues")]
+    pub large_values: Discrepancies,
+    // Số tham chiếu của blob dùng chung so với tree `files`; key ví dụ là hash dạng hex
+    #[serde(rename = "blobRefs")]
+    pub blob_refs: Discrepancies,
+    // Alias trỏ tới fileKey không còn chunk (chỉ báo, không sửa)
+    #[serde(rename = "danglingAliases")]
+    pub dangling_aliases: Discrepancies,
+}
+
+/// Tiến độ của lần dựng lại gần nhất
+#[derive(Debug, Clone, Serialize)]
+pub struct Progress {
+    pub running: bool,
+    // "idle", "scanning", "swapping", "done" hoặc "failed"
+    pub phase: &'static str,
+    // Số chunk đã quét, tính cả phần đã quét trước khi tiếp tục
+    pub scanned: u64,
+    // Lần chạy này tiếp tục từ con trỏ đã lưu
+    pub resumed: bool,
+    #[serde(rename = "startedAt")]
+    pub started_at: Option<u64>,
+    #[serde(rename = "finishedAt")]
+    pub finished_at: Option<u64>,
+    pub error: Option<String>,
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Progress {
+            running: false,
+            phase: "idle",
+            scanned: 0,
+            resumed: false,
+            started_at: None,
+            finished_at: None,
+            error: None,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Reindexer {
+    progress: Mutex<Progress>,
+}
+
+impl Reindexer {
+    pub fn progress(&self) -> Progress {
+        self.progress.lock().unwrap().clone()
+    }
+
+    fn update(&self, f: impl FnOnce(&mut Progress)) {
+        f(&mut self.progress.lock().unwrap());
+    }
+}
+
+// Tenant sở hữu chunk có fileKey `file_key` (dạng chữ hoặc nhị phân), như lúc ghi
+fn owner_of(state: &AppState, file_key: &[u8]) -> String {
+    match std::str::from_utf8(file_key) {
+        Ok(file_key) => state.quotas.owner(file_key).to_string(),
+        Err(_) => state.quotas.owner_raw(file_key),
+    }
+}
+
+// Số byte một chunk chiếm trong quota: record trong shard cộng value trong `chunks_large`
+// hoặc blob mà chunk trỏ tới
+fn chunk_bytes(state: &AppState, db_key: &[u8], value: &[u8], record: Option<&crate::StoredChunkValue>) -> sled::Result<u64> {
+    let large = match record {
+        Some(record) if record.large => state.large.get(db_key)?.map_or(0, |v| v.len()),
+        _ => 0,
+    };
+    Ok((value.len() + large) as u64)
+}
+
+/// So các index phụ với shard mà không sửa gì. Chạy đồng bộ nên cần được gọi qua `blocking`.
+pub fn check(state: &AppState) -> sled::Result<Report> {
+    let mut counts: HashMap<Vec<u8>, FileStats> = HashMap::new();
+    let mut usage: HashMap<String, u64> = HashMap::new();
+    let mut order_index = state.order.as_ref().map(|_| Discrepancies::default());
+    let mut large_values = Discrepancies::default();
+    let mut chunks = 0;
+
+    for shard in &state.shards {
+        for item in shard.iter() {
+            let (db_key, value) = item?;
+            let Some((file_key, _)) = key::decode_raw(&db_key) else {
+                continue;
+            };
+            chunks += 1;
+            let record = record::decode(&value);
+            let bytes = chunk_bytes(state, &db_key, &value, record.as_ref())?;
+            let stats = counts.entry(file_key.to_vec()).or_default();
+            stats.chunks += 1;
+            stats.bytes += bytes;
+            *usage.entry(owner_of(state, file_key)).or_default() += bytes;
+            let Some(record) = record else {
+                continue;
+            };
+            if record.large && !state.large.contains_key(&db_key)? {
+                large_values.note(|d| &mut d.missing, &db_key);
+            }
+            if let (Some(tree), Some(report), Some(parsed)) = (&state.order, order_index.as_mut(), key::decode(&db_key))
+                && !tree.contains_key(order::entry_key(parsed.file_key, record.index, parsed.chunk_hash))?
+            {
+                report.note(|d| &mut d.missing, &db_key);
+            }
+        }
+    }
+
+    let mut file_counts = Discrepancies::default();
+    for (file_key, expected) in &counts {
+        match state.files.tree.get(file_key)? {
+            None => file_counts.note(|d| &mut d.missing, file_key),
+            Some(actual) if FileStats::decode(&actual) != *expected => file_counts.note(|d| &mut d.wrong, file_key),
+            Some(_) => {}
+        }
+    }
+    for item in state.files.tree.iter() {
+        let (file_key, _) = item?;
+        if !counts.contains_key(file_key.as_ref()) {
+            file_counts.note(|d| &mut d.orphaned, &file_key);
+        }
+    }
+
+    let mut quota_usage = Discrepancies::default();
+    for item in state.quotas.usage.iter() {
+        let (owner, actual) = item?;
+        let expected = std::str::from_utf8(&owner).ok().and_then(|owner| usage.get(owner)).copied();
+        match expected {
+            Some(expected) if expected != cardinality::decode(&actual) => quota_usage.note(|d| &mut d.wrong, &owner),
+            // Tenant đã xóa hết dữ liệu có thể còn giữ giá trị 0
+            None if cardinality::decode(&actual) != 0 => quota_usage.note(|d| &mut d.orphaned, &owner),
+            _ => {}
+        }
+    }
+    for owner in usage.keys() {
+        if !state.quotas.usage.contains_key(owner)? {
+            quota_usage.note(|d| &mut d.missing, owner.as_bytes());
+        }
+    }
+
+    if let (Some(tree), Some(report)) = (&state.order, order_index.as_mut()) {
+        for item in tree.iter() {
+            let (entry, _) = item?;
+            if !is_current_entry(state, &entry)? {
+                report.note(|d| &mut d.orphaned, &entry);
+            }
+        }
+    }
+
+    for item in state.large.store.scan_keys(&[]).chain(state.large.blobs.files.iter().keys()) {
+        let db_key = item?;
+        if !has_large_record(state, &db_key)? {
+            large_values.note(|d| &mut d.orphaned, &db_key);
+        }
+    }
+
+    let mut blob_refs = Discrepancies::default();
+    let refs = dedup::references(&state.large.blobs)?;
+    for item in state.large.blobs.chunks.iter() {
+        let (hash, entry) = item?;
+        match refs.get(&hash) {
+            None => blob_refs.note(|d| &mut d.orphaned, hex::encode(&hash).as_bytes()),
+            Some(&count) if count != dedup::count_of(&entry) => blob_refs.note(|d| &mut d.wrong, hex::encode(&hash).as_bytes()),
+            Some(_) => {}
+        }
+    }
+    for hash in refs.keys() {
+        if !state.large.blobs.chunks.contains_key(hash)? {
+            blob_refs.note(|d| &mut d.missing, hex::encode(hash).as_bytes());
+        }
+    }
+
+    let mut dangling_aliases = Discrepancies::default();
+    for item in state.aliases.iter() {
+        let (alias, file_key) = item?;
+        if !counts.contains_key(file_key.as_ref()) {
+            dangling_aliases.note(|d| &mut d.orphaned, &alias);
+        }
+    }
+
+    let consistent = file_counts.is_empty()
+        && quota_usage.is_empty()
+        && order_index.as_ref().is_none_or(Discrepancies::is_empty)
+        && large_values.is_empty()
+        && blob_refs.is_empty();
+    Ok(Report {
+        consistent,
+        chunks,
+        file_counts,
+        quota_usage,
+        order_index,
+        large_values,
+        blob_refs,
+        dangling_aliases,
+    })
+}
+
+// Entry của index thứ tự có trỏ tới chunk đang tồn tại với đúng index đó không
+fn is_current_entry(state: &AppState, entry: &[u8]) -> sled::Result<bool> {
+    let Some((file_key, index, chunk_hash)) = order::decode_entry(entry) else {
+        return Ok(false);
+    };
+    let db_key = key::encode(file_key, chunk_hash);
+    let record = state.tree_for(file_key).get(&db_key)?;
+    Ok(record.and_then(|bytes| record::decode(&bytes)).is_some_and(|record| record.index == index))
+}
+
+// Value trong `chunks_large` (hoặc ánh xạ tới blob) có record tương ứng trong shard đánh dấu
+// `large` không
+fn has_large_record(state: &AppState, db_key: &[u8]) -> sled::Result<bool> {
+    let Some((file_key, _)) = key::decode_raw(db_key) else {
+        return Ok(false);
+    };
+    let record = state.tree_for_raw(file_key).get(db_key)?;
+    Ok(record.and_then(|bytes| record::decode(&bytes)).is_some_and(|record| record.large))
+}
+
+/// Bắt đầu dựng lại các index phụ nếu chưa có lần nào đang chạy; trả về tiến độ hiện tại.
+/// Với `restart`, bỏ con trỏ đã lưu và quét lại từ đầu.
+pub fn start(state: &Arc<AppState>, restart: bool) -> Progress {
+    {
+        let mut progress = state.reindex.progress.lock().unwrap();
+        if progress.running {
+            return progress.clone();
+        }
+        *progress = Progress {
+            running: true,
+            phase: "scanning",
+            started_at: Some(crate::unix_now_ms()),
+            ..Progress::default()
+        };
+    }
+
+    let job = state.clone();
+    tokio::spawn(async move {
+        let state = job;
+        let was_read_only = state.read_only.swap(true, Ordering::Relaxed);
+        // Bộ đệm ghi phải nằm trong shard trước khi quét
+        let result = match write_buffer::drain(&state).await {
+            Ok(_) => {
+                let run_state = state.clone();
+                tokio::task::spawn_blocking(move || run(&run_state, restart))
+                    .await
+                    .unwrap_or_else(|e| Err(e.to_string()))
+            }
+            Err(status) => Err(format!("không ghi được bộ đệm ghi: {}", status)),
+        };
+        state.read_only.store(was_read_only, Ordering::Relaxed);
+        match &result {
+            Ok(()) => tracing::info!("✅ Đã dựng lại các index phụ"),
+            Err(e) => tracing::error!("❌ Dựng lại các index phụ thất bại: {}", e),
+        }
+        state.reindex.update(|progress| {
+            progress.running = false;
+            progress.finished_at = Some(crate::unix_now_ms());
+            match result {
+                Ok(()) => progress.phase = "done",
+                Err(e) => {
+                    progress.phase = "failed";
+                    progress.error = Some(e);
+                }
+            }
+        });
+    });
+    state.reindex.progress()
+}
+
+// Quét shard từ con trỏ đã lưu vào các tree tạm rồi thay các tree thật
+fn run(state: &AppState, restart: bool) -> Result<(), String> {
+    let db_error = |e: sled::Error| e.to_string();
+    let progress = state.db.open_tree(STATE_TREE).map_err(db_error)?;
+    if restart {
+        progress.clear().map_err(db_error)?;
+    }
+    let trees = [FILES_TREE, USAGE_TREE, ORDER_TREE].map(|name| state.db.open_tree(name));
+    let [files, usage, order] = trees;
+    let (files, usage, order) = (files.map_err(db_error)?, usage.map_err(db_error)?, order.map_err(db_error)?);
+    let resumed = !progress.is_empty();
+    if !resumed {
+        for tree in [&files, &usage, &order] {
+            tree.clear().map_err(db_error)?;
+        }
+    }
+    let mut scanned = progress.get("scanned").map_err(db_error)?.map_or(0, |v| cardinality::decode(&v));
+    state.reindex.update(|p| {
+        p.resumed = resumed;
+        p.scanned = scanned;
+    });
+    if resumed {
+        tracing::info!("-> Tiếp tục dựng lại các index phụ sau {} chunk", scanned);
+    } else {
+        tracing::info!("-> Đang dựng lại các index phụ từ {} shard", state.shards.len());
+    }
+
+    if progress.get("phase").map_err(db_error)?.as_deref() != Some(b"swap".as_slice()) {
+        let mut shard = progress.get("shard").map_err(db_error)?.map_or(0, |v| cardinality::decode(&v) as usize);
+        let mut after = progress.get("after").map_err(db_error)?.map(|v| v.to_vec());
+        while shard < state.shards.len() {
+            let start = after.take().map_or(Bound::Unbounded, Bound::Excluded);
+            let items: Vec<(sled::IVec, sled::IVec)> = state.shards[shard]
+                .range::<Vec<u8>, _>((start, Bound::Unbounded))
+                .take(BATCH)
+                .collect::<sled::Result<_>>()
+                .map_err(db_error)?;
+            let Some(last) = items.last().map(|(key, _)| key.to_vec()) else {
+                shard += 1;
+                continue;
+            };
+            let batch = scan_batch(state, &items).map_err(db_error)?;
+            scanned += items.len() as u64;
+            let (next_shard, next_after) = if items.len() < BATCH { (shard + 1, None) } else { (shard, Some(last)) };
+            save_batch(&[&files, &usage, &order, &progress], &batch, next_shard, next_after.as_deref(), scanned)
+                .map_err(|e| e.to_string())?;
+            (shard, after) = (next_shard, next_after);
+            state.reindex.update(|p| p.scanned = scanned);
+        }
+        progress.insert("phase", "swap").map_err(db_error)?;
+    }
+
+    state.reindex.update(|p| p.phase = "swapping");
+    replace(&state.files.tree, &files).map_err(db_error)?;
+    replace(&state.quotas.usage, &usage).map_err(db_error)?;
+    if let Some(tree) = &state.order {
+        replace(tree, &order).map_err(db_error)?;
+    }
+    let mut removed = 0;
+    for item in state.large.store.scan_keys(&[]) {
+        let db_key = item.map_err(db_error)?;
+        if has_large_record(state, &db_key).map_err(db_error)? {
+            continue;
+        }
+        if let Some(value) = state.large.store.get(&db_key).map_err(db_error)?
+            && state.large.remove_stored_if(&db_key, &value).map_err(db_error)?
+        {
+            removed += 1;
+        }
+    }
+    for item in state.large.blobs.files.iter() {
+        let (db_key, _) = item.map_err(db_error)?;
+        if !has_large_record(state, &db_key).map_err(db_error)? {
+            state.large.blobs.files.remove(&db_key).map_err(db_error)?;
+            removed += 1;
+        }
+    }
+    if removed > 0 {
+        tracing::info!("   -> Đã xóa {} value chunk lớn không còn record", removed);
+    }
+    // Ánh xạ thừa vừa bị xóa mà không giảm số tham chiếu: đếm lại từ tree `files`
+    let fixed = dedup::recount(&state.large.blobs).map_err(db_error)?;
+    if fixed > 0 {
+        tracing::info!("   -> Đã sửa số tham chiếu của {} blob", fixed);
+    }
+    state.files.recount();
+
+    for name in [FILES_TREE, USAGE_TREE, ORDER_TREE, STATE_TREE] {
+        state.db.drop_tree(name).map_err(db_error)?;
+    }
+    state.flush().map_err(db_error)?;
+    Ok(())
+}
+
+// Kết quả quét một lô chunk
+#[derive(Default)]
+struct Batch {
+    counts: HashMap<Vec<u8>, FileStats>,
+    usage: HashMap<String, u64>,
+    order: Vec<Vec<u8>>,
+}
+
+fn scan_batch(state: &AppState, items: &[(sled::IVec, sled::IVec)]) -> sled::Result<Batch> {
+    let mut batch = Batch::default();
+    for (db_key, value) in items {
+        let Some((file_key, _)) = key::decode_raw(db_key) else {
+            continue;
+        };
+        let record = record::decode(value);
+        let bytes = chunk_bytes(state, db_key, value, record.as_ref())?;
+        let stats = batch.counts.entry(file_key.to_vec()).or_default();
+        stats.chunks += 1;
+        stats.bytes += bytes;
+        *batch.usage.entry(owner_of(state, file_key)).or_default() += bytes;
+        if let (Some(record), Some(parsed), Some(_)) = (record, key::decode(db_key), &state.order) {
+            batch.order.push(order::entry_key(parsed.file_key, record.index, parsed.chunk_hash));
+        }
+    }
+    Ok(batch)
+}
+
+// Cộng kết quả của một lô vào các tree tạm và dời con trỏ trong cùng một transaction, để
+// lần tiếp tục không cộng một lô hai lần
+fn save_batch(
+    trees: &[&sled::Tree; 4],
+    batch: &Batch,
+    shard: usize,
+    after: Option<&[u8]>,
+    scanned: u64,
+) -> sled::transaction::TransactionResult<(), sled::Error> {
+    trees.as_slice().transaction(|txs| {
+        let [files, usage, order, progress] = &txs[..] else {
+            unreachable!("luôn có bốn tree");
+        };
+        for (file_key, added) in &batch.counts {
+            let stats = files.get(file_key)?.map(|v| FileStats::decode(&v)).unwrap_or_default();
+            let stats = FileStats {
+                chunks: stats.chunks + added.chunks,
+                bytes: stats.bytes + added.bytes,
+            };
+            files.insert(file_key.as_slice(), &stats.encode())?;
+        }
+        for (owner, added) in &batch.usage {
+            let used = usage.get(owner)?.map_or(0, |v| cardinality::decode(&v));
+            usage.insert(owner.as_bytes(), &(used + added).to_be_bytes())?;
+        }
+        for entry in &batch.order {
+            order.insert(entry.as_slice(), &[])?;
+        }
+        progress.insert("shard", &(shard as u64).to_be_bytes())?;
+        match after {
+            Some(after) => progress.insert("after", after)?,
+            None => progress.remove("after")?,
+        };
+        progress.insert("scanned", &scanned.to_be_bytes())?;
+        Ok(())
+    })
+}
+
+// Thay toàn bộ nội dung của `live` bằng nội dung của `rebuilt`
+fn replace(live: &sled::Tree, rebuilt: &sled::Tree) -> sled::Result<()> {
+    live.clear()?;
+    for item in rebuilt.iter() {
+        let (key, value) = item?;
+        live.insert(key, value)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,379 @@
+// ## HÀNG ĐỢI REPLICATION BỀN VỮNG ##
+//
+// Khi bật STORAGE_REPLICATION, mỗi lần ghi chunk qua /store, /store/batch, /store/raw
+// (và các route `/b64/...`) thêm vào tree `replication_queue` một entry cho từng peer
+// (seed trong STORAGE_PEERS và peer biết qua gossip). Entry được ghi trước lần flush của
+// chính lần ghi đó nên còn nguyên qua restart cùng với chunk; task nền tiếp tục gửi các
+// entry còn lại ngay khi khởi động.
+//
+// Định kỳ (STORAGE_REPLICATION_INTERVAL_MS) task nền lấy tối đa STORAGE_REPLICATION_BATCH
+// entry đến hạn, đọc dữ liệu hiện tại của chunk rồi gửi tới `POST /replicate` của peer.
+// Peer trả về 2xx thì entry bị xóa; lỗi thì thử lại sau khoảng cách nhân đôi sau mỗi lần
+// (tối đa chu kỳ nhân 2^`MAX_BACKOFF_SHIFT`). Entry bị bỏ khi chunk đã bị xóa ở node này
+// hoặc peer không còn trong danh sách (đã bị gossip quên), hoặc khi peer trả về `409`
+// (peer chạy STORAGE_OVERWRITE_MODE=reject và giữ chunk của nó). Chunk nhận qua
+// /replicate không được đưa vào hàng đợi nữa để hai node không gửi qua lại mãi.
+//
+// Peer lỗi liên tiếp thì circuit breaker của peer mở (xem module breaker): entry của peer
+// được hẹn lại tới lần gọi thử mà không tăng số lần thử, và không ghi log cho từng entry.
+//
+// STORAGE_REPLICATION_FACTOR giới hạn số peer nhận mỗi chunk (0, mặc định, là mọi peer).
+// Các peer được xếp theo rendezvous hashing (SHA-256 của URL peer và key chunk) nên cùng
+// một chunk luôn chọn cùng các peer khi danh sách peer không đổi, và chunk được chia đều
+// giữa các peer.
+//
+// Tắt STORAGE_REPLICATION chỉ ngừng thêm entry mới; các entry đã có vẫn được gửi tiếp.
+// Gauge `storage_replication_queue_depth` cho biết số entry đang chờ.
+//
+// Khi bật STORAGE_READ_FALLTHROUGH, `GET /file/:fileKey/chunk/:chunkHash` (và
+// `GET /chunk/:fileKey/:chunkHash`) không có chunk ở node này thì hỏi lần lượt các peer
+// theo cùng thứ tự rendezvous và trả lại response của peer đầu tiên có chunk, chỉ trả về
+// `404` khi không peer nào có. Request gửi tới peer kèm header `X-Storage-Forwarded` để
+// peer không hỏi tiếp các peer của nó.
+
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine as _,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::record::StoreMode;
+use crate::AppState;
+
+// Khoảng thử lại tối đa là chu kỳ replication nhân 2^MAX_BACKOFF_SHIFT
+const MAX_BACKOFF_SHIFT: u32 = 6;
+// Thời gian chờ tối đa cho một lần gửi tới peer
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+// Thời gian chờ tối đa khi hỏi một peer lúc đọc chunk không có ở node này
+const FALLTHROUGH_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Header đánh dấu request đọc được node khác chuyển tới, để peer không chuyển tiếp nữa
+pub const FORWARDED_HEADER: &str = "x-storage-forwarded";
+
+// Value của một entry trong tree `replication_queue`; key là số thứ tự tăng dần (u64
+// big-endian) nên entry được gửi theo thứ tự ghi
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    peer: String,
+    // Key của chunk trong database, mã hóa base64url (fileKey có thể là nhị phân)
+    key: String,
+    #[serde(default)]
+    attempts: u32,
+    // Thời điểm được gửi lại (unix mili giây)
+    #[serde(rename = "nextAttempt", default)]
+    next_attempt: u64,
+}
+
+/// Body của `POST /replicate`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReplicatePayload {
+    // Key của chunk trong database, mã hóa base64url
+    pub key: String,
+    #[serde(rename = "chunkData")]
+    pub chunk_data: String, // Dữ liệu gốc của chunk ở dạng Base64
+    #[serde(rename = "chunkIndex", default)]
+    pub chunk_index: Option<u64>,
+    #[serde(rename = "contentType", default)]
+    pub content_type: Option<String>,
+    #[serde(default)]
+    pub mode: Option<StoreMode>,
+    #[serde(default, skip_serializing_if = "std::collections::HashMap::is_empty")]
+    pub metadata: std::collections::HashMap<String, String>,
+    // Thời điểm hết hạn (unix giây) nếu chunk được lưu kèm `ttlSeconds`
+    #[serde(rename = "expiresAt", default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+}
+
+pub struct Queue {
+    tree: sled::Tree,
+    // Số entry đang chờ, giữ trong bộ nhớ vì `len()` của sled phải quét cả tree
+    depth: AtomicU64,
+}
+
+impl Queue {
+    pub fn open(tree: sled::Tree) -> Self {
+        let depth = tree.len() as u64;
+        if depth > 0 {
+            tracing::info!("-> Tiếp tục replication {} chunk còn trong hàng đợi", depth);
+        }
+        Queue {
+            tree,
+            depth: AtomicU64::new(depth),
+        }
+    }
+
+    pub fn depth(&self) -> u64 {
+        self.depth.load(Ordering::Relaxed)
+    }
+
+    /// Thêm một entry cho mỗi key chunk và mỗi peer được chọn cho key đó (`factor` peer đầu
+    /// theo rendezvous hashing, 0 là mọi peer). Không flush: bên gọi flush cùng với các chunk
+    /// vừa ghi.
+    pub fn enqueue<'a>(
+        &self,
+        db: &sled::Db,
+        peers: &[String],
+        factor: usize,
+        keys: impl Iterator<Item = &'a [u8]>,
+    ) -> sled::Result<()> {
+        let mut batch = sled::Batch::default();
+        let mut added = 0;
+        for db_key in keys {
+            let key = URL_SAFE_NO_PAD.encode(db_key);
+            let ranked = rank(peers, db_key);
+            let chosen = if factor == 0 { &ranked[..] } else { &ranked[..factor.min(ranked.len())] };
+            for peer in chosen {
+                let entry = Entry {
+                    peer: peer.to_string(),
+                    key: key.clone(),
+                    attempts: 0,
+                    next_attempt: 0,
+                };
+                let value = serde_json::to_vec(&entry).expect("entry luôn serialize được");
+                batch.insert(&db.generate_id()?.to_be_bytes(), value);
+                added += 1;
+            }
+        }
+        self.tree.apply_batch(batch)?;
+        self.depth.fetch_add(added, Ordering::Relaxed);
+        Ok(())
+    }
+
+    // Tối đa `limit` entry đã đến hạn, theo thứ tự thêm vào
+    fn due(&self, now_ms: u64, limit: usize) -> Vec<(sled::IVec, Entry)> {
+        self.tree
+            .iter()
+            .flatten()
+            .filter_map(|(id, value)| match serde_json::from_slice::<Entry>(&value) {
+                Ok(entry) => Some((id, entry)),
+                Err(_) => {
+                    tracing::error!("Bỏ entry replication bị hỏng");
+                    self.remove(&id);
+                    None
+                }
+            })
+            .filter(|(_, entry)| entry.next_attempt <= now_ms)
+            .take(limit)
+            .collect()
+    }
+
+    fn remove(&self, id: &[u8]) {
+        match self.tree.remove(id) {
+            Ok(Some(_)) => {
+                self.depth.fetch_sub(1, Ordering::Relaxed);
+            }
+            Ok(None) => {}
+            Err(e) => tracing::error!("Lỗi khi xóa entry replication: {}", e),
+        }
+    }
+
+    // Hẹn gửi lại entry sau một lần lỗi
+    fn retry(&self, id: &[u8], mut entry: Entry, now_ms: u64, interval: Duration) {
+        entry.attempts += 1;
+        let shift = entry.attempts.min(MAX_BACKOFF_SHIFT);
+        entry.next_attempt = now_ms + (interval * (1 << shift)).as_millis() as u64;
+        let value = serde_json::to_vec(&entry).expect("entry luôn serialize được");
+        if let Err(e) = self.tree.insert(id, value) {
+            tracing::error!("Lỗi khi cập nhật entry replication: {}", e);
+        }
+    }
+
+    // Hẹn entry tới `next_ms` mà không tính là một lần thử (circuit breaker của peer đang mở)
+    fn defer(&self, id: &[u8], mut entry: Entry, next_ms: u64) {
+        entry.next_attempt = next_ms;
+        let value = serde_json::to_vec(&entry).expect("entry luôn serialize được");
+        if let Err(e) = self.tree.insert(id, value) {
+            tracing::error!("Lỗi khi cập nhật entry replication: {}", e);
+        }
+    }
+}
+
+/// Các peer xếp theo rendezvous hashing cho key chunk `db_key`: peer có SHA-256 của
+/// (URL peer, key) lớn hơn đứng trước
+pub fn rank<'a>(peers: &'a [String], db_key: &[u8]) -> Vec<&'a str> {
+    let mut scored: Vec<([u8; 32], &str)> = peers
+        .iter()
+        .map(|peer| {
+            let mut hasher = Sha256::new();
+            hasher.update(peer.as_bytes());
+            hasher.update([0]);
+            hasher.update(db_key);
+            (hasher.finalize().into(), peer.as_str())
+        })
+        .collect();
+    scored.sort_unstable_by(|a, b| b.cmp(a));
+    scored.into_iter().map(|(_, peer)| peer).collect()
+}
+
+/// Hỏi lần lượt các peer (theo thứ tự rendezvous của `db_key`) chunk không có ở node này;
+/// trả về body JSON từ peer đầu tiên có chunk cùng URL của peer đó. `query` là query string
+/// của request gốc, được chuyển nguyên cho peer.
+pub async fn fetch_from_peers(
+    state: &AppState,
+    db_key: &str,
+    path: &str,
+    query: Option<&str>,
+) -> Option<(serde_json::Value, String)> {
+    let peers = state.replication_peers();
+    let config = state.config.load();
+    let (threshold, cooldown) = (config.breaker_failures, Duration::from_millis(config.breaker_cooldown_ms));
+    let token = config.peer_token.clone();
+    drop(config);
+
+    let client = fallthrough_client();
+    for peer in rank(&peers, db_key.as_bytes()) {
+        if state.breakers.allow(peer, threshold, cooldown).is_err() {
+            continue;
+        }
+        let url = match query {
+            Some(query) => format!("{}{}?{}", peer, path, query),
+            None => format!("{}{}", peer, path),
+        };
+        let mut request = client.get(url).header(FORWARDED_HEADER, "1").timeout(FALLTHROUGH_TIMEOUT);
+        if let Some(token) = token.as_deref() {
+            request = request.bearer_auth(token);
+        }
+        match request.send().await {
+            Ok(response) if response.status().is_success() => {
+                state.breakers.record(peer, true, threshold);
+                match response.json().await {
+                    Ok(body) => return Some((body, peer.to_string())),
+                    Err(e) => tracing::warn!("Response không hợp lệ từ peer {} cho chunk {}: {}", peer, db_key, e),
+                }
+            }
+            // Peer trả lời được nhưng không có chunk
+            Ok(response) if response.status().is_client_error() => state.breakers.record(peer, true, threshold),
+            result => {
+                let reason = match result {
+                    Ok(response) => response.status().to_string(),
+                    Err(e) => e.to_string(),
+                };
+                tracing::warn!("Không hỏi được peer {} cho chunk {}: {}", peer, db_key, reason);
+                state.breakers.record(peer, false, threshold);
+            }
+        }
+    }
+    None
+}
+
+// Client dùng chung cho các lần hỏi peer, giữ kết nối giữa các request
+fn fallthrough_client() -> &'static reqwest::Client {
+    static CLIENT: std::sync::OnceLock<reqwest::Client> = std::sync::OnceLock::new();
+    CLIENT.get_or_init(reqwest::Client::new)
+}
+
+/// Body gửi cho peer từ dữ liệu hiện tại của chunk `key`; `None` nếu chunk đã bị xóa
+pub fn payload_for(state: &AppState, key: &str) -> std::io::Result<Option<ReplicatePayload>> {
+    let db_key = URL_SAFE_NO_PAD
+        .decode(key)
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidData, "key replication không hợp lệ"))?;
+    let Some((bytes, record)) = state.read_chunk(&db_key)? else {
+        return Ok(None);
+    };
+    Ok(Some(ReplicatePayload {
+        key: key.to_string(),
+        chunk_data: STANDARD.encode(bytes),
+        chunk_index: record.index,
+        content_type: record.content_type,
+        mode: record.mode,
+        metadata: record.metadata,
+        expires_at: record.expires_at,
+    }))
+}
+
+/// Task nền gửi các entry trong hàng đợi replication tới peer
+pub async fn run(state: Arc<AppState>) {
+    let client = reqwest::Client::new();
+    loop {
+        let config = state.config.load();
+        let interval = Duration::from_millis(config.replication_interval_ms);
+        let limit = config.replication_batch;
+        let (threshold, cooldown) = (config.breaker_failures, Duration::from_millis(config.breaker_cooldown_ms));
+        drop(config);
+        tokio::time::sleep(interval).await;
+        if state.replication.depth() == 0 {
+            continue;
+        }
+
+        let now_ms = crate::unix_now_ms();
+        let peers = state.replication_peers();
+        let due = {
+            let state = state.clone();
+            match tokio::task::spawn_blocking(move || state.replication.due(now_ms, limit)).await {
+                Ok(due) => due,
+                Err(_) => continue,
+            }
+        };
+
+        let mut sends = tokio::task::JoinSet::new();
+        for (id, entry) in due {
+            if !peers.contains(&entry.peer) {
+                tracing::info!("-> Bỏ replication tới peer {} không còn trong danh sách", entry.peer);
+                state.replication.remove(&id);
+                continue;
+            }
+            if let Err(wait) = state.breakers.allow(&entry.peer, threshold, cooldown) {
+                let next_ms = now_ms + wait.max(interval).as_millis() as u64;
+                state.replication.defer(&id, entry, next_ms);
+                continue;
+            }
+            let (state, client) = (state.clone(), client.clone());
+            sends.spawn(async move {
+                let read_state = state.clone();
+                let key = entry.key.clone();
+                let payload = match tokio::task::spawn_blocking(move || payload_for(&read_state, &key)).await {
+                    Ok(Ok(Some(payload))) => payload,
+                    // Chunk đã bị xóa: không còn gì để gửi
+                    Ok(Ok(None)) => {
+                        state.replication.remove(&id);
+                        return;
+                    }
+                    Ok(Err(e)) => {
+                        tracing::error!("Lỗi khi đọc chunk để replication: {}", e);
+                        state.replication.remove(&id);
+                        return;
+                    }
+                    Err(_) => return,
+                };
+                let mut request = client.post(format!("{}/replicate", entry.peer)).timeout(SEND_TIMEOUT);
+                if let Some(token) = state.config.load().peer_token.as_deref() {
+                    request = request.bearer_auth(token);
+                }
+                let sent = request.json(&payload).send().await;
+                match sent {
+                    Ok(response) if response.status().is_success() => {
+                        state.breakers.record(&entry.peer, true, threshold);
+                        state.metrics.replications.fetch_add(1, Ordering::Relaxed);
+                        state.replication.remove(&id);
+                    }
+                    // Peer từ chối ghi đè (STORAGE_OVERWRITE_MODE=reject) và giữ chunk của nó
+                    Ok(response) if response.status() == reqwest::StatusCode::CONFLICT => {
+                        state.breakers.record(&entry.peer, true, threshold);
+                        tracing::warn!("Peer {} đã có chunk khác ở key {}, bỏ replication", entry.peer, entry.key);
+                        state.replication.remove(&id);
+                    }
+                    result => {
+                        let reason = match result {
+                            Ok(response) => response.status().to_string(),
+                            Err(e) => e.to_string(),
+                        };
+                        tracing::error!(
+                            "Replication tới {} thất bại (lần {}): {}",
+                            entry.peer,
+                            entry.attempts + 1,
+                            reason
+                        );
+                        state.breakers.record(&entry.peer, false, threshold);
+                        state.metrics.replication_failures.fetch_add(1, Ordering::Relaxed);
+                        state.replication.retry(&id, entry, now_ms, interval);
+                    }
+                }
+            });
+        }
+        while sends.join_next().await.is_some() {}
+    }
+}
@@ -0,0 +1,459 @@
+// ## KIỂM TRA DỮ LIỆU TRONG NỀN (SCRUB) ##
+//
+// `GET /verify/...` chỉ phát hiện dữ liệu hỏng trên đĩa khi có người hỏi tới đúng chunk đó.
+// Khi bật STORAGE_SCRUB, một task nền đi lần lượt qua mọi shard theo lô STORAGE_SCRUB_BATCH
+// chunk, nghỉ STORAGE_SCRUB_PAUSE_MS mili giây giữa hai lô (và bỏ qua lô khi mọi suất đọc
+// đang bận, để không tranh với client), đọc lại dữ liệu gốc của từng chunk rồi so hash với
+// chunkHash trong key. Xong một lượt thì nghỉ STORAGE_SCRUB_INTERVAL_SECS giây (mặc định một
+// ngày) trước khi quét lại từ đầu; con trỏ chỉ nằm trong bộ nhớ nên restart sẽ quét lại.
+//
+// Thuật toán là STORAGE_VERIFY_HASH, mặc định `sha256`. Chỉ chunk có chunkHash dạng digest
+// 32 byte (64 ký tự hex, có hoặc không có `0x`) được kiểm tra, các chunk khác được đếm là
+// bỏ qua; chỉ nên bật khi client dùng hash của dữ liệu làm chunkHash, nếu không mọi chunk sẽ
+// bị coi là hỏng.
+//
+// Chunk hỏng (`hash_mismatch`: hash không khớp, `unreadable`: record hoặc value không đọc
+// được) được ghi vào tree `quarantine` theo key của chunk, kèm thời điểm phát hiện và số lần
+// sửa đã thử. Chunk không bị xóa: client vẫn đọc được và tự quyết định. Khi có peer
+// replication (xem module replication), scrubber hỏi peer bản của chunk (như
+// STORAGE_READ_FALLTHROUGH), kiểm tra hash của bản đó rồi ghi đè chunk hỏng như khi nhận
+// replication; chunk được sửa, được client ghi lại hay bị xóa đều ra khỏi `quarantine` ở lần
+// quét sau. Node đang chỉ đọc thì không sửa.
+//
+// `GET /admin/scrub/status` (cần STORAGE_ADMIN_API_KEY) trả về các bộ đếm từ lúc khởi
+// động, vị trí đang quét và các chunk đang bị cách ly.
+
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::Json;
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ops::Bound;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::error::ApiError;
+use crate::integrity::{self, HashAlgorithm};
+use crate::{blocking, key, quota, replication, require_admin, AppState};
+
+// Số chunk bị cách ly tối đa được liệt kê trong `/admin/scrub/status`
+const MAX_LISTED: usize = 100;
+
+/// Một chunk bị cách ly, lưu trong tree `quarantine` dưới dạng JSON
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    #[serde(rename = "fileKey")]
+    pub file_key: String,
+    #[serde(rename = "chunkHash")]
+    pub chunk_hash: String,
+    // "hash_mismatch" hoặc "unreadable"
+    pub reason: String,
+    // Hash tính được từ dữ liệu đang lưu, chỉ có khi đọc được dữ liệu
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub computed: Option<String>,
+    #[serde(rename = "detectedAt")]
+    pub detected_at: u64,
+    #[serde(rename = "repairAttempts")]
+    pub repair_attempts: u64,
+}
+
+/// Các bộ đếm của scrubber từ lúc khởi động
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Counters {
+    // Số lượt quét hết mọi shard
+    pub passes: u64,
+    // Số chunk đã kiểm tra hash, `skipped` là số chunk không kiểm tra được (chunkHash không
+    // phải digest, chunk vừa bị xóa)
+    pub scanned: u64,
+    pub skipped: u64,
+    // Số lần phát hiện một chunk hỏng chưa bị cách ly
+    #[serde(rename = "corruptFound")]
+    pub corrupt_found: u64,
+    pub repaired: u64,
+    #[serde(rename = "lastPassAt")]
+    pub last_pass_at: Option<u64>,
+}
+
+/// Response của `GET /admin/scrub/status`
+#[derive(Serialize)]
+pub(crate) struct Status {
+    enabled: bool,
+    algorithm: &'static str,
+    #[serde(flatten)]
+    counters: Counters,
+    // Vị trí đang quét: shard và key cuối cùng đã kiểm tra trong shard đó
+    shard: usize,
+    position: Option<String>,
+    quarantined: usize,
+    entries: Vec<Entry>,
+}
+
+// Vị trí của scrubber trong keyspace
+#[derive(Default)]
+struct Cursor {
+    shard: usize,
+    after: Option<Vec<u8>>,
+}
+
+pub struct Scrubber {
+    quarantine: sled::Tree,
+    cursor: Mutex<Cursor>,
+    counters: Mutex<Counters>,
+}
+
+// Kết quả kiểm tra một chunk
+enum Verdict {
+    Intact,
+    Skipped,
+    Corrupt { reason: &'static str, computed: Option<String> },
+}
+
+impl Scrubber {
+    pub fn open(quarantine: sled::Tree) -> Self {
+        Scrubber {
+            quarantine,
+            cursor: Mutex::default(),
+            counters: Mutex::default(),
+        }
+    }
+
+    fn update(&self, f: impl FnOnce(&mut Counters)) {
+        f(&mut self.counters.lock().unwrap());
+    }
+
+    // Ghi chunk vào `quarantine` nếu chưa có; trả về true khi chunk mới bị cách ly
+    fn isolate(&self, db_key: &[u8], reason: &'static str, computed: Option<String>) -> sled::Result<bool> {
+        if self.quarantine.contains_key(db_key)? {
+            return Ok(false);
+        }
+        let (file_key, chunk_hash) = key::decode_raw(db_key).unwrap_or((db_key, ""));
+        let entry = Entry {
+            file_key: String::from_utf8_lossy(file_key).into_owned(),
+            chunk_hash: chunk_hash.to_string(),
+            reason: reason.to_string(),
+            computed,
+            detected_at: crate::unix_now(),
+            repair_attempts: 0,
+        };
+        let value = serde_json::to_vec(&entry).expect("entry luôn serialize được");
+        Ok(self.quarantine.compare_and_swap(db_key, None as Option<&[u8]>, Some(value))?.is_ok())
+    }
+
+    fn release(&self, db_key: &[u8]) -> sled::Result<()> {
+        self.quarantine.remove(db_key).map(|_| ())
+    }
+
+    fn note_repair_attempt(&self, db_key: &[u8]) -> sled::Result<()> {
+        self.quarantine
+            .fetch_and_update(db_key, |old| {
+                let mut entry: Entry = serde_json::from_slice(old?).ok()?;
+                entry.repair_attempts += 1;
+                serde_json::to_vec(&entry).ok()
+            })
+            .map(|_| ())
+    }
+
+    fn entries(&self, limit: usize) -> sled::Result<Vec<Entry>> {
+        let mut entries = Vec::new();
+        for item in self.quarantine.iter().values().take(limit) {
+            if let Ok(entry) = serde_json::from_slice(&item?) {
+                entries.push(entry);
+            }
+        }
+        Ok(entries)
+    }
+}
+
+// chunkHash có dạng digest 32 byte mà scrubber kiểm tra được không
+fn is_digest(chunk_hash: &str) -> bool {
+    let hex = chunk_hash.strip_prefix("0x").or_else(|| chunk_hash.strip_prefix("0X")).unwrap_or(chunk_hash);
+    hex.len() == 64 && hex.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+fn algorithm(state: &AppState) -> HashAlgorithm {
+    state.config.load().verify_hash.unwrap_or_default()
+}
+
+// Đọc lại chunk `db_key` và so hash với chunkHash. Chạy đồng bộ.
+fn check(state: &AppState, algorithm: HashAlgorithm, db_key: &[u8]) -> Verdict {
+    let Some((_, chunk_hash)) = key::decode_raw(db_key) else {
+        return Verdict::Skipped;
+    };
+    if !is_digest(chunk_hash) {
+        return Verdict::Skipped;
+    }
+    match state.read_chunk(db_key) {
+        Ok(Some((bytes, _))) => {
+            let computed = algorithm.digest(&bytes);
+            if integrity::matches(chunk_hash, &computed) {
+                Verdict::Intact
+            } else {
+                Verdict::Corrupt { reason: "hash_mismatch", computed: Some(computed) }
+            }
+        }
+        Ok(None) => Verdict::Skipped,
+        Err(e) if e.kind() == std::io::ErrorKind::InvalidData => Verdict::Corrupt { reason: "unreadable", computed: None },
+        Err(e) => {
+            tracing::error!("Scrub: lỗi khi đọc chunk {}: {}", String::from_utf8_lossy(db_key), e);
+            Verdict::Skipped
+        }
+    }
+}
+
+// Kiểm tra tối đa `batch` chunk tiếp theo từ con trỏ. Trả về key của các chunk hỏng và
+// true nếu lô này đi hết shard cuối cùng (con trỏ quay về đầu). Chạy đồng bộ.
+fn scan_batch(state: &AppState, batch: usize) -> sled::Result<(Vec<Vec<u8>>, bool)> {
+    let scrubber = &state.scrubber;
+    let algorithm = algorithm(state);
+    // Không giữ khóa trong lúc đọc để `/admin/scrub/status` không phải chờ cả lô
+    let mut cursor = std::mem::take(&mut *scrubber.cursor.lock().unwrap());
+    let mut corrupt = Vec::new();
+    let mut remaining = batch;
+
+    while remaining > 0 {
+        let Some(tree) = state.shards.get(cursor.shard) else {
+            *scrubber.cursor.lock().unwrap() = Cursor::default();
+            return Ok((corrupt, true));
+        };
+        let lower = match cursor.after.take() {
+            Some(after) => Bound::Excluded(after),
+            None => Bound::Unbounded,
+        };
+        let keys: Vec<sled::IVec> = tree.range::<Vec<u8>, _>((lower, Bound::Unbounded)).keys().take(remaining).collect::<sled::Result<_>>()?;
+        if keys.len() < remaining {
+            cursor.shard += 1;
+        } else {
+            cursor.after = keys.last().map(|k| k.to_vec());
+        }
+        remaining -= keys.len();
+        *scrubber.cursor.lock().unwrap() = Cursor {
+            shard: cursor.shard,
+            after: cursor.after.clone(),
+        };
+
+        for db_key in keys {
+            match check(state, algorithm, &db_key) {
+                Verdict::Skipped => scrubber.update(|c| c.skipped += 1),
+                Verdict::Intact => {
+                    scrubber.update(|c| c.scanned += 1);
+                    scrubber.release(&db_key)?;
+                }
+                Verdict::Corrupt { reason, computed } => {
+                    scrubber.update(|c| c.scanned += 1);
+                    if scrubber.isolate(&db_key, reason, computed)? {
+                        tracing::warn!("Scrub: chunk {} bị hỏng ({})", String::from_utf8_lossy(&db_key), reason);
+                        scrubber.update(|c| c.corrupt_found += 1);
+                    }
+                    corrupt.push(db_key.to_vec());
+                }
+            }
+        }
+    }
+    Ok((corrupt, false))
+}
+
+// Xóa khỏi `quarantine` các chunk không còn tồn tại. Chạy đồng bộ.
+fn prune(state: &AppState) -> sled::Result<()> {
+    for db_key in state.scrubber.quarantine.iter().keys() {
+        let db_key = db_key?;
+        let exists = match key::decode_raw(&db_key) {
+            Some((file_key, _)) => state.tree_for_raw(file_key).contains_key(&db_key)?,
+            None => false,
+        };
+        if !exists {
+            state.scrubber.release(&db_key)?;
+        }
+    }
+    Ok(())
+}
+
+// Body chunk mà peer trả về cho `GET /file/:fileKey/chunk/:chunkHash`
+#[derive(Deserialize)]
+struct PeerChunk {
+    value: String,
+    #[serde(default)]
+    index: Option<u64>,
+    #[serde(rename = "contentType", default)]
+    content_type: Option<String>,
+    #[serde(default)]
+    metadata: HashMap<String, String>,
+    #[serde(rename = "expiresAt", default)]
+    expires_at: Option<u64>,
+}
+
+// Lấy bản đúng của chunk hỏng `db_key` từ peer và ghi đè; trả về true khi đã sửa
+async fn repair(state: &Arc<AppState>, algorithm: HashAlgorithm, db_key: &[u8]) -> bool {
+    // Chunk của fileKey nhị phân không có đường dẫn HTTP để hỏi peer
+    let Some((Ok(file_key), chunk_hash)) = key::decode_raw(db_key).map(|(f, c)| (std::str::from_utf8(f), c)) else {
+        return false;
+    };
+    let lookup_key = key::encode(file_key, chunk_hash);
+    let path = format!("/file/{}/chunk/{}", file_key, chunk_hash);
+    let Some((body, peer)) = replication::fetch_from_peers(state, &lookup_key, &path, None).await else {
+        return false;
+    };
+    let Ok(chunk) = serde_json::from_value::<PeerChunk>(body) else {
+        tracing::warn!("Scrub: peer {} trả về chunk {} không hợp lệ", peer, lookup_key);
+        return false;
+    };
+    let intact = STANDARD.decode(&chunk.value).is_ok_and(|bytes| integrity::matches(chunk_hash, &algorithm.digest(&bytes)));
+    if !intact {
+        tracing::warn!("Scrub: bản của chunk {} ở peer {} cũng không khớp hash", lookup_key, peer);
+        return false;
+    }
+
+    let payload = replication::ReplicatePayload {
+        key: URL_SAFE_NO_PAD.encode(db_key),
+        chunk_data: chunk.value,
+        chunk_index: chunk.index,
+        content_type: chunk.content_type,
+        mode: None,
+        metadata: chunk.metadata,
+        expires_at: chunk.expires_at,
+    };
+    let mut write = match state.replica_write(payload) {
+        Ok(write) => write,
+        Err(e) => {
+            tracing::warn!("Scrub: không sửa được chunk {}: {:?}", lookup_key, e);
+            return false;
+        }
+    };
+    // Chunk hỏng luôn bị thay, kể cả khi STORAGE_OVERWRITE_MODE=reject
+    write.overwrite = quota::OverwriteMode::Allow;
+    let write_state = state.clone();
+    let repaired_key = db_key.to_vec();
+    let result = blocking(move || {
+        write_state.commit_writes(std::slice::from_ref(&write))?;
+        write_state.scrubber.release(&repaired_key).map_err(|e| {
+            tracing::error!("Scrub: lỗi khi cập nhật quarantine: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+    })
+    .await;
+    match result {
+        Ok(()) => {
+            tracing::info!("<- Scrub: đã sửa chunk {} bằng bản từ peer {}", lookup_key, peer);
+            true
+        }
+        Err(e) => {
+            tracing::warn!("Scrub: không ghi được bản sửa của chunk {}: {:?}", lookup_key, e);
+            false
+        }
+    }
+}
+
+// Thử sửa các chunk hỏng vừa tìm thấy khi có peer và node đang nhận ghi
+async fn repair_all(state: &Arc<AppState>, corrupt: Vec<Vec<u8>>) {
+    if corrupt.is_empty() || state.replication_peers().is_empty() || state.is_read_only() {
+        return;
+    }
+    let algorithm = algorithm(state);
+    for db_key in corrupt {
+        if repair(state, algorithm, &db_key).await {
+            state.scrubber.update(|c| c.repaired += 1);
+        } else if let Err(e) = state.scrubber.note_repair_attempt(&db_key) {
+            tracing::error!("Scrub: lỗi khi cập nhật quarantine: {}", e);
+        }
+    }
+}
+
+// Một lô của scrubber; trả về true khi lô này hoàn tất một lượt
+async fn step(state: &Arc<AppState>, batch: usize) -> sled::Result<bool> {
+    let scan_state = state.clone();
+    let (corrupt, done) = tokio::task::spawn_blocking(move || scan_batch(&scan_state, batch))
+        .await
+        .map_err(|e| sled::Error::Io(std::io::Error::other(e)))??;
+    repair_all(state, corrupt).await;
+    if done {
+        let prune_state = state.clone();
+        tokio::task::spawn_blocking(move || prune(&prune_state))
+            .await
+            .map_err(|e| sled::Error::Io(std::io::Error::other(e)))??;
+        state.scrubber.update(|c| {
+            c.passes += 1;
+            c.last_pass_at = Some(crate::unix_now());
+        });
+    }
+    Ok(done)
+}
+
+/// Quét hết phần còn lại của lượt hiện tại (tới cuối shard cuối cùng) không nghỉ giữa
+/// các lô, kể cả khi không bật STORAGE_SCRUB
+pub async fn pass(state: &Arc<AppState>) -> sled::Result<()> {
+    let batch = state.config.load().scrub_batch;
+    while !step(state, batch).await? {}
+    Ok(())
+}
+
+/// Task nền kiểm tra dữ liệu khi bật STORAGE_SCRUB
+pub async fn run(state: Arc<AppState>) {
+    loop {
+        let config = state.config.load();
+        let (enabled, batch) = (config.scrub, config.scrub_batch);
+        let (pause, rest) = (Duration::from_millis(config.scrub_pause_ms), Duration::from_secs(config.scrub_interval_secs));
+        drop(config);
+        if !enabled {
+            tokio::time::sleep(rest.min(Duration::from_secs(60))).await;
+            continue;
+        }
+        tokio::time::sleep(pause).await;
+        // Nhường suất đọc cho client khi node đang bận
+        if state.read_limiter.available_permits() == 0 {
+            continue;
+        }
+
+        match step(&state, batch).await {
+            Ok(true) => {
+                let counters = state.scrubber.counters.lock().unwrap().clone();
+                tracing::info!(
+                    "-> Scrub: xong lượt {}, {} chunk hỏng, đã sửa {}",
+                    counters.passes,
+                    counters.corrupt_found,
+                    counters.repaired
+                );
+                tokio::time::sleep(rest).await;
+            }
+            Ok(false) => {}
+            Err(e) => {
+                tracing::error!("Lỗi khi kiểm tra dữ liệu trong nền: {}", e);
+                tokio::time::sleep(rest.min(Duration::from_secs(60))).await;
+            }
+        }
+    }
+}
+
+/// Handler `GET /admin/scrub/status`
+pub(crate) async fn status(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Result<Json<Status>, ApiError> {
+    require_admin(&state, &headers)?;
+    let config = state.config.load();
+    let (enabled, algorithm) = (config.scrub, config.verify_hash.unwrap_or_default().name());
+    drop(config);
+    let counters = state.scrubber.counters.lock().unwrap().clone();
+    let (shard, position) = {
+        let cursor = state.scrubber.cursor.lock().unwrap();
+        (cursor.shard, cursor.after.as_deref().map(|k| String::from_utf8_lossy(k).into_owned()))
+    };
+
+    let read_state = state.clone();
+    let (quarantined, entries) = blocking(move || {
+        let quarantine = &read_state.scrubber;
+        let db_error = |e: sled::Error| {
+            tracing::error!("Lỗi khi đọc quarantine: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        };
+        Ok((quarantine.quarantine.len(), quarantine.entries(MAX_LISTED).map_err(db_error)?))
+    })
+    .await?;
+
+    Ok(Json(Status {
+        enabled,
+        algorithm,
+        counters,
+        shard,
+        position,
+        quarantined,
+        entries,
+    }))
+}
@@ -0,0 +1,27 @@
+// ## KHÓA FILE ĐÃ HOÀN TẤT (SEAL) ##
+//
+// `POST /file/:fileKey/seal` đánh dấu một file là bất biến: từ đó các thao tác ghi chunk,
+// xóa, đổi tên (cả khi fileKey là đích của lần đổi tên), sửa metadata và CAS trên fileKey
+// này trả về `403` với mã `file_sealed`. Cờ nằm trong tree `sealed_files` (key là fileKey,
+// value là thời điểm seal theo unix giây, u64 big-endian), tách khỏi các shard nên không
+// lẫn vào kết quả quét chunk. Chỉ `POST /file/:fileKey/unseal` kèm API key quản trị mới
+// gỡ được cờ.
+//
+// Cờ được kiểm tra ở đầu handler: một lần ghi đã qua bước kiểm tra trước lúc file bị seal
+// vẫn có thể hoàn tất, nên client nên seal sau khi mọi lần upload đã trả về.
+
+/// File có đang bị seal không
+pub fn is_sealed(tree: &sled::Tree, file_key: &str) -> sled::Result<bool> {
+    tree.contains_key(file_key)
+}
+
+/// Seal `file_key`; trả về `false` nếu file đã bị seal từ trước (giữ nguyên thời điểm cũ)
+pub fn seal(tree: &sled::Tree, file_key: &str, now: u64) -> sled::Result<bool> {
+    let result = tree.compare_and_swap(file_key, None::<&[u8]>, Some(&now.to_be_bytes()))?;
+    Ok(result.is_ok())
+}
+
+/// Gỡ cờ seal; trả về `false` nếu file không bị seal
+pub fn unseal(tree: &sled::Tree, file_key: &str) -> sled::Result<bool> {
+    Ok(tree.remove(file_key)?.is_some())
+}
@@ -0,0 +1,113 @@
+// ## TỰ KIỂM TRA KHI KHỞI ĐỘNG (--selftest) ##
+//
+// `rust-p2p-storage --selftest [--dir PATH]` kiểm tra nhanh binary trước khi deploy: mở
+// một database tạm trong `PATH` (mặc định thư mục hiện tại, tức cùng filesystem với
+// database thật) và gọi thẳng vào Router thật với cấu hình STORAGE_* hiện tại, lần lượt
+// lưu một chunk tổng hợp, đọc lại và so SHA-256, xóa file rồi xác nhận file đã hết chunk.
+// Các bước đi qua serialize JSON, Base64, nén/định dạng record và sled như request thật.
+// Thành công thì thoát với mã 0, bước đầu tiên thất bại được in ra cùng chi tiết và
+// process thoát với mã khác 0. Database tạm bị xóa khi kết thúc.
+
+use axum::{
+    body::{to_bytes, Body},
+    http::{header, Method, Request},
+    Router,
+};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use tower::ServiceExt;
+
+use crate::{build_app, build_state, Config};
+
+const FILE_KEY: &str = "0xselftest";
+
+/// Đọc thư mục chứa database tạm từ các đối số đứng sau `--selftest`
+pub fn dir_from_args(args: impl IntoIterator<Item = String>) -> Result<std::path::PathBuf, String> {
+    let mut dir = std::path::PathBuf::from(".");
+    let mut args = args.into_iter();
+    while let Some(name) = args.next() {
+        match name.as_str() {
+            "--dir" => dir = args.next().ok_or("thiếu giá trị cho --dir")?.into(),
+            other => return Err(format!("đối số không hỗ trợ: {}", other)),
+        }
+    }
+    Ok(dir)
+}
+
+/// Chạy các bước tự kiểm tra trên database tạm trong `dir` với cấu hình `config`. Trả về
+/// mô tả của bước thất bại đầu tiên.
+pub async fn run(config: Config, dir: &Path) -> Result<(), String> {
+    let db = sled::Config::new()
+        .path(dir.join(format!(".selftest-{}", std::process::id())))
+        .temporary(true)
+        .open()
+        .map_err(|e| format!("không mở được database tạm trong {}: {}", dir.display(), e))?;
+    let app = build_app(build_state(db, config));
+
+    // Đủ mọi giá trị byte để Base64 và nén không chỉ gặp dữ liệu dễ
+    let data: Vec<u8> = (0..4096u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8).collect();
+    let chunk_hash = format!("0x{}", hex::encode(Sha256::digest(&data)));
+
+    let body = serde_json::json!({
+        "fileKey": FILE_KEY,
+        "chunkHash": chunk_hash,
+        "chunkData": STANDARD.encode(&data),
+        "chunkIndex": 0,
+    });
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("/store")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()));
+    call(&app, "store", request).await?;
+    println!("✅ store: đã lưu chunk {} byte", data.len());
+
+    let file_uri = format!("/file/{}", FILE_KEY);
+    let chunks = call(&app, "retrieve", Request::builder().uri(&file_uri).body(Body::empty())).await?;
+    let [chunk] = chunks["chunks"].as_array().map(Vec::as_slice).unwrap_or_default() else {
+        return Err(format!("retrieve: cần đúng 1 chunk, nhận được {}", chunks["chunks"]));
+    };
+    let stored = chunk["value"]
+        .as_str()
+        .and_then(|value| STANDARD.decode(value).ok())
+        .ok_or("retrieve: value của chunk không phải Base64")?;
+    let stored_hash = format!("0x{}", hex::encode(Sha256::digest(&stored)));
+    if stored_hash != chunk_hash {
+        return Err(format!("retrieve: SHA-256 của dữ liệu đọc lại là {}, cần {}", stored_hash, chunk_hash));
+    }
+    println!("✅ retrieve: SHA-256 khớp");
+
+    let delete = Request::builder().method(Method::DELETE).uri(&file_uri).body(Body::empty());
+    let removed = call(&app, "delete", delete).await?;
+    if removed["removed"] != serde_json::json!([chunk_hash]) {
+        return Err(format!("delete: danh sách chunk đã xóa không đúng: {}", removed["removed"]));
+    }
+    let after = call(&app, "verify-delete", Request::builder().uri(&file_uri).body(Body::empty())).await?;
+    if after["chunks"].as_array().is_none_or(|chunks| !chunks.is_empty()) {
+        return Err(format!("verify-delete: file vẫn còn chunk: {}", after["chunks"]));
+    }
+    println!("✅ delete: file đã bị xóa hết chunk");
+    Ok(())
+}
+
+// Gửi một request vào router, yêu cầu mã `2xx` và body JSON
+async fn call(app: &Router, step: &str, request: axum::http::Result<Request<Body>>) -> Result<Value, String> {
+    let request = request.map_err(|e| format!("{}: request không hợp lệ: {}", step, e))?;
+    let response = match app.clone().oneshot(request).await {
+        Ok(response) => response,
+        Err(never) => match never {},
+    };
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX)
+        .await
+        .map_err(|e| format!("{}: không đọc được body: {}", step, e))?;
+    if !status.is_success() {
+        return Err(format!("{}: HTTP {} {}", step, status, String::from_utf8_lossy(&body)));
+    }
+    if body.is_empty() {
+        return Ok(Value::Null);
+    }
+    serde_json::from_slice(&body).map_err(|e| format!("{}: body không phải JSON: {}", step, e))
+}
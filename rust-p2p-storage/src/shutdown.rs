@@ -0,0 +1,152 @@
+// ## TẮT SERVER ##
+//
+// Khi nhận SIGINT hoặc SIGTERM, HTTP và gRPC server ngừng nhận kết nối mới và chờ các
+// request đang chạy (kể cả các lần ghi chunk) xử lý xong, tối đa
+// STORAGE_SHUTDOWN_DRAIN_TIMEOUT_MS (mặc định 30 giây; kết nối giữ lâu như `/events` hay
+// websocket sẽ bị cắt khi hết thời gian này). Sau đó database được flush lần cuối trước
+// khi thoát, và log ghi tóm tắt: thời gian chạy, số request và chunk đã phục vụ, số chunk
+// còn trong bộ đệm ghi được ghi lúc tắt và số chunk còn chờ replication.
+//
+// Nếu đĩa bị treo, `db.flush()` có thể không bao giờ trả về và chặn process
+// (pod kẹt ở trạng thái Terminating khi rollout), nên lần flush này chỉ được chờ tối đa
+// STORAGE_SHUTDOWN_FLUSH_TIMEOUT_MS. Hết thời gian thì ghi lỗi và thoát luôn với mã
+// `EXIT_FLUSH_TIMEOUT`, để bên vận hành phân biệt được lần tắt có thể đã mất dữ liệu
+// chưa flush với lần tắt sạch (mã 0).
+//
+// Các chunk còn trong bộ đệm ghi (STORAGE_WRITE_BUFFER) được ghi xuống sled trước lần
+// flush này và nằm trong cùng giới hạn thời gian.
+
+use std::future::Future;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+
+use crate::{write_buffer, AppState};
+
+/// Mã thoát khi flush cuối cùng báo lỗi
+pub const EXIT_FLUSH_FAILED: i32 = 2;
+/// Mã thoát khi flush cuối cùng không xong trong thời gian cho phép; các lần ghi chưa
+/// được flush có thể đã mất
+pub const EXIT_FLUSH_TIMEOUT: i32 = 3;
+
+/// Chờ tới khi process nhận SIGINT (Ctrl+C) hoặc SIGTERM
+pub async fn signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("Không lắng nghe được SIGINT: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut stream) => {
+                stream.recv().await;
+            }
+            Err(e) => {
+                tracing::error!("Không lắng nghe được SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+/// Tín hiệu tắt dùng chung cho các server: `Trigger::stop` báo tắt, mọi `Stopped::wait`
+/// tạo từ cùng trigger hoàn tất sau đó
+pub fn channel() -> (Trigger, Stopped) {
+    let (tx, rx) = watch::channel(false);
+    (Trigger(tx), Stopped(rx))
+}
+
+/// Phía báo tắt của `channel`
+pub struct Trigger(watch::Sender<bool>);
+
+impl Trigger {
+    pub fn stop(&self) {
+        self.0.send_replace(true);
+    }
+}
+
+/// Phía chờ tắt của `channel`
+#[derive(Clone)]
+pub struct Stopped(watch::Receiver<bool>);
+
+impl Stopped {
+    /// Chờ tới khi được báo tắt (hoặc trigger bị drop)
+    pub async fn wait(mut self) {
+        let _ = self.0.wait_for(|stopped| *stopped).await;
+    }
+}
+
+/// Chờ `servers` xử lý xong các request đang chạy, tối đa STORAGE_SHUTDOWN_DRAIN_TIMEOUT_MS.
+/// Trả về `false` nếu hết thời gian mà vẫn còn request.
+pub async fn drain(state: &AppState, servers: impl Future<Output = ()>) -> bool {
+    let timeout = Duration::from_millis(state.config.load().shutdown_drain_timeout_ms);
+    let started = std::time::Instant::now();
+    match tokio::time::timeout(timeout, servers).await {
+        Ok(()) => {
+            tracing::info!("✅ Các request đang chạy đã xong sau {:?}", started.elapsed());
+            true
+        }
+        Err(_) => {
+            tracing::warn!("⚠️  Vẫn còn request chưa xong sau {:?}, bỏ qua để flush và thoát", timeout);
+            false
+        }
+    }
+}
+
+/// Ghi nốt bộ đệm ghi rồi flush database lần cuối, chờ tối đa
+/// STORAGE_SHUTDOWN_FLUSH_TIMEOUT_MS. Trả về mã thoát của process: 0 nếu flush xong,
+/// `EXIT_FLUSH_FAILED` hoặc `EXIT_FLUSH_TIMEOUT` nếu không.
+pub async fn final_flush(state: &Arc<AppState>) -> i32 {
+    let timeout = Duration::from_millis(state.config.load().shutdown_flush_timeout_ms);
+    let db = state.db.clone();
+    let started = std::time::Instant::now();
+    let buffered = state.write_buffer.pending();
+    let flush = async {
+        if let Err(status) = write_buffer::drain(state).await {
+            tracing::error!("❌ Lỗi khi ghi bộ đệm lúc tắt server: {}", status);
+        }
+        tokio::task::spawn_blocking(move || db.flush()).await
+    };
+    // Khi hết thời gian, thread đang flush vẫn bị treo nhưng process thoát ngay sau đó
+    let code = match tokio::time::timeout(timeout, flush).await {
+        Ok(Ok(Ok(bytes))) => {
+            tracing::info!("✅ Đã flush {} byte xuống đĩa trong {:?}", bytes, started.elapsed());
+            0
+        }
+        Ok(Ok(Err(e))) => {
+            tracing::error!("❌ Lỗi khi flush database lúc tắt server: {}", e);
+            EXIT_FLUSH_FAILED
+        }
+        Ok(Err(e)) => {
+            tracing::error!("❌ Tác vụ flush lúc tắt server bị lỗi: {}", e);
+            EXIT_FLUSH_FAILED
+        }
+        Err(_) => {
+            tracing::error!(
+                "❌ Flush database không xong sau {:?}, thoát luôn; các lần ghi chưa flush có thể đã mất",
+                timeout
+            );
+            EXIT_FLUSH_TIMEOUT
+        }
+    };
+    tracing::info!(
+        uptime_secs = state.uptime().as_secs(),
+        requests = state.metrics.requests_total(),
+        chunks_served = state.metrics.chunks_served.load(Ordering::Relaxed),
+        buffered_writes = buffered,
+        replication_queue = state.replication.depth(),
+        exit_code = code,
+        "👋 Server đã tắt"
+    );
+    code
+}
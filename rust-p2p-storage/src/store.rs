@@ -0,0 +1,329 @@
+// ## NƠI LƯU VALUE CỦA CHUNK ##
+//
+// Value của chunk đã tách khỏi record (chunk lớn, xem module tier) được lưu qua trait
+// `ChunkStore` theo key của chunk (`fileKey:chunkHash`). Đây chỉ là nơi lưu value, không
+// phải backend lưu trữ hoàn chỉnh: record (metadata), index thứ tự, TTL, compare-and-swap
+// record, các lượt quét file và `/admin/export` vẫn đọc ghi thẳng các tree sled trong
+// handler. Backend được chọn lúc khởi động bằng STORAGE_BACKEND và không đổi được khi đang
+// chạy:
+//
+// - `sled` (mặc định): tree `chunks_large` trong cùng database. Value được ghi, đổi tên và
+//   xóa trong cùng transaction với record như trước.
+// - `fs`: mỗi chunk là một file `<STORAGE_CHUNK_DIR>/<fileKey>/<chunkHash>` (mặc định thư
+//   mục `data`), namespace khác nằm trong `<STORAGE_CHUNK_DIR>/.ns/<tên>/`. Mọi chunk khác
+//   rỗng đều được tách khỏi record, nên shard chỉ còn metadata. Byte ngoài chữ, số, `-` và
+//   `_` trong tên được ghi thành `%XX`, nên fileKey nhị phân hay chứa `/` vẫn lưu được và tên
+//   không bao giờ bắt đầu bằng `.`; fileKey hoặc chunkHash dài hơn giới hạn tên file của hệ
+//   điều hành (thường 255 byte sau khi mã hóa) không ghi được.
+// - `rocksdb`: chưa có trong bản build này. Như mọi tên backend không biết, server báo lỗi
+//   cấu hình và không khởi động thay vì chạy trên backend khác.
+//
+// Đổi backend không chuyển value đã lưu: chunk lớn đã nằm trong `chunks_large` không đọc được
+// với `fs` (lúc khởi động có cảnh báo) và ngược lại, nên hãy đổi trên database trống.
+//
+// File không nằm trong transaction của sled. Với backend ngoài sled, value mới được ghi trước
+// transaction của record (ghi ra file tạm rồi đổi tên) và được trả lại như cũ nếu
+// transaction bị hủy; value cũ chỉ bị xóa sau khi transaction xong (xem `tier::Large::stage`).
+// Các thao tác đổi value của cùng một fileKey được tuần tự hóa bằng khóa (xem
+// `tier::Large::lock`). Process bị dừng giữa hai bước có thể để lại value không còn record,
+// `GET /admin/consistency` báo và `POST /admin/reindex` xóa chúng như value thừa trong
+// `chunks_large`. Snapshot và `/admin/export` chỉ chứa database sled, không chứa các file này.
+
+use sled::IVec;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::{fs, io};
+
+use crate::{key, Config};
+
+/// Nơi lưu value của chunk theo key `fileKey:chunkHash`
+pub trait ChunkStore: Send + Sync {
+    /// Ghi (hoặc thay) value của `key`
+    fn put(&self, key: &[u8], value: &[u8]) -> sled::Result<()>;
+
+    fn get(&self, key: &[u8]) -> sled::Result<Option<IVec>>;
+
+    /// Các cặp (key, value) có key bắt đầu bằng `prefix`, theo thứ tự key. Value được đọc
+    /// dần khi duyệt, không nạp cả backend vào bộ nhớ.
+    fn scan_prefix<'a>(&'a self, prefix: &[u8]) -> Box<dyn Iterator<Item = sled::Result<(IVec, IVec)>> + 'a>;
+
+    /// Như `scan_prefix` nhưng chỉ trả về key, không đọc value
+    fn scan_keys<'a>(&'a self, prefix: &[u8]) -> Box<dyn Iterator<Item = sled::Result<IVec>> + 'a>;
+
+    /// Xóa value của `key`; không có gì để xóa cũng không phải lỗi
+    fn delete(&self, key: &[u8]) -> sled::Result<()>;
+
+    /// Đưa các thay đổi đã ghi xuống đĩa
+    fn flush(&self) -> sled::Result<()>;
+
+    /// Tree chứa value nếu backend nằm trong database sled, để value được ghi cùng
+    /// transaction với record; `None` với backend khác
+    fn tree(&self) -> Option<&sled::Tree> {
+        None
+    }
+}
+
+/// Backend chọn bằng STORAGE_BACKEND
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Sled,
+    Fs,
+}
+
+impl std::str::FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sled" => Ok(Backend::Sled),
+            "fs" => Ok(Backend::Fs),
+            "rocksdb" => Err("backend rocksdb chưa có trong bản build này".to_string()),
+            other => Err(format!("backend không hỗ trợ: {}", other)),
+        }
+    }
+}
+
+/// Mở backend đã cấu hình cho namespace có tiền tố tree `prefix` (rỗng với namespace mặc
+/// định, xem `namespace::tree_prefix`)
+pub fn open(db: &sled::Db, config: &Config, prefix: &str) -> sled::Result<Arc<dyn ChunkStore>> {
+    Ok(match config.backend {
+        Backend::Sled => Arc::new(SledStore(db.open_tree(format!("{}chunks_large", prefix))?)),
+        Backend::Fs => {
+            let legacy = format!("{}chunks_large", prefix);
+            if db.tree_names().iter().any(|name| name == legacy.as_bytes()) && !db.open_tree(&legacy)?.is_empty() {
+                tracing::warn!("⚠️  Tree {} còn value chunk lớn mà backend fs không đọc được", legacy);
+            }
+            let mut root = PathBuf::from(&config.chunk_dir);
+            if !prefix.is_empty() {
+                root.push(format!(".{}", prefix));
+            }
+            fs::create_dir_all(&root)?;
+            Arc::new(FileStore {
+                root,
+                dirty: Mutex::default(),
+            })
+        }
+    })
+}
+
+/// Backend `sled`: tree `chunks_large`
+pub struct SledStore(pub sled::Tree);
+
+impl ChunkStore for SledStore {
+    fn put(&self, key: &[u8], value: &[u8]) -> sled::Result<()> {
+        self.0.insert(key, value)?;
+        Ok(())
+    }
+
+    fn get(&self, key: &[u8]) -> sled::Result<Option<IVec>> {
+        self.0.get(key)
+    }
+
+    fn scan_prefix<'a>(&'a self, prefix: &[u8]) -> Box<dyn Iterator<Item = sled::Result<(IVec, IVec)>> + 'a> {
+        Box::new(self.0.scan_prefix(prefix))
+    }
+
+    fn scan_keys<'a>(&'a self, prefix: &[u8]) -> Box<dyn Iterator<Item = sled::Result<IVec>> + 'a> {
+        Box::new(self.0.scan_prefix(prefix).keys())
+    }
+
+    fn delete(&self, key: &[u8]) -> sled::Result<()> {
+        self.0.remove(key)?;
+        Ok(())
+    }
+
+    fn flush(&self) -> sled::Result<()> {
+        self.0.flush()?;
+        Ok(())
+    }
+
+    fn tree(&self) -> Option<&sled::Tree> {
+        Some(&self.0)
+    }
+}
+
+/// Backend `fs`: mỗi value là file `<root>/<fileKey>/<chunkHash>`
+pub struct FileStore {
+    root: PathBuf,
+    // File đã ghi hoặc xóa kể từ lần flush trước; thư mục chứa chúng cũng cần fsync
+    dirty: Mutex<HashSet<PathBuf>>,
+}
+
+impl FileStore {
+    fn path(&self, key: &[u8]) -> sled::Result<PathBuf> {
+        let (file_key, chunk_hash) =
+            key::decode_raw(key).ok_or_else(|| sled::Error::Unsupported(format!("key không hợp lệ: {}", String::from_utf8_lossy(key))))?;
+        Ok(self.root.join(encode_name(file_key)).join(encode_name(chunk_hash.as_bytes())))
+    }
+
+    fn mark_dirty(&self, path: PathBuf) {
+        self.dirty.lock().unwrap().insert(path);
+    }
+
+    // Key và đường dẫn của các file khớp `prefix`, theo thứ tự key. Chỉ mở thư mục của
+    // fileKey có thể có key như vậy, và chỉ giữ tên các thư mục cùng tên file của thư mục
+    // đang duyệt chứ không đọc value.
+    fn files<'a>(&'a self, prefix: &[u8]) -> Box<dyn Iterator<Item = io::Result<(IVec, PathBuf)>> + 'a> {
+        let prefix = prefix.to_vec();
+        let mut dirs: Vec<(Vec<u8>, String)> = match read_names(&self.root) {
+            Ok(names) => names
+                .into_iter()
+                .filter_map(|dir| {
+                    let dir_prefix = key::prefix_raw(&decode_name(&dir)?);
+                    (dir_prefix.starts_with(&prefix) || prefix.starts_with(&dir_prefix)).then_some((dir_prefix, dir))
+                })
+                .collect(),
+            Err(e) => return Box::new(std::iter::once(Err(e))),
+        };
+        dirs.sort_unstable();
+        Box::new(dirs.into_iter().flat_map(move |(dir_prefix, dir)| self.files_in(&dir_prefix, &dir, &prefix)))
+    }
+
+    // Các file trong thư mục `dir` (có key bắt đầu bằng `dir_prefix`) khớp `prefix`
+    fn files_in(&self, dir_prefix: &[u8], dir: &str, prefix: &[u8]) -> Vec<io::Result<(IVec, PathBuf)>> {
+        let dir_path = self.root.join(dir);
+        let names = match read_names(&dir_path) {
+            Ok(names) => names,
+            Err(e) => return vec![Err(e)],
+        };
+        let mut files: Vec<(Vec<u8>, String)> = names
+            .into_iter()
+            .filter_map(|name| {
+                let chunk_hash = String::from_utf8(decode_name(&name)?).ok()?;
+                let db_key = [dir_prefix, chunk_hash.as_bytes()].concat();
+                db_key.starts_with(prefix).then_some((db_key, name))
+            })
+            .collect();
+        files.sort_unstable();
+        files.into_iter().map(|(db_key, name)| Ok((IVec::from(db_key), dir_path.join(name)))).collect()
+    }
+}
+
+impl ChunkStore for FileStore {
+    fn put(&self, key: &[u8], value: &[u8]) -> sled::Result<()> {
+        let path = self.path(key)?;
+        let dir = path.parent().expect("file luôn nằm trong thư mục của fileKey");
+        fs::create_dir_all(dir)?;
+        // Ghi ra file tạm rồi đổi tên để không ai đọc được value ghi dở
+        let mut tmp = path.clone().into_os_string();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        fs::write(&tmp, value)?;
+        fs::rename(&tmp, &path)?;
+        self.mark_dirty(path);
+        Ok(())
+    }
+
+    fn get(&self, key: &[u8]) -> sled::Result<Option<IVec>> {
+        match fs::read(self.path(key)?) {
+            Ok(value) => Ok(Some(value.into())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn scan_prefix<'a>(&'a self, prefix: &[u8]) -> Box<dyn Iterator<Item = sled::Result<(IVec, IVec)>> + 'a> {
+        Box::new(self.files(prefix).filter_map(|file| {
+            let (db_key, path) = match file {
+                Ok(file) => file,
+                Err(e) => return Some(Err(e.into())),
+            };
+            match fs::read(path) {
+                Ok(value) => Some(Ok((db_key, value.into()))),
+                // Bị xóa sau khi liệt kê thư mục
+                Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+                Err(e) => Some(Err(e.into())),
+            }
+        }))
+    }
+
+    fn scan_keys<'a>(&'a self, prefix: &[u8]) -> Box<dyn Iterator<Item = sled::Result<IVec>> + 'a> {
+        Box::new(self.files(prefix).map(|file| Ok(file?.0)))
+    }
+
+    fn delete(&self, key: &[u8]) -> sled::Result<()> {
+        let path = self.path(key)?;
+        match fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        }
+        // Thư mục của fileKey không còn chunk nào thì bỏ luôn
+        if let Some(dir) = path.parent() {
+            let _ = fs::remove_dir(dir);
+        }
+        self.mark_dirty(path);
+        Ok(())
+    }
+
+    fn flush(&self) -> sled::Result<()> {
+        let paths = std::mem::take(&mut *self.dirty.lock().unwrap());
+        let mut dirs = HashSet::new();
+        for path in &paths {
+            match fs::File::open(path) {
+                Ok(file) => file.sync_all()?,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+            dirs.extend(path.parent().map(Path::to_path_buf));
+        }
+        if !dirs.is_empty() {
+            dirs.insert(self.root.clone());
+        }
+        for dir in dirs {
+            match fs::File::open(&dir) {
+                Ok(dir) => dir.sync_all()?,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+}
+
+// Tên các mục trong thư mục, bỏ qua file tạm và thư mục namespace (bắt đầu bằng '.')
+fn read_names(dir: &Path) -> io::Result<Vec<String>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut names = Vec::new();
+    for entry in entries {
+        if let Ok(name) = entry?.file_name().into_string()
+            && !name.starts_with('.')
+            && !name.ends_with(".tmp")
+        {
+            names.push(name);
+        }
+    }
+    Ok(names)
+}
+
+// Tên file của một thành phần key: giữ chữ, số, '-' và '_', các byte khác thành %XX
+fn encode_name(part: &[u8]) -> String {
+    let mut name = String::with_capacity(part.len());
+    for &b in part {
+        if b.is_ascii_alphanumeric() || b == b'-' || b == b'_' {
+            name.push(b as char);
+        } else {
+            name.push_str(&format!("%{:02X}", b));
+        }
+    }
+    name
+}
+
+fn decode_name(name: &str) -> Option<Vec<u8>> {
+    let mut part = Vec::with_capacity(name.len());
+    let mut bytes = name.bytes();
+    while let Some(b) = bytes.next() {
+        if b == b'%' {
+            let hex = [bytes.next()?, bytes.next()?];
+            part.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            part.push(b);
+        }
+    }
+    Some(part)
+}
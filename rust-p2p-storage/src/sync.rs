@@ -0,0 +1,255 @@
+// ## ĐỒNG BỘ TOÀN BỘ CHUNK CHO NODE MỚI ##
+//
+// Node mới vào cluster kéo toàn bộ chunk của một seed thay vì chờ replication từng chunk.
+// `GET /sync/full` (cần API key quản trị) trả về luồng NDJSON: mỗi dòng là một chunk
+// cùng định dạng với body của `POST /replicate` (key base64url, dữ liệu gốc Base64,
+// index, contentType, mode), theo thứ tự byte của key trên mọi shard; dòng cuối là
+// `{"end":true,"chunks":N}`. Luồng bị cắt giữa chừng thì không có dòng cuối, và client
+// gọi lại với `?after=<key của chunk cuối đã nhận>` để tiếp tục sau chunk đó.
+//
+// Để một lần đồng bộ không chiếm hết node: tối đa STORAGE_SYNC_MAX_STREAMS luồng chạy
+// cùng lúc (luồng thêm bị từ chối `503`), và mỗi luồng gửi không quá
+// STORAGE_SYNC_RATE_BYTES byte mỗi giây (0 là không giới hạn). Luồng chỉ đọc từng chunk
+// nên không giữ suất đọc của STORAGE_MAX_CONCURRENT_READS.
+//
+// Khi đặt STORAGE_SYNC_FROM (URL của seed), node kéo toàn bộ chunk của seed lúc khởi
+// động, gửi STORAGE_SYNC_API_KEY làm API key. Chunk được ghi theo lô `INGEST_BATCH`
+// trong một transaction như `/replicate`: ghi đè chunk cùng key và không đưa vào hàng đợi
+// replication. Luồng bị ngắt thì tiếp tục từ chunk cuối đã ghi. Chỉ chunk được đồng bộ;
+// trạng thái seal, alias và parity của seed thì không.
+
+use axum::body::{Body, Bytes};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use serde::{Deserialize, Serialize};
+use std::ops::Bound;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::error::ApiError;
+use crate::replication::{payload_for, ReplicatePayload};
+use crate::AppState;
+
+/// Số chunk ghi trong một transaction khi nhận luồng đồng bộ
+pub const INGEST_BATCH: usize = 256;
+// Số lần thử lại liên tiếp khi kéo từ seed thất bại mà không nhận thêm được chunk nào
+const MAX_ATTEMPTS: u32 = 5;
+
+/// Dòng cuối của luồng `/sync/full`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SyncEnd {
+    pub end: bool,
+    // Số chunk đã gửi trong luồng này
+    pub chunks: u64,
+}
+
+// Giữ một suất luồng đồng bộ; trả lại khi luồng kết thúc hoặc client ngắt kết nối
+struct StreamPermit(Arc<AppState>);
+
+impl Drop for StreamPermit {
+    fn drop(&mut self) {
+        self.0.sync_streams.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Luồng NDJSON các chunk có key lớn hơn `after` (key base64url). Trả về `503` khi đã có
+/// STORAGE_SYNC_MAX_STREAMS luồng đang chạy.
+pub fn stream(state: &Arc<AppState>, after: Option<&str>) -> Result<Body, ApiError> {
+    let after = after
+        .map(|key| URL_SAFE_NO_PAD.decode(key).map_err(|_| ApiError::BadRequest))
+        .transpose()?;
+    let config = state.config.load();
+    let (limit, rate) = (config.sync_max_streams, config.sync_rate_bytes);
+    drop(config);
+    if state.sync_streams.fetch_add(1, Ordering::Relaxed) >= limit {
+        state.sync_streams.fetch_sub(1, Ordering::Relaxed);
+        return Err(ApiError::Overloaded);
+    }
+    let permit = StreamPermit(state.clone());
+
+    let (tx, rx) = tokio::sync::mpsc::channel::<std::io::Result<Bytes>>(16);
+    let state = state.clone();
+    tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        let started = Instant::now();
+        let (mut sent_bytes, mut sent_chunks) = (0u64, 0u64);
+        for item in merged_keys(&state.shards, after) {
+            let line = item.and_then(|db_key| {
+                let key = URL_SAFE_NO_PAD.encode(&db_key);
+                let Some(payload) = payload_for(&state, &key)? else {
+                    return Ok(None); // Không phải key chunk hoặc chunk vừa bị xóa
+                };
+                let mut line = serde_json::to_vec(&payload).expect("payload luôn serialize được");
+                line.push(b'\n');
+                Ok(Some(line))
+            });
+            let line = match line {
+                Ok(Some(line)) => line,
+                Ok(None) => continue,
+                Err(e) => {
+                    tracing::error!("Lỗi khi đọc chunk cho luồng đồng bộ: {}", e);
+                    let _ = tx.blocking_send(Err(std::io::Error::other(e)));
+                    return;
+                }
+            };
+            sent_bytes += line.len() as u64;
+            sent_chunks += 1;
+            // Client ngắt kết nối thì channel bị đóng, dừng đọc
+            if tx.blocking_send(Ok(Bytes::from(line))).is_err() {
+                return;
+            }
+            if rate > 0 {
+                let due = Duration::from_secs_f64(sent_bytes as f64 / rate as f64);
+                if let Some(wait) = due.checked_sub(started.elapsed()) {
+                    std::thread::sleep(wait);
+                }
+            }
+        }
+        let end = SyncEnd {
+            end: true,
+            chunks: sent_chunks,
+        };
+        let mut line = serde_json::to_vec(&end).expect("SyncEnd luôn serialize được");
+        line.push(b'\n');
+        let _ = tx.blocking_send(Ok(Bytes::from(line)));
+        tracing::info!("-> Đã gửi {} chunk qua /sync/full", sent_chunks);
+    });
+
+    Ok(Body::from_stream(tokio_stream::wrappers::ReceiverStream::new(rx)))
+}
+
+// Key của mọi shard lớn hơn `after`, trộn theo thứ tự byte để con trỏ `after` dùng được
+// cho cả database dù mỗi shard tự sắp xếp riêng
+fn merged_keys(shards: &[sled::Tree], after: Option<Vec<u8>>) -> impl Iterator<Item = sled::Result<sled::IVec>> {
+    let start = after.map_or(Bound::Unbounded, Bound::Excluded);
+    let mut iters: Vec<_> = shards
+        .iter()
+        .map(|tree| tree.range::<Vec<u8>, _>((start.clone(), Bound::Unbounded)).keys().peekable())
+        .collect();
+    std::iter::from_fn(move || {
+        // Lỗi của một shard được trả về ngay, luồng dừng ở đó
+        let next = iters
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(i, iter)| iter.peek().map(|key| (i, key.as_ref().ok().cloned())))
+            .min_by(|(_, a), (_, b)| match (a, b) {
+                (Some(a), Some(b)) => a.cmp(b),
+                (None, _) => std::cmp::Ordering::Less,
+                (_, None) => std::cmp::Ordering::Greater,
+            })?;
+        iters[next.0].next()
+    })
+}
+
+/// Kéo toàn bộ chunk của `seed` về node này, bắt đầu sau key `after`. Trả về số chunk đã
+/// ghi; khi lỗi, số chunk đã ghi nằm trong kết quả cùng con trỏ để tiếp tục.
+pub async fn pull(
+    state: &Arc<AppState>,
+    client: &reqwest::Client,
+    seed: &str,
+    api_key: Option<&str>,
+    after: &mut Option<String>,
+) -> Result<u64, (u64, String)> {
+    let mut request = client.get(format!("{}/sync/full", seed.trim_end_matches('/')));
+    if let Some(cursor) = after.as_deref() {
+        request = request.query(&[("after", cursor)]);
+    }
+    if let Some(api_key) = api_key {
+        request = request.header("x-api-key", api_key);
+    }
+    let mut response = match request.send().await {
+        Ok(response) if response.status().is_success() => response,
+        Ok(response) => return Err((0, format!("seed trả về {}", response.status()))),
+        Err(e) => return Err((0, e.to_string())),
+    };
+
+    let mut ingested = 0;
+    let mut pending: Vec<u8> = Vec::new();
+    let mut batch: Vec<ReplicatePayload> = Vec::new();
+    loop {
+        let bytes = match response.chunk().await {
+            Ok(Some(bytes)) => bytes,
+            Ok(None) => break,
+            Err(e) => {
+                ingested += ingest(state, std::mem::take(&mut batch), after).await.map_err(|e| (ingested, e))?;
+                return Err((ingested, e.to_string()));
+            }
+        };
+        pending.extend_from_slice(&bytes);
+        while let Some(end) = pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = pending.drain(..=end).collect();
+            if let Ok(payload) = serde_json::from_slice::<ReplicatePayload>(&line) {
+                batch.push(payload);
+            } else if let Ok(end) = serde_json::from_slice::<SyncEnd>(&line) {
+                ingested += ingest(state, std::mem::take(&mut batch), after).await.map_err(|e| (ingested, e))?;
+                tracing::info!("-> Seed đã gửi xong ({} chunk trong lượt cuối)", end.chunks);
+                return Ok(ingested);
+            } else {
+                tracing::warn!("Bỏ dòng không hợp lệ trong luồng đồng bộ");
+            }
+            if batch.len() >= INGEST_BATCH {
+                ingested += ingest(state, std::mem::take(&mut batch), after).await.map_err(|e| (ingested, e))?;
+            }
+        }
+    }
+    ingested += ingest(state, batch, after).await.map_err(|e| (ingested, e))?;
+    Err((ingested, "luồng đồng bộ kết thúc trước dòng cuối".to_string()))
+}
+
+// Ghi một lô chunk nhận từ seed rồi dời con trỏ tới chunk cuối của lô
+async fn ingest(state: &Arc<AppState>, batch: Vec<ReplicatePayload>, after: &mut Option<String>) -> Result<u64, String> {
+    let Some(last) = batch.last().map(|payload| payload.key.clone()) else {
+        return Ok(0);
+    };
+    let writes: Vec<_> = batch
+        .into_iter()
+        .filter_map(|payload| {
+            let key = payload.key.clone();
+            state
+                .replica_write(payload)
+                .map_err(|e| tracing::warn!("Bỏ chunk {} nhận từ seed: {:?}", key, e))
+                .ok()
+        })
+        .collect();
+    let count = writes.len() as u64;
+    let write_state = state.clone();
+    crate::blocking(move || write_state.commit_writes(&writes))
+        .await
+        .map_err(|status| format!("không ghi được lô chunk: {}", status))?;
+    *after = Some(last);
+    Ok(count)
+}
+
+/// Task chạy lúc khởi động: kéo toàn bộ chunk từ STORAGE_SYNC_FROM nếu được đặt, thử lại
+/// từ chunk cuối đã ghi khi luồng bị ngắt
+pub async fn run(state: Arc<AppState>) {
+    let config = state.config.load();
+    let Some(seed) = config.sync_from.clone() else {
+        return;
+    };
+    let api_key = config.sync_api_key.clone();
+    drop(config);
+
+    tracing::info!("-> Đang đồng bộ toàn bộ chunk từ seed {}", seed);
+    let client = reqwest::Client::new();
+    let (mut after, mut total, mut attempts) = (None, 0, 0);
+    loop {
+        match pull(&state, &client, &seed, api_key.as_deref(), &mut after).await {
+            Ok(count) => {
+                total += count;
+                tracing::info!("✅ Đã đồng bộ {} chunk từ seed {}", total, seed);
+                return;
+            }
+            Err((count, e)) => {
+                total += count;
+                attempts = if count > 0 { 1 } else { attempts + 1 };
+                tracing::warn!("Đồng bộ từ seed {} bị ngắt sau {} chunk (lần {}): {}", seed, total, attempts, e);
+                if attempts >= MAX_ATTEMPTS {
+                    tracing::error!("❌ Bỏ đồng bộ từ seed {}", seed);
+                    return;
+                }
+                tokio::time::sleep(Duration::from_secs(1 << attempts)).await;
+            }
+        }
+    }
+}
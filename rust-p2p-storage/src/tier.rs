@@ -0,0 +1,295 @@
+// ## TÁCH CHUNK LỚN RA TREE RIÊNG ##
+//
+// Chunk nhỏ được đọc thường xuyên nên có lợi khi nằm trong cache của sled, còn chunk lớn
+// chiếm nhiều trang cache và đẩy các chunk nhỏ ra ngoài mỗi lần quét prefix. Khi đặt
+// STORAGE_LARGE_CHUNK_BYTES, chunk có value (sau khi nén/delta) lớn hơn ngưỡng được lưu
+// value trong tree `chunks_large` với cùng key; record trong shard chỉ còn metadata và cờ
+// `large`. Quét một file chỉ đi qua các record nhỏ trong shard, sau đó mới lấy đúng các
+// value lớn cần thiết. Hai tree luôn được ghi, đổi tên và xóa cùng nhau trong một
+// transaction nên client không thấy sự khác biệt.
+//
+// Ngưỡng đổi được lúc chạy: chunk đã lưu giữ nguyên chỗ cho tới khi bị ghi lại.
+//
+// `chunks_large` là backend mặc định; STORAGE_BACKEND=fs lưu value thành file thay vì trong
+// sled (xem module store).
+//
+// Value trong `chunks_large` có thể dùng mã xóa (xem module erasure) và được mã hóa (xem
+// module cipher) như record trong shard.
+//
+// Khi bật STORAGE_DEDUP, value được tách ra cũng theo cờ `large` nhưng nằm trong blob dùng
+// chung (xem module dedup); `Large` gói cả hai nơi lưu để các đường đọc không phân biệt.
+
+use sled::IVec;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::store::ChunkStore;
+use crate::{Chunk, StoredChunkValue};
+
+// Số khóa dùng chung cho mọi fileKey (xem `Large::lock`)
+const LOCKS: usize = 64;
+
+/// Nơi lưu value đã tách khỏi record: backend theo key của chunk (`chunks_large` hoặc file,
+/// xem module store) và blob dùng chung của module dedup
+#[derive(Clone)]
+pub struct Large {
+    pub store: Arc<dyn ChunkStore>,
+    pub blobs: crate::dedup::Blobs,
+    locks: Arc<[Mutex<()>; LOCKS]>,
+}
+
+impl Large {
+    pub fn new(store: Arc<dyn ChunkStore>, blobs: crate::dedup::Blobs) -> Self {
+        Large {
+            store,
+            blobs,
+            locks: Arc::new(std::array::from_fn(|_| Mutex::new(()))),
+        }
+    }
+
+    /// Value đã lưu của chunk `key`, tìm trong blob trước rồi tới backend
+    pub fn get(&self, key: &[u8]) -> sled::Result<Option<IVec>> {
+        match self.blobs.get(key)? {
+            Some(value) => Ok(Some(value)),
+            None => self.store.get(key),
+        }
+    }
+
+    pub fn contains_key(&self, key: &[u8]) -> sled::Result<bool> {
+        Ok(self.blobs.files.contains_key(key)? || self.store.get(key)?.is_some())
+    }
+
+    /// Tree `chunks_large` khi backend là sled, để value đổi cùng transaction với record
+    pub fn tree(&self) -> Option<&sled::Tree> {
+        self.store.tree()
+    }
+
+    /// Các cặp (key, value) trong backend thuộc `file_key`
+    pub fn entries_of(&self, file_key: &[u8]) -> sled::Result<Vec<(IVec, IVec)>> {
+        self.store
+            .scan_prefix(&crate::key::prefix_raw(file_key))
+            .filter(|kv| kv.as_ref().map_or(true, |(k, _)| crate::key::chunk_hash_in_raw(k, file_key).is_some()))
+            .collect()
+    }
+
+    /// Bỏ value của chunk `key` nếu vẫn đúng là `old` (đọc bằng `get` trước đó), phòng khi
+    /// một lần ghi vừa thay value mới cho key này. Trả về `true` nếu đã bỏ.
+    pub fn remove_if(&self, key: &[u8], old: &[u8]) -> sled::Result<bool> {
+        if self.blobs.release_if(key, old)? {
+            return Ok(true);
+        }
+        self.remove_stored_if(key, old)
+    }
+
+    /// Như `remove_if` nhưng chỉ với value trong backend
+    pub fn remove_stored_if(&self, key: &[u8], old: &[u8]) -> sled::Result<bool> {
+        if let Some(tree) = self.tree() {
+            return Ok(tree.compare_and_swap(key, Some(old), None::<&[u8]>)?.is_ok());
+        }
+        let _locked = self.lock(crate::key::decode_raw(key).map(|(file_key, _)| file_key));
+        if self.store.get(key)?.as_deref() != Some(old) {
+            return Ok(false);
+        }
+        self.store.delete(key)?;
+        Ok(true)
+    }
+
+    /// Khóa các fileKey trong lúc đổi value của chúng trên backend không nằm trong sled, để
+    /// các bước đọc value cũ, ghi value mới và transaction của record không xen vào nhau.
+    /// Backend sled không cần khóa vì đã có transaction.
+    pub fn lock<'a, 'k>(&'a self, file_keys: impl IntoIterator<Item = &'k [u8]>) -> Vec<MutexGuard<'a, ()>> {
+        if self.tree().is_some() {
+            return Vec::new();
+        }
+        // Luôn khóa theo thứ tự tăng dần để hai thao tác không chờ nhau
+        let mut slots: Vec<usize> = file_keys
+            .into_iter()
+            .map(|file_key| {
+                let mut hasher = std::collections::hash_map::DefaultHasher::new();
+                file_key.hash(&mut hasher);
+                hasher.finish() as usize % LOCKS
+            })
+            .collect();
+        slots.sort_unstable();
+        slots.dedup();
+        slots
+            .into_iter()
+            .map(|slot| self.locks[slot].lock().unwrap_or_else(|e| e.into_inner()))
+            .collect()
+    }
+
+    /// Thay đổi value đi cùng một thao tác trên record (ghi `puts`, xóa `deletes`). Với backend
+    /// sled trả về batch để áp dụng trong transaction của thao tác; với backend khác thì
+    /// như `stage`. Cần giữ `lock` của các fileKey liên quan.
+    #[allow(clippy::type_complexity)]
+    pub fn changes(&self, puts: Vec<(IVec, IVec)>, deletes: Vec<IVec>) -> sled::Result<(Option<(&sled::Tree, sled::Batch)>, Staged<'_>)> {
+        let Some(tree) = self.tree() else {
+            return Ok((None, self.stage(puts, deletes)?));
+        };
+        let unchanged = self.stage(Vec::new(), Vec::new())?;
+        if puts.is_empty() && deletes.is_empty() {
+            return Ok((None, unchanged));
+        }
+        let mut batch = sled::Batch::default();
+        for key in deletes {
+            batch.remove(key);
+        }
+        for (key, value) in puts {
+            batch.insert(key, value);
+        }
+        Ok((Some((tree, batch)), unchanged))
+    }
+
+    /// Ghi trước `puts` lên backend không nằm trong sled, nhớ value cũ để trả lại nếu
+    /// transaction của record bị hủy (`Staged::rollback`); `deletes` (trừ key vừa ghi) chỉ bị
+    /// xóa sau khi transaction xong (`Staged::commit`). Cần giữ `lock` của các fileKey liên quan.
+    pub fn stage(&self, puts: Vec<(IVec, IVec)>, mut deletes: Vec<IVec>) -> sled::Result<Staged<'_>> {
+        let written: HashSet<&[u8]> = puts.iter().map(|(key, _)| key.as_ref()).collect();
+        deletes.retain(|key| !written.contains(key.as_ref()));
+        let mut staged = Staged {
+            store: self.store.as_ref(),
+            previous: Vec::new(),
+            deletes,
+        };
+        for (key, value) in &puts {
+            let result = self.store.get(key).and_then(|previous| {
+                self.store.put(key, value)?;
+                Ok(previous)
+            });
+            match result {
+                Ok(previous) => staged.previous.push((key.clone(), previous)),
+                Err(e) => {
+                    staged.rollback();
+                    return Err(e);
+                }
+            }
+        }
+        Ok(staged)
+    }
+}
+
+/// Value đã ghi trước transaction của record (xem `Large::stage`)
+pub struct Staged<'a> {
+    store: &'a dyn ChunkStore,
+    // Value trước khi ghi của các key đã ghi, `None` nếu key chưa có value
+    previous: Vec<(IVec, Option<IVec>)>,
+    // Key có value cần xóa sau khi transaction xong
+    deletes: Vec<IVec>,
+}
+
+impl Staged<'_> {
+    /// Value của `key` như trước khi ghi trước
+    pub fn get(&self, key: &[u8]) -> sled::Result<Option<IVec>> {
+        match self.previous.iter().find(|(staged, _)| staged == key) {
+            Some((_, previous)) => Ok(previous.clone()),
+            None => self.store.get(key),
+        }
+    }
+
+    /// Transaction của record đã xong: xóa value không còn dùng. Key trong `unchanged` (lần
+    /// ghi không thay record) được trả lại value cũ. Lỗi chỉ để lại value thừa nên chỉ được
+    /// ghi log (xem module reindex).
+    pub fn commit(self, unchanged: &HashSet<&[u8]>) {
+        for (key, previous) in self.previous.into_iter().rev() {
+            if unchanged.contains(key.as_ref()) {
+                restore(self.store, &key, previous);
+            }
+        }
+        for key in self.deletes {
+            if unchanged.contains(key.as_ref()) {
+                continue;
+            }
+            if let Err(e) = self.store.delete(&key) {
+                tracing::warn!("Không xóa được value cũ của chunk {}: {}", String::from_utf8_lossy(&key), e);
+            }
+        }
+    }
+
+    /// Transaction của record bị hủy: trả lại value cũ của các key đã ghi
+    pub fn rollback(self) {
+        for (key, previous) in self.previous.into_iter().rev() {
+            restore(self.store, &key, previous);
+        }
+    }
+}
+
+fn restore(store: &dyn ChunkStore, key: &[u8], previous: Option<IVec>) {
+    let result = match previous {
+        Some(value) => store.put(key, &value),
+        None => store.delete(key),
+    };
+    if let Err(e) = result {
+        tracing::error!("Không trả lại được value cũ của chunk {}: {}", String::from_utf8_lossy(key), e);
+    }
+}
+
+/// Tách value ra khỏi record nếu lớn hơn `threshold`; trả về value cần ghi vào tree
+/// `chunks_large`
+pub fn split(record: &mut StoredChunkValue, threshold: Option<usize>) -> Option<Vec<u8>> {
+    if threshold.is_none_or(|limit| record.value.len() <= limit) {
+        return None;
+    }
+    record.large = true;
+    Some(std::mem::take(&mut record.value).into_bytes())
+}
+
+/// Điền lại value của record đã bị tách ra; `None` nếu value trong `chunks_large` không
+/// còn hoặc bị hỏng
+pub fn fill(large: &Large, key: &[u8], mut record: StoredChunkValue) -> Option<StoredChunkValue> {
+    if record.large {
+        record.value = value_of(&large.get(key).ok()??)?;
+        record.large = false;
+    }
+    Some(record)
+}
+
+/// Chuỗi Base64 lưu trong `chunks_large` hoặc blob, sau khi dựng lại nếu dùng mã xóa và
+/// giải mã nếu đã mã hóa
+pub fn value_of(bytes: &[u8]) -> Option<String> {
+    let unwrapped = crate::erasure::decode(bytes)?;
+    String::from_utf8(crate::cipher::decrypt(&unwrapped)?.into_owned()).ok()
+}
+
+/// Đọc record từ bytes trong shard, điền value từ `large_value` nếu là chunk lớn
+pub fn decode_with(bytes: &[u8], large_value: Option<&[u8]>) -> Option<StoredChunkValue> {
+    let mut record = crate::record::decode(bytes)?;
+    if record.large {
+        record.value = value_of(large_value?)?;
+        record.large = false;
+    }
+    Some(record)
+}
+
+/// Điền lại value của các chunk lớn trong danh sách chunk vừa quét. Chunk không lấy được
+/// value bị bỏ qua giống như value bị hỏng.
+pub fn resolve(chunks: Vec<Chunk>, large: &Large) -> Vec<Chunk> {
+    chunks
+        .into_iter()
+        .filter_map(|mut chunk| {
+            if !chunk.large {
+                return Some(chunk);
+            }
+            match large.get(chunk.key.as_bytes()).ok().flatten().and_then(|v| value_of(&v)) {
+                Some(value) => {
+                    chunk.value = value;
+                    chunk.large = false;
+                    Some(chunk)
+                }
+                _ => {
+                    tracing::error!("Không đọc được value của chunk lớn {}", chunk.key);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Các cặp (key, value) thuộc `file_key` trong một tree có key của chunk (ví dụ
+/// `chunk_expiry`)
+pub fn entries_of(large: &sled::Tree, file_key: &[u8]) -> sled::Result<Vec<(IVec, IVec)>> {
+    large
+        .scan_prefix(crate::key::prefix_raw(file_key))
+        .filter(|kv| kv.as_ref().map_or(true, |(k, _)| crate::key::chunk_hash_in_raw(k, file_key).is_some()))
+        .collect()
+}
@@ -0,0 +1,149 @@
+// ## GIỚI HẠN THỜI GIAN XỬ LÝ THEO TỪNG ROUTE ##
+//
+// Mỗi route HTTP được bọc một `TimeoutLayer` riêng: request chưa có response sau thời gian
+// của route thì bị hủy và client nhận `408` (`request_timeout`). Thời gian tính tới lúc
+// có header của response, kể cả thời gian đọc body request; phần body được stream sau đó
+// (ví dụ `/file/:fileKey/stream-download`) không bị giới hạn.
+//
+// Thời gian của một route lấy theo thứ tự: entry trong STORAGE_ROUTE_TIMEOUTS (danh sách
+// `route=ms` phân cách bằng dấu phẩy, route viết đúng như khi đăng ký, ví dụ
+// `/store=2000,/sync/full=0`), mặc định của route trong `DEFAULTS`, rồi
+// STORAGE_REQUEST_TIMEOUT_MS (mặc định 30000) cho mọi route còn lại. Giá trị 0 tắt giới
+// hạn của route. Mặc định:
+//
+// - 10 giây cho lần ghi một chunk và các lần đọc điểm: `/store`, `/file/:fileKey/chunk/...`,
+//   `/file/:fileKey/probe`, `/file/:fileKey/resolve`, `/verify/...`, `/challenge`, `/chunk/...`,
+//   `/chunks/exists`, `/files`, `/alias`, `/manifest/...`, `/health`, `/version`.
+// - 10 phút cho tải xuống cả file, upload nhị phân, đồng bộ và các thao tác quét toàn bộ
+//   database: `/file/:fileKey/stream`, `/file/:fileKey/stream-download`,
+//   `/file/:fileKey/multipart`, `/vfile/:fileKey`, `/store/raw/...`, `/store/stream/...`,
+//   `/b64/store/raw/...`, `/upload/:session/...`, `/replicate`, `/sync/full`,
+//   `/admin/consistency`, `/admin/gc`, `/admin/export`.
+// - Không giới hạn cho `/admin/import`, vì thời gian đọc body tăng theo kích thước database.
+//
+// Router được dựng một lần khi khởi động nên hai biến này chỉ đổi được khi khởi động lại.
+
+use axum::extract::Request;
+use axum::http::StatusCode;
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::MethodRouter;
+use axum::Router;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tower_http::timeout::TimeoutLayer;
+
+use crate::error::ApiError;
+use crate::AppState;
+
+const SHORT_MS: u64 = 10_000;
+const LONG_MS: u64 = 600_000;
+
+/// Thời gian mặc định (ms) của các route không dùng STORAGE_REQUEST_TIMEOUT_MS
+pub const DEFAULTS: &[(&str, u64)] = &[
+    ("/store", SHORT_MS),
+    ("/file/:fileKey/chunk/:chunkHash", SHORT_MS),
+    ("/file/:fileKey/probe", SHORT_MS),
+    ("/file/:fileKey/resolve", SHORT_MS),
+    ("/verify/:fileKey/:chunkHash", SHORT_MS),
+    ("/challenge", SHORT_MS),
+    ("/chunk/:fileKey/:chunkHash", SHORT_MS),
+    ("/chunk/raw/:fileKey/:chunkHash", SHORT_MS),
+    ("/chunk/:fileKey/:chunkHash/cas", SHORT_MS),
+    ("/chunks/exists", SHORT_MS),
+    ("/files", SHORT_MS),
+    ("/manifest/:fileKey", SHORT_MS),
+    ("/alias", SHORT_MS),
+    ("/health", SHORT_MS),
+    ("/version", SHORT_MS),
+    ("/file/:fileKey/stream", LONG_MS),
+    ("/file/:fileKey/stream-download", LONG_MS),
+    ("/file/:fileKey/multipart", LONG_MS),
+    ("/vfile/:fileKey", LONG_MS),
+    ("/store/raw/:fileKey/:chunkHash", LONG_MS),
+    ("/store/stream/:fileKey/:chunkHash", LONG_MS),
+    ("/b64/store/raw/:encodedKey/:chunkHash", LONG_MS),
+    ("/upload/:session/:index", LONG_MS),
+    ("/upload/:session/complete", LONG_MS),
+    ("/replicate", LONG_MS),
+    ("/sync/full", LONG_MS),
+    ("/admin/consistency", LONG_MS),
+    ("/admin/gc", LONG_MS),
+    ("/admin/export", LONG_MS),
+    ("/admin/import", 0),
+];
+
+/// Giá trị của STORAGE_ROUTE_TIMEOUTS: route -> ms
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RouteTimeouts(BTreeMap<String, u64>);
+
+impl std::str::FromStr for RouteTimeouts {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut routes = BTreeMap::new();
+        for item in s.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+            let (route, ms) = item.split_once('=').ok_or_else(|| format!("thiếu '=' trong {:?}", item))?;
+            let ms = ms.trim().parse().map_err(|_| format!("số ms không hợp lệ trong {:?}", item))?;
+            routes.insert(route.trim().to_string(), ms);
+        }
+        Ok(RouteTimeouts(routes))
+    }
+}
+
+impl RouteTimeouts {
+    /// Thời gian xử lý tối đa của `route`; `None` khi không giới hạn
+    pub fn for_route(&self, route: &str, default_ms: u64) -> Option<Duration> {
+        let ms = self.0.get(route).copied().unwrap_or_else(|| {
+            DEFAULTS
+                .iter()
+                .find(|(path, _)| *path == route)
+                .map_or(default_ms, |&(_, ms)| ms)
+        });
+        (ms > 0).then(|| Duration::from_millis(ms))
+    }
+}
+
+/// Router đang được dựng, tự bọc mỗi route với giới hạn thời gian của nó
+pub struct Routes<'a> {
+    router: Router<Arc<AppState>>,
+    timeouts: &'a RouteTimeouts,
+    default_ms: u64,
+}
+
+impl<'a> Routes<'a> {
+    pub fn new(timeouts: &'a RouteTimeouts, default_ms: u64) -> Self {
+        Routes {
+            router: Router::new(),
+            timeouts,
+            default_ms,
+        }
+    }
+
+    pub fn route(mut self, path: &str, handler: MethodRouter<Arc<AppState>>) -> Self {
+        let handler = match self.timeouts.for_route(path, self.default_ms) {
+            Some(limit) => handler
+                .layer(TimeoutLayer::with_status_code(StatusCode::REQUEST_TIMEOUT, limit))
+                .layer(middleware::from_fn(render_timeout)),
+            None => handler,
+        };
+        self.router = self.router.route(path, handler);
+        self
+    }
+
+    pub fn into_router(self) -> Router<Arc<AppState>> {
+        self.router
+    }
+}
+
+// `TimeoutLayer` trả `408` không có body; đổi thành lỗi JSON như các lỗi khác
+async fn render_timeout(request: Request, next: Next) -> Response {
+    let path = request.uri().path().to_string();
+    let response = next.run(request).await;
+    if response.status() == StatusCode::REQUEST_TIMEOUT && response.extensions().get::<ApiError>().is_none() {
+        tracing::warn!("⚠️  Request {} vượt quá thời gian xử lý cho phép", path);
+        return ApiError::Timeout.into_response();
+    }
+    response
+}
@@ -0,0 +1,41 @@
+// ## TLS VÀ HTTP/2 ##
+//
+// Mặc định HTTP server chạy plaintext. Khi đặt cả STORAGE_TLS_CERT (chuỗi chứng chỉ PEM,
+// chứng chỉ của node đứng đầu) và STORAGE_TLS_KEY (private key PEM), mọi kết nối phải bắt
+// tay TLS qua `rustls`; ALPN quảng bá `h2` trước `http/1.1` nên client hỗ trợ HTTP/2 sẽ
+// được multiplex nhiều request trên một kết nối. Chỉ đặt một trong hai biến là lỗi cấu
+// hình và server không khởi động. Chứng chỉ chỉ được đọc khi khởi động.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio_rustls::rustls::{
+    self,
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+};
+pub use tokio_rustls::TlsAcceptor;
+
+// Thời gian tối đa cho bắt tay TLS, tránh client không gửi gì giữ permit kết nối mãi
+pub(crate) const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Đọc chứng chỉ và private key PEM rồi tạo acceptor phục vụ cả HTTP/2 và HTTP/1.1
+pub fn acceptor(cert_path: &str, key_path: &str) -> Result<TlsAcceptor, String> {
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("không đọc được chứng chỉ {}: {}", cert_path, e))?;
+    if certs.is_empty() {
+        return Err(format!("file {} không chứa chứng chỉ nào", cert_path));
+    }
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .map_err(|e| format!("không đọc được private key {}: {}", key_path, e))?;
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut config = rustls::ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|e| format!("cấu hình TLS không hợp lệ: {}", e))?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| format!("chứng chỉ hoặc private key không hợp lệ: {}", e))?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
@@ -0,0 +1,226 @@
+// ## HẾT HẠN CHUNK THEO PREFIX (TTL) ##
+//
+// File JSON STORAGE_TTL_FILE khai báo thời gian sống của chunk theo prefix của fileKey,
+// ví dụ {"defaultSecs": 2592000, "prefixes": {"0xtenantA": 86400, "0xarchive": null}}.
+// Mỗi chunk dùng policy có prefix dài nhất khớp với fileKey của nó; không khớp prefix nào
+// thì dùng `defaultSecs`. Giá trị `null` (hoặc không đặt `defaultSecs`) nghĩa là không
+// bao giờ hết hạn. Tuổi của chunk tính từ `storedAt`; record cũ không có trường này không
+// bao giờ hết hạn.
+//
+// Task nền quét toàn bộ shard mỗi STORAGE_TTL_SWEEP_SECS giây và xóa các chunk đã hết
+// hạn cùng value trong `chunks_large`, entry index thứ tự, dung lượng quota và số chunk
+// của file. Chunk của file đã seal không bị xóa, và không quét khi node đang ở chế độ chỉ
+// đọc. File policy được đọc lại cùng cấu hình khi `POST /admin/reload`.
+//
+// Ngoài policy, client có thể đặt thời gian sống cho từng chunk qua trường `ttlSeconds` của
+// /store (và /store/batch). Thời điểm hết hạn được lưu trong record (`expiresAt`, cũng trả về
+// khi đọc chunk) và trong tree `chunk_expiry` (key chunk -> thời điểm hết hạn u64
+// big-endian), nên task nền chỉ phải duyệt các chunk có TTL thay vì quét mọi shard. Chunk đã
+// hết hạn mà task chưa kịp xóa được coi như không tồn tại khi đọc (`404`). Entry trỏ tới
+// chunk đã bị xóa hoặc được ghi lại không kèm TTL bị bỏ ở lần quét tới hạn của nó; entry đi
+// theo chunk khi đổi tên file. TTL đi kèm chunk khi replication sang peer.
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::quota::PendingWrite;
+use crate::{key, order, record, seal, AppState};
+
+// Nội dung file STORAGE_TTL_FILE
+#[derive(Deserialize, Default, Debug, Clone, PartialEq, Eq)]
+pub struct TtlPolicy {
+    // TTL (giây) cho fileKey không khớp prefix nào; không đặt thì không hết hạn
+    #[serde(rename = "defaultSecs", default)]
+    pub default_secs: Option<u64>,
+    // prefix của fileKey -> TTL (giây), `null` là không hết hạn
+    #[serde(default)]
+    pub prefixes: HashMap<String, Option<u64>>,
+}
+
+impl TtlPolicy {
+    /// Đọc file policy. Không có đường dẫn thì không chunk nào hết hạn.
+    pub fn load(path: Option<&str>) -> Result<Self, String> {
+        let Some(path) = path else {
+            return Ok(TtlPolicy::default());
+        };
+        let raw = std::fs::read_to_string(path).map_err(|e| format!("không đọc được {}: {}", path, e))?;
+        serde_json::from_str(&raw).map_err(|e| format!("file TTL {} không hợp lệ: {}", path, e))
+    }
+
+    /// TTL của chunk thuộc `file_key` theo prefix dài nhất khớp; `None` là không hết hạn
+    pub fn ttl_for(&self, file_key: &str) -> Option<u64> {
+        self.prefixes
+            .iter()
+            .filter(|(prefix, _)| file_key.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(self.default_secs, |(_, ttl)| *ttl)
+    }
+
+    /// Không có policy nào làm chunk hết hạn, nên không cần quét
+    pub fn is_empty(&self) -> bool {
+        self.default_secs.is_none() && self.prefixes.values().all(Option::is_none)
+    }
+}
+
+/// Task nền: định kỳ xóa các chunk đã hết hạn. Chu kỳ và policy được đọc lại mỗi vòng
+/// nên thay đổi qua `/admin/reload` có hiệu lực từ lần quét sau.
+pub async fn run(state: Arc<AppState>) {
+    loop {
+        let interval = state.config.load().ttl_sweep_secs;
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+        if (state.config.load().ttl.is_empty() && state.expiry.is_empty()) || state.is_read_only() {
+            continue;
+        }
+
+        let sweep_state = state.clone();
+        match tokio::task::spawn_blocking(move || sweep(&sweep_state, crate::unix_now())).await {
+            Ok(0) => {}
+            Ok(expired) => tracing::info!("-> Đã xóa {} chunk hết hạn", expired),
+            Err(e) => tracing::error!("Lỗi khi quét chunk hết hạn: {}", e),
+        }
+    }
+}
+
+/// Xóa các chunk đã hết hạn tại thời điểm `now` (unix giây) và trả về số chunk đã xóa.
+/// Chạy đồng bộ; quét toàn bộ shard khi có policy theo prefix.
+pub fn sweep(state: &AppState, now: u64) -> usize {
+    let policy = state.config.load().ttl.clone();
+    let mut expired = sweep_tracked(state, now);
+    if policy.is_empty() {
+        return expired;
+    }
+    for tree in &state.shards {
+        for (key_bytes, value_bytes) in tree.iter().flatten() {
+            let Some(parsed) = key::decode(&key_bytes) else {
+                continue;
+            };
+            let Some(ttl) = policy.ttl_for(parsed.file_key) else {
+                continue;
+            };
+            let Some(record) = record::decode(&value_bytes) else {
+                continue; // Value hỏng không có thời điểm lưu đáng tin
+            };
+            if record.stored_at.is_none_or(|stored_at| stored_at.saturating_add(ttl) > now) {
+                continue;
+            }
+            if seal::is_sealed(&state.sealed, parsed.file_key).unwrap_or(true) {
+                continue;
+            }
+            match remove_if_unchanged(state, tree, &key_bytes, &value_bytes, record.large, record.index) {
+                Ok(true) => expired += 1,
+                Ok(false) => {}
+                Err(e) => tracing::error!("Lỗi khi xóa chunk hết hạn {}: {}", String::from_utf8_lossy(&key_bytes), e),
+            }
+        }
+    }
+    expired
+}
+
+// Xóa các chunk có `ttlSeconds` đã hết hạn theo tree `chunk_expiry`
+fn sweep_tracked(state: &AppState, now: u64) -> usize {
+    let mut expired = 0;
+    for (key_bytes, at) in state.expiry.iter().flatten() {
+        if <[u8; 8]>::try_from(at.as_ref()).is_ok_and(|at| u64::from_be_bytes(at) > now) {
+            continue;
+        }
+        let forget = || {
+            // Chỉ bỏ entry nếu chưa bị lần ghi mới thay thế
+            if let Err(e) = state.expiry.compare_and_swap(&key_bytes, Some(&at), None::<&[u8]>) {
+                tracing::error!("Lỗi khi xóa entry hết hạn {}: {}", String::from_utf8_lossy(&key_bytes), e);
+            }
+        };
+        let Some(parsed) = key::decode(&key_bytes) else {
+            forget();
+            continue;
+        };
+        let tree = state.tree_for(parsed.file_key);
+        let Ok(Some(value_bytes)) = tree.get(&key_bytes) else {
+            forget();
+            continue;
+        };
+        let Some(record) = record::decode(&value_bytes).filter(|record| record.expires_at.is_some()) else {
+            forget();
+            continue;
+        };
+        if !record.is_expired(now) || seal::is_sealed(&state.sealed, parsed.file_key).unwrap_or(true) {
+            continue;
+        }
+        match remove_if_unchanged(state, tree, &key_bytes, &value_bytes, record.large, record.index) {
+            Ok(true) => {
+                forget();
+                expired += 1;
+            }
+            Ok(false) => {}
+            Err(e) => tracing::error!("Lỗi khi xóa chunk hết hạn {}: {}", String::from_utf8_lossy(&key_bytes), e),
+        }
+    }
+    expired
+}
+
+/// Ghi thời điểm hết hạn của các chunk vừa ghi kèm `ttlSeconds` vào tree `chunk_expiry`.
+/// Không flush: bên gọi flush cùng với các chunk vừa ghi.
+pub fn track(expiry: &sled::Tree, writes: &[PendingWrite]) -> sled::Result<()> {
+    let mut batch = sled::Batch::default();
+    let mut tracked = false;
+    for write in writes {
+        if let Some(expires_at) = write.expires_at {
+            batch.insert(write.key.as_slice(), &expires_at.to_be_bytes());
+            tracked = true;
+        }
+    }
+    if tracked {
+        expiry.apply_batch(batch)?;
+    }
+    Ok(())
+}
+
+/// Batch chuyển entry hết hạn của các chunk sang fileKey mới khi đổi tên file, để áp dụng
+/// cùng batch chuyển chunk
+pub fn rename_batch(expiry: &sled::Tree, file_key: &str, new_file_key: &str) -> sled::Result<sled::Batch> {
+    let mut batch = sled::Batch::default();
+    for (key_bytes, at) in crate::tier::entries_of(expiry, file_key.as_bytes())? {
+        if let Some(chunk_hash) = key::chunk_hash_in(&key_bytes, file_key) {
+            batch.insert(key::encode(new_file_key, chunk_hash).as_bytes(), at);
+            batch.remove(key_bytes);
+        }
+    }
+    Ok(batch)
+}
+
+/// Xóa một chunk nếu record vẫn đúng là `value` lúc đọc (chưa bị ghi lại), kèm các dữ
+/// liệu đi theo nó. Trả về `false` nếu chunk vừa bị ghi lại hoặc xóa bởi request khác.
+/// Cũng dùng cho `DELETE /chunk/:fileKey/:chunkHash`.
+pub fn remove_if_unchanged(
+    state: &AppState,
+    tree: &sled::Tree,
+    key_bytes: &[u8],
+    value: &sled::IVec,
+    large: bool,
+    index: Option<u64>,
+) -> sled::Result<bool> {
+    let Some(parsed) = key::decode(key_bytes) else {
+        return Ok(false);
+    };
+    let old_large = if large { state.large.get(key_bytes)? } else { None };
+    if tree.compare_and_swap(key_bytes, Some(value), None::<&[u8]>)?.is_err() {
+        return Ok(false);
+    }
+
+    let mut freed = value.len() as u64;
+    // Chỉ xóa value lớn nếu vẫn là value cũ, phòng khi /store vừa ghi value mới cho key này
+    if let Some(old_large) = old_large
+        && state.large.remove_if(key_bytes, &old_large)?
+    {
+        freed += old_large.len() as u64;
+    }
+    if let Some(order) = &state.order {
+        order.remove(order::entry_key(parsed.file_key, index, parsed.chunk_hash))?;
+    }
+    state.quotas.adjust(state.quotas.owner(parsed.file_key), -(freed as i64));
+    if state.files.chunk_removed(parsed.file_key, freed) {
+        state.notifier.deleted(parsed.file_key);
+    }
+    Ok(true)
+}
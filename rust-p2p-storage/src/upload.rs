@@ -0,0 +1,391 @@
+// ## UPLOAD NHIỀU PHẦN CÓ THỂ TIẾP TỤC ##
+//
+// Client upload file lớn qua một phiên thay vì tự chia chunk:
+//
+// - `POST /upload/init` (body JSON, mọi trường không bắt buộc: `fileKey`, `totalSize`,
+//   `mimeType`, `filename`) tạo phiên và trả về `sessionId`, `partSize` (kích thước mỗi phần,
+//   bằng STORAGE_SPLIT_CHUNK_SIZE lúc tạo phiên) và `expiresAt`.
+// - `PUT /upload/:session/:index` gửi phần thứ `index` (từ 0) dạng nhị phân. Mọi phần trừ
+//   phần cuối phải dài đúng `partSize`; phần dài hơn trả về `413`. Gửi lại một phần thay phần
+//   cũ, nên khi mất kết nối client chỉ cần hỏi `GET /upload/:session` (các phần đã nhận) rồi
+//   gửi tiếp những phần còn thiếu.
+// - `POST /upload/:session/complete` (body JSON không bắt buộc `{"sha256": "..."}`) kiểm tra
+//   các phần liên tục từ 0, đủ `totalSize` nếu đã khai báo và khớp hash SHA-256 của cả file
+//   nếu có gửi; sai thì trả về `400` (`hash_mismatch` khi sai hash) và phiên vẫn còn để sửa.
+//   Hợp lệ thì mỗi phần thành một chunk có chunkHash là SHA-256 của nó (như `/store/raw` với
+//   `?split=true`; phần trùng nội dung với phần trước trong file được thêm hậu tố `-<index>`)
+//   và index là số thứ tự phần, mọi chunk được ghi trong một transaction, rồi manifest của
+//   file (module manifest) được lưu cùng `totalSize`, `mimeType` và `filename`. Response
+//   `201` có `fileKey` cuối cùng: fileKey đã khai báo ở init, không có thì là SHA-256 của cả
+//   file (`0x...`). Phiên bị xóa sau khi hoàn tất.
+//
+// Phiên nằm trong tree `upload_sessions` (sessionId -> JSON), các phần trong tree
+// `upload_parts` (sessionId + index u64 big-endian -> byte). Phiên hết hạn sau
+// STORAGE_UPLOAD_SESSION_TTL_SECS giây kể từ lần gửi phần gần nhất; phiên hết hạn được coi
+// như không tồn tại (`404`) và task nền xóa nó cùng các phần mỗi STORAGE_UPLOAD_SWEEP_SECS
+// giây. Các phần chưa hoàn tất không tính vào quota hay giới hạn dung lượng của node; chỉ
+// khi hoàn tất chunk mới được kiểm tra như mọi lần ghi khác.
+
+use axum::body::Bytes;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::error::ApiError;
+use crate::{key, manifest, AppState, StoredChunkValue};
+
+// Độ dài sessionId (hex) và của key một phần trong `upload_parts`
+const SESSION_ID_LEN: usize = 32;
+
+/// Phiên upload đã lưu
+#[derive(Serialize, Deserialize, Clone)]
+struct Session {
+    #[serde(rename = "fileKey", default, skip_serializing_if = "Option::is_none")]
+    file_key: Option<String>,
+    #[serde(rename = "partSize")]
+    part_size: u64,
+    #[serde(rename = "totalSize", default, skip_serializing_if = "Option::is_none")]
+    total_size: Option<u64>,
+    #[serde(rename = "mimeType", default, skip_serializing_if = "Option::is_none")]
+    mime_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    filename: Option<String>,
+    // Thời điểm hết hạn (unix giây), lùi lại mỗi lần nhận một phần
+    #[serde(rename = "expiresAt")]
+    expires_at: u64,
+}
+
+// Payload cho POST /upload/init
+#[derive(Deserialize, Default)]
+pub(crate) struct InitPayload {
+    #[serde(rename = "fileKey")]
+    file_key: Option<String>,
+    #[serde(rename = "totalSize")]
+    total_size: Option<u64>,
+    #[serde(rename = "mimeType")]
+    mime_type: Option<String>,
+    filename: Option<String>,
+}
+
+// Payload cho POST /upload/:session/complete
+#[derive(Deserialize, Default)]
+struct CompletePayload {
+    // SHA-256 của cả file (hex, có hoặc không có `0x`)
+    sha256: Option<String>,
+}
+
+// Response của POST /upload/init và GET /upload/:session
+#[derive(Serialize)]
+pub(crate) struct SessionResponse {
+    #[serde(rename = "sessionId")]
+    session_id: String,
+    #[serde(flatten)]
+    session: Session,
+    // Index các phần đã nhận, chỉ có ở GET /upload/:session
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parts: Option<Vec<u64>>,
+    #[serde(rename = "receivedBytes", skip_serializing_if = "Option::is_none")]
+    received_bytes: Option<u64>,
+}
+
+// Response của PUT /upload/:session/:index
+#[derive(Serialize)]
+pub(crate) struct PartResponse {
+    #[serde(rename = "sessionId")]
+    session_id: String,
+    index: u64,
+    bytes: u64,
+    #[serde(rename = "expiresAt")]
+    expires_at: u64,
+}
+
+// Response của POST /upload/:session/complete
+#[derive(Serialize)]
+pub(crate) struct CompleteResponse {
+    #[serde(rename = "fileKey")]
+    file_key: String,
+    #[serde(rename = "chunkHashes")]
+    chunk_hashes: Vec<String>,
+    #[serde(rename = "totalSize")]
+    total_size: u64,
+}
+
+fn db_error(e: sled::Error) -> ApiError {
+    tracing::error!("Lỗi khi truy cập phiên upload: {}", e);
+    ApiError::Internal
+}
+
+fn part_key(session_id: &str, index: u64) -> Vec<u8> {
+    let mut key = session_id.as_bytes().to_vec();
+    key.extend_from_slice(&index.to_be_bytes());
+    key
+}
+
+/// Phiên `session_id` còn hiệu lực; `404` nếu không có hoặc đã hết hạn
+fn load(state: &AppState, session_id: &str) -> Result<Session, ApiError> {
+    if session_id.len() != SESSION_ID_LEN {
+        return Err(ApiError::NotFound);
+    }
+    let session = state
+        .uploads
+        .get(session_id)
+        .map_err(db_error)?
+        .and_then(|bytes| serde_json::from_slice::<Session>(&bytes).ok())
+        .ok_or(ApiError::NotFound)?;
+    if session.expires_at <= crate::unix_now() {
+        return Err(ApiError::NotFound);
+    }
+    Ok(session)
+}
+
+fn save(state: &AppState, session_id: &str, session: &Session) -> Result<(), ApiError> {
+    let value = serde_json::to_vec(session).expect("phiên upload luôn serialize được");
+    state.uploads.insert(session_id, value).map_err(db_error)?;
+    Ok(())
+}
+
+// Các phần đã nhận của phiên theo thứ tự index
+fn parts(state: &AppState, session_id: &str) -> Result<Vec<(u64, sled::IVec)>, ApiError> {
+    state
+        .upload_parts
+        .scan_prefix(session_id)
+        .map(|entry| {
+            let (key, value) = entry.map_err(db_error)?;
+            let index = key[SESSION_ID_LEN..].try_into().map(u64::from_be_bytes).map_err(|_| ApiError::Internal)?;
+            Ok((index, value))
+        })
+        .collect()
+}
+
+// Xóa phiên cùng mọi phần của nó
+fn remove(state: &AppState, session_id: &str) -> sled::Result<()> {
+    let mut batch = sled::Batch::default();
+    for key in state.upload_parts.scan_prefix(session_id).keys() {
+        batch.remove(key?);
+    }
+    state.upload_parts.apply_batch(batch)?;
+    state.uploads.remove(session_id)?;
+    Ok(())
+}
+
+/// Xóa các phiên đã hết hạn tại thời điểm `now` cùng các phần của chúng, trả về số phiên
+/// đã xóa. Chạy đồng bộ.
+pub fn sweep(state: &AppState, now: u64) -> sled::Result<usize> {
+    let mut expired = Vec::new();
+    for entry in state.uploads.iter() {
+        let (id, value) = entry?;
+        let session = serde_json::from_slice::<Session>(&value).ok();
+        if session.is_none_or(|session| session.expires_at <= now) {
+            expired.push(String::from_utf8_lossy(&id).into_owned());
+        }
+    }
+    for id in &expired {
+        remove(state, id)?;
+    }
+    Ok(expired.len())
+}
+
+/// Task nền xóa các phiên upload hết hạn theo chu kỳ STORAGE_UPLOAD_SWEEP_SECS
+pub async fn run(state: Arc<AppState>) {
+    loop {
+        let interval = state.config.load().upload_sweep_secs;
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+        if state.is_read_only() {
+            continue;
+        }
+
+        let sweep_state = state.clone();
+        match tokio::task::spawn_blocking(move || sweep(&sweep_state, crate::unix_now())).await {
+            Ok(Ok(0)) => {}
+            Ok(Ok(expired)) => tracing::info!("-> Đã xóa {} phiên upload hết hạn", expired),
+            Ok(Err(e)) => tracing::error!("Lỗi khi xóa phiên upload hết hạn: {}", e),
+            Err(e) => tracing::error!("Lỗi khi xóa phiên upload hết hạn: {}", e),
+        }
+    }
+}
+
+/// Handler TẠO phiên upload. fileKey (nếu có) không được rỗng và file không được đang seal.
+pub(crate) async fn init(
+    State(state): State<Arc<AppState>>,
+    payload: Option<Json<InitPayload>>,
+) -> Result<(StatusCode, Json<SessionResponse>), ApiError> {
+    state.ensure_writable()?;
+    let payload = payload.map(|Json(payload)| payload).unwrap_or_default();
+    if let Some(file_key) = &payload.file_key {
+        if key::component_error(file_key, state.config.load().max_key_bytes).is_some() {
+            return Err(ApiError::InvalidKey);
+        }
+        state.ensure_unsealed(file_key)?;
+    }
+    if payload.mime_type.as_deref().is_some_and(str::is_empty) {
+        return Err(ApiError::BadRequest);
+    }
+
+    let mut id = [0u8; SESSION_ID_LEN / 2];
+    getrandom::getrandom(&mut id).expect("không lấy được byte ngẫu nhiên từ hệ điều hành");
+    let session_id = hex::encode(id);
+    let config = state.config.load();
+    let session = Session {
+        file_key: payload.file_key,
+        part_size: config.split_chunk_size as u64,
+        total_size: payload.total_size,
+        mime_type: payload.mime_type,
+        filename: payload.filename,
+        expires_at: crate::unix_now() + config.upload_session_ttl_secs,
+    };
+    save(&state, &session_id, &session)?;
+    tracing::info!("-> Đã tạo phiên upload {} (phần {} byte)", session_id, session.part_size);
+
+    let response = SessionResponse {
+        session_id,
+        session,
+        parts: None,
+        received_bytes: None,
+    };
+    Ok((StatusCode::CREATED, Json(response)))
+}
+
+/// Handler trả về thông tin phiên cùng các phần đã nhận, để client biết cần gửi lại phần nào
+pub(crate) async fn status(
+    State(state): State<Arc<AppState>>,
+    Path(session_id): Path<String>,
+) -> Result<Json<SessionResponse>, ApiError> {
+    let session = load(&state, &session_id)?;
+    let parts = parts(&state, &session_id)?;
+    let received_bytes = parts.iter().map(|(_, data)| data.len() as u64).sum();
+    Ok(Json(SessionResponse {
+        session_id,
+        session,
+        parts: Some(parts.into_iter().map(|(index, _)| index).collect()),
+        received_bytes: Some(received_bytes),
+    }))
+}
+
+/// Handler NHẬN một phần của phiên. Phần rỗng hoặc vượt `totalSize` đã khai báo trả về
+/// `400`, dài hơn `partSize` trả về `413`.
+pub(crate) async fn put_part(
+    State(state): State<Arc<AppState>>,
+    Path((session_id, index)): Path<(String, u64)>,
+    body: Bytes,
+) -> Result<Json<PartResponse>, ApiError> {
+    state.ensure_writable()?;
+    let mut session = load(&state, &session_id)?;
+    if body.len() as u64 > session.part_size {
+        return Err(ApiError::PayloadTooLarge);
+    }
+    let end = index.checked_mul(session.part_size).and_then(|start| start.checked_add(body.len() as u64));
+    if body.is_empty() || end.is_none() || session.total_size.is_some_and(|total| end > Some(total)) {
+        return Err(ApiError::BadRequest);
+    }
+
+    state.upload_parts.insert(part_key(&session_id, index), body.as_ref()).map_err(db_error)?;
+    session.expires_at = crate::unix_now() + state.config.load().upload_session_ttl_secs;
+    save(&state, &session_id, &session)?;
+
+    Ok(Json(PartResponse {
+        session_id,
+        index,
+        bytes: body.len() as u64,
+        expires_at: session.expires_at,
+    }))
+}
+
+/// Handler HOÀN TẤT phiên: kiểm tra các phần, lưu chúng thành chunk cùng manifest của file
+/// rồi xóa phiên. Phần bị thiếu hoặc sai kích thước trả về `400`.
+pub(crate) async fn complete(
+    State(state): State<Arc<AppState>>,
+    Path(session_id): Path<String>,
+    body: Bytes,
+) -> Result<(StatusCode, Json<CompleteResponse>), ApiError> {
+    state.ensure_writable()?;
+    let payload: CompletePayload = if body.is_empty() {
+        CompletePayload::default()
+    } else {
+        serde_json::from_slice(&body).map_err(|_| ApiError::BadRequest)?
+    };
+    let session = load(&state, &session_id)?;
+    let parts = parts(&state, &session_id)?;
+
+    // Các phần phải liên tục từ 0 và mọi phần trừ phần cuối dài đúng `partSize`
+    let count = parts.len();
+    let complete = count > 0
+        && parts.iter().enumerate().all(|(at, (index, data))| {
+            *index == at as u64 && (at + 1 == count || data.len() as u64 == session.part_size)
+        });
+    if !complete {
+        tracing::warn!("Phiên upload {} thiếu phần hoặc có phần sai kích thước", session_id);
+        return Err(ApiError::BadRequest);
+    }
+    let total_size: u64 = parts.iter().map(|(_, data)| data.len() as u64).sum();
+    if session.total_size.is_some_and(|declared| declared != total_size) {
+        tracing::warn!("Phiên upload {} nhận {} byte, khác totalSize đã khai báo", session_id, total_size);
+        return Err(ApiError::BadRequest);
+    }
+    let mut hasher = Sha256::new();
+    for (_, data) in &parts {
+        hasher.update(data);
+    }
+    let file_hash = hex::encode(hasher.finalize());
+    if let Some(expected) = &payload.sha256
+        && !expected.trim().trim_start_matches("0x").eq_ignore_ascii_case(&file_hash)
+    {
+        tracing::warn!("File của phiên upload {} có hash 0x{}, khác hash mong đợi {}", session_id, file_hash, expected);
+        return Err(ApiError::HashMismatch);
+    }
+
+    let file_key = session.file_key.clone().unwrap_or_else(|| format!("0x{}", file_hash));
+    state.ensure_unsealed(&file_key)?;
+    let head = &parts[0].1;
+    state.ensure_content_type_allowed(session.mime_type.as_deref(), Some(0), &head[..head.len().min(crate::mime::SNIFF_LEN)])?;
+    let _permit = state.acquire_file(&file_key)?;
+
+    let mut writes = Vec::with_capacity(count);
+    let mut chunk_hashes = Vec::with_capacity(count);
+    let mut seen = HashSet::new();
+    for (index, data) in &parts {
+        let mut chunk_hash = crate::content_hash(data);
+        if !seen.insert(chunk_hash.clone()) {
+            chunk_hash = format!("{}-{}", chunk_hash, index);
+        }
+        state.ensure_valid_key(&file_key, &chunk_hash)?;
+        let db_value = StoredChunkValue::raw(STANDARD.encode(data), Some(*index));
+        writes.push(state.pending_write(&file_key, &chunk_hash, db_value)?);
+        chunk_hashes.push(chunk_hash);
+    }
+    drop(parts);
+    state.ensure_capacity(&writes)?;
+
+    let manifest = manifest::Manifest {
+        chunks: chunk_hashes.clone(),
+        total_size: Some(total_size),
+        mime_type: session.mime_type,
+        filename: session.filename,
+    };
+    let write_state = state.clone();
+    let write_key = file_key.clone();
+    crate::blocking(move || {
+        write_state.apply_writes(&writes)?;
+        manifest::put(&write_state.manifests, &write_key, &manifest)
+            .and_then(|_| remove(&write_state, &session_id))
+            .map_err(|e| {
+                tracing::error!("Lỗi khi hoàn tất phiên upload {}: {}", session_id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        write_state.finish_writes(&writes)
+    })
+    .await?;
+    tracing::info!("-> Đã hoàn tất upload {} byte thành {} chunks cho fileKey: {}", total_size, count, file_key);
+
+    let response = CompleteResponse {
+        file_key,
+        chunk_hashes,
+        total_size,
+    };
+    Ok((StatusCode::CREATED, Json(response)))
+}
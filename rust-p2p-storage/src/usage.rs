@@ -0,0 +1,270 @@
+// ## GIỚI HẠN DUNG LƯỢNG CỦA NODE ##
+//
+// Ngoài quota theo tenant (module quota), node có thể giới hạn tổng số byte đang lưu
+// (STORAGE_MAX_TOTAL_BYTES) và số chunk của mỗi fileKey (STORAGE_MAX_CHUNKS_PER_FILE). Lần
+// ghi qua `/store`, `/store/batch`, `/store/raw`, `/b64/store/raw`, `/store/stream` và
+// compare-and-swap làm vượt một trong hai giới hạn trả về `507` (`storage_limit_exceeded`)
+// và không chunk nào của request được ghi.
+// Khác với khi đĩa đầy (module disk), node không chuyển sang chỉ đọc: ghi đè chunk đã có mà
+// không làm tăng dung lượng, hay ghi sau khi đã xóa bớt dữ liệu, vẫn thành công.
+//
+// STORAGE_MAX_TOTAL_BYTES là giới hạn của cả node nên được so với tổng số byte của mọi
+// namespace; trong một namespace có `quotaBytes`, số byte của riêng namespace đó cũng không
+// được vượt quota này (xem module namespace).
+//
+// Giới hạn được kiểm tra trước khi ghi, dựa trên các bộ đếm `quota_usage` và `file_chunks`
+// vốn được cập nhật cùng transaction với chunk. Vì vậy các request ghi đồng thời (hoặc chunk
+// còn nằm trong bộ đệm ghi) có thể làm tổng vượt giới hạn một chút.
+//
+// Task nền cộng dồn các bộ đếm đó mỗi STORAGE_USAGE_REFRESH_SECS giây và lưu kết quả vào
+// tree `storage_usage` (`bytes`, `chunks`, `files`, `updatedAt`, cùng số byte của blob dùng
+// chung và tổng số byte các chunk trỏ tới chúng, xem module dedup); `GET /stats` trả về bản
+// này kèm các giới hạn, số byte còn được ghi và tỉ lệ khử trùng lặp, để không phải quét bộ
+// đếm của mọi file mỗi lần được hỏi.
+
+use axum::extract::State;
+use axum::Json;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::error::ApiError;
+use crate::quota::PendingWrite;
+use crate::AppState;
+
+/// Dung lượng đang dùng của node tại một thời điểm
+#[derive(Serialize, Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct Usage {
+    #[serde(rename = "bytesUsed")]
+    pub bytes: u64,
+    pub chunks: u64,
+    pub files: u64,
+    // Thời điểm cộng dồn (unix giây)
+    #[serde(rename = "updatedAt")]
+    pub updated_at: u64,
+    // Số byte các blob dùng chung thực sự chiếm và tổng số byte của các chunk trỏ tới chúng
+    // (trả về trong `dedup` của /stats)
+    #[serde(skip)]
+    pub blob_bytes: u64,
+    #[serde(skip)]
+    pub blob_referenced_bytes: u64,
+}
+
+// Hiệu quả của khử trùng lặp trong response của GET /stats
+#[derive(Serialize)]
+struct DedupStats {
+    #[serde(rename = "storedBytes")]
+    stored_bytes: u64,
+    #[serde(rename = "referencedBytes")]
+    referenced_bytes: u64,
+    #[serde(rename = "bytesSaved")]
+    bytes_saved: u64,
+    // referencedBytes / storedBytes; 1 khi chưa có blob dùng chung nào
+    ratio: f64,
+}
+
+impl DedupStats {
+    fn of(usage: &Usage) -> Self {
+        let ratio = match usage.blob_bytes {
+            0 => 1.0,
+            stored => usage.blob_referenced_bytes as f64 / stored as f64,
+        };
+        DedupStats {
+            stored_bytes: usage.blob_bytes,
+            referenced_bytes: usage.blob_referenced_bytes,
+            bytes_saved: usage.blob_referenced_bytes.saturating_sub(usage.blob_bytes),
+            ratio,
+        }
+    }
+}
+
+// Response của GET /stats
+#[derive(Serialize)]
+pub(crate) struct StatsResponse {
+    #[serde(flatten)]
+    usage: Usage,
+    #[serde(rename = "maxTotalBytes", skip_serializing_if = "Option::is_none")]
+    max_total_bytes: Option<u64>,
+    #[serde(rename = "remainingBytes", skip_serializing_if = "Option::is_none")]
+    remaining_bytes: Option<u64>,
+    #[serde(rename = "maxChunksPerFile", skip_serializing_if = "Option::is_none")]
+    max_chunks_per_file: Option<u64>,
+    dedup: DedupStats,
+    durability: crate::flush::Progress,
+}
+
+const FIELDS: [&str; 6] = ["bytes", "chunks", "files", "updatedAt", "blobBytes", "blobReferencedBytes"];
+
+/// Cộng dồn dung lượng hiện tại từ các bộ đếm và ghi vào tree `storage_usage`
+pub fn refresh(state: &AppState) -> sled::Result<Usage> {
+    let chunks = state
+        .files
+        .tree
+        .iter()
+        .values()
+        .try_fold(0u64, |sum, v| Ok::<_, sled::Error>(sum + crate::cardinality::decode(&v?)))?;
+    let (mut blob_bytes, mut blob_referenced_bytes) = (0u64, 0u64);
+    for entry in state.large.blobs.chunks.iter().values() {
+        let entry = entry?;
+        let len = crate::dedup::data_of(&entry).len() as u64;
+        blob_bytes += len;
+        blob_referenced_bytes += len * crate::dedup::count_of(&entry);
+    }
+    let usage = Usage {
+        bytes: state.quotas.total()?,
+        chunks,
+        files: state.files.distinct(),
+        updated_at: crate::unix_now(),
+        blob_bytes,
+        blob_referenced_bytes,
+    };
+    let values = [usage.bytes, usage.chunks, usage.files, usage.updated_at, usage.blob_bytes, usage.blob_referenced_bytes];
+    let mut batch = sled::Batch::default();
+    for (field, value) in FIELDS.iter().zip(values) {
+        batch.insert(*field, &value.to_be_bytes());
+    }
+    state.node_usage.apply_batch(batch)?;
+    Ok(usage)
+}
+
+/// Bản dung lượng đã lưu lần gần nhất, `None` nếu chưa cộng dồn lần nào
+pub fn current(tree: &sled::Tree) -> sled::Result<Option<Usage>> {
+    let mut values = [0u64; FIELDS.len()];
+    for (field, value) in FIELDS.iter().zip(values.iter_mut()) {
+        let Some(bytes) = tree.get(field)? else {
+            return Ok(None);
+        };
+        *value = crate::cardinality::decode(&bytes);
+    }
+    let [bytes, chunks, files, updated_at, blob_bytes, blob_referenced_bytes] = values;
+    Ok(Some(Usage {
+        bytes,
+        chunks,
+        files,
+        updated_at,
+        blob_bytes,
+        blob_referenced_bytes,
+    }))
+}
+
+/// Task nền cập nhật tree `storage_usage` theo chu kỳ STORAGE_USAGE_REFRESH_SECS
+pub async fn run(state: Arc<AppState>) {
+    loop {
+        let interval = state.config.load().usage_refresh_secs;
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+
+        let refresh_state = state.clone();
+        match tokio::task::spawn_blocking(move || refresh(&refresh_state)).await {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => tracing::error!("Lỗi khi cập nhật dung lượng của node: {}", e),
+            Err(e) => tracing::error!("Lỗi khi cập nhật dung lượng của node: {}", e),
+        }
+    }
+}
+
+impl AppState {
+    /// Trả về `507` nếu ghi `writes` làm vượt STORAGE_MAX_TOTAL_BYTES (tính cho cả node),
+    /// `quotaBytes` của namespace hoặc STORAGE_MAX_CHUNKS_PER_FILE. Chunk ghi đè key đã có chỉ
+    /// tính phần dung lượng tăng thêm và không tính là chunk mới.
+    pub(crate) fn ensure_capacity(&self, writes: &[PendingWrite]) -> Result<(), ApiError> {
+        let config = self.config.load();
+        let namespace_quota = self.namespaces.quota_bytes();
+        if config.max_total_bytes.is_none() && config.max_chunks_per_file.is_none() && namespace_quota.is_none() {
+            return Ok(());
+        }
+        let db_error = |e: sled::Error| {
+            tracing::error!("Lỗi khi đọc dung lượng đang dùng: {}", e);
+            ApiError::Internal
+        };
+
+        let (mut added, mut removed) = (0u64, 0u64);
+        // fileKey -> số chunk mới
+        let mut new_chunks: HashMap<&[u8], u64> = HashMap::new();
+        for write in writes {
+            added += (write.value.len() + write.large.as_ref().map_or(0, Vec::len)) as u64;
+            match self.shards[write.shard].get(&write.key).map_err(db_error)? {
+                Some(old) => {
+                    let old_large = self.large.get(&write.key).map_err(db_error)?;
+                    removed += (old.len() + old_large.map_or(0, |v| v.len())) as u64;
+                }
+                None => {
+                    if let Some((file_key, _)) = crate::key::decode_raw(&write.key) {
+                        *new_chunks.entry(file_key).or_default() += 1;
+                    }
+                }
+            }
+        }
+
+        if let Some(limit) = config.max_chunks_per_file {
+            for (file_key, added) in new_chunks {
+                let chunks = self.files.tree.get(file_key).map_err(db_error)?;
+                let chunks = chunks.map_or(0, |v| crate::cardinality::decode(&v));
+                if chunks + added > limit {
+                    tracing::warn!(
+                        "fileKey {} đã có {} chunk, vượt giới hạn {} chunk mỗi file",
+                        String::from_utf8_lossy(file_key),
+                        chunks,
+                        limit
+                    );
+                    return Err(ApiError::StorageLimit);
+                }
+            }
+        }
+        if added <= removed {
+            return Ok(());
+        }
+        if let Some(limit) = namespace_quota {
+            let used = self.quotas.total().map_err(db_error)?;
+            if used + added - removed > limit {
+                tracing::warn!("Namespace đang lưu {} byte, ghi thêm {} byte sẽ vượt quota {}", used, added - removed, limit);
+                return Err(ApiError::StorageLimit);
+            }
+        }
+        if let Some(limit) = config.max_total_bytes {
+            let used = crate::namespace::node_bytes(&self.db).map_err(db_error)?;
+            if used + added - removed > limit {
+                tracing::warn!("Node đang lưu {} byte, ghi thêm {} byte sẽ vượt giới hạn {}", used, added - removed, limit);
+                return Err(ApiError::StorageLimit);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Handler trả về dung lượng đang dùng của node (số byte, số chunk, số file) theo lần cộng
+/// dồn gần nhất, cùng các giới hạn, số byte còn được ghi, hiệu quả khử trùng lặp (`dedup`,
+/// xem module dedup) và tiến độ flush (`durability`, xem module flush). Trong namespace có `quotaBytes`, `maxTotalBytes` là quota đó và
+/// `remainingBytes` không vượt quá phần còn lại của cả node.
+pub(crate) async fn stats(State(state): State<Arc<AppState>>) -> Result<Json<StatsResponse>, ApiError> {
+    let stats_state = state.clone();
+    let (usage, node_bytes) = tokio::task::spawn_blocking(move || {
+        let usage = match current(&stats_state.node_usage)? {
+            Some(usage) => usage,
+            None => refresh(&stats_state)?,
+        };
+        let node_bytes = crate::namespace::node_bytes(&stats_state.db)?;
+        Ok::<_, sled::Error>((usage, node_bytes))
+    })
+    .await
+    .map_err(|_| ApiError::Internal)?
+    .map_err(|e| {
+        tracing::error!("Lỗi khi đọc dung lượng của node: {}", e);
+        ApiError::Internal
+    })?;
+
+    let config = state.config.load();
+    let quota = state.namespaces.quota_bytes();
+    let remaining = [
+        quota.map(|limit| limit.saturating_sub(usage.bytes)),
+        config.max_total_bytes.map(|limit| limit.saturating_sub(node_bytes)),
+    ];
+    Ok(Json(StatsResponse {
+        usage,
+        max_total_bytes: quota.or(config.max_total_bytes),
+        remaining_bytes: remaining.into_iter().flatten().min(),
+        max_chunks_per_file: config.max_chunks_per_file,
+        dedup: DedupStats::of(&usage),
+        durability: state.durability(),
+    }))
+}
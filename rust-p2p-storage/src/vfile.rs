@@ -0,0 +1,157 @@
+// ## ĐỌC FILE THEO KHOẢNG BYTE (VFILE) ##
+//
+// `GET /vfile/:fileKey?offset=&length=` trả về đúng đoạn byte `[offset, offset + length)`
+// của file ghép từ các chunk theo thứ tự index, để client làm việc với offset trong file
+// thay vì chunkHash. Không có `length` thì đọc tới hết file.
+//
+// Bảng ánh xạ offset -> chunk được dựng từ thứ tự chunk (index thứ tự nếu bật, ngược lại
+// quét shard) và độ dài dữ liệu gốc mà mỗi record lưu kèm lúc ghi (trường `size`). Chỉ
+// những chunk giao với khoảng được yêu cầu mới bị giải nén; record cũ không có `size`
+// phải giải mã cả chunk để biết độ dài. Khoảng nằm ngoài file trả về `416`.
+// `GET /file/:fileKey/manifest` trả về chính bảng này (không kèm dữ liệu), còn
+// `GET /file/:fileKey/stream` dùng nó để phục vụ header `Range` và tính `ETag` của file.
+
+use crate::record;
+
+/// Một chunk trong bảng ánh xạ: dữ liệu gốc của nó chiếm `[offset, offset + len)` của file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Extent {
+    pub offset: u64,
+    pub len: u64,
+    pub index: Option<u64>,
+    pub db_key: String,
+}
+
+/// Dựng bảng ánh xạ offset của file từ kế hoạch đọc (chunk theo thứ tự index). Chunk đã
+/// bị xóa, ghi lại với index khác hoặc hết hạn kể từ lúc lập kế hoạch thì bị bỏ qua.
+pub fn layout(tree: &sled::Tree, large: &crate::tier::Large, plan: Vec<(Option<u64>, String)>) -> std::io::Result<Vec<Extent>> {
+    let mut extents = Vec::with_capacity(plan.len());
+    let mut offset = 0;
+    let now = crate::unix_now();
+    for (index, db_key) in plan {
+        let Some(value_bytes) = tree.get(db_key.as_bytes()).map_err(std::io::Error::other)? else {
+            continue;
+        };
+        let Some(record) = record::decode(&value_bytes) else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("value bị hỏng: {}", db_key),
+            ));
+        };
+        if record.index != index || record.is_expired(now) {
+            continue;
+        }
+        let len = match record.size {
+            Some(size) => size,
+            None => match crate::fetch_stream_chunk(tree, large, &db_key, index)? {
+                Some((bytes, _)) => bytes.len() as u64,
+                None => continue,
+            },
+        };
+        extents.push(Extent { offset, len, index, db_key });
+        offset += len;
+    }
+    Ok(extents)
+}
+
+/// Tổng kích thước file theo bảng ánh xạ
+pub fn total_len(extents: &[Extent]) -> u64 {
+    extents.last().map_or(0, |last| last.offset + last.len)
+}
+
+/// Đọc đoạn `[offset, offset + len)` (phải nằm trong file) bằng cách cắt các chunk giao
+/// với nó. Trả về `None` nếu một chunk cần đọc vừa bị xóa hoặc thay đổi độ dài.
+pub fn read_range(
+    tree: &sled::Tree,
+    large: &crate::tier::Large,
+    extents: &[Extent],
+    offset: u64,
+    len: u64,
+) -> std::io::Result<Option<Vec<u8>>> {
+    let end = offset + len;
+    let mut out = Vec::with_capacity(len as usize);
+    // Chunk đầu tiên kết thúc sau `offset`
+    let first = extents.partition_point(|extent| extent.offset + extent.len <= offset);
+    for extent in extents[first..].iter().take_while(|extent| extent.offset < end) {
+        let Some((bytes, _)) = crate::fetch_stream_chunk(tree, large, &extent.db_key, extent.index)? else {
+            return Ok(None);
+        };
+        if bytes.len() as u64 != extent.len {
+            return Ok(None);
+        }
+        let from = offset.saturating_sub(extent.offset) as usize;
+        let to = (end - extent.offset).min(extent.len) as usize;
+        out.extend_from_slice(&bytes[from..to]);
+    }
+    Ok(Some(out))
+}
+
+/// ETag mạnh của file: sha256 của chunkHash, index và độ dài từng chunk theo thứ tự
+/// trong bảng ánh xạ, nên đổi khi file có thêm/bớt chunk, đổi thứ tự hoặc độ dài chunk.
+pub fn etag(extents: &[Extent]) -> String {
+    use sha2::{Digest, Sha256};
+    let mut hasher = Sha256::new();
+    for extent in extents {
+        let chunk_hash = crate::key::decode_raw(extent.db_key.as_bytes()).map_or("", |(_, chunk_hash)| chunk_hash);
+        hasher.update(chunk_hash.as_bytes());
+        hasher.update(extent.index.map_or(u64::MAX, |index| index).to_be_bytes());
+        hasher.update(extent.len.to_be_bytes());
+    }
+    format!("\"{}\"", hex::encode(&hasher.finalize()[..16]))
+}
+
+/// Header `If-None-Match` có khớp `etag` không (so sánh yếu, chấp nhận `*` và danh sách)
+pub fn none_match(header: &str, etag: &str) -> bool {
+    header
+        .split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag)
+}
+
+/// Kết quả đọc header `Range` với file dài `total` byte
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteRange {
+    /// Không có khoảng dùng được (nhiều khoảng, đơn vị lạ, cú pháp sai): trả cả file
+    Full,
+    /// Khoảng `[start, end)` nằm trong file
+    Partial { start: u64, end: u64 },
+    /// Khoảng hợp lệ nhưng không giao với file: `416`
+    Unsatisfiable,
+}
+
+/// Đọc header `Range: bytes=a-b`, `bytes=a-` hoặc `bytes=-n`. Chỉ hỗ trợ một khoảng;
+/// `b` vượt quá file thì bị cắt về cuối file như RFC 9110.
+pub fn parse_range(header: &str, total: u64) -> ByteRange {
+    let Some(spec) = header.trim().strip_prefix("bytes=") else {
+        return ByteRange::Full;
+    };
+    let Some((first, last)) = spec.trim().split_once('-') else {
+        return ByteRange::Full;
+    };
+    if spec.contains(',') {
+        return ByteRange::Full;
+    }
+    let parse = |s: &str| if s.is_empty() { Ok(None) } else { s.parse::<u64>().map(Some) };
+    let (Ok(first), Ok(last)) = (parse(first.trim()), parse(last.trim())) else {
+        return ByteRange::Full;
+    };
+    match (first, last) {
+        (Some(start), last) => {
+            if last.is_some_and(|last| last < start) {
+                return ByteRange::Full;
+            }
+            if start >= total {
+                return ByteRange::Unsatisfiable;
+            }
+            let end = last.map_or(total, |last| last.saturating_add(1).min(total));
+            ByteRange::Partial { start, end }
+        }
+        (None, Some(suffix)) => {
+            if suffix == 0 || total == 0 {
+                return ByteRange::Unsatisfiable;
+            }
+            ByteRange::Partial { start: total.saturating_sub(suffix), end: total }
+        }
+        (None, None) => ByteRange::Full,
+    }
+}
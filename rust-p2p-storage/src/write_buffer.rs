@@ -0,0 +1,117 @@
+// ## BỘ ĐỆM GHI BẤT ĐỒNG BỘ (STORAGE_WRITE_BUFFER) ##
+//
+// Mặc định `POST /store` chỉ trả về sau khi chunk đã được ghi và flush xuống đĩa. Khi
+// STORAGE_WRITE_BUFFER > 0, handler chỉ kiểm tra request, đưa lần ghi vào bộ đệm trong bộ
+// nhớ rồi trả về `200` ngay. Task nền cứ mỗi STORAGE_WRITE_BUFFER_FLUSH_MS lấy toàn bộ bộ
+// đệm, ghi xuống sled trong một transaction rồi flush một lần. Khi bộ đệm đầy
+// (STORAGE_WRITE_BUFFER chunk), request đẩy nó đầy phải chờ bộ đệm được ghi xong. Độ trễ
+// ghi giảm mạnh, đổi lại độ bền dữ liệu:
+//
+// - CỬA SỔ MẤT DỮ LIỆU: nếu process chết (crash, OOM, SIGKILL, mất điện) thì các chunk
+//   còn trong bộ đệm mất luôn, dù client đã nhận `200`. Cửa sổ này tối đa khoảng
+//   STORAGE_WRITE_BUFFER_FLUSH_MS cộng thời gian một lần ghi + fsync, và tối đa
+//   STORAGE_WRITE_BUFFER chunk. Khi tắt bình thường (SIGINT/SIGTERM), bộ đệm được ghi hết
+//   trước lần flush cuối (xem module shutdown).
+// - Lỗi chỉ phát hiện được lúc ghi (vượt quota `403`, key đã có chunk khác hoặc xung đột
+//   chế độ ghi `409`) không còn trả về được cho client: chunk bị bỏ, ghi log và tăng
+//   `storage_write_buffer_failures_total`. Một lần ghi lỗi không kéo các lần ghi khác
+//   trong cùng lượt theo.
+// - Chunk còn trong bộ đệm chưa đọc được ở mức `consistency=weak`; `consistency=strong`
+//   ghi hết bộ đệm trước khi đọc nên vẫn đảm bảo read-your-writes.
+//
+// `POST /admin/flush` ghi hết bộ đệm ngay và flush database; `/health` trả về số chunk
+// chưa được ghi trong trường `buffered`. Các lượt ghi bộ đệm chạy lần lượt nên chunk
+// xuống sled đúng thứ tự được nhận.
+
+use axum::http::StatusCode;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::quota::PendingWrite;
+use crate::AppState;
+
+#[derive(Default)]
+pub struct WriteBuffer {
+    queue: Mutex<Vec<PendingWrite>>,
+    // Số chunk đã nhận mà chưa ghi xong xuống sled, tính cả lượt đang được ghi
+    pending: AtomicUsize,
+    // Chỉ một lượt ghi chạy một lúc để giữ thứ tự các lần ghi
+    drain_lock: tokio::sync::Mutex<()>,
+}
+
+impl WriteBuffer {
+    /// Số chunk đã trả `200` cho client nhưng chưa nằm trong sled
+    pub fn pending(&self) -> usize {
+        self.pending.load(Ordering::Relaxed)
+    }
+
+    /// Thêm một lần ghi vào cuối bộ đệm, trả về số chunk đang chờ
+    pub fn push(&self, write: PendingWrite) -> usize {
+        // Tăng bộ đếm khi còn giữ khóa để lượt ghi lấy chunk này luôn thấy nó đã được đếm
+        let mut queue = self.queue.lock().unwrap();
+        queue.push(write);
+        self.pending.fetch_add(1, Ordering::Relaxed) + 1
+    }
+}
+
+/// Ghi toàn bộ bộ đệm xuống sled và flush; trả về số chunk đã lấy ra khỏi bộ đệm (kể cả
+/// chunk bị bỏ vì lỗi). Chờ lượt ghi đang chạy (nếu có) xong trước.
+pub async fn drain(state: &Arc<AppState>) -> Result<usize, StatusCode> {
+    let buffer = &state.write_buffer;
+    let _guard = buffer.drain_lock.lock().await;
+    let writes = std::mem::take(&mut *buffer.queue.lock().unwrap());
+    if writes.is_empty() {
+        return Ok(0);
+    }
+
+    let count = writes.len();
+    let persist_state = state.clone();
+    let result = tokio::task::spawn_blocking(move || persist(&persist_state, writes))
+        .await
+        .unwrap_or_else(|e| {
+            tracing::error!("Lỗi khi chạy tác vụ ghi bộ đệm: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        });
+    buffer.pending.fetch_sub(count, Ordering::Relaxed);
+    result.map(|()| count)
+}
+
+// Ghi một lượt trong một transaction; nếu transaction lỗi thì ghi lại từng chunk để chỉ
+// những chunk lỗi bị bỏ
+fn persist(state: &AppState, writes: Vec<PendingWrite>) -> Result<(), StatusCode> {
+    let writes = if state.apply_writes(&writes).is_ok() {
+        writes
+    } else {
+        let mut applied = Vec::with_capacity(writes.len());
+        for write in writes {
+            match state.apply_writes(std::slice::from_ref(&write)) {
+                Ok(_) => applied.push(write),
+                Err(status) => {
+                    tracing::error!(
+                        "Bỏ chunk {} trong bộ đệm ghi: {}",
+                        String::from_utf8_lossy(&write.key),
+                        status
+                    );
+                    state.metrics.write_buffer_failures.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+        applied
+    };
+    state.finish_writes(&writes)
+}
+
+/// Task nền định kỳ ghi bộ đệm xuống sled
+pub async fn run(state: Arc<AppState>) {
+    loop {
+        let interval = Duration::from_millis(state.config.load().write_buffer_flush_ms);
+        tokio::time::sleep(interval).await;
+        if state.write_buffer.pending() == 0 {
+            continue;
+        }
+        if let Err(status) = drain(&state).await {
+            tracing::error!("Lỗi khi ghi bộ đệm xuống database: {}", status);
+        }
+    }
+}
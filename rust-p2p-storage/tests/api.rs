@@ -0,0 +1,1215 @@
+// Test tích hợp cho các route cơ bản: lưu, đọc, cache theo Last-Modified và xử lý lỗi.
+
+mod common;
+
+use axum::routing::get;
+use common::{chunk_bytes, TestServer};
+use rust_p2p_storage::with_middleware;
+
+#[tokio::test]
+async fn store_then_retrieve_round_trips_bytes() {
+    let server = TestServer::start().await;
+    // Dữ liệu nhị phân bất kỳ, không phải UTF-8
+    let data: Vec<u8> = (0..=255u8).rev().collect();
+
+    let response = server.store("0xfile", "0xhash", &data, Some(3)).await;
+    assert_eq!(response.status(), 201);
+
+    let chunks = server.chunks("0xfile").await;
+    assert_eq!(chunks.len(), 1);
+    assert_eq!(chunks[0]["key"], "0xfile:0xhash");
+    assert_eq!(chunks[0]["index"], 3);
+    assert_eq!(chunk_bytes(&chunks[0]), data);
+}
+
+#[tokio::test]
+async fn retrieve_does_not_mix_files_sharing_a_prefix() {
+    let server = TestServer::start().await;
+    server.store("0xab", "0x1", b"short", None).await;
+    server.store("0xabc", "0x2", b"long", None).await;
+
+    let chunks = server.chunks("0xab").await;
+    assert_eq!(chunks.len(), 1);
+    assert_eq!(chunk_bytes(&chunks[0]), b"short");
+}
+
+#[tokio::test]
+async fn unknown_file_returns_empty_chunk_list() {
+    let server = TestServer::start().await;
+    assert!(server.chunks("0xmissing").await.is_empty());
+}
+
+#[tokio::test]
+async fn store_rejects_chunk_hash_with_separator() {
+    let server = TestServer::start().await;
+    let response = server.store("0xfile", "0x:bad", b"data", None).await;
+    assert_eq!(response.status(), 400);
+}
+
+#[tokio::test]
+async fn if_modified_since_returns_304_until_file_changes() {
+    let server = TestServer::start().await;
+    server.store("0xfile", "0x1", b"data", Some(0)).await;
+
+    let response = server.get("/file/0xfile").await;
+    assert_eq!(response.status(), 200);
+    let last_modified = response.headers()["last-modified"].to_str().unwrap().to_string();
+
+    let not_modified = server
+        .client
+        .get(server.url("/file/0xfile"))
+        .header("If-Modified-Since", &last_modified)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(not_modified.status(), 304);
+
+    // Thời điểm trước lần ghi thì phải trả về dữ liệu đầy đủ
+    let modified = server
+        .client
+        .get(server.url("/file/0xfile"))
+        .header("If-Modified-Since", "Thu, 01 Jan 1970 00:00:00 GMT")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(modified.status(), 200);
+}
+
+#[tokio::test]
+async fn large_files_answer_conditional_gets_and_cap_tail_responses() {
+    let server = TestServer::with_config(&[("STORAGE_MAX_RESPONSE_BYTES", "64")]).await;
+    server.store("0xfile", "0x1", &[7u8; 100], Some(0)).await;
+
+    let too_large = server.get("/file/0xfile").await;
+    assert_eq!(too_large.status(), 413);
+    assert_eq!(too_large.json::<serde_json::Value>().await.unwrap()["code"], "response_too_large");
+    let conditional = server
+        .client
+        .get(server.url("/file/0xfile"))
+        .header("If-Modified-Since", httpdate::fmt_http_date(std::time::SystemTime::now()))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(conditional.status(), 304);
+
+    assert_eq!(server.get("/file/0xfile/tail?n=1000000").await.status(), 413);
+}
+
+async fn boom() -> &'static str {
+    panic!("boom")
+}
+
+#[tokio::test]
+async fn panicking_handler_returns_json_500() {
+    let server = TestServer::with_router(&[], |state| {
+        let router = axum::Router::new().route("/boom", get(boom));
+        with_middleware(router, state)
+    })
+    .await;
+
+    let response = server.get("/boom").await;
+    assert_eq!(response.status(), 500);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "internal_error");
+}
+
+#[tokio::test]
+async fn error_messages_follow_accept_language() {
+    let server = TestServer::start().await;
+
+    let english = server.get("/file/0xmissing/probe").await;
+    assert_eq!(english.status(), 404);
+    let english: serde_json::Value = english.json().await.unwrap();
+
+    let vietnamese = server
+        .client
+        .get(server.url("/file/0xmissing/probe"))
+        .header("Accept-Language", "vi-VN, en;q=0.5")
+        .send()
+        .await
+        .unwrap();
+    let vietnamese: serde_json::Value = vietnamese.json().await.unwrap();
+
+    assert_eq!(english["code"], vietnamese["code"]);
+    assert_ne!(english["message"], vietnamese["message"]);
+}
+
+#[tokio::test]
+async fn store_returns_201_with_the_chunk_location_unless_nothing_changed() {
+    let server = TestServer::start().await;
+
+    let created = server.store("0xfile", "0xhash", b"first", Some(0)).await;
+    assert_eq!(created.status(), 201);
+    let location = created.headers()["location"].to_str().unwrap().to_string();
+    assert_eq!(location, "/file/0xfile/chunk/0xhash");
+    let chunk: serde_json::Value = server.get(&location).await.json().await.unwrap();
+    assert_eq!(chunk["key"], "0xfile:0xhash");
+    assert_eq!(chunk["index"], 0);
+    assert_eq!(chunk_bytes(&chunk), b"first");
+
+    // Ghi lại đúng chunk cũ không thay đổi gì
+    let unchanged = server.store("0xfile", "0xhash", b"first", Some(0)).await;
+    assert_eq!(unchanged.status(), 200);
+    assert_eq!(unchanged.headers()["location"], location.as_str());
+    // Dữ liệu hoặc index khác thì bị từ chối, trừ khi client yêu cầu ghi đè
+    assert_eq!(server.store("0xfile", "0xhash", b"second", Some(0)).await.status(), 409);
+    assert_eq!(chunk_bytes(&server.get(&location).await.json().await.unwrap()), b"first");
+    for index in [0, 1] {
+        let body = serde_json::json!({"fileKey": "0xfile", "chunkHash": "0xhash", "chunkData": "c2Vjb25k", "chunkIndex": index});
+        let forced = server.client.post(server.url("/store?overwrite=true")).json(&body).send().await.unwrap();
+        assert_eq!(forced.status(), 201);
+    }
+    assert_eq!(chunk_bytes(&server.get(&location).await.json().await.unwrap()), b"second");
+
+    // fileKey có ký tự đặc biệt được mã hóa trong URL
+    let created = server.store("my file", "0x1", b"x", None).await;
+    assert_eq!(created.headers()["location"], "/file/my%20file/chunk/0x1");
+    assert_eq!(server.get("/file/my%20file/chunk/0x1").await.status(), 200);
+
+    let missing = server.get("/file/0xfile/chunk/0xnope").await;
+    assert_eq!(missing.status(), 404);
+    let body: serde_json::Value = missing.json().await.unwrap();
+    assert_eq!(body["code"], "chunk_not_found");
+}
+
+#[tokio::test]
+async fn store_reports_every_invalid_field_at_once() {
+    let server = TestServer::with_config(&[("STORAGE_MAX_CHUNK_SIZE", "4")]).await;
+    let invalid = serde_json::json!({"fileKey": "", "chunkHash": "0x:1", "chunkData": "not base64!"});
+    let response = server.client.post(server.url("/store")).json(&invalid).header("Accept-Language", "vi").send().await.unwrap();
+    assert_eq!(response.status(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "invalid_fields");
+    let fields: Vec<_> = body["errors"].as_array().unwrap().iter().map(|e| e["field"].as_str().unwrap()).collect();
+    assert_eq!(fields, ["fileKey", "chunkHash", "chunkData"]);
+    assert!(body["errors"].as_array().unwrap().iter().all(|e| e["message"].is_string()));
+
+    // Dữ liệu vượt giới hạn chunk được báo cùng với các lỗi khác
+    let too_large = serde_json::json!({"fileKey": "0xfile", "chunkHash": "", "chunkData": "aGVsbG8="});
+    let body: serde_json::Value = server.post_json("/store", &too_large).await.json().await.unwrap();
+    let fields: Vec<_> = body["errors"].as_array().unwrap().iter().map(|e| e["field"].as_str().unwrap()).collect();
+    assert_eq!(fields, ["chunkHash", "chunkData"]);
+
+    assert_eq!(server.store("0xfile", "0x1", b"ok", None).await.status(), 201);
+}
+
+#[tokio::test]
+async fn new_keys_are_checked_on_every_write_path() {
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+    let server = TestServer::with_config(&[
+        ("STORAGE_MAX_KEY_BYTES", "16"),
+        ("STORAGE_MAX_CHUNK_SIZE", "8"),
+        ("STORAGE_SPLIT_CHUNK_SIZE", "4"),
+    ])
+    .await;
+    let colon = serde_json::json!({"fileKey": "0xa:b", "chunkHash": "0x1", "chunkData": "YQ=="});
+    let body: serde_json::Value = server.post_json("/store", &colon).await.json().await.unwrap();
+    assert_eq!(body["code"], "invalid_fields");
+    assert_eq!(body["errors"][0]["field"], "fileKey");
+    assert_eq!(server.store("0x0123456789abcdef", "0x1", b"x", None).await.status(), 400);
+
+    let raw = server.client.post(server.url("/store/raw/0xa%3Ab/0x1")).body("x").send().await.unwrap();
+    assert_eq!(raw.status(), 400);
+    assert_eq!(raw.json::<serde_json::Value>().await.unwrap()["code"], "invalid_key");
+    // Blob được chia thành nhiều chunk cũng phải có fileKey hợp lệ
+    let split = server.client.post(server.url("/store/raw/0x0123456789abcdef/0x1?split=true")).body("0123456789").send().await.unwrap();
+    assert_eq!(split.json::<serde_json::Value>().await.unwrap()["code"], "invalid_key");
+    assert!(server.chunks("0x0123456789abcdef").await.is_empty());
+    let binary = URL_SAFE_NO_PAD.encode([0xff; 17]);
+    let b64 = server.client.post(server.url(&format!("/b64/store/raw/{}/0x1", binary))).body("x").send().await.unwrap();
+    assert_eq!(b64.json::<serde_json::Value>().await.unwrap()["code"], "invalid_key");
+    let b64 = server.client.post(server.url(&format!("/b64/store/raw/{}/0x%0A", URL_SAFE_NO_PAD.encode([0xff])))).body("x").send().await.unwrap();
+    assert_eq!(b64.json::<serde_json::Value>().await.unwrap()["code"], "invalid_key");
+    let stream = server.client.post(server.url("/store/stream/0xfile/0x%0A")).body("x").send().await.unwrap();
+    assert_eq!(stream.json::<serde_json::Value>().await.unwrap()["code"], "invalid_key");
+    assert_eq!(server.post_json("/upload/init", &serde_json::json!({"fileKey": "a:b"})).await.status(), 400);
+    assert!(server.chunks("0xa:b").await.is_empty());
+}
+
+#[tokio::test]
+async fn malformed_requests_get_structured_error_bodies() {
+    let server = TestServer::with_config(&[("STORAGE_MAX_CHUNK_SIZE", "4")]).await;
+    let send = |body: &'static str, content_type: &'static str| {
+        server.client.post(server.url("/store")).header("content-type", content_type).body(body).send()
+    };
+
+    let syntax = send("{not json", "application/json").await.unwrap();
+    assert_eq!(syntax.status(), 400);
+    assert!(syntax.headers()["content-type"].to_str().unwrap().starts_with("application/json"));
+    let body: serde_json::Value = syntax.json().await.unwrap();
+    assert_eq!(body["code"], "bad_request");
+    assert!(body["reason"].as_str().unwrap().contains("JSON"));
+
+    let missing = send(r#"{"fileKey": "0xfile"}"#, "application/json").await.unwrap();
+    assert_eq!(missing.status(), 422);
+    let body: serde_json::Value = missing.json().await.unwrap();
+    assert_eq!(body["code"], "invalid_body");
+    assert!(body["reason"].as_str().unwrap().contains("chunkHash"));
+
+    let text = send("{}", "text/plain").await.unwrap();
+    assert_eq!(text.status(), 415);
+    assert_eq!(text.json::<serde_json::Value>().await.unwrap()["code"], "json_required");
+
+    let query = server.get("/file/0xfile?limit=many").await;
+    assert_eq!(query.status(), 400);
+    let body: serde_json::Value = query.json().await.unwrap();
+    assert_eq!(body["code"], "bad_request");
+
+    // Thông báo vẫn được dịch, `reason` giữ nguyên
+    let response = server.client.post(server.url("/store")).header("Accept-Language", "vi").json(&"x").send().await.unwrap();
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["message"], "body của request thiếu trường hoặc sai kiểu dữ liệu, xem reason");
+    assert!(body["reason"].is_string());
+}
+
+#[tokio::test]
+async fn base64_variants_are_stored_in_canonical_form() {
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+
+    // Byte 0xfb 0xff tạo ra '+' và '/' ở dạng chuẩn, '-' và '_' ở dạng url-safe
+    let data: &[u8] = &[0xfb, 0xff, 0xfe, 0x01];
+    let canonical = STANDARD.encode(data);
+    assert_eq!(canonical, "+//+AQ==");
+    let store = |server: &TestServer, hash: &str, chunk_data: &str| {
+        let body = serde_json::json!({"fileKey": "0xfile", "chunkHash": hash, "chunkData": chunk_data});
+        server.client.post(server.url("/store")).json(&body).send()
+    };
+
+    let strict = TestServer::start().await;
+    assert_eq!(store(&strict, "0x1", "-__-AQ").await.unwrap().status(), 400);
+
+    let server = TestServer::with_config(&[("STORAGE_BASE64_NORMALIZE", "1")]).await;
+    for (hash, variant) in [("0x1", "-__-AQ=="), ("0x2", "-__-AQ"), ("0x3", "+//+AQ"), ("0x4", canonical.as_str())] {
+        assert_eq!(store(&server, hash, variant).await.unwrap().status(), 201, "{}", variant);
+    }
+    let chunks = server.chunks("0xfile").await;
+    assert_eq!(chunks.len(), 4);
+    assert!(chunks.iter().all(|chunk| chunk["value"] == canonical.as_str()));
+
+    // Trộn hai bảng chữ cái hoặc ký tự lạ thì không hợp lệ ở biến thể nào
+    for invalid in ["+_/-AQ", "-__-A", "not base64!"] {
+        let response = store(&server, "0x5", invalid).await.unwrap();
+        assert_eq!(response.status(), 400, "{}", invalid);
+    }
+
+    let batch = serde_json::json!([{"fileKey": "0xbatch", "chunkHash": "0x1", "chunkData": "-__-AQ"}]);
+    assert_eq!(server.post_json("/store/batch", &batch).await.status(), 200);
+    assert_eq!(server.chunks("0xbatch").await[0]["value"], canonical.as_str());
+}
+
+#[tokio::test]
+async fn chunk_metadata_is_stored_and_returned() {
+    for format in ["json", "bare"] {
+        let server =
+            TestServer::with_config(&[("STORAGE_VALUE_FORMAT", format), ("STORAGE_MAX_METADATA_BYTES", "32")]).await;
+        let body = serde_json::json!({
+            "fileKey": "0xfile",
+            "chunkHash": "0x1",
+            "chunkData": "ZGF0YQ==",
+            "chunkIndex": 0,
+            "metadata": {"source": "https://a.example", "tag": "x"},
+        });
+        assert_eq!(server.post_json("/store", &body).await.status(), 201);
+        server.store("0xfile", "0x2", b"plain", Some(1)).await;
+
+        let chunks = server.chunks("0xfile").await;
+        assert_eq!(chunks[0]["metadata"], serde_json::json!({"source": "https://a.example", "tag": "x"}));
+        assert!(chunks[1].get("metadata").is_none());
+
+        let mut too_large = body.clone();
+        too_large["metadata"] = serde_json::json!({"source": "https://a-much-longer-host.example/path"});
+        let response = server.post_json("/store", &too_large).await;
+        assert_eq!(response.status(), 400);
+        let error: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(error["errors"][0]["field"], "metadata");
+        let batch = serde_json::json!([too_large]);
+        assert_eq!(server.post_json("/store/batch", &batch).await.status(), 400);
+    }
+}
+
+#[tokio::test]
+async fn verified_writes_round_trip_without_failures() {
+    let server = TestServer::with_config(&[("STORAGE_VERIFY_WRITES", "1")]).await;
+    let response = server.store("0xfile", "0x1", b"checked", Some(0)).await;
+    assert_eq!(response.status(), 201);
+    assert_eq!(chunk_bytes(&server.chunks("0xfile").await[0]), b"checked");
+
+    let metrics = server.get("/metrics").await.text().await.unwrap();
+    assert!(metrics.contains("storage_write_verify_failures_total 0"));
+}
+
+#[tokio::test]
+async fn reject_mode_refuses_existing_keys() {
+    let server = TestServer::with_config(&[("STORAGE_OVERWRITE_MODE", "reject")]).await;
+    assert_eq!(server.store("0xfile", "0x1", b"first", Some(0)).await.status(), 201);
+
+    let response = server.store("0xfile", "0x1", b"first", Some(0)).await;
+    assert_eq!(response.status(), 409);
+    assert_eq!(chunk_bytes(&server.chunks("0xfile").await[0]), b"first");
+}
+
+#[tokio::test]
+async fn verify_mode_accepts_identical_rewrites_only() {
+    let server = TestServer::with_config(&[("STORAGE_OVERWRITE_MODE", "verify")]).await;
+    assert_eq!(server.store("0xfile", "0x1", b"same", Some(0)).await.status(), 201);
+    assert_eq!(server.store("0xfile", "0x1", b"same", Some(0)).await.status(), 200);
+
+    let response = server.store("0xfile", "0x1", b"different", Some(0)).await;
+    assert_eq!(response.status(), 409);
+    let chunks = server.chunks("0xfile").await;
+    assert_eq!(chunks.len(), 1);
+    assert_eq!(chunk_bytes(&chunks[0]), b"same");
+}
+
+#[tokio::test]
+async fn hex_encoding_round_trips_chunk_bytes() {
+    let server = TestServer::start().await;
+    let data = [0u8, 1, 0xab, 0xff, b'x'];
+    server.store("0xfile", "0x1", &data, Some(0)).await;
+
+    let body: serde_json::Value = server.get("/file/0xfile?encoding=hex").await.json().await.unwrap();
+    let value = body["chunks"][0]["value"].as_str().unwrap();
+    let hex_digits = value.strip_prefix("0x").expect("value hex có tiền tố 0x");
+    assert_eq!(hex::decode(hex_digits).unwrap(), data);
+
+    // Mặc định vẫn là Base64
+    assert_eq!(chunk_bytes(&server.chunks("0xfile").await[0]), data);
+}
+
+#[tokio::test]
+async fn index_page_links_each_file_with_an_encoded_key() {
+    let server = TestServer::with_config(&[("STORAGE_HTML_INDEX", "1")]).await;
+    assert_eq!(server.store("0xdir/a b#<1>", "0xc", b"data", Some(0)).await.status(), 201);
+
+    let page = server.get("/").await.text().await.unwrap();
+    assert!(page.contains("<code>0xdir/a b#&lt;1&gt;</code>"), "{}", page);
+    let href = "/file/0xdir%2Fa%20b%23%3C1%3E";
+    for link in [href.to_string(), format!("{}/manifest", href), format!("{}/stream", href)] {
+        assert!(page.contains(&format!("href=\"{}\"", link)), "thiếu {} trong {}", link, page);
+    }
+    assert_eq!(server.get(&format!("{}/stream", href)).await.bytes().await.unwrap().as_ref(), b"data");
+}
+
+#[tokio::test]
+async fn version_reports_the_enabled_features() {
+    let plain = TestServer::start().await;
+    let body: serde_json::Value = plain.get("/version").await.json().await.unwrap();
+    assert_eq!(body["features"]["grpc"], false);
+    assert_eq!(body["features"]["compression"], "identity");
+    assert_eq!(body["features"]["encryption"], false);
+    assert_eq!(body["features"]["s3"], false);
+
+    let key = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+    let server = TestServer::with_config(&[("STORAGE_COMPRESSION", "zstd"), ("STORAGE_ENCRYPTION_KEY", key)]).await;
+    let _grpc = rust_p2p_storage::grpc_service(server.state.clone());
+    let body: serde_json::Value = server.get("/version").await.json().await.unwrap();
+    assert_eq!(body["features"]["grpc"], true);
+    assert_eq!(body["features"]["compression"], "zstd");
+    assert_eq!(body["features"]["encryption"], true);
+}
+
+#[tokio::test]
+async fn connections_over_the_limit_get_503_and_are_closed() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let server = TestServer::with_config(&[("STORAGE_MAX_CONNECTIONS", "1")]).await;
+
+    // Giữ kết nối duy nhất được phép (keep-alive) sau khi chắc chắn server đã nhận nó
+    let mut held = tokio::net::TcpStream::connect(server.addr).await.unwrap();
+    held.write_all(b"GET /version HTTP/1.1\r\nhost: test\r\n\r\n").await.unwrap();
+    let mut buf = [0u8; 12];
+    held.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"HTTP/1.1 200");
+
+    let rejected = server.get("/version").await;
+    assert_eq!(rejected.status(), 503);
+    let body: serde_json::Value = rejected.json().await.unwrap();
+    assert_eq!(body["code"], "too_many_connections");
+
+    drop(held);
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    assert_eq!(server.get("/version").await.status(), 200);
+}
+
+#[tokio::test]
+async fn slow_requests_get_408_after_their_route_timeout() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let server = TestServer::with_config(&[("STORAGE_ROUTE_TIMEOUTS", "/store/raw/:fileKey/:chunkHash=200")]).await;
+
+    // Client khai báo body nhưng không gửi: request bị cắt sau thời gian của route
+    let mut slow = tokio::net::TcpStream::connect(server.addr).await.unwrap();
+    slow.write_all(b"POST /store/raw/0xfile/0x1 HTTP/1.1\r\nhost: test\r\ncontent-length: 10\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = Vec::new();
+    let read = tokio::time::timeout(std::time::Duration::from_secs(5), slow.read_to_end(&mut response)).await;
+    assert!(read.is_ok(), "server không cắt request chậm");
+    let response = String::from_utf8_lossy(&response);
+    assert!(response.starts_with("HTTP/1.1 408"), "{response}");
+    assert!(response.contains("request_timeout"), "{response}");
+
+    // Route khác vẫn dùng giới hạn của nó
+    assert_eq!(server.store("0xfile", "0x1", b"fast", Some(0)).await.status(), 201);
+}
+
+#[tokio::test]
+async fn batch_reports_a_status_for_every_chunk() {
+    let server = TestServer::start().await;
+    let entry = |hash: &str, data: &str| serde_json::json!({"fileKey": "0xfile", "chunkHash": hash, "chunkData": data});
+    let batch = serde_json::json!([entry("0x1", "YQ=="), entry("0x1", "YQ=="), entry("bad:hash", "Yg=="), entry("0x2", "Yw==")]);
+
+    // Mặc định một chunk không hợp lệ làm cả batch bị từ chối
+    assert_eq!(server.post_json("/store/batch", &batch).await.status(), 400);
+    assert!(server.chunks("0xfile").await.is_empty());
+
+    let response = server.post_json("/store/batch?partial=true", &batch).await;
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["stored"], 2);
+    let statuses: Vec<_> = body["items"].as_array().unwrap().iter().map(|item| item["status"].clone()).collect();
+    assert_eq!(statuses, ["stored", "duplicate", "rejected", "stored"]);
+    assert_eq!(body["items"][2]["code"], "invalid_fields");
+    assert_eq!(body["items"][2]["chunkHash"], "bad:hash");
+    assert!(body["items"][0].get("code").is_none());
+    assert_eq!(server.chunks("0xfile").await.len(), 2);
+}
+
+#[tokio::test]
+async fn batch_with_conflicting_duplicate_chunk_is_rejected() {
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+
+    let server = TestServer::start().await;
+    let entry = |data: &[u8]| {
+        serde_json::json!({"fileKey": "0xfile", "chunkHash": "0x1", "chunkData": STANDARD.encode(data), "chunkIndex": 0})
+    };
+    let other = serde_json::json!({"fileKey": "0xfile", "chunkHash": "0x2", "chunkData": STANDARD.encode(b"x")});
+
+    let response = server
+        .client
+        .post(server.url("/store/batch"))
+        .header("accept-language", "vi")
+        .json(&serde_json::json!([entry(b"first"), other, entry(b"second")]))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "duplicate_chunk");
+    assert_eq!(body["key"], "0xfile:0x1");
+    assert!(server.chunks("0xfile").await.is_empty());
+
+    // Bản trùng giống hệt nhau thì không phải lỗi
+    let response = server
+        .post_json("/store/batch", &serde_json::json!([entry(b"same"), entry(b"same")]))
+        .await;
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["stored"], 1);
+}
+
+#[tokio::test]
+async fn read_only_mode_blocks_writes_but_serves_reads() {
+    let server = TestServer::with_config(&[("STORAGE_ADMIN_API_KEY", "secret")]).await;
+    server.store("0xfile", "0x1", b"kept", Some(0)).await;
+
+    let toggle = |on: bool, key: &'static str| {
+        server
+            .client
+            .post(server.url("/admin/readonly"))
+            .header("x-api-key", key)
+            .json(&serde_json::json!({ "readOnly": on }))
+            .send()
+    };
+    assert_eq!(toggle(true, "wrong").await.unwrap().status(), 401);
+    assert_eq!(toggle(true, "secret").await.unwrap().status(), 200);
+
+    let health: serde_json::Value = server.get("/health").await.json().await.unwrap();
+    assert_eq!(health["readOnly"], true);
+
+    let response = server.store("0xfile", "0x2", b"new", Some(1)).await;
+    assert_eq!(response.status(), 503);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "read_only");
+    let delete = server.client.delete(server.url("/file/0xfile")).send().await.unwrap();
+    assert_eq!(delete.status(), 503);
+
+    let chunks = server.chunks("0xfile").await;
+    assert_eq!(chunks.len(), 1);
+    assert_eq!(chunk_bytes(&chunks[0]), b"kept");
+
+    toggle(false, "secret").await.unwrap();
+    assert_eq!(server.store("0xfile", "0x2", b"new", Some(1)).await.status(), 201);
+}
+
+#[tokio::test]
+async fn low_disk_space_degrades_the_node_to_read_only() {
+    let server = TestServer::with_config(&[
+        ("STORAGE_DATA_DIR", "."),
+        ("STORAGE_DISK_MIN_FREE_BYTES", "18000000000000000000"),
+        ("STORAGE_DISK_RECHECK_SECS", "1"),
+        ("STORAGE_ADMIN_API_KEY", "secret"),
+    ])
+    .await;
+    server.store("0xfile", "0x1", b"kept", Some(0)).await;
+    tokio::spawn(rust_p2p_storage::run_disk_monitor(server.state.clone()));
+
+    let mut health = serde_json::Value::Null;
+    for _ in 0..50 {
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        health = server.get("/health").await.json().await.unwrap();
+        if health["status"] == "degraded" {
+            break;
+        }
+    }
+    assert_eq!(health["status"], "degraded");
+    assert_eq!(health["readOnly"], true);
+    assert_eq!(health["diskFull"], true);
+    assert_eq!(server.store("0xfile", "0x2", b"new", Some(1)).await.status(), 503);
+    assert_eq!(chunk_bytes(&server.chunks("0xfile").await[0]), b"kept");
+
+    // Quản trị viên giữ chế độ chỉ đọc bằng tay thì không còn là trạng thái đĩa đầy
+    let response = server
+        .client
+        .post(server.url("/admin/readonly"))
+        .header("x-api-key", "secret")
+        .json(&serde_json::json!({ "readOnly": true }))
+        .send()
+        .await
+        .unwrap();
+    let health: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(health["status"], "read_only");
+    assert_eq!(health["diskFull"], false);
+}
+
+#[tokio::test]
+async fn server_timing_header_reports_read_stages_when_enabled() {
+    let server = TestServer::with_config(&[("STORAGE_SERVER_TIMING", "1"), ("STORAGE_COMPRESSION", "zstd")]).await;
+    server.store("0xfile", "0x1", &[7u8; 4096], Some(0)).await;
+
+    let response = server.get("/file/0xfile").await;
+    let timing = response.headers()["server-timing"].to_str().unwrap().to_string();
+    for stage in ["scan;dur=", "delta;dur=", "decompress;dur=", "serialize;dur="] {
+        assert!(timing.contains(stage), "thiếu {} trong {}", stage, timing);
+    }
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(chunk_bytes(&body["chunks"][0]), [7u8; 4096]);
+
+    let plain = TestServer::start().await;
+    plain.store("0xfile", "0x1", b"data", Some(0)).await;
+    assert!(plain.get("/file/0xfile").await.headers().get("server-timing").is_none());
+}
+
+#[tokio::test]
+async fn final_flush_on_shutdown_exits_cleanly() {
+    let server = TestServer::with_config(&[("STORAGE_SHUTDOWN_FLUSH_TIMEOUT_MS", "10000")]).await;
+    server.store("0xfile", "0x1", b"data", Some(0)).await;
+    assert_eq!(rust_p2p_storage::shutdown::final_flush(&server.state).await, 0);
+}
+
+#[tokio::test]
+async fn shutdown_finishes_in_flight_requests_and_refuses_new_ones() {
+    let server = TestServer::start().await;
+    let slow = axum::Router::new().route(
+        "/slow",
+        axum::routing::get(|| async {
+            tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+            "done"
+        }),
+    );
+    let app = slow.merge(rust_p2p_storage::build_app(server.state.clone()));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (stop, stopped) = rust_p2p_storage::shutdown::channel();
+    let serving = tokio::spawn(rust_p2p_storage::serve(listener, app, 16, stopped.wait()));
+
+    let client = reqwest::Client::new();
+    let in_flight = tokio::spawn(client.get(format!("http://{}/slow", addr)).send());
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    stop.stop();
+
+    // Request đang chạy vẫn nhận response đầy đủ, và server chỉ dừng sau đó
+    let response = in_flight.await.unwrap().unwrap();
+    assert_eq!(response.text().await.unwrap(), "done");
+    tokio::time::timeout(std::time::Duration::from_secs(5), serving).await.unwrap().unwrap();
+    assert!(client.get(format!("http://{}/health", addr)).send().await.is_err());
+}
+
+#[tokio::test]
+async fn concurrent_stores_share_rate_limited_flushes() {
+    let server = TestServer::with_config(&[("STORAGE_FLUSH_MIN_INTERVAL_MS", "50")]).await;
+    let mut stores = tokio::task::JoinSet::new();
+    for i in 0..20u64 {
+        let request = server.client.post(server.url("/store")).json(&serde_json::json!({
+            "fileKey": "0xfile",
+            "chunkHash": format!("0x{}", i),
+            "chunkData": "ZGF0YQ==",
+            "chunkIndex": i,
+        }));
+        stores.spawn(async move { request.send().await.unwrap().status() });
+    }
+    while let Some(status) = stores.join_next().await {
+        assert_eq!(status.unwrap(), 201);
+    }
+    assert_eq!(server.chunks("0xfile").await.len(), 20);
+
+    let metrics = server.get("/metrics").await.text().await.unwrap();
+    let flushes: u64 = metrics
+        .lines()
+        .find_map(|line| line.strip_prefix("storage_flushes_total "))
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((1..20).contains(&flushes), "{} lần flush cho 20 lần ghi", flushes);
+}
+
+#[tokio::test]
+async fn writes_flush_once_every_n_chunks() {
+    let server = TestServer::with_config(&[("STORAGE_FLUSH_EVERY_WRITES", "5")]).await;
+    let flushes = || async {
+        let metrics = server.get("/metrics").await.text().await.unwrap();
+        metrics.lines().find_map(|line| line.strip_prefix("storage_flushes_total ")).unwrap().parse::<u64>().unwrap()
+    };
+    for i in 0..12u64 {
+        assert_eq!(server.store("0xfile", &format!("0x{}", i), b"data", Some(i)).await.status(), 201);
+    }
+    assert_eq!(flushes().await, 2);
+    assert_eq!(server.chunks("0xfile").await.len(), 12);
+
+    let result = std::panic::catch_unwind(|| {
+        rust_p2p_storage::Config::from_pairs([("STORAGE_FLUSH_EVERY_WRITES", "5"), ("STORAGE_FLUSH_MIN_INTERVAL_MS", "50")])
+    });
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn interval_durability_reports_writes_until_the_background_flush() {
+    let server = TestServer::with_config(&[("STORAGE_DURABILITY", "interval"), ("STORAGE_DURABILITY_INTERVAL_MS", "20")]).await;
+    for i in 0..3u64 {
+        assert_eq!(server.store("0xfile", &format!("0x{}", i), b"data", Some(i)).await.status(), 201);
+    }
+    let durability = || async { server.get("/stats").await.json::<serde_json::Value>().await.unwrap()["durability"].clone() };
+    let before = durability().await;
+    assert_eq!(before["policy"], "interval");
+    assert_eq!(before["acknowledgedWrites"], 3);
+    assert_eq!(before["pendingWrites"], 3);
+    let metrics = server.get("/metrics").await.text().await.unwrap();
+    assert!(metrics.contains("storage_unflushed_writes 3"));
+
+    tokio::spawn(rust_p2p_storage::run_flusher(server.state.clone()));
+    let mut after = durability().await;
+    for _ in 0..100 {
+        if after["pendingWrites"] == 0 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        after = durability().await;
+    }
+    assert_eq!(after["durableWrites"], 3);
+    assert!(after["lastFlushMs"].as_u64().unwrap() > 0);
+
+    let result = std::panic::catch_unwind(|| {
+        rust_p2p_storage::Config::from_pairs([("STORAGE_DURABILITY", "always"), ("STORAGE_FLUSH_EVERY_WRITES", "5")])
+    });
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn large_batches_are_written_by_parallel_workers() {
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+
+    let batch = |file_keys: &[&str]| {
+        let entries: Vec<_> = (0..40u64)
+            .map(|i| {
+                let file_key = file_keys[i as usize % file_keys.len()];
+                let data = STANDARD.encode(i.to_be_bytes());
+                serde_json::json!({"fileKey": file_key, "chunkHash": format!("0x{}", i), "chunkData": data, "chunkIndex": i})
+            })
+            .collect();
+        serde_json::Value::from(entries)
+    };
+    let parallel = [("STORAGE_BATCH_PARALLELISM", "4"), ("STORAGE_BATCH_PARALLEL_MIN_CHUNKS", "8")];
+
+    let server = TestServer::with_config(&parallel).await;
+    let response = server.post_json("/store/batch", &batch(&["0xa", "0xb", "0xc"])).await;
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["stored"], 40);
+    for (file_key, expected) in [("0xa", 14), ("0xb", 13), ("0xc", 13)] {
+        assert_eq!(server.chunks(file_key).await.len(), expected);
+    }
+    let metrics = server.get("/metrics").await.text().await.unwrap();
+    assert!(metrics.contains("storage_file_keys 3"), "{}", metrics);
+
+    // Chế độ reject vẫn ghi nguyên tử: một key trùng thì không chunk nào được ghi
+    let mut pairs = parallel.to_vec();
+    pairs.push(("STORAGE_OVERWRITE_MODE", "reject"));
+    let server = TestServer::with_config(&pairs).await;
+    server.store("0xa", "0x39", b"existing", None).await;
+    assert_eq!(server.post_json("/store/batch", &batch(&["0xa"])).await.status(), 409);
+    assert_eq!(server.chunks("0xa").await.len(), 1);
+}
+
+#[tokio::test]
+async fn parallel_read_keeps_chunk_order() {
+    let parallel = [("STORAGE_READ_PARALLELISM", "4"), ("STORAGE_PARALLEL_READ_MIN_CHUNKS", "8")];
+    for ordered in [false, true] {
+        let mut pairs = parallel.to_vec();
+        if ordered {
+            pairs.push(("STORAGE_ORDER_INDEX", "1"));
+        }
+        let server = TestServer::with_config(&pairs).await;
+        // Thứ tự hash ngược với thứ tự index để thấy được thứ tự trả về
+        for i in 0..40u64 {
+            let data = format!("chunk-{}", i);
+            server.store("0xfile", &format!("0x{:02x}", 99 - i), data.as_bytes(), Some(i)).await;
+        }
+        let chunks = server.chunks("0xfile").await;
+        assert_eq!(chunks.len(), 40);
+        let mut expected: Vec<_> = (0..40u64).collect();
+        if !ordered {
+            // Không có index thứ tự thì chunk trả về theo thứ tự key như khi đọc tuần tự
+            expected.reverse();
+        }
+        for (chunk, i) in chunks.iter().zip(expected) {
+            assert_eq!(chunk["index"], i);
+            assert_eq!(chunk_bytes(chunk), format!("chunk-{}", i).as_bytes());
+        }
+
+        // File nhỏ hơn ngưỡng vẫn đọc được
+        server.store("0xsmall", "0x1", b"one", Some(0)).await;
+        assert_eq!(server.chunks("0xsmall").await.len(), 1);
+    }
+}
+
+#[tokio::test]
+async fn store_paths_do_not_silently_replace_each_others_chunks() {
+    let server = TestServer::start().await;
+    let raw = |path: &str, data: &'static [u8]| server.client.post(server.url(path)).body(data).send();
+
+    assert_eq!(raw("/store/raw/0xfile/0x1?index=0", b"raw bytes").await.unwrap().status(), 200);
+    // Cùng chế độ thì ghi đè được khi client yêu cầu
+    assert_eq!(raw("/store/raw/0xfile/0x1?index=0", b"raw again").await.unwrap().status(), 409);
+    assert_eq!(raw("/store/raw/0xfile/0x1?index=0&overwrite=true", b"raw again").await.unwrap().status(), 200);
+
+    let json_write = server.store("0xfile", "0x1", b"json", Some(0)).await;
+    assert_eq!(json_write.status(), 409);
+    let batch = serde_json::json!([{"fileKey": "0xfile", "chunkHash": "0x1", "chunkData": "anNvbg=="}]);
+    assert_eq!(server.post_json("/store/batch", &batch).await.status(), 409);
+    assert_eq!(chunk_bytes(&server.chunks("0xfile").await[0]), b"raw again");
+
+    let forced = server
+        .client
+        .post(server.url("/store?overwrite=true"))
+        .json(&serde_json::json!({"fileKey": "0xfile", "chunkHash": "0x1", "chunkData": "anNvbg==", "chunkIndex": 0}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(forced.status(), 201);
+    assert_eq!(chunk_bytes(&server.chunks("0xfile").await[0]), b"json");
+
+    // Chiều ngược lại: chunk ghi qua JSON không bị /store/raw thay mất
+    assert_eq!(raw("/store/raw/0xfile/0x1", b"raw").await.unwrap().status(), 409);
+    assert_eq!(raw("/store/raw/0xfile/0x1?overwrite=true", b"raw").await.unwrap().status(), 200);
+    assert_eq!(chunk_bytes(&server.chunks("0xfile").await[0]), b"raw");
+}
+
+#[tokio::test]
+async fn head_and_bulk_exists_report_present_chunks_without_data() {
+    let server = TestServer::with_config(&[("STORAGE_LARGE_CHUNK_BYTES", "1024")]).await;
+    server.store("0xfile", "0x1", b"small", Some(3)).await;
+    server.store("0xfile", "0x2", &[7u8; 4096], None).await;
+
+    let head = server.client.head(server.url("/chunk/0xfile/0x1")).send().await.unwrap();
+    assert_eq!(head.status(), 200);
+    assert_eq!(head.headers()["x-chunk-size"], "5");
+    assert_eq!(head.headers()["x-chunk-index"], "3");
+    let head = server.client.head(server.url("/chunk/0xfile/0x2")).send().await.unwrap();
+    assert_eq!(head.headers()["x-chunk-size"], "4096");
+    assert!(head.headers().get("x-chunk-index").is_none());
+    let missing = server.client.head(server.url("/chunk/0xfile/0x3")).send().await.unwrap();
+    assert_eq!(missing.status(), 404);
+
+    let query = serde_json::json!({"fileKey": "0xfile", "chunkHashes": ["0x2", "0x9", "0x1", "0x9"]});
+    let response = server.post_json("/chunks/exists", &query).await;
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["present"], serde_json::json!([true, false, true, false]));
+    assert_eq!(body["missing"], serde_json::json!(["0x9"]));
+
+    let invalid = serde_json::json!({"fileKey": "0xfile", "chunkHashes": ["0x1:bad"]});
+    assert_eq!(server.post_json("/chunks/exists", &invalid).await.status(), 400);
+}
+
+#[tokio::test]
+async fn raw_chunks_round_trip_without_base64() {
+    let server = TestServer::start().await;
+    let data: Vec<u8> = (0..=255u8).collect();
+    let response = server.client.post(server.url("/store/raw/0xfile/0x1?index=4")).body(data.clone()).send().await.unwrap();
+    assert_eq!(response.status(), 200);
+
+    let response = server.get("/chunk/raw/0xfile/0x1").await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "application/octet-stream");
+    assert_eq!(response.headers()["x-chunk-index"], "4");
+    assert_eq!(response.bytes().await.unwrap().as_ref(), data.as_slice());
+
+    // Chunk ghi qua JSON cũng đọc được ở dạng nhị phân, kèm kiểu nội dung đã lưu
+    let stored = serde_json::json!({"fileKey": "0xfile", "chunkHash": "0x2", "chunkData": "aGk=", "contentType": "text/plain"});
+    assert_eq!(server.post_json("/store", &stored).await.status(), 201);
+    let response = server.get("/chunk/raw/0xfile/0x2").await;
+    assert_eq!(response.headers()["content-type"], "text/plain");
+    assert!(response.headers().get("x-chunk-index").is_none());
+    assert_eq!(response.bytes().await.unwrap().as_ref(), b"hi");
+
+    assert_eq!(server.get("/chunk/raw/0xfile/0x3").await.status(), 404);
+}
+
+#[tokio::test]
+async fn autohash_stores_raw_chunks_under_the_server_computed_hash() {
+    use sha2::{Digest, Sha256};
+
+    let server = TestServer::start().await;
+    let raw = |path: &str, data: &'static [u8]| server.client.post(server.url(path)).body(data).send();
+    let expected = format!("0x{}", hex::encode(Sha256::digest(b"content addressed")));
+
+    let response = raw("/store/raw/0xfile/auto?autohash=true&index=0", b"content addressed").await.unwrap();
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["chunkHashes"], serde_json::json!([expected]));
+    let chunks = server.chunks("0xfile").await;
+    assert_eq!(chunks[0]["key"], format!("0xfile:{}", expected));
+    assert_eq!(chunk_bytes(&chunks[0]), b"content addressed");
+
+    // Hash của client trùng với hash server tính thì được chấp nhận, khác thì bị từ chối
+    let matching = format!("/store/raw/0xfile/{}?autohash=true&index=0", expected.to_uppercase().replacen("0X", "0x", 1));
+    let response = raw(&matching, b"content addressed").await.unwrap();
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["chunkHashes"][0], expected.as_str());
+    let response = raw("/store/raw/0xfile/0xbeef?autohash=true", b"other data").await.unwrap();
+    assert_eq!(response.status(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "hash_mismatch");
+    assert_eq!(server.chunks("0xfile").await.len(), 1);
+
+    // Không có autohash thì chunkHash của client vẫn được dùng nguyên như trước
+    assert_eq!(raw("/store/raw/0xfile/0xbeef", b"other data").await.unwrap().status(), 200);
+    assert_eq!(server.chunks("0xfile").await.len(), 2);
+}
+
+#[tokio::test]
+async fn stream_store_verifies_the_expected_hash_before_writing() {
+    use sha2::{Digest, Sha256};
+
+    let server = TestServer::with_config(&[("STORAGE_MAX_CHUNK_SIZE", "100000")]).await;
+    let data: Vec<u8> = (0..70_000u32).map(|i| (i % 251) as u8).collect();
+    let hash = hex::encode(Sha256::digest(&data));
+    let stream = |path: &str, expected: &str, body: Vec<u8>| {
+        server.client.post(server.url(path)).header("x-expected-hash", expected).body(body).send()
+    };
+
+    let response = stream("/store/stream/0xfile/0xbig?index=0", &format!("0x{}", hash), data.clone()).await.unwrap();
+    assert_eq!(response.status(), 201);
+    assert_eq!(response.headers()["location"], "/file/0xfile/chunk/0xbig");
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["bytes"], 70_000);
+    let chunks = server.chunks("0xfile").await;
+    assert_eq!(chunk_bytes(&chunks[0]), data);
+
+    // Hash khác thì không có gì được ghi
+    let response = stream("/store/stream/0xfile/0xbad", &hash, b"tampered".to_vec()).await.unwrap();
+    assert_eq!(response.status(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "hash_mismatch");
+    assert_eq!(server.chunks("0xfile").await.len(), 1);
+
+    // Vượt giới hạn chunk hoặc thiếu hash mong đợi
+    let response = stream("/store/stream/0xfile/0xhuge", &hash, vec![0; 100_001]).await.unwrap();
+    assert_eq!(response.status(), 413);
+    let missing = server.client.post(server.url("/store/stream/0xfile/0x1")).body("x").send().await.unwrap();
+    assert_eq!(missing.status(), 400);
+}
+
+#[tokio::test]
+async fn store_rejects_chunks_whose_hash_does_not_match_their_data() {
+    use sha3::{Digest, Keccak256};
+
+    let server = TestServer::with_config(&[("STORAGE_VERIFY_HASH", "keccak256")]).await;
+    let hash = format!("0x{}", hex::encode(Keccak256::digest(b"verified")));
+
+    assert_eq!(server.store("0xfile", &hash.to_uppercase().replacen("0X", "0x", 1), b"verified", Some(0)).await.status(), 201);
+    let response = server.store("0xfile", &hash, b"bit flipped", Some(1)).await;
+    assert_eq!(response.status(), 422);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "checksum_mismatch");
+    assert_eq!(server.chunks("0xfile").await.len(), 1);
+
+    // Dữ liệu đã lưu được tính lại hash theo thuật toán đã cấu hình hoặc được chỉ định
+    let stored = hash.to_uppercase().replacen("0X", "0x", 1);
+    let verify: serde_json::Value = server.get(&format!("/verify/0xfile/{}", stored)).await.json().await.unwrap();
+    assert_eq!(verify["algorithm"], "keccak256");
+    assert_eq!(verify["computed"], hash.as_str());
+    assert_eq!(verify["valid"], true);
+    let verify: serde_json::Value =
+        server.get(&format!("/verify/0xfile/{}?algorithm=sha256", stored)).await.json().await.unwrap();
+    assert_eq!(verify["valid"], false);
+    assert_eq!(server.get("/verify/0xfile/0xmissing").await.status(), 404);
+}
+
+#[tokio::test]
+async fn concurrent_writes_to_one_file_are_capped() {
+    let server = TestServer::with_config(&[
+        ("STORAGE_MAX_REQUESTS_PER_FILE", "1"),
+        ("STORAGE_FLUSH_MIN_INTERVAL_MS", "300"),
+        ("STORAGE_OVERWRITE_MODE", "reject"),
+    ])
+    .await;
+    server.store("0xbig", "0x0", b"warm", Some(0)).await;
+
+    // Các lần flush cách nhau 300ms nên các request ghi cùng lúc còn đang chạy khi tới sau
+    let (a, b, c, other) = tokio::join!(
+        server.store("0xbig", "0x1", b"a", Some(1)),
+        server.store("0xbig", "0x2", b"b", Some(2)),
+        server.store("0xbig", "0x3", b"c", Some(3)),
+        server.store("0xother", "0x1", b"d", Some(0)),
+    );
+    let statuses = [a.status(), b.status(), c.status()];
+    assert!(statuses.contains(&reqwest::StatusCode::CREATED));
+    assert!(statuses.contains(&reqwest::StatusCode::TOO_MANY_REQUESTS));
+    assert_eq!(other.status(), 201);
+    let rejected = statuses.iter().filter(|s| **s == reqwest::StatusCode::TOO_MANY_REQUESTS).count();
+    let metrics = server.get("/metrics").await.text().await.unwrap();
+    assert!(metrics.contains(&format!("storage_file_requests_rejected_total {}", rejected)));
+
+    // Đổi tên ghi vào cả fileKey đích nên tranh suất ghi với lần ghi vào fileKey đó
+    let rename = serde_json::json!({"newFileKey": "0xdest"});
+    let (stored, renamed) = tokio::join!(
+        server.store("0xdest", "0x1", b"e", Some(0)),
+        server.post_json("/file/0xother/rename", &rename),
+    );
+    assert!([stored.status(), renamed.status()].contains(&reqwest::StatusCode::TOO_MANY_REQUESTS));
+
+    // Suất ghi được trả lại cả khi request lỗi
+    assert_eq!(server.store("0xbig", "0x0", b"changed", Some(0)).await.status(), 409);
+    assert_eq!(server.store("0xbig", "0x9", b"later", Some(9)).await.status(), 201);
+
+    // fileKey nhị phân qua /b64/store/raw cũng bị giới hạn
+    let raw = |chunk_hash: &'static str| server.client.post(server.url(&format!("/b64/store/raw/_wA/{}", chunk_hash))).body("x").send();
+    assert_eq!(raw("0x0").await.unwrap().status(), 200);
+    let (a, b, c) = tokio::join!(raw("0x1"), raw("0x2"), raw("0x3"));
+    let statuses = [a.unwrap().status(), b.unwrap().status(), c.unwrap().status()];
+    assert!(statuses.contains(&reqwest::StatusCode::OK));
+    assert!(statuses.contains(&reqwest::StatusCode::TOO_MANY_REQUESTS));
+
+    // Xóa file cũng cần suất ghi
+    let (stored, deleted) = tokio::join!(
+        server.store("0xbig", "0xa", b"f", Some(10)),
+        server.client.delete(server.url("/file/0xbig")).send(),
+    );
+    assert!([stored.status(), deleted.unwrap().status()].contains(&reqwest::StatusCode::TOO_MANY_REQUESTS));
+}
+
+#[tokio::test]
+async fn batch_cas_and_patch_share_the_file_write_cap() {
+    let server = TestServer::with_config(&[("STORAGE_MAX_REQUESTS_PER_FILE", "1"), ("STORAGE_FLUSH_MIN_INTERVAL_MS", "300")]).await;
+    server.store("0xbig", "0x0", b"warm", Some(0)).await;
+    let busy = |statuses: [reqwest::StatusCode; 2]| statuses.contains(&reqwest::StatusCode::TOO_MANY_REQUESTS);
+
+    // Batch giữ một suất cho mỗi fileKey khác nhau, dù có nhiều chunk của cùng file
+    let batch = serde_json::json!([
+        {"fileKey": "0xbig", "chunkHash": "0x1", "chunkData": "YQ=="},
+        {"fileKey": "0xbig", "chunkHash": "0x2", "chunkData": "Yg=="},
+    ]);
+    let (stored, batched) = tokio::join!(server.store("0xbig", "0x3", b"c", Some(3)), server.post_json("/store/batch", &batch));
+    assert!(busy([stored.status(), batched.status()]));
+
+    let cas = serde_json::json!({"expected": null, "new": "ZA=="});
+    let (stored, swapped) = tokio::join!(server.store("0xbig", "0x4", b"e", Some(4)), server.post_json("/chunk/0xbig/0x5/cas", &cas));
+    assert!(busy([stored.status(), swapped.status()]));
+
+    let patch = server.client.patch(server.url("/chunk/0xbig/0x0")).json(&serde_json::json!({"index": 7})).send();
+    let (stored, patched) = tokio::join!(server.store("0xbig", "0x6", b"f", Some(6)), patch);
+    assert!(busy([stored.status(), patched.unwrap().status()]));
+}
+
+#[tokio::test]
+async fn content_type_allowlist_rejects_other_types_with_415() {
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+    let server = TestServer::with_config(&[("STORAGE_ALLOWED_CONTENT_TYPES", "image/*, application/pdf")]).await;
+    let store = |hash: &str, data: &[u8], index: u64, content_type: Option<&str>| {
+        let body = serde_json::json!({
+            "fileKey": "0xfile",
+            "chunkHash": hash,
+            "chunkData": STANDARD.encode(data),
+            "chunkIndex": index,
+            "contentType": content_type,
+        });
+        server.client.post(server.url("/store")).json(&body).send()
+    };
+
+    let response = store("0x1", b"<html>", 0, Some("text/html")).await.unwrap();
+    assert_eq!(response.status(), 415);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "unsupported_media_type");
+    assert_eq!(store("0x1", b"img", 0, Some("IMAGE/PNG; q=1")).await.unwrap().status(), 201);
+
+    // Không khai báo: chunk đầu tiên quyết định kiểu của file qua chữ ký ở đầu dữ liệu
+    assert_eq!(store("0x2", b"\x89PNG\r\n\x1a\n....", 0, None).await.unwrap().status(), 201);
+    assert_eq!(store("0x3", b"plain text", 0, None).await.unwrap().status(), 415);
+    assert_eq!(store("0x4", b"plain text", 1, None).await.unwrap().status(), 201);
+
+    let raw = |path: &str, body: &'static [u8]| server.client.post(server.url(path)).body(body).send();
+    assert_eq!(raw("/store/raw/0xdoc/0x1?index=0", b"%PDF-1.7 ...").await.unwrap().status(), 200);
+    assert_eq!(raw("/store/raw/0xdoc/0x2?index=0", b"MZ executable").await.unwrap().status(), 415);
+    // fileKey nhị phân (base64url của [0xff, 0x00]) không bỏ qua được danh sách cho phép
+    assert_eq!(raw("/b64/store/raw/_wA/0x2?index=0", b"MZ executable").await.unwrap().status(), 415);
+    assert_eq!(raw("/b64/store/raw/_wA/0x1?index=0", b"%PDF-1.7 ...").await.unwrap().status(), 200);
+
+    let batch = serde_json::json!([
+        {"fileKey": "0xb", "chunkHash": "0x1", "chunkData": "aW1n", "contentType": "image/gif"},
+        {"fileKey": "0xb", "chunkHash": "0x2", "chunkData": "aW1n", "contentType": "text/plain"},
+    ]);
+    assert_eq!(server.post_json("/store/batch", &batch).await.status(), 415);
+    assert!(server.chunks("0xb").await.is_empty());
+
+    let patch = server
+        .client
+        .patch(server.url("/chunk/0xfile/0x1"))
+        .json(&serde_json::json!({"contentType": "text/plain"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(patch.status(), 415);
+}
+
+#[tokio::test]
+async fn diagnostics_bundles_health_signals_behind_the_admin_key() {
+    let server = TestServer::with_config(&[("STORAGE_ADMIN_API_KEY", "secret")]).await;
+    let diagnostics = || server.client.get(server.url("/diagnostics")).header("x-api-key", "secret").send();
+    assert_eq!(server.get("/diagnostics").await.status(), 401);
+
+    server.store("0xfile", "0x1", b"one", Some(0)).await;
+    server.store("0xfile", "0x2", b"two", Some(1)).await;
+    let body: serde_json::Value = diagnostics().await.unwrap().json().await.unwrap();
+    assert_eq!(body["status"], "ok");
+    assert_eq!(body["ready"], true);
+    assert_eq!(body["chunkKeys"], 2);
+    assert_eq!(body["fileKeys"], 1);
+    assert_eq!(body["buffered"], 0);
+    assert_eq!(body["replicationQueue"], 0);
+    assert!(body["sizeOnDisk"].as_u64().unwrap() > 0);
+    assert!(body["lastFlush"].as_u64().unwrap() > 0);
+
+    // Số key được giữ lại giữa các lần gọi thay vì quét lại database
+    server.store("0xother", "0x1", b"three", Some(0)).await;
+    let body: serde_json::Value = diagnostics().await.unwrap().json().await.unwrap();
+    assert_eq!(body["chunkKeys"], 2);
+    assert_eq!(body["fileKeys"], 2);
+}
+
+#[tokio::test]
+async fn concurrent_reads_of_one_file_share_a_scan() {
+    let server = TestServer::with_config(&[("STORAGE_READ_COALESCING", "1")]).await;
+    let batch: Vec<_> = (0..3000)
+        .map(|i| serde_json::json!({"fileKey": "0xhot", "chunkHash": format!("0x{:04x}", i), "chunkData": "ZGF0YQ==", "chunkIndex": i}))
+        .collect();
+    assert_eq!(server.post_json("/store/batch", &serde_json::Value::from(batch)).await.status(), 200);
+
+    let mut reads = tokio::task::JoinSet::new();
+    for _ in 0..50 {
+        let request = server.client.get(server.url("/file/0xhot")).send();
+        reads.spawn(async move { request.await.unwrap().bytes().await.unwrap() });
+    }
+    let mut bodies = Vec::new();
+    while let Some(body) = reads.join_next().await {
+        bodies.push(body.unwrap());
+    }
+    assert!(bodies.windows(2).all(|pair| pair[0] == pair[1]));
+    let body: serde_json::Value = serde_json::from_slice(&bodies[0]).unwrap();
+    assert_eq!(body["chunks"].as_array().unwrap().len(), 3000);
+
+    let metrics = server.get("/metrics").await.text().await.unwrap();
+    let line = metrics.lines().find(|l| l.starts_with("storage_coalesced_reads_total ")).unwrap();
+    let coalesced: u64 = line.rsplit(' ').next().unwrap().parse().unwrap();
+    assert!(coalesced > 0, "không có lượt đọc nào dùng chung lượt quét");
+
+    // Lượt đọc sau khi các lượt quét đã xong thấy dữ liệu mới
+    server.store("0xhot", "0xnew", b"new", Some(3000)).await;
+    assert_eq!(server.chunks("0xhot").await.len(), 3001);
+}
+
+#[tokio::test]
+async fn auth_requires_a_token_with_enough_scope() {
+    let server = TestServer::with_config(&[
+        ("STORAGE_AUTH", "1"),
+        ("STORAGE_ADMIN_API_KEY", "secret"),
+        ("STORAGE_API_TOKENS", "reader=read"),
+    ])
+    .await;
+    let chunk = serde_json::json!({"fileKey": "0xfile", "chunkHash": "0x1", "chunkData": "ZGF0YQ=="});
+    let store = |token: &str| server.client.post(server.url("/store")).bearer_auth(token).json(&chunk).send();
+    let read = |token: &str| server.client.get(server.url("/file/0xfile")).bearer_auth(token).send();
+
+    assert_eq!(server.get("/health").await.status(), 200);
+    assert_eq!(server.post_json("/store", &chunk).await.status(), 401);
+    let response = store("reader").await.unwrap();
+    assert_eq!(response.status(), 403);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "insufficient_scope");
+
+    // Token tạo qua /admin/tokens được dùng ngay và thu hồi được
+    let mint = |scope: &str| {
+        server
+            .client
+            .post(server.url("/admin/tokens"))
+            .header("x-api-key", "secret")
+            .json(&serde_json::json!({"scope": scope, "name": "uploader"}))
+            .send()
+    };
+    let response = mint("write").await.unwrap();
+    assert_eq!(response.status(), 201);
+    let writer: serde_json::Value = response.json().await.unwrap();
+    let token = writer["token"].as_str().unwrap();
+    assert_eq!(store(token).await.unwrap().status(), 201);
+    assert_eq!(read("reader").await.unwrap().status(), 200);
+    assert_eq!(read(token).await.unwrap().status(), 200);
+    assert_eq!(mint("admin").await.unwrap().status(), 201);
+    let as_writer = server.client.post(server.url("/admin/tokens")).bearer_auth(token).json(&serde_json::json!({"scope": "admin"}));
+    assert_eq!(as_writer.send().await.unwrap().status(), 403);
+
+    let revoke = |id: &str| {
+        server
+            .client
+            .delete(server.url(&format!("/admin/tokens/{}", id)))
+            .header("x-api-key", "secret")
+            .send()
+    };
+    assert_eq!(revoke(writer["id"].as_str().unwrap()).await.unwrap().status(), 204);
+    assert_eq!(revoke(writer["id"].as_str().unwrap()).await.unwrap().status(), 404);
+    assert_eq!(store(token).await.unwrap().status(), 401);
+}
+
+#[tokio::test]
+async fn metrics_count_requests_per_route_and_report_storage_totals() {
+    let server = TestServer::start().await;
+    server.store("0xfile", "0x1", b"hello", Some(0)).await;
+    server.chunks("0xfile").await;
+    server.chunks("0xfile").await;
+    assert_eq!(server.get("/no/such/route").await.status(), 404);
+
+    let metrics = server.get("/metrics").await.text().await.unwrap();
+    let value = |series: &str| -> f64 {
+        let line = metrics.lines().find(|l| l.starts_with(series) && l[series.len()..].starts_with(' ')).unwrap();
+        line.rsplit(' ').next().unwrap().parse().unwrap()
+    };
+    assert_eq!(value(r#"storage_http_requests_total{method="POST",route="/store",status="201"}"#), 1.0);
+    assert_eq!(value(r#"storage_http_requests_total{method="GET",route="/file/:fileKey",status="200"}"#), 2.0);
+    assert_eq!(value(r#"storage_http_requests_total{method="GET",route="unmatched",status="404"}"#), 1.0);
+    assert_eq!(value(r#"storage_http_request_duration_seconds_count{method="GET",route="/file/:fileKey"}"#), 2.0);
+    assert_eq!(value(r#"storage_http_request_duration_seconds_bucket{method="GET",route="/file/:fileKey",le="+Inf"}"#), 2.0);
+    assert_eq!(value("storage_chunks"), 1.0);
+    assert!(value("storage_stored_bytes") > 0.0);
+    assert!(value("storage_db_size_bytes") > 0.0);
+    assert_eq!(value("storage_flush_failures_total"), 0.0);
+}
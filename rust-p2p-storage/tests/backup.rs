@@ -0,0 +1,33 @@
+// Test tích hợp cho snapshot database định kỳ (STORAGE_SNAPSHOT_DIR).
+
+use rust_p2p_storage::create_snapshot;
+
+#[test]
+fn snapshots_copy_every_tree_and_keep_only_the_newest() {
+    let db_dir = tempfile::TempDir::new().unwrap();
+    let snapshot_dir = tempfile::TempDir::new().unwrap();
+    let db = sled::open(db_dir.path()).unwrap();
+    db.open_tree("shard-0").unwrap().insert("0xfile:0x1", "chunk").unwrap();
+
+    let first = create_snapshot(&db, snapshot_dir.path(), 2, 1_000).unwrap();
+    assert!(first.bytes > 0);
+    assert_eq!(first.pruned, 0);
+    db.open_tree("shard-0").unwrap().insert("0xfile:0x2", "later").unwrap();
+    create_snapshot(&db, snapshot_dir.path(), 2, 2_000).unwrap();
+    let third = create_snapshot(&db, snapshot_dir.path(), 2, 3_000).unwrap();
+    assert_eq!(third.pruned, 1);
+    // Cùng thời điểm với snapshot đã có thì không ghi đè
+    assert!(create_snapshot(&db, snapshot_dir.path(), 2, 3_000).is_err());
+
+    let mut names: Vec<String> = std::fs::read_dir(snapshot_dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["snapshot-2000", "snapshot-3000"]);
+
+    let restored = sled::open(&third.path).unwrap();
+    let shard = restored.open_tree("shard-0").unwrap();
+    assert_eq!(shard.len(), 2);
+    assert_eq!(shard.get("0xfile:0x1").unwrap().unwrap(), "chunk");
+}
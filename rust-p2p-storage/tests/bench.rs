@@ -0,0 +1,47 @@
+use rust_p2p_storage::{bench, Config};
+
+#[test]
+fn bench_options_parse_flags_and_reject_unknown_ones() {
+    let args = ["--ops", "50", "--concurrency", "4"].map(String::from);
+    let options = bench::Options::from_args(args).unwrap();
+    assert_eq!(options.ops, 50);
+    assert_eq!(options.concurrency, 4);
+
+    assert!(bench::Options::from_args(["--ops".to_string()]).is_err());
+    assert!(bench::Options::from_args(["--speed", "1"].map(String::from)).is_err());
+    assert!(bench::Options::from_args(["--ops", "0"].map(String::from)).is_err());
+}
+
+#[tokio::test]
+async fn bench_runs_against_a_temporary_database() {
+    let args = ["--ops", "40", "--concurrency", "4", "--chunk-size", "64", "--large-size", "4096", "--cache-bytes", "1048576"]
+        .map(String::from);
+    let config = Config::from_pairs([]);
+    bench::run(bench::Options::from_args(args).unwrap(), config).await;
+}
+
+#[tokio::test]
+async fn bench_batch_ingest_runs_with_parallel_workers() {
+    let args = ["--ops", "64", "--concurrency", "2", "--chunk-size", "64", "--batch-size", "32"].map(String::from);
+    let config = Config::from_pairs([("STORAGE_BATCH_PARALLELISM", "4"), ("STORAGE_BATCH_PARALLEL_MIN_CHUNKS", "8")]);
+    bench::run(bench::Options::from_args(args).unwrap(), config).await;
+}
+
+#[tokio::test]
+async fn bench_reads_one_large_file_with_parallel_fetch() {
+    let args = ["--ops", "512", "--concurrency", "2", "--chunk-size", "16", "--chunks-per-file", "512", "--reads", "4"]
+        .map(String::from);
+    let options = bench::Options::from_args(args).unwrap();
+    assert_eq!(options.reads, Some(4));
+    let config = Config::from_pairs([("STORAGE_READ_PARALLELISM", "4"), ("STORAGE_PARALLEL_READ_MIN_CHUNKS", "64")]);
+    bench::run(options, config).await;
+}
+
+#[tokio::test]
+async fn bench_hot_reads_storm_one_file_with_coalescing() {
+    let args = ["--ops", "256", "--concurrency", "8", "--chunk-size", "16", "--chunks-per-file", "256", "--reads", "1", "--hot-reads", "64"]
+        .map(String::from);
+    let options = bench::Options::from_args(args).unwrap();
+    assert_eq!(options.hot_reads, 64);
+    bench::run(options, Config::from_pairs([("STORAGE_READ_COALESCING", "1")])).await;
+}
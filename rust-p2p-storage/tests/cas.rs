@@ -0,0 +1,94 @@
+// Test tích hợp cho POST /chunk/:fileKey/:chunkHash/cas.
+
+mod common;
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use common::{chunk_bytes, TestServer};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+const CAS_PATH: &str = "/chunk/0xfile/0xcounter/cas";
+
+async fn cas(server: &TestServer, expected: Option<&[u8]>, new: &[u8]) -> reqwest::Response {
+    let body = json!({
+        "expected": expected.map(|data| STANDARD.encode(data)),
+        "new": STANDARD.encode(new),
+    });
+    server.post_json(CAS_PATH, &body).await
+}
+
+#[tokio::test]
+async fn cas_only_swaps_when_the_current_value_matches() {
+    let server = TestServer::with_config(&[("STORAGE_COMPRESSION", "zstd"), ("STORAGE_ORDER_INDEX", "1")]).await;
+
+    // expected = null tạo chunk mới, lần thứ hai thất bại vì chunk đã tồn tại
+    assert_eq!(cas(&server, None, b"v1").await.status(), 200);
+    let response = cas(&server, None, b"v2").await;
+    assert_eq!(response.status(), 409);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "cas_mismatch");
+    assert_eq!(body["current"], STANDARD.encode(b"v1"));
+
+    assert_eq!(cas(&server, Some(b"stale"), b"v2").await.status(), 409);
+    assert_eq!(cas(&server, Some(b"v1"), b"v2").await.status(), 200);
+
+    let chunks = server.chunks("0xfile").await;
+    assert_eq!(chunks.len(), 1);
+    assert_eq!(chunk_bytes(&chunks[0]), b"v2");
+
+    let invalid = json!({"expected": "không phải base64", "new": STANDARD.encode(b"v3")});
+    assert_eq!(server.post_json(CAS_PATH, &invalid).await.status(), 400);
+}
+
+#[tokio::test]
+async fn concurrent_cas_increments_are_never_lost() {
+    const TASKS: u64 = 8;
+    const INCREMENTS: u64 = 10;
+
+    let server = Arc::new(TestServer::start().await);
+    assert_eq!(cas(&server, None, b"0").await.status(), 200);
+
+    let mut tasks = Vec::new();
+    for _ in 0..TASKS {
+        let server = server.clone();
+        tasks.push(tokio::spawn(async move {
+            let mut current = b"0".to_vec();
+            for _ in 0..INCREMENTS {
+                // Đọc-sửa-ghi: khi thua thì lấy giá trị hiện tại từ response 409 và thử lại
+                loop {
+                    let next: u64 = std::str::from_utf8(&current).unwrap().parse::<u64>().unwrap() + 1;
+                    let next = next.to_string().into_bytes();
+                    let response = cas(&server, Some(&current), &next).await;
+                    if response.status() == 200 {
+                        current = next;
+                        break;
+                    }
+                    assert_eq!(response.status(), 409);
+                    let body: Value = response.json().await.unwrap();
+                    current = STANDARD.decode(body["current"].as_str().unwrap()).unwrap();
+                }
+            }
+        }));
+    }
+    for task in tasks {
+        task.await.unwrap();
+    }
+
+    let chunks = server.chunks("0xfile").await;
+    assert_eq!(chunk_bytes(&chunks[0]), (TASKS * INCREMENTS).to_string().into_bytes());
+}
+
+#[tokio::test]
+async fn cas_that_creates_a_chunk_is_checked_like_store() {
+    let server = TestServer::with_config(&[("STORAGE_MAX_CHUNK_SIZE", "16")]).await;
+    let body = json!({"expected": null, "new": STANDARD.encode([7u8; 100])});
+    let response = server.post_json("/chunk/0xfile/0x1/cas", &body).await;
+    assert_eq!(response.status(), 413);
+
+    // fileKey chứa ':' hoặc ký tự điều khiển bị từ chối như trên /store
+    let small = json!({"expected": null, "new": STANDARD.encode(b"ok")});
+    assert_eq!(server.post_json("/chunk/a:b/h1/cas", &small).await.status(), 400);
+    assert_eq!(server.post_json("/chunk/a%0Ab/h2/cas", &small).await.status(), 400);
+    assert!(server.chunks("a").await.is_empty());
+    assert_eq!(server.post_json("/chunk/0xfile/0x1/cas", &small).await.status(), 200);
+}
@@ -0,0 +1,81 @@
+// Test tích hợp cho thách thức chứng minh lưu trữ (`/challenge`, `/challenge/sample`).
+
+mod common;
+
+use common::TestServer;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+#[tokio::test]
+async fn challenge_proves_the_stored_bytes_of_a_chunk() {
+    let server = TestServer::start().await;
+    server.store("0xfile", "0xa", b"stored data", None).await;
+
+    let body = json!({"fileKey": "0xfile", "chunkHash": "0xa", "nonce": "00ff10"});
+    let response = server.post_json("/challenge", &body).await;
+    assert_eq!(response.status(), 200);
+    let answer: Value = response.json().await.unwrap();
+    let expected = Sha256::new().chain_update([0x00, 0xff, 0x10]).chain_update(b"stored data").finalize();
+    assert_eq!(answer["proof"], hex::encode(expected));
+    assert_eq!(answer["size"], 11);
+
+    let missing = json!({"fileKey": "0xfile", "chunkHash": "0xb", "nonce": "00"});
+    assert_eq!(server.post_json("/challenge", &missing).await.status(), 404);
+    let bad_nonce = json!({"fileKey": "0xfile", "chunkHash": "0xa", "nonce": "not hex"});
+    assert_eq!(server.post_json("/challenge", &bad_nonce).await.status(), 400);
+}
+
+#[tokio::test]
+async fn sampled_challenge_can_be_recomputed_by_the_auditor() {
+    let server = TestServer::with_config(&[("STORAGE_CHALLENGE_MAX_SAMPLES", "8")]).await;
+    let chunks: Vec<(String, Vec<u8>)> =
+        (0..3u8).map(|i| (format!("0x{}", i), (0..100u8).map(|b| b.wrapping_mul(i + 1)).collect())).collect();
+    for (hash, data) in &chunks {
+        server.store("0xfile", hash, data, None).await;
+    }
+    let nonce = [7u8; 16];
+
+    let body = json!({"fileKey": "0xfile", "nonce": hex::encode(nonce), "samples": 8, "length": 10});
+    let response = server.post_json("/challenge/sample", &body).await;
+    assert_eq!(response.status(), 200);
+    let answer: Value = response.json().await.unwrap();
+    assert_eq!(answer["chunks"], 3);
+
+    // Bên kiểm toán tính lại từ bản dữ liệu của mình
+    let mut combined = Sha256::new();
+    for (i, sample) in answer["samples"].as_array().unwrap().iter().enumerate() {
+        let seed = Sha256::new().chain_update(nonce).chain_update((i as u64).to_be_bytes()).finalize();
+        let word = |at: usize| u64::from_be_bytes(seed[at..at + 8].try_into().unwrap());
+        let (hash, data) = &chunks[(word(0) % 3) as usize];
+        let offset = (word(8) % (data.len() as u64 - 10 + 1)) as usize;
+        assert_eq!(sample["chunkHash"], hash.as_str());
+        assert_eq!(sample["offset"], offset);
+        let proof = Sha256::new()
+            .chain_update(nonce)
+            .chain_update((i as u64).to_be_bytes())
+            .chain_update(&data[offset..offset + 10])
+            .finalize();
+        assert_eq!(sample["proof"], hex::encode(proof));
+        combined.update(proof);
+    }
+    assert_eq!(answer["proof"], hex::encode(combined.finalize()));
+
+    let too_many = json!({"fileKey": "0xfile", "nonce": "01", "samples": 9});
+    assert_eq!(server.post_json("/challenge/sample", &too_many).await.status(), 400);
+    let unknown = json!({"fileKey": "0xnone", "nonce": "01", "samples": 1});
+    assert_eq!(server.post_json("/challenge/sample", &unknown).await.status(), 404);
+}
+
+#[tokio::test]
+async fn read_tokens_may_answer_challenges() {
+    let server = TestServer::with_config(&[("STORAGE_AUTH", "1"), ("STORAGE_API_TOKENS", "reader=read,writer=write")]).await;
+    let data = json!({"fileKey": "0xfile", "chunkHash": "0xa", "chunkData": "YQ=="});
+    let stored = server.client.post(server.url("/store")).header("x-api-key", "writer").json(&data).send().await.unwrap();
+    assert_eq!(stored.status(), 201);
+
+    let body = json!({"fileKey": "0xfile", "chunkHash": "0xa", "nonce": "01"});
+    let response = server.client.post(server.url("/challenge")).header("x-api-key", "reader").json(&body).send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    let store = server.client.post(server.url("/store")).header("x-api-key", "reader").json(&data).send().await.unwrap();
+    assert_eq!(store.status(), 403);
+}
@@ -0,0 +1,93 @@
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+const BIN: &str = env!("CARGO_BIN_EXE_rust-p2p-storage");
+
+fn free_port() -> u16 {
+    std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+#[test]
+fn unknown_flags_stop_the_server() {
+    let output = Command::new(BIN).arg("--verbose").stdout(Stdio::null()).output().unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--verbose"));
+}
+
+#[test]
+fn help_lists_the_flags_and_exits() {
+    let output = Command::new(BIN).arg("--help").output().unwrap();
+    assert!(output.status.success());
+    let usage = String::from_utf8_lossy(&output.stdout);
+    for flag in ["--config <file>", "--listen <addr>", "--data-dir <dir>", "--max-chunk-size <bytes>", "--log-level <level>"] {
+        assert!(usage.contains(flag), "thiếu {} trong:\n{}", flag, usage);
+    }
+    assert!(usage.contains("STORAGE_FLUSH_MIN_INTERVAL_MS"));
+}
+
+#[test]
+fn malformed_toml_stops_the_server() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let config = dir.path().join("storage.toml");
+    std::fs::write(&config, "http_addr = 127.0.0.1:1\n").unwrap();
+    let output = Command::new(BIN).args(["--config", config.to_str().unwrap()]).stdout(Stdio::null()).output().unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("TOML"));
+}
+
+#[tokio::test]
+async fn server_reads_a_toml_config_and_command_line_overrides() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let (http_port, grpc_port) = (free_port(), free_port());
+    let config = dir.path().join("storage.toml");
+    std::fs::write(
+        &config,
+        format!(
+            "# Cấu hình thử\nhttp_addr = \"127.0.0.1:1\"\ngrpc_addr = \"127.0.0.1:{}\"\nSTORAGE_MAX_CHUNK_SIZE = 4\n\n[log]\nformat = \"json\"\n",
+            grpc_port
+        ),
+    )
+    .unwrap();
+
+    let listen = format!("127.0.0.1:{}", http_port);
+    let mut server = Command::new(BIN)
+        .args(["--config", config.to_str().unwrap(), "--listen", &listen, "--log-level", "info", "--max-chunk-size=8"])
+        .args(["--data-dir", dir.path().join("db").to_str().unwrap()])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let client = reqwest::Client::new();
+    let mut status = None;
+    for _ in 0..100 {
+        if let Ok(response) = client.get(format!("http://{}/health", listen)).send().await {
+            status = Some(response.status());
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert_eq!(status.map(|s| s.as_u16()), Some(200));
+
+    // --max-chunk-size thắng giá trị trong file
+    let store = |data: &str| {
+        let body = serde_json::json!({"fileKey": "0xfile", "chunkHash": "0xc", "chunkData": data});
+        client.post(format!("http://{}/store", listen)).json(&body).send()
+    };
+    assert_eq!(store("AAAAAAAAAAA=").await.unwrap().status(), 201);
+    assert_eq!(store("AAAAAAAAAAAA").await.unwrap().status(), 400);
+
+    server.kill().unwrap();
+    let output = server.wait_with_output().unwrap();
+    assert!(dir.path().join("db").exists());
+
+    // Log dạng JSON, dòng kết thúc request /store nằm trong span có fileKey và status
+    let logs = String::from_utf8_lossy(&output.stdout);
+    let stored = logs
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .find(|line| line["span"]["route"] == "/store" && line["fields"]["status"] == 201)
+        .expect("không có dòng log JSON của request /store");
+    assert_eq!(stored["span"]["fileKey"], "0xfile");
+    assert_eq!(stored["span"]["method"], "POST");
+    assert!(stored["fields"]["latency"].is_string());
+}
@@ -0,0 +1,134 @@
+// Harness dùng chung cho các test tích hợp: chạy router thật trên một cổng ngẫu nhiên
+// với database sled nằm trong thư mục tạm, rồi gọi vào qua HTTP như client thật.
+
+#![allow(dead_code)] // Mỗi file test chỉ dùng một phần các hàm tiện ích
+
+use axum::Router;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use rust_p2p_storage::{build_app, build_state, serve, AppState, Config};
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+/// API key quản trị mà các test đặt qua STORAGE_ADMIN_API_KEY
+pub const ADMIN_KEY: &str = "secret";
+
+pub struct TestServer {
+    pub addr: SocketAddr,
+    pub client: reqwest::Client,
+    pub state: Arc<AppState>,
+    // Giữ thư mục tạm (và database) cho tới khi server bị drop
+    _dir: TempDir,
+}
+
+impl TestServer {
+    /// Server với cấu hình mặc định
+    pub async fn start() -> Self {
+        Self::with_config(&[]).await
+    }
+
+    /// Server với các biến cấu hình `STORAGE_*` được ghi đè
+    pub async fn with_config(pairs: &[(&str, &str)]) -> Self {
+        Self::with_router(pairs, build_app).await
+    }
+
+    /// Server với router tùy chỉnh được tạo từ state (ví dụ thêm route chỉ dùng trong test)
+    pub async fn with_router(pairs: &[(&str, &str)], router: impl FnOnce(Arc<AppState>) -> Router) -> Self {
+        Self::launch(pairs, |_| {}, router).await
+    }
+
+    /// Server với database được chuẩn bị sẵn trước khi mở (ví dụ ghi thẳng vào các tree)
+    pub async fn with_db(pairs: &[(&str, &str)], prepare: impl FnOnce(&sled::Db)) -> Self {
+        Self::launch(pairs, prepare, build_app).await
+    }
+
+    /// Như `with_db`, giữ lại database để test đọc thẳng các tree
+    pub async fn with_db_handle(pairs: &[(&str, &str)]) -> (Self, sled::Db) {
+        let mut handle = None;
+        let server = Self::with_db(pairs, |db| handle = Some(db.clone())).await;
+        (server, handle.unwrap())
+    }
+
+    async fn launch(
+        pairs: &[(&str, &str)],
+        prepare: impl FnOnce(&sled::Db),
+        router: impl FnOnce(Arc<AppState>) -> Router,
+    ) -> Self {
+        let dir = TempDir::new().expect("không tạo được thư mục tạm");
+        let db = sled::open(dir.path()).expect("không mở được database tạm");
+        prepare(&db);
+        let state = build_state(db, Config::from_pairs(pairs.iter().copied()));
+        let app = router(state.clone());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, app, state.max_connections(), std::future::pending()));
+
+        TestServer {
+            addr,
+            client: reqwest::Client::new(),
+            state,
+            _dir: dir,
+        }
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+
+    pub async fn get(&self, path: &str) -> reqwest::Response {
+        self.client.get(self.url(path)).send().await.unwrap()
+    }
+
+    pub async fn post_json(&self, path: &str, body: &Value) -> reqwest::Response {
+        self.client.post(self.url(path)).json(body).send().await.unwrap()
+    }
+
+    /// Gọi route quản trị với API key `ADMIN_KEY`
+    pub async fn admin(&self, method: reqwest::Method, path: &str) -> reqwest::Response {
+        self.client
+            .request(method, self.url(path))
+            .header("x-api-key", ADMIN_KEY)
+            .send()
+            .await
+            .unwrap()
+    }
+
+    /// Kiểm tra `GET /admin/consistency` không báo sai lệch nào
+    pub async fn assert_consistent(&self) {
+        let report: Value = self.admin(reqwest::Method::GET, "/admin/consistency").await.json().await.unwrap();
+        assert_eq!(report["consistent"], true, "{}", report);
+    }
+
+    /// Lưu một chunk qua `/store` với dữ liệu gốc `data`
+    pub async fn store(&self, file_key: &str, chunk_hash: &str, data: &[u8], index: Option<u64>) -> reqwest::Response {
+        let mut body = json!({
+            "fileKey": file_key,
+            "chunkHash": chunk_hash,
+            "chunkData": STANDARD.encode(data),
+        });
+        if let Some(index) = index {
+            body["chunkIndex"] = json!(index);
+        }
+        self.post_json("/store", &body).await
+    }
+
+    /// Lấy danh sách chunk của một file qua `/file/:fileKey`
+    pub async fn chunks(&self, file_key: &str) -> Vec<Value> {
+        let response = self.get(&format!("/file/{}", file_key)).await;
+        assert_eq!(response.status(), 200);
+        let body: Value = response.json().await.unwrap();
+        body["chunks"].as_array().cloned().unwrap_or_default()
+    }
+}
+
+/// Giải Base64 trường `value` của một chunk trong response
+pub fn chunk_bytes(chunk: &Value) -> Vec<u8> {
+    STANDARD.decode(chunk["value"].as_str().expect("chunk phải có value")).expect("value phải là Base64")
+}
+
+/// `len` byte dữ liệu giả, khác nhau theo `seed`
+pub fn data(len: usize, seed: u8) -> Vec<u8> {
+    (0..len).map(|i| (i as u8).wrapping_mul(31).wrapping_add(seed)).collect()
+}
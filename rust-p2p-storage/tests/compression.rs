@@ -0,0 +1,75 @@
+// Kiểm thử cho việc nén chunk khi lưu (STORAGE_COMPRESSION): codec lz4 luôn giải nén lại
+// đúng dữ liệu gốc, không panic với dữ liệu hỏng, và server bỏ qua dữ liệu đã nén sẵn.
+
+mod common;
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use common::{chunk_bytes, TestServer};
+use proptest::prelude::*;
+use rust_p2p_storage::compress;
+
+proptest! {
+    #[test]
+    fn lz4_round_trips(
+        // Bảng chữ cái nhỏ để có nhiều match, kể cả match dài và chồng lên nhau
+        repetitive in proptest::collection::vec(0u8..4, 0..2048),
+        random in proptest::collection::vec(any::<u8>(), 0..512),
+    ) {
+        for raw in [repetitive, random] {
+            let packed = compress::lz4_compress(&raw);
+            prop_assert_eq!(compress::lz4_decompress(&packed), Some(raw));
+        }
+    }
+
+    #[test]
+    fn lz4_decompress_never_panics(bytes in proptest::collection::vec(any::<u8>(), 0..256)) {
+        let _ = compress::lz4_decompress(&bytes);
+    }
+}
+
+#[test]
+fn already_compressed_or_tiny_data_is_not_worth_compressing() {
+    let text = "chunk dữ liệu dạng văn bản lặp lại nhiều lần. ".repeat(20);
+    assert!(compress::worth_compressing(text.as_bytes()));
+    assert!(!compress::worth_compressing(b"short"));
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    png.resize(1024, 0);
+    assert!(!compress::worth_compressing(&png));
+
+    // Dữ liệu gần như ngẫu nhiên (mỗi giá trị byte xuất hiện đều nhau)
+    let noise: Vec<u8> = (0..4096u32).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8).collect();
+    assert!(!compress::worth_compressing(&noise));
+}
+
+#[tokio::test]
+async fn lz4_chunks_are_stored_compressed_and_served_either_way() {
+    let server = TestServer::with_config(&[("STORAGE_COMPRESSION", "lz4")]).await;
+    let text = "lorem ipsum dolor sit amet ".repeat(200);
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    png.resize(2048, 7);
+    assert_eq!(server.store("0xfile", "0x1", text.as_bytes(), Some(0)).await.status(), 201);
+    assert_eq!(server.store("0xfile", "0x2", &png, Some(1)).await.status(), 201);
+
+    let chunks = server.chunks("0xfile").await;
+    assert_eq!(chunks[0]["encoding"], "identity");
+    assert_eq!(chunk_bytes(&chunks[0]), text.as_bytes());
+    assert_eq!(chunk_bytes(&chunks[1]), png);
+
+    let response = server
+        .client
+        .get(server.url("/file/0xfile"))
+        .header("accept-chunk-encoding", "lz4")
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = response.json().await.unwrap();
+    let chunks = body["chunks"].as_array().unwrap();
+    assert_eq!(chunks[0]["encoding"], "lz4");
+    let packed = STANDARD.decode(chunks[0]["value"].as_str().unwrap()).unwrap();
+    assert!(packed.len() < text.len() / 10);
+    assert_eq!(compress::lz4_decompress(&packed).unwrap(), text.as_bytes());
+    // PNG đã nén sẵn nên được lưu nguyên bản
+    assert_eq!(chunks[1]["encoding"], "identity");
+    assert_eq!(chunk_bytes(&chunks[1]), png);
+}
@@ -0,0 +1,160 @@
+// Test tích hợp cho việc khử trùng lặp chunk theo nội dung (STORAGE_DEDUP) và
+// `POST /admin/dedup/migrate`.
+
+mod common;
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use common::{chunk_bytes, data, TestServer, ADMIN_KEY};
+use serde_json::{json, Value};
+
+// Số tham chiếu của từng blob trong tree `chunks`
+fn blob_counts(db: &sled::Db) -> Vec<u64> {
+    let chunks = db.open_tree("chunks").unwrap();
+    chunks
+        .iter()
+        .values()
+        .map(|entry| u64::from_be_bytes(entry.unwrap()[..8].try_into().unwrap()))
+        .collect()
+}
+
+#[tokio::test]
+async fn identical_chunks_share_one_blob_until_the_last_reference_is_gone() {
+    let (server, db) = TestServer::with_db_handle(&[("STORAGE_DEDUP", "1"), ("STORAGE_ADMIN_API_KEY", ADMIN_KEY)]).await;
+    let (shared, other) = (data(4096, 1), data(64, 2));
+    assert_eq!(server.store("0xa", "0x1", &shared, Some(0)).await.status(), 201);
+    assert_eq!(server.store("0xa", "0x2", &other, Some(1)).await.status(), 201);
+    assert_eq!(server.store("0xb", "0x1", &shared, Some(0)).await.status(), 201);
+    assert_eq!(server.store("0xc", "0x9", &shared, Some(0)).await.status(), 201);
+    let mut counts = blob_counts(&db);
+    counts.sort_unstable();
+    assert_eq!(counts, [1, 3]);
+    server.assert_consistent().await;
+
+    let chunks = server.chunks("0xa").await;
+    assert_eq!(chunks.iter().map(chunk_bytes).collect::<Vec<_>>(), [shared.clone(), other.clone()]);
+    assert_eq!(chunk_bytes(&server.chunks("0xb").await[0]), shared);
+
+    // Xóa file giảm số tham chiếu và xóa blob không còn ai dùng
+    assert_eq!(server.client.delete(server.url("/file/0xa")).send().await.unwrap().status(), 200);
+    assert_eq!(blob_counts(&db), [2]);
+
+    // Đổi tên giữ nguyên số tham chiếu; ghi đè fileKey đích bỏ tham chiếu của chunk bị thay
+    let rename = server.post_json("/file/0xb/rename?overwrite=true", &json!({"newFileKey": "0xc"})).await;
+    assert_eq!(rename.status(), 200);
+    assert_eq!(blob_counts(&db), [1]);
+    assert_eq!(chunk_bytes(&server.chunks("0xc").await[0]), shared);
+    server.assert_consistent().await;
+
+    assert_eq!(server.client.delete(server.url("/file/0xc")).send().await.unwrap().status(), 200);
+    assert!(blob_counts(&db).is_empty());
+    assert!(db.open_tree("files").unwrap().is_empty());
+    server.assert_consistent().await;
+}
+
+#[tokio::test]
+async fn overwriting_and_deleting_chunks_release_their_blobs() {
+    let (server, db) = TestServer::with_db_handle(&[
+        ("STORAGE_DEDUP", "1"),
+        ("STORAGE_OVERWRITE_MODE", "allow"),
+        ("STORAGE_ADMIN_API_KEY", ADMIN_KEY),
+    ])
+    .await;
+    assert_eq!(server.store("0xa", "0x1", &data(512, 1), None).await.status(), 201);
+    assert_eq!(server.store("0xa", "0x1", &data(512, 2), None).await.status(), 201);
+    assert_eq!(blob_counts(&db), [1]);
+    assert_eq!(chunk_bytes(&server.chunks("0xa").await[0]), data(512, 2));
+
+    // Ghi lại đúng nội dung cũ không tăng số tham chiếu
+    assert_eq!(server.store("0xa", "0x1", &data(512, 2), None).await.status(), 200);
+    assert_eq!(blob_counts(&db), [1]);
+
+    let delete = server.client.delete(server.url("/chunk/0xa/0x1")).send().await.unwrap();
+    assert_eq!(delete.status(), 200);
+    assert!(blob_counts(&db).is_empty());
+    server.assert_consistent().await;
+}
+
+#[tokio::test]
+async fn migration_moves_existing_chunks_into_shared_blobs() {
+    // Chunk nhỏ nằm trong record, chunk lớn nằm trong chunks_large trước khi chuyển
+    let (server, db) = TestServer::with_db_handle(&[("STORAGE_LARGE_CHUNK_BYTES", "1024"), ("STORAGE_ADMIN_API_KEY", ADMIN_KEY)]).await;
+    let (small, large) = (data(100, 1), data(4096, 2));
+    for file_key in ["0xa", "0xb"] {
+        assert_eq!(server.store(file_key, "0x1", &small, Some(0)).await.status(), 201);
+        assert_eq!(server.store(file_key, "0x2", &large, Some(1)).await.status(), 201);
+    }
+    assert_eq!(server.store("0xa", "0x3", &[], Some(2)).await.status(), 201);
+    assert!(blob_counts(&db).is_empty());
+
+    let response = server.admin(reqwest::Method::POST, "/admin/dedup/migrate").await;
+    assert_eq!(response.status(), 200);
+    let report: Value = response.json().await.unwrap();
+    assert_eq!(report, json!({"scanned": 5, "migrated": 4, "shared": 2, "skipped": 0}));
+    assert_eq!(blob_counts(&db), [2, 2]);
+    assert!(db.open_tree("chunks_large").unwrap().is_empty());
+    server.assert_consistent().await;
+
+    for file_key in ["0xa", "0xb"] {
+        let chunks = server.chunks(file_key).await;
+        assert_eq!(chunk_bytes(&chunks[0]), small);
+        assert_eq!(chunk_bytes(&chunks[1]), large);
+    }
+
+    // Chạy lại không còn gì để chuyển
+    let report: Value = server.admin(reqwest::Method::POST, "/admin/dedup/migrate").await.json().await.unwrap();
+    assert_eq!(report["migrated"], 0);
+    assert_eq!(server.admin(reqwest::Method::POST, "/admin/dedup/migrate").await.status(), 200);
+    assert_eq!(server.client.post(server.url("/admin/dedup/migrate")).send().await.unwrap().status(), 401);
+}
+
+#[tokio::test]
+async fn store_reports_deduplicated_chunks_and_space_saved() {
+    let (server, _db) = TestServer::with_db_handle(&[("STORAGE_DEDUP", "1")]).await;
+    let shared = data(4096, 1);
+    let first: Value = server.store("0xa", "0x1", &shared, None).await.json().await.unwrap();
+    assert_eq!(first, json!({"deduplicated": false}));
+    let second: Value = server.store("0xb", "0x1", &shared, None).await.json().await.unwrap();
+    assert_eq!(second, json!({"deduplicated": true}));
+
+    let stats: Value = server.get("/stats").await.json().await.unwrap();
+    let dedup = &stats["dedup"];
+    assert_eq!(dedup["ratio"], 2.0);
+    assert!(dedup["storedBytes"].as_u64().unwrap() > 4096);
+    assert_eq!(dedup["bytesSaved"], dedup["storedBytes"]);
+    assert_eq!(dedup["referencedBytes"].as_u64(), dedup["storedBytes"].as_u64().map(|b| b * 2));
+
+    let metrics = server.get("/metrics").await.text().await.unwrap();
+    assert!(metrics.contains("storage_dedup_chunks_total 1\n"), "{}", metrics);
+    let saved = format!("storage_dedup_bytes_saved_total {}\n", dedup["bytesSaved"]);
+    assert!(metrics.contains(&saved), "{}", metrics);
+}
+
+#[tokio::test]
+async fn concurrent_stores_of_the_same_bytes_keep_refcounts_exact() {
+    let (server, db) = TestServer::with_db_handle(&[("STORAGE_DEDUP", "1"), ("STORAGE_ADMIN_API_KEY", ADMIN_KEY)]).await;
+    let chunk_data = STANDARD.encode(data(2048, 7));
+    let mut stores = tokio::task::JoinSet::new();
+    for i in 0..32 {
+        let body = json!({"fileKey": format!("0xfile{}", i), "chunkHash": "0x1", "chunkData": chunk_data});
+        let request = server.client.post(server.url("/store")).json(&body);
+        stores.spawn(async move { request.send().await.unwrap().json::<Value>().await.unwrap() });
+    }
+    let mut new_blobs = 0;
+    while let Some(response) = stores.join_next().await {
+        new_blobs += usize::from(response.unwrap()["deduplicated"] == false);
+    }
+    assert_eq!(new_blobs, 1);
+    assert_eq!(blob_counts(&db), [32]);
+    server.assert_consistent().await;
+
+    let mut deletes = tokio::task::JoinSet::new();
+    for i in 0..32 {
+        let request = server.client.delete(server.url(&format!("/file/0xfile{}", i)));
+        deletes.spawn(async move { request.send().await.unwrap().status() });
+    }
+    while let Some(status) = deletes.join_next().await {
+        assert_eq!(status.unwrap(), 200);
+    }
+    assert!(blob_counts(&db).is_empty());
+    server.assert_consistent().await;
+}
@@ -0,0 +1,102 @@
+// Test tích hợp cho việc lưu chunk dạng delta (STORAGE_DELTA_ENCODING).
+
+mod common;
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use common::{chunk_bytes, TestServer};
+use serde_json::json;
+
+// Dữ liệu ít lặp lại để bản diff nhỏ hơn hẳn dữ liệu đầy đủ
+fn version(edit: u8) -> Vec<u8> {
+    let mut data: Vec<u8> = (0..8192u32).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8).collect();
+    data[100] = edit;
+    data[5000] = edit;
+    data
+}
+
+async fn store_with_base(server: &TestServer, hash: &str, data: &[u8], index: u64, base: &str) -> u16 {
+    let body = json!({
+        "fileKey": "0xfile",
+        "chunkHash": hash,
+        "chunkData": STANDARD.encode(data),
+        "chunkIndex": index,
+        "baseChunkHash": base,
+    });
+    server.post_json("/store", &body).await.status().as_u16()
+}
+
+#[tokio::test]
+async fn delta_chunks_are_rebuilt_on_every_read_path() {
+    let server = TestServer::with_config(&[("STORAGE_DELTA_ENCODING", "1"), ("STORAGE_COMPRESSION", "zstd")]).await;
+    server.store("0xfile", "0x1", &version(1), Some(0)).await;
+    assert_eq!(store_with_base(&server, "0x2", &version(2), 1, "0x1").await, 201);
+    // Base cũng là delta thì lưu đầy đủ, không tạo chuỗi delta
+    assert_eq!(store_with_base(&server, "0x3", &version(3), 2, "0x2").await, 201);
+
+    let mut chunks = server.chunks("0xfile").await;
+    chunks.sort_by_key(|c| c["index"].as_u64());
+    let data: Vec<Vec<u8>> = chunks.iter().map(chunk_bytes).collect();
+    assert_eq!(data, [version(1), version(2), version(3)]);
+
+    let download = server.get("/file/0xfile/stream-download").await.bytes().await.unwrap();
+    assert_eq!(download.as_ref(), [version(1), version(2), version(3)].concat());
+}
+
+#[tokio::test]
+async fn delta_chunks_count_their_diff_size_against_quota() {
+    // Đủ cho một bản đầy đủ cộng một bản diff, nhưng không đủ cho hai bản đầy đủ
+    let quota = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(quota.path(), r#"{"defaultBytes": 16000}"#).unwrap();
+    let quota_path = quota.path().to_str().unwrap();
+
+    let plain = TestServer::with_config(&[("STORAGE_QUOTA_FILE", quota_path)]).await;
+    plain.store("0xfile", "0x1", &version(1), Some(0)).await;
+    assert_eq!(store_with_base(&plain, "0x2", &version(2), 1, "0x1").await, 403);
+
+    let delta = TestServer::with_config(&[("STORAGE_QUOTA_FILE", quota_path), ("STORAGE_DELTA_ENCODING", "1")]).await;
+    delta.store("0xfile", "0x1", &version(1), Some(0)).await;
+    assert_eq!(store_with_base(&delta, "0x2", &version(2), 1, "0x1").await, 201);
+}
+
+#[tokio::test]
+async fn missing_base_or_disabled_mode_stores_full_chunks() {
+    for config in [&[("STORAGE_DELTA_ENCODING", "1")][..], &[]] {
+        let server = TestServer::with_config(config).await;
+        server.store("0xfile", "0x1", &version(1), Some(0)).await;
+        assert_eq!(store_with_base(&server, "0x2", &version(2), 1, "0xmissing").await, 201);
+        assert_eq!(store_with_base(&server, "0x3", &version(3), 2, "0x1").await, 201);
+
+        let mut chunks = server.chunks("0xfile").await;
+        chunks.sort_by_key(|c| c["index"].as_u64());
+        assert_eq!(chunk_bytes(&chunks[1]), version(2));
+        assert_eq!(chunk_bytes(&chunks[2]), version(3));
+    }
+}
+
+#[tokio::test]
+async fn paged_reads_rebuild_deltas_whose_base_is_on_another_page() {
+    for order in ["0", "1"] {
+        let server = TestServer::with_config(&[
+            ("STORAGE_DELTA_ENCODING", "1"),
+            ("STORAGE_COMPRESSION", "zstd"),
+            ("STORAGE_LARGE_CHUNK_BYTES", "1024"),
+            ("STORAGE_ORDER_INDEX", order),
+        ])
+        .await;
+        server.store("0xfile", "0x1", &version(1), Some(0)).await;
+        assert_eq!(store_with_base(&server, "0x2", &version(2), 1, "0x1").await, 201);
+        server.store("0xfile", "0x3", b"small", Some(2)).await;
+
+        let page: serde_json::Value = server.get("/file/0xfile?limit=1&after=0x1").await.json().await.unwrap();
+        assert_eq!(page["chunks"].as_array().unwrap().len(), 1, "{page}");
+        assert_eq!(page["chunks"][0]["key"], "0xfile:0x2");
+        assert_eq!(chunk_bytes(&page["chunks"][0]), version(2));
+        assert_eq!(page["nextCursor"], "0x2");
+
+        let first: serde_json::Value = server.get("/file/0xfile?limit=1").await.json().await.unwrap();
+        assert_eq!(chunk_bytes(&first["chunks"][0]), version(1));
+        let last: serde_json::Value = server.get("/file/0xfile?offset=2").await.json().await.unwrap();
+        assert_eq!(chunk_bytes(&last["chunks"][0]), b"small");
+        assert!(last.get("nextCursor").is_none());
+    }
+}
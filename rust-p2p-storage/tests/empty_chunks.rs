@@ -0,0 +1,60 @@
+// Chunk rỗng (chunkData "") là dữ liệu hợp lệ: phải được lưu, trả về và ghép lại như
+// mọi chunk khác, dù bật định dạng record, nén hay index thứ tự nào.
+
+mod common;
+
+use common::{chunk_bytes, TestServer};
+
+const CONFIGS: &[&[(&str, &str)]] = &[
+    &[],
+    &[
+        ("STORAGE_VALUE_FORMAT", "bare"),
+        ("STORAGE_COMPRESSION", "zstd"),
+        ("STORAGE_ORDER_INDEX", "1"),
+        ("STORAGE_VERIFY_WRITES", "1"),
+        ("STORAGE_OVERWRITE_MODE", "verify"),
+    ],
+];
+
+#[tokio::test]
+async fn empty_chunk_is_stored_and_returned_faithfully() {
+    for config in CONFIGS {
+        let server = TestServer::with_config(config).await;
+        assert_eq!(server.store("0xfile", "0xempty", b"", Some(0)).await.status(), 201);
+        // Ghi lại chunk rỗng giống hệt cũng không phải lỗi
+        assert_eq!(server.store("0xfile", "0xempty", b"", Some(0)).await.status(), 200);
+
+        let chunks = server.chunks("0xfile").await;
+        assert_eq!(chunks.len(), 1, "config {:?}", config);
+        assert_eq!(chunks[0]["value"], "");
+        assert_eq!(chunks[0]["index"], 0);
+        assert!(chunk_bytes(&chunks[0]).is_empty());
+
+        let hex: serde_json::Value = server.get("/file/0xfile?encoding=hex").await.json().await.unwrap();
+        assert_eq!(hex["chunks"][0]["value"], "0x");
+    }
+}
+
+#[tokio::test]
+async fn empty_chunks_keep_their_place_when_reassembling() {
+    for config in CONFIGS {
+        let server = TestServer::with_config(config).await;
+        server.store("0xfile", "0xc", b"tail", Some(3)).await;
+        server.store("0xfile", "0xa", b"head", Some(0)).await;
+        server.store("0xfile", "0xb", b"", Some(1)).await;
+        server.store("0xfile", "0xd", b"", Some(2)).await;
+
+        // Không bật index thứ tự thì client tự sắp xếp theo index như bình thường
+        let mut chunks = server.chunks("0xfile").await;
+        chunks.sort_by_key(|c| c["index"].as_u64());
+        let sizes: Vec<_> = chunks.iter().map(|c| (c["index"].as_u64().unwrap(), chunk_bytes(c).len())).collect();
+        assert_eq!(sizes, [(0, 4), (1, 0), (2, 0), (3, 4)], "config {:?}", config);
+
+        let tail: serde_json::Value = server.get("/file/0xfile/tail?n=2").await.json().await.unwrap();
+        assert_eq!(tail["chunks"].as_array().unwrap().len(), 2);
+
+        let download = server.get("/file/0xfile/stream-download").await;
+        assert_eq!(download.status(), 200);
+        assert_eq!(download.bytes().await.unwrap().as_ref(), b"headtail");
+    }
+}
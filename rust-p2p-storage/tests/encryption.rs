@@ -0,0 +1,96 @@
+// Test tích hợp cho mã hóa value khi lưu (STORAGE_ENCRYPTION_KEY, STORAGE_ENCRYPTION_KEY_FILE).
+
+mod common;
+
+use axum::body::Body;
+use axum::http::Request;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use common::chunk_bytes;
+use rust_p2p_storage::{build_app, build_state, AppState, Config};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tower::ServiceExt;
+
+const KEY: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+async fn send(state: &Arc<AppState>, path: &str, body: Option<Value>) -> (u16, Value) {
+    let request = Request::builder()
+        .method(if body.is_some() { "POST" } else { "GET" })
+        .uri(path)
+        .header("content-type", "application/json")
+        .body(body.map_or_else(Body::empty, |b| Body::from(b.to_string())))
+        .unwrap();
+    let response = build_app(state.clone()).oneshot(request).await.unwrap();
+    let status = response.status().as_u16();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&bytes).unwrap_or_default())
+}
+
+#[tokio::test]
+async fn values_are_encrypted_on_disk_and_tampering_fails_reads() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let db = sled::open(dir.path()).unwrap();
+    let config = [("STORAGE_ENCRYPTION_KEY", KEY), ("STORAGE_LARGE_CHUNK_BYTES", "1024")];
+    let state = build_state(db.clone(), Config::from_pairs(config));
+    let small = b"bi mat cua nguoi dung".repeat(3);
+    let large = b"du lieu lon ".repeat(200);
+    for (hash, bytes, index) in [("0x1", &small, 0), ("0x2", &large, 1)] {
+        let body = json!({"fileKey": "0xfile", "chunkHash": hash, "chunkData": STANDARD.encode(bytes), "chunkIndex": index});
+        assert_eq!(send(&state, "/store", Some(body)).await.0, 201);
+    }
+
+    // Trên đĩa không còn dữ liệu gốc lẫn metadata dạng chữ
+    let large_tree = db.open_tree("chunks_large").unwrap();
+    for value in [db.get("0xfile:0x1").unwrap().unwrap(), large_tree.get("0xfile:0x2").unwrap().unwrap()] {
+        assert_eq!(value[0], 0x03);
+        let text = String::from_utf8_lossy(&value);
+        assert!(!text.contains(&STANDARD.encode(&small)) && !text.contains("chunkIndex") && !text.contains("ZHUgbGll"));
+    }
+    let (_, body) = send(&state, "/file/0xfile", None).await;
+    let chunks: Vec<Vec<u8>> = body["chunks"].as_array().unwrap().iter().map(chunk_bytes).collect();
+    assert_eq!(chunks, [small.clone(), large.clone()]);
+
+    // Sửa một byte của ciphertext: đọc chunk báo lỗi thay vì trả dữ liệu sai
+    let mut value = db.get("0xfile:0x1").unwrap().unwrap().to_vec();
+    let last = value.len() - 20;
+    value[last] ^= 0x01;
+    db.insert("0xfile:0x1", value).unwrap();
+    assert_eq!(send(&state, "/file/0xfile/chunk/0x1", None).await.0, 500);
+    let (_, body) = send(&state, "/file/0xfile", None).await;
+    let chunks = body["chunks"].as_array().unwrap();
+    assert_eq!(chunks.len(), 1);
+    assert_eq!(chunk_bytes(&chunks[0]), large);
+}
+
+#[tokio::test]
+async fn key_file_holds_raw_bytes_and_restarts_read_old_values() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let key_file = dir.path().join("node.key");
+    std::fs::write(&key_file, [7u8; 32]).unwrap();
+    // Giữ cùng một handle sled: sled 0.34 nhả khóa thư mục trên thread nền sau khi handle
+    // cuối bị drop, nên mở lại ngay có thể thất bại khi máy đang bận
+    let db = sled::open(dir.path().join("db")).unwrap();
+    let config = [("STORAGE_ENCRYPTION_KEY_FILE", key_file.to_str().unwrap())];
+
+    let state = build_state(db.clone(), Config::from_pairs(config));
+    let body = json!({"fileKey": "0xfile", "chunkHash": "0x1", "chunkData": STANDARD.encode(b"hello"), "chunkIndex": 0});
+    assert_eq!(send(&state, "/store", Some(body)).await.0, 201);
+    drop(state);
+
+    let state = build_state(db, Config::from_pairs(config));
+    let (status, chunk) = send(&state, "/file/0xfile/chunk/0x1", None).await;
+    assert_eq!(status, 200);
+    assert_eq!(chunk_bytes(&chunk), b"hello");
+}
+
+#[test]
+fn encryption_keys_are_validated() {
+    let invalid: [&[(&str, &str)]; 3] = [
+        &[("STORAGE_ENCRYPTION_KEY", "not-hex")],
+        &[("STORAGE_ENCRYPTION_KEY", "0011")],
+        &[("STORAGE_ENCRYPTION_KEY", KEY), ("STORAGE_ENCRYPTION_KEY_FILE", "/dev/null")],
+    ];
+    for pairs in invalid {
+        assert!(std::panic::catch_unwind(|| Config::from_pairs(pairs.iter().copied())).is_err());
+    }
+}
@@ -0,0 +1,82 @@
+// Test tích hợp cho mã xóa Reed-Solomon của từng chunk (STORAGE_ERASURE_DATA_SHARDS).
+
+mod common;
+
+use axum::body::Body;
+use axum::http::Request;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use common::{chunk_bytes, data};
+use rust_p2p_storage::{build_app, build_state, AppState, Config};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tower::ServiceExt;
+
+const CONFIG: [(&str, &str); 3] = [
+    ("STORAGE_ERASURE_DATA_SHARDS", "4"),
+    ("STORAGE_ERASURE_PARITY_SHARDS", "2"),
+    ("STORAGE_LARGE_CHUNK_BYTES", "1024"),
+];
+
+async fn send(state: &Arc<AppState>, method: &str, path: &str, body: Option<Value>) -> (u16, Value) {
+    let request = Request::builder()
+        .method(method)
+        .uri(path)
+        .header("content-type", "application/json")
+        .body(body.map_or_else(Body::empty, |b| Body::from(b.to_string())))
+        .unwrap();
+    let response = build_app(state.clone()).oneshot(request).await.unwrap();
+    let status = response.status().as_u16();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&bytes).unwrap_or_default())
+}
+
+// Đảo một byte trong phần dữ liệu của các mảnh `shards` của value đã mã hóa ở `key`
+fn damage(tree: &sled::Tree, key: &str, shards: &[usize]) {
+    let mut value = tree.get(key).unwrap().expect("thiếu value").to_vec();
+    assert_eq!(value[..3], [0x02, 4, 2]);
+    let len = u32::from_be_bytes(value[3..7].try_into().unwrap()) as usize;
+    let width = len.div_ceil(4);
+    for &shard in shards {
+        value[7 + shard * (8 + width) + 8] ^= 0xff;
+    }
+    tree.insert(key, value).unwrap();
+}
+
+#[tokio::test]
+async fn chunks_survive_up_to_m_damaged_shards() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let db = sled::open(dir.path()).unwrap();
+    let state = build_state(db.clone(), Config::from_pairs(CONFIG));
+    let (small, large) = (data(100, 1), data(4096, 2));
+    for (hash, bytes, index) in [("0x1", &small, 0), ("0x2", &large, 1)] {
+        let body = json!({"fileKey": "0xfile", "chunkHash": hash, "chunkData": STANDARD.encode(bytes), "chunkIndex": index});
+        assert_eq!(send(&state, "POST", "/store", Some(body)).await.0, 201);
+    }
+    let (_, manifest) = send(&state, "GET", "/file/0xfile/manifest", None).await;
+    assert_eq!(manifest["erasure"], json!({"dataShards": 4, "parityShards": 2}));
+
+    // Hỏng một mảnh dữ liệu và một mảnh parity của record, hai mảnh dữ liệu của value lớn
+    damage(&db, "0xfile:0x1", &[1, 5]);
+    damage(&db.open_tree("chunks_large").unwrap(), "0xfile:0x2", &[0, 3]);
+    let (status, body) = send(&state, "GET", "/file/0xfile", None).await;
+    assert_eq!(status, 200);
+    let chunks: Vec<Vec<u8>> = body["chunks"].as_array().unwrap().iter().map(chunk_bytes).collect();
+    assert_eq!(chunks, [small.clone(), large.clone()]);
+    let (_, chunk) = send(&state, "GET", "/file/0xfile/chunk/0x1", None).await;
+    assert_eq!(chunk["index"], 0);
+    assert_eq!(chunk_bytes(&chunk), small);
+
+    // Mảnh hỏng thứ ba vượt quá m: chunk không còn đọc được
+    damage(&db, "0xfile:0x1", &[2]);
+    assert_eq!(send(&state, "GET", "/file/0xfile/chunk/0x1", None).await.0, 500);
+}
+
+#[test]
+fn erasure_parameters_are_validated() {
+    for parity_shards in ["0", "100"] {
+        let result = std::panic::catch_unwind(|| {
+            Config::from_pairs([("STORAGE_ERASURE_DATA_SHARDS", "200"), ("STORAGE_ERASURE_PARITY_SHARDS", parity_shards)])
+        });
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,90 @@
+// Test tích hợp cho export/import toàn bộ database (`/admin/export`, `/admin/import`).
+
+mod common;
+
+use common::{chunk_bytes, TestServer};
+use serde_json::{json, Value};
+
+const ADMIN: [(&str, &str); 1] = [("STORAGE_ADMIN_API_KEY", "secret")];
+
+async fn export(server: &TestServer) -> Vec<u8> {
+    let response = server.client.get(server.url("/admin/export")).header("x-api-key", "secret").send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    response.bytes().await.unwrap().to_vec()
+}
+
+async fn import(server: &TestServer, query: &str, snapshot: &[u8]) -> (u16, Value) {
+    let response = server
+        .client
+        .post(server.url(&format!("/admin/import{}", query)))
+        .header("x-api-key", "secret")
+        .body(snapshot.to_vec())
+        .send()
+        .await
+        .unwrap();
+    let status = response.status().as_u16();
+    (status, response.json().await.unwrap_or_default())
+}
+
+#[tokio::test]
+async fn snapshot_moves_chunks_and_metadata_to_another_node() {
+    let source = TestServer::with_config(&ADMIN).await;
+    source.store("0xfile", "0xa", b"first", Some(0)).await;
+    source.store("0xfile", "0xb", &b"second".repeat(1000), Some(1)).await;
+    let manifest = json!({"chunks": ["0xa", "0xb"], "filename": "f.bin"});
+    assert_eq!(source.post_json("/manifest/0xfile", &manifest).await.status(), 201);
+    assert_eq!(source.client.get(source.url("/admin/export")).send().await.unwrap().status(), 401);
+    let snapshot = export(&source).await;
+    assert_eq!(&snapshot[..8], b"P2PDUMP1");
+
+    let target = TestServer::with_config(&ADMIN).await;
+    let (status, report) = import(&target, "", &snapshot).await;
+    assert_eq!(status, 200, "{report}");
+    assert!(report["imported"].as_u64().unwrap() >= 3);
+    assert_eq!(report["skipped"], 0);
+    let chunks = target.chunks("0xfile").await;
+    assert_eq!(chunks.iter().map(chunk_bytes).collect::<Vec<_>>(), [b"first".to_vec(), b"second".repeat(1000)]);
+    let stored: Value = target.get("/manifest/0xfile").await.json().await.unwrap();
+    assert_eq!(stored["filename"], "f.bin");
+
+    // Import lại cùng luồng không ghi gì thêm
+    let (_, report) = import(&target, "", &snapshot).await;
+    assert_eq!(report["imported"], 0);
+    assert!(report["unchanged"].as_u64().unwrap() >= 3);
+}
+
+#[tokio::test]
+async fn conflict_policy_decides_which_value_wins() {
+    let source = TestServer::with_config(&ADMIN).await;
+    source.store("0xfile", "0xa", b"old", Some(0)).await;
+    let snapshot = export(&source).await;
+
+    let target = TestServer::with_config(&ADMIN).await;
+    target.store("0xfile", "0xa", b"new", Some(0)).await;
+    let (status, report) = import(&target, "", &snapshot).await;
+    assert_eq!(status, 200);
+    assert!(report["skipped"].as_u64().unwrap() >= 1);
+    assert_eq!(chunk_bytes(&target.chunks("0xfile").await[0]), b"new");
+
+    assert_eq!(import(&target, "?onConflict=abort", &snapshot).await.0, 409);
+    assert_eq!(chunk_bytes(&target.chunks("0xfile").await[0]), b"new");
+
+    let (_, report) = import(&target, "?onConflict=overwrite", &snapshot).await;
+    assert!(report["overwritten"].as_u64().unwrap() >= 1);
+    assert_eq!(chunk_bytes(&target.chunks("0xfile").await[0]), b"old");
+}
+
+#[tokio::test]
+async fn truncated_or_foreign_streams_are_rejected() {
+    let source = TestServer::with_config(&ADMIN).await;
+    source.store("0xfile", "0xa", b"data", Some(0)).await;
+    let snapshot = export(&source).await;
+
+    let target = TestServer::with_config(&ADMIN).await;
+    assert_eq!(import(&target, "", &snapshot[..snapshot.len() - 1]).await.0, 400);
+    assert_eq!(import(&target, "", b"not a snapshot").await.0, 400);
+    let mut trailing = snapshot.clone();
+    trailing.push(0x02);
+    assert_eq!(import(&target, "", &trailing).await.0, 400);
+    assert_eq!(import(&target, "", &snapshot).await.0, 200);
+}
@@ -0,0 +1,613 @@
+// Test tích hợp cho các thao tác trên cả một file.
+
+mod common;
+
+use common::{chunk_bytes, TestServer};
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn delete_removes_all_chunks_and_reports_hashes() {
+    let server = TestServer::start().await;
+    server.store("0xfile", "0x1", b"one", Some(0)).await;
+    server.store("0xfile", "0x2", b"two", Some(1)).await;
+    server.store("0xfile2", "0x3", b"other", Some(0)).await;
+
+    let response = server.client.delete(server.url("/file/0xfile")).send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    let mut removed: Vec<&str> = body["removed"].as_array().unwrap().iter().map(|v| v.as_str().unwrap()).collect();
+    removed.sort();
+    assert_eq!(removed, ["0x1", "0x2"]);
+    assert_eq!(body["count"], 2);
+
+    assert!(server.chunks("0xfile").await.is_empty());
+    assert_eq!(server.chunks("0xfile2").await.len(), 1);
+}
+
+#[tokio::test]
+async fn delete_of_unknown_file_is_not_found() {
+    let server = TestServer::start().await;
+    let response = server.client.delete(server.url("/file/0xmissing")).send().await.unwrap();
+    assert_eq!(response.status(), 404);
+}
+
+#[tokio::test]
+async fn delete_chunk_removes_only_that_chunk() {
+    let server = TestServer::with_config(&[("STORAGE_ORDER_INDEX", "1"), ("STORAGE_LARGE_CHUNK_BYTES", "8")]).await;
+    server.store("0xfile", "0x1", b"kept", Some(0)).await;
+    server.store("0xfile", "0x2", b"a larger chunk value", Some(1)).await;
+
+    let response = server.client.delete(server.url("/chunk/0xfile/0x2")).send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["removed"], serde_json::json!(["0x2"]));
+    assert_eq!(body["count"], 1);
+    let chunks = server.chunks("0xfile").await;
+    assert_eq!(chunks.len(), 1);
+    assert_eq!(chunk_bytes(&chunks[0]), b"kept");
+
+    let missing = server.client.delete(server.url("/chunk/0xfile/0x2")).send().await.unwrap();
+    assert_eq!(missing.status(), 404);
+
+    // Xóa chunk cuối cùng thì file không còn được đếm
+    server.client.delete(server.url("/chunk/0xfile/0x1")).send().await.unwrap();
+    assert_eq!(metric(&server, "storage_file_keys").await, 0);
+    let deleted = server.client.delete(server.url("/file/0xfile")).send().await.unwrap();
+    assert_eq!(deleted.status(), 404);
+}
+
+#[tokio::test]
+async fn delete_with_order_index_clears_index_entries() {
+    let server = TestServer::with_config(&[("STORAGE_ORDER_INDEX", "1")]).await;
+    server.store("0xfile", "0x1", b"old", Some(0)).await;
+    server.client.delete(server.url("/file/0xfile")).send().await.unwrap();
+
+    // Chunk mới cùng hash nhưng khác index không được lặp lại theo entry cũ
+    server.store("0xfile", "0x1", b"new", Some(5)).await;
+    let chunks = server.chunks("0xfile").await;
+    assert_eq!(chunks.len(), 1);
+    assert_eq!(chunks[0]["index"], 5);
+}
+
+async fn metric(server: &TestServer, name: &str) -> u64 {
+    let text = server.get("/metrics").await.text().await.unwrap();
+    let line = text.lines().find(|l| l.split(' ').next() == Some(name)).expect("thiếu metric");
+    line.rsplit(' ').next().unwrap().parse().unwrap()
+}
+
+#[tokio::test]
+async fn file_key_count_follows_stores_renames_and_deletes() {
+    let server = TestServer::with_config(&[("STORAGE_FILE_KEYS_ALERT", "2")]).await;
+    server.store("0xa", "0x1", b"one", Some(0)).await;
+    server.store("0xa", "0x2", b"two", Some(1)).await;
+    server.store("0xa", "0x2", b"two", Some(1)).await; // Ghi đè không tạo file mới
+    server.store("0xb", "0x3", b"three", Some(0)).await;
+    assert_eq!(metric(&server, "storage_file_keys").await, 2);
+    assert_eq!(metric(&server, "storage_file_keys_alert").await, 0);
+
+    server.store("0xc", "0x4", b"four", Some(0)).await;
+    assert_eq!(metric(&server, "storage_file_keys").await, 3);
+    assert_eq!(metric(&server, "storage_file_keys_alert").await, 1);
+
+    // Đổi tên đè lên file đã có: hai file gộp làm một
+    let renamed = server
+        .post_json("/file/0xa/rename?overwrite=true", &serde_json::json!({"newFileKey": "0xb"}))
+        .await;
+    assert_eq!(renamed.status(), 200);
+    assert_eq!(metric(&server, "storage_file_keys").await, 2);
+    assert_eq!(metric(&server, "storage_file_keys_alert").await, 0);
+
+    server.client.delete(server.url("/file/0xc")).send().await.unwrap();
+    assert_eq!(metric(&server, "storage_file_keys").await, 1);
+}
+
+async fn list(server: &TestServer, query: &str) -> Value {
+    let response = server.get(&format!("/files{}", query)).await;
+    assert_eq!(response.status(), 200);
+    response.json().await.unwrap()
+}
+
+// Bytes gần như ngẫu nhiên để không bị nén nhỏ lại
+fn noise(len: usize) -> Vec<u8> {
+    let mut x: u32 = 0x9e37_79b9;
+    (0..len)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 17;
+            x ^= x << 5;
+            x as u8
+        })
+        .collect()
+}
+
+fn listed(page: &Value) -> Vec<(String, u64, u64)> {
+    let files = page["files"].as_array().unwrap();
+    files
+        .iter()
+        .map(|f| (f["fileKey"].as_str().unwrap().to_string(), f["chunks"].as_u64().unwrap(), f["bytes"].as_u64().unwrap()))
+        .collect()
+}
+
+#[tokio::test]
+async fn files_are_listed_from_the_index_with_prefix_and_cursor() {
+    let server = TestServer::start().await;
+    server.store("0xaa1", "0x1", b"one", Some(0)).await;
+    server.store("0xaa1", "0x2", &noise(500), Some(1)).await;
+    server.store("0xaa2", "0x1", b"two", Some(0)).await;
+    server.store("0xbb", "0x1", b"three", Some(0)).await;
+
+    let all = listed(&list(&server, "").await);
+    assert_eq!(all.iter().map(|f| f.0.as_str()).collect::<Vec<_>>(), ["0xaa1", "0xaa2", "0xbb"]);
+    assert_eq!(all[0].1, 2);
+    assert!(all[0].2 > 500 && all[0].2 > all[1].2);
+
+    let first = list(&server, "?prefix=0xaa&limit=1").await;
+    assert_eq!(listed(&first)[0].0, "0xaa1");
+    assert_eq!(first["nextCursor"], "0xaa1");
+    let second = list(&server, "?prefix=0xaa&limit=1&after=0xaa1").await;
+    assert_eq!(listed(&second)[0].0, "0xaa2");
+    assert!(second.get("nextCursor").is_none());
+    assert_eq!(server.get("/files?limit=0").await.status(), 400);
+
+    // Ghi đè chunk và xóa chunk cập nhật tổng số byte, xóa file thì bỏ khỏi danh sách
+    let before = all[0].2;
+    let body = json!({"fileKey": "0xaa1", "chunkHash": "0x2", "chunkData": base64::Engine::encode(&base64::engine::general_purpose::STANDARD, noise(100)), "chunkIndex": 1});
+    assert_eq!(server.post_json("/store?overwrite=true", &body).await.status(), 201);
+    let smaller = listed(&list(&server, "?prefix=0xaa1").await)[0].2;
+    assert!(before - smaller >= 400, "{before} -> {smaller}");
+    server.client.delete(server.url("/chunk/0xaa1/0x2")).send().await.unwrap();
+    let remaining = listed(&list(&server, "?prefix=0xaa1").await);
+    assert_eq!(remaining[0].1, 1);
+    assert!(remaining[0].2 < smaller);
+    server.client.delete(server.url("/file/0xaa2")).send().await.unwrap();
+    let after_delete = listed(&list(&server, "").await);
+    assert_eq!(after_delete.iter().map(|f| f.0.as_str()).collect::<Vec<_>>(), ["0xaa1", "0xbb"]);
+}
+
+#[tokio::test]
+async fn sealed_file_rejects_changes_until_an_admin_unseals_it() {
+    let server = TestServer::with_config(&[("STORAGE_ADMIN_API_KEY", "secret")]).await;
+    assert_eq!(server.post_json("/file/0xfile/seal", &Value::Null).await.status(), 404);
+    server.store("0xfile", "0x1", b"final", Some(0)).await;
+    server.store("0xother", "0x1", b"other", Some(0)).await;
+
+    let sealed = server.post_json("/file/0xfile/seal", &Value::Null).await;
+    assert_eq!(sealed.status(), 200);
+    assert_eq!(sealed.json::<Value>().await.unwrap()["sealed"], true);
+
+    let response = server.store("0xfile", "0x2", b"more", Some(1)).await;
+    assert_eq!(response.status(), 403);
+    assert_eq!(response.json::<Value>().await.unwrap()["code"], "file_sealed");
+    let delete = server.client.delete(server.url("/file/0xfile")).send().await.unwrap();
+    assert_eq!(delete.status(), 403);
+    let moved = server.post_json("/file/0xfile/rename", &serde_json::json!({"newFileKey": "0xmoved"})).await;
+    assert_eq!(moved.status(), 403);
+    let replaced = server
+        .post_json("/file/0xother/rename?overwrite=true", &serde_json::json!({"newFileKey": "0xfile"}))
+        .await;
+    assert_eq!(replaced.status(), 403);
+    assert_eq!(server.chunks("0xfile").await.len(), 1);
+
+    let unseal = |key: &'static str| {
+        server.client.post(server.url("/file/0xfile/unseal")).header("x-api-key", key).send()
+    };
+    assert_eq!(unseal("wrong").await.unwrap().status(), 401);
+    assert_eq!(unseal("secret").await.unwrap().status(), 200);
+    assert_eq!(server.store("0xfile", "0x2", b"more", Some(1)).await.status(), 201);
+    assert_eq!(server.chunks("0xfile").await.len(), 2);
+}
+
+#[tokio::test]
+async fn stream_reassembles_the_file_in_index_order() {
+    let server = TestServer::start().await;
+    server.store("0xfile", "0xb", b" world", Some(1)).await;
+    server.store("0xfile", "0xa", b"hello", Some(0)).await;
+
+    let response = server.get("/file/0xfile/stream").await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "application/octet-stream");
+    assert_eq!(response.bytes().await.unwrap().as_ref(), b"hello world");
+    assert_eq!(server.get("/file/0xmissing/stream").await.status(), 404);
+}
+
+#[tokio::test]
+async fn multipart_export_sends_one_part_per_chunk_in_index_order() {
+    let server = TestServer::start().await;
+    assert_eq!(server.get("/file/0xfile/multipart").await.status(), 404);
+    let second: Vec<u8> = (0..=255u8).collect();
+    server.store("0xfile", "0xb", &second, Some(1)).await;
+    server.store("0xfile", "0xa", b"first\r\n--not-a-boundary", Some(0)).await;
+
+    let response = server.get("/file/0xfile/multipart").await;
+    assert_eq!(response.status(), 200);
+    let content_type = response.headers()["content-type"].to_str().unwrap().to_string();
+    let boundary = content_type.strip_prefix("multipart/mixed; boundary=").expect("thiếu boundary");
+    let body = response.bytes().await.unwrap();
+
+    // Tách part theo Content-Length để không phụ thuộc vào nội dung chunk
+    let mut rest = &body[..];
+    let mut parts = Vec::new();
+    loop {
+        let delimiter = format!("--{}", boundary);
+        rest = rest.strip_prefix(delimiter.as_bytes()).expect("thiếu boundary");
+        if rest == b"--\r\n" {
+            break;
+        }
+        let end = rest.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        let head = std::str::from_utf8(&rest[2..end]).unwrap().to_string();
+        let header = |name: &str| {
+            head.lines().find_map(|l| l.strip_prefix(name).map(|v| v.trim().to_string())).unwrap_or_default()
+        };
+        let len: usize = header("Content-Length:").parse().unwrap();
+        let data = rest[end + 4..end + 4 + len].to_vec();
+        parts.push((header("X-Chunk-Hash:"), header("X-Chunk-Index:"), data));
+        rest = rest[end + 4 + len..].strip_prefix(b"\r\n").unwrap();
+    }
+
+    assert_eq!(
+        parts,
+        [
+            ("0xa".to_string(), "0".to_string(), b"first\r\n--not-a-boundary".to_vec()),
+            ("0xb".to_string(), "1".to_string(), second),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn aliases_resolve_to_file_keys_on_read_routes() {
+    let server = TestServer::with_config(&[("STORAGE_ADMIN_API_KEY", "secret")]).await;
+    server.store("0xabc123", "0x1", b"first", Some(0)).await;
+    server.store("0xdef456", "0x1", b"second", Some(0)).await;
+    let set_alias = |alias: &str, file_key: &str, key: &str| {
+        server
+            .client
+            .post(server.url("/alias"))
+            .header("x-api-key", key)
+            .json(&serde_json::json!({"alias": alias, "fileKey": file_key}))
+            .send()
+    };
+
+    assert_eq!(set_alias("report", "0xabc123", "wrong").await.unwrap().status(), 401);
+    assert_eq!(set_alias("0xbeef", "0xabc123", "secret").await.unwrap().status(), 400);
+    assert_eq!(set_alias("cafe", "0xabc123", "secret").await.unwrap().status(), 400);
+    assert_eq!(set_alias("report", "0xabc123", "secret").await.unwrap().status(), 200);
+
+    let resolved: Value = server.get("/file/report/resolve").await.json().await.unwrap();
+    assert_eq!(resolved["fileKey"], "0xabc123");
+    assert_eq!(resolved["alias"], true);
+    assert_eq!(chunk_bytes(&server.chunks("report").await[0]), b"first");
+    let tail: Value = server.get("/file/report/tail?n=1").await.json().await.unwrap();
+    assert_eq!(tail["fileKey"], "0xabc123");
+
+    // Đổi đích của alias
+    let updated: Value = set_alias("report", "0xdef456", "secret").await.unwrap().json().await.unwrap();
+    assert_eq!(updated["previousFileKey"], "0xabc123");
+    assert_eq!(chunk_bytes(&server.chunks("report").await[0]), b"second");
+    let batch: Value = server.post_json("/files/batch", &serde_json::json!({"fileKeys": ["report"]})).await.json().await.unwrap();
+    assert_eq!(chunk_bytes(&batch["files"]["report"][0]), b"second");
+
+    // fileKey thật được trả về nguyên vẹn, tên lạ thì 404
+    let direct: Value = server.get("/file/0xabc123/resolve").await.json().await.unwrap();
+    assert_eq!(direct["alias"], false);
+    assert_eq!(server.get("/file/unknown/resolve").await.status(), 404);
+}
+
+#[tokio::test]
+async fn files_batch_reports_each_repeated_key_once() {
+    let server = TestServer::start().await;
+    server.store("0xfile", "0x1", b"one", Some(0)).await;
+    let body = json!({"fileKeys": ["0xfile", "0xnope", "0xfile", "0xnope"]});
+    let batch: Value = server.post_json("/files/batch", &body).await.json().await.unwrap();
+    assert_eq!(batch["files"].as_object().unwrap().len(), 1);
+    assert_eq!(chunk_bytes(&batch["files"]["0xfile"][0]), b"one");
+    assert_eq!(batch["notFound"], json!(["0xnope"]));
+}
+
+#[tokio::test]
+async fn vfile_returns_byte_ranges_across_chunk_boundaries() {
+    let server = TestServer::with_config(&[("STORAGE_COMPRESSION", "zstd"), ("STORAGE_ORDER_INDEX", "1")]).await;
+    // Lưu lệch thứ tự để bảng offset phải theo chunkIndex
+    server.store("0xfile", "0xc", b"IJKL", Some(2)).await;
+    server.store("0xfile", "0xa", b"ABCDE", Some(0)).await;
+    server.store("0xfile", "0xb", b"FGH", Some(1)).await;
+
+    let read = |query: &str| server.client.get(server.url(&format!("/vfile/0xfile?{}", query))).send();
+    let response = read("offset=3&length=7").await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["x-file-size"], "12");
+    assert_eq!(response.bytes().await.unwrap().as_ref(), b"DEFGHIJ");
+    assert_eq!(read("offset=5").await.unwrap().bytes().await.unwrap().as_ref(), b"FGHIJKL");
+    assert_eq!(read("").await.unwrap().bytes().await.unwrap().as_ref(), b"ABCDEFGHIJKL");
+    assert_eq!(read("offset=12&length=0").await.unwrap().bytes().await.unwrap().as_ref(), b"");
+
+    let out_of_range = read("offset=10&length=5").await.unwrap();
+    assert_eq!(out_of_range.status(), 416);
+    assert_eq!(out_of_range.headers()["content-range"], "bytes */12");
+    assert_eq!(read("offset=13").await.unwrap().status(), 416);
+    assert_eq!(server.get("/vfile/0xmissing").await.status(), 404);
+}
+
+#[tokio::test]
+async fn manifest_lists_chunk_layout_without_data() {
+    let server = TestServer::with_config(&[("STORAGE_COMPRESSION", "zstd")]).await;
+    server.store("0xfile", "0xb", &[2u8; 300], Some(1)).await;
+    server.store("0xfile", "0xa", &[1u8; 100], Some(0)).await;
+    server.store("0xfile", "0xc", b"", Some(2)).await;
+
+    let manifest: Value = server.get("/file/0xfile/manifest").await.json().await.unwrap();
+    assert_eq!(manifest["totalSize"], 400);
+    assert_eq!(
+        manifest["chunks"],
+        serde_json::json!([
+            {"index": 0, "chunkHash": "0xa", "byteLength": 100, "offset": 0},
+            {"index": 1, "chunkHash": "0xb", "byteLength": 300, "offset": 100},
+            {"index": 2, "chunkHash": "0xc", "byteLength": 0, "offset": 400},
+        ])
+    );
+    assert_eq!(server.get("/file/0xmissing/manifest").await.status(), 404);
+}
+
+#[tokio::test]
+async fn binary_file_keys_round_trip_through_b64_routes() {
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+    let server = TestServer::start().await;
+    let raw = |path: String, data: &'static [u8]| server.client.post(server.url(&path)).body(data).send();
+    let b64 = |bytes: &[u8]| URL_SAFE_NO_PAD.encode(bytes);
+
+    // fileKey nhị phân thứ hai bắt đầu bằng "fileKey thứ nhất + ':'", nằm trong prefix scan của nó
+    let short = b64(&[0xff, 0x00]);
+    let long = b64(&[0xff, 0x00, b':', 0xfe]);
+    assert_eq!(raw(format!("/b64/store/raw/{}/0x2?index=1", short), b"second").await.unwrap().status(), 200);
+    assert_eq!(raw(format!("/b64/store/raw/{}/0x1?index=0", short), b"first").await.unwrap().status(), 200);
+    assert_eq!(raw(format!("/b64/store/raw/{}/0x1", long), b"other").await.unwrap().status(), 200);
+    assert_eq!(metric(&server, "storage_file_keys").await, 2);
+
+    let body: Value = server.get(&format!("/b64/file/{}", short)).await.json().await.unwrap();
+    assert_eq!(body["fileKey"], short.as_str());
+    let chunks = body["chunks"].as_array().unwrap();
+    assert_eq!(chunks.len(), 2);
+    assert_eq!(chunks[0]["key"], format!("{}:0x1", short));
+    assert_eq!(chunk_bytes(&chunks[0]), b"first");
+    assert_eq!(chunk_bytes(&chunks[1]), b"second");
+
+    // fileKey base64url là UTF-8 hợp lệ thì dùng chung dữ liệu với route thường
+    let text = b64(b"0xtext");
+    assert_eq!(raw(format!("/b64/store/raw/{}/0x1", text), b"text").await.unwrap().status(), 200);
+    assert_eq!(chunk_bytes(&server.chunks("0xtext").await[0]), b"text");
+
+    assert_eq!(server.get("/b64/file/not*base64").await.status(), 400);
+
+    let deleted: Value = server.client.delete(server.url(&format!("/b64/file/{}", short))).send().await.unwrap().json().await.unwrap();
+    assert_eq!(deleted["removed"].as_array().unwrap().len(), 2);
+    let body: Value = server.get(&format!("/b64/file/{}", long)).await.json().await.unwrap();
+    assert_eq!(chunk_bytes(&body["chunks"][0]), b"other");
+    assert_eq!(metric(&server, "storage_file_keys").await, 2);
+}
+
+#[tokio::test]
+async fn selected_chunks_are_fetched_by_hash_with_missing_ones_listed() {
+    let server = TestServer::with_config(&[("STORAGE_MAX_CHUNK_HASHES", "4")]).await;
+    server.store("0xfile", "0x1", b"one", Some(0)).await;
+    server.store("0xfile", "0x2", b"two", Some(1)).await;
+    server.store("0xfile", "0x3", b"three", Some(2)).await;
+
+    let body = serde_json::json!({ "chunkHashes": ["0x3", "0xmissing", "0x1", "0x3"] });
+    let response = server.post_json("/file/0xfile/chunks", &body).await;
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    let chunks = body["chunks"].as_array().unwrap();
+    assert_eq!(chunks.len(), 2);
+    assert_eq!(chunks[0]["key"], "0xfile:0x3");
+    assert_eq!(chunk_bytes(&chunks[0]), b"three");
+    assert_eq!(chunks[1]["index"], 0);
+    assert_eq!(body["missing"], serde_json::json!(["0xmissing"]));
+
+    let hex: Value = server
+        .post_json("/file/0xfile/chunks?encoding=hex", &serde_json::json!({ "chunkHashes": ["0x2"] }))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(hex["chunks"][0]["value"], format!("0x{}", hex::encode(b"two")));
+
+    let invalid = serde_json::json!({ "chunkHashes": ["0x1:0x2"] });
+    assert_eq!(server.post_json("/file/0xfile/chunks", &invalid).await.status(), 400);
+    let too_many = serde_json::json!({ "chunkHashes": ["0x1", "0x2", "0x3", "0x4", "0x5"] });
+    assert_eq!(server.post_json("/file/0xfile/chunks", &too_many).await.status(), 413);
+}
+
+async fn store_tagged(server: &TestServer, hash: &str, index: u64, metadata: Value) {
+    let body = serde_json::json!({
+        "fileKey": "0xfile",
+        "chunkHash": hash,
+        "chunkData": base64::Engine::encode(&base64::engine::general_purpose::STANDARD, hash),
+        "chunkIndex": index,
+        "metadata": metadata,
+    });
+    assert_eq!(server.post_json("/store", &body).await.status(), 201);
+}
+
+// chunkHash của các chunk khớp, theo thứ tự trả về, cùng body của response
+async fn query(server: &TestServer, predicate: Value) -> (Vec<String>, Value) {
+    let response = server.post_json("/file/0xfile/query", &predicate).await;
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    let hashes = body["chunks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| c["key"].as_str().unwrap().rsplit(':').next().unwrap().to_string())
+        .collect();
+    (hashes, body)
+}
+
+#[tokio::test]
+async fn query_returns_chunks_whose_metadata_matches() {
+    let server = TestServer::start().await;
+    store_tagged(&server, "0x3", 2, serde_json::json!({"tag": "thumbnail", "size": "small"})).await;
+    store_tagged(&server, "0x1", 0, serde_json::json!({"tag": "thumbnail", "size": "large"})).await;
+    store_tagged(&server, "0x2", 1, serde_json::json!({"tag": "original"})).await;
+    server.store("0xfile", "0x4", b"untagged", Some(3)).await;
+
+    let (hashes, body) = query(&server, serde_json::json!({"tag": "thumbnail"})).await;
+    assert_eq!(hashes, ["0x1", "0x3"]);
+    assert_eq!(chunk_bytes(&body["chunks"][0]), b"0x1");
+    assert_eq!(body["chunks"][0]["metadata"]["size"], "large");
+    assert_eq!(query(&server, serde_json::json!({"tag": "thumbnail", "size": "small"})).await.0, ["0x3"]);
+    assert!(query(&server, serde_json::json!({"tag": "missing"})).await.0.is_empty());
+    assert_eq!(query(&server, serde_json::json!({})).await.0.len(), 4);
+
+    let absent = server.post_json("/file/0xnothing/query", &serde_json::json!({"tag": "thumbnail"})).await;
+    assert_eq!(absent.status(), 404);
+}
+
+#[tokio::test]
+async fn file_chunks_can_be_paged_with_a_cursor() {
+    let server = TestServer::start().await;
+    for i in 0..5u64 {
+        server.store("0xfile", &format!("0x{}", i), &[i as u8], Some(i)).await;
+    }
+    let page = |query: String| {
+        let server = &server;
+        async move { server.get(&format!("/file/0xfile?{}", query)).await.json::<Value>().await.unwrap() }
+    };
+    let hashes = |body: &Value| -> Vec<String> {
+        body["chunks"].as_array().unwrap().iter().map(|c| c["key"].as_str().unwrap().replace("0xfile:", "")).collect()
+    };
+
+    let first = page("limit=2".into()).await;
+    assert_eq!(hashes(&first), ["0x0", "0x1"]);
+    assert_eq!(first["nextCursor"], "0x1");
+    let second = page("limit=2&after=0x1".into()).await;
+    assert_eq!(hashes(&second), ["0x2", "0x3"]);
+    let last = page("limit=2&after=0x3".into()).await;
+    assert_eq!(hashes(&last), ["0x4"]);
+    assert!(last.get("nextCursor").is_none());
+
+    assert_eq!(hashes(&page("offset=3".into()).await), ["0x3", "0x4"]);
+    // Cursor vừa bị xóa: trang tiếp tục từ chunkHash lớn hơn
+    server.client.delete(server.url("/chunk/0xfile/0x1")).send().await.unwrap();
+    assert_eq!(hashes(&page("limit=1&after=0x1".into()).await), ["0x2"]);
+    assert_eq!(server.get("/file/0xfile?limit=0").await.status(), 400);
+    // Không phân trang thì response giữ nguyên như trước
+    assert!(page("".into()).await.get("nextCursor").is_none());
+}
+
+#[tokio::test]
+async fn stored_manifest_orders_the_file() {
+    let server = TestServer::start().await;
+    assert_eq!(server.get("/manifest/0xfile").await.status(), 404);
+    for (hash, data) in [("0xa", b"A"), ("0xb", b"B"), ("0xc", b"C")] {
+        server.store("0xfile", hash, data, None).await;
+    }
+
+    let manifest = json!({"chunks": ["0xc", "0xa"], "totalSize": 3, "mimeType": "text/plain", "filename": "abc.txt"});
+    assert_eq!(server.post_json("/manifest/0xfile", &manifest).await.status(), 201);
+    assert_eq!(server.post_json("/manifest/0xfile", &manifest).await.status(), 200);
+    let stored: Value = server.get("/manifest/0xfile").await.json().await.unwrap();
+    assert_eq!(stored["chunks"], json!(["0xc", "0xa"]));
+    assert_eq!(stored["filename"], "abc.txt");
+
+    // Chunk trong manifest theo thứ tự khai báo, chunk còn lại đứng sau
+    let body: Value = server.get("/file/0xfile").await.json().await.unwrap();
+    let keys: Vec<&str> = body["chunks"].as_array().unwrap().iter().map(|c| c["key"].as_str().unwrap()).collect();
+    assert_eq!(keys, ["0xfile:0xc", "0xfile:0xa", "0xfile:0xb"]);
+    let streamed = server.get("/file/0xfile/stream").await.bytes().await.unwrap();
+    assert_eq!(&streamed[..], b"CAB");
+
+    let invalid = json!({"chunks": ["0xa", "0xa"]});
+    assert_eq!(server.post_json("/manifest/0xfile", &invalid).await.status(), 400);
+    assert_eq!(server.post_json("/manifest/0xfile", &json!({"chunks": []})).await.status(), 400);
+
+    server.client.delete(server.url("/file/0xfile")).send().await.unwrap();
+    assert_eq!(server.get("/manifest/0xfile").await.status(), 404);
+}
+
+#[tokio::test]
+async fn stream_serves_byte_ranges_across_chunks() {
+    let server = TestServer::start().await;
+    server.store("0xfile", "0xa", b"hello", Some(0)).await;
+    server.store("0xfile", "0xb", b" world", Some(1)).await;
+    let get_range = |range: &'static str| server.client.get(server.url("/file/0xfile/stream")).header("range", range).send();
+
+    let full = server.get("/file/0xfile/stream").await;
+    assert_eq!(full.headers()["accept-ranges"], "bytes");
+    assert_eq!(full.headers()["content-length"], "11");
+
+    let partial = get_range("bytes=3-7").await.unwrap();
+    assert_eq!(partial.status(), 206);
+    assert_eq!(partial.headers()["content-range"], "bytes 3-7/11");
+    assert_eq!(partial.bytes().await.unwrap().as_ref(), b"lo wo");
+
+    let tail = get_range("bytes=-3").await.unwrap();
+    assert_eq!(tail.headers()["content-range"], "bytes 8-10/11");
+    assert_eq!(tail.bytes().await.unwrap().as_ref(), b"rld");
+    let open_ended = get_range("bytes=6-100").await.unwrap();
+    assert_eq!(open_ended.status(), 206);
+    assert_eq!(open_ended.bytes().await.unwrap().as_ref(), b"world");
+
+    let outside = get_range("bytes=11-").await.unwrap();
+    assert_eq!(outside.status(), 416);
+    assert_eq!(outside.headers()["content-range"], "bytes */11");
+    // Nhiều khoảng hoặc cú pháp lạ thì trả cả file
+    let multi = get_range("bytes=0-1,3-4").await.unwrap();
+    assert_eq!(multi.status(), 200);
+    assert_eq!(multi.bytes().await.unwrap().as_ref(), b"hello world");
+}
+
+#[tokio::test]
+async fn localized_416_keeps_the_range_headers() {
+    let server = TestServer::start().await;
+    server.store("0xvid", "0xa", b"hello", Some(0)).await;
+    let request = server.client.get(server.url("/file/0xvid/stream")).header("range", "bytes=100-200");
+    let response = request.header("accept-language", "vi").send().await.unwrap();
+    assert_eq!(response.status(), 416);
+    assert_eq!(response.headers()["content-range"], "bytes */5");
+    assert_eq!(response.headers()["accept-ranges"], "bytes");
+    assert!(response.headers().contains_key("etag"));
+    assert_eq!(response.headers().get_all("content-type").iter().count(), 1);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["message"], "khoảng byte được yêu cầu nằm ngoài file");
+}
+
+#[tokio::test]
+async fn stream_etag_supports_conditional_requests() {
+    let server = TestServer::start().await;
+    server.store("0xfile", "0xa", b"hello", Some(0)).await;
+    let etag = server.get("/file/0xfile/stream").await.headers()["etag"].to_str().unwrap().to_string();
+    assert!(etag.starts_with('"') && etag.ends_with('"'), "{etag}");
+
+    let conditional = |tag: String| server.client.get(server.url("/file/0xfile/stream")).header("if-none-match", tag).send();
+    let cached = conditional(etag.clone()).await.unwrap();
+    assert_eq!(cached.status(), 304);
+    assert_eq!(cached.headers()["etag"], etag.as_str());
+    assert_eq!(conditional(format!("\"other\", W/{}", etag)).await.unwrap().status(), 304);
+
+    // If-Range khớp ETag hiện tại thì vẫn trả khoảng được yêu cầu
+    let resumed = server
+        .client
+        .get(server.url("/file/0xfile/stream"))
+        .header("range", "bytes=1-")
+        .header("if-range", etag.as_str())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resumed.status(), 206);
+    assert_eq!(resumed.bytes().await.unwrap().as_ref(), b"ello");
+
+    server.store("0xfile", "0xb", b"!", Some(1)).await;
+    let changed = conditional(etag.clone()).await.unwrap();
+    assert_eq!(changed.status(), 200);
+    assert_ne!(changed.headers()["etag"], etag.as_str());
+    // File đã đổi thì If-Range với ETag cũ bỏ qua Range
+    let stale = server
+        .client
+        .get(server.url("/file/0xfile/stream"))
+        .header("range", "bytes=1-")
+        .header("if-range", etag.as_str())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(stale.status(), 200);
+    assert_eq!(stale.bytes().await.unwrap().as_ref(), b"hello!");
+}
@@ -0,0 +1,85 @@
+// Test tích hợp cho dọn chunk mồ côi (`POST /admin/gc`).
+
+mod common;
+
+use common::TestServer;
+use rust_p2p_storage::{collect_garbage, GcOptions};
+use serde_json::{json, Value};
+
+async fn gc(server: &TestServer, query: &str) -> (u16, Value) {
+    let response = server
+        .client
+        .post(server.url(&format!("/admin/gc{}", query)))
+        .header("x-api-key", "secret")
+        .send()
+        .await
+        .unwrap();
+    let status = response.status().as_u16();
+    (status, response.json().await.unwrap_or_default())
+}
+
+fn keys(chunks: &[Value]) -> Vec<&str> {
+    let mut keys: Vec<&str> = chunks.iter().map(|c| c["key"].as_str().unwrap()).collect();
+    keys.sort();
+    keys
+}
+
+#[tokio::test]
+async fn dry_run_reports_orphans_and_apply_deletes_them() {
+    let server = TestServer::with_config(&[("STORAGE_ADMIN_API_KEY", "secret"), ("STORAGE_GC_GRACE_SECS", "0")]).await;
+    for (hash, data) in [("0xa", b"A"), ("0xb", b"B"), ("0xold", b"O")] {
+        server.store("0xfile", hash, data, None).await;
+    }
+    server.store("0xloose", "0x1", b"L", None).await;
+    let manifest = json!({"chunks": ["0xa", "0xb"]});
+    assert_eq!(server.post_json("/manifest/0xfile", &manifest).await.status(), 201);
+
+    assert_eq!(server.client.post(server.url("/admin/gc")).send().await.unwrap().status(), 401);
+    let (status, report) = gc(&server, "").await;
+    assert_eq!(status, 200);
+    assert_eq!(report["applied"], false);
+    assert_eq!(report["scannedChunks"], 4);
+    assert_eq!(report["orphanedChunks"], 1);
+    assert_eq!(report["orphanedFiles"], 1);
+    assert_eq!(report["deletedChunks"], 0);
+    assert_eq!(report["examples"], json!(["0xfile:0xold"]));
+    assert!(report["reclaimableBytes"].as_u64().unwrap() > 0);
+    assert_eq!(server.chunks("0xfile").await.len(), 3);
+
+    let (_, report) = gc(&server, "?apply=true").await;
+    assert_eq!(report["applied"], true);
+    assert_eq!(report["deletedChunks"], 1);
+    assert_eq!(keys(&server.chunks("0xfile").await), ["0xfile:0xa", "0xfile:0xb"]);
+    // File không có manifest chỉ bị dọn khi yêu cầu
+    assert_eq!(server.chunks("0xloose").await.len(), 1);
+
+    let (_, report) = gc(&server, "?apply=true&unmanifested=true").await;
+    assert_eq!(report["deletedChunks"], 1);
+    assert!(server.chunks("0xloose").await.is_empty());
+    assert_eq!(gc(&server, "").await.1["orphanedChunks"], 0);
+}
+
+#[tokio::test]
+async fn sealed_aliased_and_recent_chunks_are_kept() {
+    let server = TestServer::with_config(&[("STORAGE_ADMIN_API_KEY", "secret"), ("STORAGE_GC_GRACE_SECS", "0")]).await;
+    for file_key in ["0xsealed", "0xaliased", "0xplain"] {
+        server.store(file_key, "0x1", b"data", Some(0)).await;
+    }
+    assert_eq!(server.post_json("/file/0xsealed/seal", &Value::Null).await.status(), 200);
+    let alias = json!({"alias": "report", "fileKey": "0xaliased"});
+    let response = server.client.post(server.url("/alias")).header("x-api-key", "secret").json(&alias).send();
+    assert_eq!(response.await.unwrap().status(), 200);
+
+    let (_, report) = gc(&server, "?unmanifested=true").await;
+    assert_eq!(report["orphanedChunks"], 1);
+    assert_eq!(report["examples"], json!(["0xplain:0x1"]));
+
+    // Chunk được lưu sau thời điểm quét (chưa quá thời gian chờ) thì không bị coi là mồ côi
+    let options = GcOptions {
+        apply: true,
+        unmanifested: true,
+    };
+    let report = collect_garbage(&server.state, options, 0).unwrap();
+    assert_eq!(report.orphaned_chunks, 0);
+    assert_eq!(server.chunks("0xplain").await.len(), 1);
+}
@@ -0,0 +1,49 @@
+// Test tích hợp cho danh sách peer và gossip giữa các node.
+
+mod common;
+
+use common::TestServer;
+use rust_p2p_storage::run_gossip;
+use serde_json::Value;
+use std::time::Duration;
+
+// Một địa chỉ không có ai lắng nghe
+const DEAD_PEER: &str = "http://127.0.0.1:1";
+
+#[tokio::test]
+async fn gossip_marks_reachable_peers_alive_and_spreads_the_peer_list() {
+    let b = TestServer::with_config(&[("STORAGE_GOSSIP", "1")]).await;
+    let b_url = format!("http://{}", b.addr);
+    let peers = format!("{},{}/", DEAD_PEER, b_url);
+    let a = TestServer::with_config(&[
+        ("STORAGE_GOSSIP", "1"),
+        ("STORAGE_PEERS", &peers),
+        ("STORAGE_GOSSIP_INTERVAL_MS", "100"),
+    ])
+    .await;
+    tokio::spawn(run_gossip(a.state.clone()));
+
+    let mut waited = Duration::ZERO;
+    while a.state.alive_peers().is_empty() && waited < Duration::from_secs(5) {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        waited += Duration::from_millis(50);
+    }
+    assert_eq!(a.state.alive_peers(), [b_url]);
+
+    let view: Value = a.get("/gossip").await.json().await.unwrap();
+    let dead = view["peers"].as_array().unwrap().iter().find(|p| p["url"] == DEAD_PEER).unwrap();
+    assert_eq!(dead["alive"], false);
+
+    // B học được các peer A biết nhưng chưa tự kiểm chứng nên chưa coi là sống
+    let view: Value = b.get("/gossip").await.json().await.unwrap();
+    let learned = view["peers"].as_array().unwrap().iter().find(|p| p["url"] == DEAD_PEER).unwrap();
+    assert_eq!(learned["alive"], false);
+    assert!(b.state.alive_peers().is_empty());
+}
+
+#[tokio::test]
+async fn gossip_endpoint_is_absent_when_disabled() {
+    let server = TestServer::start().await;
+    assert_eq!(server.get("/gossip").await.status(), 404);
+    assert!(server.state.alive_peers().is_empty());
+}
@@ -0,0 +1,122 @@
+// Test tích hợp cho gRPC service: chạy trên cổng riêng, dùng chung state với HTTP server.
+
+mod common;
+
+use common::{chunk_bytes, TestServer};
+use rust_p2p_storage::grpc_proto::storage_client::StorageClient;
+use rust_p2p_storage::grpc_proto::{ChunkRequest, FileRequest, StoreRequest};
+use rust_p2p_storage::grpc_service;
+use std::time::Duration;
+use tonic::transport::Channel;
+
+fn free_port() -> u16 {
+    std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+// Chạy gRPC service trên cổng ngẫu nhiên cạnh HTTP server rồi trả về client đã kết nối
+async fn connect(server: &TestServer) -> StorageClient<Channel> {
+    let addr = std::net::SocketAddr::from(([127, 0, 0, 1], free_port()));
+    let service = grpc_service(server.state.clone());
+    tokio::spawn(tonic::transport::Server::builder().add_service(service).serve(addr));
+    for _ in 0..50 {
+        if let Ok(client) = StorageClient::connect(format!("http://{}", addr)).await {
+            return client;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("gRPC server không khởi động");
+}
+
+fn chunk(file_key: &str, chunk_hash: &str, data: &[u8], index: u64) -> StoreRequest {
+    StoreRequest {
+        file_key: file_key.to_string(),
+        chunk_hash: chunk_hash.to_string(),
+        chunk_data: data.to_vec(),
+        chunk_index: Some(index),
+        overwrite: false,
+    }
+}
+
+#[tokio::test]
+async fn grpc_and_http_share_the_same_storage() {
+    let server = TestServer::start().await;
+    let mut client = connect(&server).await;
+
+    client.store_chunk(chunk("0xfile", "0x1", b"qua grpc", 0)).await.unwrap();
+    let response = server.get("/file/0xfile/chunk/0x1").await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(chunk_bytes(&response.json().await.unwrap()), b"qua grpc");
+
+    assert_eq!(server.store("0xfile", "0x2", b"qua http", Some(1)).await.status(), 201);
+    let got = client
+        .get_chunk(ChunkRequest {
+            file_key: "0xfile".to_string(),
+            chunk_hash: "0x2".to_string(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!((got.key.as_str(), got.data.as_slice(), got.index), ("0xfile:0x2", &b"qua http"[..], Some(1)));
+
+    let missing = ChunkRequest {
+        file_key: "0xfile".to_string(),
+        chunk_hash: "0x9".to_string(),
+    };
+    assert_eq!(client.get_chunk(missing).await.unwrap_err().code(), tonic::Code::NotFound);
+    let invalid = chunk("0xfile", "0x1:bad", b"x", 2);
+    assert_eq!(client.store_chunk(invalid).await.unwrap_err().code(), tonic::Code::InvalidArgument);
+}
+
+#[tokio::test]
+async fn client_stream_stores_chunks_and_server_stream_lists_them() {
+    let server = TestServer::start().await;
+    let mut client = connect(&server).await;
+
+    // Nhiều hơn một lô INGEST_BATCH để kiểm tra ghi theo lô
+    let requests: Vec<StoreRequest> = (0..300u64)
+        .rev()
+        .map(|i| chunk("0xfile", &format!("0x{:x}", i), format!("chunk {}", i).as_bytes(), i))
+        .collect();
+    let stored = client.store_chunk_stream(tokio_stream::iter(requests)).await.unwrap().into_inner();
+    assert_eq!(stored.stored, 300);
+
+    let mut stream = client
+        .list_file_chunks(FileRequest {
+            file_key: "0xfile".to_string(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    let mut indexes = Vec::new();
+    while let Some(chunk) = stream.message().await.unwrap() {
+        assert_eq!(chunk.data, format!("chunk {}", chunk.index.unwrap()).into_bytes());
+        indexes.push(chunk.index.unwrap());
+    }
+    indexes.sort_unstable();
+    assert_eq!(indexes, (0..300).collect::<Vec<_>>());
+    assert_eq!(server.chunks("0xfile").await.len(), 300);
+
+    // Chunk lỗi làm hỏng stream; chunk đứng trước trong cùng lô chưa được ghi
+    let requests = vec![chunk("0xother", "0x1", b"ok", 0), chunk("0xother", "", b"bad", 1)];
+    let error = client.store_chunk_stream(tokio_stream::iter(requests)).await.unwrap_err();
+    assert_eq!(error.code(), tonic::Code::InvalidArgument);
+    assert!(server.chunks("0xother").await.is_empty());
+}
+
+#[tokio::test]
+async fn grpc_writes_are_checked_like_store() {
+    let server = TestServer::with_config(&[("STORAGE_MAX_CHUNK_SIZE", "16"), ("STORAGE_ALLOWED_CONTENT_TYPES", "text/*, application/octet-stream")]).await;
+    let mut client = connect(&server).await;
+
+    let oversized = chunk("0xfile", "0x1", &[b'a'; 100], 1);
+    assert_eq!(client.store_chunk(oversized).await.unwrap_err().code(), tonic::Code::InvalidArgument);
+    // Chunk đầu tiên là ảnh PNG, không nằm trong danh sách kiểu nội dung được phép
+    let png = chunk("0xfile", "0x2", b"\x89PNG\r\n\x1a\n0000", 0);
+    assert_eq!(client.store_chunk(png).await.unwrap_err().code(), tonic::Code::InvalidArgument);
+    let requests = vec![chunk("0xfile", "0x3", b"ok", 2), chunk("0xfile", "0x4", &[b'a'; 100], 3)];
+    assert_eq!(client.store_chunk_stream(tokio_stream::iter(requests)).await.unwrap_err().code(), tonic::Code::InvalidArgument);
+    assert!(server.chunks("0xfile").await.is_empty());
+
+    client.store_chunk(chunk("0xfile", "0x5", b"ok", 0)).await.unwrap();
+    assert_eq!(server.chunks("0xfile").await.len(), 1);
+}
@@ -0,0 +1,73 @@
+// Kiểm thử thuộc tính cho module key: các hàm ghép/tách key không bao giờ panic
+// và luôn ghép-tách lại được đúng như ban đầu với cặp fileKey/chunkHash hợp lệ.
+
+use rust_p2p_storage::key;
+
+use proptest::prelude::*;
+
+proptest! {
+    #[test]
+    fn decode_never_panics(bytes in proptest::collection::vec(any::<u8>(), 0..64)) {
+        let _ = key::decode(&bytes);
+    }
+
+    #[test]
+    fn valid_keys_round_trip(file_key in ".{1,40}", chunk_hash in "[^:]{1,40}") {
+        prop_assume!(key::is_valid(&file_key, &chunk_hash));
+        let encoded = key::encode(&file_key, &chunk_hash);
+        let parsed = key::decode(encoded.as_bytes()).expect("key hợp lệ phải tách được");
+        prop_assert_eq!(parsed.file_key, file_key.as_str());
+        prop_assert_eq!(parsed.chunk_hash, chunk_hash.as_str());
+        prop_assert!(encoded.starts_with(&key::prefix(&file_key)));
+        prop_assert_eq!(key::chunk_hash_in(encoded.as_bytes(), &file_key), Some(chunk_hash.as_str()));
+    }
+
+    #[test]
+    fn prefix_scan_filter_rejects_other_files(file_key in "[a-z0-9]{1,8}", suffix in "[a-z0-9]{1,8}", chunk_hash in "[a-z0-9]{1,8}") {
+        // "a:b:c" nằm trong prefix scan của "a" nhưng thuộc về fileKey "a:b"
+        let other = format!("{}:{}", file_key, suffix);
+        let encoded = key::encode(&other, &chunk_hash);
+        prop_assert!(encoded.starts_with(&key::prefix(&file_key)));
+        prop_assert_eq!(key::chunk_hash_in(encoded.as_bytes(), &file_key), None);
+    }
+
+    #[test]
+    fn binary_keys_round_trip(file_key in proptest::collection::vec(any::<u8>(), 1..40), chunk_hash in "[^:]{1,40}") {
+        let encoded = key::encode_raw(&file_key, &chunk_hash);
+        prop_assert!(encoded.starts_with(&key::prefix_raw(&file_key)));
+        prop_assert_eq!(key::decode_raw(&encoded), Some((file_key.as_slice(), chunk_hash.as_str())));
+        prop_assert_eq!(key::chunk_hash_in_raw(&encoded, &file_key), Some(chunk_hash.as_str()));
+    }
+
+    #[test]
+    fn binary_prefix_scan_filter_rejects_other_files(
+        file_key in proptest::collection::vec(any::<u8>(), 1..16),
+        suffix in proptest::collection::vec(any::<u8>(), 0..16),
+        chunk_hash in "[a-z0-9]{1,8}",
+    ) {
+        // fileKey nhị phân dài hơn bắt đầu bằng `file_key:` cũng nằm trong prefix scan
+        let mut other = key::prefix_raw(&file_key);
+        other.extend_from_slice(&suffix);
+        let encoded = key::encode_raw(&other, &chunk_hash);
+        prop_assert!(encoded.starts_with(&key::prefix_raw(&file_key)));
+        prop_assert_eq!(key::chunk_hash_in_raw(&encoded, &file_key), None);
+    }
+}
+
+#[test]
+fn rejects_malformed_keys() {
+    assert_eq!(key::decode(b""), None);
+    assert_eq!(key::decode(b"no-separator"), None);
+    assert_eq!(key::decode(b":0x01"), None);
+    assert_eq!(key::decode(b"0xab:"), None);
+    assert_eq!(key::decode(b"0xab:\xff\xfe"), None);
+    assert!(!key::is_valid("0xab", "0x:01"));
+    assert!(!key::is_valid("", "0x01"));
+}
+
+#[test]
+fn splits_at_last_separator() {
+    let parsed = key::decode(b"legacy:file:0x01").unwrap();
+    assert_eq!(parsed.file_key, "legacy:file");
+    assert_eq!(parsed.chunk_hash, "0x01");
+}
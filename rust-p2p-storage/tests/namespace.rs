@@ -0,0 +1,142 @@
+// Test tích hợp cho namespace (`/ns/:namespace/...` và `/admin/namespaces`).
+
+mod common;
+
+use common::TestServer;
+use serde_json::{json, Value};
+
+async fn admin(server: &TestServer, method: reqwest::Method, path: &str, body: Option<Value>) -> reqwest::Response {
+    let mut request = server.client.request(method, server.url(path)).header("x-api-key", "secret");
+    if let Some(body) = body {
+        request = request.json(&body);
+    }
+    request.send().await.unwrap()
+}
+
+async fn create(server: &TestServer, body: Value) -> reqwest::Response {
+    admin(server, reqwest::Method::POST, "/admin/namespaces", Some(body)).await
+}
+
+async fn chunks_in(server: &TestServer, namespace: &str, file_key: &str) -> Vec<Value> {
+    let body: Value = server.get(&format!("/ns/{}/file/{}", namespace, file_key)).await.json().await.unwrap();
+    body["chunks"].as_array().unwrap().clone()
+}
+
+fn chunk(file_key: &str, chunk_hash: &str, data: &str) -> Value {
+    json!({"fileKey": file_key, "chunkHash": chunk_hash, "chunkData": data, "chunkIndex": 0})
+}
+
+#[tokio::test]
+async fn namespaces_keep_their_data_apart() {
+    let server = TestServer::with_config(&[("STORAGE_ADMIN_API_KEY", "secret")]).await;
+    assert_eq!(create(&server, json!({"name": "app1"})).await.status(), 201);
+    assert_eq!(create(&server, json!({"name": "app2"})).await.status(), 201);
+    let duplicate = create(&server, json!({"name": "app1"})).await;
+    assert_eq!(duplicate.status(), 409);
+    assert_eq!(duplicate.json::<Value>().await.unwrap()["code"], "namespace_exists");
+    for name in ["", "Upper", "default", "a/b", &"x".repeat(33)] {
+        assert_eq!(create(&server, json!({"name": name})).await.status(), 400, "{name}");
+    }
+
+    assert_eq!(server.post_json("/ns/app1/store", &chunk("0xfile", "0x1", "b25l")).await.status(), 201);
+    server.store("0xfile", "0x1", b"default", Some(0)).await;
+
+    let chunks = chunks_in(&server, "app1", "0xfile").await;
+    assert_eq!(chunks.len(), 1);
+    assert_eq!(chunks[0]["value"], "b25l");
+    assert_eq!(server.chunks("0xfile").await[0]["value"], "ZGVmYXVsdA==");
+    assert!(chunks_in(&server, "app2", "0xfile").await.is_empty());
+    let listed: Value = server.get("/ns/app2/files").await.json().await.unwrap();
+    assert_eq!(listed["files"], json!([]));
+
+    let unknown = server.get("/ns/nope/file/0xfile").await;
+    assert_eq!(unknown.status(), 404);
+    assert_eq!(unknown.json::<Value>().await.unwrap()["code"], "namespace_not_found");
+    // Route của cả node không dùng được trong namespace
+    assert_eq!(admin(&server, reqwest::Method::GET, "/ns/app1/admin/export", None).await.status(), 404);
+    assert_eq!(admin(&server, reqwest::Method::GET, "/ns/app1/admin/namespaces", None).await.status(), 404);
+
+    let listed: Value = admin(&server, reqwest::Method::GET, "/admin/namespaces", None).await.json().await.unwrap();
+    let names: Vec<&str> = listed["namespaces"].as_array().unwrap().iter().map(|n| n["name"].as_str().unwrap()).collect();
+    assert_eq!(names, ["app1", "app2"]);
+}
+
+#[tokio::test]
+async fn namespace_quota_limits_only_that_namespace() {
+    let server = TestServer::with_config(&[("STORAGE_ADMIN_API_KEY", "secret")]).await;
+    let created = create(&server, json!({"name": "small", "quotaBytes": 200})).await;
+    assert_eq!(created.status(), 201);
+    assert_eq!(created.json::<Value>().await.unwrap()["quotaBytes"], 200);
+
+    let data = "QUFB".repeat(100);
+    assert_eq!(server.post_json("/ns/small/store", &chunk("0xfile", "0x1", &data)).await.status(), 507);
+    assert_eq!(server.post_json("/store", &chunk("0xfile", "0x1", &data)).await.status(), 201);
+    assert_eq!(server.post_json("/ns/small/store", &chunk("0xfile", "0x2", "YQ==")).await.status(), 201);
+}
+
+#[tokio::test]
+async fn tokens_are_scoped_to_their_namespace() {
+    let server = TestServer::with_config(&[("STORAGE_ADMIN_API_KEY", "secret"), ("STORAGE_AUTH", "1")]).await;
+    assert_eq!(create(&server, json!({"name": "app1"})).await.status(), 201);
+    assert_eq!(create(&server, json!({"name": "app2"})).await.status(), 201);
+    let minted = admin(&server, reqwest::Method::POST, "/ns/app1/admin/tokens", Some(json!({"scope": "write"}))).await;
+    assert_eq!(minted.status(), 201);
+    let token = minted.json::<Value>().await.unwrap()["token"].as_str().unwrap().to_string();
+
+    let store = |path: &'static str| {
+        server.client.post(server.url(path)).header("x-api-key", token.as_str()).json(&chunk("0xfile", "0x1", "YQ==")).send()
+    };
+    assert_eq!(store("/ns/app1/store").await.unwrap().status(), 201);
+    assert_eq!(store("/ns/app2/store").await.unwrap().status(), 401);
+    assert_eq!(store("/store").await.unwrap().status(), 401);
+}
+
+#[tokio::test]
+async fn deleting_a_namespace_drops_its_data() {
+    let server = TestServer::with_config(&[("STORAGE_ADMIN_API_KEY", "secret")]).await;
+    assert_eq!(create(&server, json!({"name": "tmp"})).await.status(), 201);
+    assert_eq!(server.post_json("/ns/tmp/store", &chunk("0xfile", "0x1", "YQ==")).await.status(), 201);
+
+    assert_eq!(admin(&server, reqwest::Method::DELETE, "/admin/namespaces/tmp", None).await.status(), 204);
+    assert_eq!(admin(&server, reqwest::Method::DELETE, "/admin/namespaces/tmp", None).await.status(), 404);
+    let gone = server.get("/ns/tmp/file/0xfile").await;
+    assert_eq!(gone.json::<Value>().await.unwrap()["code"], "namespace_not_found");
+
+    // Tạo lại cùng tên thì bắt đầu trống
+    assert_eq!(create(&server, json!({"name": "tmp"})).await.status(), 201);
+    assert!(chunks_in(&server, "tmp", "0xfile").await.is_empty());
+}
+
+#[tokio::test]
+async fn node_wide_limits_cover_every_namespace() {
+    let server = TestServer::with_config(&[("STORAGE_ADMIN_API_KEY", "secret"), ("STORAGE_MAX_TOTAL_BYTES", "600")]).await;
+    assert_eq!(create(&server, json!({"name": "app1"})).await.status(), 201);
+    assert_eq!(create(&server, json!({"name": "app2", "quotaBytes": 1000})).await.status(), 201);
+
+    // Giới hạn của node tính trên tổng số byte của mọi namespace
+    let data = "QUFB".repeat(50);
+    assert_eq!(server.post_json("/ns/app1/store", &chunk("0xfile", "0x1", &data)).await.status(), 201);
+    assert_eq!(server.post_json("/ns/app2/store", &chunk("0xfile", "0x1", &data)).await.status(), 201);
+    assert_eq!(server.post_json("/ns/app2/store", &chunk("0xfile", "0x2", &data)).await.status(), 507);
+    assert_eq!(server.post_json("/store", &chunk("0xfile", "0x1", &data)).await.status(), 507);
+    let stats: Value = server.get("/ns/app2/stats").await.json().await.unwrap();
+    assert_eq!(stats["maxTotalBytes"], 1000);
+    assert!(stats["remainingBytes"].as_u64().unwrap() < 600, "{stats}");
+
+    // Chế độ chỉ đọc của node chặn cả ghi trong namespace, và chỉ bật được từ node
+    let toggle = |path: &'static str| admin(&server, reqwest::Method::POST, path, Some(json!({"readOnly": true})));
+    assert_eq!(toggle("/ns/app1/admin/readonly").await.status(), 404);
+    assert_eq!(toggle("/admin/readonly").await.status(), 200);
+    let blocked = server.post_json("/ns/app1/store", &chunk("0xfile", "0x3", "YQ==")).await;
+    assert_eq!(blocked.status(), 503);
+    assert_eq!(blocked.json::<Value>().await.unwrap()["code"], "read_only");
+}
+
+#[tokio::test]
+async fn node_rate_limit_is_shared_by_namespaces() {
+    let server = TestServer::with_config(&[("STORAGE_ADMIN_API_KEY", "secret"), ("STORAGE_RATE_LIMIT", "2")]).await;
+    assert_eq!(create(&server, json!({"name": "app1"})).await.status(), 201);
+    assert_eq!(server.get("/ns/app1/stats").await.status(), 200);
+    assert_eq!(server.get("/ns/app1/stats").await.status(), 429);
+    assert_eq!(server.get("/stats").await.status(), 429);
+}
@@ -0,0 +1,127 @@
+// Test tích hợp cho thông báo thay đổi qua WebSocket ở GET /events.
+
+mod common;
+
+use common::TestServer;
+use rust_p2p_storage::sweep_expired;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio_stream::StreamExt;
+use tokio_tungstenite::tungstenite::Message;
+
+type Socket = tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+async fn subscribe(server: &TestServer) -> Socket {
+    let (socket, _) = tokio_tungstenite::connect_async(format!("ws://{}/events", server.addr)).await.unwrap();
+    socket
+}
+
+// Sự kiện tiếp theo, `None` nếu không có gì trong một giây
+async fn next_event(socket: &mut Socket) -> Option<Value> {
+    loop {
+        match tokio::time::timeout(Duration::from_secs(1), socket.next()).await.ok()?? {
+            Ok(Message::Text(text)) => return Some(serde_json::from_str(&text).unwrap()),
+            Ok(_) => continue,
+            Err(e) => panic!("lỗi WebSocket: {}", e),
+        }
+    }
+}
+
+#[tokio::test]
+async fn writes_and_deletes_are_pushed_to_subscribers() {
+    let server = TestServer::start().await;
+    let mut socket = subscribe(&server).await;
+
+    server.store("0xfile", "0x1", b"data", Some(0)).await;
+    let stored = serde_json::json!({"event": "stored", "fileKey": "0xfile", "chunkHash": "0x1"});
+    assert_eq!(next_event(&mut socket).await.unwrap(), stored);
+    let deleted = server.client.delete(server.url("/file/0xfile")).send().await.unwrap();
+    assert_eq!(deleted.status(), 200);
+    assert_eq!(next_event(&mut socket).await.unwrap(), serde_json::json!({"event": "deleted", "fileKey": "0xfile"}));
+    assert_eq!(next_event(&mut socket).await, None);
+}
+
+#[tokio::test]
+async fn deleting_the_last_chunk_deletes_the_file() {
+    let server = TestServer::start().await;
+    server.store("0xfile", "0x1", b"one", Some(0)).await;
+    server.store("0xfile", "0x2", b"two", Some(1)).await;
+    let mut socket = subscribe(&server).await;
+
+    let deleted = server.client.delete(server.url("/chunk/0xfile/0x1")).send().await.unwrap();
+    assert_eq!(deleted.status(), 200);
+    assert_eq!(next_event(&mut socket).await, None);
+    let deleted = server.client.delete(server.url("/chunk/0xfile/0x2")).send().await.unwrap();
+    assert_eq!(deleted.status(), 200);
+    assert_eq!(next_event(&mut socket).await.unwrap(), json!({"event": "deleted", "fileKey": "0xfile"}));
+}
+
+#[tokio::test]
+async fn expired_files_are_reported_as_deleted() {
+    let server = TestServer::start().await;
+    let chunk = json!({"fileKey": "0xfile", "chunkHash": "0x1", "chunkData": "ZGF0YQ==", "ttlSeconds": 60});
+    assert_eq!(server.post_json("/store", &chunk).await.status(), 201);
+    let mut socket = subscribe(&server).await;
+
+    let later = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs() + 120;
+    assert_eq!(sweep_expired(&server.state, later), 1);
+    assert_eq!(next_event(&mut socket).await.unwrap(), json!({"event": "deleted", "fileKey": "0xfile"}));
+}
+
+#[tokio::test]
+async fn slow_subscribers_are_told_to_resync() {
+    let server = TestServer::with_config(&[("STORAGE_NOTIFY_BUFFER", "2")]).await;
+    let mut socket = subscribe(&server).await;
+
+    // Một batch lớn phát sự kiện nhanh hơn subscriber kịp đọc
+    let batch: Vec<Value> = (0..500)
+        .map(|i| serde_json::json!({"fileKey": "0xfile", "chunkHash": format!("0x{}", i), "chunkData": "ZGF0YQ=="}))
+        .collect();
+    assert_eq!(server.post_json("/store/batch", &Value::from(batch)).await.status(), 200);
+
+    let (mut stored, mut dropped) = (0, 0);
+    while let Some(event) = next_event(&mut socket).await {
+        match event["event"].as_str().unwrap() {
+            "stored" => stored += 1,
+            "resync_needed" => dropped += event["dropped"].as_u64().unwrap(),
+            other => panic!("sự kiện không mong đợi: {}", other),
+        }
+    }
+    assert!(dropped > 0);
+    assert_eq!(stored + dropped, 500);
+
+    let metrics = server.get("/metrics").await.text().await.unwrap();
+    let line = metrics.lines().find(|l| l.starts_with("storage_notify_dropped_events_total ")).unwrap();
+    assert_eq!(line.rsplit(' ').next().unwrap().parse::<u64>().unwrap(), dropped);
+}
+
+#[tokio::test]
+async fn filtered_subscribers_see_file_completion_and_gc_runs() {
+    let server = TestServer::with_config(&[("STORAGE_ADMIN_API_KEY", "secret")]).await;
+    let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{}/events?fileKey=0xfile", server.addr)).await.unwrap();
+
+    server.store("0xfile", "0x1", b"one", Some(0)).await;
+    server.store("0xother", "0x1", b"skip", Some(0)).await;
+    assert_eq!(next_event(&mut socket).await.unwrap()["chunkHash"], "0x1");
+
+    // Manifest gửi sau một phần chunk: file đủ khi chunk cuối được ghi
+    let manifest = serde_json::json!({"chunks": ["0x1", "0x2"]});
+    assert_eq!(server.post_json("/manifest/0xfile", &manifest).await.status(), 201);
+    server.store("0xfile", "0x2", b"two", Some(1)).await;
+    assert_eq!(next_event(&mut socket).await.unwrap()["chunkHash"], "0x2");
+    let completed = serde_json::json!({"event": "completed", "fileKey": "0xfile", "chunks": 2});
+    assert_eq!(next_event(&mut socket).await.unwrap(), completed);
+
+    // Manifest gửi sau khi đã có đủ chunk
+    let manifest = serde_json::json!({"chunks": ["0x2"]});
+    assert_eq!(server.post_json("/manifest/0xfile", &manifest).await.status(), 200);
+    let completed = serde_json::json!({"event": "completed", "fileKey": "0xfile", "chunks": 1});
+    assert_eq!(next_event(&mut socket).await.unwrap(), completed);
+
+    let gc = server.client.post(server.url("/admin/gc")).header("x-api-key", "secret").send().await.unwrap();
+    assert_eq!(gc.status(), 200);
+    let event = next_event(&mut socket).await.unwrap();
+    assert_eq!(event["event"], "gc_run");
+    assert_eq!(event["applied"], false);
+    assert_eq!(next_event(&mut socket).await, None);
+}
@@ -0,0 +1,96 @@
+// Test tích hợp cho parity Reed-Solomon của file đã seal.
+
+mod common;
+
+use axum::body::Body;
+use axum::http::Request;
+use common::chunk_bytes;
+use rust_p2p_storage::{build_app, build_state, AppState, Config};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tower::ServiceExt;
+
+const CONFIG: [(&str, &str); 3] = [
+    ("STORAGE_PARITY_DATA_SHARDS", "3"),
+    ("STORAGE_PARITY_SHARDS", "2"),
+    ("STORAGE_ADMIN_API_KEY", "secret"),
+];
+
+async fn send(state: &Arc<AppState>, method: &str, path: &str, body: Option<Value>) -> (u16, Value) {
+    let request = Request::builder()
+        .method(method)
+        .uri(path)
+        .header("content-type", "application/json")
+        .header("x-api-key", "secret")
+        .body(body.map_or_else(Body::empty, |b| Body::from(b.to_string())))
+        .unwrap();
+    let response = build_app(state.clone()).oneshot(request).await.unwrap();
+    let status = response.status().as_u16();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&bytes).unwrap_or_default())
+}
+
+fn data(i: usize) -> Vec<u8> {
+    // Độ dài khác nhau để kiểm tra phần đệm
+    (0..(10 + i * 7)).map(|b| (b * 31 + i) as u8).collect()
+}
+
+#[tokio::test]
+async fn lost_chunks_are_rebuilt_from_parity_up_to_k_per_stripe() {
+    let dir = tempfile::TempDir::new().unwrap();
+    {
+        let state = build_state(sled::open(dir.path()).unwrap(), Config::from_pairs(CONFIG));
+        for i in 0..7 {
+            let body = json!({
+                "fileKey": "0xfile",
+                "chunkHash": format!("0x{}", i),
+                "chunkData": base64::Engine::encode(&base64::engine::general_purpose::STANDARD, data(i)),
+                "chunkIndex": i,
+            });
+            assert_eq!(send(&state, "POST", "/store", Some(body)).await.0, 201);
+        }
+        assert_eq!(send(&state, "POST", "/file/0xfile/reconstruct", None).await.0, 404);
+
+        let (status, body) = send(&state, "POST", "/file/0xfile/seal", None).await;
+        assert_eq!(status, 200);
+        assert_eq!(body["parityStripes"], 3);
+        let (_, body) = send(&state, "POST", "/file/0xfile/reconstruct", None).await;
+        assert_eq!(body["reconstructed"], json!([]));
+    }
+
+    // Mất 2 chunk của stripe đầu (vừa đủ K), cả 3 chunk của stripe thứ hai và chunk duy
+    // nhất của stripe cuối
+    {
+        let db = sled::open(dir.path()).unwrap();
+        for hash in ["0x0", "0x2", "0x3", "0x4", "0x5", "0x6"] {
+            db.remove(format!("0xfile:{}", hash)).unwrap();
+        }
+        db.flush().unwrap();
+    }
+
+    let state = build_state(sled::open(dir.path()).unwrap(), Config::from_pairs(CONFIG));
+    let (status, body) = send(&state, "POST", "/file/0xfile/reconstruct", None).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["reconstructed"], json!(["0x0", "0x2", "0x6"]));
+    assert_eq!(body["unrecoverable"], json!(["0x3", "0x4", "0x5"]));
+
+    let (_, body) = send(&state, "GET", "/file/0xfile", None).await;
+    let chunks = body["chunks"].as_array().unwrap();
+    let found: Vec<(u64, Vec<u8>)> = chunks.iter().map(|c| (c["index"].as_u64().unwrap(), chunk_bytes(c))).collect();
+    assert_eq!(found.len(), 4);
+    for (index, bytes) in found {
+        assert_eq!(bytes, data(index as usize));
+    }
+
+    // Gỡ seal thì parity bị xóa
+    assert_eq!(send(&state, "POST", "/file/0xfile/unseal", None).await.0, 200);
+    assert_eq!(send(&state, "POST", "/file/0xfile/reconstruct", None).await.0, 404);
+}
+
+#[test]
+fn parity_parameters_are_validated() {
+    let result = std::panic::catch_unwind(|| {
+        Config::from_pairs([("STORAGE_PARITY_DATA_SHARDS", "250"), ("STORAGE_PARITY_SHARDS", "10")])
+    });
+    assert!(result.is_err());
+}
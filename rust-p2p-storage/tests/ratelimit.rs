@@ -0,0 +1,92 @@
+// Test tích hợp cho giới hạn tốc độ và số lượt đọc đồng thời của client.
+
+mod common;
+
+use common::TestServer;
+use serde_json::{json, Value};
+use tokio::io::AsyncWriteExt;
+
+async fn get_as(server: &TestServer, path: &str, token: &str) -> reqwest::Response {
+    server.client.get(server.url(path)).header("x-api-key", token).send().await.unwrap()
+}
+
+#[tokio::test]
+async fn each_token_has_its_own_request_budget() {
+    let server = TestServer::with_config(&[
+        ("STORAGE_CLIENT_RATE_LIMIT", "2"),
+        ("STORAGE_API_TOKENS", "alice=read,bob=read"),
+    ])
+    .await;
+    assert_eq!(get_as(&server, "/stats", "alice").await.status(), 200);
+    assert_eq!(get_as(&server, "/stats", "alice").await.status(), 200);
+
+    let limited = get_as(&server, "/stats", "alice").await;
+    assert_eq!(limited.status(), 429);
+    assert_eq!(limited.headers()["retry-after"], "1");
+    let body: Value = limited.json().await.unwrap();
+    assert_eq!(body["code"], "rate_limited");
+    assert_eq!(body["retryAfter"], 1);
+
+    // Thông báo đã dịch vẫn giữ header Retry-After
+    let localized = server
+        .client
+        .get(server.url("/stats"))
+        .header("x-api-key", "alice")
+        .header("accept-language", "vi")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(localized.status(), 429);
+    assert!(localized.headers().contains_key("retry-after"));
+
+    assert_eq!(get_as(&server, "/stats", "bob").await.status(), 200);
+    assert_eq!(server.get("/health").await.status(), 200);
+    assert!(server.get("/metrics").await.text().await.unwrap().contains("storage_rate_limited_total 2"));
+}
+
+#[tokio::test]
+async fn node_wide_rate_limit_applies_to_every_client() {
+    let server = TestServer::with_config(&[("STORAGE_RATE_LIMIT", "1")]).await;
+    assert_eq!(server.get("/stats").await.status(), 200);
+    assert_eq!(server.get("/stats").await.status(), 429);
+    assert_eq!(server.get("/version").await.status(), 200);
+}
+
+#[tokio::test]
+async fn large_uploads_delay_the_next_upload_of_the_same_client() {
+    let server = TestServer::with_config(&[("STORAGE_CLIENT_STORE_BYTES_PER_SEC", "1000")]).await;
+    assert_eq!(server.store("0xfile", "0x1", &[1; 3000], Some(0)).await.status(), 201);
+
+    let limited = server.store("0xfile", "0x2", b"small", Some(1)).await;
+    assert_eq!(limited.status(), 429);
+    let retry_after: u64 = limited.headers()["retry-after"].to_str().unwrap().parse().unwrap();
+    assert!(retry_after >= 2, "{retry_after}");
+
+    // Đọc không bị giới hạn byte
+    assert_eq!(server.chunks("0xfile").await.len(), 1);
+}
+
+#[tokio::test]
+async fn concurrent_file_reads_per_client_are_bounded() {
+    let server = TestServer::with_config(&[("STORAGE_MAX_SCANS_PER_CLIENT", "1")]).await;
+    server.store("0xfile", "0x1", b"one", Some(0)).await;
+
+    // Một lượt đọc cả file đang chờ body chưa gửi hết giữ suất đọc của client
+    let mut slow = tokio::net::TcpStream::connect(server.addr).await.unwrap();
+    let head = "POST /files/batch HTTP/1.1\r\nhost: localhost\r\ncontent-type: application/json\r\ncontent-length: 100\r\n\r\n{";
+    slow.write_all(head.as_bytes()).await.unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    let busy = server.get("/file/0xfile").await;
+    assert_eq!(busy.status(), 429);
+    let body: Value = busy.json().await.unwrap();
+    assert_eq!(body["code"], "client_busy");
+    // Các route không quét file không bị ảnh hưởng
+    assert_eq!(server.get("/file/0xfile/chunk/0x1").await.status(), 200);
+
+    drop(slow);
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    assert_eq!(server.get("/file/0xfile").await.status(), 200);
+    let batch = server.post_json("/files/batch", &json!({"fileKeys": ["0xfile"]})).await;
+    assert_eq!(batch.status(), 200);
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use common::{TestServer, ADMIN_KEY};
+use serde_json::Value;
+use std::time::Duration;
+
+#[tokio::test]
+async fn reindex_repairs_drift_reported_by_the_consistency_check() {
+    // Các index phụ còn entry của một file đã không còn chunk nào
+    let server = TestServer::with_db(&[("STORAGE_ADMIN_API_KEY", ADMIN_KEY)], |db| {
+        let files = db.open_tree("file_chunks").unwrap();
+        files.insert("0xghost", [3u64.to_be_bytes(), 500u64.to_be_bytes()].concat()).unwrap();
+        let usage = db.open_tree("quota_usage").unwrap();
+        usage.insert("0xghost", &500u64.to_be_bytes()).unwrap();
+        let large = db.open_tree("chunks_large").unwrap();
+        large.insert("0xghost:0x1", "lost".as_bytes()).unwrap();
+    })
+    .await;
+    server.store("0xfile", "0x1", b"one", Some(0)).await;
+    server.store("0xfile", "0x2", b"two", Some(1)).await;
+
+    let report: Value = server.admin(reqwest::Method::GET, "/admin/consistency").await.json().await.unwrap();
+    assert_eq!(report["consistent"], false);
+    assert_eq!(report["chunks"], 2);
+    assert_eq!(report["fileCounts"]["orphaned"], 1);
+    assert_eq!(report["fileCounts"]["examples"][0], "0xghost");
+    assert_eq!(report["fileCounts"]["missing"], 0);
+    assert_eq!(report["quotaUsage"]["orphaned"], 1);
+    assert_eq!(report["largeValues"]["orphaned"], 1);
+    assert_eq!(report["orderIndex"], Value::Null);
+
+    let response = server.admin(reqwest::Method::POST, "/admin/reindex").await;
+    assert_eq!(response.status(), 202);
+    let mut progress: Value = response.json().await.unwrap();
+    for _ in 0..100 {
+        if progress["running"] == false {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        progress = server.admin(reqwest::Method::GET, "/admin/reindex").await.json().await.unwrap();
+    }
+    assert_eq!(progress["phase"], "done", "{progress}");
+    assert_eq!(progress["scanned"], 2);
+    assert_eq!(progress["resumed"], false);
+
+    let report: Value = server.admin(reqwest::Method::GET, "/admin/consistency").await.json().await.unwrap();
+    assert_eq!(report["consistent"], true, "{report}");
+    assert_eq!(server.chunks("0xfile").await.len(), 2);
+    // Node trở lại nhận ghi sau khi dựng lại xong
+    assert!(!server.state.is_read_only());
+    assert_eq!(server.store("0xfile", "0x3", b"three", Some(2)).await.status(), 201);
+}
+
+#[tokio::test]
+async fn consistency_check_requires_the_admin_key() {
+    let server = TestServer::with_config(&[("STORAGE_ADMIN_API_KEY", ADMIN_KEY)]).await;
+    assert_eq!(server.get("/admin/consistency").await.status(), 401);
+    assert_eq!(server.client.post(server.url("/admin/reindex")).send().await.unwrap().status(), 401);
+}
@@ -0,0 +1,111 @@
+// Test tích hợp cho `POST /admin/reload`: chạy binary thật với file STORAGE_CONFIG_FILE rồi
+// sửa file và đọc lại.
+
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+
+const BIN: &str = env!("CARGO_BIN_EXE_rust-p2p-storage");
+
+fn free_port() -> u16 {
+    std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+struct Node {
+    child: Child,
+    url: String,
+    config: PathBuf,
+    // Các dòng cố định của file cấu hình (địa chỉ, thư mục dữ liệu, API key)
+    base: String,
+    client: reqwest::Client,
+}
+
+impl Node {
+    async fn start(dir: &Path, extra: &str) -> Self {
+        let http = format!("127.0.0.1:{}", free_port());
+        let base = format!(
+            "http_addr = \"{}\"\ngrpc_addr = \"127.0.0.1:{}\"\ndata_dir = \"{}\"\nadmin_api_key = \"secret\"\n",
+            http,
+            free_port(),
+            dir.join("db").display()
+        );
+        let config = dir.join("storage.toml");
+        std::fs::write(&config, format!("{}{}", base, extra)).unwrap();
+        let child = Command::new(BIN).args(["--config", config.to_str().unwrap()]).stdout(Stdio::null()).spawn().unwrap();
+        let node = Node {
+            child,
+            url: format!("http://{}", http),
+            config,
+            base,
+            client: reqwest::Client::new(),
+        };
+        for _ in 0..100 {
+            if node.client.get(format!("{}/health", node.url)).send().await.is_ok() {
+                return node;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("server không khởi động được");
+    }
+
+    async fn reload(&self, extra: &str) -> (u16, Value) {
+        std::fs::write(&self.config, format!("{}{}", self.base, extra)).unwrap();
+        let response = self.client.post(format!("{}/admin/reload", self.url)).header("x-api-key", "secret").send().await.unwrap();
+        (response.status().as_u16(), response.json().await.unwrap())
+    }
+
+    async fn peers(&self) -> Vec<String> {
+        let view: Value = self.client.get(format!("{}/gossip", self.url)).send().await.unwrap().json().await.unwrap();
+        view["peers"].as_array().unwrap().iter().map(|p| p["url"].as_str().unwrap().to_string()).collect()
+    }
+}
+
+impl Drop for Node {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[tokio::test]
+async fn reload_applies_new_peers_but_not_gossip_toggles() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let node = Node::start(dir.path(), "gossip = \"1\"\npeers = \"http://127.0.0.1:1\"\n").await;
+    assert_eq!(node.peers().await, ["http://127.0.0.1:1"]);
+
+    let (status, body) = node.reload("gossip = \"1\"\npeers = \"http://127.0.0.1:2/,http://127.0.0.1:3\"\n").await;
+    assert_eq!(status, 200, "{body}");
+    let mut peers = node.peers().await;
+    peers.sort();
+    assert_eq!(peers, ["http://127.0.0.1:2", "http://127.0.0.1:3"]);
+
+    let (status, body) = node.reload("peers = \"http://127.0.0.1:2\"\n").await;
+    assert_eq!(status, 409);
+    assert_eq!(body["rejected"], serde_json::json!(["STORAGE_GOSSIP"]));
+    assert_eq!(node.peers().await.len(), 2);
+}
+
+#[tokio::test]
+async fn malformed_values_fail_the_reload_and_keep_the_old_config() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let node = Node::start(dir.path(), "max_chunk_size = \"8\"\n").await;
+    let store = |data: &str| {
+        let body = serde_json::json!({"fileKey": "0xfile", "chunkHash": "0xc", "chunkData": data});
+        node.client.post(format!("{}/store", node.url)).json(&body).send()
+    };
+    assert_eq!(store("AAAAAAAAAAAA").await.unwrap().status(), 400);
+
+    let (status, body) = node.reload("max_chunk_size = \"4MB\"\nmax_total_bytes = \"lots\"\n").await;
+    assert_eq!(status, 400);
+    assert_eq!(body["reloaded"], false);
+    assert_eq!(
+        body["invalid"],
+        serde_json::json!(["STORAGE_MAX_CHUNK_SIZE=\"4MB\"", "STORAGE_MAX_TOTAL_BYTES=\"lots\""])
+    );
+    assert_eq!(store("AAAAAAAAAAAA").await.unwrap().status(), 400);
+
+    let (status, _) = node.reload("max_chunk_size = \"16\"\n").await;
+    assert_eq!(status, 200);
+    assert_eq!(store("AAAAAAAAAAAA").await.unwrap().status(), 201);
+}
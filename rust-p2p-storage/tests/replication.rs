@@ -0,0 +1,229 @@
+// Test tích hợp cho hàng đợi replication giữa các node.
+
+mod common;
+
+use axum::body::Body;
+use axum::http::Request;
+use base64::{engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD}, Engine as _};
+use common::{chunk_bytes, TestServer};
+use rust_p2p_storage::{build_app, build_state, run_replication, AppState, Config};
+use std::sync::Arc;
+use std::time::Duration;
+use tower::ServiceExt;
+
+async fn queue_depth(state: &Arc<AppState>) -> u64 {
+    let response = build_app(state.clone())
+        .oneshot(Request::get("/metrics").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let text = String::from_utf8(body.to_vec()).unwrap();
+    let line = text.lines().find(|l| l.starts_with("storage_replication_queue_depth ")).unwrap();
+    line.rsplit(' ').next().unwrap().parse().unwrap()
+}
+
+// Chờ tới khi `peer` có `expected` chunk của `file_key`
+async fn wait_for_chunks(peer: &TestServer, file_key: &str, expected: usize) {
+    for _ in 0..100 {
+        if peer.chunks(file_key).await.len() == expected {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("peer không nhận được {} chunk của {}", expected, file_key);
+}
+
+#[tokio::test]
+async fn writes_are_replicated_to_peers_through_the_queue() {
+    let b = TestServer::start().await;
+    let peers = format!("http://{}", b.addr);
+    let a = TestServer::with_config(&[
+        ("STORAGE_REPLICATION", "1"),
+        ("STORAGE_PEERS", &peers),
+        ("STORAGE_REPLICATION_INTERVAL_MS", "20"),
+    ])
+    .await;
+
+    a.store("0xfile", "0x1", b"first", Some(0)).await;
+    let binary = URL_SAFE_NO_PAD.encode([0xff, b':', 0x00]);
+    let raw = a.client.post(a.url(&format!("/b64/store/raw/{}/0x2?index=1", binary))).body("second");
+    assert_eq!(raw.send().await.unwrap().status(), 200);
+    assert_eq!(queue_depth(&a.state).await, 2);
+
+    tokio::spawn(run_replication(a.state.clone()));
+    wait_for_chunks(&b, "0xfile", 1).await;
+    let chunks = b.chunks("0xfile").await;
+    assert_eq!(chunks[0]["index"], 0);
+    assert_eq!(chunk_bytes(&chunks[0]), b"first");
+    let body: serde_json::Value = b.get(&format!("/b64/file/{}", binary)).await.json().await.unwrap();
+    assert_eq!(chunk_bytes(&body["chunks"][0]), b"second");
+
+    for _ in 0..100 {
+        if queue_depth(&a.state).await == 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(queue_depth(&a.state).await, 0);
+    // Chunk nhận qua replication không bị gửi tiếp
+    assert_eq!(queue_depth(&b.state).await, 0);
+}
+
+#[tokio::test]
+async fn replicated_chunks_are_checked_like_store() {
+    let server = TestServer::with_config(&[("STORAGE_MAX_CHUNK_SIZE", "16")]).await;
+    let replicate = |key: &[u8], data: &[u8]| serde_json::json!({"key": URL_SAFE_NO_PAD.encode(key), "chunkData": STANDARD.encode(data)});
+    let response = server.post_json("/replicate", &replicate(b"evil\nfile:h9", b"small")).await;
+    assert_eq!(response.status(), 400);
+    let response = server.post_json("/replicate", &replicate(b"0xfile:0x1", &[1; 500])).await;
+    assert_eq!(response.status(), 413);
+    assert!(server.chunks("0xfile").await.is_empty());
+    let response = server.post_json("/replicate", &replicate(b"0xfile:0x1", b"small")).await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(chunk_bytes(&server.chunks("0xfile").await[0]), b"small");
+}
+
+#[tokio::test]
+async fn pending_replications_survive_a_restart() {
+    let b = TestServer::start().await;
+    let peers = format!("http://{}", b.addr);
+    let pairs = [("STORAGE_REPLICATION", "1"), ("STORAGE_PEERS", peers.as_str()), ("STORAGE_REPLICATION_INTERVAL_MS", "20")];
+    let dir = tempfile::TempDir::new().unwrap();
+
+    // Ghi một chunk rồi tắt node trước khi task replication kịp chạy
+    {
+        let state = build_state(sled::open(dir.path()).unwrap(), Config::from_pairs(pairs));
+        let body = serde_json::json!({"fileKey": "0xfile", "chunkHash": "0x1", "chunkData": "ZGF0YQ=="});
+        let request = Request::post("/store")
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        assert_eq!(build_app(state.clone()).oneshot(request).await.unwrap().status(), 201);
+        assert_eq!(queue_depth(&state).await, 1);
+    }
+
+    let state = build_state(sled::open(dir.path()).unwrap(), Config::from_pairs(pairs));
+    assert_eq!(queue_depth(&state).await, 1);
+    tokio::spawn(run_replication(state.clone()));
+    wait_for_chunks(&b, "0xfile", 1).await;
+    assert_eq!(chunk_bytes(&b.chunks("0xfile").await[0]), b"data");
+}
+
+#[tokio::test]
+async fn failing_peer_trips_its_circuit_breaker() {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    // Peer giả: trả về 500 khi `down`, đếm số lần được gọi
+    let (down, calls) = (Arc::new(AtomicBool::new(true)), Arc::new(AtomicUsize::new(0)));
+    let (peer_down, peer_calls) = (down.clone(), calls.clone());
+    let peer = TestServer::with_router(&[], move |_| {
+        axum::Router::new().route(
+            "/replicate",
+            axum::routing::post(move || async move {
+                peer_calls.fetch_add(1, Ordering::SeqCst);
+                if peer_down.load(Ordering::SeqCst) {
+                    axum::http::StatusCode::INTERNAL_SERVER_ERROR
+                } else {
+                    axum::http::StatusCode::OK
+                }
+            }),
+        )
+    })
+    .await;
+    let peer_url = format!("http://{}", peer.addr);
+    let a = TestServer::with_config(&[
+        ("STORAGE_REPLICATION", "1"),
+        ("STORAGE_PEERS", &peer_url),
+        ("STORAGE_REPLICATION_INTERVAL_MS", "20"),
+        ("STORAGE_BREAKER_FAILURES", "3"),
+        ("STORAGE_BREAKER_COOLDOWN_MS", "600"),
+        ("STORAGE_ADMIN_API_KEY", "secret"),
+    ])
+    .await;
+    let breaker = || async {
+        let response = a.client.get(a.url("/diagnostics")).header("x-api-key", "secret").send().await.unwrap();
+        let body: serde_json::Value = response.json().await.unwrap();
+        body["breakers"][peer_url.as_str()].clone()
+    };
+    assert_eq!(breaker().await["state"], "closed");
+
+    for i in 0..5u64 {
+        a.store("0xfile", &format!("0x{}", i), b"data", Some(i)).await;
+    }
+    tokio::spawn(run_replication(a.state.clone()));
+
+    // Lượt gửi đầu lỗi hết và mở breaker; sau đó peer không bị gọi nữa trong lúc chờ
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    let after_trip = calls.load(Ordering::SeqCst);
+    assert_eq!(after_trip, 5);
+    let state = breaker().await;
+    assert_eq!(state["state"], "open");
+    assert_eq!(state["consecutiveFailures"], 5);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(calls.load(Ordering::SeqCst), after_trip);
+    assert_eq!(queue_depth(&a.state).await, 5);
+
+    // Peer phục hồi: lần gọi thử sau thời gian chờ đóng breaker và hàng đợi được gửi hết
+    down.store(false, Ordering::SeqCst);
+    for _ in 0..100 {
+        if queue_depth(&a.state).await == 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(queue_depth(&a.state).await, 0);
+    assert_eq!(breaker().await["state"], "closed");
+    assert_eq!(calls.load(Ordering::SeqCst), after_trip + 5);
+}
+
+#[tokio::test]
+async fn replication_factor_limits_the_peers_of_each_chunk() {
+    let (b, c) = (TestServer::start().await, TestServer::start().await);
+    let peers = format!("http://{},http://{}", b.addr, c.addr);
+    let a = TestServer::with_config(&[
+        ("STORAGE_REPLICATION", "1"),
+        ("STORAGE_PEERS", &peers),
+        ("STORAGE_REPLICATION_FACTOR", "1"),
+        ("STORAGE_REPLICATION_INTERVAL_MS", "20"),
+    ])
+    .await;
+
+    for i in 0..8u64 {
+        a.store("0xfile", &format!("0x{}", i), b"data", Some(i)).await;
+    }
+    assert_eq!(queue_depth(&a.state).await, 8);
+
+    tokio::spawn(run_replication(a.state.clone()));
+    for _ in 0..100 {
+        if queue_depth(&a.state).await == 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    // Mỗi chunk nằm ở đúng một peer
+    let (on_b, on_c) = (b.chunks("0xfile").await.len(), c.chunks("0xfile").await.len());
+    assert_eq!(on_b + on_c, 8);
+}
+
+#[tokio::test]
+async fn chunk_reads_fall_through_to_peers() {
+    let b = TestServer::start().await;
+    let peers = format!("http://{}", b.addr);
+    let a = TestServer::with_config(&[("STORAGE_PEERS", &peers), ("STORAGE_READ_FALLTHROUGH", "1")]).await;
+    b.store("0xfile", "0x1", b"remote", Some(3)).await;
+
+    for path in ["/file/0xfile/chunk/0x1", "/chunk/0xfile/0x1"] {
+        let response = a.get(path).await;
+        assert_eq!(response.status(), 200);
+        let chunk: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(chunk_bytes(&chunk), b"remote");
+        assert_eq!(chunk["index"], 3);
+    }
+    let hex: serde_json::Value = a.get("/chunk/0xfile/0x1?encoding=hex").await.json().await.unwrap();
+    assert_eq!(hex["value"], format!("0x{}", hex::encode(b"remote")));
+    assert_eq!(a.get("/chunk/0xfile/0x2").await.status(), 404);
+
+    // Request đã được chuyển tiếp thì không hỏi peer nữa
+    let forwarded = a.client.get(a.url("/chunk/0xfile/0x1")).header("x-storage-forwarded", "1");
+    assert_eq!(forwarded.send().await.unwrap().status(), 404);
+}
@@ -0,0 +1,97 @@
+// Test tích hợp cho việc kiểm tra hash của chunk trong nền (module scrub).
+
+mod common;
+
+use base64::Engine;
+use common::{chunk_bytes, TestServer};
+use rust_p2p_storage::scrub_pass;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+fn digest(data: &[u8]) -> String {
+    format!("0x{}", hex::encode(Sha256::digest(data)))
+}
+
+// Ghi thẳng vào database một chunk có dữ liệu khác với chunkHash
+fn corrupt(db: &sled::Db, file_key: &str, chunk_hash: &str, data: &[u8]) {
+    let value = base64::engine::general_purpose::STANDARD.encode(data);
+    let record = json!({"value": value, "index": 0});
+    db.insert(format!("{}:{}", file_key, chunk_hash), serde_json::to_vec(&record).unwrap()).unwrap();
+}
+
+async fn status(server: &TestServer) -> Value {
+    let response = server.client.get(server.url("/admin/scrub/status")).header("x-api-key", "secret").send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    response.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn corrupt_chunks_are_quarantined_until_rewritten() {
+    let good = digest(b"expected");
+    let broken = digest(b"broken record");
+    let server = TestServer::with_db(&[("STORAGE_ADMIN_API_KEY", "secret")], |db| {
+        corrupt(db, "0xfile", &good, b"bit rot");
+        db.insert(format!("0xfile:{}", broken), "not a record".as_bytes()).unwrap();
+    })
+    .await;
+    server.store("0xfile", &digest(b"intact"), b"intact", Some(1)).await;
+    // chunkHash không phải digest thì không kiểm tra được
+    server.store("0xfile", "0x1", b"other", Some(2)).await;
+
+    scrub_pass(&server.state).await.unwrap();
+    let report = status(&server).await;
+    assert_eq!(report["enabled"], false);
+    assert_eq!(report["algorithm"], "sha256");
+    assert_eq!(report["passes"], 1);
+    assert_eq!(report["scanned"], 3);
+    assert_eq!(report["skipped"], 1);
+    assert_eq!(report["corruptFound"], 2);
+    assert_eq!(report["repaired"], 0);
+    assert_eq!(report["quarantined"], 2);
+    let entries = report["entries"].as_array().unwrap();
+    let entry = |hash: &str| entries.iter().find(|e| e["chunkHash"] == hash).unwrap().clone();
+    assert_eq!(entry(&good)["reason"], "hash_mismatch");
+    assert_eq!(entry(&good)["computed"], digest(b"bit rot"));
+    assert_eq!(entry(&broken)["reason"], "unreadable");
+
+    // Lượt sau không đếm lại chunk đã bị cách ly
+    scrub_pass(&server.state).await.unwrap();
+    assert_eq!(status(&server).await["corruptFound"], 2);
+
+    // Chunk được ghi lại đúng dữ liệu hoặc bị xóa thì ra khỏi quarantine
+    let body = json!({"fileKey": "0xfile", "chunkHash": good, "chunkData": "ZXhwZWN0ZWQ="});
+    assert_eq!(server.post_json("/store?overwrite=true", &body).await.status(), 201);
+    let deleted = server.client.delete(server.url(&format!("/chunk/0xfile/{}", broken))).send().await.unwrap();
+    assert!(deleted.status().is_success());
+    scrub_pass(&server.state).await.unwrap();
+    let report = status(&server).await;
+    assert_eq!(report["passes"], 3);
+    assert_eq!(report["quarantined"], 0);
+
+    assert_eq!(server.get("/admin/scrub/status").await.status(), 401);
+}
+
+#[tokio::test]
+async fn corrupt_chunks_are_repaired_from_a_peer() {
+    let good = digest(b"expected");
+    let source = TestServer::start().await;
+    source.store("0xfile", &good, b"expected", Some(4)).await;
+    let peer = format!("http://{}", source.addr);
+    let target = TestServer::with_db(&[("STORAGE_ADMIN_API_KEY", "secret"), ("STORAGE_PEERS", &peer)], |db| {
+        corrupt(db, "0xfile", &good, b"bit rot");
+        corrupt(db, "0xfile", &digest(b"only here"), b"lost");
+    })
+    .await;
+
+    scrub_pass(&target.state).await.unwrap();
+    let report = status(&target).await;
+    assert_eq!(report["corruptFound"], 2);
+    assert_eq!(report["repaired"], 1);
+    assert_eq!(report["quarantined"], 1);
+    assert_eq!(report["entries"][0]["chunkHash"], digest(b"only here"));
+    assert_eq!(report["entries"][0]["repairAttempts"], 1);
+
+    let chunk: Value = target.get(&format!("/file/0xfile/chunk/{}", good)).await.json().await.unwrap();
+    assert_eq!(chunk_bytes(&chunk), b"expected");
+    assert_eq!(chunk["index"], 4);
+}
@@ -0,0 +1,34 @@
+use rust_p2p_storage::{selftest, Config};
+
+#[test]
+fn selftest_args_accept_only_a_directory() {
+    assert_eq!(selftest::dir_from_args([]).unwrap(), std::path::PathBuf::from("."));
+    assert_eq!(selftest::dir_from_args(["--dir", "/data"].map(String::from)).unwrap(), std::path::PathBuf::from("/data"));
+    assert!(selftest::dir_from_args(["--dir".to_string()]).is_err());
+    assert!(selftest::dir_from_args(["--fast".to_string()]).is_err());
+}
+
+#[tokio::test]
+async fn selftest_passes_with_default_and_packed_record_settings() {
+    let dir = tempfile::TempDir::new().unwrap();
+    selftest::run(Config::from_pairs([]), dir.path()).await.unwrap();
+
+    let packed = [
+        ("STORAGE_COMPRESSION", "zstd"),
+        ("STORAGE_VALUE_FORMAT", "bare"),
+        ("STORAGE_LARGE_CHUNK_BYTES", "1024"),
+        ("STORAGE_ORDER_INDEX", "1"),
+    ];
+    selftest::run(Config::from_pairs(packed), dir.path()).await.unwrap();
+}
+
+#[tokio::test]
+async fn selftest_reports_the_failing_step() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let quota = dir.path().join("quota.json");
+    std::fs::write(&quota, r#"{"defaultBytes": 16}"#).unwrap();
+    let config = Config::from_pairs([("STORAGE_QUOTA_FILE", quota.to_str().unwrap())]);
+
+    let error = selftest::run(config, dir.path()).await.unwrap_err();
+    assert!(error.starts_with("store: HTTP 403"), "{}", error);
+}
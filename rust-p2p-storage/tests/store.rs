@@ -0,0 +1,143 @@
+// Test tích hợp cho backend lưu value của chunk thành file (STORAGE_BACKEND=fs).
+
+mod common;
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use common::{chunk_bytes, data, TestServer, ADMIN_KEY};
+use serde_json::{json, Value};
+use std::path::Path;
+use std::time::Duration;
+use tempfile::TempDir;
+
+// Server dùng backend fs trong thư mục tạm, giữ lại database để đọc thẳng các tree
+async fn start(dir: &TempDir, pairs: &[(&str, &str)]) -> (TestServer, sled::Db) {
+    let mut config = vec![
+        ("STORAGE_BACKEND", "fs"),
+        ("STORAGE_CHUNK_DIR", dir.path().to_str().unwrap()),
+        ("STORAGE_ADMIN_API_KEY", ADMIN_KEY),
+    ];
+    config.extend_from_slice(pairs);
+    TestServer::with_db_handle(&config).await
+}
+
+// Tên các chunk đang có file trong thư mục của `file_key`
+fn stored_chunks(dir: &Path, file_key: &str) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(dir.join(file_key)) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries.map(|e| e.unwrap().file_name().into_string().unwrap()).collect();
+    names.sort();
+    names
+}
+
+#[tokio::test]
+async fn fs_backend_stores_each_chunk_as_a_file_under_its_file_key() {
+    let dir = TempDir::new().unwrap();
+    let (server, db) = start(&dir, &[("STORAGE_ORDER_INDEX", "1")]).await;
+    let (first, second) = (data(16, 1), data(4096, 2));
+    assert_eq!(server.store("0xfile", "0x1", &first, Some(0)).await.status(), 201);
+    assert_eq!(server.store("0xfile", "0x2", &second, Some(1)).await.status(), 201);
+    assert_eq!(stored_chunks(dir.path(), "0xfile"), ["0x1", "0x2"]);
+    assert!(db.open_tree("chunks_large").unwrap().is_empty());
+    server.assert_consistent().await;
+
+    let chunks = server.chunks("0xfile").await;
+    assert_eq!(chunks.iter().map(chunk_bytes).collect::<Vec<_>>(), [first.clone(), second.clone()]);
+    let download = server.get("/file/0xfile/stream-download").await.bytes().await.unwrap();
+    assert_eq!(download.as_ref(), [first.clone(), second.clone()].concat());
+
+    // Đổi tên chuyển file sang thư mục của fileKey mới
+    let rename = server.post_json("/file/0xfile/rename", &json!({"newFileKey": "0xrenamed"})).await;
+    assert_eq!(rename.status(), 200);
+    assert!(!dir.path().join("0xfile").exists());
+    assert_eq!(stored_chunks(dir.path(), "0xrenamed"), ["0x1", "0x2"]);
+    let chunks = server.chunks("0xrenamed").await;
+    assert_eq!(chunks.iter().map(chunk_bytes).collect::<Vec<_>>(), [first.clone(), second.clone()]);
+
+    let cas = json!({"expected": STANDARD.encode(&second), "new": STANDARD.encode(&first)});
+    assert_eq!(server.post_json("/chunk/0xrenamed/0x2/cas", &cas).await.status(), 200);
+    assert_eq!(chunk_bytes(&server.chunks("0xrenamed").await[1]), first);
+    assert_eq!(stored_chunks(dir.path(), "0xrenamed"), ["0x1", "0x2"]);
+    let conflict = json!({"expected": STANDARD.encode(&second), "new": STANDARD.encode(&second)});
+    assert_eq!(server.post_json("/chunk/0xrenamed/0x2/cas", &conflict).await.status(), 409);
+    assert_eq!(chunk_bytes(&server.chunks("0xrenamed").await[1]), first);
+    let created = json!({"expected": null, "new": STANDARD.encode(&second)});
+    assert_eq!(server.post_json("/chunk/0xrenamed/0x3/cas", &created).await.status(), 200);
+    assert_eq!(stored_chunks(dir.path(), "0xrenamed"), ["0x1", "0x2", "0x3"]);
+    server.assert_consistent().await;
+
+    assert_eq!(server.client.delete(server.url("/file/0xrenamed")).send().await.unwrap().status(), 200);
+    assert!(!dir.path().join("0xrenamed").exists());
+    assert!(server.chunks("0xrenamed").await.is_empty());
+    server.assert_consistent().await;
+}
+
+#[tokio::test]
+async fn rejected_writes_leave_the_stored_file_untouched() {
+    let dir = TempDir::new().unwrap();
+    let quota = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(quota.path(), r#"{"defaultBytes": 8000}"#).unwrap();
+    let (server, _db) = start(
+        &dir,
+        &[
+            ("STORAGE_OVERWRITE_MODE", "reject"),
+            ("STORAGE_QUOTA_FILE", quota.path().to_str().unwrap()),
+            ("STORAGE_QUOTA_OWNER_PREFIX_LEN", "2"),
+        ],
+    )
+    .await;
+    assert_eq!(server.store("0xa", "0x1", &data(1024, 1), None).await.status(), 201);
+
+    // Xung đột và vượt quota hủy transaction sau khi file mới đã được ghi trước
+    assert_eq!(server.store("0xa", "0x1", &data(1024, 2), None).await.status(), 409);
+    assert_eq!(chunk_bytes(&server.chunks("0xa").await[0]), data(1024, 1));
+    assert_eq!(server.store("0xb", "0x1", &data(8192, 3), None).await.status(), 403);
+    assert!(!dir.path().join("0xb").join("0x1").exists());
+    server.assert_consistent().await;
+}
+
+#[tokio::test]
+async fn binary_file_keys_are_escaped_in_file_names() {
+    let dir = TempDir::new().unwrap();
+    let (server, _db) = start(&dir, &[]).await;
+    // fileKey "a/b." mã hóa base64url
+    let url = server.url("/b64/store/raw/YS9iLg/0x1");
+    let response = server.client.post(url).body(data(64, 1)).send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(stored_chunks(dir.path(), "a%2Fb%2E"), ["0x1"]);
+    let file: Value = server.get("/b64/file/YS9iLg").await.json().await.unwrap();
+    assert_eq!(chunk_bytes(&file["chunks"][0]), data(64, 1));
+    server.assert_consistent().await;
+}
+
+#[tokio::test]
+async fn reindex_removes_files_without_a_record() {
+    let dir = TempDir::new().unwrap();
+    let (server, _db) = start(&dir, &[]).await;
+    assert_eq!(server.store("0xfile", "0x1", b"one", Some(0)).await.status(), 201);
+    std::fs::create_dir(dir.path().join("0xghost")).unwrap();
+    std::fs::write(dir.path().join("0xghost").join("0x1"), b"lost").unwrap();
+
+    let report: Value = server.admin(reqwest::Method::GET, "/admin/consistency").await.json().await.unwrap();
+    assert_eq!(report["largeValues"]["orphaned"], 1);
+    assert_eq!(report["largeValues"]["examples"][0], "0xghost:0x1");
+
+    let mut progress: Value = server.admin(reqwest::Method::POST, "/admin/reindex").await.json().await.unwrap();
+    for _ in 0..100 {
+        if progress["running"] == false {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        progress = server.admin(reqwest::Method::GET, "/admin/reindex").await.json().await.unwrap();
+    }
+    assert_eq!(progress["phase"], "done", "{progress}");
+    assert!(!dir.path().join("0xghost").exists());
+    server.assert_consistent().await;
+    assert_eq!(chunk_bytes(&server.chunks("0xfile").await[0]), b"one");
+}
+
+#[test]
+#[should_panic(expected = "backend rocksdb chưa có trong bản build này")]
+fn unavailable_backend_is_a_config_error() {
+    rust_p2p_storage::Config::from_pairs([("STORAGE_BACKEND", "rocksdb")]);
+}
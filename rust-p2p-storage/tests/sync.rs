@@ -0,0 +1,98 @@
+// Test tích hợp cho đồng bộ toàn bộ chunk qua GET /sync/full.
+
+mod common;
+
+use common::{chunk_bytes, TestServer};
+use rust_p2p_storage::run_full_sync;
+use serde_json::Value;
+
+async fn sync_lines(server: &TestServer, after: Option<&str>) -> Vec<Value> {
+    let mut request = server.client.get(server.url("/sync/full")).header("x-api-key", "secret");
+    if let Some(after) = after {
+        request = request.query(&[("after", after)]);
+    }
+    let response = request.send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    let body = response.text().await.unwrap();
+    body.lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+}
+
+#[tokio::test]
+async fn full_sync_streams_every_chunk_in_key_order_and_resumes() {
+    let seed = TestServer::with_config(&[("STORAGE_SHARDS", "3"), ("STORAGE_ADMIN_API_KEY", "secret")]).await;
+    for file in 0..4 {
+        for i in 0..3u64 {
+            let data = format!("file{}-chunk{}", file, i);
+            seed.store(&format!("0xfile{}", file), &format!("0x{}", i), data.as_bytes(), Some(i)).await;
+        }
+    }
+
+    assert_eq!(seed.get("/sync/full").await.status(), 401);
+
+    let lines = sync_lines(&seed, None).await;
+    assert_eq!(lines.len(), 13);
+    assert_eq!(lines[12], serde_json::json!({"end": true, "chunks": 12}));
+    let keys: Vec<&str> = lines[..12].iter().map(|line| line["key"].as_str().unwrap()).collect();
+    let mut sorted = keys.clone();
+    sorted.sort_by_key(|key| base64::Engine::decode(&base64::engine::general_purpose::URL_SAFE_NO_PAD, key).unwrap());
+    assert_eq!(keys, sorted);
+
+    // Tiếp tục sau chunk thứ 5
+    let rest = sync_lines(&seed, Some(keys[4])).await;
+    assert_eq!(rest.len(), 8);
+    assert_eq!(rest[0]["key"], keys[5]);
+    assert_eq!(rest[7]["chunks"], 7);
+}
+
+#[tokio::test]
+async fn new_node_pulls_everything_from_the_seed() {
+    let seed = TestServer::with_config(&[("STORAGE_SHARDS", "2"), ("STORAGE_ADMIN_API_KEY", "secret")]).await;
+    for i in 0..600u64 {
+        seed.store(&format!("0xfile{}", i % 3), &format!("0x{}", i), &i.to_be_bytes(), Some(i)).await;
+    }
+
+    let url = seed.url("");
+    let node = TestServer::with_config(&[("STORAGE_SYNC_FROM", url.as_str()), ("STORAGE_SYNC_API_KEY", "secret")]).await;
+    run_full_sync(node.state.clone()).await;
+
+    for file in 0..3 {
+        let chunks = node.chunks(&format!("0xfile{}", file)).await;
+        assert_eq!(chunks.len(), 200);
+        for chunk in &chunks {
+            let index = chunk["index"].as_u64().unwrap();
+            assert_eq!(index % 3, file);
+            assert_eq!(chunk_bytes(chunk), index.to_be_bytes());
+        }
+    }
+}
+
+#[tokio::test]
+async fn concurrent_sync_streams_are_capped() {
+    let seed = TestServer::with_config(&[
+        ("STORAGE_ADMIN_API_KEY", "secret"),
+        ("STORAGE_SYNC_MAX_STREAMS", "1"),
+        ("STORAGE_SYNC_RATE_BYTES", "100"),
+    ])
+    .await;
+    for i in 0..5u64 {
+        seed.store("0xfile", &format!("0x{}", i), b"some chunk data", Some(i)).await;
+    }
+
+    // Luồng đầu bị giới hạn tốc độ nên vẫn đang chạy khi luồng thứ hai tới
+    let first = seed.client.get(seed.url("/sync/full")).header("x-api-key", "secret").send().await.unwrap();
+    assert_eq!(first.status(), 200);
+    let second = seed.client.get(seed.url("/sync/full")).header("x-api-key", "secret").send().await.unwrap();
+    assert_eq!(second.status(), 503);
+
+    drop(first);
+    let mut status = 503;
+    for _ in 0..100 {
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        let response = seed.client.get(seed.url("/sync/full")).header("x-api-key", "secret").send().await.unwrap();
+        status = response.status().as_u16();
+        if status == 200 {
+            break;
+        }
+    }
+    assert_eq!(status, 200);
+}
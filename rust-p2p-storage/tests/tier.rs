@@ -0,0 +1,58 @@
+// Test tích hợp cho việc tách chunk lớn ra tree riêng (STORAGE_LARGE_CHUNK_BYTES).
+
+mod common;
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use common::{chunk_bytes, data, TestServer};
+use serde_json::json;
+
+#[tokio::test]
+async fn large_chunks_are_transparent_to_every_read_and_write_path() {
+    let server = TestServer::with_config(&[
+        ("STORAGE_LARGE_CHUNK_BYTES", "256"),
+        ("STORAGE_ORDER_INDEX", "1"),
+        ("STORAGE_OVERWRITE_MODE", "verify"),
+    ])
+    .await;
+    let (small, large) = (data(16, 1), data(4096, 2));
+    assert_eq!(server.store("0xfile", "0x1", &small, Some(0)).await.status(), 201);
+    assert_eq!(server.store("0xfile", "0x2", &large, Some(1)).await.status(), 201);
+    // Ghi lại đúng dữ liệu cũ vẫn được nhận ra là giống hệt
+    assert_eq!(server.store("0xfile", "0x2", &large, Some(1)).await.status(), 200);
+    assert_eq!(server.store("0xfile", "0x2", &data(4096, 3), Some(1)).await.status(), 409);
+
+    let chunks = server.chunks("0xfile").await;
+    assert_eq!(chunks.iter().map(chunk_bytes).collect::<Vec<_>>(), [small.clone(), large.clone()]);
+    let download = server.get("/file/0xfile/stream-download").await.bytes().await.unwrap();
+    assert_eq!(download.as_ref(), [small.clone(), large.clone()].concat());
+
+    let rename = server.post_json("/file/0xfile/rename", &json!({"newFileKey": "0xrenamed"})).await;
+    assert_eq!(rename.status(), 200);
+    let chunks = server.chunks("0xrenamed").await;
+    assert_eq!(chunks.iter().map(chunk_bytes).collect::<Vec<_>>(), [small.clone(), large.clone()]);
+
+    let cas = json!({"expected": STANDARD.encode(&large), "new": STANDARD.encode(&small)});
+    assert_eq!(server.post_json("/chunk/0xrenamed/0x2/cas", &cas).await.status(), 200);
+    let chunks = server.chunks("0xrenamed").await;
+    assert_eq!(chunk_bytes(&chunks[1]), small);
+}
+
+#[tokio::test]
+async fn deleting_large_chunks_releases_their_quota() {
+    // Đủ cho một chunk lớn nhưng không đủ cho hai
+    let quota = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(quota.path(), r#"{"defaultBytes": 8000}"#).unwrap();
+    let server = TestServer::with_config(&[
+        ("STORAGE_LARGE_CHUNK_BYTES", "256"),
+        ("STORAGE_QUOTA_FILE", quota.path().to_str().unwrap()),
+        ("STORAGE_QUOTA_OWNER_PREFIX_LEN", "2"),
+    ])
+    .await;
+
+    assert_eq!(server.store("0xa", "0x1", &data(4096, 1), None).await.status(), 201);
+    assert_eq!(server.store("0xb", "0x1", &data(4096, 2), None).await.status(), 403);
+
+    assert_eq!(server.client.delete(server.url("/file/0xa")).send().await.unwrap().status(), 200);
+    assert_eq!(server.store("0xb", "0x1", &data(4096, 2), None).await.status(), 201);
+    assert_eq!(chunk_bytes(&server.chunks("0xb").await[0]), data(4096, 2));
+}
@@ -0,0 +1,52 @@
+// Test tích hợp cho HTTPS/HTTP2 qua rustls (STORAGE_TLS_CERT, STORAGE_TLS_KEY).
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use rust_p2p_storage::{build_app, build_state, serve_tls, Config};
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn tls_listener_serves_http2_to_clients_that_trust_the_certificate() {
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let dir = tempfile::TempDir::new().unwrap();
+    let cert_path = dir.path().join("cert.pem");
+    let key_path = dir.path().join("key.pem");
+    std::fs::write(&cert_path, cert.cert.pem()).unwrap();
+    std::fs::write(&key_path, cert.key_pair.serialize_pem()).unwrap();
+
+    let db = sled::open(dir.path().join("db")).unwrap();
+    let state = build_state(
+        db,
+        Config::from_pairs([
+            ("STORAGE_TLS_CERT", cert_path.to_str().unwrap()),
+            ("STORAGE_TLS_KEY", key_path.to_str().unwrap()),
+        ]),
+    );
+    let acceptor = state.tls_acceptor().unwrap().expect("phải có acceptor khi đặt chứng chỉ");
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let app = build_app(state.clone());
+    tokio::spawn(serve_tls(listener, app, state.max_connections(), acceptor, std::future::pending()));
+
+    let client = reqwest::Client::builder()
+        .add_root_certificate(reqwest::Certificate::from_pem(cert.cert.pem().as_bytes()).unwrap())
+        .build()
+        .unwrap();
+    let base = format!("https://localhost:{}", port);
+    let body = json!({"fileKey": "0xtls", "chunkHash": "0x1", "chunkData": STANDARD.encode(b"secret")});
+    let stored = client.post(format!("{}/store", base)).json(&body).send().await.unwrap();
+    assert_eq!(stored.status(), 201);
+    assert_eq!(stored.version(), reqwest::Version::HTTP_2);
+
+    let chunks: Value = client.get(format!("{}/file/0xtls", base)).send().await.unwrap().json().await.unwrap();
+    assert_eq!(chunks["chunks"][0]["value"], STANDARD.encode(b"secret"));
+
+    // Client plaintext không nói chuyện được với listener TLS
+    let plain = reqwest::get(format!("http://localhost:{}/file/0xtls", port)).await;
+    assert!(plain.is_err());
+}
+
+#[test]
+#[should_panic(expected = "STORAGE_TLS_CERT và STORAGE_TLS_KEY phải được đặt cùng nhau")]
+fn certificate_without_key_is_a_config_error() {
+    Config::from_pairs([("STORAGE_TLS_CERT", "/tmp/cert.pem")]);
+}
@@ -0,0 +1,93 @@
+// Test tích hợp cho việc xóa chunk hết hạn theo policy TTL từng prefix (STORAGE_TTL_FILE)
+// và theo `ttlSeconds` của từng chunk.
+
+mod common;
+
+use common::TestServer;
+use rust_p2p_storage::sweep_expired;
+
+fn now() -> u64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs()
+}
+
+async fn server_with_policy(policy: &str, extra: &[(&str, &str)]) -> (TestServer, tempfile::NamedTempFile) {
+    let file = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(file.path(), policy).unwrap();
+    let mut pairs = vec![("STORAGE_TTL_FILE", file.path().to_str().unwrap())];
+    pairs.extend_from_slice(extra);
+    (TestServer::with_config(&pairs).await, file)
+}
+
+#[tokio::test]
+async fn shorter_prefix_ttl_expires_before_the_default() {
+    let policy = r#"{"defaultSecs": 3600, "prefixes": {"0xshort": 60, "0xshortkeep": null}}"#;
+    let (server, _policy) = server_with_policy(policy, &[("STORAGE_ORDER_INDEX", "1")]).await;
+    server.store("0xshortfile", "0x1", b"brief", Some(0)).await;
+    server.store("0xshortkeepfile", "0x1", b"forever", Some(0)).await;
+    server.store("0xlongfile", "0x1", b"lasting", Some(0)).await;
+    let start = now();
+
+    assert_eq!(sweep_expired(&server.state, start + 30), 0);
+    assert_eq!(sweep_expired(&server.state, start + 120), 1);
+    assert!(server.chunks("0xshortfile").await.is_empty());
+    assert_eq!(server.chunks("0xlongfile").await.len(), 1);
+
+    assert_eq!(sweep_expired(&server.state, start + 3700), 1);
+    assert!(server.chunks("0xlongfile").await.is_empty());
+    // Prefix dài hơn với `null` thắng prefix ngắn hơn có TTL
+    assert_eq!(server.chunks("0xshortkeepfile").await.len(), 1);
+
+    // Chunk mới cùng key sau khi hết hạn không bị entry index cũ làm lặp lại
+    server.store("0xshortfile", "0x1", b"again", Some(0)).await;
+    assert_eq!(server.chunks("0xshortfile").await.len(), 1);
+}
+
+#[tokio::test]
+async fn expired_chunks_release_quota_and_sealed_files_are_kept() {
+    let quota = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(quota.path(), r#"{"defaultBytes": 4096}"#).unwrap();
+    let (server, _policy) = server_with_policy(
+        r#"{"defaultSecs": 60}"#,
+        &[("STORAGE_QUOTA_FILE", quota.path().to_str().unwrap()), ("STORAGE_QUOTA_OWNER_PREFIX_LEN", "2")],
+    )
+    .await;
+    server.store("0xa", "0x1", &[1u8; 2048], None).await;
+    server.store("0xsealed", "0x1", b"kept", None).await;
+    assert_eq!(server.post_json("/file/0xsealed/seal", &serde_json::Value::Null).await.status(), 200);
+    assert_eq!(server.store("0xb", "0x1", &[2u8; 2048], None).await.status(), 403);
+
+    assert_eq!(sweep_expired(&server.state, now() + 120), 1);
+    assert_eq!(server.chunks("0xsealed").await.len(), 1);
+    assert_eq!(server.store("0xb", "0x1", &[2u8; 2048], None).await.status(), 201);
+}
+
+#[tokio::test]
+async fn chunks_stored_with_ttl_seconds_expire_on_their_own() {
+    let server = TestServer::start().await;
+    let payload = |file_key: &str, ttl: u64| {
+        serde_json::json!({"fileKey": file_key, "chunkHash": "0x1", "chunkData": "ZGF0YQ==", "ttlSeconds": ttl})
+    };
+    assert_eq!(server.post_json("/store", &payload("0xbrief", 1)).await.status(), 201);
+    assert_eq!(server.post_json("/store", &payload("0xhour", 3600)).await.status(), 201);
+    assert_eq!(server.post_json("/store", &payload("0xzero", 0)).await.status(), 400);
+    server.store("0xkept", "0x1", b"data", None).await;
+
+    let chunk: serde_json::Value = server.get("/file/0xbrief/chunk/0x1").await.json().await.unwrap();
+    assert!(chunk["expiresAt"].as_u64().unwrap() <= now() + 1);
+
+    // Hết hạn nhưng chưa bị quét: đọc như chunk không tồn tại
+    tokio::time::sleep(std::time::Duration::from_millis(2100)).await;
+    assert_eq!(server.get("/file/0xbrief/chunk/0x1").await.status(), 404);
+    assert!(server.chunks("0xbrief").await.is_empty());
+    assert_eq!(server.chunks("0xhour").await.len(), 1);
+
+    assert_eq!(sweep_expired(&server.state, now()), 1);
+    assert_eq!(sweep_expired(&server.state, now()), 0);
+
+    // TTL đi theo chunk khi đổi tên file
+    let rename = server.post_json("/file/0xhour/rename", &serde_json::json!({"newFileKey": "0xmoved"})).await;
+    assert_eq!(rename.status(), 200);
+    assert_eq!(sweep_expired(&server.state, now() + 7200), 1);
+    assert_eq!(server.get("/file/0xmoved/chunk/0x1").await.status(), 404);
+    assert_eq!(server.chunks("0xkept").await.len(), 1);
+}
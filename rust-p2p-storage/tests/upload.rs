@@ -0,0 +1,93 @@
+// Test tích hợp cho upload nhiều phần có thể tiếp tục (`/upload/...`).
+
+mod common;
+
+use common::{chunk_bytes, TestServer};
+use rust_p2p_storage::sweep_uploads;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+async fn put_part(server: &TestServer, session: &str, index: u64, data: &[u8]) -> reqwest::Response {
+    let url = server.url(&format!("/upload/{}/{}", session, index));
+    server.client.put(url).body(data.to_vec()).send().await.unwrap()
+}
+
+async fn init(server: &TestServer, body: Value) -> Value {
+    let response = server.post_json("/upload/init", &body).await;
+    assert_eq!(response.status(), 201);
+    response.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn parts_are_resumed_verified_and_stored_with_a_manifest() {
+    let server = TestServer::with_config(&[("STORAGE_SPLIT_CHUNK_SIZE", "4")]).await;
+    let data = b"0123456789";
+    let session = init(&server, json!({"fileKey": "0xfile", "totalSize": 10, "filename": "a.txt"})).await;
+    assert_eq!(session["partSize"], 4);
+    let id = session["sessionId"].as_str().unwrap();
+
+    // Phần dài hơn partSize hoặc vượt totalSize bị từ chối
+    assert_eq!(put_part(&server, id, 0, b"01234").await.status(), 413);
+    assert_eq!(put_part(&server, id, 3, b"xx").await.status(), 400);
+
+    // Gửi phần 2 và phần 0 (sai nội dung) rồi hỏi trạng thái để gửi tiếp
+    assert_eq!(put_part(&server, id, 2, &data[8..]).await.status(), 200);
+    assert_eq!(put_part(&server, id, 0, b"XXXX").await.status(), 200);
+    let complete = server.client.post(server.url(&format!("/upload/{}/complete", id))).send();
+    assert_eq!(complete.await.unwrap().status(), 400);
+    let status: Value = server.get(&format!("/upload/{}", id)).await.json().await.unwrap();
+    assert_eq!(status["parts"], json!([0, 2]));
+    assert_eq!(status["receivedBytes"], 6);
+
+    assert_eq!(put_part(&server, id, 0, &data[..4]).await.status(), 200);
+    assert_eq!(put_part(&server, id, 1, &data[4..8]).await.status(), 200);
+    let wrong_hash = json!({"sha256": hex::encode(Sha256::digest(b"khac"))});
+    assert_eq!(server.post_json(&format!("/upload/{}/complete", id), &wrong_hash).await.status(), 400);
+
+    let sha256 = json!({"sha256": hex::encode(Sha256::digest(data))});
+    let response = server.post_json(&format!("/upload/{}/complete", id), &sha256).await;
+    assert_eq!(response.status(), 201);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["fileKey"], "0xfile");
+    assert_eq!(body["totalSize"], 10);
+
+    let manifest: Value = server.get("/manifest/0xfile").await.json().await.unwrap();
+    assert_eq!(manifest["chunks"], body["chunkHashes"]);
+    assert_eq!(manifest["filename"], "a.txt");
+    let stored: Vec<u8> = server.chunks("0xfile").await.iter().flat_map(chunk_bytes).collect();
+    assert_eq!(stored, data);
+
+    // Phiên đã hoàn tất thì không còn
+    assert_eq!(server.get(&format!("/upload/{}", id)).await.status(), 404);
+}
+
+#[tokio::test]
+async fn file_key_defaults_to_content_hash_and_repeated_parts_get_distinct_hashes() {
+    let server = TestServer::with_config(&[("STORAGE_SPLIT_CHUNK_SIZE", "3")]).await;
+    let id = init(&server, json!({})).await["sessionId"].as_str().unwrap().to_string();
+    for (index, part) in [b"abc".as_slice(), b"abc", b"d"].into_iter().enumerate() {
+        assert_eq!(put_part(&server, &id, index as u64, part).await.status(), 200);
+    }
+    let response = server.client.post(server.url(&format!("/upload/{}/complete", id))).send().await.unwrap();
+    assert_eq!(response.status(), 201);
+    let body: Value = response.json().await.unwrap();
+    let file_key = format!("0x{}", hex::encode(Sha256::digest(b"abcabcd")));
+    assert_eq!(body["fileKey"], file_key.as_str());
+    let hashes: Vec<&str> = body["chunkHashes"].as_array().unwrap().iter().map(|h| h.as_str().unwrap()).collect();
+    let abc = format!("0x{}", hex::encode(Sha256::digest(b"abc")));
+    assert_eq!(hashes[..2], [abc.clone(), format!("{}-1", abc)]);
+
+    let response = server.get(&format!("/file/{}/stream", file_key)).await;
+    assert_eq!(response.bytes().await.unwrap().as_ref(), b"abcabcd");
+}
+
+#[tokio::test]
+async fn expired_sessions_are_rejected_and_swept() {
+    let server = TestServer::with_config(&[("STORAGE_UPLOAD_SESSION_TTL_SECS", "0")]).await;
+    let id = init(&server, json!({"fileKey": "0xfile"})).await["sessionId"].as_str().unwrap().to_string();
+    assert_eq!(put_part(&server, &id, 0, b"data").await.status(), 404);
+    assert_eq!(server.get(&format!("/upload/{}", id)).await.status(), 404);
+
+    assert_eq!(sweep_uploads(&server.state, u64::MAX).unwrap(), 1);
+    assert_eq!(sweep_uploads(&server.state, u64::MAX).unwrap(), 0);
+}
@@ -0,0 +1,82 @@
+// Test tích hợp cho giới hạn dung lượng của node (STORAGE_MAX_TOTAL_BYTES,
+// STORAGE_MAX_CHUNKS_PER_FILE) và `GET /stats`.
+
+mod common;
+
+use common::TestServer;
+use rust_p2p_storage::refresh_usage;
+use serde_json::Value;
+
+#[tokio::test]
+async fn chunks_per_file_limit_returns_507_without_going_read_only() {
+    let server = TestServer::with_config(&[("STORAGE_MAX_CHUNKS_PER_FILE", "2")]).await;
+    assert_eq!(server.store("0xfile", "0x1", b"one", Some(0)).await.status(), 201);
+    assert_eq!(server.store("0xfile", "0x2", b"two", Some(1)).await.status(), 201);
+
+    let response = server.store("0xfile", "0x3", b"three", Some(2)).await;
+    assert_eq!(response.status(), 507);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "storage_limit_exceeded");
+
+    // Ghi đè chunk đã có và ghi vào file khác vẫn được
+    let overwrite = serde_json::json!({"fileKey": "0xfile", "chunkHash": "0x2", "chunkData": "VFdP", "chunkIndex": 1});
+    let response = server.client.post(server.url("/store?overwrite=true")).json(&overwrite).send().await.unwrap();
+    assert_eq!(response.status(), 201);
+    assert_eq!(server.store("0xother", "0x1", b"one", Some(0)).await.status(), 201);
+
+    // Batch làm vượt giới hạn bị từ chối cả batch
+    let batch = serde_json::json!([
+        {"fileKey": "0xbatch", "chunkHash": "0x1", "chunkData": "AA=="},
+        {"fileKey": "0xbatch", "chunkHash": "0x2", "chunkData": "AA=="},
+        {"fileKey": "0xbatch", "chunkHash": "0x3", "chunkData": "AA=="},
+    ]);
+    assert_eq!(server.post_json("/store/batch", &batch).await.status(), 507);
+    assert!(server.chunks("0xbatch").await.is_empty());
+}
+
+#[tokio::test]
+async fn total_bytes_limit_is_released_by_deletes_and_reported_in_stats() {
+    let server = TestServer::with_config(&[("STORAGE_MAX_TOTAL_BYTES", "2048")]).await;
+    assert_eq!(server.store("0xa", "0x1", &[1u8; 900], None).await.status(), 201);
+    assert_eq!(server.store("0xb", "0x1", &[2u8; 900], None).await.status(), 507);
+
+    let stats: Value = server.get("/stats").await.json().await.unwrap();
+    assert_eq!(stats["chunks"], 1);
+    assert_eq!(stats["files"], 1);
+    assert_eq!(stats["maxTotalBytes"], 2048);
+    let used = stats["bytesUsed"].as_u64().unwrap();
+    assert!(used > 900);
+    assert_eq!(stats["remainingBytes"], 2048 - used);
+    assert!(stats.get("maxChunksPerFile").is_none());
+
+    let delete = server.client.delete(server.url("/file/0xa")).send().await.unwrap();
+    assert_eq!(delete.status(), 200);
+    assert_eq!(server.store("0xb", "0x1", &[2u8; 900], None).await.status(), 201);
+    assert_eq!(server.store("0xb", "0x2", b"small", None).await.status(), 201);
+
+    // /stats trả về lần cộng dồn gần nhất, task nền cập nhật lại theo chu kỳ
+    let usage = refresh_usage(&server.state).unwrap();
+    assert_eq!((usage.chunks, usage.files), (2, 1));
+    let stats: Value = server.get("/stats").await.json().await.unwrap();
+    assert_eq!(stats["chunks"], 2);
+    assert_eq!(stats["bytesUsed"], usage.bytes);
+}
+
+#[tokio::test]
+async fn cas_and_binary_key_writes_respect_node_limits() {
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+    let server = TestServer::with_config(&[("STORAGE_MAX_TOTAL_BYTES", "1024"), ("STORAGE_MAX_CHUNKS_PER_FILE", "1")]).await;
+    assert_eq!(server.store("0xa", "0x1", &[1u8; 600], None).await.status(), 201);
+
+    let cas = serde_json::json!({"expected": null, "new": base64::engine::general_purpose::STANDARD.encode([2u8; 600])});
+    let response = server.post_json("/chunk/0xb/0x1/cas", &cas).await;
+    assert_eq!(response.status(), 507);
+    assert_eq!(response.json::<Value>().await.unwrap()["code"], "storage_limit_exceeded");
+    assert!(server.chunks("0xb").await.is_empty());
+
+    let binary = URL_SAFE_NO_PAD.encode([0xff, 0x00]);
+    let raw = |chunk_hash: &str, data: Vec<u8>| server.client.post(server.url(&format!("/b64/store/raw/{}/{}", binary, chunk_hash))).body(data).send();
+    assert_eq!(raw("0x1", vec![3u8; 600]).await.unwrap().status(), 507);
+    assert_eq!(raw("0x1", b"small".to_vec()).await.unwrap().status(), 200);
+    assert_eq!(raw("0x2", b"small".to_vec()).await.unwrap().status(), 507);
+}
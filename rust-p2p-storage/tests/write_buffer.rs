@@ -0,0 +1,91 @@
+// Test tích hợp cho bộ đệm ghi bất đồng bộ (STORAGE_WRITE_BUFFER).
+
+mod common;
+
+use common::{chunk_bytes, TestServer};
+use rust_p2p_storage::run_write_buffer;
+use std::time::Duration;
+
+async fn buffered(server: &TestServer) -> u64 {
+    let health: serde_json::Value = server.get("/health").await.json().await.unwrap();
+    health["buffered"].as_u64().unwrap()
+}
+
+async fn admin_flush(server: &TestServer) -> serde_json::Value {
+    let response = server.client.post(server.url("/admin/flush")).header("x-api-key", "secret").send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    response.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn buffered_writes_are_persisted_by_admin_flush() {
+    let server =
+        TestServer::with_config(&[("STORAGE_WRITE_BUFFER", "100"), ("STORAGE_ADMIN_API_KEY", "secret")]).await;
+
+    assert_eq!(server.store("0xfile", "0x1", b"one", Some(0)).await.status(), 201);
+    assert_eq!(server.store("0xfile", "0x2", b"two", Some(1)).await.status(), 201);
+    assert_eq!(buffered(&server).await, 2);
+    let weak: serde_json::Value = server.get("/file/0xfile").await.json().await.unwrap();
+    assert!(weak["chunks"].as_array().is_none_or(|chunks| chunks.is_empty()));
+
+    let unauthorized = server.client.post(server.url("/admin/flush")).send().await.unwrap();
+    assert_eq!(unauthorized.status(), 401);
+    assert_eq!(admin_flush(&server).await["drained"], 2);
+    assert_eq!(buffered(&server).await, 0);
+
+    let chunks = server.chunks("0xfile").await;
+    assert_eq!(chunks.len(), 2);
+    assert_eq!(chunk_bytes(&chunks[0]), b"one");
+    assert_eq!(admin_flush(&server).await["drained"], 0);
+}
+
+#[tokio::test]
+async fn strong_reads_see_buffered_writes() {
+    let server = TestServer::with_config(&[("STORAGE_WRITE_BUFFER", "100")]).await;
+    server.store("0xfile", "0x1", b"data", Some(0)).await;
+
+    let body: serde_json::Value = server.get("/file/0xfile?consistency=strong").await.json().await.unwrap();
+    assert_eq!(chunk_bytes(&body["chunks"][0]), b"data");
+    assert_eq!(buffered(&server).await, 0);
+}
+
+#[tokio::test]
+async fn full_buffer_and_background_task_persist_writes() {
+    let server = TestServer::with_config(&[("STORAGE_WRITE_BUFFER", "2"), ("STORAGE_WRITE_BUFFER_FLUSH_MS", "10")]).await;
+    server.store("0xfile", "0x1", b"a", Some(0)).await;
+    // Lần ghi làm đầy bộ đệm chờ ghi xuống sled trước khi trả về
+    server.store("0xfile", "0x2", b"b", Some(1)).await;
+    assert_eq!(buffered(&server).await, 0);
+    assert_eq!(server.chunks("0xfile").await.len(), 2);
+
+    server.store("0xfile", "0x3", b"c", Some(2)).await;
+    tokio::spawn(run_write_buffer(server.state.clone()));
+    for _ in 0..100 {
+        if buffered(&server).await == 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(server.chunks("0xfile").await.len(), 3);
+}
+
+#[tokio::test]
+async fn a_failing_buffered_write_does_not_drop_the_others() {
+    let server = TestServer::with_config(&[
+        ("STORAGE_WRITE_BUFFER", "100"),
+        ("STORAGE_OVERWRITE_MODE", "reject"),
+        ("STORAGE_ADMIN_API_KEY", "secret"),
+    ])
+    .await;
+    server.store("0xfile", "0x1", b"first", Some(0)).await;
+    // Cùng key với dữ liệu khác: bị từ chối lúc ghi xuống sled, sau khi đã trả `201`
+    assert_eq!(server.store("0xfile", "0x1", b"second", Some(0)).await.status(), 201);
+    server.store("0xfile", "0x2", b"other", Some(1)).await;
+    assert_eq!(admin_flush(&server).await["drained"], 3);
+
+    let chunks = server.chunks("0xfile").await;
+    assert_eq!(chunks.len(), 2);
+    assert_eq!(chunk_bytes(&chunks[0]), b"first");
+    let metrics = server.get("/metrics").await.text().await.unwrap();
+    assert!(metrics.contains("storage_write_buffer_failures_total 1"));
+}